@@ -0,0 +1,199 @@
+use wasm_bindgen::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::format::cached;
+use crate::links::{parse_inline_link, parse_reference_definitions};
+use crate::markdown::{code_ranges, escape_html, in_ranges};
+use crate::url;
+
+/// A `*[ABBR]: Expansion` definition line
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct AbbreviationDef {
+    pub abbr: String,
+    pub expansion: String,
+    pub line: usize,
+}
+
+#[derive(Serialize)]
+struct AbbreviationUse {
+    abbr: String,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize)]
+struct AbbreviationReport {
+    definitions: Vec<AbbreviationDef>,
+    undefined: Vec<AbbreviationUse>,
+}
+
+fn definition_regex() -> &'static Regex {
+    static DEFINITION: OnceLock<Regex> = OnceLock::new();
+    cached(&DEFINITION, r"^\*\[([^\]]+)\]:[ \t]*(.*?)[ \t]*$")
+}
+
+/// Parse all abbreviation definitions. Later definitions win.
+pub(crate) fn parse_definitions(content: &str) -> Vec<AbbreviationDef> {
+    let re = definition_regex();
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            re.captures(line).map(|caps| AbbreviationDef {
+                abbr: caps[1].trim().to_string(),
+                expansion: caps[2].to_string(),
+                line: i + 1,
+            })
+        })
+        .collect()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_whole_word(content: &str, start: usize, end: usize) -> bool {
+    content[..start].chars().next_back().is_none_or(|c| !is_word_char(c))
+        && content[end..].chars().next().is_none_or(|c| !is_word_char(c))
+}
+
+/// Byte offsets of whole-word occurrences of `word`, skipping code and
+/// definition lines
+fn find_word(content: &str, word: &str, skip: &[(usize, usize)]) -> Vec<usize> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    while let Some(pos) = content[start..].find(word) {
+        let abs = start + pos;
+        let end = abs + word.len();
+        if is_whole_word(content, abs, end) && !in_ranges(skip, abs) {
+            found.push(abs);
+        }
+        start = end;
+    }
+    found
+}
+
+/// Destinations of inline links, `<...>` autolinks and reference
+/// definitions
+fn link_destination_ranges(content: &str) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> =
+        parse_reference_definitions(content).iter().map(|def| (def.start, def.end)).collect();
+    for (i, c) in content.char_indices() {
+        match c {
+            '[' => {
+                if let Some(link) = parse_inline_link(content, i) {
+                    ranges.push((link.dest_start, link.end));
+                }
+            }
+            '<' => {
+                if let Some(close) = content[i..].find('>') {
+                    if !content[i + 1..i + close].contains(char::is_whitespace) {
+                        ranges.push((i, i + close + 1));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// Ranges that abbreviation matching must not touch: code, URLs, link
+/// destinations and the definition lines themselves
+fn skipped_ranges(content: &str) -> Vec<(usize, usize)> {
+    let re = definition_regex();
+    let mut skip = code_ranges(content);
+    skip.extend(url::find_urls(content));
+    skip.extend(link_destination_ranges(content));
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if re.is_match(line.trim_end_matches(['\n', '\r'])) {
+            skip.push((offset, offset + line.len()));
+        }
+        offset += line.len();
+    }
+    skip
+}
+
+/// Parse abbreviation definitions and report abbreviations that are used
+/// but never defined, as JSON:
+/// `{definitions: [{abbr, expansion, line}], undefined: [{abbr, start, end}]}`
+///
+/// An "abbreviation" is any all-caps word of two or more characters.
+#[wasm_bindgen]
+pub fn check_abbreviations(content: &str) -> String {
+    let definitions = parse_definitions(content);
+    let skip = skipped_ranges(content);
+    static CAPS: OnceLock<Regex> = OnceLock::new();
+    let re_caps = cached(&CAPS, r"[A-Z][A-Z0-9]+");
+
+    let undefined = re_caps
+        .find_iter(content)
+        .filter(|m| is_whole_word(content, m.start(), m.end()))
+        .filter(|m| !in_ranges(&skip, m.start()))
+        .filter(|m| !definitions.iter().any(|d| d.abbr == m.as_str()))
+        .map(|m| AbbreviationUse {
+            abbr: m.as_str().to_string(),
+            start: m.start(),
+            end: m.end(),
+        })
+        .collect();
+
+    let report = AbbreviationReport { definitions, undefined };
+    serde_json::to_string(&report).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e))
+}
+
+/// Prepare markdown for HTML export: remove the definition lines and wrap
+/// every use of a defined abbreviation in `<abbr title="...">`.
+#[wasm_bindgen]
+pub fn expand_abbreviations(content: &str) -> String {
+    // Later definitions override earlier ones
+    let definitions: BTreeMap<String, String> = parse_definitions(content)
+        .into_iter()
+        .map(|d| (d.abbr, d.expansion))
+        .collect();
+    if definitions.is_empty() {
+        return content.to_string();
+    }
+
+    let skip = skipped_ranges(content);
+    let mut replacements: Vec<(usize, usize, String)> = Vec::new();
+    for (abbr, expansion) in &definitions {
+        for pos in find_word(content, abbr, &skip) {
+            let tag = format!("<abbr title=\"{}\">{}</abbr>", escape_html(expansion), escape_html(abbr));
+            replacements.push((pos, pos + abbr.len(), tag));
+        }
+    }
+    replacements.sort_by_key(|r| r.0);
+
+    let re = definition_regex();
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (start, end, tag) in replacements {
+        // Overlapping matches (one multi-word abbreviation containing another): keep the first
+        if start < last {
+            continue;
+        }
+        result.push_str(&content[last..start]);
+        result.push_str(&tag);
+        last = end;
+    }
+    result.push_str(&content[last..]);
+
+    let expanded = result
+        .split('\n')
+        .filter(|line| !re.is_match(line.trim_end_matches('\r')))
+        .collect::<Vec<_>>()
+        .join("\n");
+    // Drop the blank lines the definitions leave at the end, but not the
+    // final newline
+    let trimmed = expanded.trim_end();
+    if content.ends_with('\n') {
+        format!("{}\n", trimmed)
+    } else {
+        trimmed.to_string()
+    }
+}
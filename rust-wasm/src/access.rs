@@ -0,0 +1,591 @@
+// Document access control. Owners, registered with their Ed25519 keys as
+// for DocumentMetadata, give collaborators a role (read, comment or write)
+// with signed `acl_grant`, `acl_role_change` and `acl_revoke` messages.
+// Every client applies them to its AccessController and drops content
+// messages from users who may not write. Roles are only honoured for
+// messages signed with the key registered for the `user_id` they claim,
+// and no role allows writing to a document its DocumentMetadata marks as
+// expired or frozen.
+// A user's entry keeps only the newest change, so clients agree whatever
+// order the changes arrive in.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::audit::AuditLog;
+use crate::capability_token::{self, CAPABILITY_FIELD};
+use crate::comments::COMMENT_MESSAGE_TYPES;
+use crate::metadata::{write_messages, DocumentMetadata};
+use crate::payload::{decode_payload, payload_data, AclChange, Payload};
+use crate::signing;
+use crate::{data_text, decode_with_grid_tag, encode_promisegrid_payload, PromiseGridMessage};
+
+/// Message types that change a user's role
+pub(crate) const ACL_MESSAGE_TYPES: &[&str] = &["acl_grant", "acl_role_change", "acl_revoke"];
+
+/// What a user may do with a document; each role includes the ones before
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Role {
+    Read,
+    Comment,
+    Write,
+}
+
+impl Role {
+    fn parse(role: &str) -> Result<Role, JsValue> {
+        match role {
+            "read" => Ok(Role::Read),
+            "comment" => Ok(Role::Comment),
+            "write" => Ok(Role::Write),
+            other => Err(JsValue::from_str(&format!(
+                "Unknown role: {} (expected read, comment or write)",
+                other
+            ))),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Role::Read => "read",
+            Role::Comment => "comment",
+            Role::Write => "write",
+        }
+    }
+}
+
+/// The newest change to one user's role
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Grant {
+    /// None once revoked; kept so an older grant arriving late stays void
+    role: Option<Role>,
+    granted_by: String,
+    timestamp: f64,
+}
+
+#[derive(Serialize)]
+struct Member<'a> {
+    user_id: &'a str,
+    role: &'static str,
+}
+
+/// Bytes covered by the signature of an ACL message
+fn acl_signing_bytes(message_type: &str, change: &AclChange) -> Vec<u8> {
+    format!(
+        "{}\0{}\0{}\0{}\0{}\0{}",
+        message_type,
+        change.document_id,
+        change.target_user_id,
+        change.role.as_deref().unwrap_or(""),
+        change.user_id,
+        change.timestamp
+    )
+    .into_bytes()
+}
+
+/// Create a signed ACL message. `action` is "grant", "role_change" or
+/// "revoke"; `role` is "read", "comment" or "write", and ignored for a
+/// revoke. Only messages signed with the secret key of a registered owner
+/// are accepted.
+#[wasm_bindgen]
+pub fn create_acl_message(
+    action: &str,
+    document_id: &str,
+    target_user_id: &str,
+    role: &str,
+    user_id: &str,
+    secret_key: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    let message_type = format!("acl_{}", action);
+    if !ACL_MESSAGE_TYPES.contains(&message_type.as_str()) {
+        return Err(JsValue::from_str(&format!(
+            "Unknown ACL action: {} (expected grant, role_change or revoke)",
+            action
+        )));
+    }
+    let role = if action == "revoke" { None } else { Some(Role::parse(role)?.as_str().to_string()) };
+    let mut change = AclChange {
+        document_id: document_id.to_string(),
+        user_id: user_id.to_string(),
+        target_user_id: target_user_id.to_string(),
+        role,
+        timestamp: js_sys::Date::now(),
+        signature: Vec::new(),
+    };
+    change.signature = signing::sign(secret_key, &acl_signing_bytes(&message_type, &change))?;
+    Ok(encode_promisegrid_payload(&message_type, payload_data(&change)))
+}
+
+/// Who may do what with one document
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccessController {
+    document_id: String,
+    /// Owner user_id -> Ed25519 public key used to verify ACL messages.
+    /// Owners may always write.
+    owners: BTreeMap<String, Vec<u8>>,
+    /// Other user_id -> Ed25519 public key their messages are signed with
+    #[serde(default)]
+    member_keys: BTreeMap<String, Vec<u8>>,
+    grants: BTreeMap<String, Grant>,
+    /// Role of users with no grant
+    default_role: Option<Role>,
+    /// Token mode: writes need a capability token unless signed by an owner
+    #[serde(default)]
+    require_tokens: bool,
+    audit: AuditLog,
+}
+
+#[wasm_bindgen]
+impl AccessController {
+    /// A controller for `document_id` where, until roles are granted, only
+    /// owners have access
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str) -> AccessController {
+        AccessController {
+            document_id: document_id.to_string(),
+            owners: BTreeMap::new(),
+            member_keys: BTreeMap::new(),
+            grants: BTreeMap::new(),
+            default_role: None,
+            require_tokens: false,
+            audit: AuditLog::default(),
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn document_id(&self) -> String {
+        self.document_id.clone()
+    }
+
+    /// Register an owner allowed to sign ACL messages
+    pub fn add_owner(&mut self, user_id: &str, public_key: &[u8]) -> Result<(), JsValue> {
+        if public_key.len() != 32 {
+            return Err(JsValue::from_str("Owner public key must be 32 bytes"));
+        }
+        self.owners.insert(user_id.to_string(), public_key.to_vec());
+        Ok(())
+    }
+
+    pub fn remove_owner(&mut self, user_id: &str) {
+        self.owners.remove(user_id);
+    }
+
+    /// Register the Ed25519 public key a collaborator signs their messages
+    /// with (see `sign_message`). Edits and comments claiming a user_id are
+    /// accepted only when signed with the key registered for it here or as
+    /// an owner.
+    pub fn add_member_key(&mut self, user_id: &str, public_key: &[u8]) -> Result<(), JsValue> {
+        if public_key.len() != 32 {
+            return Err(JsValue::from_str("Member public key must be 32 bytes"));
+        }
+        self.member_keys.insert(user_id.to_string(), public_key.to_vec());
+        Ok(())
+    }
+
+    pub fn remove_member_key(&mut self, user_id: &str) {
+        self.member_keys.remove(user_id);
+    }
+
+    pub fn is_owner(&self, user_id: &str) -> bool {
+        self.owners.contains_key(user_id)
+    }
+
+    /// Role of users with no grant of their own: "none" (default),
+    /// "read", "comment" or "write", e.g. "read" for a link-shared
+    /// document
+    pub fn set_default_role(&mut self, role: &str) -> Result<(), JsValue> {
+        self.default_role = match role {
+            "none" => None,
+            role => Some(Role::parse(role)?),
+        };
+        Ok(())
+    }
+
+    /// In token mode every write must carry a valid capability token, or be
+    /// signed by an owner; write roles from grants and the default role
+    /// are ignored. Comments still follow roles.
+    pub fn set_require_tokens(&mut self, require: bool) {
+        self.require_tokens = require;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn require_tokens(&self) -> bool {
+        self.require_tokens
+    }
+
+    /// Apply a signed ACL message received at `now`. The signer must be a
+    /// registered owner; every attempt is audited. Returns false when a
+    /// newer change to the same user has already been applied and this one
+    /// was ignored.
+    pub fn apply_acl_message(&mut self, cbor_bytes: &[u8], now: f64) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let message_type = message.payload.message_type.as_str();
+        let (_, Payload::AclChange(change)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Err(JsValue::from_str(&format!("Unsupported access control message: {}", message_type)));
+        };
+        let role = match (message_type, &change.role) {
+            ("acl_revoke", _) => None,
+            (_, Some(role)) => Some(Role::parse(role)?),
+            (_, None) => return Err(JsValue::from_str(&format!("{} message has no role", message_type))),
+        };
+
+        let verified = change.document_id == self.document_id
+            && self.owners.get(&change.user_id).is_some_and(|public_key| {
+                signing::verify(public_key, &acl_signing_bytes(message_type, &change), &change.signature)
+            });
+        let detail = format!("{}: {}", change.target_user_id, role.map_or("none", |r| r.as_str()));
+        self.audit.record(now, &change.user_id, message_type, verified, &detail);
+        if !verified {
+            return Err(JsValue::from_str(&format!(
+                "Rejected {} for {} on {}: not signed by a document owner",
+                message_type, change.target_user_id, self.document_id
+            )));
+        }
+
+        // Newest change wins; on a tie the more restrictive role does
+        if let Some(current) = self.grants.get(&change.target_user_id) {
+            let newer = change.timestamp > current.timestamp
+                || (change.timestamp == current.timestamp && role < current.role);
+            if !newer {
+                return Ok(false);
+            }
+        }
+        self.grants.insert(
+            change.target_user_id,
+            Grant { role, granted_by: change.user_id, timestamp: change.timestamp },
+        );
+        Ok(true)
+    }
+
+    /// The user's role: "owner", "write", "comment", "read" or "none"
+    pub fn role(&self, user_id: &str) -> String {
+        if self.is_owner(user_id) {
+            return "owner".to_string();
+        }
+        self.effective_role(user_id).map_or("none", |r| r.as_str()).to_string()
+    }
+
+    pub fn can_read(&self, user_id: &str) -> bool {
+        self.allows(user_id, Role::Read)
+    }
+
+    pub fn can_comment(&self, user_id: &str) -> bool {
+        self.allows(user_id, Role::Comment)
+    }
+
+    pub fn can_edit(&self, user_id: &str) -> bool {
+        self.allows(user_id, Role::Write)
+    }
+
+    /// Check an incoming PromiseGrid message received at `now` against the
+    /// sender's role. Content-changing messages (edits and snapshots, also
+    /// when relayed in a `sync_response`) and comment messages must be for
+    /// this document and signed with the key registered for their sender.
+    /// Edits from users without write permission are rejected and audited,
+    /// unless they carry a capability token from an owner allowing them;
+    /// so are comments from users who may not comment. In token mode only
+    /// owners may edit without a token. Edits allowed by role or token are
+    /// still rejected and audited once `metadata` says the document has
+    /// expired or is frozen.
+    pub fn check_message(&mut self, cbor_bytes: &[u8], now: f64, metadata: &mut DocumentMetadata) -> Result<(), JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        self.check(message, now, metadata).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Check the capability token an incoming message of any type carries
+    /// at `now`: it must be signed by an owner of this document, issued to
+    /// the sender, unexpired and allow the message's type. Every check is
+    /// audited.
+    pub fn check_capability(&mut self, cbor_bytes: &[u8], now: f64) -> Result<(), JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let verified = capability_token::verify(&message, &self.document_id, &self.owners, now);
+        let user_id = data_text(&message.payload.data, "user_id").unwrap_or("unknown");
+        self.audit_capability(user_id, &message.payload.message_type, verified, now).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Owners and users with a role, as JSON `[{user_id, role}]`
+    pub fn members(&self) -> String {
+        let owners = self.owners.keys().map(|user_id| Member { user_id, role: "owner" });
+        let granted = self
+            .grants
+            .iter()
+            .filter(|(user_id, _)| !self.owners.contains_key(*user_id))
+            .filter_map(|(user_id, grant)| grant.role.map(|role| Member { user_id, role: role.as_str() }));
+        let members: Vec<Member> = owners.chain(granted).collect();
+        serde_json::to_string(&members).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Audit log entries as JSON
+    /// (`[{timestamp, user_id, action, allowed, detail}]`)
+    pub fn audit_log(&self) -> String {
+        serde_json::to_string(self.audit.entries()).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Serialize to CBOR for persistence alongside the document
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<AccessController, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
+
+impl AccessController {
+    fn check(&mut self, message: PromiseGridMessage, now: f64, metadata: &mut DocumentMetadata) -> Result<(), String> {
+        if metadata.document_id() != self.document_id {
+            return Err(format!("Metadata for document {} does not apply to document {}", metadata.document_id(), self.document_id));
+        }
+        let message_type = message.payload.message_type.as_str();
+        if COMMENT_MESSAGE_TYPES.contains(&message_type) {
+            let user_id = self.sender(&message, now)?;
+            if self.can_comment(&user_id) {
+                return Ok(());
+            }
+            let role = self.role(&user_id);
+            self.audit.record(now, &user_id, message_type, false, &format!("comment without permission (role: {})", role));
+            return Err(format!("User {} may not comment on document {} (role: {})", user_id, self.document_id, role));
+        }
+        write_messages(message).iter().try_for_each(|write| {
+            let user_id = self.check_write(write, now)?;
+            metadata.check_write(write, now).inspect_err(|problem| {
+                self.audit.record(now, &user_id, &write.payload.message_type, false, problem);
+            })
+        })
+    }
+
+    /// Whether the sender of a write message may write; returns their user_id
+    fn check_write(&mut self, message: &PromiseGridMessage, now: f64) -> Result<String, String> {
+        let message_type = message.payload.message_type.as_str();
+        let data = &message.payload.data;
+        let user_id = data_text(data, "user_id").unwrap_or("unknown");
+        if data.contains_key(CAPABILITY_FIELD) {
+            let verified = capability_token::verify(message, &self.document_id, &self.owners, now);
+            return self.audit_capability(user_id, message_type, verified, now).map(|()| user_id.to_string());
+        }
+        let user_id = self.sender(message, now)?;
+        if self.is_owner(&user_id) || (!self.require_tokens && self.can_edit(&user_id)) {
+            return Ok(user_id);
+        }
+        if self.require_tokens {
+            self.audit.record(now, &user_id, message_type, false, "edit without a capability token");
+            return Err(format!("User {} may not edit document {} without a capability token", user_id, self.document_id));
+        }
+        let role = self.role(&user_id);
+        let detail = format!("edit without write permission (role: {})", role);
+        self.audit.record(now, &user_id, message_type, false, &detail);
+        Err(format!("User {} may not edit document {} (role: {})", user_id, self.document_id, role))
+    }
+
+    /// The sender of a write or comment message: it must be for this
+    /// document and signed with the key registered for the user_id it
+    /// claims. Rejections are audited.
+    fn sender(&mut self, message: &PromiseGridMessage, now: f64) -> Result<String, String> {
+        let message_type = message.payload.message_type.as_str();
+        let data = &message.payload.data;
+        let user_id = data_text(data, "user_id").unwrap_or("unknown").to_string();
+        let problem = match data_text(data, "document_id") {
+            None => "no document_id".to_string(),
+            Some(document_id) if document_id != self.document_id => format!("for document {}", document_id),
+            Some(_) => match self.owners.get(&user_id).or_else(|| self.member_keys.get(&user_id)) {
+                None => "no signing key registered for the sender".to_string(),
+                Some(public_key) if !signing::verify_message(public_key, message) => {
+                    "not signed with the sender's registered key".to_string()
+                }
+                Some(_) => return Ok(user_id),
+            },
+        };
+        self.audit.record(now, &user_id, message_type, false, &problem);
+        Err(format!("Rejected {} from {} on document {}: {}", message_type, user_id, self.document_id, problem))
+    }
+
+    fn audit_capability(
+        &mut self,
+        user_id: &str,
+        message_type: &str,
+        verified: Result<String, String>,
+        now: f64,
+    ) -> Result<(), String> {
+        match verified {
+            Ok(grantor) => {
+                self.audit.record(now, user_id, message_type, true, &format!("capability token from {}", grantor));
+                Ok(())
+            }
+            Err(reason) => {
+                self.audit.record(now, user_id, message_type, false, &reason);
+                Err(format!(
+                    "User {} may not send {} to document {}: {}",
+                    user_id, message_type, self.document_id, reason
+                ))
+            }
+        }
+    }
+
+    fn effective_role(&self, user_id: &str) -> Option<Role> {
+        match self.grants.get(user_id) {
+            Some(grant) => grant.role,
+            None => self.default_role,
+        }
+    }
+
+    fn allows(&self, user_id: &str, needed: Role) -> bool {
+        self.is_owner(user_id) || self.effective_role(user_id).is_some_and(|role| role >= needed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::freeze_data;
+    use crate::payload::{ByteString, SyncResponse};
+    use crate::{encode_with_grid_tag, MessagePayload, PROTOCOL_HASH};
+    use ciborium::Value;
+
+    fn message(message_type: &str, fields: &[(&str, Value)]) -> PromiseGridMessage {
+        PromiseGridMessage {
+            protocol_hash: PROTOCOL_HASH.to_string(),
+            payload: MessagePayload {
+                message_type: message_type.to_string(),
+                data: fields.iter().map(|(key, value)| (key.to_string(), value.clone())).collect(),
+            },
+        }
+    }
+
+    const OWNER_KEY: [u8; 32] = [7; 32];
+    const VIEWER_KEY: [u8; 32] = [9; 32];
+
+    fn signed(mut message: PromiseGridMessage, secret_key: &[u8]) -> PromiseGridMessage {
+        let signature = signing::sign(secret_key, &signing::message_signing_bytes(&message).unwrap()).unwrap();
+        message.payload.data.insert(signing::SENDER_SIGNATURE_FIELD.into(), Value::Bytes(signature));
+        message
+    }
+
+    fn snapshot(user_id: &str) -> PromiseGridMessage {
+        message(
+            "document_snapshot",
+            &[
+                ("document_id", Value::Text("doc".into())),
+                ("user_id", Value::Text(user_id.into())),
+                ("content", Value::Text("replaced".into())),
+            ],
+        )
+    }
+
+    fn edit(fields: &[(&str, Value)]) -> PromiseGridMessage {
+        let mut data = vec![("edit_type", Value::Text("insert".into())), ("content", Value::Text("x".into()))];
+        data.extend(fields.iter().cloned());
+        message("document_edit", &data)
+    }
+
+    fn controller() -> AccessController {
+        let mut access = AccessController::new("doc");
+        access.set_default_role("read").unwrap();
+        access.add_owner("owner", &signing::signing_public_key(&OWNER_KEY).unwrap()).unwrap();
+        access.add_member_key("viewer", &signing::signing_public_key(&VIEWER_KEY).unwrap()).unwrap();
+        access
+    }
+
+    fn check(access: &mut AccessController, message: PromiseGridMessage) -> Result<(), String> {
+        access.check(message, 1.0, &mut DocumentMetadata::new("doc"))
+    }
+
+    #[test]
+    fn viewer_snapshot_is_refused() {
+        let mut access = controller();
+        let refused = check(&mut access, signed(snapshot("viewer"), &VIEWER_KEY)).unwrap_err();
+        assert!(refused.contains("may not edit document doc"), "{}", refused);
+        assert!(access.audit_log().contains("document_snapshot"));
+        assert!(check(&mut access, signed(snapshot("owner"), &OWNER_KEY)).is_ok());
+    }
+
+    #[test]
+    fn relayed_viewer_snapshot_is_refused() {
+        let relay = |relayed: PromiseGridMessage| {
+            let response = SyncResponse {
+                user_id: "owner".into(),
+                target_user_id: "viewer".into(),
+                target_session: "session".into(),
+                operations: vec![ByteString(encode_with_grid_tag(&relayed).unwrap())],
+                complete: true,
+                more: false,
+                timestamp: 1.0,
+            };
+            PromiseGridMessage {
+                protocol_hash: PROTOCOL_HASH.to_string(),
+                payload: MessagePayload { message_type: "sync_response".into(), data: payload_data(&response) },
+            }
+        };
+        let mut access = controller();
+        assert!(check(&mut access, relay(signed(snapshot("viewer"), &VIEWER_KEY))).is_err());
+        assert!(check(&mut access, relay(signed(snapshot("owner"), &OWNER_KEY))).is_ok());
+    }
+
+    #[test]
+    fn spoofed_owner_edit_is_refused() {
+        let mut access = controller();
+        let owner = [("document_id", Value::Text("doc".into())), ("user_id", Value::Text("owner".into()))];
+        assert!(check(&mut access, edit(&owner)).is_err());
+        let refused = check(&mut access, signed(edit(&owner), &VIEWER_KEY)).unwrap_err();
+        assert!(refused.contains("not signed with the sender's registered key"), "{}", refused);
+        assert!(check(&mut access, signed(edit(&owner), &OWNER_KEY)).is_ok());
+    }
+
+    #[test]
+    fn edit_without_this_document_id_is_refused() {
+        let mut access = controller();
+        let missing = signed(edit(&[("user_id", Value::Text("owner".into()))]), &OWNER_KEY);
+        let refused = check(&mut access, missing).unwrap_err();
+        assert!(refused.contains("no document_id"), "{}", refused);
+        let other = [("document_id", Value::Text("other".into())), ("user_id", Value::Text("owner".into()))];
+        assert!(check(&mut access, signed(edit(&other), &OWNER_KEY)).is_err());
+    }
+
+    #[test]
+    fn token_mode_ignores_write_roles() {
+        let mut access = controller();
+        access.set_default_role("write").unwrap();
+        let viewer = [("document_id", Value::Text("doc".into())), ("user_id", Value::Text("viewer".into()))];
+        assert!(check(&mut access, signed(edit(&viewer), &VIEWER_KEY)).is_ok());
+        access.set_require_tokens(true);
+        let refused = check(&mut access, signed(edit(&viewer), &VIEWER_KEY)).unwrap_err();
+        assert!(refused.contains("without a capability token"), "{}", refused);
+        let owner = [("document_id", Value::Text("doc".into())), ("user_id", Value::Text("owner".into()))];
+        assert!(check(&mut access, signed(edit(&owner), &OWNER_KEY)).is_ok());
+    }
+
+    #[test]
+    fn expired_document_refuses_edits() {
+        let mut access = controller();
+        let mut metadata = DocumentMetadata::new("doc");
+        metadata.set_expiry(10.0, None).unwrap();
+        let owner = [("document_id", Value::Text("doc".into())), ("user_id", Value::Text("owner".into()))];
+        assert!(access.check(signed(edit(&owner), &OWNER_KEY), 5.0, &mut metadata).is_ok());
+        let refused = access.check(signed(edit(&owner), &OWNER_KEY), 10.0, &mut metadata).unwrap_err();
+        assert!(refused.contains("expired at 10"), "{}", refused);
+        assert!(access.audit_log().contains("expired at 10"));
+    }
+
+    #[test]
+    fn frozen_document_refuses_edits() {
+        let mut access = controller();
+        let mut metadata = DocumentMetadata::new("doc");
+        metadata.add_owner("owner", &signing::signing_public_key(&OWNER_KEY).unwrap()).unwrap();
+        let data = freeze_data("doc", true, "litigation", "owner", &OWNER_KEY, 1.0).unwrap();
+        let freeze = PromiseGridMessage {
+            protocol_hash: PROTOCOL_HASH.to_string(),
+            payload: MessagePayload { message_type: "document_freeze".into(), data },
+        };
+        metadata.apply_control_message(&encode_with_grid_tag(&freeze).unwrap(), 1.0).unwrap();
+        let owner = [("document_id", Value::Text("doc".into())), ("user_id", Value::Text("owner".into()))];
+        let refused = access.check(signed(edit(&owner), &OWNER_KEY), 2.0, &mut metadata).unwrap_err();
+        assert!(refused.contains("frozen"), "{}", refused);
+        assert!(metadata.audit_log().contains("edit attempted during legal hold"));
+    }
+}
+
@@ -0,0 +1,190 @@
+// Workspace activity log and the markdown digest built from it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::headings::{parse_headings, plain_heading_text};
+use crate::journal::civil_from_days;
+use crate::locale::LocaleInfo;
+use crate::performance;
+
+/// Oldest events are dropped once the log grows past this
+pub(crate) const MAX_EVENTS: usize = 5_000;
+/// Revision summaries kept per document (fewer under a reduced
+/// performance profile)
+pub(crate) const MAX_REVISIONS: usize = 50;
+
+/// Something that happened in the workspace
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ActivityEvent {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: f64,
+    pub user_id: String,
+    pub document_id: String,
+    /// "edited", "renamed", "moved", "deleted", "restored", "comment" or
+    /// "mention"
+    pub kind: String,
+    /// Kind-specific text: new title, folder, comment text, mentioned user
+    pub detail: String,
+}
+
+/// Compact summary of a document version, enough to describe how the
+/// document changed without keeping its content
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Revision {
+    timestamp: f64,
+    headings: Vec<String>,
+    words: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct ActivityLog {
+    events: Vec<ActivityEvent>,
+    revisions: BTreeMap<String, Vec<Revision>>,
+}
+
+impl ActivityLog {
+    pub fn record(&mut self, timestamp: f64, user_id: &str, document_id: &str, kind: &str, detail: &str) {
+        if self.events.len() >= MAX_EVENTS {
+            self.events.remove(0);
+        }
+        self.events.push(ActivityEvent {
+            timestamp,
+            user_id: user_id.to_string(),
+            document_id: document_id.to_string(),
+            kind: kind.to_string(),
+            detail: detail.to_string(),
+        });
+    }
+
+    /// Remember the outline and size of a document version
+    pub fn record_revision(&mut self, timestamp: f64, document_id: &str, content: &str) {
+        let revisions = self.revisions.entry(document_id.to_string()).or_default();
+        // The performance profile may have lowered the limit since the last
+        // revision, so trim down to it rather than dropping just one
+        let keep = performance::current().max_revisions().saturating_sub(1);
+        if revisions.len() > keep {
+            revisions.drain(..revisions.len() - keep);
+        }
+        revisions.push(Revision {
+            timestamp,
+            headings: parse_headings(content).iter().map(|h| plain_heading_text(&h.text)).collect(),
+            words: content.split_whitespace().count(),
+        });
+    }
+
+    pub fn forget(&mut self, document_id: &str) {
+        self.events.retain(|e| e.document_id != document_id);
+        self.revisions.remove(document_id);
+    }
+
+    /// Headings added and removed and the word count change since `since`:
+    /// the last revision before `since` compared with the latest one
+    fn headline_diff(&self, document_id: &str, since: f64) -> Option<(Vec<&str>, Vec<&str>, i64)> {
+        let revisions = self.revisions.get(document_id)?;
+        let latest = revisions.last().filter(|r| r.timestamp >= since)?;
+        let base = revisions.iter().rev().find(|r| r.timestamp < since);
+        let before: BTreeSet<&str> = base.map(|r| r.headings.iter().map(String::as_str).collect()).unwrap_or_default();
+        let after: BTreeSet<&str> = latest.headings.iter().map(String::as_str).collect();
+        let words = latest.words as i64 - base.map_or(0, |r| r.words as i64);
+        Some((
+            after.difference(&before).copied().collect(),
+            before.difference(&after).copied().collect(),
+            words,
+        ))
+    }
+
+    /// Markdown digest of everything since `since`, using `title` to name
+    /// documents and `locale` to format numbers
+    pub fn digest(&self, since: f64, locale: &LocaleInfo, title: impl Fn(&str) -> String) -> String {
+        let events: Vec<&ActivityEvent> = self.events.iter().filter(|e| e.timestamp >= since).collect();
+        let mut out = format!("# Activity since {}\n\n", format_timestamp(since));
+        if events.is_empty() {
+            out.push_str("_No activity._\n");
+            return out;
+        }
+
+        // Group document changes, keeping first-seen order
+        let mut order: Vec<&str> = Vec::new();
+        let mut changes: BTreeMap<&str, Vec<&ActivityEvent>> = BTreeMap::new();
+        for event in events.iter().filter(|e| !matches!(e.kind.as_str(), "comment" | "mention")) {
+            if !changes.contains_key(event.document_id.as_str()) {
+                order.push(&event.document_id);
+            }
+            changes.entry(&event.document_id).or_default().push(event);
+        }
+
+        if !order.is_empty() {
+            out.push_str("## Documents changed\n\n");
+        }
+        for document_id in order {
+            let doc_events = &changes[document_id];
+            let edits: Vec<&&ActivityEvent> = doc_events.iter().filter(|e| e.kind == "edited").collect();
+            let editors: BTreeSet<&str> = edits.iter().map(|e| e.user_id.as_str()).collect();
+            let editors = editors.into_iter().collect::<Vec<_>>().join(", ");
+            out.push_str(&format!("### {}\n\n", title(document_id)));
+            match edits.len() {
+                0 => {}
+                1 => out.push_str(&format!("- 1 edit by {}\n", editors)),
+                n => out.push_str(&format!("- {} edits by {}\n", locale.format_integer(n as i64), editors)),
+            }
+            for event in doc_events {
+                let line = match event.kind.as_str() {
+                    "renamed" => format!("- Renamed to \"{}\" by {}\n", event.detail, event.user_id),
+                    "moved" if event.detail.is_empty() => format!("- Moved to the top level by {}\n", event.user_id),
+                    "moved" => format!("- Moved to `{}` by {}\n", event.detail, event.user_id),
+                    "deleted" => format!("- Moved to the trash by {}\n", event.user_id),
+                    "restored" => format!("- Restored from the trash by {}\n", event.user_id),
+                    _ => continue,
+                };
+                out.push_str(&line);
+            }
+            if let Some((added, removed, words)) = self.headline_diff(document_id, since) {
+                if !added.is_empty() {
+                    out.push_str(&format!("- New sections: {}\n", quoted_list(&added)));
+                }
+                if !removed.is_empty() {
+                    out.push_str(&format!("- Removed sections: {}\n", quoted_list(&removed)));
+                }
+                if words != 0 {
+                    let sign = if words > 0 { "+" } else { "" };
+                    out.push_str(&format!("- {}{} words\n", sign, locale.format_integer(words)));
+                }
+            }
+            out.push('\n');
+        }
+
+        let comments: Vec<&&ActivityEvent> = events.iter().filter(|e| e.kind == "comment").collect();
+        if !comments.is_empty() {
+            out.push_str("## New comments\n\n");
+            for c in comments {
+                out.push_str(&format!("- **{}** on *{}*: {}\n", c.user_id, title(&c.document_id), c.detail));
+            }
+            out.push('\n');
+        }
+        let mentions: Vec<&&ActivityEvent> = events.iter().filter(|e| e.kind == "mention").collect();
+        if !mentions.is_empty() {
+            out.push_str("## Mentions\n\n");
+            for m in mentions {
+                out.push_str(&format!("- **{}** mentioned @{} in *{}*\n", m.user_id, m.detail, title(&m.document_id)));
+            }
+            out.push('\n');
+        }
+        out.trim_end().to_string() + "\n"
+    }
+}
+
+fn quoted_list(items: &[&str]) -> String {
+    items.iter().map(|i| format!("\"{}\"", i)).collect::<Vec<_>>().join(", ")
+}
+
+/// Format ms since the Unix epoch as `YYYY-MM-DD HH:MM UTC`
+pub(crate) fn format_timestamp(ms: f64) -> String {
+    let secs = (ms / 1000.0).floor() as i64;
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+
+    let date = civil_from_days(days);
+
+    format!("{} {:02}:{:02} UTC", date, rem / 3600, rem % 3600 / 60)
+}
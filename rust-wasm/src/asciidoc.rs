@@ -0,0 +1,190 @@
+// AsciiDoc export for publishing with Antora. Headings become `=`
+// sections, code blocks `[source]` listings, alert blockquotes admonition
+// blocks, tables `|===` blocks and wiki links `xref:` cross references.
+
+use wasm_bindgen::prelude::*;
+use std::collections::HashMap;
+
+use crate::ast::{alert, inline_text, parse_markdown, Align, Block, Inline, ListItem};
+
+/// Escape characters that would open constrained formatting at the start
+/// of a word
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut previous: Option<char> = None;
+    for c in text.chars() {
+        let word_start = previous.is_none_or(|p| !p.is_alphanumeric());
+        if matches!(c, '*' | '_' | '`' | '#' | '+') && word_start {
+            out.push('\\');
+        }
+        out.push(c);
+        previous = Some(c);
+    }
+    out
+}
+
+/// Text inside a macro's `[...]` attribute list
+fn macro_text(text: &str) -> String {
+    text.replace(']', "\\]")
+}
+
+struct Writer<'a> {
+    /// Footnote bodies by label, written inline at the reference
+    footnotes: HashMap<&'a str, &'a [Block]>,
+}
+
+impl Writer<'_> {
+    fn inlines(&self, items: &[Inline]) -> String {
+        let mut out = String::new();
+        for inline in items {
+            match inline {
+                Inline::Text { text } => out.push_str(&escape(text)),
+                Inline::Code { code } => out.push_str(&format!("`+{}+`", code)),
+                Inline::Emphasis { content } => out.push_str(&format!("_{}_", self.inlines(content))),
+                Inline::Strong { content } => out.push_str(&format!("*{}*", self.inlines(content))),
+                Inline::Strikethrough { content } => out.push_str(&format!("[.line-through]#{}#", self.inlines(content))),
+                Inline::Underline { content } => out.push_str(&format!("[.underline]#{}#", self.inlines(content))),
+                Inline::Link { url, content, .. } => {
+                    out.push_str(&format!("link:{}[{}]", url.replace(' ', "%20"), macro_text(&self.inlines(content))))
+                }
+                Inline::Image { url, alt, .. } => out.push_str(&format!("image:{}[{}]", url.replace(' ', "%20"), macro_text(alt))),
+                Inline::Math { tex } => out.push_str(&format!("latexmath:[{}]", macro_text(tex))),
+                Inline::FootnoteRef { label } => {
+                    let body = self.footnotes.get(label.as_str()).map(|b| self.footnote_text(b)).unwrap_or_default();
+                    out.push_str(&format!("footnote:[{}]", macro_text(&body)));
+                }
+                Inline::WikiLink { target, anchor, alias } => {
+                    let anchor = anchor.as_ref().map(|a| format!("#{}", a)).unwrap_or_default();
+                    let text = alias.as_deref().unwrap_or(target);
+                    out.push_str(&format!("xref:{}.adoc{}[{}]", target.replace(' ', "-"), anchor, macro_text(text)));
+                }
+                Inline::Html { html } => out.push_str(&format!("pass:[{}]", macro_text(html))),
+                Inline::SoftBreak => out.push('\n'),
+                Inline::LineBreak => out.push_str(" +\n"),
+            }
+        }
+        out
+    }
+
+    /// A footnote's body flattened onto one line
+    fn footnote_text(&self, blocks: &[Block]) -> String {
+        let paragraphs: Vec<String> = blocks
+            .iter()
+            .map(|b| match b {
+                Block::Paragraph { content } => self.inlines(content).replace('\n', " "),
+                other => escape(&inline_text(&block_inlines(other))),
+            })
+            .collect();
+        paragraphs.join(" ")
+    }
+
+    /// `depth` is the list nesting level, which sets the marker length
+    fn list(&self, ordered: bool, items: &[ListItem], depth: usize) -> String {
+        let marker = if ordered { ".".repeat(depth) } else { "*".repeat(depth) };
+        items.iter().map(|i| self.item(i, &marker, depth)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn item(&self, item: &ListItem, marker: &str, depth: usize) -> String {
+        let task = match item.checked {
+            Some(true) => "[x] ",
+            Some(false) => "[ ] ",
+            None => "",
+        };
+        let mut out = format!("{} {}", marker, task);
+        for (n, block) in item.blocks.iter().enumerate() {
+            match block {
+                Block::List { ordered, items, .. } => {
+                    out.push('\n');
+                    out.push_str(&self.list(*ordered, items, depth + 1));
+                }
+                // Further blocks are attached to the item with `+`
+                _ if n > 0 => {
+                    out.push_str("\n+\n");
+                    out.push_str(&self.block(block, depth));
+                }
+                _ => out.push_str(&self.block(block, depth)),
+            }
+        }
+        out.trim_end().to_string()
+    }
+
+    fn table(&self, align: &[Align], header: &[Vec<Inline>], rows: &[Vec<Vec<Inline>>]) -> String {
+        let cols: Vec<&str> = (0..header.len())
+            .map(|n| match align.get(n) {
+                Some(Align::Center) => "^1",
+                Some(Align::Right) => ">1",
+                _ => "1",
+            })
+            .collect();
+        let row = |cells: &[Vec<Inline>]| {
+            cells.iter().map(|c| format!("| {}", self.inlines(c).replace('\n', " ").replace('|', "\\|"))).collect::<Vec<_>>().join(" ")
+        };
+        let mut out = vec![format!("[%header,cols=\"{}\"]", cols.join(",")), "|===".to_string(), row(header)];
+        out.extend(rows.iter().map(|r| row(r)));
+        out.push("|===".to_string());
+        out.join("\n")
+    }
+
+    fn block(&self, block: &Block, depth: usize) -> String {
+        match block {
+            Block::Heading { level, content } => {
+                format!("{} {}", "=".repeat(*level as usize), self.inlines(content).replace('\n', " "))
+            }
+            Block::Paragraph { content } => match content.as_slice() {
+                [Inline::Image { url, alt, .. }] => format!("image::{}[{}]", url.replace(' ', "%20"), macro_text(alt)),
+                _ => self.inlines(content),
+            },
+            Block::CodeBlock { language, code } if language.is_empty() => format!("----\n{}\n----", code),
+            Block::CodeBlock { language, code } => format!("[source,{}]\n----\n{}\n----", language, code),
+            Block::BlockQuote { blocks } => match alert(blocks) {
+                Some((kind, body)) => format!("[{}]\n====\n{}\n====", kind.to_ascii_uppercase(), self.blocks(&body, depth)),
+                None => format!("____\n{}\n____", self.blocks(blocks, depth)),
+            },
+            Block::List { ordered, items, .. } => self.list(*ordered, items, depth + 1),
+            Block::Table { align, header, rows } => self.table(align, header, rows),
+            Block::ThematicBreak => "'''".to_string(),
+            Block::Html { html } => format!("++++\n{}\n++++", html),
+            Block::MathBlock { tex } => format!("[latexmath]\n++++\n{}\n++++", tex),
+            // Written inline at the reference
+            Block::FootnoteDefinition { .. } => String::new(),
+        }
+    }
+
+    fn blocks(&self, blocks: &[Block], depth: usize) -> String {
+        blocks.iter().map(|b| self.block(b, depth)).filter(|b| !b.is_empty()).collect::<Vec<_>>().join("\n\n")
+    }
+}
+
+/// Inline content of a block other than a paragraph, for flattening
+fn block_inlines(block: &Block) -> Vec<Inline> {
+    match block {
+        Block::Heading { content, .. } | Block::Paragraph { content } => content.clone(),
+        Block::CodeBlock { code, .. } => vec![Inline::Code { code: code.clone() }],
+        _ => Vec::new(),
+    }
+}
+
+/// Export markdown as AsciiDoc for Antora. Headings become `=` sections
+/// by level, fenced code `[source,<language>]` listings, tables `|===`
+/// blocks with their column alignment, GitHub-style alerts (`> [!NOTE]`)
+/// `[NOTE]` admonition blocks, math `latexmath`, footnotes inline
+/// `footnote:[...]` macros and wiki links `xref:` to the page's `.adoc`
+/// file. Front matter is dropped.
+#[wasm_bindgen]
+pub fn export_asciidoc(content: &str) -> String {
+    let doc = parse_markdown(content);
+    let footnotes = doc
+        .blocks
+        .iter()
+        .filter_map(|b| match b {
+            Block::FootnoteDefinition { label, blocks } => Some((label.as_str(), blocks.as_slice())),
+            _ => None,
+        })
+        .collect();
+    let writer = Writer { footnotes };
+    let mut out = writer.blocks(&doc.blocks, 0);
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,1207 @@
+// Markdown document tree shared by the converters: a block parser and an
+// inline parser producing `Block`/`Inline` nodes, and a serializer that
+// writes a tree back out as markdown. The dialect is what the editor
+// supports: CommonMark blocks plus tables, task lists, strikethrough,
+// footnotes, `$` math, `<u>` underline and `[[wiki links]]`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::front_matter::find_front_matter;
+use crate::headings::{parse_atx, setext_level};
+use crate::links::{find_closing, normalize_label, reference_map, ReferenceDef};
+use crate::url::{href_for, url_len};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Align {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ListItem {
+    /// `Some` for task list items
+    pub checked: Option<bool>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum Block {
+    Heading { level: u8, content: Vec<Inline> },
+    Paragraph { content: Vec<Inline> },
+    CodeBlock { language: String, code: String },
+    BlockQuote { blocks: Vec<Block> },
+    List { ordered: bool, start: u64, items: Vec<ListItem> },
+    Table { align: Vec<Align>, header: Vec<Vec<Inline>>, rows: Vec<Vec<Vec<Inline>>> },
+    ThematicBreak,
+    Html { html: String },
+    MathBlock { tex: String },
+    FootnoteDefinition { label: String, blocks: Vec<Block> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Inline {
+    Text { text: String },
+    Code { code: String },
+    Emphasis { content: Vec<Inline> },
+    Strong { content: Vec<Inline> },
+    Strikethrough { content: Vec<Inline> },
+    Underline { content: Vec<Inline> },
+    Link { url: String, title: Option<String>, content: Vec<Inline> },
+    Image { url: String, title: Option<String>, alt: String },
+    Math { tex: String },
+    FootnoteRef { label: String },
+    WikiLink { target: String, anchor: Option<String>, alias: Option<String> },
+    Html { html: String },
+    SoftBreak,
+    LineBreak,
+}
+
+/// A parsed document
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub(crate) struct Document {
+    /// Raw YAML front matter, without the `---` lines
+    pub front_matter: Option<String>,
+    pub blocks: Vec<Block>,
+    /// 1-based source line of each top-level block
+    #[serde(skip)]
+    pub lines: Vec<usize>,
+}
+
+fn text(s: &str) -> Inline {
+    Inline::Text { text: s.to_string() }
+}
+
+/// Plain text of inline content (alt text, comparisons)
+pub(crate) fn inline_text(inlines: &[Inline]) -> String {
+    let mut out = String::new();
+    for inline in inlines {
+        match inline {
+            Inline::Text { text } | Inline::Code { code: text } | Inline::Math { tex: text } => out.push_str(text),
+            Inline::Emphasis { content }
+            | Inline::Strong { content }
+            | Inline::Strikethrough { content }
+            | Inline::Underline { content }
+            | Inline::Link { content, .. } => out.push_str(&inline_text(content)),
+            Inline::Image { alt, .. } => out.push_str(alt),
+            Inline::WikiLink { target, alias, .. } => out.push_str(alias.as_deref().unwrap_or(target)),
+            Inline::SoftBreak | Inline::LineBreak => out.push(' '),
+            Inline::FootnoteRef { .. } | Inline::Html { .. } => {}
+        }
+    }
+    out
+}
+
+/// Footnotes in the order the renderers number and list them: by first
+/// reference in the document, then definitions that are never referenced,
+/// then footnotes first referenced inside another footnote's definition
+pub(crate) struct Footnotes<'a> {
+    /// Labels in number order, each with its definition (empty when the
+    /// label is never defined)
+    order: Vec<(&'a str, &'a [Block])>,
+}
+
+impl<'a> Footnotes<'a> {
+    pub fn new(blocks: &'a [Block]) -> Self {
+        let definitions: HashMap<&str, &[Block]> = blocks
+            .iter()
+            .filter_map(|b| match b {
+                Block::FootnoteDefinition { label, blocks } => Some((label.as_str(), blocks.as_slice())),
+                _ => None,
+            })
+            .collect();
+        let mut labels = Vec::new();
+        footnote_refs(blocks, &mut labels);
+        for block in blocks {
+            if let Block::FootnoteDefinition { label, .. } = block {
+                if !labels.contains(&label.as_str()) {
+                    labels.push(label);
+                }
+            }
+        }
+        let mut n = 0;
+        while n < labels.len() {
+            if let Some(body) = definitions.get(labels[n]) {
+                footnote_refs(body, &mut labels);
+            }
+            n += 1;
+        }
+        let order = labels.into_iter().map(|l| (l, definitions.get(l).copied().unwrap_or(&[]))).collect();
+        Footnotes { order }
+    }
+
+    /// 1-based number of a footnote
+    pub fn number(&self, label: &str) -> Option<usize> {
+        self.order.iter().position(|(l, _)| *l == label).map(|n| n + 1)
+    }
+
+    /// Labels and definitions in number order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &'a str, &'a [Block])> + '_ {
+        self.order.iter().enumerate().map(|(n, (label, body))| (n + 1, *label, *body))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+/// Footnote labels referenced in `blocks`, outside footnote definitions,
+/// appended to `out` in order of first reference
+fn footnote_refs<'a>(blocks: &'a [Block], out: &mut Vec<&'a str>) {
+    fn inlines<'a>(content: &'a [Inline], out: &mut Vec<&'a str>) {
+        for inline in content {
+            match inline {
+                Inline::FootnoteRef { label } if !out.contains(&label.as_str()) => out.push(label),
+                Inline::Emphasis { content }
+                | Inline::Strong { content }
+                | Inline::Strikethrough { content }
+                | Inline::Underline { content }
+                | Inline::Link { content, .. } => inlines(content, out),
+                _ => {}
+            }
+        }
+    }
+    for block in blocks {
+        match block {
+            Block::Heading { content, .. } | Block::Paragraph { content } => inlines(content, out),
+            Block::BlockQuote { blocks } => footnote_refs(blocks, out),
+            Block::List { items, .. } => items.iter().for_each(|i| footnote_refs(&i.blocks, out)),
+            Block::Table { header, rows, .. } => header.iter().chain(rows.iter().flatten()).for_each(|c| inlines(c, out)),
+            _ => {}
+        }
+    }
+}
+
+/// Kinds of GitHub-style alert blockquote (`> [!NOTE]`)
+pub(crate) const ALERT_KINDS: &[&str] = &["note", "tip", "important", "warning", "caution"];
+
+/// The kind and body of a blockquote written as an alert: its first line
+/// is `[!NOTE]`, `[!TIP]`, `[!IMPORTANT]`, `[!WARNING]` or `[!CAUTION]`
+pub(crate) fn alert(blocks: &[Block]) -> Option<(&'static str, Vec<Block>)> {
+    let Some(Block::Paragraph { content }) = blocks.first() else {
+        return None;
+    };
+    let line_end = content.iter().position(|i| matches!(i, Inline::SoftBreak | Inline::LineBreak)).unwrap_or(content.len());
+    let marker = inline_text(&content[..line_end]);
+    let name = marker.trim().strip_prefix("[!")?.strip_suffix(']')?.to_ascii_lowercase();
+    let kind = ALERT_KINDS.iter().find(|k| **k == name)?;
+    let rest: Vec<Inline> = content.iter().skip(line_end + 1).cloned().collect();
+    let mut body = Vec::with_capacity(blocks.len());
+    if !rest.is_empty() {
+        body.push(Block::Paragraph { content: rest });
+    }
+    body.extend(blocks[1..].iter().cloned());
+    Some((kind, body))
+}
+
+// ---------------------------------------------------------------------------
+// Block parsing
+
+/// A source line with the prefix of enclosing containers removed
+type Line = (usize, String);
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// Opening fence: marker char, fence length and info string
+fn fence_open(line: &str) -> Option<(char, usize, String)> {
+    if indent_of(line) > 3 {
+        return None;
+    }
+    let body = line.trim_start();
+    let marker = body.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = body.chars().take_while(|c| *c == marker).count();
+    let info = body[len..].trim();
+    (len >= 3 && !(marker == '`' && info.contains('`'))).then(|| (marker, len, info.to_string()))
+}
+
+fn is_fence_close(line: &str, marker: char, len: usize) -> bool {
+    let body = line.trim();
+    indent_of(line) <= 3 && body.len() >= len && body.chars().all(|c| c == marker)
+}
+
+fn is_thematic_break(line: &str) -> bool {
+    if indent_of(line) > 3 {
+        return false;
+    }
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && matches!(chars[0], '-' | '*' | '_') && chars.iter().all(|c| *c == chars[0])
+}
+
+/// List marker: ordered, start number, marker char and content offset
+fn list_marker(line: &str) -> Option<(bool, u64, char, usize)> {
+    let indent = indent_of(line);
+    if indent > 3 {
+        return None;
+    }
+    let body = &line[indent..];
+    let first = body.chars().next()?;
+    let (ordered, number, marker, width) = if matches!(first, '-' | '*' | '+') {
+        (false, 1, first, 1)
+    } else {
+        let digits = body.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 || digits > 9 {
+            return None;
+        }
+        let delim = body[digits..].chars().next().filter(|c| *c == '.' || *c == ')')?;
+        (true, body[..digits].parse().ok()?, delim, digits + 1)
+    };
+    let rest = &body[width..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let spaces = rest.len() - rest.trim_start_matches(' ').len();
+    let spaces = if rest.trim().is_empty() || spaces > 4 { 1 } else { spaces };
+    Some((ordered, number, marker, indent + width + spaces))
+}
+
+fn is_table_delimiter(line: &str) -> bool {
+    let cells = split_row(line);
+    line.contains('-')
+        && !cells.is_empty()
+        && cells.iter().all(|c| {
+            let c = c.trim();
+            let inner = c.trim_start_matches(':').trim_end_matches(':');
+            !inner.is_empty() && inner.chars().all(|ch| ch == '-')
+        })
+}
+
+/// Cells of a pipe table row (escaped `\|` stays in the cell)
+fn split_row(line: &str) -> Vec<String> {
+    let trimmed = line.trim();
+    let trimmed = trimmed.strip_prefix('|').unwrap_or(trimmed);
+    let trimmed = if trimmed.ends_with('|') && !trimmed.ends_with("\\|") {
+        &trimmed[..trimmed.len() - 1]
+    } else {
+        trimmed
+    };
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = trimmed.chars().peekable();
+    let mut in_code = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '`' => {
+                in_code = !in_code;
+                cell.push(c);
+            }
+            '|' if !in_code => cells.push(std::mem::take(&mut cell)),
+            _ => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    cells.into_iter().map(|c| c.trim().to_string()).collect()
+}
+
+const HTML_BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "details", "dialog", "div", "dl", "fieldset", "figcaption",
+    "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "iframe", "main", "nav",
+    "ol", "p", "pre", "script", "section", "style", "summary", "table", "ul", "video", "audio", "center",
+];
+
+fn is_html_block_start(line: &str) -> bool {
+    if indent_of(line) > 3 {
+        return false;
+    }
+    let body = line.trim_start();
+    if body.starts_with("<!--") {
+        return true;
+    }
+    let Some(rest) = body.strip_prefix('<') else {
+        return false;
+    };
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    let name: String = rest.chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+    let after = rest[name.len()..].chars().next();
+    HTML_BLOCK_TAGS.contains(&name.to_ascii_lowercase().as_str())
+        && matches!(after, None | Some(' ') | Some('>') | Some('/') | Some('\t'))
+}
+
+fn is_reference_definition(line: &str) -> bool {
+    let body = line.trim_start();
+    if indent_of(line) > 3 || !body.starts_with('[') || body.starts_with("[^") {
+        return false;
+    }
+    find_closing(body, 0, b'[', b']')
+        .is_some_and(|close| close > 1 && body[close + 1..].starts_with(':') && !body[close + 2..].trim().is_empty())
+}
+
+/// `[^label]: text` footnote definition: label and the text after the colon
+fn footnote_definition(line: &str) -> Option<(String, String)> {
+    let body = line.strip_prefix("[^")?;
+    let close = body.find("]:")?;
+    let label = &body[..close];
+    (!label.is_empty() && !label.contains([' ', ']'])).then(|| (label.to_string(), body[close + 2..].trim().to_string()))
+}
+
+/// Whether `line` ends a paragraph by starting a new block
+fn interrupts_paragraph(line: &str) -> bool {
+    fence_open(line).is_some()
+        || parse_atx(line).is_some()
+        || is_thematic_break(line)
+        || line.trim_start().starts_with('>')
+        || line.trim_start().starts_with("$$")
+        || is_html_block_start(line)
+        || list_marker(line).is_some_and(|(ordered, number, _, offset)| {
+            (!ordered || number == 1) && !line[offset.min(line.len())..].trim().is_empty()
+        })
+}
+
+struct BlockParser<'a> {
+    refs: &'a HashMap<String, ReferenceDef>,
+}
+
+impl BlockParser<'_> {
+    fn parse(&self, lines: &[Line]) -> Vec<(usize, Block)> {
+        let mut blocks = Vec::new();
+        let mut i = 0;
+
+        while i < lines.len() {
+            let (number, line) = (&lines[i].0, lines[i].1.as_str());
+            let number = *number;
+            if is_blank(line) {
+                i += 1;
+                continue;
+            }
+
+            // Fenced code
+            if let Some((marker, len, info)) = fence_open(line) {
+                let indent = indent_of(line);
+                let mut code = Vec::new();
+                i += 1;
+                while i < lines.len() && !is_fence_close(&lines[i].1, marker, len) {
+                    let l = &lines[i].1;
+                    code.push(&l[indent_of(l).min(indent)..]);
+                    i += 1;
+                }
+                i += 1;
+                let language = info.split_whitespace().next().unwrap_or("").to_string();
+                blocks.push((number, Block::CodeBlock { language, code: code.join("\n") }));
+                continue;
+            }
+
+            // Indented code
+            if indent_of(line) >= 4 {
+                let mut code: Vec<&str> = Vec::new();
+                while i < lines.len() && (indent_of(&lines[i].1) >= 4 || is_blank(&lines[i].1)) {
+                    let l = lines[i].1.as_str();
+                    code.push(if is_blank(l) { "" } else { &l[4..] });
+                    i += 1;
+                }
+                while code.last() == Some(&"") {
+                    code.pop();
+                }
+                blocks.push((number, Block::CodeBlock { language: String::new(), code: code.join("\n") }));
+                continue;
+            }
+
+            // Display math
+            if let Some(rest) = line.trim_start().strip_prefix("$$") {
+                let mut tex = Vec::new();
+                if let Some(end) = rest.find("$$") {
+                    tex.push(rest[..end].to_string());
+                    i += 1;
+                } else {
+                    if !rest.trim().is_empty() {
+                        tex.push(rest.to_string());
+                    }
+                    i += 1;
+                    while i < lines.len() {
+                        let l = &lines[i].1;
+                        i += 1;
+                        if let Some(end) = l.find("$$") {
+                            if !l[..end].trim().is_empty() {
+                                tex.push(l[..end].to_string());
+                            }
+                            break;
+                        }
+                        tex.push(l.clone());
+                    }
+                }
+                blocks.push((number, Block::MathBlock { tex: tex.join("\n").trim().to_string() }));
+                continue;
+            }
+
+            if let Some((level, heading)) = parse_atx(line) {
+                blocks.push((number, Block::Heading { level, content: parse_inlines(heading, self.refs) }));
+                i += 1;
+                continue;
+            }
+
+            if is_thematic_break(line) {
+                blocks.push((number, Block::ThematicBreak));
+                i += 1;
+                continue;
+            }
+
+            // Block quote: `>` lines plus lazy continuation lines
+            if line.trim_start().starts_with('>') {
+                let mut inner = Vec::new();
+                while i < lines.len() {
+                    let l = lines[i].1.trim_start();
+                    if let Some(rest) = l.strip_prefix('>') {
+                        inner.push((lines[i].0, rest.strip_prefix(' ').unwrap_or(rest).to_string()));
+                    } else if !is_blank(l) && !interrupts_paragraph(l) && inner.last().is_some_and(|(_, p): &Line| !is_blank(p)) {
+                        inner.push((lines[i].0, l.to_string()));
+                    } else {
+                        break;
+                    }
+                    i += 1;
+                }
+                let blocks_inside = self.parse(&inner).into_iter().map(|(_, b)| b).collect();
+                blocks.push((number, Block::BlockQuote { blocks: blocks_inside }));
+                continue;
+            }
+
+            if let Some((ordered, start, marker, _)) = list_marker(line) {
+                let (list, next) = self.parse_list(lines, i, ordered, marker);
+                let Block::List { items, .. } = list else { unreachable!() };
+                blocks.push((number, Block::List { ordered, start, items }));
+                i = next;
+                continue;
+            }
+
+            if let Some((label, first)) = footnote_definition(line.trim_start()) {
+                let mut inner = vec![(number, first)];
+                i += 1;
+                while i < lines.len() {
+                    let l = &lines[i].1;
+                    if is_blank(l) {
+                        if lines.get(i + 1).is_some_and(|(_, n)| indent_of(n) >= 4) {
+                            inner.push((lines[i].0, String::new()));
+                            i += 1;
+                            continue;
+                        }
+                        break;
+                    }
+                    if indent_of(l) >= 4 {
+                        inner.push((lines[i].0, l[4..].to_string()));
+                    } else if !interrupts_paragraph(l) && footnote_definition(l.trim_start()).is_none() {
+                        inner.push((lines[i].0, l.trim_start().to_string()));
+                    } else {
+                        break;
+                    }
+                    i += 1;
+                }
+                let blocks_inside = self.parse(&inner).into_iter().map(|(_, b)| b).collect();
+                blocks.push((number, Block::FootnoteDefinition { label, blocks: blocks_inside }));
+                continue;
+            }
+
+            if is_reference_definition(line) {
+                i += 1;
+                continue;
+            }
+
+            if is_html_block_start(line) {
+                let mut html = Vec::new();
+                while i < lines.len() && !is_blank(&lines[i].1) {
+                    html.push(lines[i].1.as_str());
+                    i += 1;
+                }
+                blocks.push((number, Block::Html { html: html.join("\n") }));
+                continue;
+            }
+
+            // Table: header row followed by a delimiter row
+            if line.contains('|') && lines.get(i + 1).is_some_and(|(_, l)| is_table_delimiter(l)) {
+                let header = split_row(line);
+                let align = split_row(&lines[i + 1].1)
+                    .iter()
+                    .map(|c| match (c.starts_with(':'), c.ends_with(':')) {
+                        (true, true) => Align::Center,
+                        (true, false) => Align::Left,
+                        (false, true) => Align::Right,
+                        _ => Align::None,
+                    })
+                    .collect::<Vec<_>>();
+                if align.len() == header.len() {
+                    i += 2;
+                    let mut rows = Vec::new();
+                    while i < lines.len() && !is_blank(&lines[i].1) && lines[i].1.contains('|') {
+                        let mut cells = split_row(&lines[i].1);
+                        cells.resize(header.len(), String::new());
+                        rows.push(cells.iter().map(|c| parse_inlines(c, self.refs)).collect());
+                        i += 1;
+                    }
+                    let header = header.iter().map(|c| parse_inlines(c, self.refs)).collect();
+                    blocks.push((number, Block::Table { align, header, rows }));
+                    continue;
+                }
+            }
+
+            // Paragraph, possibly a setext heading
+            let mut para = vec![line.trim()];
+            i += 1;
+            let mut level = None;
+            while i < lines.len() {
+                let l = lines[i].1.as_str();
+                if is_blank(l) {
+                    break;
+                }
+                if let Some(setext) = setext_level(l) {
+                    level = Some(setext);
+                    i += 1;
+                    break;
+                }
+                if interrupts_paragraph(l) {
+                    break;
+                }
+                para.push(l.trim_start());
+                i += 1;
+            }
+            let source = para.join("\n");
+            let content = parse_inlines(&source, self.refs);
+            blocks.push((number, match level {
+                Some(level) => Block::Heading { level, content },
+                None => Block::Paragraph { content },
+            }));
+        }
+        blocks
+    }
+
+    /// Parse the list starting at `lines[start]`; returns it and the index
+    /// of the first line after it
+    fn parse_list(&self, lines: &[Line], start: usize, ordered: bool, marker: char) -> (Block, usize) {
+        let mut items = Vec::new();
+        let mut i = start;
+
+        while i < lines.len() {
+            let Some((item_ordered, _, item_marker, offset)) = list_marker(&lines[i].1) else {
+                break;
+            };
+            if item_ordered != ordered || item_marker != marker {
+                break;
+            }
+            let first = &lines[i].1;
+            let mut inner: Vec<Line> = vec![(lines[i].0, first[offset.min(first.len())..].to_string())];
+            i += 1;
+            while i < lines.len() {
+                let l = lines[i].1.as_str();
+                if is_blank(l) {
+                    // A blank line continues the item only if indented content follows
+                    let next = lines[i + 1..].iter().find(|(_, n)| !is_blank(n));
+                    if next.is_some_and(|(_, n)| indent_of(n) >= offset) {
+                        inner.push((lines[i].0, String::new()));
+                        i += 1;
+                        continue;
+                    }
+                    break;
+                }
+                if indent_of(l) >= offset {
+                    inner.push((lines[i].0, l[offset..].to_string()));
+                } else if list_marker(l).is_some() || interrupts_paragraph(l) {
+                    break;
+                } else if inner.last().is_some_and(|(_, p)| !is_blank(p)) {
+                    // Lazy continuation of the item's paragraph
+                    inner.push((lines[i].0, l.trim_start().to_string()));
+                } else {
+                    break;
+                }
+                i += 1;
+            }
+            // Skip blank lines between items
+            let mut j = i;
+            while j < lines.len() && is_blank(&lines[j].1) {
+                j += 1;
+            }
+            if j > i && lines.get(j).and_then(|(_, l)| list_marker(l)).is_some_and(|(o, _, m, _)| o == ordered && m == marker) {
+                i = j;
+            }
+
+            let mut checked = None;
+            if let Some((_, first)) = inner.first_mut() {
+                for (prefix, state) in [("[ ] ", false), ("[x] ", true), ("[X] ", true)] {
+                    if let Some(rest) = first.strip_prefix(prefix) {
+                        checked = Some(state);
+                        *first = rest.to_string();
+                        break;
+                    }
+                }
+                if checked.is_none() && matches!(first.as_str(), "[ ]" | "[x]" | "[X]") {
+                    checked = Some(first != "[ ]");
+                    first.clear();
+                }
+            }
+            let blocks = self.parse(&inner).into_iter().map(|(_, b)| b).collect();
+            items.push(ListItem { checked, blocks });
+        }
+        (Block::List { ordered, start: 1, items }, i)
+    }
+}
+
+/// Parse a markdown document into a tree
+pub(crate) fn parse_markdown(content: &str) -> Document {
+    let (front_matter, body, first_line) = match find_front_matter(content) {
+        Some(fm) => (
+            Some(content[fm.yaml_start..fm.yaml_end].trim_end_matches(['\n', '\r']).to_string()),
+            &content[fm.body_start..],
+            content[..fm.body_start].matches('\n').count() + 1,
+        ),
+        None => (None, content, 1),
+    };
+    let refs = reference_map(body);
+    let lines: Vec<Line> = body
+        .lines()
+        .enumerate()
+        .map(|(n, l)| (first_line + n, l.replace('\t', "    ")))
+        .collect();
+    let parsed = BlockParser { refs: &refs }.parse(&lines);
+    Document {
+        front_matter,
+        lines: parsed.iter().map(|(n, _)| *n).collect(),
+        blocks: parsed.into_iter().map(|(_, b)| b).collect(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Inline parsing
+
+struct InlineParser<'a> {
+    src: &'a str,
+    refs: &'a HashMap<String, ReferenceDef>,
+    out: Vec<Inline>,
+    text: String,
+}
+
+fn flanking(src: &str, start: usize, end: usize) -> (bool, bool) {
+    let before = src[..start].chars().next_back();
+    let after = src[end..].chars().next();
+    let left = after.is_some_and(|c| !c.is_whitespace());
+    let right = before.is_some_and(|c| !c.is_whitespace());
+    (left, right)
+}
+
+impl<'a> InlineParser<'a> {
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            self.out.push(Inline::Text { text: std::mem::take(&mut self.text) });
+        }
+    }
+
+    fn push(&mut self, inline: Inline) {
+        self.flush();
+        self.out.push(inline);
+    }
+
+    /// End of the code span opening at `i`, skipping escapes elsewhere
+    fn code_span(&self, i: usize) -> Option<(usize, String)> {
+        let run = self.src[i..].bytes().take_while(|b| *b == b'`').count();
+        let ticks = &self.src[i..i + run];
+        let mut search = i + run;
+        while let Some(pos) = self.src[search..].find(ticks) {
+            let close = search + pos;
+            let close_run = self.src[close..].bytes().take_while(|b| *b == b'`').count();
+            if close_run == run {
+                let code = self.src[i + run..close].replace('\n', " ");
+                let code = if code.len() > 2 && code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty() {
+                    code[1..code.len() - 1].to_string()
+                } else {
+                    code
+                };
+                return Some((close + run, code));
+            }
+            search = close + close_run;
+        }
+        None
+    }
+
+    /// Find the closing delimiter run for an emphasis opener, skipping code
+    /// spans, escapes and links
+    fn find_closer(&self, from: usize, delim: &str) -> Option<usize> {
+        let bytes = self.src.as_bytes();
+        let ch = delim.as_bytes()[0];
+        let mut j = from;
+        while j < bytes.len() {
+            match bytes[j] {
+                b'\\' => j += 2,
+                b'`' => match self.code_span(j) {
+                    Some((end, _)) => j = end,
+                    None => j += 1,
+                },
+                c if c == ch => {
+                    let run = bytes[j..].iter().take_while(|b| **b == ch).count();
+                    let (_, right) = flanking(self.src, j, j + run);
+                    let intraword = ch == b'_' && self.src[j + run..].starts_with(|c: char| c.is_alphanumeric());
+                    if run == delim.len() && right && !intraword && j > from {
+                        return Some(j);
+                    }
+                    j += run;
+                }
+                _ => j += 1,
+            }
+        }
+        None
+    }
+
+    fn parse(mut self) -> Vec<Inline> {
+        let src = self.src;
+        let bytes = src.as_bytes();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let rest = &src[i..];
+            let c = rest.chars().next().unwrap();
+            match c {
+                '\\' => {
+                    match rest[1..].chars().next() {
+                        Some('\n') => {
+                            self.push(Inline::LineBreak);
+                            i += 2;
+                        }
+                        Some(next) if next.is_ascii_punctuation() => {
+                            self.text.push(next);
+                            i += 2;
+                        }
+                        _ => {
+                            self.text.push('\\');
+                            i += 1;
+                        }
+                    }
+                    continue;
+                }
+                '\n' => {
+                    let trailing = self.text.len() - self.text.trim_end_matches(' ').len();
+                    self.text.truncate(self.text.trim_end_matches(' ').len());
+                    self.push(if trailing >= 2 { Inline::LineBreak } else { Inline::SoftBreak });
+                    i += 1;
+                    // Leading spaces of the next line are not content
+                    i += src[i..].len() - src[i..].trim_start_matches(' ').len();
+                    continue;
+                }
+                '`' => {
+                    if let Some((end, code)) = self.code_span(i) {
+                        self.push(Inline::Code { code });
+                        i = end;
+                    } else {
+                        let run = rest.bytes().take_while(|b| *b == b'`').count();
+                        self.text.push_str(&rest[..run]);
+                        i += run;
+                    }
+                    continue;
+                }
+                '$' => {
+                    if let Some((end, tex)) = inline_math(src, i) {
+                        self.push(Inline::Math { tex });
+                        i = end;
+                        continue;
+                    }
+                }
+                '!' if rest.starts_with("![") && !rest.starts_with("![[") => {
+                    if let Some((end, url, title, alt)) = self.link_at(i + 1) {
+                        self.push(Inline::Image { url, title, alt: inline_text(&parse_inlines(&alt, self.refs)) });
+                        i = end;
+                        continue;
+                    }
+                }
+                '!' if rest.starts_with("![[") => {
+                    // Embeds keep their wiki-link syntax
+                    if let Some(close) = rest.find("]]") {
+                        self.push(Inline::Html { html: rest[..close + 2].to_string() });
+                        i += close + 2;
+                        continue;
+                    }
+                }
+                '[' if rest.starts_with("[[") => {
+                    if let Some(close) = rest[2..].find("]]").filter(|c| !rest[2..2 + c].contains('\n')) {
+                        let inner = &rest[2..2 + close];
+                        let (target, alias) = match inner.split_once('|') {
+                            Some((t, a)) => (t.trim(), Some(a.trim().to_string())),
+                            None => (inner.trim(), None),
+                        };
+                        let (target, anchor) = match target.split_once('#') {
+                            Some((t, a)) => (t, Some(a.to_string())),
+                            None => (target, None),
+                        };
+                        self.push(Inline::WikiLink { target: target.to_string(), anchor, alias });
+                        i += close + 4;
+                        continue;
+                    }
+                }
+                '[' if rest.starts_with("[^") => {
+                    if let Some(close) = rest.find(']') {
+                        let label = &rest[2..close];
+                        if !label.is_empty() && !label.contains([' ', '\n']) && !rest[close + 1..].starts_with(':') {
+                            self.push(Inline::FootnoteRef { label: label.to_string() });
+                            i += close + 1;
+                            continue;
+                        }
+                    }
+                }
+                '[' => {
+                    if let Some((end, url, title, inner)) = self.link_at(i) {
+                        let content = parse_inlines(&inner, self.refs);
+                        self.push(Inline::Link { url, title, content });
+                        i = end;
+                        continue;
+                    }
+                }
+                '<' => {
+                    if let Some(end) = rest.find('>') {
+                        let inner = &rest[1..end];
+                        if !inner.contains([' ', '<', '\n']) && (inner.contains(':') || inner.contains('@')) && !inner.is_empty() {
+                            let url = if inner.contains(':') { inner.to_string() } else { format!("mailto:{}", inner) };
+                            self.push(Inline::Link { url, title: None, content: vec![text(inner)] });
+                            i += end + 1;
+                            continue;
+                        }
+                        if rest.starts_with("<u>") {
+                            if let Some(close) = rest.find("</u>") {
+                                let content = parse_inlines(&rest[3..close], self.refs);
+                                self.push(Inline::Underline { content });
+                                i += close + 4;
+                                continue;
+                            }
+                        }
+                        if rest.starts_with("<!--") {
+                            let end = rest.find("-->").map_or(rest.len(), |e| e + 3);
+                            self.push(Inline::Html { html: rest[..end].to_string() });
+                            i += end;
+                            continue;
+                        }
+                        let tag = inner.strip_prefix('/').unwrap_or(inner);
+                        if tag.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                            self.push(Inline::Html { html: rest[..end + 1].to_string() });
+                            i += end + 1;
+                            continue;
+                        }
+                    }
+                }
+                '*' | '_' | '~' => {
+                    let run = rest.bytes().take_while(|b| *b == c as u8).count();
+                    let (left, _) = flanking(src, i, i + run);
+                    let intraword = c == '_' && src[..i].ends_with(|p: char| p.is_alphanumeric());
+                    if left && !intraword && (c != '~' || run == 2) {
+                        let delim = &rest[..run.min(3)];
+                        if let Some(close) = self.find_closer(i + delim.len(), delim) {
+                            let content = parse_inlines(&src[i + delim.len()..close], self.refs);
+                            let node = match (c, delim.len()) {
+                                ('~', _) => Inline::Strikethrough { content },
+                                (_, 1) => Inline::Emphasis { content },
+                                (_, 2) => Inline::Strong { content },
+                                _ => Inline::Strong { content: vec![Inline::Emphasis { content }] },
+                            };
+                            self.push(node);
+                            i = close + delim.len();
+                            continue;
+                        }
+                    }
+                    self.text.push_str(&rest[..run]);
+                    i += run;
+                    continue;
+                }
+                _ => {
+                    // Bare URLs at word starts
+                    let at_word_start = !src[..i].ends_with(|p: char| p.is_alphanumeric() || p == '/' || p == '.' || p == '@');
+                    if at_word_start && c.is_ascii_alphabetic() {
+                        if let Some(len) = url_len(rest) {
+                            let url = &rest[..len];
+                            self.push(Inline::Link { url: href_for(url), title: None, content: vec![text(url)] });
+                            i += len;
+                            continue;
+                        }
+                    }
+                }
+            }
+            self.text.push(c);
+            i += c.len_utf8();
+        }
+        self.flush();
+        self.out
+    }
+
+    /// Inline or reference link whose text starts at the `[` at `i`:
+    /// end offset, url, title and raw link text
+    fn link_at(&self, i: usize) -> Option<(usize, String, Option<String>, String)> {
+        let src = self.src;
+        let close = find_closing(src, i, b'[', b']')?;
+        let inner = src[i + 1..close].to_string();
+        let after = &src[close + 1..];
+
+        if after.starts_with('(') {
+            let dest_end = find_closing(src, close + 1, b'(', b')')?;
+            let dest = src[close + 2..dest_end].trim();
+            let (url, title) = match dest.find([' ', '\n']) {
+                Some(pos) => {
+                    let title = dest[pos..].trim();
+                    let title = title
+                        .strip_prefix('"')
+                        .and_then(|t| t.strip_suffix('"'))
+                        .or_else(|| title.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')))
+                        .or_else(|| title.strip_prefix('(').and_then(|t| t.strip_suffix(')')))?;
+                    (&dest[..pos], Some(title.replace("\\\"", "\"")))
+                }
+                None => (dest, None),
+            };
+            let url = url.strip_prefix('<').and_then(|u| u.strip_suffix('>')).unwrap_or(url);
+            return Some((dest_end + 1, unescape_url(url), title, inner));
+        }
+
+        // Reference link: [text][label], [text][] or [text]
+        let (label, end) = match after.strip_prefix('[') {
+            Some(rest) => {
+                let len = rest.find(']')?;
+                let label = if len == 0 { inner.as_str() } else { &rest[..len] };
+                (label.to_string(), close + 2 + len + 1)
+            }
+            None => (inner.clone(), close + 1),
+        };
+        let def = self.refs.get(&normalize_label(&label))?;
+        Some((end, def.url.clone(), def.title.clone(), inner))
+    }
+}
+
+fn unescape_url(url: &str) -> String {
+    let mut out = String::with_capacity(url.len());
+    let mut chars = url.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek().is_some_and(|n| n.is_ascii_punctuation()) {
+            out.push(chars.next().unwrap());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// `$tex$` or `$$tex$$` at `i`, following the rules of `math::scan_math`
+fn inline_math(src: &str, i: usize) -> Option<(usize, String)> {
+    let bytes = src.as_bytes();
+    if src[i..].starts_with("$$") {
+        let close = src[i + 2..].find("$$")?;
+        return Some((i + 2 + close + 2, src[i + 2..i + 2 + close].trim().to_string()));
+    }
+    if bytes.get(i + 1).is_none_or(|b| b.is_ascii_whitespace()) {
+        return None;
+    }
+    let mut j = i + 1;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' => j += 1,
+            b'\n' if bytes.get(j + 1) == Some(&b'\n') => return None,
+            b'$' if !bytes[j - 1].is_ascii_whitespace() && !bytes.get(j + 1).is_some_and(u8::is_ascii_digit) => {
+                return (j > i + 1).then(|| (j + 1, src[i + 1..j].to_string()));
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
+
+/// Parse inline markdown, resolving reference links against `refs`
+pub(crate) fn parse_inlines(src: &str, refs: &HashMap<String, ReferenceDef>) -> Vec<Inline> {
+    InlineParser { src, refs, out: Vec::new(), text: String::new() }.parse()
+}
+
+// ---------------------------------------------------------------------------
+// Markdown output
+
+/// Escape characters that would otherwise be read as markdown syntax
+fn escape_text(text: &str, at_line_start: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    if at_line_start {
+        let needs_escape = out.starts_with(['#', '>', '+', '-', '='])
+            || list_marker(&out).is_some_and(|(ordered, ..)| ordered);
+        if needs_escape {
+            let digits = out.bytes().take_while(u8::is_ascii_digit).count();
+            out.insert(digits, '\\');
+        }
+    }
+    out
+}
+
+fn code_span(code: &str) -> String {
+    let longest = code
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let ticks = "`".repeat(longest + 1);
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{} {} {}", ticks, code, ticks)
+    } else {
+        format!("{}{}{}", ticks, code, ticks)
+    }
+}
+
+fn link_destination(url: &str, title: &Option<String>) -> String {
+    let url = if url.contains([' ', '(', ')']) { format!("<{}>", url) } else { url.to_string() };
+    match title {
+        Some(title) => format!("({} \"{}\")", url, title.replace('"', "\\\"")),
+        None => format!("({})", url),
+    }
+}
+
+pub(crate) fn inlines_to_markdown(inlines: &[Inline]) -> String {
+    let mut out = String::new();
+    for inline in inlines {
+        let line_start = out.is_empty() || out.ends_with('\n');
+        match inline {
+            Inline::Text { text } => out.push_str(&escape_text(text, line_start)),
+            Inline::Code { code } => out.push_str(&code_span(code)),
+            Inline::Emphasis { content } => out.push_str(&format!("*{}*", inlines_to_markdown(content))),
+            Inline::Strong { content } => out.push_str(&format!("**{}**", inlines_to_markdown(content))),
+            Inline::Strikethrough { content } => out.push_str(&format!("~~{}~~", inlines_to_markdown(content))),
+            Inline::Underline { content } => out.push_str(&format!("<u>{}</u>", inlines_to_markdown(content))),
+            Inline::Link { url, title, content } => {
+                // Bare URLs stay bare
+                let shown = inline_text(content);
+                if title.is_none() && content.len() == 1 && (href_for(&shown) == *url) && url_len(&shown) == Some(shown.len()) {
+                    out.push_str(&shown);
+                } else {
+                    out.push_str(&format!("[{}]{}", inlines_to_markdown(content), link_destination(url, title)));
+                }
+            }
+            Inline::Image { url, title, alt } => {
+                out.push_str(&format!("![{}]{}", escape_text(alt, false), link_destination(url, title)))
+            }
+            Inline::Math { tex } => out.push_str(&format!("${}$", tex)),
+            Inline::FootnoteRef { label } => out.push_str(&format!("[^{}]", label)),
+            Inline::WikiLink { target, anchor, alias } => {
+                out.push_str(&crate::links::build_wiki_link(target, anchor.as_deref(), alias.as_deref()))
+            }
+            Inline::Html { html } => out.push_str(html),
+            Inline::SoftBreak => out.push('\n'),
+            Inline::LineBreak => out.push_str("\\\n"),
+        }
+    }
+    out
+}
+
+pub(crate) fn indent_lines(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(n, line)| {
+            let prefix = if n == 0 { first } else { rest };
+            if line.is_empty() { prefix.trim_end().to_string() } else { format!("{}{}", prefix, line) }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `alternate` switches to `*` / `)` markers, so a list directly after
+/// another list of the same kind doesn't merge into it
+fn list_to_markdown(ordered: bool, start: u64, items: &[ListItem], alternate: bool) -> String {
+    items
+        .iter()
+        .enumerate()
+        .map(|(n, item)| {
+            let marker = match (ordered, alternate) {
+                (true, false) => format!("{}. ", start + n as u64),
+                (true, true) => format!("{}) ", start + n as u64),
+                (false, false) => "- ".to_string(),
+                (false, true) => "* ".to_string(),
+            };
+            let task = match item.checked {
+                Some(true) => "[x] ",
+                Some(false) => "[ ] ",
+                None => "",
+            };
+            let body = blocks_to_markdown(&item.blocks);
+            let body = if body.is_empty() { task.trim_end().to_string() } else { format!("{}{}", task, body) };
+            indent_lines(&body, &marker, &" ".repeat(marker.len()))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn block_to_markdown(block: &Block) -> String {
+    match block {
+        Block::Heading { level, content } => {
+            format!("{} {}", "#".repeat(*level as usize), inlines_to_markdown(content).replace('\n', " "))
+        }
+        Block::Paragraph { content } => inlines_to_markdown(content),
+        Block::CodeBlock { language, code } => {
+            let longest = code.lines().map(|l| l.trim_start().bytes().take_while(|b| *b == b'`').count()).max().unwrap_or(0);
+            let fence = "`".repeat(longest.max(2) + 1);
+            format!("{}{}\n{}\n{}", fence, language, code, fence)
+        }
+        Block::BlockQuote { blocks } => indent_lines(&blocks_to_markdown(blocks), "> ", "> "),
+        Block::List { ordered, start, items } => list_to_markdown(*ordered, *start, items, false),
+        Block::Table { align, header, rows } => {
+            let cell = |c: &Vec<Inline>| inlines_to_markdown(c).replace('|', "\\|").replace('\n', " ");
+            let row = |cells: &Vec<Vec<Inline>>| format!("| {} |", cells.iter().map(cell).collect::<Vec<_>>().join(" | "));
+            let delimiter = align
+                .iter()
+                .map(|a| match a {
+                    Align::None => "---",
+                    Align::Left => ":---",
+                    Align::Center => ":---:",
+                    Align::Right => "---:",
+                })
+                .collect::<Vec<_>>()
+                .join(" | ");
+            let mut lines = vec![row(header), format!("| {} |", delimiter)];
+            lines.extend(rows.iter().map(row));
+            lines.join("\n")
+        }
+        Block::ThematicBreak => "---".to_string(),
+        Block::Html { html } => html.clone(),
+        Block::MathBlock { tex } => format!("$$\n{}\n$$", tex),
+        Block::FootnoteDefinition { label, blocks } => {
+            indent_lines(&blocks_to_markdown(blocks), &format!("[^{}]: ", label), "    ")
+        }
+    }
+}
+
+pub(crate) fn blocks_to_markdown(blocks: &[Block]) -> String {
+    let mut out = String::new();
+    let mut alternate = false;
+    for (n, block) in blocks.iter().enumerate() {
+        if n > 0 {
+            out.push_str("\n\n");
+        }
+        match block {
+            Block::List { ordered, start, items } => {
+                let follows_same = n > 0 && matches!(&blocks[n - 1], Block::List { ordered: o, .. } if o == ordered);
+                alternate = follows_same && !alternate;
+                out.push_str(&list_to_markdown(*ordered, *start, items, alternate));
+            }
+            _ => out.push_str(&block_to_markdown(block)),
+        }
+    }
+    out
+}
+
+/// Write a document tree back out as markdown
+pub(crate) fn to_markdown(doc: &Document) -> String {
+    let body = blocks_to_markdown(&doc.blocks);
+    match &doc.front_matter {
+        Some(yaml) => format!("---\n{}\n---\n\n{}\n", yaml, body),
+        None => format!("{}\n", body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn footnotes_referenced_only_from_definitions_are_numbered() {
+        let doc = parse_markdown("Text[^a].\n\n[^b]: Unreferenced.\n\n[^a]: See[^c].\n\n[^c]: Nested.\n");
+        let order: Vec<(usize, &str)> = Footnotes::new(&doc.blocks).iter().map(|(n, l, _)| (n, l)).collect();
+        assert_eq!(order, vec![(1, "a"), (2, "b"), (3, "c")]);
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+/// Oldest entries are dropped once the log grows past this
+pub(crate) const MAX_AUDIT_ENTRIES: usize = 10_000;
+
+/// One recorded action or attempted action on a document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct AuditEntry {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: f64,
+    pub user_id: String,
+    /// What was attempted, e.g. "document_edit", "freeze", "prune_history"
+    pub action: String,
+    pub allowed: bool,
+    pub detail: String,
+}
+
+/// Append-only log of security-relevant actions on a document
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct AuditLog {
+    entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn record(&mut self, timestamp: f64, user_id: &str, action: &str, allowed: bool, detail: &str) {
+        if self.entries.len() >= MAX_AUDIT_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push(AuditEntry {
+            timestamp,
+            user_id: user_id.to_string(),
+            action: action.to_string(),
+            allowed,
+            detail: detail.to_string(),
+        });
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+}
@@ -0,0 +1,243 @@
+// Autosave. A SaveScheduler watches the edits applied to a document and
+// decides when to save and what: the edit messages since the last save as
+// a delta, or a full snapshot once the deltas have grown. Saving waits for
+// a pause in typing, never runs more often than a minimum interval, and is
+// skipped when the content hashes the same as what was last saved.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::decode_with_grid_tag;
+use crate::metadata::CONTENT_MESSAGE_TYPES;
+use crate::snapshot::content_hash;
+
+/// Pause in editing before saving, by default (ms)
+const DEFAULT_DEBOUNCE_MS: f64 = 1_000.0;
+
+/// Least time between saves, by default (ms)
+const DEFAULT_MIN_INTERVAL_MS: f64 = 5_000.0;
+
+/// Longest a change waits for a pause in editing, by default (ms)
+const DEFAULT_MAX_WAIT_MS: f64 = 30_000.0;
+
+/// Deltas saved before the next save is a full snapshot again
+const MAX_DELTAS: u32 = 50;
+
+/// Save a full snapshot instead once the delta is this large a fraction
+/// of the content
+const MAX_DELTA_RATIO: f64 = 0.5;
+
+#[derive(Serialize)]
+struct SavePlan<'a> {
+    /// "full" or "delta"
+    kind: &'a str,
+    content_hash: String,
+    /// Edit messages in the delta; 0 for a full save
+    edits: usize,
+    /// Why now: "idle", "max_wait" or "flush"
+    reason: &'a str,
+}
+
+/// A save handed to JS and not yet confirmed
+struct InFlight {
+    full: bool,
+    /// Pending edits it covers
+    edits: usize,
+    content_hash: String,
+}
+
+/// Decides when a document is saved and whether as a full snapshot or a
+/// delta. Feed it each applied edit message with `record_edit` and call
+/// `poll` on a timer (`next_poll_in` says when); when `poll` returns a
+/// plan, persist the content (or `delta()` for a delta) and confirm with
+/// `mark_saved`, or `save_failed` to retry later.
+#[wasm_bindgen]
+pub struct SaveScheduler {
+    debounce_ms: f64,
+    min_interval_ms: f64,
+    max_wait_ms: f64,
+    /// Edit messages since the last confirmed save, with when they came
+    pending: Vec<(f64, Vec<u8>)>,
+    /// Changed without an edit message, or by a checkpoint; the next save
+    /// must be full
+    needs_full: bool,
+    /// When the oldest unsaved change was made
+    dirty_since: Option<f64>,
+    last_change: f64,
+    /// When a save was last started, successful or not
+    last_attempt: f64,
+    last_saved_at: Option<f64>,
+    last_saved_hash: Option<String>,
+    deltas_since_full: u32,
+    in_flight: Option<InFlight>,
+}
+
+#[wasm_bindgen]
+impl SaveScheduler {
+    /// Save after `debounce_ms` without edits, at most every
+    /// `min_interval_ms`, and at latest `max_wait_ms` after a change even
+    /// while editing goes on. 0 uses the defaults (1 s, 5 s and 30 s).
+    #[wasm_bindgen(constructor)]
+    pub fn new(debounce_ms: f64, min_interval_ms: f64, max_wait_ms: f64) -> SaveScheduler {
+        let or_default = |value: f64, default: f64| if value > 0.0 { value } else { default };
+        SaveScheduler {
+            debounce_ms: or_default(debounce_ms, DEFAULT_DEBOUNCE_MS),
+            min_interval_ms: or_default(min_interval_ms, DEFAULT_MIN_INTERVAL_MS),
+            max_wait_ms: or_default(max_wait_ms, DEFAULT_MAX_WAIT_MS),
+            pending: Vec::new(),
+            needs_full: false,
+            dirty_since: None,
+            last_change: f64::NEG_INFINITY,
+            last_attempt: f64::NEG_INFINITY,
+            last_saved_at: None,
+            last_saved_hash: None,
+            deltas_since_full: 0,
+            in_flight: None,
+        }
+    }
+
+    /// Start from content already saved, e.g. on load, so an unchanged
+    /// document isn't saved again
+    pub fn set_saved(&mut self, content: &str, now: f64) {
+        self.last_saved_hash = Some(content_hash(content.as_bytes()));
+        self.last_saved_at = Some(now);
+        self.pending.clear();
+        self.needs_full = false;
+        self.dirty_since = None;
+        self.in_flight = None;
+    }
+
+    /// Note an edit message applied at `now` (local or remote). Returns
+    /// false for messages that don't change content.
+    pub fn record_edit(&mut self, cbor_bytes: &[u8], now: f64) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let message_type = message.payload.message_type.as_str();
+        if !CONTENT_MESSAGE_TYPES.contains(&message_type) {
+            return Ok(false);
+        }
+        if message_type == "document_checkpoint" {
+            self.needs_full = true;
+        }
+        self.pending.push((now, cbor_bytes.to_vec()));
+        self.changed(now);
+        Ok(true)
+    }
+
+    /// Note a change that didn't come as an edit message, e.g. a whole
+    /// document replaced on import; the next save will be full
+    pub fn mark_dirty(&mut self, now: f64) {
+        self.needs_full = true;
+        self.changed(now);
+    }
+
+    /// Whether there are changes not yet saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_since.is_some()
+    }
+
+    /// When the last save was confirmed
+    pub fn last_saved_at(&self) -> Option<f64> {
+        self.last_saved_at
+    }
+
+    /// How long until `poll` could next have something to do (ms), or
+    /// `undefined` when there's nothing to save or a save is in progress
+    pub fn next_poll_in(&self, now: f64) -> Option<f64> {
+        let dirty_since = self.dirty_since?;
+        if self.in_flight.is_some() {
+            return None;
+        }
+        let idle_at = (self.last_change + self.debounce_ms).min(dirty_since + self.max_wait_ms);
+        let allowed_at = self.last_attempt + self.min_interval_ms;
+        Some((idle_at.max(allowed_at) - now).max(0.0))
+    }
+
+    /// Check whether to save `content`, the document now. Returns the plan
+    /// as JSON `{kind: "full" | "delta", content_hash, edits, reason}` when
+    /// a save is due; `undefined` otherwise, including when the content is
+    /// the same as last saved (the document is then clean again).
+    pub fn poll(&mut self, content: &str, now: f64) -> Option<String> {
+        let dirty_since = self.dirty_since?;
+        if self.in_flight.is_some() || now - self.last_attempt < self.min_interval_ms {
+            return None;
+        }
+        let reason = if now - self.last_change >= self.debounce_ms {
+            "idle"
+        } else if now - dirty_since >= self.max_wait_ms {
+            "max_wait"
+        } else {
+            return None;
+        };
+        self.plan(content, now, reason)
+    }
+
+    /// Plan a save now whatever the timing, e.g. when the page is closing
+    pub fn flush(&mut self, content: &str, now: f64) -> Option<String> {
+        self.dirty_since?;
+        self.plan(content, now, "flush")
+    }
+
+    /// The edit messages of a delta save, concatenated as in an edit log,
+    /// to append to the log saved so far; empty for a full save
+    pub fn delta(&self) -> Vec<u8> {
+        match &self.in_flight {
+            Some(save) if !save.full => self.pending[..save.edits].iter().flat_map(|(_, bytes)| bytes.clone()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Confirm the planned save finished at `now`. Edits made while it ran
+    /// stay pending.
+    pub fn mark_saved(&mut self, now: f64) -> Result<(), JsValue> {
+        let save = self.in_flight.take().ok_or_else(|| JsValue::from_str("No save in progress"))?;
+        self.pending.drain(..save.edits);
+        if save.full {
+            self.deltas_since_full = 0;
+            self.needs_full = false;
+        } else {
+            self.deltas_since_full += 1;
+        }
+        self.last_saved_hash = Some(save.content_hash);
+        self.last_saved_at = Some(now);
+        self.dirty_since = self.pending.first().map(|(at, _)| *at);
+        Ok(())
+    }
+
+    /// The planned save failed; it's retried after the minimum interval
+    pub fn save_failed(&mut self) {
+        self.in_flight = None;
+    }
+}
+
+impl SaveScheduler {
+    fn changed(&mut self, now: f64) {
+        self.dirty_since.get_or_insert(now);
+        self.last_change = now;
+    }
+
+    fn plan(&mut self, content: &str, now: f64, reason: &str) -> Option<String> {
+        let hash = content_hash(content.as_bytes());
+        self.last_attempt = now;
+        if self.last_saved_hash.as_deref() == Some(hash.as_str()) {
+            // Edits that cancel out: nothing to write
+            self.pending.clear();
+            self.dirty_since = None;
+            return None;
+        }
+        let delta_bytes: usize = self.pending.iter().map(|(_, bytes)| bytes.len()).sum();
+        let full = self.needs_full
+            || self.last_saved_hash.is_none()
+            || self.deltas_since_full >= MAX_DELTAS
+            || delta_bytes as f64 > content.len() as f64 * MAX_DELTA_RATIO;
+        let edits = self.pending.len();
+        let plan = SavePlan {
+            kind: if full { "full" } else { "delta" },
+            content_hash: hash.clone(),
+            edits: if full { 0 } else { edits },
+            reason,
+        };
+        self.in_flight = Some(InFlight { full, edits, content_hash: hash });
+        Some(serde_json::to_string(&plan).unwrap_or_else(|_| "{}".into()))
+    }
+}
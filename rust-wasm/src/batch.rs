@@ -0,0 +1,215 @@
+// Batch messages: several edits and cursor updates sent under one
+// envelope. During fast typing the envelope (tag, ids, sequence and clock
+// stamps, signature) can outweigh the operation itself; a `batch` carries
+// it once, and the document and user ids once, for the whole run.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::payload::{decode_payload, payload_data, Batch, CursorUpdate, Payload};
+use crate::signing;
+use crate::{
+    decode_with_grid_tag, encode_promisegrid_payload, encode_with_grid_tag, to_canonical_cbor, DocumentEdit,
+    MessagePayload, PromiseGridMessage,
+};
+
+/// Message types a batch may carry
+const BATCHABLE_TYPES: &[&str] = &["document_edit", "cursor_update"];
+
+/// Fields every operation shares with its batch, stored once on the batch
+const SHARED_FIELDS: &[&str] = &["document_id", "user_id"];
+
+/// Envelope fields dropped from operations added as whole messages. Their
+/// `seq` and `session` stay, so the sequence numbers they were given still
+/// reach receivers and don't show up there as gaps.
+const DROPPED_ENVELOPE_FIELDS: &[&str] = &["message_id", "lamport", "nonce", "sent_at", "capability", "capability_signature", "sender_signature"];
+
+/// What the signature covers: everything in the batch but the envelope
+#[derive(Serialize)]
+struct SignedBatch<'a> {
+    message_type: &'static str,
+    document_id: &'a str,
+    user_id: &'a str,
+    timestamp: f64,
+    operations: &'a [MessagePayload],
+}
+
+fn signing_bytes(batch: &Batch) -> Result<Vec<u8>, JsValue> {
+    let signed = SignedBatch {
+        message_type: "batch",
+        document_id: &batch.document_id,
+        user_id: &batch.user_id,
+        timestamp: batch.timestamp,
+        operations: &batch.operations,
+    };
+    to_canonical_cbor(&signed).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+}
+
+/// Collects edits and cursor updates by one user into a `batch` message
+#[wasm_bindgen]
+pub struct MessageBatch {
+    document_id: String,
+    user_id: String,
+    operations: Vec<MessagePayload>,
+}
+
+#[wasm_bindgen]
+impl MessageBatch {
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str, user_id: &str) -> MessageBatch {
+        MessageBatch { document_id: document_id.to_string(), user_id: user_id.to_string(), operations: Vec::new() }
+    }
+
+    /// Add an edit, as `create_promisegrid_edit_message` would send it
+    pub fn add_edit(&mut self, edit_type: &str, position: u32, content: &str) {
+        let edit = DocumentEdit {
+            document_id: self.document_id.clone(),
+            edit_type: edit_type.to_string(),
+            position,
+            content: content.to_string(),
+            timestamp: js_sys::Date::now(),
+            user_id: self.user_id.clone(),
+            length: None,
+            prev_hash: None,
+        };
+        self.push("document_edit", payload_data(&edit));
+    }
+
+    /// Add a cursor position, as `create_cursor_message` would send it
+    pub fn add_cursor(&mut self, offset: u32, selection_start: u32, selection_end: u32) {
+        let cursor = CursorUpdate {
+            document_id: self.document_id.clone(),
+            user_id: self.user_id.clone(),
+            offset: offset as u64,
+            selection_start: selection_start.min(selection_end) as u64,
+            selection_end: selection_start.max(selection_end) as u64,
+            timestamp: js_sys::Date::now(),
+        };
+        self.push("cursor_update", payload_data(&cursor));
+    }
+
+    /// Add an edit or cursor message already built, e.g. by
+    /// `PromiseGridHandler`. Its envelope is dropped in favour of the
+    /// batch's but for its sequence number; it must be for this batch's
+    /// document and user.
+    pub fn add_message(&mut self, cbor_bytes: &[u8]) -> Result<(), JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let (_, payload) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))?;
+        let (document_id, user_id) = match &payload {
+            Payload::DocumentEdit(edit) => (&edit.document_id, &edit.user_id),
+            Payload::CursorUpdate(cursor) => (&cursor.document_id, &cursor.user_id),
+            _ => {
+                return Err(JsValue::from_str(&format!(
+                    "A batch can't carry {} messages",
+                    message.payload.message_type
+                )))
+            }
+        };
+        if *document_id != self.document_id || *user_id != self.user_id {
+            return Err(JsValue::from_str("Message is for a different document or user than the batch"));
+        }
+        let mut data = message.payload.data;
+        data.retain(|key, _| !DROPPED_ENVELOPE_FIELDS.contains(&key.as_str()));
+        self.push(&message.payload.message_type, data);
+        Ok(())
+    }
+
+    /// Operations added so far
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Encode the operations as one `batch` message and start a new batch.
+    /// With a 32-byte Ed25519 `secret_key` the batch is signed; pass an
+    /// empty array to leave it unsigned.
+    pub fn finish(&mut self, secret_key: &[u8]) -> Result<Vec<u8>, JsValue> {
+        if self.operations.is_empty() {
+            return Err(JsValue::from_str("Batch is empty"));
+        }
+        let mut batch = Batch {
+            document_id: self.document_id.clone(),
+            user_id: self.user_id.clone(),
+            timestamp: js_sys::Date::now(),
+            operations: std::mem::take(&mut self.operations),
+            signature: None,
+        };
+        if !secret_key.is_empty() {
+            batch.signature = Some(signing::sign(secret_key, &signing_bytes(&batch)?)?);
+        }
+        Ok(encode_promisegrid_payload("batch", payload_data(&batch)))
+    }
+}
+
+impl MessageBatch {
+    fn push(&mut self, message_type: &str, mut data: HashMap<String, ciborium::Value>) {
+        data.retain(|key, _| !SHARED_FIELDS.contains(&key.as_str()));
+        self.operations.push(MessagePayload { message_type: message_type.to_string(), data });
+    }
+}
+
+/// Reads the operations of a `batch` message back out, each as a message
+/// of its own for the usual handlers, in the order they were added
+#[wasm_bindgen]
+pub struct BatchReader {
+    protocol_hash: String,
+    document_id: String,
+    user_id: String,
+    operations: std::vec::IntoIter<MessagePayload>,
+    len: usize,
+}
+
+#[wasm_bindgen]
+impl BatchReader {
+    /// Open a batch. With a 32-byte Ed25519 `public_key` the batch must be
+    /// signed by it; with an empty array the signature isn't checked.
+    #[wasm_bindgen(constructor)]
+    pub fn new(cbor_bytes: &[u8], public_key: &[u8]) -> Result<BatchReader, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let (_, Payload::Batch(batch)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Err(JsValue::from_str(&format!("Expected a batch message, got {}", message.payload.message_type)));
+        };
+        if let Some(operation) = batch.operations.iter().find(|op| !BATCHABLE_TYPES.contains(&op.message_type.as_str())) {
+            return Err(JsValue::from_str(&format!("A batch can't carry {} messages", operation.message_type)));
+        }
+        if !public_key.is_empty() {
+            let Some(signature) = &batch.signature else {
+                return Err(JsValue::from_str("Batch is not signed"));
+            };
+            if !signing::verify(public_key, &signing_bytes(&batch)?, signature) {
+                return Err(JsValue::from_str("Batch signature does not verify"));
+            }
+        }
+        Ok(BatchReader {
+            protocol_hash: message.protocol_hash,
+            document_id: batch.document_id,
+            user_id: batch.user_id,
+            len: batch.operations.len(),
+            operations: batch.operations.into_iter(),
+        })
+    }
+
+    /// The next operation as a PromiseGrid message, or `undefined` after
+    /// the last
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Vec<u8>> {
+        let mut payload = self.operations.next()?;
+        payload.data.insert("document_id".to_string(), ciborium::Value::Text(self.document_id.clone()));
+        payload.data.insert("user_id".to_string(), ciborium::Value::Text(self.user_id.clone()));
+        let message = PromiseGridMessage { protocol_hash: self.protocol_hash.clone(), payload };
+        encode_with_grid_tag(&message).ok()
+    }
+
+    /// Operations in the batch
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
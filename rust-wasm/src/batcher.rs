@@ -0,0 +1,153 @@
+// Batching of local edits before they are sent: keystrokes typed or
+// deleted in a run are merged into one edit, and the pending edits go out
+// together as one `edit_batch` message once enough time has passed or
+// enough edits have built up.
+
+use wasm_bindgen::prelude::*;
+use std::collections::HashMap;
+
+use crate::encode_promisegrid_payload;
+
+/// Flush after this long since the first pending edit, in ms
+const DEFAULT_MAX_DELAY_MS: f64 = 100.0;
+
+/// Flush once this many edits are pending after merging
+const DEFAULT_MAX_EDITS: usize = 50;
+
+/// An edit waiting to be sent. Offsets are in bytes.
+struct PendingEdit {
+    edit_type: String,
+    position: usize,
+    /// Inserted text, or the deleted text for a delete
+    content: String,
+    timestamp: f64,
+}
+
+impl PendingEdit {
+    /// Fold `next` into this edit when it continues it, returning false
+    /// when it doesn't
+    fn absorb(&mut self, next: &PendingEdit) -> bool {
+        match (self.edit_type.as_str(), next.edit_type.as_str()) {
+            // Typing on at the end of the insert
+            ("insert", "insert") if next.position == self.position + self.content.len() => {
+                self.content.push_str(&next.content);
+            }
+            // Backspacing over the end of what was just typed
+            ("insert", "delete")
+                if next.position >= self.position
+                    && next.position + next.content.len() == self.position + self.content.len()
+                    && self.content.ends_with(&next.content) =>
+            {
+                self.content.truncate(self.content.len() - next.content.len());
+            }
+            // Backspace: the next deletion ends where this one starts
+            ("delete", "delete") if next.position + next.content.len() == self.position => {
+                self.content.insert_str(0, &next.content);
+                self.position = next.position;
+            }
+            // Forward delete: the next deletion is at the same place
+            ("delete", "delete") if next.position == self.position => {
+                self.content.push_str(&next.content);
+            }
+            _ => return false,
+        }
+        self.timestamp = next.timestamp;
+        true
+    }
+}
+
+/// Collects local edits and sends them in batches. Call `push` for each
+/// edit and `poll` on a timer; whenever either returns bytes, send them as
+/// one message. Adjacent single-character inserts and deletes are merged
+/// into one edit as they arrive, and an insert whose text is deleted again
+/// before the flush is dropped.
+#[wasm_bindgen]
+pub struct EditBatcher {
+    document_id: String,
+    user_id: String,
+    max_delay_ms: f64,
+    max_edits: usize,
+    pending: Vec<PendingEdit>,
+    /// When the oldest pending edit arrived
+    since: Option<f64>,
+}
+
+#[wasm_bindgen]
+impl EditBatcher {
+    /// `max_delay_ms` and `max_edits` of 0 use the defaults (100 ms, 50
+    /// edits)
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str, user_id: &str, max_delay_ms: f64, max_edits: usize) -> EditBatcher {
+        EditBatcher {
+            document_id: document_id.to_string(),
+            user_id: user_id.to_string(),
+            max_delay_ms: if max_delay_ms > 0.0 { max_delay_ms } else { DEFAULT_MAX_DELAY_MS },
+            max_edits: if max_edits > 0 { max_edits } else { DEFAULT_MAX_EDITS },
+            pending: Vec::new(),
+            since: None,
+        }
+    }
+
+    /// Add a local edit (`insert` or `delete` with the deleted text, or
+    /// any other type, which is never merged). Returns a batch message
+    /// when the size limit is reached.
+    pub fn push(&mut self, edit_type: &str, position: usize, content: &str, now: f64) -> Option<Vec<u8>> {
+        let edit = PendingEdit { edit_type: edit_type.to_string(), position, content: content.to_string(), timestamp: now };
+        let merged = self.pending.last_mut().is_some_and(|last| last.absorb(&edit));
+        if !merged {
+            self.pending.push(edit);
+        }
+        if self.pending.last().is_some_and(|last| last.edit_type == "insert" && last.content.is_empty()) {
+            self.pending.pop();
+        }
+        self.since = if self.pending.is_empty() { None } else { self.since.or(Some(now)) };
+        if self.pending.len() >= self.max_edits {
+            self.flush(now)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a batch message once the oldest pending edit has waited
+    /// `max_delay_ms`
+    pub fn poll(&mut self, now: f64) -> Option<Vec<u8>> {
+        match self.since {
+            Some(since) if now - since >= self.max_delay_ms => self.flush(now),
+            _ => None,
+        }
+    }
+
+    /// Send whatever is pending now, e.g. before the page unloads.
+    /// `undefined` when nothing is pending.
+    pub fn flush(&mut self, now: f64) -> Option<Vec<u8>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let edits = self
+            .pending
+            .drain(..)
+            .map(|edit| {
+                // Keys in canonical order, shortest first
+                ciborium::Value::Map(vec![
+                    ("content".into(), ciborium::Value::Text(edit.content)),
+                    ("position".into(), ciborium::Value::from(edit.position as u64)),
+                    ("edit_type".into(), ciborium::Value::Text(edit.edit_type)),
+                    ("timestamp".into(), ciborium::Value::Float(edit.timestamp)),
+                ])
+            })
+            .collect();
+        self.since = None;
+
+        let mut data = HashMap::new();
+        data.insert("document_id".to_string(), ciborium::Value::Text(self.document_id.clone()));
+        data.insert("user_id".to_string(), ciborium::Value::Text(self.user_id.clone()));
+        data.insert("timestamp".to_string(), ciborium::Value::Float(now));
+        data.insert("edits".to_string(), ciborium::Value::Array(edits));
+        Some(encode_promisegrid_payload("edit_batch", data))
+    }
+
+    /// Edits waiting to be sent, after merging
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+}
@@ -0,0 +1,129 @@
+// Line attribution from the edit log: replay the edits, tracking which
+// edit wrote each byte, then match the replayed text to the current
+// document so each line is credited to the last edit that touched it.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::diff::{diff_strs, DiffKind};
+use crate::edit_log::{read_edit_log, Edit, CHECKPOINT};
+
+/// Replay `edits` from an empty document: the text and, per byte, the
+/// index of the edit that wrote it
+fn replay_with_authors(edits: &[Edit]) -> (String, Vec<usize>) {
+    let mut text = String::new();
+    let mut authors: Vec<usize> = Vec::new();
+    for (n, edit) in edits.iter().enumerate() {
+        let (range, inserted) = edit.span(&text);
+        authors.splice(range.clone(), std::iter::repeat_n(n, inserted.len()));
+        text.replace_range(range, inserted);
+    }
+    (text, authors)
+}
+
+#[derive(Serialize)]
+struct LineBlame<'a> {
+    /// 1-based
+    line: usize,
+    /// `None` for lines the log doesn't account for
+    user_id: Option<&'a str>,
+    timestamp: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct BlameRange<'a> {
+    start_line: usize,
+    end_line: usize,
+    user_id: Option<&'a str>,
+    timestamp: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct AuthorLines<'a> {
+    user_id: &'a str,
+    lines: usize,
+}
+
+#[derive(Serialize)]
+struct Blame<'a> {
+    lines: Vec<LineBlame<'a>>,
+    ranges: Vec<BlameRange<'a>>,
+    /// Lines credited to each author, most first
+    authors: Vec<AuthorLines<'a>>,
+    /// Edit messages read from the log
+    edits: usize,
+}
+
+/// Credit each line of `document` to the user and time of the last edit
+/// that touched it, as JSON `{lines, ranges, authors, edits}`. `edit_log`
+/// is the document's edit messages concatenated as sent (other message
+/// types are skipped); they are replayed in order from an empty document,
+/// and the replayed text is matched line by line to `document`. Lines
+/// changed outside the log (or by edits whose `length` is missing) come
+/// back with a null `user_id` rather than a wrong author, as do lines
+/// last written before a compaction checkpoint. `lines` has one `{line,
+/// user_id, timestamp}` per line; `ranges` merges runs of lines from the
+/// same edit for a gutter.
+#[wasm_bindgen]
+pub fn blame(document: &str, edit_log: &[u8]) -> Result<String, JsValue> {
+    let edits = read_edit_log(edit_log).map_err(|e| JsValue::from_str(&e))?;
+    let (replayed, authors) = replay_with_authors(&edits);
+
+    // Last edit touching each replayed line, newline included
+    let mut replayed_lines = Vec::new();
+    let mut line_edits = Vec::new();
+    let mut offset = 0;
+    for line in replayed.split_inclusive('\n') {
+        let last = authors[offset..offset + line.len()]
+            .iter()
+            .copied()
+            .max()
+            .filter(|&e| edits[e].edit_type != CHECKPOINT);
+        replayed_lines.push(line.trim_end_matches('\n'));
+        line_edits.push(last);
+        offset += line.len();
+    }
+
+    let document_lines: Vec<&str> = document.lines().collect();
+    let mut credited: Vec<Option<usize>> = vec![None; document_lines.len()];
+    for op in diff_strs(&replayed_lines, &document_lines) {
+        if op.kind == DiffKind::Equal {
+            for (i, j) in op.old.zip(op.new) {
+                credited[j] = line_edits[i];
+            }
+        }
+    }
+
+    let lines: Vec<LineBlame> = credited
+        .iter()
+        .enumerate()
+        .map(|(n, edit)| LineBlame {
+            line: n + 1,
+            user_id: edit.map(|e| edits[e].user_id.as_str()),
+            timestamp: edit.map(|e| edits[e].timestamp),
+        })
+        .collect();
+    let mut ranges: Vec<BlameRange> = Vec::new();
+    for (n, edit) in credited.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(range) if n > 0 && credited[n - 1] == *edit => range.end_line = n + 1,
+            _ => ranges.push(BlameRange {
+                start_line: n + 1,
+                end_line: n + 1,
+                user_id: edit.map(|e| edits[e].user_id.as_str()),
+                timestamp: edit.map(|e| edits[e].timestamp),
+            }),
+        }
+    }
+    let mut counts: Vec<AuthorLines> = Vec::new();
+    for user_id in lines.iter().filter_map(|l| l.user_id) {
+        match counts.iter_mut().find(|a| a.user_id == user_id) {
+            Some(author) => author.lines += 1,
+            None => counts.push(AuthorLines { user_id, lines: 1 }),
+        }
+    }
+    counts.sort_by_key(|a| std::cmp::Reverse(a.lines));
+
+    let result = Blame { lines, ranges, authors: counts, edits: edits.len() };
+    Ok(serde_json::to_string(&result).unwrap_or_else(|_| "{}".into()))
+}
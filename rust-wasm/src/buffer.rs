@@ -0,0 +1,170 @@
+// The text of an open document, kept current by applying the edit messages
+// of its stream. A Workspace holds one per open tab, so a single WASM
+// instance can edit several documents at once.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::edit_log::message_edits;
+use crate::metadata::CONTENT_MESSAGE_TYPES;
+use crate::{count_lines, count_words, data_text, decode_with_grid_tag, PromiseGridMessage};
+
+/// Document statistics as `calculate_document_stats` reports them
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub(crate) struct BufferStats {
+    pub words: usize,
+    pub chars_with_spaces: usize,
+    pub chars_without_spaces: usize,
+    pub lines: usize,
+}
+
+impl BufferStats {
+    pub(crate) fn of(text: &str) -> BufferStats {
+        BufferStats {
+            words: count_words(text),
+            chars_with_spaces: text.len(),
+            chars_without_spaces: text.chars().filter(|c| !c.is_whitespace()).count(),
+            lines: count_lines(text),
+        }
+    }
+
+    pub(crate) fn add(&mut self, other: BufferStats) {
+        self.words += other.words;
+        self.chars_with_spaces += other.chars_with_spaces;
+        self.chars_without_spaces += other.chars_without_spaces;
+        self.lines += other.lines;
+    }
+}
+
+/// A match of a search
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct Match {
+    pub start: usize,
+    pub end: usize,
+    /// 1-based
+    pub line: usize,
+}
+
+/// Whether `text` starts with `query`, ignoring case, and how many bytes
+/// of `text` the match covers
+fn starts_with_ignoring_case(text: &str, query: &str) -> Option<usize> {
+    let mut text_chars = text.char_indices().flat_map(|(i, c)| c.to_lowercase().map(move |l| (i + c.len_utf8(), l)));
+    let mut end = 0;
+    for expected in query.chars().flat_map(char::to_lowercase) {
+        let (after, c) = text_chars.next()?;
+        if c != expected {
+            return None;
+        }
+        end = after;
+    }
+    Some(end)
+}
+
+/// Non-overlapping matches of `query` in `text`, by byte range
+pub(crate) fn find_matches(text: &str, query: &str, case_sensitive: bool) -> Vec<Match> {
+    let mut matches = Vec::new();
+    if query.is_empty() {
+        return matches;
+    }
+    let mut line = 1;
+    let mut counted = 0;
+    let mut from = 0;
+    while from < text.len() {
+        let found = if case_sensitive {
+            text[from..].find(query).map(|at| (from + at, from + at + query.len()))
+        } else {
+            text[from..]
+                .char_indices()
+                .find_map(|(at, _)| starts_with_ignoring_case(&text[from + at..], query).map(|len| (from + at, from + at + len)))
+        };
+        let Some((start, end)) = found else { break };
+        line += text[counted..start].matches('\n').count();
+        counted = start;
+        matches.push(Match { start, end, line });
+        from = end.max(start + 1);
+        while !text.is_char_boundary(from) {
+            from += 1;
+        }
+    }
+    matches
+}
+
+/// An open document's text
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DocumentBuffer {
+    document_id: String,
+    text: String,
+    /// Edits applied since it was opened
+    version: u64,
+}
+
+#[wasm_bindgen]
+impl DocumentBuffer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str, content: &str) -> DocumentBuffer {
+        DocumentBuffer { document_id: document_id.to_string(), text: content.to_string(), version: 0 }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn document_id(&self) -> String {
+        self.document_id.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn content(&self) -> String {
+        self.text.clone()
+    }
+
+    /// Replace the whole text, e.g. after loading a snapshot
+    pub fn set_content(&mut self, content: &str) {
+        self.text = content.to_string();
+        self.version += 1;
+    }
+
+    /// Edits applied since the buffer was opened
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Apply an edit message (`document_edit`, `document_checkpoint`,
+    /// `edit_batch` or `batch`) for this document. Returns false for other
+    /// messages. Check messages with an InboundDeduper first: a repeated
+    /// edit would be applied twice.
+    pub fn apply_message(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        Ok(self.apply(&message))
+    }
+
+    /// Statistics as JSON `{words, chars_with_spaces,
+    /// chars_without_spaces, lines}`
+    pub fn stats(&self) -> String {
+        serde_json::to_string(&BufferStats::of(&self.text)).unwrap_or_else(|_| "{}".into())
+    }
+
+    /// Matches of `query` as JSON `[{start, end, line}]` (byte offsets)
+    pub fn search(&self, query: &str, case_sensitive: bool) -> String {
+        serde_json::to_string(&find_matches(&self.text, query, case_sensitive)).unwrap_or_else(|_| "[]".into())
+    }
+}
+
+impl DocumentBuffer {
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub(crate) fn apply(&mut self, message: &PromiseGridMessage) -> bool {
+        if !CONTENT_MESSAGE_TYPES.contains(&message.payload.message_type.as_str())
+            || data_text(&message.payload.data, "document_id") != Some(self.document_id.as_str())
+        {
+            return false;
+        }
+        for edit in message_edits(message) {
+            edit.apply(&mut self.text);
+            self.version += 1;
+        }
+        true
+    }
+}
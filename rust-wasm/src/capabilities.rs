@@ -0,0 +1,175 @@
+// Introspection: what this build supports, so the frontend can enable
+// features by asking instead of sniffing versions.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::{activity, audit, export, fidelity, highlight, locale, performance, punctuation, quota, share};
+
+/// Feature names the frontend can test for. Add an entry when a new
+/// user-visible capability lands.
+const FEATURES: &[&str] = &[
+    "formatting",
+    "format_options",
+    "incremental_formatting",
+    "toggles",
+    "document_stats",
+    "style_metrics",
+    "search",
+    "compression",
+    "syntax_highlighting",
+    "math",
+    "footnotes",
+    "front_matter",
+    "headings",
+    "table_of_contents",
+    "outline",
+    "wiki_links",
+    "link_check",
+    "images",
+    "abbreviations",
+    "lint_scheduling",
+    "roundtrip_check",
+    "archive_import",
+    "folders",
+    "workspace",
+    "document_metadata",
+    "activity_log",
+    "audit_log",
+    "share_codes",
+    "signing",
+    "edit_hash_chain",
+    "macros",
+    "daily_notes",
+    "quotas",
+    "performance_profiles",
+    "html_sanitizer",
+    "paste_cleanup",
+    "syntax_tree",
+    "export_manager",
+    "document_import",
+    "document_diff",
+    "word_diff",
+    "three_way_merge",
+    "patches",
+    "version_history",
+    "blame",
+    "history_replay",
+    "history_compaction",
+    "session_archive",
+    "edit_batching",
+    "live_cursors",
+    "presence",
+    "delivery_acks",
+    "sequence_numbers",
+    "lamport_clock",
+    "typed_payloads",
+    "message_validation",
+    "e2e_encryption",
+    "passphrase_keys",
+    "key_exchange",
+    "replay_protection",
+    "batch_messages",
+    "snapshot_sync",
+    "delta_sync",
+    "access_control",
+    "capability_tokens",
+    "protocol_handshake",
+    "message_handlers",
+    "offline_queue",
+    "deduplication",
+    "transport_stats",
+    "cbor_diagnostic",
+    "cbor_json",
+    "legacy_framing",
+    "logging",
+    "comments",
+    "mentions",
+    "user_profiles",
+    "document_properties",
+    "multi_document",
+    "snippets",
+    "autosave",
+    "writing_goals",
+    "citations",
+    "smart_paste",
+];
+
+/// Formats exported outside the `ExportManager` registry
+const EXTRA_EXPORT_FORMATS: &[&str] = &["promisegrid"];
+
+const IMPORT_FORMATS: &[&str] = &["notion", "obsidian", "markdown", "html", "rtf", "csv", "cbor_bundle", "promisegrid"];
+
+#[derive(Serialize)]
+struct Protocol {
+    protocol_hash: &'static str,
+    cbor_tag: u64,
+    message_types: &'static [&'static str],
+    share_payload_version: u8,
+}
+
+#[derive(Serialize)]
+struct Limits {
+    max_audit_entries: usize,
+    max_activity_events: usize,
+    max_revisions_per_document: usize,
+    min_share_code_bits: u32,
+    max_share_code_bits: u32,
+    default_max_document_bytes: usize,
+    default_max_attachment_bytes: usize,
+    default_max_total_attachment_bytes: usize,
+}
+
+#[derive(Serialize)]
+struct Capabilities {
+    version: &'static str,
+    debug_build: bool,
+    features: &'static [&'static str],
+    export_formats: Vec<&'static str>,
+    import_formats: &'static [&'static str],
+    roundtrip_formats: &'static [&'static str],
+    highlight_languages: Vec<&'static str>,
+    punctuation_locales: &'static [&'static str],
+    display_locales: Vec<&'static str>,
+    performance_profiles: &'static [&'static str],
+    protocol: Protocol,
+    limits: Limits,
+}
+
+/// Describe this build as JSON: `{version, debug_build, features,
+/// export_formats, import_formats, roundtrip_formats, highlight_languages,
+/// punctuation_locales, display_locales, performance_profiles,
+/// protocol: {protocol_hash, cbor_tag, message_types,
+/// share_payload_version}, limits: {...}}`
+#[wasm_bindgen]
+pub fn get_capabilities() -> String {
+    let capabilities = Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        debug_build: cfg!(debug_assertions),
+        features: FEATURES,
+        export_formats: export::format_ids().into_iter().chain(EXTRA_EXPORT_FORMATS.iter().copied()).collect(),
+        import_formats: IMPORT_FORMATS,
+        roundtrip_formats: fidelity::ROUNDTRIP_FORMATS,
+        highlight_languages: highlight::highlight_languages(),
+        punctuation_locales: punctuation::PUNCTUATION_LOCALES,
+        display_locales: locale::locale_tags(),
+        performance_profiles: performance::PERFORMANCE_PROFILES,
+        protocol: Protocol {
+            protocol_hash: crate::PROTOCOL_HASH,
+            cbor_tag: crate::GRID_TAG,
+            message_types: crate::MESSAGE_TYPES,
+            share_payload_version: share::SHARE_PAYLOAD_VERSION,
+        },
+        limits: Limits {
+            max_audit_entries: audit::MAX_AUDIT_ENTRIES,
+            max_activity_events: activity::MAX_EVENTS,
+            max_revisions_per_document: activity::MAX_REVISIONS,
+            min_share_code_bits: share::MIN_SHARE_CODE_BITS,
+            max_share_code_bits: share::MAX_SHARE_CODE_BITS,
+            default_max_document_bytes: quota::DEFAULT_MAX_DOCUMENT_BYTES,
+            default_max_attachment_bytes: quota::DEFAULT_MAX_ATTACHMENT_BYTES,
+            default_max_total_attachment_bytes: quota::DEFAULT_MAX_TOTAL_ATTACHMENT_BYTES,
+        },
+    };
+    serde_json::to_string(&capabilities).unwrap_or_else(|_| "{}".to_string())
+}
@@ -0,0 +1,208 @@
+// Capability tokens, PromiseGrid's promises applied to access. An owner
+// mints a token promising one collaborator the right to send certain
+// message types to a document until it expires, signed with the owner's
+// key. The token names the collaborator's Ed25519 public key; they attach
+// it to outgoing messages and sign each message with the matching secret
+// key. Receivers check the token against the owners they know and the
+// message against the holder's key. An AccessController in token mode
+// (`set_require_tokens`) accepts a write only with a valid token or an
+// owner's signature; otherwise a token widens what the sender's signed
+// role allows.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::payload::byte_string;
+use crate::signing;
+use crate::{data_bytes, data_text, decode_with_grid_tag, encode_with_grid_tag, to_canonical_cbor, PromiseGridMessage};
+
+/// Data field a message carries its token in
+pub(crate) const CAPABILITY_FIELD: &str = "capability";
+
+/// Data field carrying the holder's signature of the message
+pub(crate) const CAPABILITY_SIGNATURE_FIELD: &str = "capability_signature";
+
+/// A signed promise of access to one document
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CapabilityToken {
+    pub document_id: String,
+    /// Owner who signed the token
+    pub grantor: String,
+    /// User the token was issued to; only messages from them may carry it
+    pub holder: String,
+    /// Holder's Ed25519 public key, which must sign messages carrying it
+    #[serde(with = "byte_string")]
+    pub holder_key: Vec<u8>,
+    /// Message types the holder may send, e.g. "document_edit"
+    pub operations: Vec<String>,
+    /// Milliseconds since the Unix epoch
+    pub issued_at: f64,
+    pub expires_at: f64,
+    #[serde(with = "byte_string")]
+    pub signature: Vec<u8>,
+}
+
+/// What the signature covers: everything in the token but the signature
+#[derive(Serialize)]
+struct SignedToken<'a> {
+    document_id: &'a str,
+    grantor: &'a str,
+    holder: &'a str,
+    #[serde(with = "byte_string")]
+    holder_key: &'a [u8],
+    operations: &'a [String],
+    issued_at: f64,
+    expires_at: f64,
+}
+
+fn signing_bytes(token: &CapabilityToken) -> Result<Vec<u8>, JsValue> {
+    let signed = SignedToken {
+        document_id: &token.document_id,
+        grantor: &token.grantor,
+        holder: &token.holder,
+        holder_key: &token.holder_key,
+        operations: &token.operations,
+        issued_at: token.issued_at,
+        expires_at: token.expires_at,
+    };
+    to_canonical_cbor(&signed).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+}
+
+fn parse_token(token: &[u8]) -> Result<CapabilityToken, String> {
+    crate::from_cbor(token).map_err(|e| format!("Invalid capability token: {}", e))
+}
+
+/// Mint a token letting `holder`, who signs with the Ed25519 public key
+/// `holder_key`, send the message types in `operations` (a JSON array such
+/// as `["document_edit", "cursor_update"]`) to `document_id` until
+/// `expires_at` (ms since epoch). `grantor` signs it with their 32-byte
+/// Ed25519 `secret_key`; receivers accept it only if they know the grantor
+/// as an owner of the document.
+#[wasm_bindgen]
+pub fn mint_capability_token(
+    document_id: &str,
+    holder: &str,
+    holder_key: &[u8],
+    operations: &str,
+    expires_at: f64,
+    grantor: &str,
+    secret_key: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    if holder_key.len() != 32 {
+        return Err(JsValue::from_str("Holder public key must be 32 bytes"));
+    }
+    let operations: Vec<String> = serde_json::from_str(operations)
+        .map_err(|e| JsValue::from_str(&format!("Invalid capability operations: {}", e)))?;
+    if operations.is_empty() {
+        return Err(JsValue::from_str("A capability token must allow at least one operation"));
+    }
+    let issued_at = js_sys::Date::now();
+    if !expires_at.is_finite() || expires_at <= issued_at {
+        return Err(JsValue::from_str("Capability token expiry must be in the future"));
+    }
+    let mut token = CapabilityToken {
+        document_id: document_id.to_string(),
+        grantor: grantor.to_string(),
+        holder: holder.to_string(),
+        holder_key: holder_key.to_vec(),
+        operations,
+        issued_at,
+        expires_at,
+        signature: Vec::new(),
+    };
+    token.signature = signing::sign(secret_key, &signing_bytes(&token)?)?;
+    to_canonical_cbor(&token).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+}
+
+/// Attach a token from `mint_capability_token` to an outgoing message,
+/// replacing any already attached, and sign the message with the holder's
+/// 32-byte Ed25519 `secret_key`. The message must be from the token's
+/// holder and for its document.
+#[wasm_bindgen]
+pub fn attach_capability_token(cbor_bytes: &[u8], token: &[u8], secret_key: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let parsed = parse_token(token).map_err(|e| JsValue::from_str(&e))?;
+    let mut message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    let data = &message.payload.data;
+    if data_text(data, "user_id") != Some(parsed.holder.as_str()) {
+        return Err(JsValue::from_str(&format!("Capability token was issued to {}, not the sender", parsed.holder)));
+    }
+    if data_text(data, "document_id") != Some(parsed.document_id.as_str()) {
+        return Err(JsValue::from_str(&format!("Capability token is for document {}", parsed.document_id)));
+    }
+    if signing::signing_public_key(secret_key)? != parsed.holder_key {
+        return Err(JsValue::from_str("Signing key does not match the capability token's holder key"));
+    }
+    message.payload.data.insert(CAPABILITY_FIELD.to_string(), ciborium::Value::Bytes(token.to_vec()));
+    let signed = signing::message_signing_bytes(&message).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))?;
+    let signature = signing::sign(secret_key, &signed)?;
+    message.payload.data.insert(CAPABILITY_SIGNATURE_FIELD.to_string(), ciborium::Value::Bytes(signature));
+    encode_with_grid_tag(&message).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+}
+
+#[derive(Serialize)]
+struct TokenSummary<'a> {
+    document_id: &'a str,
+    grantor: &'a str,
+    holder: &'a str,
+    operations: &'a [String],
+    issued_at: f64,
+    expires_at: f64,
+}
+
+/// A token's contents as JSON `{document_id, grantor, holder, operations,
+/// issued_at, expires_at}`, for display; the signature is not checked
+#[wasm_bindgen]
+pub fn describe_capability_token(token: &[u8]) -> Result<String, JsValue> {
+    let token = parse_token(token).map_err(|e| JsValue::from_str(&e))?;
+    let summary = TokenSummary {
+        document_id: &token.document_id,
+        grantor: &token.grantor,
+        holder: &token.holder,
+        operations: &token.operations,
+        issued_at: token.issued_at,
+        expires_at: token.expires_at,
+    };
+    Ok(serde_json::to_string(&summary).unwrap_or_else(|_| "{}".into()))
+}
+
+/// Check the token a message carries at `now`: signed by one of `owners`
+/// (user_id -> Ed25519 public key), for `document_id`, issued to the
+/// message's sender, unexpired and allowing the message's type, with the
+/// message signed by the holder's key. Returns the grantor, or why the
+/// token doesn't hold.
+pub(crate) fn verify(
+    message: &PromiseGridMessage,
+    document_id: &str,
+    owners: &BTreeMap<String, Vec<u8>>,
+    now: f64,
+) -> Result<String, String> {
+    let data = &message.payload.data;
+    let token = parse_token(data_bytes(data, CAPABILITY_FIELD).ok_or("no capability token attached")?)?;
+    let public_key = owners
+        .get(&token.grantor)
+        .ok_or_else(|| format!("capability token from {}, who is not a document owner", token.grantor))?;
+    let signed = signing_bytes(&token).map_err(|_| "capability token could not be encoded".to_string())?;
+    if !signing::verify(public_key, &signed, &token.signature) {
+        return Err("capability token signature does not verify".to_string());
+    }
+    if token.document_id != document_id || data_text(data, "document_id") != Some(document_id) {
+        return Err(format!("capability token is for document {}", token.document_id));
+    }
+    if data_text(data, "user_id") != Some(token.holder.as_str()) {
+        return Err(format!("capability token was issued to {}", token.holder));
+    }
+    let holder_signature = data_bytes(data, CAPABILITY_SIGNATURE_FIELD).ok_or("message is not signed by the token holder")?;
+    let message_signed = signing::message_signing_bytes(message).map_err(|_| "message could not be encoded".to_string())?;
+    if !signing::verify(&token.holder_key, &message_signed, holder_signature) {
+        return Err("message signature does not verify against the token holder's key".to_string());
+    }
+    if now >= token.expires_at {
+        return Err(format!("capability token expired at {}", token.expires_at));
+    }
+    if !token.operations.contains(&message.payload.message_type) {
+        return Err(format!("capability token does not allow {}", message.payload.message_type));
+    }
+    Ok(token.grantor)
+}
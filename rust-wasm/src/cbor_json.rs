@@ -0,0 +1,466 @@
+// Conversion between CBOR and JSON, so fixtures and debugging tools can
+// be written in JS against the encoding the handler uses. JSON has no
+// byte strings, tags, big integers, non-finite floats or non-text map
+// keys, so those are written as objects with a single `$` key:
+//
+//   {"$bytes": "AQID"}                      byte string, standard base64
+//   {"$bigint": "-18446744073709551617"}    integer outside -2^63..2^64
+//                                           (or a tag 2/3 bignum), decimal
+//   {"$tag": 1735551332, "$value": {...}}   any other tag
+//   {"$float": "NaN"}                       also "Infinity", "-Infinity"
+//   {"$simple": 23}                         undefined and other simple values
+//   {"$map": [[1, "a"], [2, "b"]]}          a map with a key that isn't text
+//                                           or that starts with `$`
+//
+// Numbers with a fraction or exponent (`1.0`) are floats and the rest are
+// integers. Indefinite lengths are read but not kept. When encoding,
+// integers and floats take the shortest head that holds them exactly and
+// map keys are sorted canonically, as `to_canonical_cbor` does; a
+// PromiseGrid message is `{"$tag": 1735551332, "$value": {...}}`.
+
+use wasm_bindgen::prelude::*;
+use serde_json::{Map, Value};
+
+use crate::diagnostic::{half_to_f64, Reader, MAX_DEPTH};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn from_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut n, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        let value = BASE64.iter().position(|b| *b == c)? as u32;
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// A big-endian magnitude in decimal
+fn to_decimal(magnitude: &[u8]) -> String {
+    let mut digits = Vec::new();
+    let mut rest: Vec<u8> = magnitude.iter().copied().skip_while(|b| *b == 0).collect();
+    while !rest.is_empty() {
+        let mut remainder = 0u32;
+        for byte in rest.iter_mut() {
+            let n = remainder << 8 | *byte as u32;
+            *byte = (n / 10) as u8;
+            remainder = n % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+        rest = rest.into_iter().skip_while(|b| *b == 0).collect();
+    }
+    if digits.is_empty() {
+        return "0".to_string();
+    }
+    digits.iter().rev().map(|d| *d as char).collect()
+}
+
+/// Decimal digits as a big-endian magnitude without leading zeros
+fn from_decimal(digits: &str) -> Option<Vec<u8>> {
+    if digits.is_empty() {
+        return None;
+    }
+    let mut magnitude: Vec<u8> = Vec::new();
+    for c in digits.bytes() {
+        let mut carry = (c as char).to_digit(10)?;
+        for byte in magnitude.iter_mut().rev() {
+            let n = *byte as u32 * 10 + carry;
+            *byte = n as u8;
+            carry = n >> 8;
+        }
+        if carry > 0 {
+            magnitude.insert(0, carry as u8);
+        }
+    }
+    Some(magnitude)
+}
+
+/// Add one to, or take one from, a big-endian magnitude
+fn step(magnitude: &mut Vec<u8>, up: bool) {
+    for byte in magnitude.iter_mut().rev() {
+        let (n, overflow) = if up { byte.overflowing_add(1) } else { byte.overflowing_sub(1) };
+        *byte = n;
+        if !overflow {
+            break;
+        }
+    }
+    if up && magnitude.iter().all(|b| *b == 0) {
+        magnitude.insert(0, 1);
+    }
+    while magnitude.first() == Some(&0) {
+        magnitude.remove(0);
+    }
+}
+
+fn dollar(key: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(key.to_string(), value);
+    Value::Object(map)
+}
+
+/// The text of a string item, reading the chunks of an indefinite one
+fn string_bytes(reader: &mut Reader, major: u8, info: u8) -> Result<Vec<u8>, String> {
+    if info != 31 {
+        let len = reader.argument(info)?;
+        return Ok(reader.take(len)?.to_vec());
+    }
+    let mut bytes = Vec::new();
+    while !reader.at_break()? {
+        let head = reader.byte()?;
+        if head >> 5 != major || head & 0x1f == 31 {
+            return Err("string chunk is not a definite-length string of the same type".to_string());
+        }
+        let len = reader.argument(head & 0x1f)?;
+        bytes.extend_from_slice(reader.take(len)?);
+    }
+    Ok(bytes)
+}
+
+/// Items of an array or map: `len` of them, or up to a break when None
+fn items(reader: &mut Reader, len: Option<u64>, depth: usize) -> Result<Vec<Value>, String> {
+    let mut items = Vec::new();
+    match len {
+        Some(len) => {
+            for _ in 0..len {
+                items.push(read(reader, depth + 1)?);
+            }
+        }
+        None => {
+            while !reader.at_break()? {
+                items.push(read(reader, depth + 1)?);
+            }
+        }
+    }
+    Ok(items)
+}
+
+/// Read one data item as JSON
+fn read(reader: &mut Reader, depth: usize) -> Result<Value, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("nested deeper than {} levels", MAX_DEPTH));
+    }
+    let head = reader.byte()?;
+    let (major, info) = (head >> 5, head & 0x1f);
+    let len = |reader: &mut Reader| if info == 31 { Ok(None) } else { reader.argument(info).map(Some) };
+    if info == 31 && !(2..=5).contains(&major) {
+        return Err(if major == 7 {
+            "break outside an indefinite-length item".to_string()
+        } else {
+            format!("major type {} can't have an indefinite length", major)
+        });
+    }
+    Ok(match major {
+        0 => Value::from(reader.argument(info)?),
+        1 => {
+            let n = reader.argument(info)?;
+            match i64::try_from(n) {
+                Ok(n) => Value::from(-1 - n),
+                Err(_) => dollar("$bigint", Value::String((-1 - n as i128).to_string())),
+            }
+        }
+        2 => dollar("$bytes", Value::String(to_base64(&string_bytes(reader, major, info)?))),
+        3 => {
+            let bytes = string_bytes(reader, major, info)?;
+            Value::String(String::from_utf8(bytes).map_err(|_| "text string is not valid UTF-8")?)
+        }
+        4 => {
+            let len = len(reader)?;
+            Value::Array(items(reader, len, depth)?)
+        }
+        5 => {
+            let len = len(reader)?.map(|n| n.saturating_mul(2));
+            let flat = items(reader, len, depth)?;
+            if flat.len() % 2 != 0 {
+                return Err("map ends after a key with no value".to_string());
+            }
+            let plain = flat.chunks(2).all(|pair| matches!(&pair[0], Value::String(key) if !key.starts_with('$')));
+            if plain {
+                let mut map = Map::new();
+                for pair in flat.chunks(2) {
+                    if let Value::String(key) = &pair[0] {
+                        map.insert(key.clone(), pair[1].clone());
+                    }
+                }
+                Value::Object(map)
+            } else {
+                dollar("$map", Value::Array(flat.chunks(2).map(|pair| Value::Array(pair.to_vec())).collect()))
+            }
+        }
+        6 => {
+            let tag = reader.argument(info)?;
+            if matches!(tag, 2 | 3) && reader.peek().is_some_and(|next| next >> 5 == 2) {
+                let next = reader.byte()?;
+                let mut magnitude = string_bytes(reader, 2, next & 0x1f)?;
+                if tag == 3 {
+                    step(&mut magnitude, true);
+                }
+                let sign = if tag == 3 { "-" } else { "" };
+                return Ok(dollar("$bigint", Value::String(format!("{}{}", sign, to_decimal(&magnitude)))));
+            }
+            let mut map = Map::new();
+            map.insert("$tag".to_string(), Value::from(tag));
+            map.insert("$value".to_string(), read(reader, depth + 1)?);
+            Value::Object(map)
+        }
+        _ => match info {
+            20 => Value::Bool(false),
+            21 => Value::Bool(true),
+            22 => Value::Null,
+            0..=19 | 23 => dollar("$simple", Value::from(info)),
+            24 => dollar("$simple", Value::from(reader.byte()?)),
+            25 => float(half_to_f64(reader.argument(info)? as u16)),
+            26 => float(f32::from_bits(reader.argument(info)? as u32) as f64),
+            27 => float(f64::from_bits(reader.argument(info)?)),
+            _ => return Err(format!("reserved additional information {}", info)),
+        },
+    })
+}
+
+fn float(value: f64) -> Value {
+    if value.is_nan() {
+        dollar("$float", Value::String("NaN".to_string()))
+    } else if value.is_infinite() {
+        dollar("$float", Value::String(if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()))
+    } else {
+        Value::from(value)
+    }
+}
+
+/// Convert CBOR to JSON. Tags, byte strings, big integers and the other
+/// things JSON can't hold are written as `$` objects: `{"$bytes":
+/// base64}`, `{"$tag": n, "$value": v}`, `{"$bigint": decimal}`,
+/// `{"$float": "NaN"}`, `{"$simple": n}` and `{"$map": [[key, value],
+/// ...]}`. A PromiseGrid message converts with its grid tag.
+#[wasm_bindgen]
+pub fn cbor_to_json(cbor_bytes: &[u8]) -> Result<String, JsValue> {
+    let mut reader = Reader::new(cbor_bytes);
+    let value = read(&mut reader, 0)
+        .and_then(|value| if reader.is_done() { Ok(value) } else { Err("bytes left after the first item".to_string()) })
+        .map_err(|e| JsValue::from_str(&format!("Malformed CBOR at byte {}: {}", reader.pos, e)))?;
+    Ok(value.to_string())
+}
+
+fn head(major: u8, n: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match n {
+        0..=23 => out.push(major | n as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, n as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+}
+
+/// The half-precision bits of `value` when it has them exactly
+fn half_bits(value: f32) -> Option<u16> {
+    let bits = value.to_bits();
+    let sign = (bits >> 16 & 0x8000) as u16;
+    let exponent = (bits >> 23 & 0xff) as i32 - 127;
+    let mantissa = bits & 0x7f_ffff;
+    let half = if value == 0.0 {
+        sign
+    } else if (-14..=15).contains(&exponent) && mantissa & 0x1fff == 0 {
+        sign | ((exponent + 15) as u16) << 10 | (mantissa >> 13) as u16
+    } else if (-24..-14).contains(&exponent) {
+        let significand = mantissa | 0x80_0000;
+        let shift = -(exponent + 1);
+        if significand & ((1 << shift) - 1) != 0 {
+            return None;
+        }
+        sign | (significand >> shift) as u16
+    } else {
+        return None;
+    };
+    (half_to_f64(half) == value as f64).then_some(half)
+}
+
+/// A float in the shortest width that holds it exactly, as the handler
+/// writes them
+fn write_float(value: f64, out: &mut Vec<u8>) {
+    if value.is_nan() {
+        out.extend_from_slice(&[0xf9, 0x7e, 0x00]);
+    } else if value.is_infinite() {
+        out.extend_from_slice(if value > 0.0 { &[0xf9, 0x7c, 0x00] } else { &[0xf9, 0xfc, 0x00] });
+    } else if value as f32 as f64 != value {
+        out.push(0xfb);
+        out.extend_from_slice(&value.to_bits().to_be_bytes());
+    } else if let Some(half) = half_bits(value as f32) {
+        out.push(0xf9);
+        out.extend_from_slice(&half.to_be_bytes());
+    } else {
+        out.push(0xfa);
+        out.extend_from_slice(&(value as f32).to_bits().to_be_bytes());
+    }
+}
+
+fn write_bigint(text: &str, out: &mut Vec<u8>) -> Result<(), String> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let mut magnitude = from_decimal(digits).ok_or_else(|| format!("$bigint {:?} is not a decimal integer", text))?;
+    if negative && !magnitude.is_empty() {
+        // -1 - n is stored as n
+        step(&mut magnitude, false);
+    }
+    let major = if negative && digits.bytes().any(|d| d != b'0') { 1 } else { 0 };
+    if magnitude.len() <= 8 {
+        let n = magnitude.iter().fold(0u64, |n, b| n << 8 | *b as u64);
+        head(major, n, out);
+    } else {
+        head(6, 2 + major as u64, out);
+        head(2, magnitude.len() as u64, out);
+        out.extend_from_slice(&magnitude);
+    }
+    Ok(())
+}
+
+/// Encode a `$` object, or None when `map` is an ordinary one
+fn write_special(map: &Map<String, Value>, out: &mut Vec<u8>, depth: usize) -> Option<Result<(), String>> {
+    let mut keys: Vec<&str> = map.keys().map(String::as_str).collect();
+    keys.sort_unstable();
+    let result = match (keys.as_slice(), map.values().next()?) {
+        (["$bytes"], Value::String(text)) => from_base64(text)
+            .map(|bytes| {
+                head(2, bytes.len() as u64, out);
+                out.extend_from_slice(&bytes);
+            })
+            .ok_or_else(|| "$bytes is not base64".to_string()),
+        (["$bigint"], Value::String(text)) => write_bigint(text, out),
+        (["$tag", "$value"], _) => match map["$tag"].as_u64() {
+            Some(tag) => {
+                head(6, tag, out);
+                write(&map["$value"], out, depth + 1)
+            }
+            None => Err("$tag is not a non-negative integer".to_string()),
+        },
+        (["$float"], Value::String(text)) => {
+            let value = match text.as_str() {
+                "NaN" => f64::NAN,
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                other => return Some(Err(format!("$float {:?} is not NaN, Infinity or -Infinity", other))),
+            };
+            write_float(value, out);
+            Ok(())
+        }
+        (["$simple"], value) => {
+            match value.as_u64() {
+                Some(n @ (0..=19 | 23)) => out.push(0xe0 | n as u8),
+                Some(n @ 32..=255) => out.extend_from_slice(&[0xf8, n as u8]),
+                _ => return Some(Err(format!("$simple {} is not an unassigned simple value", value))),
+            }
+            Ok(())
+        }
+        (["$map"], Value::Array(pairs)) => {
+            let pairs: Option<Vec<(&Value, &Value)>> = pairs
+                .iter()
+                .map(|pair| match pair.as_array().map(Vec::as_slice) {
+                    Some([key, value]) => Some((key, value)),
+                    _ => None,
+                })
+                .collect();
+            match pairs {
+                Some(pairs) => write_map(pairs, out, depth),
+                None => Err("$map is not an array of [key, value] pairs".to_string()),
+            }
+        }
+        _ if keys.iter().any(|key| key.starts_with('$')) => {
+            Err(format!("{} is not one of the $ forms; write maps with $ keys as $map", Value::Object(map.clone())))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// A map with keys sorted by their encoding, shorter first
+fn write_map(pairs: Vec<(&Value, &Value)>, out: &mut Vec<u8>, depth: usize) -> Result<(), String> {
+    let mut encoded = Vec::with_capacity(pairs.len());
+    for (key, value) in pairs {
+        let mut key_bytes = Vec::new();
+        write(key, &mut key_bytes, depth + 1)?;
+        encoded.push((key_bytes, value));
+    }
+    encoded.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    head(5, encoded.len() as u64, out);
+    for (key_bytes, value) in encoded {
+        out.extend_from_slice(&key_bytes);
+        write(value, out, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn write(value: &Value, out: &mut Vec<u8>, depth: usize) -> Result<(), String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("nested deeper than {} levels", MAX_DEPTH));
+    }
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) => head(0, n, out),
+            (None, Some(n)) => head(1, (-1 - n) as u64, out),
+            _ => write_float(n.as_f64().unwrap_or(f64::NAN), out),
+        },
+        Value::String(text) => {
+            head(3, text.len() as u64, out);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            head(4, items.len() as u64, out);
+            for item in items {
+                write(item, out, depth + 1)?;
+            }
+        }
+        Value::Object(map) => {
+            if let Some(result) = write_special(map, out, depth) {
+                return result;
+            }
+            let keys: Vec<Value> = map.keys().map(|key| Value::String(key.clone())).collect();
+            write_map(keys.iter().zip(map.values()).collect(), out, depth)?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert JSON to CBOR, reading the `$` objects `cbor_to_json` writes
+/// back into the byte strings, tags, big integers and other values they
+/// stand for. Objects whose keys start with `$` must be one of those
+/// forms.
+#[wasm_bindgen]
+pub fn json_to_cbor(json: &str) -> Result<Vec<u8>, JsValue> {
+    let value: Value = serde_json::from_str(json).map_err(|e| JsValue::from_str(&format!("Invalid JSON: {}", e)))?;
+    let mut out = Vec::new();
+    write(&value, &mut out, 0).map_err(|e| JsValue::from_str(&format!("Can't encode JSON as CBOR: {}", e)))?;
+    Ok(out)
+}
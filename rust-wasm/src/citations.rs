@@ -0,0 +1,915 @@
+// Citations for academic writing. A .bib file parses into a
+// CitationDatabase; papers cite its entries with pandoc-style markdown
+// citations (`[@key]`, `[-@key]`, `@key`) and generate_bibliography
+// appends the reference list for the keys cited, formatted APA or IEEE.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::markdown::{code_ranges, in_ranges};
+
+/// Heading of the section generate_bibliography writes
+const REFERENCES_HEADING: &str = "## References";
+
+/// Authors listed before APA elides the rest, and before IEEE uses "et al."
+const APA_MAX_AUTHORS: usize = 20;
+const IEEE_MAX_AUTHORS: usize = 6;
+
+const MONTHS: [(&str, &str); 12] = [
+    ("jan", "January"),
+    ("feb", "February"),
+    ("mar", "March"),
+    ("apr", "April"),
+    ("may", "May"),
+    ("jun", "June"),
+    ("jul", "July"),
+    ("aug", "August"),
+    ("sep", "September"),
+    ("oct", "October"),
+    ("nov", "November"),
+    ("dec", "December"),
+];
+
+/// LaTeX accents: the command, the letters with a precomposed form and
+/// those forms, and the combining mark for any other letter
+const ACCENTS: &[(char, &str, &str, char)] = &[
+    ('"', "aeiouyAEIOU", "äëïöüÿÄËÏÖÜ", '\u{308}'),
+    ('\'', "aeiouyAEIOUYcnszCNSZ", "áéíóúýÁÉÍÓÚÝćńśźĆŃŚŹ", '\u{301}'),
+    ('`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ", '\u{300}'),
+    ('^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ", '\u{302}'),
+    ('~', "anoANO", "ãñõÃÑÕ", '\u{303}'),
+    ('=', "aeiouAEIOU", "āēīōūĀĒĪŌŪ", '\u{304}'),
+    ('.', "zZ", "żŻ", '\u{307}'),
+    ('c', "csCS", "çşÇŞ", '\u{327}'),
+    ('v', "cszrneCSZRNE", "čšžřňěČŠŽŘŇĚ", '\u{30C}'),
+    ('u', "agAG", "ăğĂĞ", '\u{306}'),
+    ('H', "ouOU", "őűŐŰ", '\u{30B}'),
+    ('k', "aeAE", "ąęĄĘ", '\u{328}'),
+    ('r', "auAU", "åůÅŮ", '\u{30A}'),
+];
+
+/// LaTeX commands that stand for a character or word
+const SYMBOLS: &[(&str, &str)] = &[
+    ("ss", "ß"),
+    ("ae", "æ"),
+    ("AE", "Æ"),
+    ("oe", "œ"),
+    ("OE", "Œ"),
+    ("o", "ø"),
+    ("O", "Ø"),
+    ("aa", "å"),
+    ("AA", "Å"),
+    ("l", "ł"),
+    ("L", "Ł"),
+    ("i", "ı"),
+    ("j", "ȷ"),
+    ("textendash", "–"),
+    ("textemdash", "—"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("textregistered", "®"),
+    ("copyright", "©"),
+    ("TeX", "TeX"),
+    ("LaTeX", "LaTeX"),
+];
+
+/// A .bib entry. Field values are kept as written, LaTeX and braces
+/// included, once string macros and `#` concatenation are resolved.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BibEntry {
+    /// Lowercase, e.g. "article"
+    entry_type: String,
+    key: String,
+    /// By lowercase field name
+    fields: BTreeMap<String, String>,
+}
+
+impl BibEntry {
+    fn raw(&self, field: &str) -> Option<&str> {
+        self.fields.get(field).map(String::as_str).filter(|v| !v.trim().is_empty())
+    }
+
+    /// A field as plain text
+    fn text(&self, field: &str) -> Option<String> {
+        self.raw(field).map(latex_to_text)
+    }
+
+    fn names(&self, field: &str) -> (Vec<Name>, bool) {
+        self.raw(field).map(parse_names).unwrap_or_default()
+    }
+
+    fn pages(&self) -> Option<String> {
+        self.text("pages").map(|pages| pages.replace('-', "–").replace("––", "–"))
+    }
+
+    fn link(&self) -> Option<String> {
+        match self.text("doi") {
+            Some(doi) if doi.starts_with("http") => Some(doi),
+            Some(doi) => Some(format!("https://doi.org/{}", doi)),
+            None => self.text("url"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EntrySummary<'a> {
+    key: &'a str,
+    entry_type: &'a str,
+    author: Option<String>,
+    title: Option<String>,
+    year: Option<String>,
+}
+
+/// A person's name split the BibTeX way
+#[derive(Debug, Clone, Default)]
+struct Name {
+    first: String,
+    von: String,
+    last: String,
+    jr: String,
+}
+
+impl Name {
+    /// "F. M." for "Fred Michael", "J.-P." for "Jean-Paul"
+    fn initials(&self) -> String {
+        self.first
+            .split_whitespace()
+            .map(|word| {
+                word.split('-')
+                    .filter_map(|part| part.chars().next())
+                    .map(|c| format!("{}.", c.to_uppercase()))
+                    .collect::<Vec<_>>()
+                    .join("-")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn surname(&self) -> String {
+        [self.von.as_str(), self.last.as_str()].iter().filter(|s| !s.is_empty()).copied().collect::<Vec<_>>().join(" ")
+    }
+
+    /// "von Last, F. M., Jr."
+    fn apa(&self) -> String {
+        let mut name = self.surname();
+        let initials = self.initials();
+        if !initials.is_empty() {
+            name = format!("{}, {}", name, initials);
+        }
+        if !self.jr.is_empty() {
+            name = format!("{}, {}", name, self.jr);
+        }
+        name
+    }
+
+    /// "F. M. von Last Jr."
+    fn ieee(&self) -> String {
+        [self.initials(), self.surname(), self.jr.clone()].into_iter().filter(|s| !s.is_empty()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+fn bib_error(source: &str, at: usize, message: &str) -> String {
+    format!("BibTeX error at line {}: {}", source[..at.min(source.len())].matches('\n').count() + 1, message)
+}
+
+/// Parses BibTeX source into entries
+struct BibParser<'a> {
+    source: &'a str,
+    pos: usize,
+    strings: HashMap<String, String>,
+}
+
+impl<'a> BibParser<'a> {
+    fn new(source: &'a str) -> BibParser<'a> {
+        BibParser { source, pos: 0, strings: HashMap::new() }
+    }
+
+    fn error(&self, message: &str) -> String {
+        bib_error(self.source, self.pos, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{}'", expected)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// An entry type, field name or macro name
+    fn identifier(&mut self) -> Result<&'a str, String> {
+        self.skip_whitespace();
+        let rest = &self.source[self.pos..];
+        let len = rest.find(|c: char| c.is_whitespace() || "{}()=,#\"".contains(c)).unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// The inside of a `{...}` group starting at the current position,
+    /// leaving the position after it
+    fn braced(&mut self) -> Result<&'a str, String> {
+        let start = self.pos;
+        let mut depth = 0;
+        for (i, c) in self.source[start..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos = start + i + 1;
+                        return Ok(&self.source[start + 1..start + i]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(bib_error(self.source, start, "unbalanced braces"))
+    }
+
+    /// A `"..."` string, which may hold quotes inside braces
+    fn quoted(&mut self) -> Result<&'a str, String> {
+        let start = self.pos;
+        let mut depth = 0;
+        for (i, c) in self.source[start + 1..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                '"' if depth == 0 => {
+                    self.pos = start + 1 + i + 1;
+                    return Ok(&self.source[start + 1..start + 1 + i]);
+                }
+                _ => {}
+            }
+        }
+        Err(bib_error(self.source, start, "unterminated string"))
+    }
+
+    /// A field value: braced or quoted text, a number or a string macro,
+    /// joined by `#`
+    fn value(&mut self) -> Result<String, String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => value.push_str(self.braced()?),
+                Some('"') => value.push_str(self.quoted()?),
+                Some(c) if c.is_ascii_digit() => {
+                    let rest = &self.source[self.pos..];
+                    let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                    value.push_str(&rest[..len]);
+                    self.pos += len;
+                }
+                Some(_) => {
+                    let at = self.pos;
+                    let name = self.identifier()?.to_lowercase();
+                    let expansion = self
+                        .strings
+                        .get(&name)
+                        .cloned()
+                        .or_else(|| MONTHS.iter().find(|(m, _)| *m == name).map(|(_, month)| month.to_string()))
+                        .ok_or_else(|| bib_error(self.source, at, &format!("undefined string '{}'", name)))?;
+                    value.push_str(&expansion);
+                }
+                None => return Err(self.error("expected a value")),
+            }
+            self.skip_whitespace();
+            if self.peek() != Some('#') {
+                return Ok(value.split_whitespace().collect::<Vec<_>>().join(" "));
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Parse everything; text outside entries is a comment
+    fn entries(mut self) -> Result<Vec<BibEntry>, String> {
+        let mut entries: Vec<BibEntry> = Vec::new();
+        while let Some(at) = self.source[self.pos..].find('@') {
+            self.pos += at + 1;
+            let entry_start = self.pos - 1;
+            let entry_type = self.identifier()?.to_lowercase();
+            self.skip_whitespace();
+            let close = match self.peek() {
+                Some('{') => '}',
+                Some('(') => ')',
+                _ => return Err(self.error("expected '{' or '(' after the entry type")),
+            };
+            match entry_type.as_str() {
+                "comment" | "preamble" => {
+                    if close == '}' {
+                        self.braced()?;
+                    } else {
+                        let end = self.source[self.pos..].find(')').ok_or_else(|| self.error("unterminated entry"))?;
+                        self.pos += end + 1;
+                    }
+                }
+                "string" => {
+                    self.pos += 1;
+                    let name = self.identifier()?.to_lowercase();
+                    self.expect('=')?;
+                    let value = self.value()?;
+                    self.expect(close)?;
+                    self.strings.insert(name, value);
+                }
+                _ => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    let rest = &self.source[self.pos..];
+                    let len = rest.find(|c: char| c == ',' || c == close || c.is_whitespace()).unwrap_or(rest.len());
+                    let key = rest[..len].to_string();
+                    if key.is_empty() {
+                        return Err(self.error("entry has no citation key"));
+                    }
+                    if entries.iter().any(|e| e.key == key) {
+                        return Err(bib_error(self.source, entry_start, &format!("duplicate citation key '{}'", key)));
+                    }
+                    self.pos += len;
+                    let mut fields = BTreeMap::new();
+                    loop {
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(',') => self.pos += 1,
+                            Some(c) if c == close => {
+                                self.pos += 1;
+                                break;
+                            }
+                            _ => return Err(self.error(&format!("expected ',' or '{}' in entry '{}'", close, key))),
+                        }
+                        self.skip_whitespace();
+                        // A trailing comma before the end
+                        if self.peek() == Some(close) {
+                            self.pos += 1;
+                            break;
+                        }
+                        let field = self.identifier()?.to_lowercase();
+                        self.expect('=')?;
+                        let value = self.value()?;
+                        fields.insert(field, value);
+                    }
+                    entries.push(BibEntry { entry_type, key, fields });
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// The letter `base` with a LaTeX accent
+fn compose(accent: char, base: char) -> String {
+    let Some(&(_, bases, composed, mark)) = ACCENTS.iter().find(|(a, ..)| *a == accent) else {
+        return base.to_string();
+    };
+    match bases.chars().position(|c| c == base) {
+        Some(i) => composed.chars().nth(i).map(String::from).unwrap_or_default(),
+        None => format!("{}{}", base, mark),
+    }
+}
+
+/// BibTeX text as plain text: accents and symbol commands turned into
+/// characters, formatting commands and braces dropped, dashes and ties
+/// turned into their characters
+fn latex_to_text(raw: &str) -> String {
+    let chars: Vec<char> = raw.chars().collect();
+    let mut out = String::with_capacity(raw.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            '{' | '}' | '$' => {}
+            '~' => out.push(' '),
+            '-' if chars.get(i) == Some(&'-') => {
+                if chars.get(i + 1) == Some(&'-') {
+                    out.push('—');
+                    i += 2;
+                } else {
+                    out.push('–');
+                    i += 1;
+                }
+            }
+            '\\' => {
+                let Some(&next) = chars.get(i) else { break };
+                let command: String = if next.is_ascii_alphabetic() {
+                    chars[i..].iter().take_while(|c| c.is_ascii_alphabetic()).collect()
+                } else {
+                    next.to_string()
+                };
+                i += command.chars().count();
+                let is_accent = command.chars().count() == 1 && ACCENTS.iter().any(|(a, ..)| command.starts_with(*a));
+                if is_accent {
+                    // The accented letter: `\"o`, `\"{o}`, `\c c` or `\'{\i}`
+                    while command.chars().all(|c| c.is_ascii_alphabetic()) && chars.get(i) == Some(&' ') {
+                        i += 1;
+                    }
+                    let braced = chars.get(i) == Some(&'{');
+                    if braced {
+                        i += 1;
+                    }
+                    if chars.get(i) == Some(&'\\') {
+                        i += 1;
+                    }
+                    if let Some(&base) = chars.get(i) {
+                        out.push_str(&compose(command.chars().next().unwrap_or(' '), base));
+                        i += 1;
+                    }
+                    if braced && chars.get(i) == Some(&'}') {
+                        i += 1;
+                    }
+                } else if let Some((_, symbol)) = SYMBOLS.iter().find(|(name, _)| *name == command) {
+                    out.push_str(symbol);
+                } else if !next.is_ascii_alphabetic() {
+                    // An escaped character: \& \% \_ \# \{ ...
+                    out.push(next);
+                }
+                // Other commands (\emph, \textit, ...) are dropped, their
+                // argument kept; the space ending a command name goes too
+                if next.is_ascii_alphabetic() && !is_accent && chars.get(i) == Some(&' ') {
+                    i += 1;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Split `raw` at `separator` characters outside braces
+fn split_top_level(raw: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in raw.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&raw[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&raw[start..]);
+    parts
+}
+
+/// Whether a name word is a lowercase particle like "von" or "de"
+fn is_particle(word: &str) -> bool {
+    !word.starts_with('{') && latex_to_text(word).chars().next().is_some_and(char::is_lowercase)
+}
+
+fn parse_name(raw: &str) -> Name {
+    let raw = raw.trim();
+    // A name in one brace group is an organisation's: all last name
+    let mut depth = 0;
+    let group_end = raw.char_indices().find_map(|(i, c)| {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        (depth == 0).then_some(i)
+    });
+    if raw.starts_with('{') && group_end == Some(raw.len() - 1) {
+        return Name { last: latex_to_text(raw), ..Name::default() };
+    }
+    let parts: Vec<&str> = split_top_level(raw, ',').into_iter().map(str::trim).collect();
+    let text = |words: &[&str]| latex_to_text(&words.join(" "));
+    let (first, von_last, jr) = match parts.as_slice() {
+        [name] => {
+            // "First von Last": the last word is the last name, lowercase
+            // words before it the particles
+            let words: Vec<&str> = split_top_level(name, ' ').into_iter().filter(|w| !w.is_empty()).collect();
+            if words.len() <= 1 {
+                return Name { last: text(&words), ..Name::default() };
+            }
+            let von_start = words[..words.len() - 1].iter().position(|w| is_particle(w)).unwrap_or(words.len() - 1);
+            (text(&words[..von_start]), words[von_start..].to_vec(), String::new())
+        }
+        [von_last, first] => (latex_to_text(first), split_top_level(von_last, ' '), String::new()),
+        [von_last, jr, first, ..] => (latex_to_text(first), split_top_level(von_last, ' '), latex_to_text(jr)),
+        [] => return Name::default(),
+    };
+    let von_last: Vec<&str> = von_last.into_iter().filter(|w| !w.is_empty()).collect();
+    let particles = von_last[..von_last.len().saturating_sub(1)].iter().take_while(|w| is_particle(w)).count();
+    Name { first, von: text(&von_last[..particles]), last: text(&von_last[particles..]), jr }
+}
+
+/// The names in an author or editor field, and whether it ends with
+/// "and others"
+fn parse_names(raw: &str) -> (Vec<Name>, bool) {
+    let words: Vec<&str> = split_top_level(raw, ' ').into_iter().filter(|w| !w.is_empty()).collect();
+    let mut names = Vec::new();
+    let mut others = false;
+    for name in words.split(|w| w.eq_ignore_ascii_case("and")) {
+        match name {
+            [] => {}
+            [word] if word.eq_ignore_ascii_case("others") => others = true,
+            _ => names.push(parse_name(&name.join(" "))),
+        }
+    }
+    (names, others)
+}
+
+/// `text` ending in a full stop, unless it already ends in punctuation
+fn sentence(text: &str) -> String {
+    if text.ends_with(['.', '?', '!']) {
+        text.to_string()
+    } else {
+        format!("{}.", text)
+    }
+}
+
+fn apa_names(names: &[Name], others: bool) -> String {
+    let formatted: Vec<String> = names.iter().map(Name::apa).collect();
+    match formatted.as_slice() {
+        [] => String::new(),
+        [only] if !others => only.clone(),
+        _ if others => format!("{}, et al.", formatted.join(", ")),
+        _ if formatted.len() > APA_MAX_AUTHORS => {
+            format!("{}, … {}", formatted[..APA_MAX_AUTHORS - 1].join(", "), formatted[formatted.len() - 1])
+        }
+        [rest @ .., last] => format!("{}, & {}", rest.join(", "), last),
+    }
+}
+
+fn ieee_names(names: &[Name], others: bool) -> String {
+    let formatted: Vec<String> = names.iter().map(Name::ieee).collect();
+    match formatted.as_slice() {
+        [] => String::new(),
+        [first, ..] if others || formatted.len() > IEEE_MAX_AUTHORS => format!("{} et al.", first),
+        [only] => only.clone(),
+        [first, second] => format!("{} and {}", first, second),
+        [rest @ .., last] => format!("{}, and {}", rest.join(", "), last),
+    }
+}
+
+/// An APA 7 reference in markdown
+fn format_apa(entry: &BibEntry) -> String {
+    let (authors, others) = entry.names("author");
+    let (editors, _) = entry.names("editor");
+    let year = entry.text("year").unwrap_or_else(|| "n.d.".into());
+    let title = entry.text("title").unwrap_or_else(|| entry.key.clone());
+    let pages = entry.pages();
+    let publisher = entry.text("publisher");
+
+    let standalone = !matches!(entry.entry_type.as_str(), "article" | "inproceedings" | "conference" | "incollection" | "inbook");
+    let title = if standalone { format!("*{}*", title) } else { title };
+    let (title, source) = match entry.entry_type.as_str() {
+        "article" => {
+            let mut source = entry.text("journal").map(|journal| format!("*{}*", journal)).unwrap_or_default();
+            if let Some(volume) = entry.text("volume") {
+                source = format!("{}, *{}*", source, volume);
+                if let Some(number) = entry.text("number") {
+                    source = format!("{}({})", source, number);
+                }
+            }
+            if let Some(pages) = pages {
+                source = format!("{}, {}", source, pages);
+            }
+            (sentence(&title), source.trim_start_matches(", ").to_string())
+        }
+        "inproceedings" | "conference" | "incollection" | "inbook" => {
+            let mut source = String::from("In ");
+            if !editors.is_empty() {
+                let label = if editors.len() == 1 { "Ed." } else { "Eds." };
+                let names: Vec<String> = editors.iter().map(|n| format!("{} {}", n.initials(), n.surname()).trim().to_string()).collect();
+                source.push_str(&format!("{} ({}), ", names.join(", "), label));
+            }
+            if let Some(container) = entry.text("booktitle") {
+                source.push_str(&format!("*{}*", container));
+            }
+            if let Some(pages) = pages {
+                source.push_str(&format!(" (pp. {})", pages));
+            }
+            let source = match &publisher {
+                Some(publisher) => format!("{}. {}", source, publisher),
+                None => source,
+            };
+            (sentence(&title), source)
+        }
+        "phdthesis" | "mastersthesis" => {
+            let kind = if entry.entry_type == "phdthesis" { "Doctoral dissertation" } else { "Master's thesis" };
+            let school = entry.text("school").map(|school| format!(", {}", school)).unwrap_or_default();
+            (format!("{} [{}{}].", title, kind, school), String::new())
+        }
+        "techreport" => {
+            let number = entry.text("number").map(|number| format!(" ({})", number)).unwrap_or_default();
+            (format!("{}{}.", title, number), entry.text("institution").unwrap_or_default())
+        }
+        _ => {
+            let edition = entry.text("edition").map(|edition| format!(" ({} ed.)", edition)).unwrap_or_default();
+            let source = publisher.or_else(|| entry.text("howpublished")).unwrap_or_default();
+            (format!("{}{}.", title, edition), source)
+        }
+    };
+
+    let names = apa_names(&authors, others);
+    let mut reference = if names.is_empty() {
+        // No author: the title takes its place
+        format!("{} ({}).", title, year)
+    } else {
+        format!("{} ({}). {}", sentence(&names), year, title)
+    };
+    if !source.is_empty() {
+        reference = format!("{} {}", reference, sentence(&source));
+    }
+    if let Some(link) = entry.link() {
+        reference = format!("{} {}", reference, link);
+    }
+    reference
+}
+
+/// An IEEE reference in markdown, without its number
+fn format_ieee(entry: &BibEntry) -> String {
+    let (authors, others) = entry.names("author");
+    let title = entry.text("title").unwrap_or_else(|| entry.key.clone());
+    let year = entry.text("year");
+    let mut details: Vec<String> = Vec::new();
+
+    let book = entry.entry_type == "book";
+    match entry.entry_type.as_str() {
+        "book" => {
+            details.extend(entry.text("edition").map(|edition| format!("{} ed.", edition)));
+            let publisher = match (entry.text("address"), entry.text("publisher")) {
+                (Some(address), Some(publisher)) => Some(format!("{}: {}", address, publisher)),
+                (None, publisher) => publisher,
+                (address, None) => address,
+            };
+            details.extend(publisher);
+            details.extend(year);
+        }
+        "article" => {
+            details.extend(entry.text("journal").map(|journal| format!("*{}*", journal)));
+            details.extend(entry.text("volume").map(|volume| format!("vol. {}", volume)));
+            details.extend(entry.text("number").map(|number| format!("no. {}", number)));
+            details.extend(entry.pages().map(|pages| format!("pp. {}", pages)));
+            details.extend(year);
+        }
+        "inproceedings" | "conference" | "incollection" | "inbook" => {
+            details.extend(entry.text("booktitle").map(|booktitle| format!("in *{}*", booktitle)));
+            details.extend(year);
+            details.extend(entry.pages().map(|pages| format!("pp. {}", pages)));
+        }
+        kind @ ("phdthesis" | "mastersthesis") => {
+            details.push(if kind == "phdthesis" { "Ph.D. dissertation" } else { "M.S. thesis" }.to_string());
+            details.extend(entry.text("school"));
+            details.extend(year);
+        }
+        "techreport" => {
+            details.extend(entry.text("institution"));
+            details.push(match entry.text("number") {
+                Some(number) => format!("Tech. Rep. {}", number),
+                None => "Tech. Rep.".to_string(),
+            });
+            details.extend(year);
+        }
+        _ => {
+            details.extend(entry.text("howpublished").or_else(|| entry.text("publisher")));
+            details.extend(year);
+        }
+    }
+
+    // Books are italic; other titles are quoted, with the comma or full
+    // stop inside the quotes
+    let title = match (book, details.is_empty()) {
+        (true, true) => format!("*{}*.", title),
+        (true, false) => format!("*{}*, {}.", title, details.join(", ")),
+        (false, true) => format!("\"{}.\"", title.trim_end_matches('.')),
+        (false, false) => format!("\"{},\" {}.", title, details.join(", ")),
+    };
+    let names = ieee_names(&authors, others);
+    let mut reference = if names.is_empty() { title } else { format!("{}, {}", names, title) };
+    if let Some(doi) = entry.text("doi").filter(|doi| !doi.starts_with("http")) {
+        reference = format!("{} doi: {}.", reference, doi);
+    } else if let Some(link) = entry.link() {
+        reference = format!("{} [Online]. Available: {}", reference, link);
+    }
+    reference
+}
+
+/// Whether `c` may continue a citation key (pandoc's internal punctuation)
+fn key_char(c: char) -> bool {
+    c.is_alphanumeric() || "_:.#$%&-+?<>~/".contains(c)
+}
+
+/// Keys cited in markdown `content` (`[@key]`, `[-@key]`, `[see @a; @b]`,
+/// `@key`, `@{key}`), in order of first citation. Code is skipped, and so
+/// are email addresses.
+pub(crate) fn cited_keys(content: &str) -> Vec<String> {
+    let skip = code_ranges(content);
+    let mut keys: Vec<String> = Vec::new();
+    for (at, _) in content.match_indices('@') {
+        if in_ranges(&skip, at) || content[..at].chars().next_back().is_some_and(|c| c.is_alphanumeric() || "_.+".contains(c)) {
+            continue;
+        }
+        let rest = &content[at + 1..];
+        let key = if let Some(braced) = rest.strip_prefix('{') {
+            braced.find('}').map(|end| &braced[..end])
+        } else if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            let len = rest.find(|c: char| !key_char(c)).unwrap_or(rest.len());
+            // Punctuation at the end belongs to the sentence
+            Some(rest[..len].trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_'))
+        } else {
+            None
+        };
+        if let Some(key) = key.filter(|k| !k.is_empty()) {
+            if !keys.iter().any(|k| k == key) {
+                keys.push(key.to_string());
+            }
+        }
+    }
+    keys
+}
+
+/// Byte range of the `## References` section, from its heading to the
+/// next heading of level 1 or 2 or the end
+fn references_section(content: &str) -> Option<(usize, usize)> {
+    let skip = code_ranges(content);
+    let mut offset = 0;
+    let mut section: Option<(usize, usize)> = None;
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        if in_ranges(&skip, start) {
+            continue;
+        }
+        let line = line.trim_end();
+        match &mut section {
+            None if line == REFERENCES_HEADING => section = Some((start, content.len())),
+            Some((_, end)) if *end == content.len() && (line.starts_with("# ") || line.starts_with("## ")) => *end = start,
+            _ => {}
+        }
+    }
+    section
+}
+
+/// A library of BibTeX entries to cite from
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CitationDatabase {
+    entries: BTreeMap<String, BibEntry>,
+}
+
+/// Parse a .bib file (UTF-8) into a citation database
+#[wasm_bindgen]
+pub fn parse_bibtex(bytes: &[u8]) -> Result<CitationDatabase, JsValue> {
+    let mut database = CitationDatabase::new();
+    database.add_bibtex(bytes)?;
+    Ok(database)
+}
+
+#[wasm_bindgen]
+impl CitationDatabase {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> CitationDatabase {
+        CitationDatabase::default()
+    }
+
+    /// Add the entries of a .bib file, replacing entries with the same
+    /// keys. Returns how many it had.
+    pub fn add_bibtex(&mut self, bytes: &[u8]) -> Result<usize, JsValue> {
+        let source = std::str::from_utf8(bytes).map_err(|e| JsValue::from_str(&format!("BibTeX is not valid UTF-8: {}", e)))?;
+        let entries = BibParser::new(source).entries().map_err(|e| JsValue::from_str(&e))?;
+        let count = entries.len();
+        for entry in entries {
+            self.entries.insert(entry.key.clone(), entry);
+        }
+        Ok(count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Entries for a citation picker, as JSON
+    /// `[{key, entry_type, author, title, year}]` sorted by key, in plain
+    /// text
+    pub fn entries(&self) -> String {
+        let summaries: Vec<EntrySummary> = self
+            .entries
+            .values()
+            .map(|entry| {
+                let (authors, others) = entry.names("author");
+                EntrySummary {
+                    key: &entry.key,
+                    entry_type: &entry.entry_type,
+                    author: Some(ieee_names(&authors, others)).filter(|a| !a.is_empty()),
+                    title: entry.text("title"),
+                    year: entry.text("year"),
+                }
+            })
+            .collect();
+        serde_json::to_string(&summaries).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// The markdown citing `key`: `[@key]` for "parenthetical" (or ""),
+    /// `@key` for "narrative" and `[-@key]` for "suppress_author" (the
+    /// year only)
+    pub fn insert_citation(&self, key: &str, style: &str) -> Result<String, JsValue> {
+        if !self.entries.contains_key(key) {
+            return Err(JsValue::from_str(&format!("Unknown citation key: {}", key)));
+        }
+        // Keys that wouldn't parse back whole need braces
+        let plain = key.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+            && key.chars().all(key_char)
+            && key.ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        let cite = if plain { format!("@{}", key) } else { format!("@{{{}}}", key) };
+        match style {
+            "" | "parenthetical" => Ok(format!("[{}]", cite)),
+            "narrative" => Ok(cite),
+            "suppress_author" => Ok(format!("[-{}]", cite)),
+            _ => Err(JsValue::from_str(&format!(
+                "Unknown citation form: {} (expected parenthetical, narrative or suppress_author)",
+                style
+            ))),
+        }
+    }
+
+    /// `content` with a `## References` section listing the entries it
+    /// cites, in `style` "apa" (alphabetical) or "ieee" (numbered in order
+    /// of first citation). An existing References section is replaced, and
+    /// removed when nothing is cited. Citations of keys not in the
+    /// database are left out; `@name` mentions of users are never taken
+    /// for citations unless a key matches.
+    pub fn generate_bibliography(&self, content: &str, style: &str) -> Result<String, JsValue> {
+        let style = style.to_ascii_lowercase();
+        if style != "apa" && style != "ieee" {
+            return Err(JsValue::from_str(&format!("Unknown citation style: {} (expected apa or ieee)", style)));
+        }
+        let section = references_section(content);
+        let body = match section {
+            Some((start, end)) => format!("{}{}", &content[..start], &content[end..]),
+            None => content.to_string(),
+        };
+        let mut cited: Vec<&BibEntry> = cited_keys(&body).iter().filter_map(|key| self.entries.get(key)).collect();
+
+        let references: Vec<String> = if style == "apa" {
+            let sort_key = |entry: &BibEntry| {
+                let (authors, _) = entry.names("author");
+                let lead = authors.first().map(Name::surname).or_else(|| entry.text("title")).unwrap_or_default();
+                (lead.to_lowercase(), entry.text("year").unwrap_or_default(), entry.text("title").unwrap_or_default())
+            };
+            cited.sort_by_cached_key(|entry| sort_key(entry));
+            cited.iter().map(|entry| format_apa(entry)).collect()
+        } else {
+            cited.iter().enumerate().map(|(i, entry)| format!("[{}] {}", i + 1, format_ieee(entry))).collect()
+        };
+
+        if section.is_none() && references.is_empty() {
+            return Ok(content.to_string());
+        }
+
+        // Put the section back where it was, or at the end
+        let (before, after) = match section {
+            Some((start, end)) => (&content[..start], &content[end..]),
+            None => (content, ""),
+        };
+        let mut parts: Vec<String> = Vec::new();
+        if !before.trim().is_empty() {
+            parts.push(before.trim_end().to_string());
+        }
+        if !references.is_empty() {
+            parts.push(format!("{}\n\n{}", REFERENCES_HEADING, references.join("\n\n")));
+        }
+        if !after.trim().is_empty() {
+            parts.push(after.trim().to_string());
+        }
+        let mut result = parts.join("\n\n");
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        Ok(result)
+    }
+
+    /// Serialize the database to CBOR bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore a database from `to_bytes` output
+    pub fn from_bytes(bytes: &[u8]) -> Result<CitationDatabase, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
@@ -0,0 +1,84 @@
+// Lamport clocks. Wall-clock timestamps from different machines can't be
+// compared, so every message also carries a Lamport time: a client's clock
+// ticks for each message sent and jumps past the time of each message
+// received, so a message always carries a later time than everything its
+// sender had seen. A time further ahead than MAX_LAMPORT_JUMP is refused:
+// one peer sending u64::MAX would otherwise pin every clock that saw it
+// there, and all later messages would tie.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::{data_text, data_u64};
+
+/// How far past the local clock a received Lamport time may be. No real
+/// document sees anywhere near this many messages.
+pub(crate) const MAX_LAMPORT_JUMP: u64 = 1 << 32;
+
+/// Whether a received Lamport time is close enough to `local` to witness
+pub(crate) fn plausible(local: u64, lamport: u64) -> bool {
+    lamport <= local.saturating_add(MAX_LAMPORT_JUMP)
+}
+
+/// A client's Lamport clock, kept by its PromiseGridHandler. A cell, as
+/// messages are created from `&self` methods too.
+#[derive(Default)]
+pub(crate) struct LamportClock(Cell<u64>);
+
+impl LamportClock {
+    /// The current Lamport time
+    pub(crate) fn time(&self) -> u64 {
+        self.0.get()
+    }
+
+    /// Advance past a time seen in a received message. Returns false,
+    /// leaving the clock alone, for a time implausibly far ahead.
+    pub(crate) fn witness(&self, lamport: u64) -> bool {
+        if !plausible(self.0.get(), lamport) {
+            return false;
+        }
+        self.0.set(self.0.get().max(lamport));
+        true
+    }
+
+    /// Give an outgoing message the next Lamport time. Messages that carry
+    /// their own (workspace and folder changes keep per-structure clocks)
+    /// are left as they are, and the clock moves past them. The clock stops
+    /// at `u64::MAX` rather than wrapping back before everything else.
+    pub(crate) fn stamp(&self, data: &mut HashMap<String, ciborium::Value>) {
+        if let Some(lamport) = data_u64(data, "lamport") {
+            self.witness(lamport);
+            return;
+        }
+        let lamport = self.0.get().saturating_add(1);
+        self.0.set(lamport);
+        data.insert("lamport".to_string(), ciborium::Value::from(lamport));
+    }
+}
+
+/// Causal order of two messages: by Lamport time, then by user id so
+/// concurrent messages settle the same way on every peer. Messages
+/// without a Lamport time sort first.
+pub(crate) fn causal_cmp(a: &HashMap<String, ciborium::Value>, b: &HashMap<String, ciborium::Value>) -> Ordering {
+    data_u64(a, "lamport")
+        .cmp(&data_u64(b, "lamport"))
+        .then_with(|| data_text(a, "user_id").cmp(&data_text(b, "user_id")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn far_future_time_is_refused() {
+        let clock = LamportClock::default();
+        assert!(clock.witness(5));
+        assert!(!clock.witness(u64::MAX));
+        assert_eq!(clock.time(), 5);
+        let mut data = HashMap::new();
+        clock.stamp(&mut data);
+        assert_eq!(data_u64(&data, "lamport"), Some(6));
+        assert!(clock.witness(6 + MAX_LAMPORT_JUMP));
+    }
+}
@@ -0,0 +1,333 @@
+// The CBOR codec every PromiseGrid message goes through: one encoder and
+// one decoder for the `grid`-tagged framing, and the canonical encoding
+// used for hashing and signing. Older peers sent messages without the tag,
+// or with the tag number in an eight-byte head; those legacy framings are
+// refused unless turned back on with `set_legacy_framing`.
+
+use wasm_bindgen::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use crate::{logger, PromiseGridMessage};
+
+/// CBOR tag wrapped around every PromiseGrid message ('grid')
+pub(crate) const GRID_TAG: u64 = 0x67726964;
+
+/// The head this build writes for the grid tag: major type 6 with a
+/// four-byte argument
+const GRID_TAG_HEAD: [u8; 5] = [0xda, b'g', b'r', b'i', b'd'];
+
+thread_local! {
+    static LEGACY_FRAMING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether legacy framings are accepted
+pub(crate) fn legacy_framing() -> bool {
+    LEGACY_FRAMING.with(Cell::get)
+}
+
+/// Accept (or, by default, refuse) messages in the legacy framings: no
+/// grid tag, or the grid tag written with an eight-byte head. Turn on only
+/// while peers on old builds remain.
+#[wasm_bindgen]
+pub fn set_legacy_framing(allowed: bool) {
+    LEGACY_FRAMING.with(|flag| flag.set(allowed));
+}
+
+/// How a message is framed
+pub(crate) enum Framing {
+    /// The grid tag as this build writes it; the message starts after
+    /// `header` bytes
+    Grid { header: usize },
+    /// Legacy: the grid tag in a longer head than it needs
+    LongGrid { header: usize },
+    /// Legacy: no tag at all
+    Untagged,
+    /// Some other tag
+    OtherTag(u64),
+}
+
+impl Framing {
+    /// The bytes of the message inside the framing, when it's one to
+    /// decode. Legacy framings are an error unless `set_legacy_framing`
+    /// allows them.
+    pub(crate) fn body<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8], String> {
+        match *self {
+            Framing::Grid { header } => Ok(&bytes[header..]),
+            Framing::LongGrid { header } if legacy_framing() => Ok(&bytes[header..]),
+            Framing::Untagged if legacy_framing() => Ok(bytes),
+            Framing::LongGrid { .. } => {
+                Err("Grid tag is written with an over-long head (legacy framing is turned off)".to_string())
+            }
+            Framing::Untagged => Err("Message has no grid tag (legacy framing is turned off)".to_string()),
+            Framing::OtherTag(tag) => Err(format!("Invalid tag: expected 0x{:x}, got 0x{:x}", GRID_TAG, tag)),
+        }
+    }
+}
+
+/// Read the framing from the head of a message
+pub(crate) fn framing(bytes: &[u8]) -> Result<Framing, String> {
+    if bytes.starts_with(&GRID_TAG_HEAD) {
+        return Ok(Framing::Grid { header: GRID_TAG_HEAD.len() });
+    }
+    let Some(&first) = bytes.first() else {
+        return Err("Message is empty".to_string());
+    };
+    if first >> 5 != 6 {
+        return Ok(Framing::Untagged);
+    }
+    let width = match first & 0x1f {
+        n @ 0..=23 => return Ok(Framing::OtherTag(n as u64)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return Err("Malformed CBOR tag".to_string()),
+    };
+    let number = bytes.get(1..1 + width).ok_or("Truncated CBOR tag")?;
+    match number.iter().fold(0u64, |n, &b| n << 8 | b as u64) {
+        GRID_TAG => Ok(Framing::LongGrid { header: 1 + width }),
+        tag => Ok(Framing::OtherTag(tag)),
+    }
+}
+
+/// Encode as CBOR in one pass
+pub(crate) fn to_cbor<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// A decoding error as a sentence rather than ciborium's debug form
+pub(crate) fn cbor_error(error: ciborium::de::Error<std::io::Error>) -> String {
+    use ciborium::de::Error;
+    match error {
+        Error::Io(_) => "unexpected end of input".to_string(),
+        Error::Syntax(offset) => format!("malformed CBOR at byte {}", offset),
+        Error::Semantic(Some(offset), problem) => format!("{} at byte {}", problem, offset),
+        Error::Semantic(None, problem) => problem,
+        Error::RecursionLimitExceeded => "nested too deeply".to_string(),
+    }
+}
+
+/// Decode the one CBOR item that makes up `bytes`, straight from the
+/// slice
+pub(crate) fn from_cbor<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    let mut rest = bytes;
+    let value = ciborium::from_reader(&mut rest).map_err(cbor_error)?;
+    if !rest.is_empty() {
+        return Err(format!("{} bytes left after the CBOR item", rest.len()));
+    }
+    Ok(value)
+}
+
+/// Sort the keys of every map in `value`, shortest encoding first and then
+/// bytewise
+fn canonicalize(value: &mut ciborium::Value) {
+    match value {
+        ciborium::Value::Map(entries) => {
+            for (_, item) in entries.iter_mut() {
+                canonicalize(item);
+            }
+            let mut keyed: Vec<(Vec<u8>, (ciborium::Value, ciborium::Value))> =
+                entries.drain(..).map(|(key, item)| (to_cbor(&key).unwrap_or_default(), (key, item))).collect();
+            keyed.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+            entries.extend(keyed.into_iter().map(|(_, entry)| entry));
+        }
+        ciborium::Value::Array(items) => items.iter_mut().for_each(canonicalize),
+        ciborium::Value::Tag(_, inner) => canonicalize(inner),
+        _ => {}
+    }
+}
+
+/// Encode as canonical CBOR (RFC 7049 section 3.9): every map has a
+/// definite length and its keys sorted shortest first, then bytewise, so
+/// the same value always encodes to the same bytes for hashing and
+/// signing, whatever order its `HashMap`s iterate in.
+pub(crate) fn to_canonical_cbor<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    let mut value = ciborium::Value::serialized(value).map_err(|e| e.to_string())?;
+    canonicalize(&mut value);
+    to_cbor(&value)
+}
+
+/// Serialize a payload data map with its keys in canonical order, so a
+/// message encodes to the same bytes in one pass without a canonicalizing
+/// round trip through `Value`
+pub(crate) fn sorted_data<S: serde::Serializer>(
+    data: &HashMap<String, ciborium::Value>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+    let mut keys: Vec<&String> = data.keys().collect();
+    keys.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    let mut map = serializer.serialize_map(Some(keys.len()))?;
+    for key in keys {
+        map.serialize_entry(key, &data[key])?;
+    }
+    map.end()
+}
+
+/// Encode a PromiseGrid message with the grid tag, in a single pass. The
+/// only encoder of the framing.
+pub(crate) fn encode_with_grid_tag(message: &PromiseGridMessage) -> Result<Vec<u8>, String> {
+    let mut bytes = GRID_TAG_HEAD.to_vec();
+    ciborium::into_writer(message, &mut bytes).map_err(|e| e.to_string())?;
+    logger::log_trace!("codec", "Encoded {} message, {} bytes", message.payload.message_type, bytes.len());
+    Ok(bytes)
+}
+
+/// Decode a PromiseGrid message, checking its framing, straight from the
+/// bytes. The only decoder of the framing.
+pub(crate) fn decode_with_grid_tag(cbor_bytes: &[u8]) -> Result<PromiseGridMessage, Box<dyn std::error::Error>> {
+    let framing = framing(cbor_bytes)?;
+    let body = framing.body(cbor_bytes).inspect_err(|e| logger::log_debug!("codec", "Refused message: {}", e))?;
+    if !matches!(framing, Framing::Grid { .. }) {
+        logger::log_debug!("codec", "Accepted a message in a legacy framing");
+    }
+    let message: PromiseGridMessage =
+        from_cbor(body).inspect_err(|e| logger::log_debug!("codec", "Undecodable message: {}", e))?;
+    logger::log_trace!("codec", "Decoded {} message, {} bytes", message.payload.message_type, cbor_bytes.len());
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_chain::to_hex;
+    use crate::{MessagePayload, PROTOCOL_HASH};
+    use ciborium::Value;
+
+    fn message(entries: &[(&str, Value)]) -> PromiseGridMessage {
+        PromiseGridMessage {
+            protocol_hash: PROTOCOL_HASH.to_string(),
+            payload: MessagePayload {
+                message_type: "document_edit".to_string(),
+                data: entries.iter().map(|(key, value)| (key.to_string(), value.clone())).collect(),
+            },
+        }
+    }
+
+    fn entries(reversed: bool) -> Vec<(&'static str, Value)> {
+        let mut nested = vec![
+            (Value::Text("b".into()), Value::Integer(1.into())),
+            (Value::Text("aa".into()), Value::Integer(2.into())),
+            (Value::Text("a".into()), Value::Array(vec![Value::Bool(true), Value::Null])),
+        ];
+        let mut entries = vec![
+            ("user_id", Value::Text("alice".into())),
+            ("seq", Value::Integer(7.into())),
+            ("document_id", Value::Text("doc".into())),
+            ("position", Value::Integer(3.into())),
+            ("attributes", Value::Map(nested.clone())),
+        ];
+        if reversed {
+            nested.reverse();
+            entries.reverse();
+            entries[0].1 = Value::Map(nested);
+        }
+        entries
+    }
+
+    #[test]
+    fn canonical_cbor_ignores_map_key_order() {
+        let forward = to_canonical_cbor(&message(&entries(false))).unwrap();
+        let reversed = to_canonical_cbor(&message(&entries(true))).unwrap();
+        assert_eq!(forward, reversed);
+    }
+
+    #[test]
+    fn canonical_cbor_matches_golden_vector() {
+        let bytes = to_canonical_cbor(&message(&entries(false))).unwrap();
+        // Keys shortest first, then bytewise: payload before protocol_hash,
+        // seq before user_id, a before b before aa
+        let expected = concat!(
+            "a2677061796c6f6164a26464617461a5637365710767757365725f696465616c696365",
+            "68706f736974696f6e036a61747472696275746573a3616182f5f6616201626161026b",
+            "646f63756d656e745f696463646f636c6d6573736167655f747970656d646f63756d65",
+            "6e745f656469746d70726f746f636f6c5f6861736877516d50726f6d69736547726964",
+            "50726f746f636f6c5631",
+        );
+        assert_eq!(to_hex(&bytes), expected);
+    }
+
+    fn sample(message_type: &str) -> PromiseGridMessage {
+        let mut message = message(&[
+            ("document_id", Value::Text("doc".into())),
+            ("user_id", Value::Text("日本語".into())),
+            ("seq", Value::Integer(u64::MAX.into())),
+            ("offset", Value::Integer((-3).into())),
+            ("timestamp", Value::Float(1_700_000_000_000.5)),
+            ("signature", Value::Bytes(vec![0, 0xff, 0x67])),
+            ("complete", Value::Bool(false)),
+            ("operations", Value::Array(vec![Value::Text("é*é*é".into()), Value::Null])),
+            ("attributes", Value::Map(vec![(Value::Text("bold".into()), Value::Bool(true))])),
+        ]);
+        message.payload.message_type = message_type.to_string();
+        message
+    }
+
+    fn decode_error(bytes: &[u8]) -> String {
+        decode_with_grid_tag(bytes).map(|_| ()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn every_message_type_round_trips() {
+        for message_type in crate::MESSAGE_TYPES {
+            let message = sample(message_type);
+            let bytes = encode_with_grid_tag(&message).unwrap();
+            assert!(bytes.starts_with(&GRID_TAG_HEAD));
+            let decoded = decode_with_grid_tag(&bytes).unwrap();
+            assert_eq!(decoded.protocol_hash, message.protocol_hash);
+            assert_eq!(decoded.payload.message_type, *message_type);
+            assert_eq!(decoded.payload.data, message.payload.data, "{}", message_type);
+            assert_eq!(encode_with_grid_tag(&decoded).unwrap(), bytes, "{}", message_type);
+        }
+    }
+
+    #[test]
+    fn framing_errors() {
+        let body = to_cbor(&sample("ack")).unwrap();
+        let mut long_head = vec![0xdb, 0, 0, 0, 0];
+        long_head.extend_from_slice(b"grid");
+        long_head.extend_from_slice(&body);
+
+        assert_eq!(decode_error(&[]), "Message is empty");
+        assert!(decode_error(&body).contains("no grid tag"));
+        assert!(decode_error(&long_head).contains("over-long head"));
+        assert_eq!(decode_error(&[0xc1, 0x00]), "Invalid tag: expected 0x67726964, got 0x1");
+        assert_eq!(decode_error(&[0xd9, 0x01, 0x00, 0x00]), "Invalid tag: expected 0x67726964, got 0x100");
+        assert_eq!(decode_error(&[0xda, b'g', b'r']), "Truncated CBOR tag");
+        assert_eq!(decode_error(&[0xdc, 0x00]), "Malformed CBOR tag");
+
+        set_legacy_framing(true);
+        let untagged = decode_with_grid_tag(&body).map(|m| m.payload.message_type);
+        let long = decode_with_grid_tag(&long_head).map(|m| m.payload.message_type);
+        set_legacy_framing(false);
+        assert_eq!(untagged.unwrap(), "ack");
+        assert_eq!(long.unwrap(), "ack");
+    }
+
+    #[test]
+    fn body_errors() {
+        let bytes = encode_with_grid_tag(&sample("ack")).unwrap();
+        assert_eq!(decode_error(&bytes[..bytes.len() - 1]), "unexpected end of input");
+
+        let mut trailing = bytes.clone();
+        trailing.push(0x00);
+        assert_eq!(decode_error(&trailing), "1 bytes left after the CBOR item");
+
+        let mut not_a_message = GRID_TAG_HEAD.to_vec();
+        not_a_message.push(0x07);
+        assert!(decode_error(&not_a_message).contains("invalid type"));
+
+        let mut missing_payload = GRID_TAG_HEAD.to_vec();
+        missing_payload.extend(to_cbor(&Value::Map(vec![(Value::Text("protocol_hash".into()), Value::Text("x".into()))])).unwrap());
+        assert!(decode_error(&missing_payload).contains("payload"));
+
+        let mut malformed = GRID_TAG_HEAD.to_vec();
+        malformed.push(0x1c);
+        assert!(decode_error(&malformed).starts_with("malformed CBOR at byte"));
+    }
+}
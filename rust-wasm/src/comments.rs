@@ -0,0 +1,514 @@
+// Comments on ranges of a document. Each comment keeps the text it was on
+// and a little context either side, and its range is moved along by the
+// edits that arrive. When edits can't be followed (a checkpoint replaced the
+// text, or the commented text was deleted) the range is found again from
+// the quote and its context, so a comment survives the document changing
+// under it.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::edit_log::{floor_boundary, message_edits, CHECKPOINT};
+use crate::payload::{decode_payload, payload_data, CommentChange, Payload};
+use crate::{decode_with_grid_tag, encode_promisegrid_payload, hash_chain, share};
+
+pub(crate) const COMMENT_MESSAGE_TYPES: &[&str] = &["comment_add", "comment_reply", "comment_resolve", "comment_delete"];
+
+/// Bytes of text kept either side of a comment's range to find it again
+const CONTEXT_BYTES: usize = 32;
+
+/// Where a comment is
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Anchor {
+    start: usize,
+    end: usize,
+    /// The commented text when last seen
+    quote: String,
+    /// Text just before and after the range when last seen
+    prefix: String,
+    suffix: String,
+    /// Edits inside the range changed the text since `quote` was taken;
+    /// the range itself is still right
+    #[serde(default)]
+    edited: bool,
+    /// The range couldn't be followed through the edits and has to be found
+    /// again
+    #[serde(default)]
+    stale: bool,
+    /// The range couldn't be found again
+    #[serde(default)]
+    detached: bool,
+}
+
+/// Bytes `a` and `b` share at their ends
+fn common_suffix(a: &str, b: &str) -> usize {
+    a.bytes().rev().zip(b.bytes().rev()).take_while(|(x, y)| x == y).count()
+}
+
+/// Bytes `a` and `b` share at their starts
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count()
+}
+
+impl Anchor {
+    /// Anchor to `start..end` of `text`, snapped to character boundaries
+    fn capture(text: &str, start: usize, end: usize) -> Anchor {
+        let start = floor_boundary(text, start);
+        let end = floor_boundary(text, end).max(start);
+        let before = floor_boundary(text, start.saturating_sub(CONTEXT_BYTES));
+        let after = floor_boundary(text, end + CONTEXT_BYTES);
+        Anchor {
+            start,
+            end,
+            quote: text[start..end].to_string(),
+            prefix: text[before..start].to_string(),
+            suffix: text[end..after].to_string(),
+            edited: false,
+            stale: false,
+            detached: false,
+        }
+    }
+
+    /// Follow an edit replacing `removed` bytes at `position` with
+    /// `inserted` bytes. Text typed at either edge of the range stays
+    /// outside it; text replacing part of it joins it.
+    fn shift(&mut self, position: usize, removed: usize, inserted: usize) {
+        let edit_end = position + removed;
+        let moved = |pos: usize| pos - removed + inserted;
+        let was_empty = self.start == self.end;
+        if position < self.end && edit_end > self.start {
+            self.edited = true;
+        }
+        let start = if self.start < position {
+            self.start
+        } else if self.start >= edit_end {
+            moved(self.start)
+        } else {
+            position
+        };
+        let end = if was_empty {
+            start
+        } else if self.end <= position {
+            self.end
+        } else if self.end >= edit_end {
+            moved(self.end)
+        } else {
+            position + inserted
+        };
+        if start >= end && !was_empty {
+            // Everything commented on is gone
+            self.stale = true;
+        }
+        self.start = start;
+        self.end = end.max(start);
+    }
+
+    /// Bring the anchor up to date with `text`, finding it again if needed
+    fn reanchor(&mut self, text: &str) {
+        let in_range = self.end <= text.len() && text.is_char_boundary(self.start) && text.is_char_boundary(self.end);
+        let quoted = in_range && text[self.start..self.end] == self.quote;
+        if !self.stale && (quoted || (self.edited && in_range)) {
+            *self = Anchor::capture(text, self.start, self.end);
+            return;
+        }
+        match self.locate(text) {
+            Some((start, end)) => *self = Anchor::capture(text, start, end),
+            None => {
+                self.start = floor_boundary(text, self.start);
+                self.end = floor_boundary(text, self.end).max(self.start);
+                self.stale = true;
+                self.detached = true;
+            }
+        }
+    }
+
+    /// Where the anchored text is in `text`: the occurrence of the quote
+    /// whose surroundings best match the context, nearest the old place on
+    /// a tie; failing that, the stretch between the prefix and the suffix
+    fn locate(&self, text: &str) -> Option<(usize, usize)> {
+        let fit = |start: usize, end: usize| common_suffix(&text[..start], &self.prefix) + common_prefix(&text[end..], &self.suffix);
+        if !self.quote.is_empty() {
+            let best = text
+                .match_indices(self.quote.as_str())
+                .map(|(start, _)| (start, start + self.quote.len()))
+                .max_by_key(|&(start, end)| (fit(start, end), std::cmp::Reverse(start.abs_diff(self.start))));
+            if best.is_some() {
+                return best;
+            }
+        }
+        if self.prefix.is_empty() && self.suffix.is_empty() {
+            return None;
+        }
+        // The quote itself was rewritten; allow it to have grown a little
+        let reach = self.quote.len() * 2 + CONTEXT_BYTES;
+        let starts: Vec<usize> = if self.prefix.is_empty() {
+            vec![0]
+        } else {
+            text.match_indices(self.prefix.as_str()).map(|(at, _)| at + self.prefix.len()).collect()
+        };
+        starts
+            .into_iter()
+            .filter_map(|start| {
+                let end = if self.suffix.is_empty() {
+                    Some(text.len()).filter(|&end| end - start <= reach)?
+                } else {
+                    start + text[start..].find(self.suffix.as_str()).filter(|&gap| gap <= reach)?
+                };
+                Some((start, end))
+            })
+            .min_by_key(|&(start, _)| start.abs_diff(self.start))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Reply {
+    reply_id: String,
+    user_id: String,
+    body: String,
+    created_at: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Comment {
+    comment_id: String,
+    user_id: String,
+    body: String,
+    created_at: f64,
+    anchor: Anchor,
+    resolved: bool,
+    resolved_by: Option<String>,
+    /// When `resolved` was last set, so the newest resolve or reopen wins
+    resolved_at: f64,
+    replies: Vec<Reply>,
+}
+
+/// A comment as `positions` reports it, for gutter markers
+#[derive(Serialize)]
+struct Position<'a> {
+    comment_id: &'a str,
+    start: usize,
+    end: usize,
+    resolved: bool,
+    detached: bool,
+    replies: usize,
+}
+
+/// A comment thread as `comments` reports it
+#[derive(Serialize)]
+struct Thread<'a> {
+    comment_id: &'a str,
+    user_id: &'a str,
+    body: &'a str,
+    created_at: f64,
+    start: usize,
+    end: usize,
+    quote: &'a str,
+    resolved: bool,
+    resolved_by: Option<&'a str>,
+    detached: bool,
+    replies: &'a [Reply],
+}
+
+/// The comments on one document, kept in step with the other peers by
+/// `comment_add`, `comment_reply`, `comment_resolve` and `comment_delete`
+/// messages. Feed it every message for the document, edits included, so
+/// the ranges follow the text.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommentStore {
+    document_id: String,
+    comments: BTreeMap<String, Comment>,
+    /// Ids of deleted comments and replies, so a late or repeated message
+    /// can't bring them back
+    deleted: BTreeSet<String>,
+}
+
+fn random_id() -> Result<String, JsValue> {
+    let mut id = [0u8; 16];
+    share::random_bytes(&mut id)?;
+    Ok(hash_chain::to_hex(&id))
+}
+
+#[wasm_bindgen]
+impl CommentStore {
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str) -> CommentStore {
+        CommentStore { document_id: document_id.to_string(), comments: BTreeMap::new(), deleted: BTreeSet::new() }
+    }
+
+    /// Comment on `start..end` (bytes) of `text`, the document as the user
+    /// sees it; returns the `comment_add` message to send
+    pub fn add_comment(&mut self, user_id: &str, body: &str, start: usize, end: usize, text: &str, now: f64) -> Result<Vec<u8>, JsValue> {
+        if start > end || end > text.len() {
+            return Err(JsValue::from_str(&format!("Invalid comment range {}..{} for {} bytes of text", start, end, text.len())));
+        }
+        let anchor = Anchor::capture(text, start, end);
+        let change = CommentChange {
+            document_id: self.document_id.clone(),
+            user_id: user_id.to_string(),
+            comment_id: random_id()?,
+            reply_id: None,
+            body: Some(body.to_string()),
+            start: Some(anchor.start as u64),
+            end: Some(anchor.end as u64),
+            quote: Some(anchor.quote),
+            prefix: Some(anchor.prefix),
+            suffix: Some(anchor.suffix),
+            resolved: None,
+            timestamp: now,
+        };
+        self.send("comment_add", change)
+    }
+
+    /// Reply to a comment; returns the `comment_reply` message to send
+    pub fn reply(&mut self, comment_id: &str, user_id: &str, body: &str, now: f64) -> Result<Vec<u8>, JsValue> {
+        let change = CommentChange {
+            reply_id: Some(random_id()?),
+            body: Some(body.to_string()),
+            ..self.change(comment_id, user_id, now)
+        };
+        self.send("comment_reply", change)
+    }
+
+    /// Resolve a comment, or reopen it with `resolved` false; returns the
+    /// `comment_resolve` message to send
+    pub fn resolve(&mut self, comment_id: &str, user_id: &str, resolved: bool, now: f64) -> Result<Vec<u8>, JsValue> {
+        let change = CommentChange { resolved: Some(resolved), ..self.change(comment_id, user_id, now) };
+        self.send("comment_resolve", change)
+    }
+
+    /// Delete a comment and its replies, or just the reply `reply_id`. Only
+    /// the author may. Returns the `comment_delete` message to send.
+    pub fn delete(&mut self, comment_id: &str, reply_id: Option<String>, user_id: &str, now: f64) -> Result<Vec<u8>, JsValue> {
+        let change = CommentChange { reply_id, ..self.change(comment_id, user_id, now) };
+        self.send("comment_delete", change)
+    }
+
+    /// Apply a message from the document's stream: comment messages update
+    /// the threads and edits move their ranges. Returns whether anything
+    /// changed; a message for another document, or a repeat, changes
+    /// nothing.
+    pub fn apply_message(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let message_type = message.payload.message_type.as_str();
+        if COMMENT_MESSAGE_TYPES.contains(&message_type) {
+            let (_, Payload::CommentChange(change)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+                return Ok(false);
+            };
+            return self.apply_change(message_type, change).map_err(|e| JsValue::from_str(&e));
+        }
+        if crate::data_text(&message.payload.data, "document_id") != Some(self.document_id.as_str()) {
+            return Ok(false);
+        }
+        let mut changed = false;
+        for edit in message_edits(&message) {
+            for comment in self.comments.values_mut() {
+                let anchor = &mut comment.anchor;
+                if edit.edit_type == CHECKPOINT {
+                    anchor.stale = true;
+                } else {
+                    let (removed, inserted) = edit.extent();
+                    anchor.shift(edit.position, removed, inserted);
+                }
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Where each comment is in `text`, the current document, as JSON
+    /// `[{comment_id, start, end, resolved, detached, replies}]` in
+    /// document order, for gutter markers. Comments whose text can't be
+    /// found any more are `detached` and keep their last position.
+    pub fn positions(&mut self, text: &str) -> String {
+        let comments = self.reanchored(text);
+        let positions: Vec<Position> = comments
+            .iter()
+            .map(|comment| Position {
+                comment_id: &comment.comment_id,
+                start: comment.anchor.start,
+                end: comment.anchor.end,
+                resolved: comment.resolved,
+                detached: comment.anchor.detached,
+                replies: comment.replies.len(),
+            })
+            .collect();
+        serde_json::to_string(&positions).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// Every thread against `text`, as JSON `[{comment_id, user_id, body,
+    /// created_at, start, end, quote, resolved, resolved_by, detached,
+    /// replies: [{reply_id, user_id, body, created_at}]}]` in document
+    /// order
+    pub fn comments(&mut self, text: &str) -> String {
+        let comments = self.reanchored(text);
+        let threads: Vec<Thread> = comments
+            .iter()
+            .map(|comment| Thread {
+                comment_id: &comment.comment_id,
+                user_id: &comment.user_id,
+                body: &comment.body,
+                created_at: comment.created_at,
+                start: comment.anchor.start,
+                end: comment.anchor.end,
+                quote: &comment.anchor.quote,
+                resolved: comment.resolved,
+                resolved_by: comment.resolved_by.as_deref(),
+                detached: comment.anchor.detached,
+                replies: &comment.replies,
+            })
+            .collect();
+        serde_json::to_string(&threads).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// Comments in the store, resolved ones included
+    pub fn len(&self) -> usize {
+        self.comments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+    }
+
+    /// Serialize the store to CBOR bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore a store from `to_bytes` output
+    pub fn from_bytes(bytes: &[u8]) -> Result<CommentStore, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
+
+impl CommentStore {
+    /// A change to an existing comment, with nothing set yet
+    fn change(&self, comment_id: &str, user_id: &str, now: f64) -> CommentChange {
+        CommentChange {
+            document_id: self.document_id.clone(),
+            user_id: user_id.to_string(),
+            comment_id: comment_id.to_string(),
+            reply_id: None,
+            body: None,
+            start: None,
+            end: None,
+            quote: None,
+            prefix: None,
+            suffix: None,
+            resolved: None,
+            timestamp: now,
+        }
+    }
+
+    /// Apply a change made here and encode it to send
+    fn send(&mut self, message_type: &str, change: CommentChange) -> Result<Vec<u8>, JsValue> {
+        if message_type != "comment_add" && !self.comments.contains_key(&change.comment_id) {
+            return Err(JsValue::from_str(&format!("No comment {} on document {}", change.comment_id, self.document_id)));
+        }
+        let data = payload_data(&change);
+        self.apply_change(message_type, change).map_err(|e| JsValue::from_str(&e))?;
+        Ok(encode_promisegrid_payload(message_type, data))
+    }
+
+    /// Comments in document order, re-anchored against `text`
+    fn reanchored(&mut self, text: &str) -> Vec<&Comment> {
+        for comment in self.comments.values_mut() {
+            comment.anchor.reanchor(text);
+        }
+        let mut comments: Vec<&Comment> = self.comments.values().collect();
+        comments.sort_by_key(|comment| (comment.anchor.start, comment.anchor.end));
+        comments
+    }
+
+    fn apply_change(&mut self, message_type: &str, change: CommentChange) -> Result<bool, String> {
+        if change.document_id != self.document_id || self.deleted.contains(&change.comment_id) {
+            return Ok(false);
+        }
+        if message_type == "comment_add" {
+            if self.comments.contains_key(&change.comment_id) {
+                return Ok(false);
+            }
+            let (Some(body), Some(start), Some(end), Some(quote)) = (change.body, change.start, change.end, change.quote)
+            else {
+                return Err("comment_add message needs body, start, end and quote".to_string());
+            };
+            let anchor = Anchor {
+                start: start as usize,
+                end: (end as usize).max(start as usize),
+                quote,
+                prefix: change.prefix.unwrap_or_default(),
+                suffix: change.suffix.unwrap_or_default(),
+                edited: false,
+                stale: false,
+                detached: false,
+            };
+            let comment = Comment {
+                comment_id: change.comment_id.clone(),
+                user_id: change.user_id,
+                body,
+                created_at: change.timestamp,
+                anchor,
+                resolved: false,
+                resolved_by: None,
+                resolved_at: change.timestamp,
+                replies: Vec::new(),
+            };
+            self.comments.insert(change.comment_id, comment);
+            return Ok(true);
+        }
+
+        let Some(comment) = self.comments.get_mut(&change.comment_id) else {
+            return Ok(false);
+        };
+        match message_type {
+            "comment_reply" => {
+                let (Some(reply_id), Some(body)) = (change.reply_id, change.body) else {
+                    return Err("comment_reply message needs reply_id and body".to_string());
+                };
+                if self.deleted.contains(&reply_id) || comment.replies.iter().any(|reply| reply.reply_id == reply_id) {
+                    return Ok(false);
+                }
+                comment.replies.push(Reply { reply_id, user_id: change.user_id, body, created_at: change.timestamp });
+                comment.replies.sort_by(|a, b| a.created_at.total_cmp(&b.created_at).then_with(|| a.reply_id.cmp(&b.reply_id)));
+                Ok(true)
+            }
+            "comment_resolve" => {
+                let resolved = change.resolved.ok_or("comment_resolve message needs resolved")?;
+                // Newest wins; on a tie, resolving does
+                let newer = change.timestamp > comment.resolved_at
+                    || (change.timestamp == comment.resolved_at && resolved && !comment.resolved);
+                if !newer {
+                    return Ok(false);
+                }
+                comment.resolved = resolved;
+                comment.resolved_by = resolved.then_some(change.user_id);
+                comment.resolved_at = change.timestamp;
+                Ok(true)
+            }
+            "comment_delete" => match change.reply_id {
+                Some(reply_id) => {
+                    let Some(index) = comment.replies.iter().position(|reply| reply.reply_id == reply_id) else {
+                        return Ok(false);
+                    };
+                    if comment.replies[index].user_id != change.user_id {
+                        return Err(format!("Only the author may delete reply {}", reply_id));
+                    }
+                    comment.replies.remove(index);
+                    self.deleted.insert(reply_id);
+                    Ok(true)
+                }
+                None => {
+                    if comment.user_id != change.user_id {
+                        return Err(format!("Only the author may delete comment {}", change.comment_id));
+                    }
+                    self.comments.remove(&change.comment_id);
+                    self.deleted.insert(change.comment_id);
+                    Ok(true)
+                }
+            },
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Edit log compaction: fold the old edits of a long-lived document into a
+// single checkpoint holding the text they produced, keeping recent edits
+// as they were sent so they can still be replayed, blamed and verified.
+
+use wasm_bindgen::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::edit_log::{message_edits, split_messages, CHECKPOINT};
+use crate::metadata::DocumentMetadata;
+use crate::{data_text, data_u64, encode_local_payload};
+
+/// Edits kept when no retention limit is given
+const DEFAULT_KEEP_COUNT: usize = 1000;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Retention {
+    /// Keep at most this many of the latest edits
+    count: Option<usize>,
+    /// Keep edits newer than this many ms before `now`
+    age_ms: Option<f64>,
+    /// Reference time for `age_ms`; defaults to the latest edit's time
+    now: Option<f64>,
+    /// Keep at most this many bytes of the latest edit messages
+    max_bytes: Option<usize>,
+}
+
+/// Compact an edit log (concatenated messages, as sent): edits older than
+/// the retention window are replaced by one `document_checkpoint` message
+/// with the text they produced, followed by the kept messages byte for
+/// byte. `retention` is JSON `{count, age_ms, now, max_bytes}`; the kept
+/// edits are the latest ones within every limit given, or the last 1000
+/// when none is. A checkpoint already at the start of the log is folded
+/// into the new one. The log is returned unchanged when nothing is old
+/// enough to compact. Compaction by `user_id` at `now` is refused, and
+/// recorded in the audit log of the document's `metadata`, while the
+/// document is frozen.
+#[wasm_bindgen]
+pub fn compact_history(
+    edit_log: &[u8],
+    retention: &str,
+    metadata: &mut DocumentMetadata,
+    user_id: &str,
+    now: f64,
+) -> Result<Vec<u8>, JsValue> {
+    compact(edit_log, retention, metadata, user_id, now).map_err(|e| JsValue::from_str(&e))
+}
+
+fn compact(edit_log: &[u8], retention: &str, metadata: &mut DocumentMetadata, user_id: &str, now: f64) -> Result<Vec<u8>, String> {
+    metadata.prune_allowed(user_id, now)?;
+    let retention: Retention = if retention.trim().is_empty() {
+        Retention::default()
+    } else {
+        serde_json::from_str(retention).map_err(|e| format!("Invalid retention options: {}", e))?
+    };
+    let messages = split_messages(edit_log)?;
+    let edits: Vec<_> = messages.iter().map(|(_, message)| message_edits(message)).collect();
+
+    let reference = retention.now.unwrap_or_else(|| {
+        edits.iter().flatten().map(|e| e.timestamp).fold(f64::NEG_INFINITY, f64::max)
+    });
+    let unlimited = retention.count.is_none() && retention.age_ms.is_none() && retention.max_bytes.is_none();
+    let max_count = if unlimited { Some(DEFAULT_KEEP_COUNT) } else { retention.count };
+
+    // Walk back from the end while the next older edit still fits
+    let mut cut = messages.len();
+    let (mut kept, mut bytes) = (0, 0);
+    while cut > 0 {
+        let (message_bytes, _) = &messages[cut - 1];
+        if let Some(edit) = edits[cut - 1].last() {
+            let fits = max_count.is_none_or(|max| kept < max)
+                && retention.age_ms.is_none_or(|age| edit.timestamp >= reference - age)
+                && retention.max_bytes.is_none_or(|max| bytes + message_bytes.len() <= max);
+            if !fits {
+                break;
+            }
+            kept += edits[cut - 1].len();
+        }
+        bytes += message_bytes.len();
+        cut -= 1;
+    }
+    let compacted: Vec<_> = edits[..cut].iter().flatten().collect();
+    if compacted.len() < 2 && compacted.iter().all(|e| e.edit_type == CHECKPOINT) {
+        return Ok(edit_log.to_vec());
+    }
+
+    let mut text = String::new();
+    for edit in &compacted {
+        edit.apply(&mut text);
+    }
+    // Edits the checkpoint stands for, counting earlier checkpoints'
+    let replaced: u64 = messages[..cut]
+        .iter()
+        .filter_map(|(_, message)| match message.payload.message_type.as_str() {
+            "document_edit" => Some(1),
+            "edit_batch" | "batch" => Some(message_edits(message).len() as u64),
+            "document_checkpoint" => data_u64(&message.payload.data, "edits"),
+            _ => None,
+        })
+        .sum();
+    let last = compacted[compacted.len() - 1];
+    let document_id = messages[..cut]
+        .iter()
+        .rev()
+        .find_map(|(_, message)| data_text(&message.payload.data, "document_id"))
+        .unwrap_or("");
+
+    let mut data = HashMap::new();
+    data.insert("document_id".to_string(), ciborium::Value::Text(document_id.to_string()));
+    data.insert("content".to_string(), ciborium::Value::Text(text));
+    data.insert("timestamp".to_string(), ciborium::Value::Float(last.timestamp));
+    data.insert("edits".to_string(), ciborium::Value::from(replaced));
+    let mut log = encode_local_payload("document_checkpoint", data);
+    for (message_bytes, _) in &messages[cut..] {
+        log.extend_from_slice(message_bytes);
+    }
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::freeze_data;
+    use crate::{encode_with_grid_tag, signing, MessagePayload, PromiseGridMessage, PROTOCOL_HASH};
+
+    fn freeze_message(frozen: bool, secret_key: &[u8], timestamp: f64) -> Vec<u8> {
+        let data = freeze_data("doc", frozen, "litigation", "owner", secret_key, timestamp).unwrap();
+        let payload = MessagePayload { message_type: "document_freeze".to_string(), data };
+        encode_with_grid_tag(&PromiseGridMessage { protocol_hash: PROTOCOL_HASH.to_string(), payload }).unwrap()
+    }
+
+    #[test]
+    fn frozen_document_is_not_compacted() {
+        let secret_key = [5u8; 32];
+        let mut metadata = DocumentMetadata::new("doc");
+        metadata.add_owner("owner", &signing::signing_public_key(&secret_key).unwrap()).unwrap();
+        let freeze = freeze_message(true, &secret_key, 1.0);
+        metadata.apply_control_message(&freeze, 1.0).unwrap();
+
+        let refused = compact(&[], "", &mut metadata, "editor", 2.0).unwrap_err();
+        assert!(refused.contains("frozen"), "{}", refused);
+        assert!(metadata.audit_log().contains("\"prune_history\""));
+
+        let unfreeze = freeze_message(false, &secret_key, 3.0);
+        metadata.apply_control_message(&unfreeze, 3.0).unwrap();
+        assert!(compact(&[], "", &mut metadata, "editor", 4.0).is_ok());
+    }
+}
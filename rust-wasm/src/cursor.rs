@@ -0,0 +1,111 @@
+// Live carets: `cursor_update` messages carrying where a collaborator's
+// cursor and selection are, and a throttle so a burst of cursor moves
+// goes out as a few messages rather than one per keystroke.
+
+use wasm_bindgen::prelude::*;
+
+use crate::payload::{decode_payload, payload_data, CursorUpdate, Payload};
+use crate::{decode_with_grid_tag, encode_promisegrid_payload};
+
+/// Minimum gap between cursor messages by default, in ms
+const DEFAULT_CURSOR_INTERVAL_MS: f64 = 50.0;
+
+/// Create a `cursor_update` message: the caret at `offset` and the
+/// selection from `selection_start` to `selection_end` (equal when nothing
+/// is selected), all byte offsets
+#[wasm_bindgen]
+pub fn create_cursor_message(
+    document_id: &str,
+    user_id: &str,
+    offset: u32,
+    selection_start: u32,
+    selection_end: u32,
+) -> Vec<u8> {
+    let position = CursorUpdate {
+        document_id: document_id.to_string(),
+        user_id: user_id.to_string(),
+        offset: offset as u64,
+        selection_start: selection_start.min(selection_end) as u64,
+        selection_end: selection_start.max(selection_end) as u64,
+        timestamp: js_sys::Date::now(),
+    };
+    encode_promisegrid_payload("cursor_update", payload_data(&position))
+}
+
+/// Read a `cursor_update` message as JSON `{document_id, user_id, offset,
+/// selection_start, selection_end, timestamp}` for rendering the caret
+#[wasm_bindgen]
+pub fn parse_cursor_message(cbor_bytes: &[u8]) -> Result<String, JsValue> {
+    let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    if message.payload.message_type != "cursor_update" {
+        return Err(JsValue::from_str(&format!(
+            "Expected a cursor_update message, got {}",
+            message.payload.message_type
+        )));
+    }
+    let (_, Payload::CursorUpdate(position)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+        return Err(JsValue::from_str("Expected a cursor_update payload"));
+    };
+    Ok(serde_json::to_string(&position).unwrap_or_else(|_| "{}".into()))
+}
+
+/// Rate-limits a user's cursor messages. `update` sends at once when the
+/// last message is old enough and otherwise holds the position; `poll`
+/// sends the held position once the interval has passed, so the final
+/// resting place of the caret always goes out.
+#[wasm_bindgen]
+pub struct CursorThrottle {
+    document_id: String,
+    user_id: String,
+    interval_ms: f64,
+    last_sent: Option<f64>,
+    /// Last position sent, to skip repeats
+    sent: Option<(u32, u32, u32)>,
+    held: Option<(u32, u32, u32)>,
+}
+
+#[wasm_bindgen]
+impl CursorThrottle {
+    /// `interval_ms` of 0 uses the default of 50 ms
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str, user_id: &str, interval_ms: f64) -> CursorThrottle {
+        CursorThrottle {
+            document_id: document_id.to_string(),
+            user_id: user_id.to_string(),
+            interval_ms: if interval_ms > 0.0 { interval_ms } else { DEFAULT_CURSOR_INTERVAL_MS },
+            last_sent: None,
+            sent: None,
+            held: None,
+        }
+    }
+
+    /// The caret moved. Returns a message to send now, or `undefined` when
+    /// it is held back (or unchanged).
+    pub fn update(&mut self, offset: u32, selection_start: u32, selection_end: u32, now: f64) -> Option<Vec<u8>> {
+        let position = (offset, selection_start, selection_end);
+        if self.sent == Some(position) {
+            self.held = None;
+            return None;
+        }
+        self.held = Some(position);
+        self.poll(now)
+    }
+
+    /// Send the held position if the interval has passed since the last
+    /// message
+    pub fn poll(&mut self, now: f64) -> Option<Vec<u8>> {
+        let (offset, selection_start, selection_end) = self.held?;
+        if self.last_sent.is_some_and(|last| now - last < self.interval_ms) {
+            return None;
+        }
+        self.held = None;
+        self.sent = Some((offset, selection_start, selection_end));
+        self.last_sent = Some(now);
+        Some(create_cursor_message(&self.document_id, &self.user_id, offset, selection_start, selection_end))
+    }
+
+    /// Whether a position is waiting for `poll`
+    pub fn has_pending(&self) -> bool {
+        self.held.is_some()
+    }
+}
@@ -0,0 +1,105 @@
+// Inbound deduplication. A message can arrive more than once: retried
+// after a lost ack, relayed by several peers, or resent after a
+// reconnect. Each is keyed by a stable id that every copy shares, and an
+// InboundDeduper remembers the most recent ids so each message is applied
+// exactly once, without growing without bound.
+
+use wasm_bindgen::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::hash_chain::to_hex;
+use crate::{data_text, data_u64, decode_with_grid_tag, to_canonical_cbor, PromiseGridMessage};
+
+/// Ids remembered by default
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// The id every copy of a message shares: sender, session and sequence
+/// number when it has them, or else the SHA-256 of its canonical payload
+pub(crate) fn stable_id(message: &PromiseGridMessage) -> String {
+    let data = &message.payload.data;
+    if let (Some(user_id), Some(session), Some(seq)) =
+        (data_text(data, "user_id"), data_text(data, "session"), data_u64(data, "seq"))
+    {
+        return format!("{}/{}/{}", user_id, session, seq);
+    }
+    to_hex(&Sha256::digest(to_canonical_cbor(&message.payload).unwrap_or_default()))
+}
+
+/// The stable id of a message: `user_id/session/seq` for sequenced
+/// messages, or the hex SHA-256 of its canonical payload. Copies of a
+/// message (retries, relays, resends) share it.
+#[wasm_bindgen]
+pub fn message_key(cbor_bytes: &[u8]) -> Result<String, JsValue> {
+    let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    Ok(stable_id(&message))
+}
+
+/// Remembers the ids of the most recently seen messages, forgetting the
+/// least recently seen past its capacity. Check every incoming message
+/// before applying it; check a `batch` before reading its operations,
+/// which have no ids of their own.
+#[wasm_bindgen]
+pub struct InboundDeduper {
+    capacity: usize,
+    /// Id -> when it was last seen
+    seen: HashMap<String, u64>,
+    /// When -> id, oldest first, for eviction
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+#[wasm_bindgen]
+impl InboundDeduper {
+    /// Remember up to `capacity` ids; 0 uses the default of 10,000
+    #[wasm_bindgen(constructor)]
+    pub fn new(capacity: usize) -> InboundDeduper {
+        InboundDeduper {
+            capacity: if capacity > 0 { capacity } else { DEFAULT_CAPACITY },
+            seen: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Whether the message is new and should be applied. A copy of one
+    /// already seen returns false (and counts as seen again, so a message
+    /// that keeps being relayed stays remembered).
+    pub fn check(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        Ok(self.admit(stable_id(&message)))
+    }
+
+    /// Ids remembered
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Forget every id, e.g. after loading a snapshot
+    pub fn clear(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+    }
+}
+
+impl InboundDeduper {
+    fn admit(&mut self, id: String) -> bool {
+        self.tick += 1;
+        if let Some(last_seen) = self.seen.insert(id.clone(), self.tick) {
+            self.order.remove(&last_seen);
+            self.order.insert(self.tick, id);
+            return false;
+        }
+        self.order.insert(self.tick, id);
+        if self.seen.len() > self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
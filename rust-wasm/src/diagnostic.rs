@@ -0,0 +1,237 @@
+// CBOR diagnostic notation (RFC 8949 section 8) for debugging. The bytes
+// are read directly rather than through the codec, which skips tags and
+// stops at the first problem without saying where: this shows tags,
+// indefinite lengths and simple values as they are on the wire, and for
+// malformed input, how far it got.
+
+use wasm_bindgen::prelude::*;
+
+/// Deepest nesting rendered, so hostile input can't exhaust the stack
+pub(crate) const MAX_DEPTH: usize = 256;
+
+/// Reads CBOR data items from raw bytes
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, pos: 0 }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub(crate) fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.bytes.get(self.pos).ok_or("unexpected end of input")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    pub(crate) fn take(&mut self, len: u64) -> Result<&'a [u8], String> {
+        let available = self.bytes.len() - self.pos;
+        if len > available as u64 {
+            return Err(format!("{} bytes declared but only {} left", len, available));
+        }
+        let taken = &self.bytes[self.pos..self.pos + len as usize];
+        self.pos += len as usize;
+        Ok(taken)
+    }
+
+    /// The argument of a head with additional information `info`
+    pub(crate) fn argument(&mut self, info: u8) -> Result<u64, String> {
+        let size = match info {
+            0..=23 => return Ok(info as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(format!("reserved additional information {}", info)),
+        };
+        Ok(self.take(size)?.iter().fold(0, |value, byte| value << 8 | *byte as u64))
+    }
+
+    /// Whether the next byte is the break ending an indefinite-length item,
+    /// consuming it if so
+    pub(crate) fn at_break(&mut self) -> Result<bool, String> {
+        match self.bytes.get(self.pos) {
+            Some(0xff) => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => Err("unexpected end of input in indefinite-length item".to_string()),
+        }
+    }
+
+    /// Render one data item onto `out`
+    fn item(&mut self, out: &mut String, depth: usize) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("nested deeper than {} levels", MAX_DEPTH));
+        }
+        let start = self.pos;
+        let head = self.byte()?;
+        let (major, info) = (head >> 5, head & 0x1f);
+        if info == 31 {
+            return self.indefinite(major, out, depth).map_err(|e| {
+                if e.starts_with("byte ") { e } else { format!("byte {}: {}", start, e) }
+            });
+        }
+        match major {
+            0 => out.push_str(&self.argument(info)?.to_string()),
+            1 => out.push_str(&(-1 - self.argument(info)? as i128).to_string()),
+            2 => {
+                let len = self.argument(info)?;
+                out.push_str(&format!("h'{}'", crate::hash_chain::to_hex(self.take(len)?)));
+            }
+            3 => {
+                let len = self.argument(info)?;
+                let text = std::str::from_utf8(self.take(len)?).map_err(|_| "text string is not valid UTF-8")?;
+                out.push_str(&serde_json::to_string(text).unwrap_or_default());
+            }
+            4 => {
+                let len = self.argument(info)?;
+                out.push('[');
+                for i in 0..len {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.item(out, depth + 1)?;
+                }
+                out.push(']');
+            }
+            5 => {
+                let len = self.argument(info)?;
+                out.push('{');
+                for i in 0..len {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.item(out, depth + 1)?;
+                    out.push_str(": ");
+                    self.item(out, depth + 1)?;
+                }
+                out.push('}');
+            }
+            6 => {
+                let tag = self.argument(info)?;
+                out.push_str(&format!("{}(", tag));
+                self.item(out, depth + 1)?;
+                out.push(')');
+            }
+            _ => self.simple(info, out)?,
+        }
+        Ok(())
+    }
+
+    /// Major type 7: simple values and floats
+    fn simple(&mut self, info: u8, out: &mut String) -> Result<(), String> {
+        let rendered = match info {
+            20 => "false".to_string(),
+            21 => "true".to_string(),
+            22 => "null".to_string(),
+            23 => "undefined".to_string(),
+            0..=19 => format!("simple({})", info),
+            24 => format!("simple({})", self.byte()?),
+            25 => float(half_to_f64(self.argument(info)? as u16)),
+            26 => {
+                let value = f32::from_bits(self.argument(info)? as u32);
+                if value.is_finite() { format!("{:?}", value) } else { float(value as f64) }
+            }
+            27 => float(f64::from_bits(self.argument(info)?)),
+            _ => return Err(format!("reserved additional information {}", info)),
+        };
+        out.push_str(&rendered);
+        Ok(())
+    }
+
+    /// An indefinite-length item: `(_ chunks)`, `[_ items]` or
+    /// `{_ pairs}`
+    fn indefinite(&mut self, major: u8, out: &mut String, depth: usize) -> Result<(), String> {
+        let (open, close) = match major {
+            2 | 3 => ("(_ ", ")"),
+            4 => ("[_ ", "]"),
+            5 => ("{_ ", "}"),
+            7 => return Err("break outside an indefinite-length item".to_string()),
+            _ => return Err(format!("major type {} can't have an indefinite length", major)),
+        };
+        out.push_str(open);
+        // Empty ones stay `[_ ]` and `{_ }`, as RFC 8949 writes them
+        let mut first = true;
+        while !self.at_break()? {
+            if !first {
+                out.push_str(", ");
+            }
+            first = false;
+            if major == 2 || major == 3 {
+                // Chunks must be definite-length strings of the same type
+                let chunk = self.bytes[self.pos];
+                if chunk >> 5 != major || chunk & 0x1f == 31 {
+                    return Err(format!("byte {}: string chunk is not a definite-length string of the same type", self.pos));
+                }
+            }
+            self.item(out, depth + 1)?;
+            if major == 5 {
+                out.push_str(": ");
+                if self.at_break()? {
+                    return Err("map ends after a key with no value".to_string());
+                }
+                self.item(out, depth + 1)?;
+            }
+        }
+        out.push_str(close);
+        Ok(())
+    }
+}
+
+pub(crate) fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let mantissa = (bits & 0x3ff) as f64;
+    sign * match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent as i32 - 15),
+    }
+}
+
+/// A float as diagnostic notation writes it: always with a fraction or
+/// exponent, and `NaN`, `Infinity` or `-Infinity` for the rest
+fn float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Render CBOR in RFC 8949 diagnostic notation: tags as `24(...)`, byte
+/// strings as `h'...'`, indefinite-length items with `_`. Several items
+/// in a row (a CBOR sequence such as an edit log) are separated by
+/// commas. Malformed input is an error giving the byte offset of the
+/// problem and what was rendered up to it.
+#[wasm_bindgen]
+pub fn cbor_to_diagnostic(bytes: &[u8]) -> Result<String, JsValue> {
+    let mut reader = Reader::new(bytes);
+    let mut out = String::new();
+    while !reader.is_done() {
+        if !out.is_empty() {
+            out.push_str(", ");
+        }
+        let start = reader.pos;
+        if let Err(e) = reader.item(&mut out, 0) {
+            let e = if e.starts_with("byte ") { e } else { format!("byte {}: {}", reader.pos.max(start), e) };
+            return Err(JsValue::from_str(&format!("Malformed CBOR at {}; rendered so far: {}", e, out)));
+        }
+    }
+    Ok(out)
+}
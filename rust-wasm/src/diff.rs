@@ -0,0 +1,396 @@
+// Myers difference algorithm, in the linear-space divide-and-conquer form
+// (find the middle snake, recurse on both halves), over any slice of
+// comparable items. Line diffs for version history are built on it.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DiffKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// Edit distance searched for a middle snake before the range is treated
+/// as replaced wholesale; bounds the time spent on unrelated texts
+const MAX_SEARCH_COST: isize = 1024;
+
+/// A run of items: equal in both, only in `new` or only in `old`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DiffOp {
+    pub kind: DiffKind,
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Furthest-reaching x per diagonal k, indexable by negative k
+struct V {
+    offset: isize,
+    values: Vec<usize>,
+}
+
+impl V {
+    fn new(max_d: usize) -> V {
+        V { offset: max_d as isize + 1, values: vec![0; 2 * max_d + 3] }
+    }
+}
+
+impl std::ops::Index<isize> for V {
+    type Output = usize;
+    fn index(&self, k: isize) -> &usize {
+        &self.values[(k + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for V {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.values[(k + self.offset) as usize]
+    }
+}
+
+fn common_prefix<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    old.iter().rev().zip(new.iter().rev()).take_while(|(a, b)| a == b).count()
+}
+
+fn push(ops: &mut Vec<DiffOp>, kind: DiffKind, old: Range<usize>, new: Range<usize>) {
+    if old.is_empty() && new.is_empty() {
+        return;
+    }
+    if let Some(last) = ops.last_mut() {
+        if last.kind == kind && last.old.end == old.start && last.new.end == new.start {
+            last.old.end = old.end;
+            last.new.end = new.end;
+            return;
+        }
+    }
+    ops.push(DiffOp { kind, old, new });
+}
+
+/// Start of the middle snake of an optimal path from the start of both
+/// ranges to their end, searching forwards and backwards at once
+fn middle_snake<T: PartialEq>(
+    old: &[T],
+    old_range: Range<usize>,
+    new: &[T],
+    new_range: Range<usize>,
+    vf: &mut V,
+    vb: &mut V,
+) -> Option<(usize, usize)> {
+    let n = old_range.len();
+    let m = new_range.len();
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    vf[1] = 0;
+    vb[1] = 0;
+    let max_d = ((n + m).div_ceil(2) as isize).min(MAX_SEARCH_COST);
+    for d in 0..=max_d {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) { vf[k + 1] } else { vf[k - 1] + 1 };
+            let y = (x as isize - k) as usize;
+            let (x0, y0) = (x, y);
+            if x < n && y < m {
+                x += common_prefix(&old[old_range.start + x..old_range.end], &new[new_range.start + y..new_range.end]);
+            }
+            vf[k] = x;
+            if odd && (k - delta).abs() < d && vf[k] + vb[-(k - delta)] >= n {
+                return Some((old_range.start + x0, new_range.start + y0));
+            }
+        }
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vb[k - 1] < vb[k + 1]) { vb[k + 1] } else { vb[k - 1] + 1 };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let advance = common_suffix(
+                    &old[old_range.start..old_range.start + n - x],
+                    &new[new_range.start..new_range.start + m - y],
+                );
+                x += advance;
+                y += advance;
+            }
+            vb[k] = x;
+            if !odd && (k - delta).abs() <= d && vb[k] + vf[-(k - delta)] >= n {
+                return Some((old_range.start + n - x, new_range.start + m - y));
+            }
+        }
+    }
+    None
+}
+
+fn conquer<T: PartialEq>(
+    old: &[T],
+    mut old_range: Range<usize>,
+    new: &[T],
+    mut new_range: Range<usize>,
+    vf: &mut V,
+    vb: &mut V,
+    ops: &mut Vec<DiffOp>,
+) {
+    let prefix = common_prefix(&old[old_range.clone()], &new[new_range.clone()]);
+    push(ops, DiffKind::Equal, old_range.start..old_range.start + prefix, new_range.start..new_range.start + prefix);
+    old_range.start += prefix;
+    new_range.start += prefix;
+    let suffix = common_suffix(&old[old_range.clone()], &new[new_range.clone()]);
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() || new_range.is_empty() {
+        push(ops, DiffKind::Delete, old_range.clone(), new_range.start..new_range.start);
+        push(ops, DiffKind::Insert, old_range.end..old_range.end, new_range.clone());
+    } else if let Some((x, y)) = middle_snake(old, old_range.clone(), new, new_range.clone(), vf, vb) {
+        conquer(old, old_range.start..x, new, new_range.start..y, vf, vb, ops);
+        conquer(old, x..old_range.end, new, y..new_range.end, vf, vb, ops);
+    } else {
+        push(ops, DiffKind::Delete, old_range.clone(), new_range.start..new_range.start);
+        push(ops, DiffKind::Insert, old_range.end..old_range.end, new_range.clone());
+    }
+
+    push(ops, DiffKind::Equal, old_range.end..old_range.end + suffix, new_range.end..new_range.end + suffix);
+}
+
+/// Shortest edit script turning `old` into `new`, as runs of equal,
+/// deleted and inserted items covering both slices in order. Past
+/// `MAX_SEARCH_COST` edits within a range the script may be longer than
+/// the shortest.
+pub(crate) fn diff_slices<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffOp> {
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut vf = V::new(max_d);
+    let mut vb = V::new(max_d);
+    let mut ops = Vec::new();
+    conquer(old, 0..old.len(), new, 0..new.len(), &mut vf, &mut vb, &mut ops);
+
+    // Between two equal runs, put every deletion before every insertion
+    let mut normalized = Vec::with_capacity(ops.len());
+    let mut i = 0;
+    while i < ops.len() {
+        if ops[i].kind == DiffKind::Equal {
+            normalized.push(ops[i].clone());
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < ops.len() && ops[i].kind != DiffKind::Equal {
+            i += 1;
+        }
+        let (old_start, new_start) = (ops[start].old.start, ops[start].new.start);
+        let (old_end, new_end) = (ops[i - 1].old.end, ops[i - 1].new.end);
+        push(&mut normalized, DiffKind::Delete, old_start..old_end, new_start..new_start);
+        push(&mut normalized, DiffKind::Insert, old_end..old_end, new_start..new_end);
+    }
+    normalized
+}
+
+/// `diff_slices` over lines or other strings, comparing interned ids
+pub(crate) fn diff_strs<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffOp> {
+    let mut ids: HashMap<&'a str, usize> = HashMap::new();
+    let mut intern = |lines: &[&'a str]| -> Vec<usize> {
+        lines
+            .iter()
+            .map(|line| {
+                let next = ids.len();
+                *ids.entry(*line).or_insert(next)
+            })
+            .collect()
+    };
+    let old_ids = intern(old);
+    let new_ids = intern(new);
+    diff_slices(&old_ids, &new_ids)
+}
+
+#[derive(Serialize)]
+struct Hunk<'a> {
+    kind: DiffKind,
+    /// 1-based first line in the old text
+    old_start: usize,
+    old_lines: usize,
+    /// 1-based first line in the new text
+    new_start: usize,
+    new_lines: usize,
+    /// The hunk's lines (from the new text for insertions, the old text
+    /// otherwise)
+    lines: &'a [&'a str],
+}
+
+#[derive(Serialize)]
+struct DocumentDiff<'a> {
+    hunks: Vec<Hunk<'a>>,
+    inserted: usize,
+    deleted: usize,
+    unchanged: usize,
+}
+
+/// Line-by-line difference between two versions of a document as JSON
+/// `{hunks, inserted, deleted, unchanged}`. Each hunk is `{kind: "equal" |
+/// "insert" | "delete", old_start, old_lines, new_start, new_lines,
+/// lines}` with 1-based line numbers; the hunks cover both texts in order,
+/// a replaced block being a delete followed by an insert.
+#[wasm_bindgen]
+pub fn diff_documents(old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_strs(&old_lines, &new_lines);
+    let count = |kind: DiffKind| ops.iter().filter(|op| op.kind == kind).map(|op| op.old.len().max(op.new.len())).sum();
+    let hunks = ops
+        .iter()
+        .map(|op| Hunk {
+            kind: op.kind,
+            old_start: op.old.start + 1,
+            old_lines: op.old.len(),
+            new_start: op.new.start + 1,
+            new_lines: op.new.len(),
+            lines: if op.kind == DiffKind::Insert { &new_lines[op.new.clone()] } else { &old_lines[op.old.clone()] },
+        })
+        .collect();
+    let diff = DocumentDiff {
+        inserted: count(DiffKind::Insert),
+        deleted: count(DiffKind::Delete),
+        unchanged: count(DiffKind::Equal),
+        hunks,
+    };
+    serde_json::to_string(&diff).unwrap_or_else(|_| "{}".into())
+}
+
+/// Split text into diff tokens as byte ranges: words, whitespace runs,
+/// line breaks, single CJK characters, and runs of one punctuation
+/// character so markdown syntax (`**`, `~~`, `##`, backtick fences) stays
+/// a unit apart from the words it wraps
+fn word_tokens(text: &str) -> Vec<Range<usize>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        let same_class = |next: char| -> bool {
+            if c == '\n' || crate::is_cjk(c) || crate::is_cjk(next) {
+                false
+            } else if c.is_alphanumeric() {
+                next.is_alphanumeric()
+            } else if c.is_whitespace() {
+                next.is_whitespace() && next != '\n'
+            } else {
+                next == c
+            }
+        };
+        while let Some(&(i, next)) = chars.peek() {
+            // An apostrophe between letters stays in the word (don't)
+            let apostrophe = c.is_alphanumeric()
+                && matches!(next, '\'' | '’')
+                && text[i + next.len_utf8()..].starts_with(char::is_alphanumeric);
+            if !same_class(next) && !apostrophe {
+                break;
+            }
+            end = i + next.len_utf8();
+            chars.next();
+        }
+        tokens.push(start..end);
+    }
+    tokens
+}
+
+#[derive(Serialize)]
+struct WordChange<'a> {
+    kind: DiffKind,
+    /// Byte range in the old text (empty for insertions)
+    old_start: usize,
+    old_end: usize,
+    /// Byte range in the new text (empty for deletions)
+    new_start: usize,
+    new_end: usize,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct WordDiff<'a> {
+    changes: Vec<WordChange<'a>>,
+    inserted_words: usize,
+    deleted_words: usize,
+}
+
+/// Word-level difference between two versions of a text as JSON
+/// `{changes, inserted_words, deleted_words}`. Words, whitespace and
+/// markdown syntax runs (`**`, `[`, `](`...) are compared as separate
+/// tokens, so an edit inside a line highlights only what changed and
+/// bold markers added around a word show as inserted syntax, not a new
+/// word. Each change is `{kind: "equal" | "insert" | "delete", old_start,
+/// old_end, new_start, new_end, text}` with byte offsets; the changes
+/// cover both texts in order, so deletions mark up the old side and
+/// insertions the new side of a side-by-side view, and the whole list in
+/// order renders an inline view. A lone space between two changes is
+/// folded into them so replaced phrases read as one change.
+#[wasm_bindgen]
+pub fn diff_words(old: &str, new: &str) -> String {
+    let old_tokens = word_tokens(old);
+    let new_tokens = word_tokens(new);
+    let old_strs: Vec<&str> = old_tokens.iter().map(|r| &old[r.clone()]).collect();
+    let new_strs: Vec<&str> = new_tokens.iter().map(|r| &new[r.clone()]).collect();
+    let mut ops = diff_strs(&old_strs, &new_strs);
+
+    // Fold whitespace-only equal runs between changes into the change
+    let mut folded: Vec<DiffOp> = Vec::with_capacity(ops.len());
+    for (n, op) in ops.iter().enumerate() {
+        let between_changes = n > 0 && n + 1 < ops.len();
+        let blank = op.kind == DiffKind::Equal && old_strs[op.old.clone()].iter().all(|t| t.trim().is_empty() && !t.contains('\n'));
+        if between_changes && blank {
+            push(&mut folded, DiffKind::Delete, op.old.clone(), op.new.start..op.new.start);
+            push(&mut folded, DiffKind::Insert, op.old.end..op.old.end, op.new.clone());
+        } else {
+            folded.push(op.clone());
+        }
+    }
+    ops = Vec::with_capacity(folded.len());
+    let mut i = 0;
+    while i < folded.len() {
+        if folded[i].kind == DiffKind::Equal {
+            ops.push(folded[i].clone());
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < folded.len() && folded[i].kind != DiffKind::Equal {
+            i += 1;
+        }
+        let (old_start, new_start) = (folded[start].old.start, folded[start].new.start);
+        let (old_end, new_end) = (folded[i - 1].old.end, folded[i - 1].new.end);
+        push(&mut ops, DiffKind::Delete, old_start..old_end, new_start..new_start);
+        push(&mut ops, DiffKind::Insert, old_end..old_end, new_start..new_end);
+    }
+
+    // Token ranges to byte ranges
+    let bytes = |tokens: &[Range<usize>], range: &Range<usize>, len: usize| -> Range<usize> {
+        let start = tokens.get(range.start).map_or(len, |t| t.start);
+        let end = if range.is_empty() { start } else { tokens[range.end - 1].end };
+        start..end
+    };
+    let words = |strs: &[&str]| strs.iter().filter(|t| t.starts_with(char::is_alphanumeric)).count();
+    let mut diff = WordDiff { changes: Vec::new(), inserted_words: 0, deleted_words: 0 };
+    for op in &ops {
+        let old_range = bytes(&old_tokens, &op.old, old.len());
+        let new_range = bytes(&new_tokens, &op.new, new.len());
+        let text = match op.kind {
+            DiffKind::Insert => {
+                diff.inserted_words += words(&new_strs[op.new.clone()]);
+                &new[new_range.clone()]
+            }
+            DiffKind::Delete => {
+                diff.deleted_words += words(&old_strs[op.old.clone()]);
+                &old[old_range.clone()]
+            }
+            DiffKind::Equal => &old[old_range.clone()],
+        };
+        diff.changes.push(WordChange {
+            kind: op.kind,
+            old_start: old_range.start,
+            old_end: old_range.end,
+            new_start: new_range.start,
+            new_end: new_range.end,
+            text,
+        });
+    }
+    serde_json::to_string(&diff).unwrap_or_else(|_| "{}".into())
+}
@@ -0,0 +1,406 @@
+// Single-file import for drag and drop: work out what a dropped file is
+// from its bytes (falling back on its name or MIME type where the bytes
+// are ambiguous) and convert it to markdown with whatever metadata it
+// carries.
+
+use wasm_bindgen::prelude::*;
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::io::Read;
+
+use crate::ast::{inline_text, parse_markdown, to_markdown, Align, Block, Document, Inline};
+use crate::codec::{framing, Framing};
+use crate::front_matter::parse_yaml;
+use crate::html::{decode_entities, html_to_document, parse_attrs};
+use crate::rtf::rtf_to_document;
+
+/// Largest decompressed snapshot accepted, against zip bombs
+const MAX_DECOMPRESSED_BYTES: u64 = 32 * 1024 * 1024;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+#[derive(Serialize)]
+struct ImportResult {
+    /// "markdown", "html", "rtf", "csv", "cbor_bundle" or "promisegrid"
+    format: &'static str,
+    /// "gzip" or "zstd" for compressed snapshots
+    compression: Option<&'static str>,
+    content: String,
+    metadata: Map<String, Value>,
+    warnings: Vec<String>,
+}
+
+/// The format a file name or MIME type points to
+fn hinted_format(hint: &str) -> Option<&'static str> {
+    let hint = hint.trim().to_ascii_lowercase();
+    let mime = hint.split(';').next().unwrap_or("").trim();
+    let by_mime = match mime {
+        "text/markdown" | "text/x-markdown" | "text/plain" => Some("markdown"),
+        "text/html" | "application/xhtml+xml" => Some("html"),
+        "application/rtf" | "text/rtf" => Some("rtf"),
+        "text/csv" | "text/tab-separated-values" => Some("csv"),
+        "application/cbor" => Some("cbor"),
+        _ => None,
+    };
+    if by_mime.is_some() {
+        return by_mime;
+    }
+    let name = hint.trim_end_matches(".gz").trim_end_matches(".zst");
+    match name.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("md" | "markdown" | "mdown" | "txt" | "text") => Some("markdown"),
+        Some("html" | "htm" | "xhtml") => Some("html"),
+        Some("rtf") => Some("rtf"),
+        Some("csv" | "tsv") => Some("csv"),
+        Some("cbor") => Some("cbor"),
+        _ => None,
+    }
+}
+
+/// A file name hint without its directory and extensions, for a title
+fn file_stem(hint: &str) -> Option<String> {
+    let media_types = ["text/", "application/", "image/", "audio/", "video/"];
+    if media_types.iter().any(|t| hint.trim().to_ascii_lowercase().starts_with(t)) {
+        return None;
+    }
+    let name = hint.rsplit(['/', '\\']).next()?.trim();
+    let (stem, _) = name.split_once('.')?;
+    (!stem.trim().is_empty()).then(|| stem.trim().to_string())
+}
+
+pub(crate) fn decompress(bytes: &[u8], zstd: bool) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let read = if zstd {
+        let decoder = ruzstd::StreamingDecoder::new(bytes).map_err(|e| format!("Invalid zstd data: {}", e))?;
+        decoder.take(MAX_DECOMPRESSED_BYTES + 1).read_to_end(&mut out)
+    } else {
+        GzDecoder::new(bytes).take(MAX_DECOMPRESSED_BYTES + 1).read_to_end(&mut out)
+    };
+    read.map_err(|e| format!("Could not decompress: {}", e))?;
+    if out.len() as u64 > MAX_DECOMPRESSED_BYTES {
+        return Err("Decompressed file is too large".to_string());
+    }
+    Ok(out)
+}
+
+/// Decode text, honouring UTF-8 and UTF-16 byte order marks
+fn decode_text(bytes: &[u8], warnings: &mut Vec<String>) -> String {
+    let utf16 = |big_endian: bool| {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|p| if big_endian { u16::from_be_bytes([p[0], p[1]]) } else { u16::from_le_bytes([p[0], p[1]]) })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    match bytes {
+        [0xef, 0xbb, 0xbf, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        [0xff, 0xfe, ..] => utf16(false),
+        [0xfe, 0xff, ..] => utf16(true),
+        _ => match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => {
+                warnings.push("File is not valid UTF-8; unreadable characters were replaced".to_string());
+                String::from_utf8_lossy(bytes).into_owned()
+            }
+        },
+    }
+}
+
+fn looks_like_html(text: &str) -> bool {
+    let start: String = text.trim_start().chars().take(256).collect::<String>().to_ascii_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        return true;
+    }
+    let lower = text.to_ascii_lowercase();
+    start.starts_with('<')
+        && ["</p>", "</div>", "</body>", "</table>", "</ul>", "</ol>", "</h1>", "</h2>", "</span>", "</section>"]
+            .iter()
+            .any(|tag| lower.contains(tag))
+}
+
+/// Split a CSV line into fields, with `"` quoting (`""` inside quotes is
+/// a literal quote). Returns the fields and the rest of the input.
+fn csv_record(input: &str, delimiter: char) -> (Vec<String>, &str) {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek().map(|(_, n)| *n) == Some('"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            _ if quoted => field.push(c),
+            '\r' => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                return (fields, &input[i + 1..]);
+            }
+            _ if c == delimiter => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    (fields, "")
+}
+
+pub(crate) fn csv_records(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (fields, next) = csv_record(rest, delimiter);
+        if !(fields.len() == 1 && fields[0].trim().is_empty()) {
+            records.push(fields);
+        }
+        rest = next;
+    }
+    records
+}
+
+/// The delimiter of text that parses as a table with at least two rows and
+/// two columns and the same column count on every row
+fn csv_delimiter(text: &str) -> Option<char> {
+    [',', '\t', ';'].into_iter().find(|&delimiter| {
+        let records = csv_records(text, delimiter);
+        records.len() >= 2 && records[0].len() >= 2 && records.iter().all(|r| r.len() == records[0].len())
+    })
+}
+
+/// Lines that only markdown would have, which rule out CSV
+fn has_markdown_syntax(text: &str) -> bool {
+    text.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with('#') || line.starts_with("- ") || line.starts_with("* ") || line.starts_with("```") || line.starts_with('>')
+    })
+}
+
+pub(crate) fn csv_to_markdown(text: &str, delimiter: char) -> (String, Map<String, Value>) {
+    let records = csv_records(text, delimiter);
+    let columns = records.iter().map(Vec::len).max().unwrap_or(0);
+    let cell = |text: &String| vec![Inline::Text { text: text.replace(['\r', '\n'], " ").trim().to_string() }];
+    let mut rows: Vec<Vec<Vec<Inline>>> = records
+        .iter()
+        .map(|r| {
+            let mut cells: Vec<Vec<Inline>> = r.iter().map(cell).collect();
+            cells.resize(columns, Vec::new());
+            cells
+        })
+        .collect();
+    let mut metadata = Map::new();
+    if rows.is_empty() {
+        return (String::new(), metadata);
+    }
+    metadata.insert("rows".to_string(), Value::from(rows.len() - 1));
+    metadata.insert("columns".to_string(), Value::from(columns));
+    let header = rows.remove(0);
+    let table = Block::Table { align: vec![Align::None; columns], header, rows };
+    (to_markdown(&Document { front_matter: None, lines: vec![0], blocks: vec![table] }), metadata)
+}
+
+/// Attributes of each `<name ...>` start tag in `html`
+fn start_tags(html: &str, name: &str) -> Vec<Vec<(String, String)>> {
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", name);
+    let mut tags = Vec::new();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(&open).map(|p| p + from) {
+        let attrs_start = pos + open.len();
+        let end = lower[attrs_start..].find('>').map_or(html.len(), |e| e + attrs_start);
+        from = end;
+        // `<metadata>` is not `<meta>`
+        if html[attrs_start..].starts_with(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/') {
+            tags.push(parse_attrs(html[attrs_start..end].trim_end_matches('/')));
+        }
+    }
+    tags
+}
+
+/// `<title>`, `<html lang>` and `<meta name=... content=...>` of an HTML
+/// page (the tree parser drops the head)
+fn html_metadata(html: &str, metadata: &mut Map<String, Value>) {
+    let lower = html.to_ascii_lowercase();
+    let title = lower.find("<title").and_then(|start| {
+        let open_end = start + lower[start..].find('>')? + 1;
+        let close = open_end + lower[open_end..].find("</title")?;
+        Some(decode_entities(&html[open_end..close]).trim().to_string())
+    });
+    if let Some(title) = title.filter(|t| !t.is_empty()) {
+        metadata.insert("title".to_string(), Value::String(title));
+    }
+    let attr = |attrs: &[(String, String)], key: &str| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+    if let Some(lang) = start_tags(html, "html").first().and_then(|a| attr(a, "lang")) {
+        metadata.insert("lang".to_string(), Value::String(lang));
+    }
+    for tag in start_tags(html, "meta") {
+        let key = attr(&tag, "name").or_else(|| attr(&tag, "property")).map(|k| k.trim_start_matches("og:").to_ascii_lowercase());
+        if let (Some(key), Some(content)) = (key, attr(&tag, "content")) {
+            if matches!(key.as_str(), "author" | "description" | "keywords" | "title") {
+                metadata.entry(key).or_insert(Value::String(content));
+            }
+        }
+    }
+}
+
+/// A CBOR value as JSON; byte strings become arrays of numbers
+fn cbor_to_json(value: &ciborium::Value) -> Value {
+    use ciborium::Value as Cbor;
+    match value {
+        Cbor::Null => Value::Null,
+        Cbor::Bool(b) => Value::Bool(*b),
+        Cbor::Integer(n) => i64::try_from(*n).map(Value::from).unwrap_or_else(|_| Value::from(i128::from(*n) as f64)),
+        Cbor::Float(f) => Value::from(*f),
+        Cbor::Bytes(bytes) => Value::from(bytes.clone()),
+        Cbor::Text(text) => Value::String(text.clone()),
+        Cbor::Array(items) => Value::Array(items.iter().map(cbor_to_json).collect()),
+        Cbor::Map(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Cbor::Text(text) => text.clone(),
+                        other => cbor_to_json(other).to_string(),
+                    };
+                    (key, cbor_to_json(v))
+                })
+                .collect(),
+        ),
+        Cbor::Tag(_, inner) => cbor_to_json(inner),
+        _ => Value::Null,
+    }
+}
+
+/// A PromiseGrid message carrying document content (an export or edit)
+fn import_promisegrid(bytes: &[u8], result: &mut ImportResult) -> Result<(), String> {
+    let message = crate::decode_with_grid_tag(bytes).map_err(|e| format!("Invalid PromiseGrid message: {}", e))?;
+    let data = &message.payload.data;
+    result.content = crate::data_text(data, "content").ok_or("PromiseGrid message has no document content")?.to_string();
+    result.metadata.insert("message_type".to_string(), Value::String(message.payload.message_type.clone()));
+    for key in ["document_id", "user_id", "timestamp"] {
+        if let Some(value) = data.get(key) {
+            result.metadata.insert(key.to_string(), cbor_to_json(value));
+        }
+    }
+    Ok(())
+}
+
+/// A CBOR bundle `{content, metadata}` as saved by the editor
+fn import_cbor_bundle(bytes: &[u8], result: &mut ImportResult) -> Result<(), String> {
+    let value: ciborium::Value = crate::from_cbor(bytes).map_err(|e| format!("Invalid CBOR: {}", e))?;
+    let Value::Object(mut fields) = cbor_to_json(&value) else {
+        return Err("CBOR bundle is not a map".to_string());
+    };
+    match fields.remove("content") {
+        Some(Value::String(content)) => result.content = content,
+        _ => return Err("CBOR bundle has no text content".to_string()),
+    }
+    if let Some(Value::Object(metadata)) = fields.remove("metadata") {
+        result.metadata.extend(metadata);
+    }
+    Ok(())
+}
+
+fn import_text(text: &str, hint: Option<&'static str>, result: &mut ImportResult) {
+    let trimmed = text.trim_start();
+    let format = if trimmed.starts_with("{\\rtf") {
+        "rtf"
+    } else if let Some(hint @ ("markdown" | "html" | "csv")) = hint {
+        hint
+    } else if looks_like_html(text) {
+        "html"
+    } else if !has_markdown_syntax(text) && csv_delimiter(text).is_some() {
+        "csv"
+    } else {
+        "markdown"
+    };
+    result.format = format;
+    match format {
+        "rtf" => {
+            let (doc, metadata) = rtf_to_document(text);
+            result.content = to_markdown(&doc);
+            result.metadata.extend(metadata);
+        }
+        "html" => {
+            html_metadata(text, &mut result.metadata);
+            result.content = to_markdown(&html_to_document(text));
+        }
+        "csv" => {
+            let delimiter = csv_delimiter(text).unwrap_or_else(|| {
+                result.warnings.push("Rows have differing column counts; short rows were padded".to_string());
+                if text.lines().next().unwrap_or("").contains('\t') { '\t' } else { ',' }
+            });
+            let (content, metadata) = csv_to_markdown(text, delimiter);
+            result.content = content;
+            result.metadata.extend(metadata);
+        }
+        _ => {
+            result.content = text.to_string();
+            let doc = parse_markdown(text);
+            if let Some(Ok(yaml)) = doc.front_matter.as_deref().map(parse_yaml) {
+                result.metadata.extend(yaml);
+            }
+        }
+    }
+}
+
+fn import_bytes(bytes: &[u8], hint: &str, result: &mut ImportResult) -> Result<(), String> {
+    let hint_format = hinted_format(hint);
+    if bytes.starts_with(GZIP_MAGIC) || bytes.starts_with(ZSTD_MAGIC) {
+        if result.compression.is_some() {
+            return Err("Nested compression is not supported".to_string());
+        }
+        let zstd = bytes.starts_with(ZSTD_MAGIC);
+        result.compression = Some(if zstd { "zstd" } else { "gzip" });
+        return import_bytes(&decompress(bytes, zstd)?, hint, result);
+    }
+    if bytes.starts_with(ZIP_MAGIC) {
+        return Err("Zip archives hold several documents; import them with import_archive".to_string());
+    }
+    if matches!(framing(bytes), Ok(Framing::Grid { .. } | Framing::LongGrid { .. })) {
+        result.format = "promisegrid";
+        return import_promisegrid(bytes, result);
+    }
+    // CBOR maps start with a byte that can't begin UTF-8 text
+    if hint_format == Some("cbor") || matches!(bytes.first(), Some(0xa0..=0xbf)) || bytes.starts_with(&[0xd9, 0xd9, 0xf7]) {
+        result.format = "cbor_bundle";
+        return import_cbor_bundle(bytes, result);
+    }
+    let text = decode_text(bytes, &mut result.warnings);
+    import_text(&text, hint_format, result);
+    Ok(())
+}
+
+/// Import a dropped file of any supported kind as markdown. The content is
+/// sniffed: gzip and zstd snapshots are decompressed first, then CBOR
+/// bundles (`{content, metadata}`), PromiseGrid export messages, RTF,
+/// HTML, CSV/TSV and markdown are told apart by their bytes. `hint` is the
+/// file name or MIME type, used where text could be several formats (a
+/// `.csv` is read as CSV even if it would also pass as markdown). Returns
+/// JSON `{format, compression, content, metadata, warnings}`; `metadata`
+/// holds what the file carries (front matter, HTML `<title>` and
+/// `<meta>`, the RTF info group, CSV dimensions, bundle metadata) with a
+/// `title` from the first heading or file name when it has none.
+#[wasm_bindgen]
+pub fn import_document(bytes: &[u8], hint: &str) -> Result<String, JsValue> {
+    let mut result = ImportResult {
+        format: "markdown",
+        compression: None,
+        content: String::new(),
+        metadata: Map::new(),
+        warnings: Vec::new(),
+    };
+    import_bytes(bytes, hint, &mut result).map_err(|e| JsValue::from_str(&e))?;
+    if !result.metadata.contains_key("title") {
+        let heading = parse_markdown(&result.content).blocks.iter().find_map(|b| match b {
+            Block::Heading { content, .. } => Some(inline_text(content)),
+            _ => None,
+        });
+        if let Some(title) = heading.or_else(|| file_stem(hint)) {
+            result.metadata.insert("title".to_string(), Value::String(title));
+        }
+    }
+    Ok(serde_json::to_string(&result).unwrap_or_else(|_| "{}".into()))
+}
@@ -0,0 +1,866 @@
+// Minimal DOCX export: a WordprocessingML package with just the parts Word
+// needs (document, styles, numbering and their relationships). Formatting
+// is expressed through named styles so the result edits like a document
+// written in Word rather than one full of direct formatting. The reader at
+// the end understands packages of that shape, so the fidelity check can
+// round-trip through docx.
+
+use wasm_bindgen::prelude::*;
+
+use std::collections::HashMap;
+
+use crate::ast::{inline_text, parse_markdown, Align, Block, Document, Footnotes, Inline, ListItem};
+use crate::html::{html_to_document, parse_html, Node};
+use crate::zip::{read_zip, write_zip, ZipEntry};
+use crate::markdown::escape_xml;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/><Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/><Override PartName="/word/numbering.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml"/></Types>"#;
+
+const PACKAGE_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#;
+
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:ascii="Calibri" w:hAnsi="Calibri" w:eastAsia="Calibri" w:cs="Calibri"/><w:sz w:val="22"/><w:szCs w:val="22"/><w:lang w:val="en-US"/></w:rPr></w:rPrDefault><w:pPrDefault><w:pPr><w:spacing w:after="160" w:line="259" w:lineRule="auto"/></w:pPr></w:pPrDefault></w:docDefaults><w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/><w:qFormat/></w:style><w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="360" w:after="120"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr><w:b/><w:sz w:val="36"/><w:szCs w:val="36"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="240" w:after="80"/><w:outlineLvl w:val="1"/></w:pPr><w:rPr><w:b/><w:sz w:val="30"/><w:szCs w:val="30"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading3"><w:name w:val="heading 3"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="240" w:after="80"/><w:outlineLvl w:val="2"/></w:pPr><w:rPr><w:b/><w:sz w:val="26"/><w:szCs w:val="26"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading4"><w:name w:val="heading 4"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="200" w:after="60"/><w:outlineLvl w:val="3"/></w:pPr><w:rPr><w:b/><w:i/><w:sz w:val="24"/><w:szCs w:val="24"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading5"><w:name w:val="heading 5"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="200" w:after="60"/><w:outlineLvl w:val="4"/></w:pPr><w:rPr><w:b/><w:sz w:val="22"/><w:szCs w:val="22"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading6"><w:name w:val="heading 6"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/><w:spacing w:before="200" w:after="60"/><w:outlineLvl w:val="5"/></w:pPr><w:rPr><w:b/><w:i/><w:sz w:val="22"/><w:szCs w:val="22"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Quote"><w:name w:val="Quote"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:pBdr><w:left w:val="single" w:sz="12" w:space="8" w:color="BFBFBF"/></w:pBdr><w:ind w:left="720"/></w:pPr><w:rPr><w:i/><w:color w:val="595959"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Code"><w:name w:val="Code"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:shd w:val="clear" w:color="auto" w:fill="F2F2F2"/><w:spacing w:after="0" w:line="240" w:lineRule="auto"/></w:pPr><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas" w:cs="Consolas"/><w:sz w:val="20"/><w:szCs w:val="20"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="ListParagraph"><w:name w:val="List Paragraph"/><w:basedOn w:val="Normal"/><w:qFormat/><w:pPr><w:spacing w:after="60"/><w:ind w:left="720"/></w:pPr></w:style><w:style w:type="character" w:default="1" w:styleId="DefaultParagraphFont"><w:name w:val="Default Paragraph Font"/><w:uiPriority w:val="1"/><w:semiHidden/></w:style><w:style w:type="character" w:styleId="Hyperlink"><w:name w:val="Hyperlink"/><w:basedOn w:val="DefaultParagraphFont"/><w:rPr><w:color w:val="0563C1"/><w:u w:val="single"/></w:rPr></w:style><w:style w:type="character" w:styleId="CodeChar"><w:name w:val="Code Char"/><w:basedOn w:val="DefaultParagraphFont"/><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas" w:cs="Consolas"/><w:sz w:val="20"/><w:szCs w:val="20"/><w:shd w:val="clear" w:color="auto" w:fill="F2F2F2"/></w:rPr></w:style><w:style w:type="table" w:default="1" w:styleId="TableNormal"><w:name w:val="Normal Table"/><w:semiHidden/><w:tblPr><w:tblInd w:w="0" w:type="dxa"/><w:tblCellMar><w:top w:w="0" w:type="dxa"/><w:left w:w="108" w:type="dxa"/><w:bottom w:w="0" w:type="dxa"/><w:right w:w="108" w:type="dxa"/></w:tblCellMar></w:tblPr></w:style><w:style w:type="table" w:styleId="TableGrid"><w:name w:val="Table Grid"/><w:basedOn w:val="TableNormal"/><w:pPr><w:spacing w:after="0"/></w:pPr><w:tblPr><w:tblBorders><w:top w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:left w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:bottom w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:right w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:insideH w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:insideV w:val="single" w:sz="4" w:space="0" w:color="auto"/></w:tblBorders></w:tblPr></w:style></w:styles>"#;
+
+/// Numbering definition for bullets
+const BULLET_NUM_ID: usize = 1;
+/// Bullet characters by nesting level
+const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+/// Nesting levels Word supports in one numbering definition
+const LIST_LEVELS: usize = 9;
+
+/// Character formatting inherited from enclosing inline elements
+#[derive(Default, Clone, Copy)]
+struct RunStyle {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    underline: bool,
+    code: bool,
+    superscript: bool,
+    link: bool,
+}
+
+impl RunStyle {
+    fn properties(&self) -> String {
+        let mut props = String::new();
+        if self.code {
+            props.push_str("<w:rStyle w:val=\"CodeChar\"/>");
+        } else if self.link {
+            props.push_str("<w:rStyle w:val=\"Hyperlink\"/>");
+        }
+        if self.bold {
+            props.push_str("<w:b/>");
+        }
+        if self.italic {
+            props.push_str("<w:i/>");
+        }
+        if self.strike {
+            props.push_str("<w:strike/>");
+        }
+        if self.underline {
+            props.push_str("<w:u w:val=\"single\"/>");
+        }
+        if self.superscript {
+            props.push_str("<w:vertAlign w:val=\"superscript\"/>");
+        }
+        if props.is_empty() { props } else { format!("<w:rPr>{}</w:rPr>", props) }
+    }
+}
+
+fn run(text: &str, style: RunStyle) -> String {
+    format!("<w:r>{}<w:t xml:space=\"preserve\">{}</w:t></w:r>", style.properties(), escape_xml(text))
+}
+
+/// Paragraph-level context: a quote style and the list an item belongs to
+#[derive(Default, Clone, Copy)]
+struct Context<'a> {
+    style: Option<&'a str>,
+    /// Numbering instance and level of the first paragraph of a list item
+    numbering: Option<(usize, usize)>,
+    /// Indentation of further paragraphs inside a list item
+    indent: Option<usize>,
+}
+
+struct Writer<'a> {
+    body: String,
+    /// External hyperlink targets; relationship ids start after styles
+    /// and numbering
+    links: Vec<String>,
+    /// Start number of each ordered list's numbering instance
+    ordered_lists: Vec<u64>,
+    footnotes: &'a Footnotes<'a>,
+    /// Runs to put at the start of the next paragraph (footnote numbers)
+    prefix: String,
+}
+
+impl Writer<'_> {
+    fn link_id(&mut self, url: &str) -> String {
+        let n = match self.links.iter().position(|u| u == url) {
+            Some(n) => n,
+            None => {
+                self.links.push(url.to_string());
+                self.links.len() - 1
+            }
+        };
+        format!("rId{}", n + 3)
+    }
+
+    fn runs(&mut self, inlines: &[Inline], style: RunStyle) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text { text } | Inline::Math { tex: text } => out.push_str(&run(text, style)),
+                Inline::Code { code } => out.push_str(&run(code, RunStyle { code: true, ..style })),
+                Inline::Emphasis { content } => out.push_str(&self.runs(content, RunStyle { italic: true, ..style })),
+                Inline::Strong { content } => out.push_str(&self.runs(content, RunStyle { bold: true, ..style })),
+                Inline::Strikethrough { content } => out.push_str(&self.runs(content, RunStyle { strike: true, ..style })),
+                Inline::Underline { content } => out.push_str(&self.runs(content, RunStyle { underline: true, ..style })),
+                Inline::Link { url, content, .. } if !url.starts_with('#') && !style.link => {
+                    let id = self.link_id(url);
+                    let runs = self.runs(content, RunStyle { link: true, ..style });
+                    out.push_str(&format!("<w:hyperlink r:id=\"{}\">{}</w:hyperlink>", id, runs));
+                }
+                Inline::Link { content, .. } => out.push_str(&self.runs(content, style)),
+                // Images are not embedded; their alt text stands in
+                Inline::Image { alt, .. } if !alt.is_empty() => out.push_str(&run(&format!("[{}]", alt), style)),
+                Inline::Image { .. } | Inline::Html { .. } => {}
+                Inline::WikiLink { target, alias, .. } => out.push_str(&run(alias.as_deref().unwrap_or(target), style)),
+                Inline::FootnoteRef { label } => {
+                    if let Some(n) = self.footnotes.number(label) {
+                        out.push_str(&run(&n.to_string(), RunStyle { superscript: true, ..style }));
+                    }
+                }
+                Inline::SoftBreak => out.push_str(&run(" ", style)),
+                Inline::LineBreak => out.push_str("<w:r><w:br/></w:r>"),
+            }
+        }
+        out
+    }
+
+    /// `properties` are borders and other paragraph properties beyond the
+    /// context's style, numbering and indentation
+    fn paragraph(&mut self, ctx: Context, properties: &str, runs: &str) {
+        let mut props = String::new();
+        if let Some(style) = ctx.style {
+            props.push_str(&format!("<w:pStyle w:val=\"{}\"/>", style));
+        }
+        // WordprocessingML wants paragraph properties in schema order:
+        // style, numbering, borders, then indentation
+        if let Some((num, level)) = ctx.numbering {
+            props.push_str(&format!("<w:numPr><w:ilvl w:val=\"{}\"/><w:numId w:val=\"{}\"/></w:numPr>", level, num));
+        }
+        props.push_str(properties);
+        if let (None, Some(indent)) = (ctx.numbering, ctx.indent) {
+            props.push_str(&format!("<w:ind w:left=\"{}\"/>", indent));
+        }
+        let props = if props.is_empty() { props } else { format!("<w:pPr>{}</w:pPr>", props) };
+        let prefix = std::mem::take(&mut self.prefix);
+        self.body.push_str(&format!("<w:p>{}{}{}</w:p>", props, prefix, runs));
+    }
+
+    fn list(&mut self, ordered: bool, start: u64, items: &[ListItem], level: usize, ctx: Context) {
+        let num = if ordered {
+            self.ordered_lists.push(start);
+            BULLET_NUM_ID + self.ordered_lists.len()
+        } else {
+            BULLET_NUM_ID
+        };
+        let level = level.min(LIST_LEVELS - 1);
+        for item in items {
+            let task = match item.checked {
+                Some(true) => "☒ ",
+                Some(false) => "☐ ",
+                None => "",
+            };
+            let inner = Context { style: Some(ctx.style.unwrap_or("ListParagraph")), numbering: None, indent: Some(720 * (level + 1)) };
+            for (n, block) in item.blocks.iter().enumerate() {
+                match block {
+                    Block::List { ordered, start, items } => self.list(*ordered, *start, items, level + 1, ctx),
+                    Block::Paragraph { content } if n == 0 => {
+                        let runs = format!("{}{}", if task.is_empty() { String::new() } else { run(task, RunStyle::default()) }, self.runs(content, RunStyle::default()));
+                        self.paragraph(Context { numbering: Some((num, level)), ..inner }, "", &runs);
+                    }
+                    _ => self.block(block, inner),
+                }
+            }
+            if item.blocks.is_empty() {
+                self.paragraph(Context { numbering: Some((num, level)), ..inner }, "", &run(task, RunStyle::default()));
+            }
+        }
+    }
+
+    fn table(&mut self, align: &[Align], header: &[Vec<Inline>], rows: &[Vec<Vec<Inline>>]) {
+        let columns = header.len().max(rows.iter().map(Vec::len).max().unwrap_or(0));
+        self.body.push_str("<w:tbl><w:tblPr><w:tblStyle w:val=\"TableGrid\"/><w:tblW w:w=\"0\" w:type=\"auto\"/></w:tblPr><w:tblGrid>");
+        self.body.push_str(&"<w:gridCol/>".repeat(columns));
+        self.body.push_str("</w:tblGrid>");
+        for (r, row) in std::iter::once(header).chain(rows.iter().map(Vec::as_slice)).enumerate() {
+            self.body.push_str(if r == 0 { "<w:tr><w:trPr><w:tblHeader/></w:trPr>" } else { "<w:tr>" });
+            for n in 0..columns {
+                let jc = match align.get(n) {
+                    Some(Align::Center) => "<w:jc w:val=\"center\"/>",
+                    Some(Align::Right) => "<w:jc w:val=\"right\"/>",
+                    _ => "",
+                };
+                let style = RunStyle { bold: r == 0, ..RunStyle::default() };
+                let runs = row.get(n).map(|cell| self.runs(cell, style)).unwrap_or_default();
+                let props = if jc.is_empty() { String::new() } else { format!("<w:pPr>{}</w:pPr>", jc) };
+                self.body.push_str(&format!("<w:tc><w:tcPr><w:tcW w:w=\"0\" w:type=\"auto\"/></w:tcPr><w:p>{}{}</w:p></w:tc>", props, runs));
+            }
+            self.body.push_str("</w:tr>");
+        }
+        self.body.push_str("</w:tbl>");
+        // Word expects a paragraph between a table and what follows it
+        self.body.push_str("<w:p/>");
+    }
+
+    fn block(&mut self, block: &Block, ctx: Context) {
+        match block {
+            Block::Heading { level, content } => {
+                let runs = self.runs(content, RunStyle::default());
+                self.paragraph(Context { style: Some(&format!("Heading{}", level.clamp(&1, &6))), ..ctx }, "", &runs);
+            }
+            Block::Paragraph { content } => {
+                let runs = self.runs(content, RunStyle::default());
+                self.paragraph(ctx, "", &runs);
+            }
+            Block::CodeBlock { code, .. } => {
+                for line in code.split('\n') {
+                    self.paragraph(Context { style: Some("Code"), ..ctx }, "", &run(line, RunStyle::default()));
+                }
+            }
+            Block::BlockQuote { blocks } => {
+                for block in blocks {
+                    self.block(block, Context { style: Some("Quote"), ..ctx });
+                }
+            }
+            Block::List { ordered, start, items } => self.list(*ordered, *start, items, 0, ctx),
+            Block::Table { align, header, rows } => self.table(align, header, rows),
+            Block::ThematicBreak => {
+                let border = "<w:pBdr><w:bottom w:val=\"single\" w:sz=\"6\" w:space=\"1\" w:color=\"auto\"/></w:pBdr>";
+                self.paragraph(ctx, border, "");
+            }
+            Block::Html { html } => {
+                for block in html_to_document(html).blocks {
+                    self.block(&block, ctx);
+                }
+            }
+            Block::MathBlock { tex } => self.paragraph(Context { style: Some("Code"), ..ctx }, "", &run(tex, RunStyle::default())),
+            // Listed at the end of the document
+            Block::FootnoteDefinition { .. } => {}
+        }
+    }
+
+    fn relationships(&self) -> String {
+        let mut rels = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+             <Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles\" Target=\"styles.xml\"/>\
+             <Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering\" Target=\"numbering.xml\"/>",
+        );
+        for (n, url) in self.links.iter().enumerate() {
+            rels.push_str(&format!(
+                "<Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink\" Target=\"{}\" TargetMode=\"External\"/>",
+                n + 3,
+                escape_xml(url)
+            ));
+        }
+        rels.push_str("</Relationships>");
+        rels
+    }
+
+    fn numbering(&self) -> String {
+        let levels = |ordered: bool| {
+            (0..LIST_LEVELS)
+                .map(|l| {
+                    let (format, text) = if ordered {
+                        ("decimal", format!("%{}.", l + 1))
+                    } else {
+                        ("bullet", BULLETS[l % BULLETS.len()].to_string())
+                    };
+                    format!(
+                        "<w:lvl w:ilvl=\"{}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{}\"/><w:lvlText w:val=\"{}\"/><w:lvlJc w:val=\"left\"/><w:pPr><w:ind w:left=\"{}\" w:hanging=\"360\"/></w:pPr></w:lvl>",
+                        l,
+                        format,
+                        text,
+                        720 * (l + 1)
+                    )
+                })
+                .collect::<String>()
+        };
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:numbering xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">\
+             <w:abstractNum w:abstractNumId=\"0\"><w:multiLevelType w:val=\"hybridMultilevel\"/>{}</w:abstractNum>\
+             <w:abstractNum w:abstractNumId=\"1\"><w:multiLevelType w:val=\"hybridMultilevel\"/>{}</w:abstractNum>\
+             <w:num w:numId=\"{}\"><w:abstractNumId w:val=\"0\"/></w:num>",
+            levels(false),
+            levels(true),
+            BULLET_NUM_ID
+        );
+        // Each ordered list counts from its own start
+        for (n, start) in self.ordered_lists.iter().enumerate() {
+            xml.push_str(&format!(
+                "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"1\"/><w:lvlOverride w:ilvl=\"0\"><w:startOverride w:val=\"{}\"/></w:lvlOverride></w:num>",
+                BULLET_NUM_ID + n + 1,
+                start
+            ));
+        }
+        xml.push_str("</w:numbering>");
+        xml
+    }
+}
+
+/// Export markdown as a Word document (.docx bytes). Headings, quotes,
+/// code and lists use Word's named styles and numbering, so they show up
+/// in the navigation pane and restyle like native content. Bold, italic,
+/// strikethrough, underline, inline code, links and tables are kept;
+/// footnotes are numbered and listed at the end. Images are not embedded:
+/// their alt text is written in brackets instead.
+#[wasm_bindgen]
+pub fn export_docx(content: &str) -> Vec<u8> {
+    write_docx(&parse_markdown(content))
+}
+
+/// Write a parsed document as .docx bytes
+pub(crate) fn write_docx(doc: &Document) -> Vec<u8> {
+    let footnotes = Footnotes::new(&doc.blocks);
+    let mut writer = Writer {
+        body: String::new(),
+        links: Vec::new(),
+        ordered_lists: Vec::new(),
+        footnotes: &footnotes,
+        prefix: String::new(),
+    };
+    for block in &doc.blocks {
+        writer.block(block, Context::default());
+    }
+
+    if !footnotes.is_empty() {
+        writer.block(&Block::ThematicBreak, Context::default());
+    }
+    for (n, _, body) in footnotes.iter() {
+        writer.prefix = run(&format!("{} ", n), RunStyle { superscript: true, ..RunStyle::default() });
+        for block in body {
+            writer.block(block, Context::default());
+        }
+        if !writer.prefix.is_empty() {
+            let prefix = std::mem::take(&mut writer.prefix);
+            writer.paragraph(Context::default(), "", &prefix);
+        }
+    }
+
+    let document = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:document xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\" \
+         xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\"><w:body>{}\
+         <w:sectPr><w:pgSz w:w=\"12240\" w:h=\"15840\"/><w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" \
+         w:header=\"720\" w:footer=\"720\" w:gutter=\"0\"/></w:sectPr></w:body></w:document>",
+        writer.body
+    );
+    let entry = |name: &str, data: String| ZipEntry { name: name.to_string(), data: data.into_bytes() };
+    write_zip(
+        &[
+            entry("[Content_Types].xml", CONTENT_TYPES.to_string()),
+            entry("_rels/.rels", PACKAGE_RELS.to_string()),
+            entry("word/document.xml", document),
+            entry("word/styles.xml", STYLES.to_string()),
+            entry("word/numbering.xml", writer.numbering()),
+            entry("word/_rels/document.xml.rels", writer.relationships()),
+        ],
+        &[],
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Reading
+
+/// Character formatting of a run that maps to a wrapping inline element
+#[derive(Default, Clone, PartialEq)]
+struct Marks {
+    link: Option<String>,
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    underline: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Mark {
+    Link,
+    Bold,
+    Italic,
+    Strike,
+    Underline,
+}
+
+const MARKS: [Mark; 5] = [Mark::Link, Mark::Bold, Mark::Italic, Mark::Strike, Mark::Underline];
+
+impl Mark {
+    fn on(self, marks: &Marks) -> bool {
+        match self {
+            Mark::Link => marks.link.is_some(),
+            Mark::Bold => marks.bold,
+            Mark::Italic => marks.italic,
+            Mark::Strike => marks.strike,
+            Mark::Underline => marks.underline,
+        }
+    }
+
+    /// Whether `other` continues the same element as `marks`
+    fn continues(self, marks: &Marks, other: &Marks) -> bool {
+        match self {
+            Mark::Link => other.link.is_some() && other.link == marks.link,
+            _ => self.on(other),
+        }
+    }
+
+    fn without(self, marks: &Marks) -> Marks {
+        let mut marks = marks.clone();
+        match self {
+            Mark::Link => marks.link = None,
+            Mark::Bold => marks.bold = false,
+            Mark::Italic => marks.italic = false,
+            Mark::Strike => marks.strike = false,
+            Mark::Underline => marks.underline = false,
+        }
+        marks
+    }
+
+    fn wrap(self, marks: &Marks, content: Vec<Inline>) -> Inline {
+        match self {
+            Mark::Link => Inline::Link { url: marks.link.clone().unwrap_or_default(), title: None, content },
+            Mark::Bold => Inline::Strong { content },
+            Mark::Italic => Inline::Emphasis { content },
+            Mark::Strike => Inline::Strikethrough { content },
+            Mark::Underline => Inline::Underline { content },
+        }
+    }
+}
+
+/// Run content that is not itself a wrapping element
+#[derive(Clone)]
+enum Leaf {
+    Text(String),
+    Code(String),
+    Break,
+    Footnote(String),
+}
+
+fn child<'a>(node: &'a Node, name: &str) -> Option<&'a Node> {
+    node.children().iter().find(|c| c.name() == name)
+}
+
+/// `w:val` of the child property `name`
+fn val<'a>(node: &'a Node, name: &str) -> Option<&'a str> {
+    child(node, name)?.attr("w:val")
+}
+
+/// Whether the toggle property `name` (`<w:b/>`, `<w:i w:val="0"/>`) is on
+fn toggle(props: &Node, name: &str) -> bool {
+    child(props, name).is_some_and(|p| !matches!(p.attr("w:val"), Some("0" | "false" | "none")))
+}
+
+fn descendants<'a>(nodes: &'a [Node], name: &str, out: &mut Vec<&'a Node>) {
+    for node in nodes {
+        if node.name() == name {
+            out.push(node);
+        } else {
+            descendants(node.children(), name, out);
+        }
+    }
+}
+
+fn collect_runs(node: &Node, marks: &Marks, rels: &HashMap<String, String>, out: &mut Vec<(Marks, Leaf)>) {
+    for child_node in node.children() {
+        match child_node.name() {
+            "r" => {
+                let props = child(child_node, "rpr");
+                let has = |name: &str| props.is_some_and(|p| toggle(p, name));
+                let run_marks = Marks {
+                    bold: has("b"),
+                    italic: has("i"),
+                    strike: has("strike"),
+                    underline: has("u"),
+                    ..marks.clone()
+                };
+                let code = props.and_then(|p| val(p, "rstyle")) == Some("CodeChar");
+                let superscript = props.and_then(|p| val(p, "vertalign")) == Some("superscript");
+                for part in child_node.children() {
+                    let leaf = match part.name() {
+                        "t" => {
+                            let text = part.raw_text();
+                            let label = text.trim();
+                            if superscript && !label.is_empty() && label.chars().all(|c| c.is_ascii_digit()) {
+                                Leaf::Footnote(label.to_string())
+                            } else if code {
+                                Leaf::Code(text)
+                            } else {
+                                Leaf::Text(text)
+                            }
+                        }
+                        "tab" => Leaf::Text("\t".to_string()),
+                        "br" => Leaf::Break,
+                        _ => continue,
+                    };
+                    out.push((run_marks.clone(), leaf));
+                }
+            }
+            "hyperlink" => {
+                let link = child_node.attr("r:id").and_then(|id| rels.get(id)).cloned();
+                collect_runs(child_node, &Marks { link: link.or_else(|| marks.link.clone()), ..marks.clone() }, rels, out);
+            }
+            "ppr" | "rpr" => {}
+            _ => collect_runs(child_node, marks, rels, out),
+        }
+    }
+}
+
+/// Rebuild nested inline elements from flat runs: the mark that spans the
+/// most runs from the current one becomes the outermost element, so
+/// `bold ` + `it` (bold and italic) reads back as `**bold *it***`
+fn nest(runs: &[(Marks, Leaf)]) -> Vec<Inline> {
+    let mut out: Vec<Inline> = Vec::new();
+    let mut i = 0;
+    while i < runs.len() {
+        let marks = &runs[i].0;
+        let span = |mark: Mark| runs[i..].iter().take_while(|(other, _)| mark.continues(marks, other)).count();
+        // `max_by_key` keeps the last maximum, so walk the marks in reverse
+        // to prefer the earlier one on ties
+        let widest = MARKS.iter().rev().copied().filter(|m| m.on(marks)).max_by_key(|&m| span(m));
+        match widest {
+            Some(mark) => {
+                let len = span(mark);
+                let inner: Vec<(Marks, Leaf)> = runs[i..i + len].iter().map(|(m, leaf)| (mark.without(m), leaf.clone())).collect();
+                out.push(mark.wrap(marks, nest(&inner)));
+                i += len;
+            }
+            None => {
+                match (&runs[i].1, out.last_mut()) {
+                    (Leaf::Text(text), Some(Inline::Text { text: prev })) => prev.push_str(text),
+                    (Leaf::Text(text), _) => out.push(Inline::Text { text: text.clone() }),
+                    (Leaf::Code(code), _) => out.push(Inline::Code { code: code.clone() }),
+                    (Leaf::Break, _) => out.push(Inline::LineBreak),
+                    (Leaf::Footnote(label), _) => out.push(Inline::FootnoteRef { label: label.clone() }),
+                }
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn node_inlines(node: &Node, rels: &HashMap<String, String>, plain: bool) -> Vec<Inline> {
+    let mut runs = Vec::new();
+    collect_runs(node, &Marks::default(), rels, &mut runs);
+    if plain {
+        // Header cells are bold because they are header cells
+        for (marks, _) in &mut runs {
+            marks.bold = false;
+        }
+    }
+    nest(&runs)
+}
+
+/// A body paragraph with the properties the reader maps to blocks
+struct Paragraph {
+    style: String,
+    /// Numbering instance and level
+    numbering: Option<(String, usize)>,
+    /// Left indentation in twentieths of a point
+    indent: usize,
+    /// A bottom border: a thematic break when the paragraph is empty
+    rule: bool,
+    content: Vec<Inline>,
+}
+
+enum Part {
+    Paragraph(Paragraph),
+    Table(Block),
+}
+
+fn paragraph(p: &Node, rels: &HashMap<String, String>) -> Paragraph {
+    let props = child(p, "ppr");
+    let numbering = props.and_then(|pr| child(pr, "numpr")).and_then(|n| {
+        let level = val(n, "ilvl").and_then(|l| l.parse().ok()).unwrap_or(0);
+        val(n, "numid").map(|id| (id.to_string(), level))
+    });
+    Paragraph {
+        style: props.and_then(|pr| val(pr, "pstyle")).unwrap_or_default().to_string(),
+        numbering,
+        indent: props.and_then(|pr| child(pr, "ind")).and_then(|ind| ind.attr("w:left")).and_then(|l| l.parse().ok()).unwrap_or(0),
+        rule: props.and_then(|pr| child(pr, "pbdr")).is_some_and(|b| child(b, "bottom").is_some()),
+        content: node_inlines(p, rels, false),
+    }
+}
+
+fn table(tbl: &Node, rels: &HashMap<String, String>) -> Block {
+    let rows: Vec<&Node> = tbl.children().iter().filter(|c| c.name() == "tr").collect();
+    fn cells(row: &Node) -> Vec<&Node> {
+        row.children().iter().filter(|c| c.name() == "tc").collect()
+    }
+    let cell_inlines = |cell: &Node, header: bool| {
+        let mut content = Vec::new();
+        for p in cell.children().iter().filter(|c| c.name() == "p") {
+            if !content.is_empty() {
+                content.push(Inline::LineBreak);
+            }
+            content.extend(node_inlines(p, rels, header));
+        }
+        content
+    };
+    let Some((first, rest)) = rows.split_first() else {
+        return Block::Table { align: Vec::new(), header: Vec::new(), rows: Vec::new() };
+    };
+    let align = cells(first)
+        .iter()
+        .map(|cell| {
+            let jc = child(cell, "p").and_then(|p| child(p, "ppr")).and_then(|pr| val(pr, "jc"));
+            match jc {
+                Some("center") => Align::Center,
+                Some("right" | "end") => Align::Right,
+                _ => Align::None,
+            }
+        })
+        .collect();
+    Block::Table {
+        align,
+        header: cells(first).iter().map(|c| cell_inlines(c, true)).collect(),
+        rows: rest.iter().map(|row| cells(row).iter().map(|c| cell_inlines(c, false)).collect()).collect(),
+    }
+}
+
+/// Whether each numbering instance is an ordered list, and where it starts
+fn list_kinds(numbering: &[Node]) -> HashMap<String, (bool, u64)> {
+    let mut abstracts = HashMap::new();
+    let mut nodes = Vec::new();
+    descendants(numbering, "abstractnum", &mut nodes);
+    for node in nodes {
+        let first = node.children().iter().find(|l| l.name() == "lvl" && l.attr("w:ilvl") == Some("0"));
+        let ordered = first.and_then(|l| val(l, "numfmt")).is_some_and(|f| f != "bullet" && f != "none");
+        let start = first.and_then(|l| val(l, "start")).and_then(|s| s.parse().ok()).unwrap_or(1);
+        if let Some(id) = node.attr("w:abstractnumid") {
+            abstracts.insert(id.to_string(), (ordered, start));
+        }
+    }
+    let mut kinds = HashMap::new();
+    let mut nodes = Vec::new();
+    descendants(numbering, "num", &mut nodes);
+    for node in nodes {
+        let Some(&(ordered, start)) = val(node, "abstractnumid").and_then(|id| abstracts.get(id)) else {
+            continue;
+        };
+        let start = node
+            .children()
+            .iter()
+            .find(|o| o.name() == "lvloverride" && o.attr("w:ilvl") == Some("0"))
+            .and_then(|o| val(o, "startoverride"))
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(start);
+        if let Some(id) = node.attr("w:numid") {
+            kinds.insert(id.to_string(), (ordered, start));
+        }
+    }
+    kinds
+}
+
+struct Reader<'a> {
+    parts: &'a [Part],
+    lists: &'a HashMap<String, (bool, u64)>,
+}
+
+impl Reader<'_> {
+    fn blocks(&self, range: std::ops::Range<usize>) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut i = range.start;
+        while i < range.end {
+            let p = match &self.parts[i] {
+                Part::Table(table) => {
+                    blocks.push(table.clone());
+                    i += 1;
+                    continue;
+                }
+                Part::Paragraph(p) => p,
+            };
+            if let Some((_, level)) = p.numbering {
+                blocks.push(self.list(&mut i, range.end, level));
+                continue;
+            }
+            // Consecutive paragraphs in the same code or quote style form one block
+            let run_end = |style: &str| {
+                (i..range.end)
+                    .find(|&j| !matches!(&self.parts[j], Part::Paragraph(q) if q.style == style && q.numbering.is_none()))
+                    .unwrap_or(range.end)
+            };
+            match p.style.as_str() {
+                "Code" => {
+                    let end = run_end("Code");
+                    let code = self.parts[i..end]
+                        .iter()
+                        .map(|part| match part {
+                            Part::Paragraph(q) => inline_text(&q.content),
+                            Part::Table(_) => String::new(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    blocks.push(Block::CodeBlock { language: String::new(), code });
+                    i = end;
+                }
+                "Quote" => {
+                    let end = run_end("Quote");
+                    let content = self.parts[i..end]
+                        .iter()
+                        .filter_map(|part| match part {
+                            Part::Paragraph(q) if !q.content.is_empty() => Some(Block::Paragraph { content: q.content.clone() }),
+                            _ => None,
+                        })
+                        .collect();
+                    blocks.push(Block::BlockQuote { blocks: content });
+                    i = end;
+                }
+                style => {
+                    let heading = style.strip_prefix("Heading").and_then(|n| n.parse::<u8>().ok()).filter(|n| (1..=6).contains(n));
+                    match heading {
+                        Some(level) => blocks.push(Block::Heading { level, content: p.content.clone() }),
+                        None if p.content.is_empty() => {
+                            if p.rule {
+                                blocks.push(Block::ThematicBreak);
+                            }
+                        }
+                        None => blocks.push(Block::Paragraph { content: p.content.clone() }),
+                    }
+                    i += 1;
+                }
+            }
+        }
+        blocks
+    }
+
+    /// A list starting at paragraph `i`: items at `level` of its numbering
+    /// instance, deeper levels as nested lists, and indented paragraphs
+    /// without numbering as further blocks of the item before them
+    fn list(&self, i: &mut usize, end: usize, level: usize) -> Block {
+        let num = match &self.parts[*i] {
+            Part::Paragraph(Paragraph { numbering: Some((num, _)), .. }) => num.clone(),
+            _ => String::new(),
+        };
+        let (ordered, start) = self.lists.get(&num).copied().unwrap_or((false, 1));
+        let mut items: Vec<ListItem> = Vec::new();
+        while *i < end {
+            let Part::Paragraph(p) = &self.parts[*i] else { break };
+            match &p.numbering {
+                Some((_, lvl)) if *lvl > level => {
+                    let nested = self.list(i, end, *lvl);
+                    if items.is_empty() {
+                        items.push(ListItem { checked: None, blocks: Vec::new() });
+                    }
+                    items.last_mut().unwrap().blocks.push(nested);
+                }
+                Some((id, lvl)) if *lvl == level && *id == num => {
+                    let mut content = p.content.clone();
+                    let mut checked = None;
+                    if let Some(Inline::Text { text }) = content.first_mut() {
+                        for (prefix, state) in [("☒ ", true), ("☐ ", false)] {
+                            if let Some(rest) = text.strip_prefix(prefix) {
+                                *text = rest.to_string();
+                                checked = Some(state);
+                            }
+                        }
+                        if text.is_empty() {
+                            content.remove(0);
+                        }
+                    }
+                    let blocks = if content.is_empty() { Vec::new() } else { vec![Block::Paragraph { content }] };
+                    items.push(ListItem { checked, blocks });
+                    *i += 1;
+                }
+                None if p.indent >= 720 * (level + 1) && !items.is_empty() => {
+                    let start = *i;
+                    while *i < end && matches!(&self.parts[*i], Part::Paragraph(q) if q.numbering.is_none() && q.indent >= 720 * (level + 1)) {
+                        *i += 1;
+                    }
+                    items.last_mut().unwrap().blocks.extend(self.blocks(start..*i));
+                }
+                _ => break,
+            }
+        }
+        Block::List { ordered, start, items }
+    }
+}
+
+/// Turn the paragraphs after the last thematic break back into footnote
+/// definitions when each group starts with a footnote number
+fn restore_footnotes(blocks: &mut Vec<Block>) {
+    let Some(rule) = blocks.iter().rposition(|b| matches!(b, Block::ThematicBreak)) else {
+        return;
+    };
+    let starts_with_note = |b: &Block| matches!(b, Block::Paragraph { content } if matches!(content.first(), Some(Inline::FootnoteRef { .. })));
+    if !blocks.get(rule + 1).is_some_and(starts_with_note) {
+        return;
+    }
+    let mut definitions: Vec<Block> = Vec::new();
+    for block in blocks.drain(rule..).skip(1) {
+        match block {
+            Block::Paragraph { mut content } if matches!(content.first(), Some(Inline::FootnoteRef { .. })) => {
+                let Inline::FootnoteRef { label } = content.remove(0) else { unreachable!() };
+                if let Some(Inline::Text { text }) = content.first_mut() {
+                    *text = text.trim_start().to_string();
+                }
+                content.retain(|i| !matches!(i, Inline::Text { text } if text.is_empty()));
+                let blocks = if content.is_empty() { Vec::new() } else { vec![Block::Paragraph { content }] };
+                definitions.push(Block::FootnoteDefinition { label, blocks });
+            }
+            block => {
+                if let Some(Block::FootnoteDefinition { blocks, .. }) = definitions.last_mut() {
+                    blocks.push(block);
+                }
+            }
+        }
+    }
+    blocks.extend(definitions);
+}
+
+/// Read a .docx package of the shape `write_docx` produces (named heading,
+/// quote, code and list styles, numbering, hyperlinks, tables and the
+/// trailing footnote list) back into a document tree
+pub(crate) fn read_docx(bytes: &[u8]) -> Result<Document, String> {
+    let entries = read_zip(bytes)?;
+    let part = |name: &str| -> Result<Option<Vec<Node>>, String> {
+        match entries.iter().find(|e| e.name == name) {
+            Some(entry) => {
+                let xml = std::str::from_utf8(&entry.data).map_err(|_| format!("{} is not UTF-8", name))?;
+                Ok(Some(parse_html(xml)))
+            }
+            None => Ok(None),
+        }
+    };
+    let document = part("word/document.xml")?.ok_or_else(|| "Not a Word document: word/document.xml is missing".to_string())?;
+
+    let mut rels = HashMap::new();
+    let mut nodes = Vec::new();
+    let rel_nodes = part("word/_rels/document.xml.rels")?.unwrap_or_default();
+    descendants(&rel_nodes, "relationship", &mut nodes);
+    for node in nodes {
+        if let (Some(id), Some(target)) = (node.attr("id"), node.attr("target")) {
+            rels.insert(id.to_string(), target.to_string());
+        }
+    }
+    let lists = list_kinds(&part("word/numbering.xml")?.unwrap_or_default());
+
+    let mut bodies = Vec::new();
+    descendants(&document, "body", &mut bodies);
+    let parts: Vec<Part> = bodies
+        .first()
+        .map(|body| body.children())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|node| match node.name() {
+            "p" => Some(Part::Paragraph(paragraph(node, &rels))),
+            "tbl" => Some(Part::Table(table(node, &rels))),
+            _ => None,
+        })
+        .collect();
+
+    let mut blocks = Reader { parts: &parts, lists: &lists }.blocks(0..parts.len());
+    restore_footnotes(&mut blocks);
+    Ok(Document { front_matter: None, lines: vec![0; blocks.len()], blocks })
+}
@@ -0,0 +1,159 @@
+// The edit log: document_edit messages concatenated as sent (a CBOR
+// sequence), possibly starting with a checkpoint from compaction, read
+// back into edits that can be replayed to rebuild the document.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::{data_f64, data_text, data_u64, decode_with_grid_tag, PromiseGridMessage};
+
+/// Edit type of a checkpoint: the whole document as of the edits it
+/// replaced
+pub(crate) const CHECKPOINT: &str = "checkpoint";
+
+/// One document_edit message. Offsets are in bytes, like the rest of the
+/// API.
+pub(crate) struct Edit {
+    pub edit_type: String,
+    pub position: usize,
+    pub content: String,
+    /// Bytes replaced, when the message says
+    pub length: Option<usize>,
+    pub user_id: String,
+    pub timestamp: f64,
+}
+
+pub(crate) fn floor_boundary(text: &str, mut pos: usize) -> usize {
+    pos = pos.min(text.len());
+    while !text.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    pos
+}
+
+impl Edit {
+    /// The byte range of `text` this edit replaces and what it writes
+    /// there. An `insert` adds `content` at `position`; a `delete` removes
+    /// `length` bytes, or as many as `content` holds when it carries the
+    /// deleted text; a `checkpoint` replaces the whole text; any other type
+    /// (`replace`, `format`, ...) replaces `length` bytes (none when
+    /// missing) with `content`.
+    pub fn span(&self, text: &str) -> (Range<usize>, &str) {
+        if self.edit_type == CHECKPOINT {
+            return (0..text.len(), &self.content);
+        }
+        let start = floor_boundary(text, self.position);
+        let (removed, _) = self.extent();
+        let inserted = if self.edit_type == "delete" { "" } else { self.content.as_str() };
+        (start..floor_boundary(text, start + removed), inserted)
+    }
+
+    /// Bytes the edit removes and inserts at `position`, as `span` reads
+    /// them, for following an offset through it without the text
+    pub fn extent(&self) -> (usize, usize) {
+        match self.edit_type.as_str() {
+            "insert" => (0, self.content.len()),
+            "delete" => (self.length.unwrap_or(self.content.len()), 0),
+            _ => (self.length.unwrap_or(0), self.content.len()),
+        }
+    }
+
+    /// Apply the edit to `text`
+    pub fn apply(&self, text: &mut String) {
+        let (range, inserted) = self.span(text);
+        text.replace_range(range, inserted);
+    }
+}
+
+/// Split a log of concatenated messages into each message's bytes and
+/// decoded form
+pub(crate) fn split_messages(edit_log: &[u8]) -> Result<Vec<(&[u8], PromiseGridMessage)>, String> {
+    let mut messages = Vec::new();
+    let mut rest = edit_log;
+    let mut start = 0;
+    while !rest.is_empty() {
+        // Reading from the slice advances it past exactly one item
+        ciborium::from_reader::<serde::de::IgnoredAny, _>(&mut rest)
+            .map_err(|e| format!("CBOR parsing error at byte {}: {}", start, crate::codec::cbor_error(e)))?;
+        let end = edit_log.len() - rest.len();
+        let bytes = &edit_log[start..end];
+        let message = decode_with_grid_tag(bytes).map_err(|e| format!("CBOR parsing error at byte {}: {}", start, e))?;
+        messages.push((bytes, message));
+        start = end;
+    }
+    Ok(messages)
+}
+
+/// Edit from a data map, for the fields the message doesn't override
+fn edit_from(edit_type: &str, data: &HashMap<String, ciborium::Value>, user_id: &str, timestamp: f64) -> Edit {
+    Edit {
+        edit_type: edit_type.to_string(),
+        position: data_u64(data, "position").unwrap_or(0) as usize,
+        content: data_text(data, "content").unwrap_or("").to_string(),
+        length: data_u64(data, "length").map(|n| n as usize),
+        user_id: data_text(data, "user_id").unwrap_or(user_id).to_string(),
+        timestamp: data_f64(data, "timestamp").unwrap_or(timestamp),
+    }
+}
+
+/// The edits a message makes: a `document_edit`, each edit of an
+/// `edit_batch` or each `document_edit` of a `batch` in order, or a
+/// `document_checkpoint` as a `checkpoint` edit replacing the whole text
+pub(crate) fn message_edits(message: &PromiseGridMessage) -> Vec<Edit> {
+    let data = &message.payload.data;
+    match message.payload.message_type.as_str() {
+        "document_edit" => vec![edit_from(data_text(data, "edit_type").unwrap_or("insert"), data, "unknown", 0.0)],
+        "document_checkpoint" => vec![edit_from(CHECKPOINT, data, "unknown", 0.0)],
+        "edit_batch" => {
+            let user_id = data_text(data, "user_id").unwrap_or("unknown");
+            let timestamp = data_f64(data, "timestamp").unwrap_or(0.0);
+            let Some(ciborium::Value::Array(edits)) = data.get("edits") else {
+                return Vec::new();
+            };
+            edits
+                .iter()
+                .filter_map(|edit| match edit {
+                    ciborium::Value::Map(fields) => Some(cbor_text_map(fields)),
+                    _ => None,
+                })
+                .map(|fields| edit_from(data_text(&fields, "edit_type").unwrap_or("insert"), &fields, user_id, timestamp))
+                .collect()
+        }
+        "batch" => {
+            let user_id = data_text(data, "user_id").unwrap_or("unknown");
+            let timestamp = data_f64(data, "timestamp").unwrap_or(0.0);
+            let Some(ciborium::Value::Array(operations)) = data.get("operations") else {
+                return Vec::new();
+            };
+            operations
+                .iter()
+                .filter_map(|operation| match operation {
+                    ciborium::Value::Map(fields) => Some(cbor_text_map(fields)),
+                    _ => None,
+                })
+                .filter(|op| data_text(op, "message_type") == Some("document_edit"))
+                .filter_map(|op| match op.get("data") {
+                    Some(ciborium::Value::Map(fields)) => Some(cbor_text_map(fields)),
+                    _ => None,
+                })
+                .map(|fields| edit_from(data_text(&fields, "edit_type").unwrap_or("insert"), &fields, user_id, timestamp))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The text-keyed entries of a CBOR map, in the shape payload data uses
+pub(crate) fn cbor_text_map(map: &[(ciborium::Value, ciborium::Value)]) -> HashMap<String, ciborium::Value> {
+    map.iter()
+        .filter_map(|(key, value)| match key {
+            ciborium::Value::Text(key) => Some((key.clone(), value.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The edits of a log, skipping other message types
+pub(crate) fn read_edit_log(edit_log: &[u8]) -> Result<Vec<Edit>, String> {
+    Ok(split_messages(edit_log)?.iter().flat_map(|(_, message)| message_edits(message)).collect())
+}
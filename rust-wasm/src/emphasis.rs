@@ -0,0 +1,464 @@
+// Emphasis delimiters (`*`, `_`, `~~`) matched the way CommonMark does it:
+// split a line into delimiter runs, decide from the surrounding characters
+// whether each run can open or close, then pair closers with the nearest
+// compatible opener. Used by the formatter to tidy `** bold **` without
+// touching nested emphasis, bullets or arithmetic, and by the bold, italic
+// and strikethrough toggles.
+
+use wasm_bindgen::prelude::*;
+
+/// A run of one delimiter character, e.g. the `***` closing `**a *b***`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DelimiterRun {
+    /// Byte offset in the line
+    pub start: usize,
+    pub len: usize,
+    pub ch: char,
+    /// Opens or closes by the CommonMark flanking rules
+    tight_open: bool,
+    tight_close: bool,
+    /// Would open or close if the spaces inside it were removed, as in the
+    /// `** ` of `** bold **`
+    loose_open: bool,
+    loose_close: bool,
+}
+
+impl DelimiterRun {
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    fn can_open(&self) -> bool {
+        self.tight_open || self.loose_open
+    }
+
+    fn can_close(&self) -> bool {
+        self.tight_close || self.loose_close
+    }
+}
+
+/// A matched pair of delimiters: `width` chars at `open` and at `close`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EmphasisSpan {
+    pub ch: char,
+    /// 1 for emphasis, 2 for strong (or strikethrough with `~`)
+    pub width: usize,
+    /// Byte offsets of the opening and closing delimiters
+    pub open: usize,
+    pub close: usize,
+    /// Indices into the run list
+    pub open_run: usize,
+    pub close_run: usize,
+}
+
+fn is_punctuation(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
+
+/// Whether a line is a thematic break like `***` or `* * *`
+fn is_thematic_break(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && ["*", "-", "_"].iter().any(|m| marks.chars().all(|c| c.to_string() == *m))
+}
+
+/// Delimiter runs of `line`, skipping backslash escapes, intraword `_` and
+/// a leading `*` list bullet
+pub(crate) fn delimiter_runs(line: &str) -> Vec<DelimiterRun> {
+    if is_thematic_break(line) {
+        return Vec::new();
+    }
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, ch) = chars[i];
+        if ch == '\\' {
+            i += 2;
+            continue;
+        }
+        if !matches!(ch, '*' | '_' | '~') {
+            i += 1;
+            continue;
+        }
+        let mut j = i;
+        while j < chars.len() && chars[j].1 == ch {
+            j += 1;
+        }
+        let len = j - i;
+        let end = chars.get(j).map_or(line.len(), |&(o, _)| o);
+        i = j;
+
+        let prev = line[..start].chars().next_back();
+        let next = line[end..].chars().next();
+        let ws = |c: Option<char>| c.is_none_or(char::is_whitespace);
+        let punct = |c: Option<char>| c.is_some_and(is_punctuation);
+
+        // `~` only counts doubled, as in `~~strike~~`
+        if ch == '~' && len != 2 {
+            continue;
+        }
+        // A bullet, not emphasis
+        if ch != '_' && len == 1 && line[..start].trim().is_empty() && next == Some(' ') {
+            continue;
+        }
+
+        let left = !ws(next) && (!punct(next) || ws(prev) || punct(prev));
+        let right = !ws(prev) && (!punct(prev) || ws(next) || punct(next));
+        let (tight_open, tight_close) = if ch == '_' {
+            // snake_case and 1_000 are not emphasis
+            (left && (!right || punct(prev)), right && (!left || punct(next)))
+        } else {
+            (left, right)
+        };
+
+        // Spaces just inside the delimiter with a word beyond them
+        let text_after = !line[end..].trim().is_empty();
+        let text_before = !line[..start].trim().is_empty();
+        let loose_open = next == Some(' ') && text_after && (ws(prev) || punct(prev));
+        let loose_close = prev == Some(' ') && text_before && (ws(next) || punct(next));
+
+        runs.push(DelimiterRun { start, len, ch, tight_open, tight_close, loose_open, loose_close });
+    }
+    runs
+}
+
+/// Pair up delimiter runs into emphasis spans, innermost first
+pub(crate) fn match_runs(runs: &[DelimiterRun]) -> Vec<EmphasisSpan> {
+    let mut remaining: Vec<usize> = runs.iter().map(|r| r.len).collect();
+    // Delimiters used so far from the front of each closer
+    let mut closed: Vec<usize> = vec![0; runs.len()];
+    let mut openers: Vec<usize> = Vec::new();
+    let mut spans = Vec::new();
+
+    for (i, closer) in runs.iter().enumerate() {
+        if closer.can_close() {
+            while remaining[i] > 0 {
+                let found = openers.iter().rposition(|&j| {
+                    let opener = &runs[j];
+                    if opener.ch != closer.ch || remaining[j] == 0 {
+                        return false;
+                    }
+                    // Two loose single stars are more likely `2 * 3 * 4`
+                    if !opener.tight_open && !closer.tight_close && opener.len.min(closer.len) < 2 {
+                        return false;
+                    }
+                    // CommonMark's rule of three for runs that both open and close
+                    let both = (opener.can_open() && opener.can_close()) || (closer.can_open() && closer.can_close());
+                    !(both
+                        && (opener.len + closer.len).is_multiple_of(3)
+                        && !(opener.len.is_multiple_of(3) && closer.len.is_multiple_of(3)))
+                });
+                let Some(position) = found else { break };
+                let j = openers[position];
+                if closer.ch == '~' && (remaining[i] < 2 || remaining[j] < 2) {
+                    break;
+                }
+                let width = if remaining[i] >= 2 && remaining[j] >= 2 { 2 } else { 1 };
+                remaining[j] -= width;
+                spans.push(EmphasisSpan {
+                    ch: closer.ch,
+                    width,
+                    open: runs[j].start + remaining[j],
+                    close: closer.start + closed[i],
+                    open_run: j,
+                    close_run: i,
+                });
+                remaining[i] -= width;
+                closed[i] += width;
+                // Openers between the pair can no longer be matched
+                openers.truncate(if remaining[j] > 0 { position + 1 } else { position });
+            }
+        }
+        if remaining[i] > 0 && closer.can_open() {
+            openers.push(i);
+        }
+    }
+    spans
+}
+
+/// Remove the stray spaces inside matched delimiters on each line:
+/// `** bold **` -> `**bold**`, `*it *` -> `*it*`. Already tidy text is
+/// returned unchanged, so the pass is idempotent.
+pub(crate) fn fix_emphasis(text: &str) -> String {
+    text.split('\n').map(fix_emphasis_until_stable).collect::<Vec<_>>().join("\n")
+}
+
+/// Dropping spaces can join runs into new pairs (`** ** a` -> `**** a`),
+/// so repeat until nothing changes
+fn fix_emphasis_until_stable(line: &str) -> String {
+    let mut line = fix_emphasis_line(line);
+    for _ in 0..3 {
+        let next = fix_emphasis_line(&line);
+        if next == line {
+            break;
+        }
+        line = next;
+    }
+    line
+}
+
+fn fix_emphasis_line(line: &str) -> String {
+    let runs = delimiter_runs(line);
+    let spans = match_runs(&runs);
+    // Byte ranges of spaces to drop
+    let mut drops: Vec<(usize, usize)> = Vec::new();
+    for span in &spans {
+        let (opener, closer) = (&runs[span.open_run], &runs[span.close_run]);
+        if !opener.tight_open && opener.loose_open {
+            let spaces = line[opener.end()..].len() - line[opener.end()..].trim_start_matches(' ').len();
+            drops.push((opener.end(), opener.end() + spaces));
+        }
+        if !closer.tight_close && closer.loose_close {
+            let spaces = line[..closer.start].len() - line[..closer.start].trim_end_matches(' ').len();
+            drops.push((closer.start - spaces, closer.start));
+        }
+    }
+    if drops.is_empty() {
+        return line.to_string();
+    }
+    drops.sort();
+    drops.dedup();
+    let mut out = String::with_capacity(line.len());
+    let mut last = 0;
+    for (start, end) in drops {
+        if start >= last {
+            out.push_str(&line[last..start]);
+            last = end;
+        }
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+/// An inline style the toggles add or remove
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum InlineStyle {
+    Bold,
+    Italic,
+    Strikethrough,
+}
+
+impl InlineStyle {
+    fn marker(self) -> &'static str {
+        match self {
+            InlineStyle::Bold => "**",
+            InlineStyle::Italic => "*",
+            InlineStyle::Strikethrough => "~~",
+        }
+    }
+
+    fn is(self, span: &EmphasisSpan) -> bool {
+        match self {
+            InlineStyle::Bold => span.width == 2 && span.ch != '~',
+            InlineStyle::Italic => span.width == 1 && span.ch != '~',
+            InlineStyle::Strikethrough => span.ch == '~',
+        }
+    }
+}
+
+/// A content and selection after a toggle
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Toggled {
+    pub content: String,
+    pub selection_start: usize,
+    pub selection_end: usize,
+}
+
+/// Spans of `style` in `line`
+fn styled_spans(line: &str, style: InlineStyle) -> (Vec<DelimiterRun>, Vec<EmphasisSpan>) {
+    let runs = delimiter_runs(line);
+    let spans = match_runs(&runs).into_iter().filter(|s| style.is(s)).collect();
+    (runs, spans)
+}
+
+/// Shrink `start..end` past whitespace and delimiter characters
+fn core_range(line: &str, start: usize, end: usize) -> (usize, usize) {
+    let text = &line[start..end];
+    let trimmed = text.trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '*' | '_' | '~'));
+    let core_start = start + text.len() - trimmed.len();
+    let trimmed = trimmed.trim_end_matches(|c: char| c.is_whitespace() || matches!(c, '*' | '_' | '~'));
+    (core_start, core_start + trimmed.len())
+}
+
+/// The span of `style` whose content contains `start..end`, if any
+fn covering(spans: &[EmphasisSpan], start: usize, end: usize) -> Option<&EmphasisSpan> {
+    spans.iter().find(|s| s.open + s.width <= start && end <= s.close)
+}
+
+/// Wrap `text` in `marker`, keeping surrounding whitespace outside it.
+/// Text that is only whitespace and other markers is left as it is.
+fn wrap(text: &str, marker: &str) -> String {
+    let core = text.trim();
+    if core.chars().all(|c| matches!(c, '*' | '_' | '~')) {
+        return text.to_string();
+    }
+    let lead = &text[..text.len() - text.trim_start().len()];
+    let trail = &text[text.trim_end().len()..];
+    format!("{}{}{}{}{}", lead, marker, core, marker, trail)
+}
+
+/// Remove `style` from `start..end` inside `span`; the rest of the span
+/// keeps its formatting. Returns the new line and the selection in it.
+fn remove_style(line: &str, span: &EmphasisSpan, start: usize, end: usize, style: InlineStyle) -> (String, usize, usize) {
+    let marker = style.marker();
+    let (content_start, content_end) = (span.open + span.width, span.close);
+    let before = wrap(&line[content_start..start], marker);
+    let after = wrap(&line[end..content_end], marker);
+    let mut out = String::with_capacity(line.len());
+    out.push_str(&line[..span.open]);
+    out.push_str(&before);
+    let selection_start = out.len();
+    out.push_str(&line[start..end]);
+    let selection_end = out.len();
+    out.push_str(&after);
+    out.push_str(&line[span.close + span.width..]);
+    (out, selection_start, selection_end)
+}
+
+/// Apply `style` to `start..end`, merging any overlapping spans of the same
+/// style and escaping unpaired delimiters that could pair with the new ones
+fn add_style(line: &str, runs: &[DelimiterRun], spans: &[EmphasisSpan], start: usize, end: usize, style: InlineStyle) -> (String, usize, usize) {
+    let marker = style.marker();
+    let text = &line[start..end];
+    let (mut from, mut to) = (start + text.len() - text.trim_start().len(), start + text.trim_end().len());
+    if from >= to {
+        // Nothing selected: leave an empty pair with the cursor inside
+        let out = format!("{}{}{}{}", &line[..start], marker, marker, &line[start..]);
+        return (out, start + marker.len(), start + marker.len());
+    }
+    let overlapping: Vec<&EmphasisSpan> = spans.iter().filter(|s| s.open < to && s.close + s.width > from).collect();
+    for span in &overlapping {
+        from = from.min(span.open);
+        to = to.max(span.close + span.width);
+    }
+
+    // Markers to drop and stray delimiter runs to escape, within from..to
+    let mut dropped: Vec<(usize, usize)> = overlapping
+        .iter()
+        .flat_map(|s| [(s.open, s.open + s.width), (s.close, s.close + s.width)])
+        .collect();
+    dropped.sort();
+    let mut used = vec![0; runs.len()];
+    for span in match_runs(runs) {
+        used[span.open_run] += span.width;
+        used[span.close_run] += span.width;
+    }
+    let marker_char = marker.chars().next().unwrap_or('*');
+    let stray: Vec<&DelimiterRun> = runs
+        .iter()
+        .enumerate()
+        .filter(|(i, r)| used[*i] == 0 && r.ch == marker_char && (r.tight_open || r.tight_close))
+        .filter(|(_, r)| r.start >= from && r.end() <= to)
+        .map(|(_, r)| r)
+        .collect();
+
+    let mut inner = String::with_capacity(to - from + 4);
+    for (i, c) in line[from..to].char_indices() {
+        let pos = from + i;
+        if dropped.iter().any(|&(s, e)| pos >= s && pos < e) {
+            continue;
+        }
+        if stray.iter().any(|r| pos >= r.start && pos < r.end()) {
+            inner.push('\\');
+        }
+        inner.push(c);
+    }
+    let out = format!("{}{}{}{}{}", &line[..from], marker, inner, marker, &line[to..]);
+    let selection_start = from + marker.len();
+    (out, selection_start, selection_start + inner.len())
+}
+
+/// Toggle `style` on the selection `start..end` of `content`, looking at the
+/// surrounding lines so partially formatted selections (`**bo` + `ld**`)
+/// and nested markers are handled. A selection that is entirely formatted
+/// loses the style (a cursor inside a span unformats the whole span);
+/// otherwise the selection, merged with any spans it overlaps, gains it.
+pub(crate) fn toggle_style(content: &str, start: usize, end: usize, style: InlineStyle) -> Toggled {
+    let clamp = |pos: usize| {
+        let mut pos = pos.min(content.len());
+        while !content.is_char_boundary(pos) {
+            pos -= 1;
+        }
+        pos
+    };
+    let (start, end) = (clamp(start.min(end)), clamp(start.max(end)));
+
+    // The selected part of each line it touches
+    let mut segments: Vec<(usize, usize, usize, usize)> = Vec::new();
+    let mut line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    loop {
+        let line_end = content[line_start..].find('\n').map_or(content.len(), |i| line_start + i);
+        segments.push((line_start, line_end, start.max(line_start), end.min(line_end)));
+        if line_end >= end || line_end == content.len() {
+            break;
+        }
+        line_start = line_end + 1;
+    }
+    if segments.len() > 1 {
+        segments.retain(|&(_, _, s, e)| !content[s..e].trim().is_empty());
+    }
+
+    // Unformat only if every selected part is already formatted
+    let covered = |&(ls, le, s, e): &(usize, usize, usize, usize)| {
+        let line = &content[ls..le];
+        let (_, spans) = styled_spans(line, style);
+        let (cs, ce) = if s == e { (s - ls, e - ls) } else { core_range(line, s - ls, e - ls) };
+        covering(&spans, cs, ce).cloned().map(|span| (span, cs, ce))
+    };
+    let remove = !segments.is_empty() && segments.iter().all(|seg| covered(seg).is_some());
+
+    let mut out = String::with_capacity(content.len() + 8);
+    let mut last = 0;
+    let (mut selection_start, mut selection_end) = (None, start);
+    for seg in &segments {
+        let &(ls, le, s, e) = seg;
+        let line = &content[ls..le];
+        let (new_line, sel_s, sel_e) = match covered(seg) {
+            Some((span, cs, ce)) if remove => {
+                // A cursor unformats the whole span
+                let (cs, ce) = if s == e { (span.open + span.width, span.close) } else { (cs, ce) };
+                remove_style(line, &span, cs, ce, style)
+            }
+            _ => {
+                let (runs, spans) = styled_spans(line, style);
+                add_style(line, &runs, &spans, s - ls, e - ls, style)
+            }
+        };
+        out.push_str(&content[last..ls]);
+        let base = out.len();
+        out.push_str(&new_line);
+        last = le;
+        selection_start.get_or_insert(base + sel_s);
+        selection_end = base + sel_e;
+    }
+    out.push_str(&content[last..]);
+    Toggled { content: out, selection_start: selection_start.unwrap_or(start), selection_end }
+}
+
+fn toggled_json(toggled: &Toggled) -> String {
+    serde_json::json!({
+        "content": toggled.content,
+        "selection_start": toggled.selection_start,
+        "selection_end": toggled.selection_end,
+    })
+    .to_string()
+}
+
+/// Toggle bold on the selection `start..end` (byte offsets) of `content`.
+/// Returns JSON `{content, selection_start, selection_end}`.
+#[wasm_bindgen]
+pub fn toggle_bold_at(content: &str, start: usize, end: usize) -> String {
+    toggled_json(&toggle_style(content, start, end, InlineStyle::Bold))
+}
+
+/// Toggle italic on a selection; see `toggle_bold_at`
+#[wasm_bindgen]
+pub fn toggle_italic_at(content: &str, start: usize, end: usize) -> String {
+    toggled_json(&toggle_style(content, start, end, InlineStyle::Italic))
+}
+
+/// Toggle strikethrough on a selection; see `toggle_bold_at`
+#[wasm_bindgen]
+pub fn toggle_strikethrough_at(content: &str, start: usize, end: usize) -> String {
+    toggled_json(&toggle_style(content, start, end, InlineStyle::Strikethrough))
+}
@@ -0,0 +1,126 @@
+// End-to-end encryption of message payloads. The payload (type and data)
+// of a message is sealed with XChaCha20-Poly1305 under the document's key
+// and sent as an `encrypted` message, so relays route it without seeing
+// the content. The associated data binds the ciphertext to the document
+// the receiver expects and to the envelope fields left in the clear: a
+// message replayed into another document, or with its clear nonce or
+// sequence number rewritten, fails to decrypt even if both share a key.
+
+use wasm_bindgen::prelude::*;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::collections::HashMap;
+
+use crate::payload::{decode_payload, payload_data, EncryptedPayload, Payload as MessageData};
+use crate::share::random_bytes;
+use crate::{
+    data_text, decode_with_grid_tag, encode_with_grid_tag, to_canonical_cbor, MessagePayload, PromiseGridMessage,
+    PROTOCOL_HASH,
+};
+
+/// Message type of a sealed message
+const ENCRYPTED: &str = "encrypted";
+
+/// Data fields copied from the sealed message into the clear, so delivery
+/// tracking, ordering, resends and replay checks keep working without the
+/// key. They're authenticated as associated data.
+const CLEAR_FIELDS: &[&str] = &["message_id", "seq", "session", "lamport", "nonce", "sent_at"];
+
+const NONCE_BYTES: usize = 24;
+
+fn cipher(key: &[u8]) -> Result<XChaCha20Poly1305, JsValue> {
+    XChaCha20Poly1305::new_from_slice(key).map_err(|_| JsValue::from_str("Encryption key must be 32 bytes"))
+}
+
+/// Associated data: the protocol and document the ciphertext belongs to,
+/// then the clear envelope fields as canonical CBOR
+fn associated_data(document_id: &str, data: &HashMap<String, ciborium::Value>) -> Result<Vec<u8>, JsValue> {
+    let clear: HashMap<&str, &ciborium::Value> =
+        CLEAR_FIELDS.iter().filter_map(|key| data.get(*key).map(|value| (*key, value))).collect();
+    let mut aad = format!("{}\0{}\0", PROTOCOL_HASH, document_id).into_bytes();
+    aad.extend(to_canonical_cbor(&clear).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))?);
+    Ok(aad)
+}
+
+/// Generate a random 32-byte document key. Share it with collaborators
+/// out of band; the relay must never see it.
+#[wasm_bindgen]
+pub fn generate_document_key() -> Result<Vec<u8>, JsValue> {
+    let mut key = vec![0u8; 32];
+    random_bytes(&mut key)?;
+    Ok(key)
+}
+
+/// Seal a PromiseGrid message for `document_id` with the 32-byte document
+/// `key`. The result is an `encrypted` message carrying the document id,
+/// a random nonce and the ciphertext of the original payload; the original
+/// envelope (`message_id`, `seq`, `session`, `lamport`, `nonce` and
+/// `sent_at`) stays readable but authenticated, so it can't be rewritten.
+#[wasm_bindgen]
+pub fn encrypt_message(cbor_bytes: &[u8], document_id: &str, key: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let cipher = cipher(key)?;
+    let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    if message.payload.message_type == ENCRYPTED {
+        return Err(JsValue::from_str("Message is already encrypted"));
+    }
+    if let Some(other) = data_text(&message.payload.data, "document_id").filter(|id| *id != document_id) {
+        return Err(JsValue::from_str(&format!("Message belongs to document {}, not {}", other, document_id)));
+    }
+    let plaintext = to_canonical_cbor(&message.payload)
+        .map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))?;
+    let mut nonce = vec![0u8; NONCE_BYTES];
+    random_bytes(&mut nonce)?;
+    let aad = associated_data(document_id, &message.payload.data)?;
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &aad })
+        .map_err(|_| JsValue::from_str("Encryption failed"))?;
+
+    let sealed = EncryptedPayload { document_id: document_id.to_string(), aead_nonce: nonce, ciphertext };
+    let mut data = payload_data(&sealed);
+    for key in CLEAR_FIELDS {
+        if let Some(value) = message.payload.data.get(*key) {
+            data.insert(key.to_string(), value.clone());
+        }
+    }
+    let wrapped = PromiseGridMessage {
+        protocol_hash: PROTOCOL_HASH.to_string(),
+        payload: MessagePayload { message_type: ENCRYPTED.to_string(), data },
+    };
+    encode_with_grid_tag(&wrapped).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+}
+
+/// Open an `encrypted` message received for `document_id` with the
+/// document key, returning the original message for the usual handlers.
+/// Fails when the key is wrong, the ciphertext or its clear envelope
+/// fields were altered, or the message was sealed for another document.
+#[wasm_bindgen]
+pub fn decrypt_message(cbor_bytes: &[u8], document_id: &str, key: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let cipher = cipher(key)?;
+    let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    let (_, MessageData::Encrypted(sealed)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+        return Err(JsValue::from_str(&format!(
+            "Expected an encrypted message, got {}",
+            message.payload.message_type
+        )));
+    };
+    if sealed.aead_nonce.len() != NONCE_BYTES {
+        return Err(JsValue::from_str("Encrypted message has a malformed nonce"));
+    }
+    if sealed.document_id != document_id {
+        return Err(JsValue::from_str(&format!(
+            "Encrypted message is addressed to document {}, not {}",
+            sealed.document_id, document_id
+        )));
+    }
+    let aad = associated_data(document_id, &message.payload.data)?;
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(&sealed.aead_nonce), Payload { msg: &sealed.ciphertext, aad: &aad })
+        .map_err(|_| JsValue::from_str("Decryption failed: wrong key, wrong document or tampered message"))?;
+    let payload: MessagePayload = crate::from_cbor(&plaintext)
+        .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    if data_text(&payload.data, "document_id").is_some_and(|id| id != document_id) {
+        return Err(JsValue::from_str("Decrypted message names a different document"));
+    }
+    let opened = PromiseGridMessage { protocol_hash: message.protocol_hash, payload };
+    encode_with_grid_tag(&opened).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+}
@@ -0,0 +1,341 @@
+// EPUB 3 export. The document is split into one XHTML chapter per
+// top-level heading and packaged with the OPF manifest, an EPUB 3
+// navigation document and an NCX table of contents for older readers.
+
+use wasm_bindgen::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{inline_text, parse_markdown, Block, Document, Inline};
+use crate::front_matter::parse_yaml;
+use crate::headings::Slugger;
+use crate::html::{parse_html, render_html_with, Node, VOID_ELEMENTS};
+use crate::journal::civil_from_days;
+use crate::markdown::escape_xml;
+use crate::zip::{write_zip, ZipEntry};
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+const STYLESHEET: &str = "body { font-family: serif; line-height: 1.5; }
+h1, h2, h3, h4, h5, h6 { font-family: sans-serif; line-height: 1.2; }
+pre, code { font-family: monospace; }
+pre { white-space: pre-wrap; }
+blockquote { margin-left: 1.5em; font-style: italic; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #999; padding: 0.2em 0.5em; }
+.footnotes { font-size: 0.9em; }
+";
+
+/// Book metadata. Missing fields come from the front matter (`title`,
+/// `author`, `lang`/`language`), then the first heading.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+struct EpubMetadata {
+    title: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+    /// Unique identifier; derived from the title and content when missing
+    identifier: Option<String>,
+    /// Last modified time, `YYYY-MM-DDThh:mm:ssZ`; now when missing
+    modified: Option<String>,
+}
+
+struct Chapter {
+    title: String,
+    file: String,
+    body: Vec<Node>,
+}
+
+/// The current time as `YYYY-MM-DDThh:mm:ssZ`
+fn now_utc() -> String {
+    let secs = (js_sys::Date::now() / 1000.0).floor() as i64;
+    let rem = secs.rem_euclid(86_400);
+    format!("{}T{:02}:{:02}:{:02}Z", civil_from_days(secs.div_euclid(86_400)), rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+/// A stable `urn:uuid:` for the book, so re-exports replace the old copy
+/// in a reader's library rather than duplicating it
+fn derived_identifier(title: &str, content: &str) -> String {
+    let hash = Sha256::digest(format!("{}\n{}", title, content).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    // Version 5 (name-based) and RFC 4122 variant bits
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("urn:uuid:{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Labels of the footnotes referenced in `blocks`
+fn footnote_refs(blocks: &[Block], out: &mut HashSet<String>) {
+    fn inlines(content: &[Inline], out: &mut HashSet<String>) {
+        for inline in content {
+            match inline {
+                Inline::FootnoteRef { label } => {
+                    out.insert(label.clone());
+                }
+                Inline::Emphasis { content }
+                | Inline::Strong { content }
+                | Inline::Strikethrough { content }
+                | Inline::Underline { content }
+                | Inline::Link { content, .. } => inlines(content, out),
+                _ => {}
+            }
+        }
+    }
+    for block in blocks {
+        match block {
+            Block::Heading { content, .. } | Block::Paragraph { content } => inlines(content, out),
+            Block::BlockQuote { blocks } | Block::FootnoteDefinition { blocks, .. } => footnote_refs(blocks, out),
+            Block::List { items, .. } => items.iter().for_each(|i| footnote_refs(&i.blocks, out)),
+            Block::Table { header, rows, .. } => {
+                header.iter().chain(rows.iter().flatten()).for_each(|c| inlines(c, out));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Split the document at its top-level headings. Content before the first
+/// heading becomes an opening chapter named after the book.
+fn split_chapters(doc: &Document, book_title: &str) -> Vec<(String, Vec<Block>)> {
+    let top = doc.blocks.iter().filter_map(|b| match b {
+        Block::Heading { level, .. } => Some(*level),
+        _ => None,
+    });
+    let top = top.min();
+    let mut chapters: Vec<(String, Vec<Block>)> = Vec::new();
+    for block in &doc.blocks {
+        match block {
+            Block::FootnoteDefinition { .. } => {}
+            Block::Heading { level, content } if Some(*level) == top => {
+                chapters.push((inline_text(content), vec![block.clone()]));
+            }
+            _ => match chapters.last_mut() {
+                Some((_, blocks)) => blocks.push(block.clone()),
+                None => chapters.push((book_title.to_string(), vec![block.clone()])),
+            },
+        }
+    }
+    if chapters.is_empty() {
+        chapters.push((book_title.to_string(), Vec::new()));
+    }
+    chapters
+}
+
+/// Write nodes as XHTML: void elements self-closed, boolean attributes
+/// given values, links to headings in other chapters pointed at their
+/// file, and images that aren't embedded replaced by their alt text
+fn write_xhtml(nodes: &[Node], anchors: &HashMap<String, String>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(&escape_xml(text)),
+            Node::Element { name, .. } if name == "img" && !node.attr("src").unwrap_or("").starts_with("data:image/") => {
+                let alt = node.attr("alt").unwrap_or("");
+                if !alt.is_empty() {
+                    out.push_str(&format!("<span class=\"image-alt\">[{}]</span>", escape_xml(alt)));
+                }
+            }
+            Node::Element { name, attrs, children } => {
+                out.push('<');
+                out.push_str(name);
+                for (key, value) in attrs {
+                    let value = if value.is_empty() { key.as_str() } else { value.as_str() };
+                    let value = match value.strip_prefix('#') {
+                        Some(id) if key == "href" => anchors.get(id).map_or_else(|| value.to_string(), |file| format!("{}#{}", file, id)),
+                        _ => value.to_string(),
+                    };
+                    out.push_str(&format!(" {}=\"{}\"", key, escape_xml(&value)));
+                }
+                if VOID_ELEMENTS.contains(&name.as_str()) {
+                    out.push_str("/>");
+                } else {
+                    out.push('>');
+                    write_xhtml(children, anchors, out);
+                    out.push_str(&format!("</{}>", name));
+                }
+            }
+        }
+    }
+}
+
+/// Ids defined in a chapter
+fn collect_ids(nodes: &[Node], out: &mut Vec<String>) {
+    for node in nodes {
+        if let Some(id) = node.attr("id") {
+            out.push(id.to_string());
+        }
+        collect_ids(node.children(), out);
+    }
+}
+
+fn xhtml_page(title: &str, language: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{lang}\" lang=\"{lang}\">\n\
+         <head>\n<meta charset=\"UTF-8\"/>\n<title>{title}</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n\
+         <body>\n{body}\n</body>\n</html>\n",
+        lang = escape_xml(language),
+        title = escape_xml(title),
+        body = body
+    )
+}
+
+/// Export markdown as an EPUB 3 e-book (bytes). The document is split
+/// into a chapter per top-level heading; each becomes an XHTML file listed
+/// in the OPF manifest, the navigation document and the NCX table of
+/// contents. `metadata` is a JSON object `{title, author, language,
+/// identifier, modified}`; missing fields come from the front matter and
+/// the first heading, and an empty string uses those alone. Footnotes are
+/// repeated at the end of each chapter that references them. Remote images
+/// are not packaged and show as their alt text.
+#[wasm_bindgen]
+pub fn export_epub(content: &str, metadata: &str) -> Result<Vec<u8>, JsValue> {
+    let mut meta: EpubMetadata = if metadata.trim().is_empty() {
+        EpubMetadata::default()
+    } else {
+        serde_json::from_str(metadata).map_err(|e| JsValue::from_str(&format!("Invalid EPUB metadata: {}", e)))?
+    };
+    let doc = parse_markdown(content);
+
+    let yaml = doc.front_matter.as_deref().and_then(|y| parse_yaml(y).ok()).unwrap_or_default();
+    let field = |keys: &[&str]| keys.iter().find_map(|k| yaml.get(*k).and_then(Value::as_str).map(str::to_string));
+    let first_heading = doc.blocks.iter().find_map(|b| match b {
+        Block::Heading { content, .. } => Some(inline_text(content)),
+        _ => None,
+    });
+    let title = meta.title.take().or_else(|| field(&["title"])).or(first_heading).unwrap_or_else(|| "Untitled".to_string());
+    let author = meta.author.take().or_else(|| field(&["author"]));
+    let language = meta.language.take().or_else(|| field(&["lang", "language"])).unwrap_or_else(|| "en".to_string());
+    let identifier = meta.identifier.take().unwrap_or_else(|| derived_identifier(&title, content));
+    let modified = meta.modified.take().unwrap_or_else(now_utc);
+
+    let definitions: Vec<&Block> = doc.blocks.iter().filter(|b| matches!(b, Block::FootnoteDefinition { .. })).collect();
+    let mut chapters = Vec::new();
+    // One slugger for the whole book, so heading ids are unique across chapters
+    let mut slugger = Slugger::default();
+    for (n, (chapter_title, mut blocks)) in split_chapters(&doc, &title).into_iter().enumerate() {
+        let mut refs = HashSet::new();
+        footnote_refs(&blocks, &mut refs);
+        blocks.extend(definitions.iter().filter(|d| matches!(d, Block::FootnoteDefinition { label, .. } if refs.contains(label))).map(|d| (*d).clone()));
+        let html = render_html_with(&Document { front_matter: None, lines: vec![0; blocks.len()], blocks }, &mut slugger);
+        chapters.push(Chapter { title: chapter_title, file: format!("chapter-{:03}.xhtml", n + 1), body: parse_html(&html) });
+    }
+
+    let mut anchors = HashMap::new();
+    let mut chapter_ids = Vec::new();
+    for chapter in &chapters {
+        let mut ids = Vec::new();
+        collect_ids(&chapter.body, &mut ids);
+        for id in &ids {
+            anchors.entry(id.clone()).or_insert_with(|| chapter.file.clone());
+        }
+        chapter_ids.push(ids.into_iter().collect::<HashSet<_>>());
+    }
+
+    let mut entries = vec![
+        ZipEntry { name: "mimetype".to_string(), data: b"application/epub+zip".to_vec() },
+        ZipEntry { name: "META-INF/container.xml".to_string(), data: CONTAINER.as_bytes().to_vec() },
+        ZipEntry { name: "OEBPS/style.css".to_string(), data: STYLESHEET.as_bytes().to_vec() },
+    ];
+    for (chapter, ids) in chapters.iter().zip(&chapter_ids) {
+        // Links to ids in the same chapter, including its repeated
+        // footnotes, stay bare fragments
+        let local: HashMap<String, String> = anchors.iter().filter(|(id, _)| !ids.contains(*id)).map(|(k, v)| (k.clone(), v.clone())).collect();
+        let mut body = String::new();
+        write_xhtml(&chapter.body, &local, &mut body);
+        entries.push(ZipEntry {
+            name: format!("OEBPS/{}", chapter.file),
+            data: xhtml_page(&chapter.title, &language, &body).into_bytes(),
+        });
+    }
+
+    let nav_items: String = chapters
+        .iter()
+        .map(|c| format!("<li><a href=\"{}\">{}</a></li>\n", c.file, escape_xml(&c.title)))
+        .collect();
+    let nav = xhtml_page(&title, &language, &format!("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n{}</ol>\n</nav>", nav_items));
+    entries.push(ZipEntry { name: "OEBPS/nav.xhtml".to_string(), data: nav.into_bytes() });
+
+    let nav_points: String = chapters
+        .iter()
+        .enumerate()
+        .map(|(n, c)| {
+            format!(
+                "<navPoint id=\"nav-{n}\" playOrder=\"{n}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/></navPoint>\n",
+                escape_xml(&c.title),
+                c.file,
+                n = n + 1
+            )
+        })
+        .collect();
+    let ncx = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n\
+         <head><meta name=\"dtb:uid\" content=\"{}\"/></head>\n<docTitle><text>{}</text></docTitle>\n<navMap>\n{}</navMap>\n</ncx>\n",
+        escape_xml(&identifier),
+        escape_xml(&title),
+        nav_points
+    );
+    entries.push(ZipEntry { name: "OEBPS/toc.ncx".to_string(), data: ncx.into_bytes() });
+
+    let manifest: String = chapters
+        .iter()
+        .enumerate()
+        .map(|(n, c)| format!("<item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n", n + 1, c.file))
+        .collect();
+    let spine: String = (1..=chapters.len()).map(|n| format!("<itemref idref=\"chapter-{}\"/>\n", n)).collect();
+    let creator = author.map(|a| format!("<dc:creator>{}</dc:creator>\n", escape_xml(&a))).unwrap_or_default();
+    let opf = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{lang}\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<dc:identifier id=\"book-id\">{id}</dc:identifier>\n\
+         <dc:title>{title}</dc:title>\n<dc:language>{lang}</dc:language>\n{creator}\
+         <meta property=\"dcterms:modified\">{modified}</meta>\n</metadata>\n\
+         <manifest>\n<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+         <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n\
+         <item id=\"css\" href=\"style.css\" media-type=\"text/css\"/>\n{manifest}</manifest>\n\
+         <spine toc=\"ncx\">\n{spine}</spine>\n</package>\n",
+        lang = escape_xml(&language),
+        id = escape_xml(&identifier),
+        title = escape_xml(&title),
+        creator = creator,
+        modified = escape_xml(&modified),
+        manifest = manifest,
+        spine = spine
+    );
+    entries.push(ZipEntry { name: "OEBPS/content.opf".to_string(), data: opf.into_bytes() });
+
+    // The mimetype entry must come first and be stored uncompressed
+    Ok(write_zip(&entries, &["mimetype"]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zip::read_zip;
+
+    fn chapter(entries: &[ZipEntry], n: usize) -> String {
+        let name = format!("OEBPS/chapter-{:03}.xhtml", n);
+        String::from_utf8(entries.iter().find(|e| e.name == name).unwrap().data.clone()).unwrap()
+    }
+
+    #[test]
+    fn chapters_get_unique_ids_and_valid_xml() {
+        let content = "# One\n\n## Setup\n\nBell \u{7} here.\n\n# Two\n\n## Setup\n\nSee [the first](#setup).\n";
+        let epub = export_epub(content, r#"{"modified": "2026-01-01T00:00:00Z"}"#).unwrap();
+        let entries = read_zip(&epub).unwrap();
+        let (one, two) = (chapter(&entries, 1), chapter(&entries, 2));
+        assert!(one.contains("id=\"setup\""));
+        assert!(!one.contains('\u{7}'));
+        assert!(two.contains("id=\"setup-1\""));
+        assert!(two.contains("href=\"chapter-001.xhtml#setup\""));
+    }
+}
@@ -0,0 +1,210 @@
+// Export format registry: every format the editor can export, with what
+// the UI needs to list it and save the result, behind one entry point.
+// A new format is one more entry in `registry`.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::abbreviations::expand_abbreviations;
+use crate::asciidoc::export_asciidoc;
+use crate::ast::{inline_text, parse_markdown, Block};
+use crate::docx::export_docx;
+use crate::epub::export_epub;
+use crate::front_matter::parse_yaml;
+use crate::highlight::highlight_code_blocks;
+use crate::html::render_html;
+use crate::markdown::escape_html;
+use crate::plaintext::export_plaintext;
+use crate::rst::export_rst;
+
+/// Exporter: document content and options JSON to file bytes
+type Exporter = fn(&str, &str) -> Result<Vec<u8>, JsValue>;
+
+#[derive(Serialize)]
+struct ExportFormat {
+    id: &'static str,
+    name: &'static str,
+    mime_type: &'static str,
+    extension: &'static str,
+    /// Whether the output is binary rather than UTF-8 text
+    binary: bool,
+    /// JSON Schema of the options object
+    options: Value,
+    #[serde(skip)]
+    export: Exporter,
+}
+
+fn no_options() -> Value {
+    json!({ "type": "object", "properties": {} })
+}
+
+fn export_markdown(content: &str, _options: &str) -> Result<Vec<u8>, JsValue> {
+    Ok(content.as_bytes().to_vec())
+}
+
+/// A standalone HTML page. The title comes from the `title` option, then
+/// the front matter, then the first heading.
+fn export_html(content: &str, options: &str) -> Result<Vec<u8>, JsValue> {
+    let options: Value = if options.trim().is_empty() {
+        json!({})
+    } else {
+        serde_json::from_str(options).map_err(|e| JsValue::from_str(&format!("Invalid HTML options: {}", e)))?
+    };
+    let doc = parse_markdown(&expand_abbreviations(content));
+    let title = options["title"].as_str().map(str::to_string).or_else(|| {
+        let yaml = doc.front_matter.as_deref().and_then(|y| parse_yaml(y).ok())?;
+        yaml.get("title").and_then(Value::as_str).map(str::to_string)
+    });
+    let title = title.or_else(|| {
+        doc.blocks.iter().find_map(|b| match b {
+            Block::Heading { content, .. } => Some(inline_text(content)),
+            _ => None,
+        })
+    });
+    let body = highlight_code_blocks(&render_html(&doc));
+    let page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title.as_deref().unwrap_or("Document")),
+        body
+    );
+    Ok(page.into_bytes())
+}
+
+fn registry() -> Vec<ExportFormat> {
+    vec![
+        ExportFormat {
+            id: "markdown",
+            name: "Markdown",
+            mime_type: "text/markdown",
+            extension: "md",
+            binary: false,
+            options: no_options(),
+            export: export_markdown,
+        },
+        ExportFormat {
+            id: "html",
+            name: "HTML",
+            mime_type: "text/html",
+            extension: "html",
+            binary: false,
+            options: json!({
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string", "description": "Page title; defaults to the front matter title or first heading" }
+                }
+            }),
+            export: export_html,
+        },
+        ExportFormat {
+            id: "plaintext",
+            name: "Plain text",
+            mime_type: "text/plain",
+            extension: "txt",
+            binary: false,
+            options: json!({
+                "type": "object",
+                "properties": {
+                    "links": { "type": "string", "enum": ["text", "text_url"], "default": "text_url" },
+                    "headings": { "type": "string", "enum": ["plain", "uppercase", "underlined"], "default": "underlined" }
+                }
+            }),
+            export: |content, options| export_plaintext(content, options).map(String::into_bytes),
+        },
+        ExportFormat {
+            id: "rst",
+            name: "reStructuredText",
+            mime_type: "text/x-rst",
+            extension: "rst",
+            binary: false,
+            options: no_options(),
+            export: |content, _| Ok(export_rst(content).into_bytes()),
+        },
+        ExportFormat {
+            id: "asciidoc",
+            name: "AsciiDoc",
+            mime_type: "text/asciidoc",
+            extension: "adoc",
+            binary: false,
+            options: no_options(),
+            export: |content, _| Ok(export_asciidoc(content).into_bytes()),
+        },
+        ExportFormat {
+            id: "docx",
+            name: "Word",
+            mime_type: "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            extension: "docx",
+            binary: true,
+            options: no_options(),
+            export: |content, _| Ok(export_docx(content)),
+        },
+        ExportFormat {
+            id: "epub",
+            name: "EPUB e-book",
+            mime_type: "application/epub+zip",
+            extension: "epub",
+            binary: true,
+            options: json!({
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string" },
+                    "author": { "type": "string" },
+                    "language": { "type": "string", "default": "en" },
+                    "identifier": { "type": "string" },
+                    "modified": { "type": "string", "format": "date-time" }
+                }
+            }),
+            export: export_epub,
+        },
+    ]
+}
+
+/// Ids of the registered formats, for the capabilities report
+pub(crate) fn format_ids() -> Vec<&'static str> {
+    registry().iter().map(|f| f.id).collect()
+}
+
+/// All export formats behind one entry point. `formats()` lists them with
+/// their display name, MIME type, file extension and an options JSON
+/// Schema, so the UI can build its export menu from the list.
+#[wasm_bindgen]
+pub struct ExportManager {
+    formats: Vec<ExportFormat>,
+}
+
+impl Default for ExportManager {
+    fn default() -> Self {
+        ExportManager { formats: registry() }
+    }
+}
+
+#[wasm_bindgen]
+impl ExportManager {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ExportManager {
+        ExportManager::default()
+    }
+
+    /// JSON array of `{id, name, mime_type, extension, binary, options}`
+    pub fn formats(&self) -> String {
+        serde_json::to_string(&self.formats).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// JSON for one format, or `null` when the id isn't registered
+    pub fn format(&self, format_id: &str) -> String {
+        let format = self.formats.iter().find(|f| f.id == format_id);
+        serde_json::to_string(&format).unwrap_or_else(|_| "null".into())
+    }
+
+    /// Export `content` as `format_id`. `options` is a JSON object matching
+    /// the format's schema; an empty string uses the defaults.
+    pub fn export(&self, content: &str, format_id: &str, options: &str) -> Result<Vec<u8>, JsValue> {
+        let format = self
+            .formats
+            .iter()
+            .find(|f| f.id == format_id)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown export format: {}", format_id)))?;
+        (format.export)(content, options)
+    }
+}
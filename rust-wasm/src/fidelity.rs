@@ -0,0 +1,309 @@
+// Conversion fidelity: run a document through an export format and back,
+// and report which constructs didn't survive the trip.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::ast::{inline_text, parse_markdown, to_markdown, Block, Document, Inline};
+use crate::docx::{read_docx, write_docx};
+use crate::html::{html_to_document, render_html};
+
+/// Formats `roundtrip_check` can test, with the conversion there and back
+pub(crate) const ROUNDTRIP_FORMATS: &[&str] = &["markdown", "html", "docx"];
+
+fn roundtrip(doc: &Document, format: &str) -> Result<Document, String> {
+    match format {
+        "markdown" | "md" => Ok(parse_markdown(&to_markdown(doc))),
+        "html" => Ok(html_to_document(&render_html(doc))),
+        "docx" => read_docx(&write_docx(doc)),
+        _ => Err(format!("Unknown format: {} (supported: {})", format, ROUNDTRIP_FORMATS.join(", "))),
+    }
+}
+
+/// One occurrence of a markdown construct
+#[derive(Serialize, Debug, Clone)]
+struct Construct {
+    /// "heading", "link", "table", ...
+    kind: &'static str,
+    /// Human-readable description for the UI
+    description: String,
+    /// Line of the top-level block containing it
+    line: usize,
+    /// What has to match for the construct to count as preserved
+    #[serde(skip)]
+    key: String,
+}
+
+#[derive(Serialize)]
+struct KindCount {
+    kind: &'static str,
+    before: usize,
+    after: usize,
+}
+
+#[derive(Serialize)]
+struct TextChange {
+    before: String,
+    after: String,
+}
+
+#[derive(Serialize)]
+struct FidelityReport {
+    format: String,
+    lossless: bool,
+    /// Constructs of the original with no counterpart after the round trip
+    lost: Vec<Construct>,
+    /// Constructs that only exist after the round trip
+    added: Vec<Construct>,
+    counts: Vec<KindCount>,
+    /// First place where the plain text differs, if it does
+    text_change: Option<TextChange>,
+    /// The document after the round trip, as markdown
+    roundtrip: String,
+}
+
+fn excerpt(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(40) {
+        Some((i, _)) => format!("{}…", &text[..i]),
+        None => text,
+    }
+}
+
+struct Inventory {
+    items: Vec<Construct>,
+    line: usize,
+}
+
+impl Inventory {
+    fn add(&mut self, kind: &'static str, key: String, description: String) {
+        self.items.push(Construct { kind, description, line: self.line, key });
+    }
+
+    fn inlines(&mut self, inlines: &[Inline]) {
+        for inline in inlines {
+            match inline {
+                Inline::Text { .. } | Inline::SoftBreak => {}
+                Inline::Code { code } => self.add("inline_code", code.clone(), format!("Inline code `{}`", excerpt(code))),
+                Inline::Emphasis { content } => self.styled("emphasis", "Italic", content),
+                Inline::Strong { content } => self.styled("strong", "Bold", content),
+                Inline::Strikethrough { content } => self.styled("strikethrough", "Strikethrough", content),
+                Inline::Underline { content } => self.styled("underline", "Underline", content),
+                Inline::Link { url, title, content } => {
+                    self.add("link", url.clone(), format!("Link to {}", url));
+                    if let Some(title) = title {
+                        self.add("link_title", title.clone(), format!("Link title \"{}\"", excerpt(title)));
+                    }
+                    self.inlines(content);
+                }
+                Inline::Image { url, title, alt } => {
+                    self.add("image", url.clone(), format!("Image {}", url));
+                    if !alt.is_empty() {
+                        self.add("image_alt", alt.clone(), format!("Alt text \"{}\"", excerpt(alt)));
+                    }
+                    if let Some(title) = title {
+                        self.add("image_title", title.clone(), format!("Image title \"{}\"", excerpt(title)));
+                    }
+                }
+                Inline::Math { tex } => self.add("inline_math", tex.clone(), format!("Math ${}$", excerpt(tex))),
+                Inline::FootnoteRef { label } => self.add("footnote_ref", label.clone(), format!("Footnote reference [^{}]", label)),
+                Inline::WikiLink { target, anchor, .. } => {
+                    let key = format!("{}#{}", target, anchor.as_deref().unwrap_or(""));
+                    self.add("wiki_link", key, format!("Wiki link to \"{}\"", target));
+                }
+                Inline::Html { html } => self.add("inline_html", html.clone(), format!("Inline HTML {}", excerpt(html))),
+                Inline::LineBreak => self.add("line_break", String::new(), "Hard line break".to_string()),
+            }
+        }
+    }
+
+    fn styled(&mut self, kind: &'static str, label: &str, content: &[Inline]) {
+        let text = inline_text(content);
+        self.add(kind, text.clone(), format!("{} \"{}\"", label, excerpt(&text)));
+        self.inlines(content);
+    }
+
+    fn blocks(&mut self, blocks: &[Block], depth: usize) {
+        for block in blocks {
+            self.block(block, depth);
+        }
+    }
+
+    fn block(&mut self, block: &Block, depth: usize) {
+        match block {
+            Block::Heading { level, content } => {
+                let text = inline_text(content);
+                self.add("heading", format!("{}:{}", level, text), format!("Heading {} \"{}\"", level, excerpt(&text)));
+                self.inlines(content);
+            }
+            Block::Paragraph { content } => self.inlines(content),
+            Block::CodeBlock { language, code } => {
+                let lines = code.lines().count();
+                self.add("code_block", code.clone(), format!("Code block ({} lines)", lines));
+                if !language.is_empty() {
+                    self.add("code_language", language.clone(), format!("Code block language \"{}\"", language));
+                }
+            }
+            Block::BlockQuote { blocks } => {
+                self.add("block_quote", String::new(), "Block quote".to_string());
+                self.blocks(blocks, depth);
+            }
+            Block::List { ordered, start, items } => {
+                let (kind, label) = match (depth, ordered) {
+                    (0, true) => ("ordered_list", "Numbered list"),
+                    (0, false) => ("bullet_list", "Bulleted list"),
+                    (_, _) => ("nested_list", "Nested list"),
+                };
+                self.add(kind, items.len().to_string(), format!("{} ({} items)", label, items.len()));
+                if *ordered && *start != 1 {
+                    self.add("list_start", start.to_string(), format!("List numbering starting at {}", start));
+                }
+                for item in items {
+                    if let Some(checked) = item.checked {
+                        let text = excerpt(&blocks_text(&item.blocks));
+                        let state = if checked { "Done" } else { "Open" };
+                        self.add("task_item", format!("{}:{}", checked, text), format!("{} task \"{}\"", state, text));
+                    }
+                    self.blocks(&item.blocks, depth + 1);
+                }
+            }
+            Block::Table { align, header, rows } => {
+                let size = format!("{}x{}", header.len(), rows.len());
+                self.add("table", size.clone(), format!("Table ({} columns, {} rows)", header.len(), rows.len()));
+                if align.iter().any(|a| *a != crate::ast::Align::None) {
+                    self.add("table_alignment", format!("{:?}", align), "Table column alignment".to_string());
+                }
+                for cell in header.iter().chain(rows.iter().flatten()) {
+                    self.inlines(cell);
+                }
+            }
+            Block::ThematicBreak => self.add("thematic_break", String::new(), "Horizontal rule".to_string()),
+            Block::Html { html } => self.add("html_block", html.clone(), format!("HTML block {}", excerpt(html))),
+            Block::MathBlock { tex } => self.add("math_block", tex.clone(), format!("Display math $${}$$", excerpt(tex))),
+            Block::FootnoteDefinition { label, blocks } => {
+                self.add("footnote", label.clone(), format!("Footnote [^{}]", label));
+                self.blocks(blocks, depth);
+            }
+        }
+    }
+}
+
+fn blocks_text(blocks: &[Block]) -> String {
+    let mut out = Vec::new();
+    for block in blocks {
+        match block {
+            Block::Heading { content, .. } | Block::Paragraph { content } => out.push(inline_text(content)),
+            Block::CodeBlock { code, .. } => out.push(code.clone()),
+            Block::BlockQuote { blocks } | Block::FootnoteDefinition { blocks, .. } => out.push(blocks_text(blocks)),
+            Block::List { items, .. } => out.extend(items.iter().map(|i| blocks_text(&i.blocks))),
+            Block::Table { header, rows, .. } => {
+                out.extend(header.iter().chain(rows.iter().flatten()).map(|c| inline_text(c)))
+            }
+            Block::MathBlock { tex } => out.push(tex.clone()),
+            Block::Html { html } => out.push(strip_tags(html)),
+            Block::ThematicBreak => {}
+        }
+    }
+    out.join(" ")
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn inventory(doc: &Document) -> Vec<Construct> {
+    let mut inventory = Inventory { items: Vec::new(), line: 1 };
+    if let Some(yaml) = &doc.front_matter {
+        inventory.add("front_matter", yaml.clone(), "YAML front matter".to_string());
+    }
+    for (n, block) in doc.blocks.iter().enumerate() {
+        inventory.line = doc.lines.get(n).copied().unwrap_or(0);
+        inventory.block(block, 0);
+    }
+    inventory.items
+}
+
+/// Constructs in `a` without a matching construct in `b`
+fn unmatched(a: &[Construct], b: &[Construct]) -> Vec<Construct> {
+    let mut available: HashMap<(&str, &str), usize> = HashMap::new();
+    for c in b {
+        *available.entry((c.kind, c.key.as_str())).or_default() += 1;
+    }
+    a.iter()
+        .filter(|c| match available.get_mut(&(c.kind, c.key.as_str())) {
+            Some(n) if *n > 0 => {
+                *n -= 1;
+                false
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+fn text_change(before: &str, after: &str) -> Option<TextChange> {
+    let before: Vec<&str> = before.split_whitespace().collect();
+    let after: Vec<&str> = after.split_whitespace().collect();
+    let first = before.iter().zip(&after).position(|(a, b)| a != b);
+    let at = match first {
+        Some(at) => at,
+        None if before.len() == after.len() => return None,
+        None => before.len().min(after.len()),
+    };
+    let window = |words: &[&str]| words[at.saturating_sub(3)..(at + 6).min(words.len())].join(" ");
+    Some(TextChange { before: window(&before), after: window(&after) })
+}
+
+/// Convert markdown to `format` ("markdown", "html" or "docx") and back,
+/// and report what didn't survive. Returns JSON `{format, lossless, lost,
+/// added, counts, text_change, roundtrip}`, where `lost`/`added` list
+/// constructs as `{kind, description, line}`, `counts` compares occurrences
+/// per kind, and `text_change` shows the first place the plain text differs.
+#[wasm_bindgen]
+pub fn roundtrip_check(text: &str, format: &str) -> Result<String, JsValue> {
+    let format = format.trim().to_lowercase();
+    let original = parse_markdown(text);
+    let converted = roundtrip(&original, &format).map_err(|e| JsValue::from_str(&e))?;
+
+    let before = inventory(&original);
+    let after = inventory(&converted);
+    let mut kinds: Vec<&'static str> = before.iter().chain(&after).map(|c| c.kind).collect();
+    kinds.sort();
+    kinds.dedup();
+    let counts: Vec<KindCount> = kinds
+        .into_iter()
+        .map(|kind| KindCount {
+            kind,
+            before: before.iter().filter(|c| c.kind == kind).count(),
+            after: after.iter().filter(|c| c.kind == kind).count(),
+        })
+        .collect();
+
+    let lost = unmatched(&before, &after);
+    let added = unmatched(&after, &before);
+    let text_change = text_change(&blocks_text(&original.blocks), &blocks_text(&converted.blocks));
+    let report = FidelityReport {
+        lossless: lost.is_empty() && added.is_empty() && text_change.is_none(),
+        format,
+        lost,
+        added,
+        counts,
+        text_change,
+        roundtrip: to_markdown(&converted),
+    };
+    serde_json::to_string(&report).map_err(|e| JsValue::from_str(&format!("JSON encoding error: {}", e)))
+}
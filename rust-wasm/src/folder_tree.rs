@@ -0,0 +1,456 @@
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::clock;
+use crate::workspace::{supersedes, Stamp};
+use crate::{data_f64, data_text, data_u64, decode_with_grid_tag, encode_promisegrid_payload};
+
+/// A folder in the workspace tree. Each field group is last-writer-wins by
+/// Lamport stamp, so peers applying the same messages in any order agree.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Folder {
+    name: String,
+    parent: Option<String>,
+    /// Sort key among siblings (fractional, so inserts never renumber)
+    position: f64,
+    deleted: bool,
+    name_changed: Stamp,
+    placed: Stamp,
+    deleted_changed: Option<Stamp>,
+}
+
+/// Where a document sits in the tree
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Placement {
+    folder: Option<String>,
+    position: f64,
+    placed: Stamp,
+}
+
+#[derive(Serialize)]
+struct FolderNode {
+    id: String,
+    name: String,
+    folders: Vec<FolderNode>,
+    documents: Vec<String>,
+}
+
+/// Visible children per parent folder (None is the root): (position, id)
+type Children = HashMap<Option<String>, Vec<(f64, String)>>;
+
+fn child_ids(children: &Children, parent: Option<String>) -> Vec<String> {
+    children
+        .get(&parent)
+        .map(|c| c.iter().map(|(_, id)| id.clone()).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Serialize)]
+struct TreeView {
+    folders: Vec<FolderNode>,
+    documents: Vec<String>,
+}
+
+/// Sort key halfway between two neighbours
+fn position_between(before: Option<f64>, after: Option<f64>) -> f64 {
+    match (before, after) {
+        (None, None) => 1.0,
+        (Some(b), None) => b + 1.0,
+        (None, Some(a)) => a - 1.0,
+        (Some(b), Some(a)) => (b + a) / 2.0,
+    }
+}
+
+/// Position for inserting at `index` into siblings sorted by position
+fn position_at(sibling_positions: &[f64], index: usize) -> f64 {
+    let index = index.min(sibling_positions.len());
+    let before = index.checked_sub(1).map(|i| sibling_positions[i]);
+    position_between(before, sibling_positions.get(index).copied())
+}
+
+/// The sidebar folder tree: nested folders and document placement, synced
+/// through PromiseGrid control messages (`folder_create`, `folder_rename`,
+/// `folder_move`, `folder_delete`, `document_place`).
+///
+/// Deleted folders and folders caught in a move cycle never lose content:
+/// their children are shown under the nearest live ancestor (or the root).
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FolderTree {
+    folders: BTreeMap<String, Folder>,
+    documents: BTreeMap<String, Placement>,
+    clock: u64,
+}
+
+#[wasm_bindgen]
+impl FolderTree {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> FolderTree {
+        FolderTree::default()
+    }
+
+    /// Current Lamport time of this replica
+    pub fn lamport_time(&self) -> u64 {
+        self.clock
+    }
+
+    /// Create a folder under `parent_id` (root if undefined) at sibling
+    /// `index`. Returns the `folder_create` message to broadcast.
+    pub fn create_folder(
+        &mut self,
+        folder_id: &str,
+        name: &str,
+        parent_id: Option<String>,
+        index: usize,
+        user_id: &str,
+    ) -> Result<Vec<u8>, JsValue> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(JsValue::from_str("Folder name cannot be empty"));
+        }
+        if self.folders.contains_key(folder_id) {
+            return Err(JsValue::from_str(&format!("Folder {} already exists", folder_id)));
+        }
+        self.check_parent(parent_id.as_deref())?;
+        let position = position_at(&self.folder_positions(parent_id.as_deref(), folder_id), index);
+        let stamp = self.tick(user_id);
+        self.apply_create(folder_id, name, parent_id.clone(), position, stamp);
+
+        let mut data = tree_data(folder_id, self.clock, user_id);
+        data.insert("name".to_string(), ciborium::Value::Text(name.to_string()));
+        data.insert("parent_id".to_string(), optional_text(parent_id));
+        data.insert("position".to_string(), ciborium::Value::Float(position));
+        Ok(encode_promisegrid_payload("folder_create", data))
+    }
+
+    /// Rename a folder. Returns the `folder_rename` message to broadcast.
+    pub fn rename_folder(&mut self, folder_id: &str, name: &str, user_id: &str) -> Result<Vec<u8>, JsValue> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(JsValue::from_str("Folder name cannot be empty"));
+        }
+        self.check_folder(folder_id)?;
+        let stamp = self.tick(user_id);
+        self.apply_rename(folder_id, name, stamp);
+
+        let mut data = tree_data(folder_id, self.clock, user_id);
+        data.insert("name".to_string(), ciborium::Value::Text(name.to_string()));
+        Ok(encode_promisegrid_payload("folder_rename", data))
+    }
+
+    /// Move a folder under `parent_id` (root if undefined) at sibling
+    /// `index`. Moving a folder into its own subtree is refused.
+    pub fn move_folder(
+        &mut self,
+        folder_id: &str,
+        parent_id: Option<String>,
+        index: usize,
+        user_id: &str,
+    ) -> Result<Vec<u8>, JsValue> {
+        self.check_folder(folder_id)?;
+        self.check_parent(parent_id.as_deref())?;
+        if let Some(parent) = parent_id.as_deref() {
+            if parent == folder_id || self.ancestors(parent).iter().any(|a| a == folder_id) {
+                return Err(JsValue::from_str("Cannot move a folder into itself"));
+            }
+        }
+        let position = position_at(&self.folder_positions(parent_id.as_deref(), folder_id), index);
+        let stamp = self.tick(user_id);
+        self.apply_move(folder_id, parent_id.clone(), position, stamp);
+
+        let mut data = tree_data(folder_id, self.clock, user_id);
+        data.insert("parent_id".to_string(), optional_text(parent_id));
+        data.insert("position".to_string(), ciborium::Value::Float(position));
+        Ok(encode_promisegrid_payload("folder_move", data))
+    }
+
+    /// Delete a folder. Its contents move up to the nearest live ancestor.
+    pub fn delete_folder(&mut self, folder_id: &str, user_id: &str) -> Result<Vec<u8>, JsValue> {
+        self.check_folder(folder_id)?;
+        let stamp = self.tick(user_id);
+        self.apply_delete(folder_id, stamp);
+        Ok(encode_promisegrid_payload("folder_delete", tree_data(folder_id, self.clock, user_id)))
+    }
+
+    /// Place a document in `folder_id` (root if undefined) at `index` among
+    /// that folder's documents. Returns the `document_place` message.
+    pub fn place_document(
+        &mut self,
+        document_id: &str,
+        folder_id: Option<String>,
+        index: usize,
+        user_id: &str,
+    ) -> Result<Vec<u8>, JsValue> {
+        self.check_parent(folder_id.as_deref())?;
+        let position = position_at(&self.document_positions(folder_id.as_deref(), document_id), index);
+        let stamp = self.tick(user_id);
+        self.apply_place(document_id, folder_id.clone(), position, stamp);
+
+        let mut data = HashMap::new();
+        data.insert("document_id".to_string(), ciborium::Value::Text(document_id.to_string()));
+        data.insert("folder_id".to_string(), optional_text(folder_id));
+        data.insert("position".to_string(), ciborium::Value::Float(position));
+        data.insert("lamport".to_string(), ciborium::Value::from(self.clock));
+        data.insert("timestamp".to_string(), ciborium::Value::Float(js_sys::Date::now()));
+        data.insert("user_id".to_string(), ciborium::Value::Text(user_id.to_string()));
+        Ok(encode_promisegrid_payload("document_place", data))
+    }
+
+    /// Apply a folder tree control message from a peer. Returns true if the
+    /// message was newer than the state it touches. Messages with a Lamport
+    /// time implausibly far ahead are refused.
+    pub fn apply_control_message(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let data = &message.payload.data;
+        let lamport = data_u64(data, "lamport")
+            .ok_or_else(|| JsValue::from_str("Control message is missing lamport time"))?;
+        if !clock::plausible(self.clock, lamport) {
+            return Err(JsValue::from_str(&format!(
+                "Control message Lamport time {} is too far ahead of ours ({})",
+                lamport, self.clock
+            )));
+        }
+        self.clock = self.clock.max(lamport);
+        let stamp = (lamport, data_text(data, "user_id").unwrap_or("unknown").to_string());
+        let position = data_f64(data, "position").unwrap_or(0.0);
+        let id = |key: &str| {
+            data_text(data, key).ok_or_else(|| JsValue::from_str(&format!("Control message is missing {}", key)))
+        };
+        let optional = |key: &str| data_text(data, key).map(str::to_string);
+
+        let changed = match message.payload.message_type.as_str() {
+            "folder_create" => {
+                let name = data_text(data, "name").unwrap_or("").trim();
+                self.apply_create(id("folder_id")?, name, optional("parent_id"), position, stamp)
+            }
+            "folder_rename" => self.apply_rename(id("folder_id")?, data_text(data, "name").unwrap_or("").trim(), stamp),
+            "folder_move" => self.apply_move(id("folder_id")?, optional("parent_id"), position, stamp),
+            "folder_delete" => self.apply_delete(id("folder_id")?, stamp),
+            "document_place" => self.apply_place(id("document_id")?, optional("folder_id"), position, stamp),
+            other => return Err(JsValue::from_str(&format!("Unsupported control message: {}", other))),
+        };
+        Ok(changed)
+    }
+
+    /// Folder of a document as shown in the tree (undefined for the root)
+    pub fn document_folder(&self, document_id: &str) -> Option<String> {
+        let placement = self.documents.get(document_id)?;
+        self.visible_folder(placement.folder.as_deref())
+    }
+
+    /// Forget a document, e.g. after it has been purged from the workspace
+    pub fn remove_document(&mut self, document_id: &str) {
+        self.documents.remove(document_id);
+    }
+
+    /// The visible tree as nested JSON:
+    /// `{folders: [{id, name, folders, documents}], documents: [ids]}`,
+    /// siblings in display order
+    pub fn to_json(&self) -> String {
+        let mut folders: Children = HashMap::new();
+        for (id, folder) in self.folders.iter().filter(|(_, f)| !f.deleted) {
+            folders.entry(self.visible_parent(id)).or_default().push((folder.position, id.clone()));
+        }
+        let mut documents: Children = HashMap::new();
+        for (id, placement) in &self.documents {
+            let folder = self.visible_folder(placement.folder.as_deref());
+            documents.entry(folder).or_default().push((placement.position, id.clone()));
+        }
+        for siblings in folders.values_mut().chain(documents.values_mut()) {
+            siblings.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+        }
+
+        let view = TreeView {
+            folders: self.folder_nodes(None, &folders, &documents),
+            documents: child_ids(&documents, None),
+        };
+        serde_json::to_string(&view).unwrap_or_default()
+    }
+
+    /// Serialize a snapshot to CBOR for persistence
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<FolderTree, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
+
+impl FolderTree {
+    fn folder_nodes(&self, parent: Option<String>, folders: &Children, documents: &Children) -> Vec<FolderNode> {
+        child_ids(folders, parent)
+            .into_iter()
+            .map(|id| FolderNode {
+                name: self.folders[&id].name.clone(),
+                folders: self.folder_nodes(Some(id.clone()), folders, documents),
+                documents: child_ids(documents, Some(id.clone())),
+                id,
+            })
+            .collect()
+    }
+
+    fn tick(&mut self, user_id: &str) -> Stamp {
+        self.clock = self.clock.saturating_add(1);
+        (self.clock, user_id.to_string())
+    }
+
+    fn check_folder(&self, folder_id: &str) -> Result<(), JsValue> {
+        match self.folders.get(folder_id) {
+            Some(folder) if !folder.deleted => Ok(()),
+            _ => Err(JsValue::from_str(&format!("Unknown folder: {}", folder_id))),
+        }
+    }
+
+    fn check_parent(&self, parent_id: Option<&str>) -> Result<(), JsValue> {
+        parent_id.map_or(Ok(()), |id| self.check_folder(id))
+    }
+
+    /// Stored (not visible) ancestors of a folder, stopping at a cycle
+    fn ancestors(&self, folder_id: &str) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut current = self.folders.get(folder_id).and_then(|f| f.parent.clone());
+        while let Some(id) = current {
+            if id == folder_id || chain.contains(&id) {
+                break;
+            }
+            current = self.folders.get(&id).and_then(|f| f.parent.clone());
+            chain.push(id);
+        }
+        chain
+    }
+
+    /// Nearest live folder at or above `folder_id`, None for the root
+    fn visible_folder(&self, folder_id: Option<&str>) -> Option<String> {
+        let id = folder_id?;
+        let folder = self.folders.get(id)?;
+        if !folder.deleted {
+            return Some(id.to_string());
+        }
+        self.ancestors(id).into_iter().find(|a| self.folders.get(a).is_some_and(|f| !f.deleted))
+    }
+
+    /// Parent under which a live folder is displayed
+    fn visible_parent(&self, folder_id: &str) -> Option<String> {
+        if self.in_cycle(folder_id) {
+            return None;
+        }
+        let parent = self.folders.get(folder_id)?.parent.clone();
+        self.visible_folder(parent.as_deref())
+    }
+
+    /// Whether concurrent moves have made a folder its own ancestor
+    fn in_cycle(&self, folder_id: &str) -> bool {
+        let mut seen = HashSet::new();
+        let mut current = self.folders.get(folder_id).and_then(|f| f.parent.clone());
+        while let Some(id) = current {
+            if id == folder_id {
+                return true;
+            }
+            if !seen.insert(id.clone()) {
+                return false;
+            }
+            current = self.folders.get(&id).and_then(|f| f.parent.clone());
+        }
+        false
+    }
+
+    fn folder_positions(&self, parent: Option<&str>, exclude: &str) -> Vec<f64> {
+        let mut positions: Vec<f64> = self
+            .folders
+            .iter()
+            .filter(|(id, f)| !f.deleted && id.as_str() != exclude && f.parent.as_deref() == parent)
+            .map(|(_, f)| f.position)
+            .collect();
+        positions.sort_by(f64::total_cmp);
+        positions
+    }
+
+    fn document_positions(&self, folder: Option<&str>, exclude: &str) -> Vec<f64> {
+        let mut positions: Vec<f64> = self
+            .documents
+            .iter()
+            .filter(|(id, p)| id.as_str() != exclude && p.folder.as_deref() == folder)
+            .map(|(_, p)| p.position)
+            .collect();
+        positions.sort_by(f64::total_cmp);
+        positions
+    }
+
+    /// Folder for an incoming message, created as an unnamed placeholder if
+    /// its `folder_create` hasn't arrived yet so no update is lost
+    fn folder_entry(&mut self, folder_id: &str) -> &mut Folder {
+        self.folders.entry(folder_id.to_string()).or_insert_with(|| Folder {
+            name: String::new(),
+            parent: None,
+            position: 0.0,
+            deleted: false,
+            name_changed: (0, String::new()),
+            placed: (0, String::new()),
+            deleted_changed: None,
+        })
+    }
+
+    fn apply_create(&mut self, folder_id: &str, name: &str, parent: Option<String>, position: f64, stamp: Stamp) -> bool {
+        let renamed = self.apply_rename(folder_id, name, stamp.clone());
+        let moved = self.apply_move(folder_id, parent, position, stamp);
+        renamed || moved
+    }
+
+    fn apply_rename(&mut self, folder_id: &str, name: &str, stamp: Stamp) -> bool {
+        let folder = self.folder_entry(folder_id);
+        if name.is_empty() || stamp <= folder.name_changed {
+            return false;
+        }
+        folder.name = name.to_string();
+        folder.name_changed = stamp;
+        true
+    }
+
+    fn apply_move(&mut self, folder_id: &str, parent: Option<String>, position: f64, stamp: Stamp) -> bool {
+        let folder = self.folder_entry(folder_id);
+        if stamp <= folder.placed {
+            return false;
+        }
+        folder.parent = parent;
+        folder.position = position;
+        folder.placed = stamp;
+        true
+    }
+
+    fn apply_delete(&mut self, folder_id: &str, stamp: Stamp) -> bool {
+        let folder = self.folder_entry(folder_id);
+        if !supersedes(&stamp, &folder.deleted_changed) {
+            return false;
+        }
+        folder.deleted_changed = Some(stamp);
+        let changed = !folder.deleted;
+        folder.deleted = true;
+        changed
+    }
+
+    fn apply_place(&mut self, document_id: &str, folder: Option<String>, position: f64, stamp: Stamp) -> bool {
+        if self.documents.get(document_id).is_some_and(|p| stamp <= p.placed) {
+            return false;
+        }
+        self.documents.insert(document_id.to_string(), Placement { folder, position, placed: stamp });
+        true
+    }
+}
+
+fn optional_text(value: Option<String>) -> ciborium::Value {
+    value.map_or(ciborium::Value::Null, ciborium::Value::Text)
+}
+
+/// Common fields of folder messages
+fn tree_data(folder_id: &str, lamport: u64, user_id: &str) -> HashMap<String, ciborium::Value> {
+    let mut data = HashMap::new();
+    data.insert("folder_id".to_string(), ciborium::Value::Text(folder_id.to_string()));
+    data.insert("lamport".to_string(), ciborium::Value::from(lamport));
+    data.insert("timestamp".to_string(), ciborium::Value::Float(js_sys::Date::now()));
+    data.insert("user_id".to_string(), ciborium::Value::Text(user_id.to_string()));
+    data
+}
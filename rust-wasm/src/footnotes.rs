@@ -0,0 +1,250 @@
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::markdown::{apply_edits, code_ranges, in_ranges};
+
+/// A `[^label]: text` definition, including indented continuation lines
+#[derive(Debug, Clone)]
+pub(crate) struct FootnoteDef {
+    pub label: String,
+    /// Byte range of the whole definition block
+    pub start: usize,
+    pub end: usize,
+    /// Byte offset of the label inside `[^...]`
+    pub label_start: usize,
+}
+
+/// A `[^label]` reference in the text
+#[derive(Debug, Clone)]
+pub(crate) struct FootnoteRef {
+    pub label: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize)]
+struct Orphan {
+    label: String,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize)]
+struct OrphanReport {
+    missing_definitions: Vec<Orphan>,
+    unused_definitions: Vec<Orphan>,
+}
+
+#[derive(Serialize)]
+struct InsertResult {
+    content: String,
+    label: String,
+    cursor: usize,
+}
+
+fn valid_label(label: &str) -> bool {
+    !label.is_empty() && !label.contains(|c: char| c.is_whitespace() || c == ']' || c == '[')
+}
+
+/// Parse footnote definitions. A definition starts at the beginning of a
+/// line; following lines indented by 4 spaces or a tab (optionally after
+/// blank lines) belong to it.
+pub(crate) fn parse_definitions(content: &str) -> Vec<FootnoteDef> {
+    let skip = code_ranges(content);
+    let lines: Vec<(usize, &str)> = {
+        let mut offset = 0;
+        content
+            .split_inclusive('\n')
+            .map(|line| {
+                let start = offset;
+                offset += line.len();
+                (start, line)
+            })
+            .collect()
+    };
+
+    let mut defs = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let (start, line) = lines[i];
+        i += 1;
+        if in_ranges(&skip, start) || !line.starts_with("[^") {
+            continue;
+        }
+        let Some(close) = line.find("]:") else { continue };
+        let label = &line[2..close];
+        if !valid_label(label) {
+            continue;
+        }
+
+        // Extend over continuation lines
+        let mut end = start + line.trim_end_matches(['\n', '\r']).len();
+        let mut j = i;
+        while j < lines.len() {
+            let (next_start, next) = lines[j];
+            if next.starts_with("    ") || next.starts_with('\t') {
+                end = next_start + next.trim_end_matches(['\n', '\r']).len();
+                j += 1;
+                i = j;
+            } else if next.trim().is_empty() {
+                j += 1;
+            } else {
+                break;
+            }
+        }
+        defs.push(FootnoteDef {
+            label: label.to_string(),
+            start,
+            end,
+            label_start: start + 2,
+        });
+    }
+    defs
+}
+
+/// Parse footnote references (`[^label]` not starting a definition)
+pub(crate) fn parse_references(content: &str, defs: &[FootnoteDef]) -> Vec<FootnoteRef> {
+    let skip = code_ranges(content);
+    content
+        .match_indices("[^")
+        .filter(|(pos, _)| !in_ranges(&skip, *pos) && !defs.iter().any(|d| d.start == *pos))
+        .filter_map(|(pos, _)| {
+            let close = content[pos..].find(']')? + pos;
+            let label = &content[pos + 2..close];
+            valid_label(label).then(|| FootnoteRef {
+                label: label.to_string(),
+                start: pos,
+                end: close + 1,
+            })
+        })
+        .collect()
+}
+
+/// Insert a new footnote reference at `cursor` (a byte offset) and append
+/// its definition at the end of the document. The label is the next free
+/// number. Returns JSON `{content, label, cursor}` where `cursor` is the
+/// offset just after the inserted reference.
+#[wasm_bindgen]
+pub fn insert_footnote(content: &str, cursor: usize, text: &str) -> String {
+    let defs = parse_definitions(content);
+    let refs = parse_references(content, &defs);
+    let next = defs
+        .iter()
+        .map(|d| d.label.as_str())
+        .chain(refs.iter().map(|r| r.label.as_str()))
+        .filter_map(|l| l.parse::<u32>().ok())
+        .max()
+        .unwrap_or(0)
+        + 1;
+
+    let mut cursor = cursor.min(content.len());
+    while !content.is_char_boundary(cursor) {
+        cursor -= 1;
+    }
+    let reference = format!("[^{}]", next);
+    let mut result = format!("{}{}{}", &content[..cursor], reference, &content[cursor..]);
+
+    // Keep a blank line between the body and the first definition
+    let body = result.trim_end().to_string();
+    let separator = if defs.is_empty() { "\n\n" } else { "\n" };
+    result = format!("{}{}[^{}]: {}\n", body, separator, next, text.trim());
+
+    let out = InsertResult {
+        content: result,
+        label: next.to_string(),
+        cursor: cursor + reference.len(),
+    };
+    serde_json::to_string(&out).unwrap_or_default()
+}
+
+/// Renumber numeric footnotes 1..n in order of first reference.
+/// Named footnotes (`[^note]`) keep their labels; numeric definitions that
+/// are never referenced are numbered after the referenced ones.
+#[wasm_bindgen]
+pub fn renumber_footnotes(content: &str) -> String {
+    let defs = parse_definitions(content);
+    let refs = parse_references(content, &defs);
+
+    let mut mapping: HashMap<&str, String> = HashMap::new();
+    let numeric = |l: &str| l.parse::<u32>().is_ok();
+    for label in refs.iter().map(|r| r.label.as_str()).chain(defs.iter().map(|d| d.label.as_str())) {
+        if numeric(label) && !mapping.contains_key(label) {
+            let n = mapping.len() + 1;
+            mapping.insert(label, n.to_string());
+        }
+    }
+
+    let mut edits = Vec::new();
+    for r in &refs {
+        if let Some(new) = mapping.get(r.label.as_str()) {
+            edits.push((r.start + 2, r.end - 1, new.clone()));
+        }
+    }
+    for d in &defs {
+        if let Some(new) = mapping.get(d.label.as_str()) {
+            edits.push((d.label_start, d.label_start + d.label.len(), new.clone()));
+        }
+    }
+    apply_edits(content, edits)
+}
+
+/// Move all footnote definitions to the end of the document, ordered by
+/// first reference (unreferenced definitions last).
+#[wasm_bindgen]
+pub fn move_footnotes_to_end(content: &str) -> String {
+    let defs = parse_definitions(content);
+    if defs.is_empty() {
+        return content.to_string();
+    }
+    let refs = parse_references(content, &defs);
+
+    let position = |label: &str| refs.iter().position(|r| r.label == label).unwrap_or(usize::MAX);
+    let mut ordered: Vec<&FootnoteDef> = defs.iter().collect();
+    ordered.sort_by_key(|d| (position(&d.label), d.start));
+
+    // Remove the definitions along with their line break. If a definition
+    // sits after a blank line, swallow the blank lines after it too so no
+    // double gaps are left behind.
+    let edits = defs
+        .iter()
+        .map(|d| {
+            let after_blank = d.start == 0 || content[..d.start].ends_with("\n\n");
+            let rest = &content[d.end..];
+            let trailing = if after_blank {
+                rest.len() - rest.trim_start_matches(['\n', '\r']).len()
+            } else {
+                rest.len() - rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n')).unwrap_or(rest).len()
+            };
+            (d.start, d.end + trailing, String::new())
+        })
+        .collect();
+    let body = apply_edits(content, edits);
+    let body = body.trim_end();
+
+    let definitions: Vec<&str> = ordered.iter().map(|d| &content[d.start..d.end]).collect();
+    format!("{}\n\n{}\n", body, definitions.join("\n"))
+}
+
+/// List footnote references without a definition and definitions that are
+/// never referenced, as JSON
+/// `{missing_definitions: [{label, start, end}], unused_definitions: [...]}`.
+#[wasm_bindgen]
+pub fn find_orphan_footnotes(content: &str) -> String {
+    let defs = parse_definitions(content);
+    let refs = parse_references(content, &defs);
+
+    let report = OrphanReport {
+        missing_definitions: refs
+            .iter()
+            .filter(|r| !defs.iter().any(|d| d.label == r.label))
+            .map(|r| Orphan { label: r.label.clone(), start: r.start, end: r.end })
+            .collect(),
+        unused_definitions: defs
+            .iter()
+            .filter(|d| !refs.iter().any(|r| r.label == d.label))
+            .map(|d| Orphan { label: d.label.clone(), start: d.start, end: d.end })
+            .collect(),
+    };
+    serde_json::to_string(&report).unwrap_or_default()
+}
@@ -0,0 +1,577 @@
+// The format_text pipeline, plus block-level variants for formatting part
+// of a large document without reprocessing all of it.
+
+use wasm_bindgen::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use crate::emphasis::fix_emphasis;
+use crate::front_matter::{front_matter_range, split_front_matter};
+use crate::headings::{parse_headings, setext_heading, Heading};
+use crate::lint_scheduler::{hash_block, split_blocks, BlockRange};
+use crate::markdown::{code_ranges, indented_code_ranges, line_of, mask_ranges, unmask};
+use crate::punctuation::{fix_punctuation, Locale};
+use crate::{math, url};
+
+/// Compile a regex once and reuse it across calls
+pub(crate) fn cached(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+/// Which steps of the formatting pipeline to run. Missing fields take
+/// their default, so `{"punctuation": false}` is a complete options object.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct FormatOptions {
+    /// Collapse repeated spaces and blank lines, trim trailing spaces
+    pub whitespace: bool,
+    /// Normalize the space after `#` in headers and setext underlines
+    pub headers: bool,
+    /// Trim blank lines inside fenced code blocks
+    pub code_blocks: bool,
+    /// Remove stray spaces inside `**bold**`, `*italic*` and `~~strike~~`
+    pub emphasis: bool,
+    /// Remove spaces before punctuation and doubled `..`/`,,`
+    pub punctuation: bool,
+    /// Language whose punctuation spacing to follow ("en", "fr", ...)
+    pub locale: String,
+    /// Use `-` for every bullet list marker (off by default)
+    pub bullet_markers: bool,
+    /// Put a blank line before and after every header (off by default)
+    pub header_spacing: bool,
+    /// End the document with exactly one newline (off by default)
+    pub final_newline: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            whitespace: true,
+            headers: true,
+            code_blocks: true,
+            emphasis: true,
+            punctuation: true,
+            locale: "en".to_string(),
+            bullet_markers: false,
+            header_spacing: false,
+            final_newline: false,
+        }
+    }
+}
+
+// Format the text for better readability and consistency
+#[wasm_bindgen]
+pub fn format_text(input: &str) -> String {
+    format_with(input, &FormatOptions::default())
+}
+
+/// `format_text` with some steps switched on or off. `options` is a JSON
+/// object with boolean fields `whitespace`, `headers`, `code_blocks`,
+/// `emphasis`, `punctuation` (all on by default) and `bullet_markers`,
+/// `header_spacing`, `final_newline` (off by default), plus a `locale`
+/// tag for punctuation spacing ("en" by default).
+#[wasm_bindgen]
+pub fn format_text_with_options(input: &str, options: &str) -> Result<String, JsValue> {
+    Ok(format_with(input, &parse_options(options)?))
+}
+
+/// Options from JSON as `format_text_with_options` takes them; empty for
+/// the defaults
+fn parse_options(options: &str) -> Result<FormatOptions, JsValue> {
+    if options.trim().is_empty() {
+        return Ok(FormatOptions::default());
+    }
+    serde_json::from_str(options).map_err(|e| JsValue::from_str(&format!("Invalid format options: {}", e)))
+}
+
+/// The default format options as JSON, for building a settings panel
+#[wasm_bindgen]
+pub fn default_format_options() -> String {
+    serde_json::to_string(&FormatOptions::default()).unwrap_or_else(|_| "{}".to_string())
+}
+
+pub(crate) fn format_with(input: &str, options: &FormatOptions) -> String {
+    // Dropping a space can move where a URL or other protected span ends,
+    // exposing text the passes would now change; repeat until nothing
+    // does, so formatting is idempotent
+    let mut text = format_once(input, options);
+    if text == input {
+        return text;
+    }
+    for _ in 0..3 {
+        let next = format_once(&text, options);
+        if next == text {
+            break;
+        }
+        text = next;
+    }
+    text
+}
+
+fn format_once(input: &str, options: &FormatOptions) -> String {
+    // Front matter is YAML, not markdown: keep it exactly as written
+    let (front_matter, body) = split_front_matter(input);
+
+    // 1. Format code blocks, before they are masked
+    let body = if options.code_blocks { format_code_blocks(body) } else { body.to_string() };
+    let (mut text, protected) = mask_protected(&body);
+
+    // 2. Clean up extra whitespace and line breaks
+    if options.whitespace {
+        text = clean_whitespace(&text);
+    }
+
+    // 3. Use `-` for bullets
+    if options.bullet_markers {
+        text = text.split('\n').map(normalize_bullet).collect::<Vec<_>>().join("\n");
+    }
+
+    // 4-6. Punctuation, emphasis and headers
+    text = format_markdown(&text, &protected, options);
+
+    // 7. Blank lines around headers
+    if options.header_spacing {
+        text = space_headers(&text);
+    }
+    text = unmask(&text, &protected);
+
+    if !front_matter.is_empty() {
+        text = if text.is_empty() { front_matter.to_string() } else { format!("{}\n{}", front_matter, text) };
+    }
+    // 8. Exactly one trailing newline
+    if options.final_newline {
+        text = format!("{}\n", text.trim_end_matches(['\n', ' ']));
+    }
+    text
+}
+
+/// Spans no formatting step may touch: code (fenced, indented and
+/// inline), math and URLs. Sorted, with nested or overlapping spans merged.
+fn protected_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = code_ranges(text);
+    ranges.extend(indented_code_ranges(text));
+    ranges.extend(math::math_ranges(text));
+    ranges.extend(url::find_urls(text));
+    ranges.sort();
+
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start < last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Replace the protected spans of `text` with placeholders; restore them
+/// with `unmask`
+fn mask_protected(text: &str) -> (String, Vec<String>) {
+    mask_ranges(text, &protected_ranges(text))
+}
+
+/// The steps of the pipeline that only look within a block. Protected
+/// spans must already be masked; `protected` holds them for measuring.
+fn format_markdown(text: &str, protected: &[String], options: &FormatOptions) -> String {
+    let mut text = text.to_string();
+
+    // 4. Fix punctuation: no space before sentence punctuation, no
+    //    doubled `..` or `,,`
+    if options.punctuation {
+        text = fix_punctuation(&text, Locale::parse(&options.locale));
+    }
+
+    // 5. Fix bold, italic, strikethrough formatting. Dropping the spaces
+    //    inside delimiters never creates punctuation to fix.
+    if options.emphasis {
+        text = fix_emphasis(&text);
+    }
+
+    // 6. Fix markdown headers last, so setext underlines match the final
+    //    heading text
+    if options.headers {
+        text = fix_markdown_headers(&text, protected);
+    }
+    text
+}
+
+/// Format one block (paragraph, list or fenced code block). Unlike
+/// `format_text` this keeps the block's leading indentation and the blank
+/// lines around it, so the result can be spliced back in place. Steps that
+/// only make sense for a whole document (header spacing, the final
+/// newline) are skipped.
+fn format_block(block: &str, options: &FormatOptions) -> String {
+    let block = if options.code_blocks { format_code_blocks(block) } else { block.to_string() };
+    let (mut text, protected) = mask_protected(&block);
+    if options.whitespace {
+        text = collapse_spaces(&text);
+    }
+    if options.bullet_markers {
+        text = text.split('\n').map(normalize_bullet).collect::<Vec<_>>().join("\n");
+    }
+    unmask(&format_markdown(&text, &protected, options), &protected)
+}
+
+fn normalize_bullet(line: &str) -> String {
+    let content = line.trim_start_matches(' ');
+    let indent = &line[..line.len() - content.len()];
+    // `* * *` is a horizontal rule, not a list
+    let rule = content.chars().all(|c| c == '*' || c == ' ');
+    match content.strip_prefix("* ").or_else(|| content.strip_prefix("+ ")) {
+        Some(rest) if !rule => format!("{}- {}", indent, rest),
+        _ => line.to_string(),
+    }
+}
+
+fn is_atx_header(line: &str) -> bool {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    (1..=6).contains(&hashes) && line[hashes..].chars().next().is_none_or(|c| c == ' ')
+}
+
+fn space_headers(text: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut out: Vec<&str> = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        let header = is_atx_header(line);
+        if header && out.last().is_some_and(|l| !l.trim().is_empty()) {
+            out.push("");
+        }
+        out.push(line);
+        if header && lines.get(i + 1).is_some_and(|l| !l.trim().is_empty()) {
+            out.push("");
+        }
+    }
+    out.join("\n")
+}
+
+fn collapse_spaces(text: &str) -> String {
+    static MULTIPLE_SPACES: OnceLock<Regex> = OnceLock::new();
+    static TRAILING_SPACES: OnceLock<Regex> = OnceLock::new();
+    let re_multiple_spaces = cached(&MULTIPLE_SPACES, r"([^ \n]) {2,}");
+    let re_trailing_spaces = cached(&TRAILING_SPACES, r"(?m) +$");
+
+    let result = re_multiple_spaces.replace_all(text, "$1 ");
+    re_trailing_spaces.replace_all(&result, "").to_string()
+}
+
+fn clean_whitespace(text: &str) -> String {
+    static MULTIPLE_SPACES: OnceLock<Regex> = OnceLock::new();
+    static MULTIPLE_NEWLINES: OnceLock<Regex> = OnceLock::new();
+    static TRAILING_SPACES: OnceLock<Regex> = OnceLock::new();
+    let re_multiple_spaces = cached(&MULTIPLE_SPACES, r" {2,}");
+    let re_multiple_newlines = cached(&MULTIPLE_NEWLINES, r"\n{3,}");
+    let re_trailing_spaces = cached(&TRAILING_SPACES, r" +$");
+
+    let mut result = re_multiple_spaces.replace_all(text, " ").to_string();
+    result = re_multiple_newlines.replace_all(&result, "\n\n").to_string();
+    result = re_trailing_spaces.replace_all(&result, "").to_string();
+
+    result.trim().to_string()
+}
+
+/// Normalize headers: one space after the `#`s of ATX headers, and setext
+/// headers with the text trimmed and an even `===`/`---` underline as wide
+/// as the text once the `protected` spans masked in it are put back
+fn fix_markdown_headers(text: &str, protected: &[String]) -> String {
+    static HEADERS: OnceLock<Regex> = OnceLock::new();
+    let re_headers = cached(&HEADERS, r"^(#{1,6}) *(.+)$");
+
+    let setext: Vec<Heading> = parse_headings(text).into_iter().filter(|h| h.setext).collect();
+    let mut out = Vec::new();
+    let mut offset = 0;
+    let mut lines = text.split('\n');
+    while let Some(line) = lines.next() {
+        let start = offset;
+        offset += line.len() + 1;
+        if let Some(heading) = setext.iter().find(|h| h.start == start) {
+            let underline = lines.next().unwrap_or("");
+            offset += underline.len() + 1;
+            let width = unmask(&heading.text, protected).chars().count();
+            out.push(setext_heading(&heading.text, heading.level, width));
+            continue;
+        }
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        out.push(re_headers.replace(line, |caps: &regex::Captures| {
+            format!("{} {}", &caps[1], &caps[2].trim())
+        }).to_string());
+    }
+    out.join("\n")
+}
+
+fn format_code_blocks(text: &str) -> String {
+    static CODE_BLOCKS: OnceLock<Regex> = OnceLock::new();
+    let re_code_blocks = cached(&CODE_BLOCKS, r"```([a-zA-Z]*)\n((?s:.*?))\n```");
+
+    re_code_blocks.replace_all(text, |caps: &regex::Captures| {
+        let lang = &caps[1];
+        let code = caps[2].trim();
+        format!("```{}\n{}\n```", lang, code)
+    }).to_string()
+}
+
+/// A replacement of `content[start..end]` by `text` (byte offsets)
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct FormatEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// 1-based line of `start`
+    pub line: usize,
+}
+
+/// Blocks of `content` outside the front matter
+fn markdown_blocks(content: &str) -> Vec<BlockRange> {
+    let offset = front_matter_range(content).map_or(0, |(_, end)| end);
+    split_blocks(&content[offset..])
+        .into_iter()
+        .map(|b| BlockRange { start: b.start + offset, end: b.end + offset })
+        .collect()
+}
+
+fn block_edit(content: &str, block: BlockRange, options: &FormatOptions) -> Option<FormatEdit> {
+    let original = &content[block.start..block.end];
+    let formatted = format_block(original, options);
+    (formatted != original).then(|| FormatEdit {
+        start: block.start,
+        end: block.end,
+        text: formatted,
+        line: line_of(content, block.start),
+    })
+}
+
+fn edits_json(edits: &[FormatEdit]) -> String {
+    serde_json::to_string(edits).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Format only the blocks overlapping the byte range `start..end` (e.g. the
+/// selection or the paragraph being typed in). Returns JSON edits
+/// `[{start, end, text, line}]` against `content`, sorted by offset; apply
+/// them from last to first. Blank lines between blocks are left alone.
+#[wasm_bindgen]
+pub fn format_range(content: &str, start: usize, end: usize) -> String {
+    range_edits(content, start, end, &FormatOptions::default())
+}
+
+/// `format_range` with the steps chosen by `options`, JSON as for
+/// `format_text_with_options`
+#[wasm_bindgen]
+pub fn format_range_with_options(content: &str, start: usize, end: usize, options: &str) -> Result<String, JsValue> {
+    Ok(range_edits(content, start, end, &parse_options(options)?))
+}
+
+fn range_edits(content: &str, start: usize, end: usize, options: &FormatOptions) -> String {
+    let (start, end) = (start.min(end), start.max(end));
+    let edits: Vec<FormatEdit> = markdown_blocks(content)
+        .into_iter()
+        .filter(|b| b.start <= end && b.end >= start)
+        .filter_map(|b| block_edit(content, b, options))
+        .collect();
+    edits_json(&edits)
+}
+
+/// Formats a document incrementally: each call only reformats blocks that
+/// changed since the previous call. Blocks are tracked by content hash, so
+/// text that moves without changing is not formatted again.
+#[wasm_bindgen]
+pub struct IncrementalFormatter {
+    /// Hashes of blocks already in formatted form under `options`
+    clean: HashSet<u64>,
+    options: FormatOptions,
+}
+
+impl Default for IncrementalFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl IncrementalFormatter {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> IncrementalFormatter {
+        IncrementalFormatter { clean: HashSet::new(), options: FormatOptions::default() }
+    }
+
+    /// Use the steps chosen by `options`, JSON as for
+    /// `format_text_with_options`. Blocks formatted under other options
+    /// are formatted again on the next call.
+    pub fn set_options(&mut self, options: &str) -> Result<(), JsValue> {
+        let options = parse_options(options)?;
+        if options != self.options {
+            self.options = options;
+            self.clean.clear();
+        }
+        Ok(())
+    }
+
+    /// Format the dirty blocks of `content`. Returns JSON edits like
+    /// `format_range`; the first call covers the whole document.
+    pub fn format(&mut self, content: &str) -> String {
+        let blocks = markdown_blocks(content);
+        let mut live = HashSet::with_capacity(blocks.len());
+        let mut edits = Vec::new();
+
+        for block in blocks {
+            let hash = hash_block(&content[block.start..block.end]);
+            if !self.clean.contains(&hash) {
+                if let Some(edit) = block_edit(content, block, &self.options) {
+                    live.insert(hash_block(&edit.text));
+                    edits.push(edit);
+                    continue;
+                }
+            }
+            live.insert(hash);
+        }
+        // Only remember blocks that still exist
+        self.clean = live;
+        edits_json(&edits)
+    }
+
+    /// Number of blocks in `content` that the next `format` call would
+    /// look at
+    pub fn dirty_count(&self, content: &str) -> usize {
+        markdown_blocks(content)
+            .iter()
+            .filter(|b| !self.clean.contains(&hash_block(&content[b.start..b.end])))
+            .count()
+    }
+
+    /// Forget what has been formatted, so the next call covers everything
+    pub fn reset(&mut self) {
+        self.clean.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs already as the formatter would leave them, each with
+    /// something a formatting step would otherwise change
+    const UNTOUCHED: &[&str] = &[
+        // Code
+        "Use `a  b , c` here.",
+        "Run ``x  `y`  ,z`` now.",
+        "```\nlet x  =  1 ,2;\n\n\nfoo()\n```",
+        "```rust\nfn main()  { }  ..\n```",
+        "~~~python\nx  =  [1 ,2]\n\n\n~~~",
+        "    indented  code ,here\n    # not a header",
+        "Text before.\n\n    let x  =  1 ,2;\n\n    y  ..  z\n\nText after.",
+        // Math
+        "Inline math $a  ,b$ and $$x  ..  y$$ done.",
+        "$$\nf(x)  =  x ,\n$$",
+        r"Escaped \$5 ,and more.",
+        // URLs
+        "See https://example.com/a__b__c?x=1,,2&y=..3 now.",
+        "Link <https://example.com/**x**> here.",
+        "See www.example.com/a_b_c_ ok.",
+        "[text](https://example.com/a__b__ ,c)",
+        "Mail mailto:a..b@example.com today.",
+        "email me at a.b@example.com ok.",
+        // Front matter
+        "---\ntitle:  My  Doc\ntags: [a ,b]\n#  not a header\n---\n\nBody.",
+        // Punctuation that is already right
+        "Wait... really?",
+        "Pages 1.5-2.5, or 3.14.",
+        "Smile :) and :-( too.",
+        "e.g. and i.e. stay.",
+        // Non-ASCII
+        "日本語",
+        "é*é*é",
+        "日本語のテキスト。句読点、そのまま。",
+        "中文**粗体**文字",
+        "Ünïcödé text, fine.",
+        "Café au lait.",
+        "Emoji 🎉 party!",
+        "Привет, мир.",
+        "x*y*z math-ish",
+        "a_b_c snake_case_name",
+        // Structure
+        "# Header",
+        "Title\n=====",
+        "- item one\n- item two",
+        "1. one\n2. two",
+        "> quote text",
+        "| a | b |\n|---|---|\n| 1 | 2 |",
+        "**bold** and *it* and ~~s~~.",
+    ];
+
+    #[test]
+    fn leaves_formatted_input_untouched() {
+        for input in UNTOUCHED {
+            assert_eq!(format_text(input), *input, "format_text changed {:?}", input);
+        }
+    }
+
+    #[test]
+    fn blocks_leave_formatted_input_untouched() {
+        for input in UNTOUCHED {
+            assert_eq!(format_block(input, &FormatOptions::default()), *input, "format_block changed {:?}", input);
+        }
+    }
+
+    /// Inputs on which passes used to undo or redo each other's work
+    const UNSTABLE: &[&str] = &[
+        "**\t.     **",
+        "** ** a ** **",
+        "#!.",
+        "#! .",
+        "# ! .",
+        "Title . ,\n---",
+        "---\n---",
+        "www.a.com? )[\t):\n [(é[",
+        "#www.a.com** ---_** **www.a.com;",
+    ];
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let pieces = [
+            "*", "**", "_", "~~", "#", "!", ".", ",", ";", ":", "?", " ", "  ", "\t", "\n", "a", "Tx", "-", "---",
+            "=", "`", "$", "(", ")", "[", "]", "1", ">", "\"", "é", "www.a.com", "https://a.b",
+        ];
+        // xorshift, so failures reproduce
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        let generated = (0..10_000).map(|_| (0..next() % 16).map(|_| pieces[next() % pieces.len()]).collect::<String>());
+        for input in UNSTABLE.iter().map(|s| s.to_string()).chain(generated) {
+            let once = format_text(&input);
+            assert_eq!(format_text(&once), once, "format_text is not idempotent on {:?}", input);
+        }
+    }
+
+    #[test]
+    fn setext_underline_matches_final_text() {
+        assert_eq!(format_text("Title . ,\n---"), "Title.,\n-------");
+        assert_eq!(format_text("Use `a  b` now\n==="), "Use `a  b` now\n==============");
+    }
+
+    #[test]
+    fn block_formatting_follows_options() {
+        let content = "Wait , what ?\n\nSecond  para .";
+        assert_ne!(format_range(content, 0, 0), "[]");
+        let options = r#"{"punctuation": false, "whitespace": false}"#;
+        assert_eq!(format_range_with_options(content, 0, content.len(), options).unwrap(), "[]");
+
+        let mut formatter = IncrementalFormatter::new();
+        formatter.set_options(options).unwrap();
+        assert_eq!(formatter.format(content), "[]");
+        formatter.set_options("").unwrap();
+        assert_eq!(formatter.dirty_count(content), 2);
+        assert_ne!(formatter.format(content), "[]");
+    }
+
+    #[test]
+    fn french_locale_keeps_its_spacing() {
+        let options = FormatOptions { locale: "fr".to_string(), ..FormatOptions::default() };
+        for input in ["Vraiment ?", "Attention : « ici » !", "Prix : 5 € ; voilà."] {
+            assert_eq!(format_with(input, &options), input);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+// YAML front matter (`---` ... `---` at the top of a document). Only the
+// subset of YAML used for document metadata is understood: mappings,
+// block and flow sequences, flow mappings, block scalars and plain or
+// quoted scalars.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Location of a front matter block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct FrontMatter {
+    /// Byte range of the YAML between the delimiter lines
+    pub yaml_start: usize,
+    pub yaml_end: usize,
+    /// Offset where the markdown body starts (after the closing delimiter)
+    pub body_start: usize,
+}
+
+#[derive(Serialize)]
+struct ParsedFrontMatter<'a> {
+    front_matter: Option<Map<String, Value>>,
+    body: &'a str,
+    body_start: usize,
+    error: Option<String>,
+}
+
+/// Find the front matter block, which must open on the very first line
+pub(crate) fn find_front_matter(content: &str) -> Option<FrontMatter> {
+    let first = content.split_inclusive('\n').next()?;
+    if first.trim_end() != "---" {
+        return None;
+    }
+    let yaml_start = first.len();
+    let mut offset = yaml_start;
+    for line in content[yaml_start..].split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return Some(FrontMatter {
+                yaml_start,
+                yaml_end: offset,
+                body_start: offset + line.len(),
+            });
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Byte range of the whole front matter block, delimiters included
+pub(crate) fn front_matter_range(content: &str) -> Option<(usize, usize)> {
+    find_front_matter(content).map(|fm| (0, fm.body_start))
+}
+
+/// Split a document into its front matter block (delimiters included) and
+/// body
+pub(crate) fn split_front_matter(content: &str) -> (&str, &str) {
+    match find_front_matter(content) {
+        Some(fm) => content.split_at(fm.body_start),
+        None => ("", content),
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_ignorable(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Remove a ` # comment` from an unquoted value
+fn strip_comment(value: &str) -> &str {
+    match value.find(" #") {
+        Some(pos) if !value.starts_with(['"', '\'']) => value[..pos].trim_end(),
+        _ => value,
+    }
+}
+
+/// Split a flow collection body on top-level commas
+fn split_flow(body: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (i, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '{') => depth += 1,
+            (None, ']' | '}') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(body[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = body[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+/// Split `key: value` at the first colon followed by a space or line end
+fn split_key(line: &str) -> Option<(&str, &str)> {
+    let mut search = 0;
+    while let Some(pos) = line[search..].find(':').map(|p| p + search) {
+        let rest = &line[pos + 1..];
+        if rest.is_empty() || rest.starts_with([' ', '\t']) {
+            let key = line[..pos].trim().trim_matches(|c| c == '"' || c == '\'');
+            return (!key.is_empty()).then_some((key, rest.trim()));
+        }
+        search = pos + 1;
+    }
+    None
+}
+
+fn parse_double_quoted(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+/// Parse an inline scalar or flow collection
+fn parse_scalar(text: &str) -> Value {
+    let text = strip_comment(text.trim());
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return Value::String(parse_double_quoted(inner));
+    }
+    if let Some(inner) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        return Value::String(inner.replace("''", "'"));
+    }
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return Value::Array(split_flow(inner).into_iter().map(parse_scalar).collect());
+    }
+    if let Some(inner) = text.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+        let mut map = Map::new();
+        for entry in split_flow(inner) {
+            if let Some((key, value)) = split_key(entry) {
+                map.insert(key.to_string(), parse_scalar(value));
+            }
+        }
+        return Value::Object(map);
+    }
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+    if let Ok(n) = text.parse::<i64>() {
+        return Value::from(n);
+    }
+    if text.contains(['.', 'e', 'E']) && text.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') {
+        if let Some(n) = text.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+            return Value::Number(n);
+        }
+    }
+    Value::String(text.to_string())
+}
+
+/// Lines belonging to the block that follows line `i`: indented lines, and
+/// with `dash_items` also unindented `- item` lines (YAML allows a mapping
+/// value's list at the key's own indentation). Returns the dedented block and
+/// the index after it.
+fn nested_block<'a>(lines: &[&'a str], i: usize, dash_items: bool) -> (Vec<&'a str>, usize) {
+    let belongs = |line: &str| is_ignorable(line) || indent_of(line) > 0 || (dash_items && line.starts_with('-'));
+    let mut end = i;
+    while end < lines.len() && belongs(lines[end]) {
+        end += 1;
+    }
+    // Trailing blank lines belong to whatever comes next
+    while end > i && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    let block = &lines[i..end];
+    let min = block.iter().filter(|l| !is_ignorable(l)).map(|l| indent_of(l)).min().unwrap_or(0);
+    let dedented = block.iter().map(|l| if l.len() >= min { &l[min..] } else { l.trim_start() }).collect();
+    (dedented, end)
+}
+
+/// Value of a `key:` with nothing after the colon: a nested mapping,
+/// a block sequence, or null
+fn parse_block(lines: &[&str]) -> Result<Value, String> {
+    let first = lines.iter().find(|l| !is_ignorable(l));
+    match first {
+        None => Ok(Value::Null),
+        Some(line) if line.starts_with("- ") || line.trim_end() == "-" => parse_sequence(lines),
+        Some(_) => parse_mapping(lines).map(Value::Object),
+    }
+}
+
+fn parse_sequence(lines: &[&str]) -> Result<Value, String> {
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if is_ignorable(line) {
+            i += 1;
+            continue;
+        }
+        let Some(item) = line.strip_prefix('-') else {
+            return Err(format!("Expected a list item, found: {}", line.trim()));
+        };
+        let item = item.trim();
+        let (block, next) = nested_block(lines, i + 1, false);
+        i = next;
+        if item.is_empty() {
+            items.push(parse_block(&block)?);
+        } else if split_key(item).is_some() && !item.starts_with(['"', '\'', '[', '{']) {
+            // `- key: value` starts a mapping item
+            let mut entry = vec![item];
+            entry.extend(block);
+            items.push(Value::Object(parse_mapping(&entry)?));
+        } else {
+            items.push(parse_scalar(item));
+        }
+    }
+    Ok(Value::Array(items))
+}
+
+fn parse_mapping(lines: &[&str]) -> Result<Map<String, Value>, String> {
+    let mut map = Map::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if is_ignorable(line) {
+            i += 1;
+            continue;
+        }
+        if indent_of(line) > 0 {
+            return Err(format!("Unexpected indentation: {}", line.trim()));
+        }
+        let (key, value) = split_key(line).ok_or_else(|| format!("Expected `key: value`, found: {}", line.trim()))?;
+        let (block, next) = nested_block(lines, i + 1, value.is_empty());
+        i = next;
+        let value = match strip_comment(value) {
+            "" => parse_block(&block)?,
+            indicator @ ("|" | "|-" | ">" | ">-") => {
+                let joiner = if indicator.starts_with('|') { "\n" } else { " " };
+                let text = block.iter().map(|l| l.trim_end()).collect::<Vec<_>>().join(joiner);
+                Value::String(if indicator.ends_with('-') { text } else { format!("{}\n", text) })
+            }
+            _ => parse_scalar(value),
+        };
+        map.insert(key.to_string(), value);
+    }
+    Ok(map)
+}
+
+/// Parse front matter YAML into a JSON object
+pub(crate) fn parse_yaml(yaml: &str) -> Result<Map<String, Value>, String> {
+    let lines: Vec<&str> = yaml.lines().collect();
+    parse_mapping(&lines)
+}
+
+/// Whether a string must be quoted to survive as a plain YAML scalar
+fn needs_quotes(text: &str) -> bool {
+    text.is_empty()
+        || text != text.trim()
+        || text.contains(": ")
+        || text.contains(" #")
+        || text.contains(['\n', '\t'])
+        || text.ends_with(':')
+        || text.starts_with(['-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`'])
+        || !matches!(parse_scalar(text), Value::String(_))
+}
+
+/// Render a JSON value as an inline YAML value
+pub(crate) fn yaml_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::String(s) if needs_quotes(s) => serde_json::to_string(s).unwrap_or_default(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => format!("[{}]", items.iter().map(yaml_value).collect::<Vec<_>>().join(", ")),
+        Value::Object(map) => format!(
+            "{{{}}}",
+            map.iter().map(|(k, v)| format!("{}: {}", k, yaml_value(v))).collect::<Vec<_>>().join(", ")
+        ),
+        other => other.to_string(),
+    }
+}
+
+/// Parse the document's YAML front matter. Returns JSON
+/// `{front_matter, body, body_start, error}`: `front_matter` is an object
+/// (null when the document has none), `body` the markdown after it and
+/// `error` a message if the YAML couldn't be read.
+#[wasm_bindgen]
+pub fn parse_front_matter(content: &str) -> String {
+    let result = match find_front_matter(content) {
+        Some(fm) => {
+            let (front_matter, error) = match parse_yaml(&content[fm.yaml_start..fm.yaml_end]) {
+                Ok(map) => (Some(map), None),
+                Err(e) => (None, Some(e)),
+            };
+            ParsedFrontMatter { front_matter, body: &content[fm.body_start..], body_start: fm.body_start, error }
+        }
+        None => ParsedFrontMatter { front_matter: None, body: content, body_start: 0, error: None },
+    };
+    serde_json::to_string(&result).unwrap_or_default()
+}
+
+/// Set a top-level front matter field, creating the front matter block if
+/// needed. `value` is JSON (`"Title"`, `["a", "b"]`, `3`); anything that
+/// isn't valid JSON is stored as a plain string. Passing `undefined` removes
+/// the field. Other lines, comments and formatting are left as they are.
+#[wasm_bindgen]
+pub fn set_front_matter_field(content: &str, key: &str, value: Option<String>) -> Result<String, JsValue> {
+    let key = key.trim();
+    if key.is_empty() || key.contains([':', '\n', '#']) || key.starts_with(['-', '"', '\'']) {
+        return Err(JsValue::from_str(&format!("Invalid front matter key: {}", key)));
+    }
+    let line = value.map(|v| {
+        let value = serde_json::from_str(&v).unwrap_or(Value::String(v));
+        format!("{}: {}\n", key, yaml_value(&value))
+    });
+
+    let Some(fm) = find_front_matter(content) else {
+        return Ok(match line {
+            Some(line) => format!("---\n{}---\n\n{}", line, content),
+            None => content.to_string(),
+        });
+    };
+
+    // Locate the existing field: its key line plus any indented lines below
+    // (blank lines inside a block scalar included, trailing ones not)
+    let yaml = &content[fm.yaml_start..fm.yaml_end];
+    let mut offset = fm.yaml_start;
+    let mut field: Option<(usize, usize)> = None;
+    // An empty value may be followed by an unindented `- item` list
+    let mut dash_items = false;
+    for raw in yaml.split_inclusive('\n') {
+        let blank = raw.trim().is_empty();
+        let continues = indent_of(raw) > 0 || (dash_items && raw.starts_with('-'));
+        match field {
+            Some((start, _)) if !blank && continues => field = Some((start, offset + raw.len())),
+            Some(_) if blank => {}
+            Some(_) => break,
+            None if indent_of(raw) == 0 => {
+                if let Some((_, value)) = split_key(raw.trim_end()).filter(|(k, _)| *k == key) {
+                    dash_items = strip_comment(value).is_empty();
+                    field = Some((offset, offset + raw.len()));
+                }
+            }
+            None => {}
+        }
+        offset += raw.len();
+    }
+
+    // The YAML always ends with a newline (or is empty), so appending
+    // before the closing delimiter is safe
+    let replacement = line.unwrap_or_default();
+    let (start, end) = field.unwrap_or((fm.yaml_end, fm.yaml_end));
+    Ok(format!("{}{}{}", &content[..start], replacement, &content[end..]))
+}
+
+/// Remove the front matter block, returning only the markdown body
+#[wasm_bindgen]
+pub fn strip_front_matter(content: &str) -> String {
+    split_front_matter(content).1.trim_start_matches(['\n', '\r']).to_string()
+}
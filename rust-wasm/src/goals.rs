@@ -0,0 +1,228 @@
+// Writing goals: a word count to reach in a document, optionally by a
+// deadline, and how the writing is going against it (words today, words
+// this session, the daily pace still needed). A `word_goal` message shares
+// the goal and the sender's progress, so co-authors see the same target
+// and each other's work towards it.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::payload::{decode_payload, payload_data, Payload, WordGoal};
+use crate::{count_words, decode_with_grid_tag, encode_promisegrid_payload};
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Goal {
+    target: u64,
+    /// ms since epoch
+    deadline: Option<f64>,
+    set_by: String,
+    /// When it was set, so the newest goal wins
+    set_at: f64,
+}
+
+/// A co-author's progress, from their latest `word_goal` message
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Contribution {
+    user_id: String,
+    words_today: u64,
+    updated_at: f64,
+}
+
+/// Word counts tracked for one document
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Tracking {
+    goal: Option<Goal>,
+    /// Start of the day `day_start_words` belongs to
+    day_start: f64,
+    day_start_words: u64,
+    last_words: u64,
+    /// Words when this session first saw the document; not persisted
+    #[serde(skip)]
+    session_start_words: Option<u64>,
+    #[serde(default)]
+    contributions: BTreeMap<String, Contribution>,
+}
+
+impl Tracking {
+    /// Note the document has `words` words, `day_start` being the start
+    /// of the writer's current day
+    fn record(&mut self, words: u64, day_start: f64) {
+        if day_start != self.day_start {
+            // The first count of a new day: today starts from where the
+            // last day ended, or from here when nothing was recorded
+            self.day_start_words = if self.day_start == 0.0 { words } else { self.last_words };
+            self.day_start = day_start;
+        }
+        self.session_start_words.get_or_insert(words);
+        self.last_words = words;
+    }
+
+    fn words_today(&self) -> u64 {
+        self.last_words.saturating_sub(self.day_start_words)
+    }
+}
+
+#[derive(Serialize)]
+struct Progress<'a> {
+    target: Option<u64>,
+    deadline: Option<f64>,
+    words: u64,
+    remaining: u64,
+    /// 0 to 100
+    percent: f64,
+    words_today: u64,
+    words_session: u64,
+    /// Days left including today, when there's a deadline
+    days_left: Option<u64>,
+    /// Words a day needed from the start of today to make the deadline
+    pace_needed: Option<u64>,
+    /// Today's words meet the pace needed, or the goal is reached
+    on_track: bool,
+    collaborators: Vec<&'a Contribution>,
+}
+
+/// Word goals and progress per document. Feed it the text with
+/// `goal_progress` as the document changes; `day_start` is the start of
+/// the writer's day (local midnight) in ms, so "today" follows their clock.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WritingGoals {
+    documents: BTreeMap<String, Tracking>,
+}
+
+#[wasm_bindgen]
+impl WritingGoals {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WritingGoals {
+        WritingGoals::default()
+    }
+
+    /// Aim for `target` words in the document, optionally by `deadline`
+    /// (ms since epoch)
+    pub fn set_word_goal(&mut self, document_id: &str, target: u64, deadline: Option<f64>, user_id: &str, now: f64) -> Result<(), JsValue> {
+        if target == 0 {
+            return Err(JsValue::from_str("Word goal must be at least one word"));
+        }
+        if deadline.is_some_and(|deadline| !deadline.is_finite() || deadline <= 0.0) {
+            return Err(JsValue::from_str("Deadline must be a positive number of milliseconds"));
+        }
+        let tracking = self.documents.entry(document_id.to_string()).or_default();
+        tracking.goal = Some(Goal { target, deadline, set_by: user_id.to_string(), set_at: now });
+        Ok(())
+    }
+
+    /// Remove the document's goal; returns whether it had one
+    pub fn clear_goal(&mut self, document_id: &str) -> bool {
+        self.documents.get_mut(document_id).is_some_and(|tracking| tracking.goal.take().is_some())
+    }
+
+    /// Record the document's `content` and report progress as
+    /// JSON `{target, deadline, words, remaining, percent, words_today,
+    /// words_session, days_left, pace_needed, on_track, collaborators:
+    /// [{user_id, words_today, updated_at}]}`. Without a goal, `target`,
+    /// `deadline`, `days_left` and `pace_needed` are null.
+    pub fn goal_progress(&mut self, document_id: &str, content: &str, day_start: f64) -> String {
+        let tracking = self.documents.entry(document_id.to_string()).or_default();
+        tracking.record(count_words(content) as u64, day_start);
+        let words = tracking.last_words;
+        let words_today = tracking.words_today();
+        let goal = tracking.goal.as_ref();
+        let target = goal.map(|goal| goal.target);
+        let remaining = target.map_or(0, |target| target.saturating_sub(words));
+
+        let days_left = goal.and_then(|goal| goal.deadline).map(|deadline| {
+            let days = ((deadline - day_start) / DAY_MS).ceil();
+            if days.is_finite() && days > 0.0 { days as u64 } else { 0 }
+        });
+        // The pace is fixed for the day, so writing today doesn't raise it
+        let pace_needed = match (target, days_left) {
+            (Some(target), Some(days)) => Some(target.saturating_sub(tracking.day_start_words).div_ceil(days.max(1))),
+            _ => None,
+        };
+        let on_track = target.is_some_and(|_| remaining == 0)
+            || match pace_needed {
+                Some(pace) => days_left != Some(0) && words_today >= pace,
+                None => target.is_some(),
+            };
+        let percent = target.map_or(0.0, |target| (words as f64 / target as f64 * 100.0).min(100.0));
+
+        let progress = Progress {
+            target,
+            deadline: goal.and_then(|goal| goal.deadline),
+            words,
+            remaining,
+            percent: (percent * 10.0).round() / 10.0,
+            words_today,
+            words_session: words.saturating_sub(tracking.session_start_words.unwrap_or(words)),
+            days_left,
+            pace_needed,
+            on_track,
+            collaborators: tracking.contributions.values().collect(),
+        };
+        serde_json::to_string(&progress).unwrap_or_else(|_| "{}".into())
+    }
+
+    /// A `word_goal` message sharing the document's goal and `user_id`'s
+    /// progress today, as of the last `goal_progress`
+    pub fn goal_message(&self, document_id: &str, user_id: &str) -> Result<Vec<u8>, JsValue> {
+        let tracking = self.documents.get(document_id);
+        let goal = tracking
+            .and_then(|tracking| tracking.goal.as_ref())
+            .ok_or_else(|| JsValue::from_str(&format!("No word goal for document {}", document_id)))?;
+        let message = WordGoal {
+            document_id: document_id.to_string(),
+            user_id: user_id.to_string(),
+            target: goal.target,
+            deadline: goal.deadline,
+            set_by: goal.set_by.clone(),
+            set_at: goal.set_at,
+            words_today: tracking.map_or(0, Tracking::words_today),
+            timestamp: js_sys::Date::now(),
+        };
+        Ok(encode_promisegrid_payload("word_goal", payload_data(&message)))
+    }
+
+    /// Apply a co-author's `word_goal` message: take their goal if it was
+    /// set more recently than ours and note their progress. Returns false
+    /// for other messages.
+    pub fn apply_goal_message(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        if message.payload.message_type != "word_goal" {
+            return Ok(false);
+        }
+        let (_, Payload::WordGoal(shared)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Ok(false);
+        };
+        let tracking = self.documents.entry(shared.document_id).or_default();
+        let newer = tracking.goal.as_ref().is_none_or(|goal| (shared.set_at, &shared.set_by) > (goal.set_at, &goal.set_by));
+        if newer {
+            tracking.goal =
+                Some(Goal { target: shared.target, deadline: shared.deadline, set_by: shared.set_by, set_at: shared.set_at });
+        }
+        let contribution = tracking.contributions.entry(shared.user_id.clone()).or_insert_with(|| Contribution {
+            user_id: shared.user_id,
+            words_today: 0,
+            updated_at: f64::NEG_INFINITY,
+        });
+        if shared.timestamp >= contribution.updated_at {
+            contribution.words_today = shared.words_today;
+            contribution.updated_at = shared.timestamp;
+        }
+        Ok(true)
+    }
+
+    /// Serialize goals and daily counts to CBOR. Session counts aren't
+    /// kept.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<WritingGoals, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
@@ -0,0 +1,185 @@
+// Protocol negotiation between peers. On connecting, a client sends a
+// `hello` with the protocol versions it speaks and the compression,
+// encryption and editing modes it supports, most preferred first. Each
+// peer answers with a `hello_ack` naming the newest common version and,
+// for each kind of mode, the first of the sender's choices it supports
+// too; when there is none the ack says why and the peers stay apart.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::payload::{Hello, HelloAck};
+
+/// Newest protocol version this build speaks
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version this build still speaks
+const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Modes this build can offer, by kind
+const COMPRESSION_MODES: &[&str] = &["gzip", "none"];
+const ENCRYPTION_MODES: &[&str] = &["xchacha20poly1305", "none"];
+const EDITING_MODES: &[&str] = &["ot"];
+
+/// The modes a client offers, most preferred first. Leave "none" out of
+/// `encryption` to refuse peers that won't encrypt.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HandshakeOptions {
+    compression: Vec<String>,
+    encryption: Vec<String>,
+    editing: Vec<String>,
+}
+
+impl Default for HandshakeOptions {
+    fn default() -> Self {
+        let owned = |modes: &[&str]| modes.iter().map(|m| m.to_string()).collect();
+        HandshakeOptions {
+            compression: owned(COMPRESSION_MODES),
+            encryption: owned(ENCRYPTION_MODES),
+            editing: owned(EDITING_MODES),
+        }
+    }
+}
+
+impl HandshakeOptions {
+    /// Parse JSON `{compression, encryption, editing}`; missing fields (or
+    /// an empty string) use the defaults
+    pub(crate) fn parse(options: &str) -> Result<HandshakeOptions, JsValue> {
+        let options: HandshakeOptions = if options.trim().is_empty() {
+            HandshakeOptions::default()
+        } else {
+            serde_json::from_str(options).map_err(|e| JsValue::from_str(&format!("Invalid handshake options: {}", e)))?
+        };
+        for (kind, offered, supported) in [
+            ("compression", &options.compression, COMPRESSION_MODES),
+            ("encryption", &options.encryption, ENCRYPTION_MODES),
+            ("editing", &options.editing, EDITING_MODES),
+        ] {
+            if offered.is_empty() {
+                return Err(JsValue::from_str(&format!("Invalid handshake options: no {} modes", kind)));
+            }
+            if let Some(mode) = offered.iter().find(|m| !supported.contains(&m.as_str())) {
+                return Err(JsValue::from_str(&format!(
+                    "Invalid handshake options: unsupported {} mode {} (expected {})",
+                    kind,
+                    mode,
+                    supported.join(", ")
+                )));
+            }
+        }
+        Ok(options)
+    }
+
+    /// The `hello` announcing these options
+    pub(crate) fn hello(&self, user_id: &str, now: f64) -> Hello {
+        Hello {
+            user_id: user_id.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            compression: self.compression.clone(),
+            encryption: self.encryption.clone(),
+            editing: self.editing.clone(),
+            timestamp: now,
+        }
+    }
+
+    /// Choose the modes for `user_id` to work with the sender of `hello`,
+    /// or say why there are none
+    pub(crate) fn negotiate(&self, user_id: &str, hello: &Hello) -> Result<Mode, String> {
+        let version = PROTOCOL_VERSION.min(hello.protocol_version);
+        if version < MIN_PROTOCOL_VERSION.max(hello.min_protocol_version) {
+            return Err(format!(
+                "no protocol version in common ({} speaks {}-{}, {} speaks {}-{})",
+                user_id,
+                MIN_PROTOCOL_VERSION,
+                PROTOCOL_VERSION,
+                hello.user_id,
+                hello.min_protocol_version,
+                hello.protocol_version
+            ));
+        }
+        let choose = |kind: &str, theirs: &[String], ours: &[String]| {
+            theirs.iter().find(|mode| ours.contains(mode)).cloned().ok_or_else(|| {
+                format!(
+                    "no {} mode in common ({}: {}; {}: {})",
+                    kind,
+                    user_id,
+                    ours.join(", "),
+                    hello.user_id,
+                    theirs.join(", ")
+                )
+            })
+        };
+        Ok(Mode {
+            protocol_version: version,
+            compression: choose("compression", &hello.compression, &self.compression)?,
+            encryption: choose("encryption", &hello.encryption, &self.encryption)?,
+            editing: choose("editing", &hello.editing, &self.editing)?,
+        })
+    }
+
+    /// The modes a peer chose in answer to our `hello`, checked against
+    /// what we offered, or why they can't be used
+    pub(crate) fn accept(&self, ack: &HelloAck) -> Result<Mode, String> {
+        if let Some(error) = &ack.error {
+            return Err(format!("{} rejected the handshake: {}", ack.user_id, error));
+        }
+        let offered = |kind: &str, chosen: &Option<String>, ours: &[String]| match chosen {
+            Some(mode) if ours.contains(mode) => Ok(mode.clone()),
+            Some(mode) => Err(format!("{} chose {} mode {}, which this client didn't offer", ack.user_id, kind, mode)),
+            None => Err(format!("{} chose no {} mode", ack.user_id, kind)),
+        };
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&ack.protocol_version) {
+            return Err(format!(
+                "{} chose protocol version {}, which this client doesn't speak",
+                ack.user_id, ack.protocol_version
+            ));
+        }
+        Ok(Mode {
+            protocol_version: ack.protocol_version,
+            compression: offered("compression", &ack.compression, &self.compression)?,
+            encryption: offered("encryption", &ack.encryption, &self.encryption)?,
+            editing: offered("editing", &ack.editing, &self.editing)?,
+        })
+    }
+}
+
+/// What two peers agreed on
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct Mode {
+    pub protocol_version: u32,
+    pub compression: String,
+    pub encryption: String,
+    pub editing: String,
+}
+
+/// The `hello_ack` answering `hello` with the outcome of negotiating
+pub(crate) fn ack(user_id: &str, hello: &Hello, outcome: &Result<Mode, String>, now: f64) -> HelloAck {
+    let mut ack = HelloAck {
+        user_id: user_id.to_string(),
+        target_user_id: hello.user_id.clone(),
+        protocol_version: PROTOCOL_VERSION,
+        compression: None,
+        encryption: None,
+        editing: None,
+        error: None,
+        timestamp: now,
+    };
+    match outcome {
+        Ok(mode) => {
+            ack.protocol_version = mode.protocol_version;
+            ack.compression = Some(mode.compression.clone());
+            ack.encryption = Some(mode.encryption.clone());
+            ack.editing = Some(mode.editing.clone());
+        }
+        Err(reason) => ack.error = Some(reason.clone()),
+    }
+    ack
+}
+
+/// How the handshake with one peer ended
+pub(crate) enum PeerState {
+    Agreed(Mode),
+    Rejected(String),
+}
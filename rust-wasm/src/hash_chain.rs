@@ -0,0 +1,240 @@
+// Optional hash chaining of edit messages. Each chained edit carries the
+// SHA-256 of the previous edit message its sender had seen, so a relay that
+// drops, injects or alters edits leaves a break in the chain that anyone
+// holding the messages can find after the fact.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::{data_bytes, data_f64, data_text, decode_with_grid_tag, edit_data, encode_promisegrid_payload};
+
+/// SHA-256 of an encoded message, exactly as sent or received. Relays must
+/// forward message bytes unchanged for the chain to verify.
+pub(crate) fn message_hash(cbor_bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(cbor_bytes).into()
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+/// Hex SHA-256 of an encoded edit message: the value to pass as `prev_hash`
+/// for the next chained edit
+#[wasm_bindgen]
+pub fn edit_message_hash(cbor_bytes: &[u8]) -> String {
+    to_hex(&message_hash(cbor_bytes))
+}
+
+/// Create a document_edit message chained to the previous edit the sender
+/// observed. `prev_hash` is the hex hash from `edit_message_hash` (or
+/// `EditChainVerifier::head`), or empty for the first edit of a document.
+#[wasm_bindgen]
+pub fn create_chained_edit_message(
+    document_id: &str,
+    edit_type: &str,
+    position: u32,
+    content: &str,
+    user_id: &str,
+    prev_hash: &str,
+) -> Result<Vec<u8>, JsValue> {
+    let prev = from_hex(prev_hash.trim())
+        .filter(|bytes| bytes.is_empty() || bytes.len() == 32)
+        .ok_or_else(|| JsValue::from_str("prev_hash must be 64 hex characters or empty"))?;
+    let mut data = edit_data(document_id, edit_type, position, content, user_id);
+    data.insert("prev_hash".to_string(), ciborium::Value::Bytes(prev));
+    Ok(encode_promisegrid_payload("document_edit", data))
+}
+
+/// One edit as the verifier saw it
+struct ChainEntry {
+    hash: String,
+    /// `None` for an edit sent without chaining; `Some("")` for a first edit
+    prev: Option<String>,
+    user_id: String,
+    timestamp: f64,
+}
+
+#[derive(Serialize)]
+struct ChainIssue {
+    /// "unchained", "duplicate", "missing_predecessor", "out_of_order",
+    /// "multiple_roots" or "fork"
+    kind: &'static str,
+    /// "error" breaks the chain; "warning" is legitimate but worth showing
+    severity: &'static str,
+    hash: String,
+    user_id: String,
+    detail: String,
+}
+
+#[derive(Serialize)]
+struct ChainReport {
+    /// No errors: every edit links back to a single first edit
+    valid: bool,
+    edits: usize,
+    /// Edits with an empty prev_hash
+    roots: Vec<String>,
+    /// Edits no other edit builds on
+    heads: Vec<String>,
+    issues: Vec<ChainIssue>,
+}
+
+/// Collects the edit messages of a document and checks that they form an
+/// unbroken hash chain. Feed it every edit, local and remote, in the order
+/// they were observed.
+#[wasm_bindgen]
+pub struct EditChainVerifier {
+    entries: Vec<ChainEntry>,
+    /// Hash -> index of its first occurrence
+    index: HashMap<String, usize>,
+    duplicates: Vec<usize>,
+}
+
+impl Default for EditChainVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl EditChainVerifier {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> EditChainVerifier {
+        EditChainVerifier { entries: Vec::new(), index: HashMap::new(), duplicates: Vec::new() }
+    }
+
+    /// Record an edit message. Returns its hex hash.
+    pub fn observe(&mut self, cbor_bytes: &[u8]) -> Result<String, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        if message.payload.message_type != "document_edit" {
+            return Err(JsValue::from_str(&format!(
+                "Expected a document_edit message, got {}",
+                message.payload.message_type
+            )));
+        }
+        let data = &message.payload.data;
+        let hash = edit_message_hash(cbor_bytes);
+        let entry = ChainEntry {
+            hash: hash.clone(),
+            prev: data_bytes(data, "prev_hash").map(to_hex),
+            user_id: data_text(data, "user_id").unwrap_or("unknown").to_string(),
+            timestamp: data_f64(data, "timestamp").unwrap_or(0.0),
+        };
+        if self.index.contains_key(&hash) {
+            self.duplicates.push(self.entries.len());
+        } else {
+            self.index.insert(hash.clone(), self.entries.len());
+        }
+        self.entries.push(entry);
+        Ok(hash)
+    }
+
+    /// Hash of the most recently observed edit (empty if none): the
+    /// `prev_hash` for the next local edit
+    pub fn head(&self) -> String {
+        self.entries.last().map(|e| e.hash.clone()).unwrap_or_default()
+    }
+
+    /// Number of edits observed
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Rebuild the chain and report breaks as JSON `{valid, edits, roots,
+    /// heads, issues: [{kind, severity, hash, user_id, detail}]}`
+    pub fn verify(&self) -> String {
+        let mut issues = Vec::new();
+        let mut roots = Vec::new();
+        let mut children: HashMap<&str, Vec<usize>> = HashMap::new();
+        let issue = |kind, severity, entry: &ChainEntry, detail: String| ChainIssue {
+            kind,
+            severity,
+            hash: entry.hash.clone(),
+            user_id: entry.user_id.clone(),
+            detail,
+        };
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            if self.duplicates.contains(&i) {
+                issues.push(issue("duplicate", "warning", entry, "Same edit message received again".to_string()));
+                continue;
+            }
+            let Some(prev) = &entry.prev else {
+                issues.push(issue("unchained", "error", entry, "Edit has no prev_hash".to_string()));
+                continue;
+            };
+            if prev.is_empty() {
+                roots.push(entry.hash.clone());
+                continue;
+            }
+            children.entry(prev.as_str()).or_default().push(i);
+            match self.index.get(prev) {
+                None => issues.push(issue(
+                    "missing_predecessor",
+                    "error",
+                    entry,
+                    format!("Previous edit {} was never received", short(prev)),
+                )),
+                Some(&p) if p > i => issues.push(issue(
+                    "out_of_order",
+                    "warning",
+                    entry,
+                    format!("Arrived before its previous edit {}", short(prev)),
+                )),
+                Some(_) => {}
+            }
+        }
+
+        if roots.len() > 1 {
+            for hash in &roots[1..] {
+                let entry = &self.entries[self.index[hash]];
+                issues.push(issue("multiple_roots", "error", entry, "A second edit claims to be the first".to_string()));
+            }
+        }
+
+        // Concurrent edits from different peers legitimately share a parent
+        let mut forks: Vec<(&str, &Vec<usize>)> = children.iter().filter(|(_, c)| c.len() > 1).map(|(p, c)| (*p, c)).collect();
+        forks.sort_by(|a, b| a.1[0].cmp(&b.1[0]));
+        for (prev, siblings) in forks {
+            for &i in &siblings[1..] {
+                let entry = &self.entries[i];
+                let detail = format!("{} edits build on {}", siblings.len(), short(prev));
+                issues.push(issue("fork", "warning", entry, detail));
+            }
+        }
+
+        let mut heads: Vec<&ChainEntry> = self
+            .index
+            .values()
+            .map(|&i| &self.entries[i])
+            .filter(|e| e.prev.is_some() && !children.contains_key(e.hash.as_str()))
+            .collect();
+        heads.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+        let report = ChainReport {
+            valid: issues.iter().all(|i| i.severity != "error"),
+            edits: self.index.len(),
+            roots,
+            heads: heads.into_iter().map(|e| e.hash.clone()).collect(),
+            issues,
+        };
+        serde_json::to_string(&report).unwrap_or_else(|_| "{}".to_string())
+    }
+}
+
+fn short(hash: &str) -> &str {
+    &hash[..hash.len().min(12)]
+}
@@ -0,0 +1,341 @@
+// Heading parsing and GitHub-compatible anchor slugs, shared by the TOC,
+// outline and link-checking features.
+
+use wasm_bindgen::prelude::*;
+use std::collections::HashSet;
+
+use crate::front_matter::front_matter_range;
+use crate::links::parse_inline_link;
+use crate::markdown::{fenced_code_ranges, in_ranges};
+
+/// A heading found in the document
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Heading {
+    pub level: u8,
+    /// Heading text with the markers removed (inline markdown kept)
+    pub text: String,
+    /// Byte range of the heading line(s), including setext underlines
+    pub start: usize,
+    pub end: usize,
+    /// 1-based line number of the heading text
+    pub line: usize,
+    /// Whether the heading uses `===`/`---` underlines
+    pub setext: bool,
+}
+
+/// Level and text of an ATX heading line (`## Title ##`)
+pub(crate) fn parse_atx(line: &str) -> Option<(u8, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let body = &line[indent..];
+    let level = body.bytes().take_while(|&b| b == b'#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    let rest = &body[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    // Strip an optional closing sequence of #s
+    let text = rest.trim();
+    let stripped = text.trim_end_matches('#');
+    let text = if stripped.is_empty() || stripped.ends_with([' ', '\t']) {
+        stripped.trim_end()
+    } else {
+        text
+    };
+    Some((level as u8, text))
+}
+
+/// Level of a setext underline (`===` is 1, `---` is 2)
+pub(crate) fn setext_level(line: &str) -> Option<u8> {
+    let trimmed = line.trim();
+    if trimmed.len() - trimmed.trim_start_matches(' ').len() > 3 || trimmed.is_empty() {
+        return None;
+    }
+    if trimmed.chars().all(|c| c == '=') {
+        Some(1)
+    } else if trimmed.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Whether a line can be the text line of a setext heading
+fn is_paragraph_line(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty()
+        && parse_atx(line).is_none()
+        && !trimmed.starts_with(['>', '-', '*', '+', '|'])
+        && !trimmed.starts_with("```")
+        && line.len() - line.trim_start().len() < 4
+}
+
+/// All ATX and setext headings outside fenced code blocks and front matter,
+/// in order
+pub(crate) fn parse_headings(content: &str) -> Vec<Heading> {
+    let mut fences = fenced_code_ranges(content);
+    fences.extend(front_matter_range(content));
+    let mut lines: Vec<(usize, &str)> = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        lines.push((offset, line.trim_end_matches(['\n', '\r'])));
+        offset += line.len();
+    }
+
+    let mut headings = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let (start, line) = lines[i];
+        if in_ranges(&fences, start) {
+            i += 1;
+            continue;
+        }
+        if let Some((level, text)) = parse_atx(line) {
+            headings.push(Heading {
+                level,
+                text: text.to_string(),
+                start,
+                end: start + line.len(),
+                line: i + 1,
+                setext: false,
+            });
+        } else if let Some(&(next_start, next)) = lines.get(i + 1) {
+            // A setext heading needs a paragraph line right after a blank
+            // line (or document start), so list items and hrs don't match
+            let starts_paragraph = i == 0 || lines[i - 1].1.trim().is_empty() || in_ranges(&fences, lines[i - 1].0);
+            if let Some(level) = setext_level(next).filter(|_| starts_paragraph && is_paragraph_line(line)) {
+                headings.push(Heading {
+                    level,
+                    text: line.trim().to_string(),
+                    start,
+                    end: next_start + next.len(),
+                    line: i + 1,
+                    setext: true,
+                });
+                i += 2;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    headings
+}
+
+/// Reduce inline markdown to its visible text: link text instead of links,
+/// no emphasis markers, code spans without backticks
+pub(crate) fn plain_heading_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with('[') {
+            if let Some(link) = parse_inline_link(text, i) {
+                out.push_str(&plain_heading_text(&link.text));
+                i = link.end;
+                continue;
+            }
+        }
+        if rest.starts_with("![") {
+            if let Some(link) = parse_inline_link(text, i + 1) {
+                out.push_str(&link.text);
+                i = link.end;
+                continue;
+            }
+        }
+        let c = rest.chars().next().unwrap();
+        match c {
+            '*' | '`' => {}
+            '~' if rest.starts_with("~~") => i += 1,
+            '\\' => {
+                if let Some(next) = rest[1..].chars().next() {
+                    out.push(next);
+                    i += next.len_utf8();
+                }
+            }
+            _ => out.push(c),
+        }
+        i += c.len_utf8();
+    }
+    out.trim().to_string()
+}
+
+/// GitHub-style anchor slug: lowercase, punctuation removed (except `-` and
+/// `_`), spaces turned into hyphens
+pub(crate) fn github_slug(text: &str) -> String {
+    plain_heading_text(text)
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Hands out unique slugs, appending `-1`, `-2`, ... to duplicates the way
+/// GitHub does
+#[derive(Default)]
+pub(crate) struct Slugger {
+    seen: HashSet<String>,
+}
+
+impl Slugger {
+    pub fn slug(&mut self, text: &str) -> String {
+        let base = github_slug(text);
+        let mut candidate = base.clone();
+        let mut n = 0;
+        while self.seen.contains(&candidate) {
+            n += 1;
+            candidate = format!("{}-{}", base, n);
+        }
+        self.seen.insert(candidate.clone());
+        candidate
+    }
+}
+
+/// Headings paired with their unique slugs, in document order
+pub(crate) fn slugged_headings(content: &str) -> Vec<(Heading, String)> {
+    let mut slugger = Slugger::default();
+    parse_headings(content)
+        .into_iter()
+        .map(|h| {
+            let slug = slugger.slug(&h.text);
+            (h, slug)
+        })
+        .collect()
+}
+
+/// Byte length of the indent and `#` run opening an ATX heading line
+fn atx_marker(line: &str) -> Option<(usize, usize)> {
+    parse_atx(line)?;
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let hashes = line[indent..].bytes().take_while(|&b| b == b'#').count();
+    Some((indent, hashes))
+}
+
+/// Set the heading level of one line: 0 removes the heading, anything else
+/// replaces the `#` run (or adds one), keeping a closing sequence
+pub(crate) fn set_heading_level(line: &str, level: u8) -> String {
+    let level = level.min(6) as usize;
+    match atx_marker(line) {
+        Some((indent, hashes)) => {
+            let rest = &line[indent + hashes..];
+            if level == 0 {
+                let (_, text) = parse_atx(line).unwrap_or((0, rest));
+                text.to_string()
+            } else if rest.is_empty() {
+                "#".repeat(level)
+            } else {
+                format!("{}{}{}", &line[..indent], "#".repeat(level), rest)
+            }
+        }
+        None if level == 0 || line.trim().is_empty() => line.to_string(),
+        None => format!("{} {}", "#".repeat(level), line.trim()),
+    }
+}
+
+/// Cycle each non-blank line of `text` through no heading, h1, ..., h6 and
+/// back to no heading. Code is left alone: lines in fenced blocks and lines
+/// indented four or more spaces.
+#[wasm_bindgen]
+pub fn cycle_heading(text: &str) -> String {
+    let fences = fenced_code_ranges(text);
+    let mut out = String::with_capacity(text.len() + 2);
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let body = line.trim_end_matches(['\n', '\r']);
+        let indent: usize = body.chars().take_while(|c| matches!(c, ' ' | '\t')).map(|c| if c == '\t' { 4 } else { 1 }).sum();
+        if indent >= 4 || in_ranges(&fences, start) {
+            out.push_str(line);
+            continue;
+        }
+        let next = match parse_atx(body) {
+            Some((6, _)) => 0,
+            Some((level, _)) => level + 1,
+            None => 1,
+        };
+        out.push_str(&set_heading_level(body, next));
+        out.push_str(&line[body.len()..]);
+    }
+    out
+}
+
+/// Promote (negative `delta`) or demote (positive `delta`) every heading in
+/// `content`, clamping at levels 1 to 6. Setext headings keep their
+/// underline style while they stay at level 1 or 2 and become ATX
+/// headings below that. Headings in code blocks are left alone.
+#[wasm_bindgen]
+pub fn shift_headings(content: &str, delta: i32) -> String {
+    let mut out = String::with_capacity(content.len() + 16);
+    let mut last = 0;
+    for heading in parse_headings(content) {
+        let level = (i32::from(heading.level) + delta).clamp(1, 6) as u8;
+        if level == heading.level {
+            continue;
+        }
+        out.push_str(&content[last..heading.start]);
+        let block = &content[heading.start..heading.end];
+        if heading.setext {
+            let (text, underline) = block.split_once('\n').unwrap_or((block, ""));
+            let text = text.trim_end_matches('\r');
+            if level <= 2 {
+                let width = underline.trim().chars().count();
+                out.push_str(text);
+                out.push_str(&block[text.len()..block.len() - underline.len()]);
+                out.push_str(&(if level == 1 { "=" } else { "-" }).repeat(width));
+            } else {
+                out.push_str(&set_heading_level(text.trim(), level));
+            }
+        } else {
+            out.push_str(&set_heading_level(block, level));
+        }
+        last = heading.end;
+    }
+    out.push_str(&content[last..]);
+    out
+}
+
+/// A setext heading: the text line and an underline as wide as the text
+/// (at least three characters)
+pub(crate) fn setext_heading(text: &str, level: u8, width: usize) -> String {
+    let mark = if level == 1 { "=" } else { "-" };
+    format!("{}\n{}", text, mark.repeat(width.max(3)))
+}
+
+/// Convert headings between ATX (`## Title`) and setext (`Title` underlined
+/// with `===` or `---`) style. `style` is "atx" or "setext"; only levels 1
+/// and 2 can be setext, so deeper headings stay ATX. Headings in code
+/// blocks and front matter are left alone.
+#[wasm_bindgen]
+pub fn convert_headings(content: &str, style: &str) -> Result<String, JsValue> {
+    let to_setext = match style.trim().to_ascii_lowercase().as_str() {
+        "atx" => false,
+        "setext" => true,
+        _ => return Err(JsValue::from_str(&format!("Unknown heading style: {} (expected \"atx\" or \"setext\")", style))),
+    };
+    let mut out = String::with_capacity(content.len());
+    let mut last = 0;
+    for heading in parse_headings(content) {
+        let replacement = if heading.setext && !to_setext {
+            format!("{} {}", "#".repeat(heading.level as usize), heading.text)
+        } else if !heading.setext && to_setext && heading.level <= 2 && !heading.text.is_empty() {
+            setext_heading(&heading.text, heading.level, heading.text.chars().count())
+        } else {
+            continue;
+        };
+        out.push_str(&content[last..heading.start]);
+        out.push_str(&replacement);
+        last = heading.end;
+    }
+    out.push_str(&content[last..]);
+    Ok(out)
+}
+
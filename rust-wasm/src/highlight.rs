@@ -0,0 +1,515 @@
+// Syntax highlighting for code blocks. Languages are described by small
+// syntax tables (in the spirit of syntect's definitions) driving one shared
+// lexer; HTML/XML get a dedicated markup lexer. Output is escaped HTML with
+// `hl-*` classes that the preview stylesheet colors.
+
+use wasm_bindgen::prelude::*;
+use regex::Regex;
+use std::sync::OnceLock;
+
+use crate::format::cached;
+use crate::markdown::escape_html;
+
+struct Syntax {
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    /// Characters that open a single-line string
+    quotes: &'static [char],
+    /// Delimiters of strings that may span lines (`"""`, backticks)
+    long_strings: &'static [&'static str],
+    /// `$name` / `${name}` variables
+    variables: bool,
+    /// `@name` decorators and annotations
+    annotations: bool,
+    /// `#include`-style lines
+    preprocessor: bool,
+    /// Rust `#[attr]` attributes and `name!` macros
+    rust_extras: bool,
+    /// Capitalized identifiers are types
+    capitalized_types: bool,
+    /// Identifiers and strings followed by this are keys (`key:`, `key =`)
+    key_suffix: Option<char>,
+    case_insensitive: bool,
+}
+
+const NONE: &[&str] = &[];
+
+const BASE: Syntax = Syntax {
+    names: NONE,
+    keywords: NONE,
+    types: NONE,
+    literals: NONE,
+    line_comments: NONE,
+    block_comment: None,
+    quotes: &['"', '\''],
+    long_strings: NONE,
+    variables: false,
+    annotations: false,
+    preprocessor: false,
+    rust_extras: false,
+    capitalized_types: false,
+    key_suffix: None,
+    case_insensitive: false,
+};
+
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn",
+            "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+            "static", "struct", "super", "trait", "type", "unsafe", "use", "where", "while",
+        ],
+        types: &[
+            "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32", "f64",
+            "bool", "char", "str",
+        ],
+        literals: &["true", "false", "self", "Self", "None", "Some", "Ok", "Err"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+        rust_extras: true,
+        capitalized_types: true,
+        ..BASE
+    },
+    Syntax {
+        names: &["javascript", "js", "jsx", "mjs", "typescript", "ts", "tsx"],
+        keywords: &[
+            "async", "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default",
+            "delete", "do", "else", "export", "extends", "finally", "for", "from", "function", "if", "import",
+            "in", "instanceof", "interface", "let", "new", "of", "return", "static", "switch", "throw", "try",
+            "type", "typeof", "var", "void", "while", "yield", "enum", "implements", "private", "protected",
+            "public", "readonly",
+        ],
+        types: &["string", "number", "boolean", "any", "unknown", "never", "object", "bigint", "symbol"],
+        literals: &["true", "false", "null", "undefined", "NaN", "Infinity", "this", "super"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        long_strings: &["`"],
+        annotations: true,
+        capitalized_types: true,
+        ..BASE
+    },
+    Syntax {
+        names: &["python", "py", "python3"],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+            "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not",
+            "or", "pass", "raise", "return", "try", "while", "with", "yield", "match", "case",
+        ],
+        types: &["int", "float", "str", "bytes", "bool", "list", "dict", "tuple", "set", "object"],
+        literals: &["True", "False", "None", "self", "cls"],
+        line_comments: &["#"],
+        long_strings: &["\"\"\"", "'''"],
+        annotations: true,
+        ..BASE
+    },
+    Syntax {
+        names: &["json", "jsonc", "json5"],
+        literals: &["true", "false", "null"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+        key_suffix: Some(':'),
+        ..BASE
+    },
+    Syntax {
+        names: &["bash", "sh", "shell", "zsh", "console", "shellsession"],
+        keywords: &[
+            "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac", "in",
+            "function", "return", "export", "local", "readonly", "source", "exit",
+        ],
+        literals: &["true", "false"],
+        line_comments: &["#"],
+        variables: true,
+        ..BASE
+    },
+    Syntax {
+        names: &["go", "golang"],
+        keywords: &[
+            "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "for",
+            "func", "go", "goto", "if", "import", "interface", "map", "package", "range", "return", "select",
+            "struct", "switch", "type", "var",
+        ],
+        types: &[
+            "bool", "byte", "complex64", "complex128", "error", "float32", "float64", "int", "int8", "int16",
+            "int32", "int64", "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr", "any",
+        ],
+        literals: &["true", "false", "nil", "iota"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        long_strings: &["`"],
+        ..BASE
+    },
+    Syntax {
+        names: &["c", "h", "cpp", "c++", "cc", "hpp", "cxx"],
+        keywords: &[
+            "auto", "break", "case", "class", "const", "constexpr", "continue", "default", "delete", "do",
+            "else", "enum", "extern", "for", "goto", "if", "inline", "namespace", "new", "private", "protected",
+            "public", "return", "sizeof", "static", "struct", "switch", "template", "typedef", "typename",
+            "union", "using", "virtual", "volatile", "while",
+        ],
+        types: &[
+            "bool", "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void", "size_t",
+            "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+        ],
+        literals: &["true", "false", "NULL", "nullptr", "this"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        preprocessor: true,
+        ..BASE
+    },
+    Syntax {
+        names: &["java", "kotlin", "kt", "csharp", "cs", "c#"],
+        keywords: &[
+            "abstract", "break", "case", "catch", "class", "continue", "default", "do", "else", "enum",
+            "extends", "final", "finally", "for", "fun", "if", "implements", "import", "interface", "instanceof",
+            "namespace", "new", "override", "package", "private", "protected", "public", "return", "static",
+            "switch", "throw", "throws", "try", "using", "val", "var", "void", "when", "while",
+        ],
+        types: &["boolean", "byte", "char", "double", "float", "int", "long", "short", "string"],
+        literals: &["true", "false", "null", "this", "super"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        long_strings: &["\"\"\""],
+        annotations: true,
+        capitalized_types: true,
+        ..BASE
+    },
+    Syntax {
+        names: &["css", "scss", "less"],
+        literals: &["inherit", "initial", "unset", "none", "auto", "important"],
+        block_comment: Some(("/*", "*/")),
+        annotations: true,
+        key_suffix: Some(':'),
+        ..BASE
+    },
+    Syntax {
+        names: &["sql", "mysql", "postgresql", "postgres", "sqlite"],
+        keywords: &[
+            "select", "from", "where", "insert", "into", "values", "update", "set", "delete", "create", "table",
+            "drop", "alter", "add", "index", "join", "left", "right", "inner", "outer", "on", "group", "by",
+            "order", "having", "limit", "offset", "as", "and", "or", "not", "in", "is", "like", "between",
+            "distinct", "union", "all", "primary", "key", "foreign", "references", "default", "case", "when",
+            "then", "else", "end", "with", "returning", "asc", "desc",
+        ],
+        types: &["int", "integer", "bigint", "text", "varchar", "char", "boolean", "date", "timestamp", "real", "numeric"],
+        literals: &["null", "true", "false"],
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        case_insensitive: true,
+        ..BASE
+    },
+    Syntax {
+        names: &["yaml", "yml"],
+        literals: &["true", "false", "null", "yes", "no", "on", "off", "~"],
+        line_comments: &["#"],
+        key_suffix: Some(':'),
+        ..BASE
+    },
+    Syntax {
+        names: &["toml", "ini", "cfg", "conf"],
+        literals: &["true", "false"],
+        line_comments: &["#", ";"],
+        long_strings: &["\"\"\"", "'''"],
+        key_suffix: Some('='),
+        ..BASE
+    },
+];
+
+const MARKUP_NAMES: &[&str] = &["html", "xml", "svg", "xhtml", "vue"];
+
+/// Canonical name of every highlightable language
+pub(crate) fn highlight_languages() -> Vec<&'static str> {
+    SYNTAXES.iter().map(|s| s.names[0]).chain(MARKUP_NAMES.iter().copied()).collect()
+}
+
+fn find_syntax(language: &str) -> Option<&'static Syntax> {
+    SYNTAXES.iter().find(|s| s.names.contains(&language))
+}
+
+/// First word of a fence info string, lowercased: "Rust,ignore" -> "rust"
+fn language_name(info: &str) -> String {
+    info.trim()
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+        .next()
+        .unwrap_or("")
+        .trim_start_matches("language-")
+        .to_lowercase()
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte offset just past the run of chars matching `pred` from `pos`
+fn scan_while(code: &str, pos: usize, pred: impl Fn(char) -> bool) -> usize {
+    code[pos..].find(|c: char| !pred(c)).map_or(code.len(), |i| pos + i)
+}
+
+/// End of a string opened at `pos` by `quote`: past the closing quote, or
+/// the end of the line for an unterminated string
+fn string_end(code: &str, pos: usize, quote: char) -> usize {
+    let mut chars = code[pos + quote.len_utf8()..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' => return pos + 1 + i,
+            c if c == quote => return pos + 1 + i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    code.len()
+}
+
+/// Next non-space character at or after `pos` on the same line
+fn next_char(code: &str, pos: usize) -> Option<char> {
+    code[pos..].chars().find(|c| *c != ' ' && *c != '\t')
+}
+
+fn at_line_start(code: &str, pos: usize) -> bool {
+    code[..pos].rsplit('\n').next().unwrap_or("").trim().is_empty()
+}
+
+/// Split code into `(start, end, class)` tokens; unclassified text is left
+/// out
+fn tokenize(code: &str, syntax: &Syntax) -> Vec<(usize, usize, &'static str)> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < code.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().unwrap();
+
+        if let Some((open, close)) = syntax.block_comment.filter(|(open, _)| rest.starts_with(open)) {
+            let end = rest[open.len()..].find(close).map_or(code.len(), |e| i + open.len() + e + close.len());
+            tokens.push((i, end, "hl-comment"));
+            i = end;
+            continue;
+        }
+        // In shell, `#` only starts a comment at the beginning of a word
+        let word_start = !syntax.variables || code[..i].ends_with(|ch: char| ch.is_whitespace()) || i == 0;
+        if word_start && syntax.line_comments.iter().any(|p| rest.starts_with(p)) {
+            let end = rest.find('\n').map_or(code.len(), |e| i + e);
+            tokens.push((i, end, "hl-comment"));
+            i = end;
+            continue;
+        }
+        if syntax.preprocessor && c == '#' && at_line_start(code, i) {
+            let end = rest.find('\n').map_or(code.len(), |e| i + e);
+            tokens.push((i, end, "hl-meta"));
+            i = end;
+            continue;
+        }
+        if syntax.rust_extras && (rest.starts_with("#[") || rest.starts_with("#![")) {
+            let end = rest.find([']', '\n']).map_or(code.len(), |e| i + e + 1).min(code.len());
+            tokens.push((i, end, "hl-meta"));
+            i = end;
+            continue;
+        }
+        if let Some(delim) = syntax.long_strings.iter().find(|d| rest.starts_with(**d)) {
+            let end = rest[delim.len()..].find(delim).map_or(code.len(), |e| i + delim.len() + e + delim.len());
+            tokens.push((i, end, "hl-string"));
+            i = end;
+            continue;
+        }
+        if syntax.quotes.contains(&c) {
+            let end = string_end(code, i, c);
+            let class = if syntax.key_suffix.is_some() && next_char(code, end) == syntax.key_suffix {
+                "hl-attr"
+            } else {
+                "hl-string"
+            };
+            tokens.push((i, end, class));
+            i = end;
+            continue;
+        }
+        if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|d: char| d.is_ascii_digit())) {
+            // Digits, hex/exponent letters, suffixes and unit names (`10px`)
+            let mut end = i + 1;
+            loop {
+                end = scan_while(code, end, |ch| ch.is_ascii_alphanumeric() || ch == '_');
+                if code[end..].starts_with('.') && code[end + 1..].starts_with(|d: char| d.is_ascii_digit()) {
+                    end += 1;
+                } else {
+                    break;
+                }
+            }
+            tokens.push((i, end, "hl-number"));
+            i = end;
+            continue;
+        }
+        if syntax.variables && c == '$' {
+            let end = if rest.starts_with("${") {
+                rest.find('}').map_or(code.len(), |e| i + e + 1)
+            } else {
+                scan_while(code, i + 1, |ch| is_ident_char(ch) || "?#@!*".contains(ch)).max(i + 1)
+            };
+            if end > i + 1 {
+                tokens.push((i, end, "hl-variable"));
+            }
+            i = end;
+            continue;
+        }
+        if syntax.annotations && c == '@' && rest[1..].starts_with(is_ident_start) {
+            let end = scan_while(code, i + 1, |ch| is_ident_char(ch) || ch == '.' || ch == '-');
+            tokens.push((i, end, "hl-meta"));
+            i = end;
+            continue;
+        }
+        if is_ident_start(c) {
+            // CSS property names and shell flags contain hyphens
+            let hyphens = syntax.key_suffix == Some(':') || syntax.variables;
+            let end = scan_while(code, i, |ch| is_ident_char(ch) || (hyphens && ch == '-'));
+            let word = &code[i..end];
+            let matches = |list: &[&str]| {
+                if syntax.case_insensitive {
+                    list.iter().any(|k| k.eq_ignore_ascii_case(word))
+                } else {
+                    list.contains(&word)
+                }
+            };
+            let next = next_char(code, end);
+            let class = if syntax.key_suffix.is_some() && next == syntax.key_suffix && !matches(syntax.literals) {
+                Some("hl-attr")
+            } else if matches(syntax.keywords) {
+                Some("hl-keyword")
+            } else if matches(syntax.literals) {
+                Some("hl-literal")
+            } else if matches(syntax.types) {
+                Some("hl-type")
+            } else if next == Some('(') || (syntax.rust_extras && code[end..].starts_with('!') && !code[end..].starts_with("!=")) {
+                Some("hl-function")
+            } else if syntax.capitalized_types && c.is_uppercase() {
+                Some("hl-type")
+            } else {
+                None
+            };
+            let end = if class == Some("hl-function") && code[end..].starts_with('!') { end + 1 } else { end };
+            if let Some(class) = class {
+                tokens.push((i, end, class));
+            }
+            i = end;
+            continue;
+        }
+        i += c.len_utf8();
+    }
+    tokens
+}
+
+/// Tokens for HTML/XML: tags, attributes, attribute values, comments and
+/// entities
+fn tokenize_markup(code: &str) -> Vec<(usize, usize, &'static str)> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < code.len() {
+        let rest = &code[i..];
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(code.len(), |e| i + e + 3);
+            tokens.push((i, end, "hl-comment"));
+            i = end;
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>').map_or(code.len(), |e| i + e + 1);
+            tokens.push((i, end, "hl-meta"));
+            i = end;
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_alphabetic() || c == '/') {
+            let name_end = scan_while(code, i + 1, |c| c.is_alphanumeric() || "/-_:.".contains(c));
+            tokens.push((i, name_end, "hl-tag"));
+            i = name_end;
+            // Attributes up to the closing `>`
+            while i < code.len() {
+                let c = code[i..].chars().next().unwrap();
+                if c == '>' || code[i..].starts_with("/>") {
+                    let end = i + if c == '>' { 1 } else { 2 };
+                    tokens.push((i, end, "hl-tag"));
+                    i = end;
+                    break;
+                } else if c == '"' || c == '\'' {
+                    let end = code[i + 1..].find(c).map_or(code.len(), |e| i + 1 + e + 1);
+                    tokens.push((i, end, "hl-string"));
+                    i = end;
+                } else if is_ident_start(c) {
+                    let end = scan_while(code, i, |ch| ch.is_alphanumeric() || "-_:.@".contains(ch));
+                    tokens.push((i, end, "hl-attr"));
+                    i = end;
+                } else {
+                    i += c.len_utf8();
+                }
+            }
+        } else if rest.starts_with('&') {
+            let end = scan_while(code, i + 1, |c| c.is_alphanumeric() || c == '#');
+            if code[end..].starts_with(';') {
+                tokens.push((i, end + 1, "hl-literal"));
+                i = end + 1;
+            } else {
+                i += 1;
+            }
+        } else {
+            i += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    tokens
+}
+
+fn render(code: &str, tokens: &[(usize, usize, &str)]) -> String {
+    let mut html = String::with_capacity(code.len() * 2);
+    let mut last = 0;
+    for &(start, end, class) in tokens {
+        html.push_str(&escape_html(&code[last..start]));
+        html.push_str(&format!("<span class=\"{}\">{}</span>", class, escape_html(&code[start..end])));
+        last = end;
+    }
+    html.push_str(&escape_html(&code[last..]));
+    html
+}
+
+/// Highlighted HTML for `code`, or `None` for an unsupported language
+pub(crate) fn highlight(code: &str, language: &str) -> Option<String> {
+    let language = language_name(language);
+    if MARKUP_NAMES.contains(&language.as_str()) {
+        return Some(render(code, &tokenize_markup(code)));
+    }
+    find_syntax(&language).map(|syntax| render(code, &tokenize(code, syntax)))
+}
+
+/// Highlight `code` as `language` (a fence info string such as "rust" or
+/// "js {1,3}"). Returns escaped HTML with `<span class="hl-keyword">`-style
+/// spans (keyword, type, literal, string, number, comment, function,
+/// variable, attr, tag, meta); unsupported languages are escaped only.
+#[wasm_bindgen]
+pub fn highlight_code(code: &str, language: &str) -> String {
+    highlight(code, language).unwrap_or_else(|| escape_html(code))
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Highlight every `<pre><code class="language-...">` block in rendered
+/// HTML, leaving blocks in unsupported languages untouched
+#[wasm_bindgen]
+pub fn highlight_code_blocks(html: &str) -> String {
+    static BLOCK: OnceLock<Regex> = OnceLock::new();
+    let re_block = cached(&BLOCK, r#"(?s)(<pre><code class="[^"]*language-([A-Za-z0-9_+#.-]+)[^"]*">)(.*?)(</code></pre>)"#);
+    re_block
+        .replace_all(html, |caps: &regex::Captures| match highlight(&unescape_html(&caps[3]), &caps[2]) {
+            Some(highlighted) => format!("{}{}{}", &caps[1], highlighted, &caps[4]),
+            None => caps[0].to_string(),
+        })
+        .to_string()
+}
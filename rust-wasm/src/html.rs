@@ -0,0 +1,748 @@
+// HTML on both sides of the document tree: rendering a `Document` to HTML,
+// and reading HTML (exports, pasted content) back into one.
+
+use crate::ast::{inline_text, Align, Block, Document, Footnotes, Inline, ListItem};
+use crate::headings::Slugger;
+use crate::markdown::escape_html;
+use crate::sanitize::{sanitize, SanitizePolicy};
+
+// ---------------------------------------------------------------------------
+// Rendering
+
+struct Renderer<'a> {
+    slugger: &'a mut Slugger,
+    footnotes: &'a Footnotes<'a>,
+    /// Applied to raw HTML passed through from the markdown
+    policy: SanitizePolicy,
+}
+
+impl Renderer<'_> {
+    fn inlines(&mut self, inlines: &[Inline]) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text { text } => out.push_str(&escape_html(text)),
+                Inline::Code { code } => out.push_str(&format!("<code>{}</code>", escape_html(code))),
+                Inline::Emphasis { content } => out.push_str(&format!("<em>{}</em>", self.inlines(content))),
+                Inline::Strong { content } => out.push_str(&format!("<strong>{}</strong>", self.inlines(content))),
+                Inline::Strikethrough { content } => out.push_str(&format!("<del>{}</del>", self.inlines(content))),
+                Inline::Underline { content } => out.push_str(&format!("<u>{}</u>", self.inlines(content))),
+                Inline::Link { url, title, content } => {
+                    let title = title.as_ref().map(|t| format!(" title=\"{}\"", escape_html(t))).unwrap_or_default();
+                    out.push_str(&format!("<a href=\"{}\"{}>{}</a>", escape_html(url), title, self.inlines(content)));
+                }
+                Inline::Image { url, title, alt } => {
+                    let title = title.as_ref().map(|t| format!(" title=\"{}\"", escape_html(t))).unwrap_or_default();
+                    out.push_str(&format!("<img src=\"{}\" alt=\"{}\"{}>", escape_html(url), escape_html(alt), title));
+                }
+                Inline::Math { tex } => out.push_str(&format!("<span class=\"math inline\">\\({}\\)</span>", escape_html(tex))),
+                Inline::FootnoteRef { label } => {
+                    let Some(n) = self.footnotes.number(label) else { continue };
+                    let id = escape_html(label);
+                    out.push_str(&format!(
+                        "<sup class=\"footnote-ref\"><a href=\"#fn-{}\" id=\"fnref-{}\">{}</a></sup>",
+                        id, id, n
+                    ));
+                }
+                Inline::WikiLink { target, anchor, alias } => {
+                    let anchor_attr = anchor.as_ref().map(|a| format!(" data-anchor=\"{}\"", escape_html(a))).unwrap_or_default();
+                    out.push_str(&format!(
+                        "<a class=\"wiki-link\" data-target=\"{}\"{}>{}</a>",
+                        escape_html(target),
+                        anchor_attr,
+                        escape_html(alias.as_deref().unwrap_or(target))
+                    ));
+                }
+                Inline::Html { html } => out.push_str(&sanitize(html, &self.policy)),
+                Inline::SoftBreak => out.push('\n'),
+                Inline::LineBreak => out.push_str("<br>\n"),
+            }
+        }
+        out
+    }
+
+    fn item(&mut self, item: &ListItem) -> String {
+        // Tight items: a lone paragraph is rendered without <p>
+        let body = match item.blocks.as_slice() {
+            [Block::Paragraph { content }] => self.inlines(content),
+            blocks => self.blocks(blocks),
+        };
+        match item.checked {
+            Some(checked) => format!(
+                "<li class=\"task\"><input type=\"checkbox\" disabled{}> {}</li>",
+                if checked { " checked" } else { "" },
+                body
+            ),
+            None => format!("<li>{}</li>", body),
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> String {
+        match block {
+            Block::Heading { level, content } => {
+                let id = self.slugger.slug(&inline_text(content));
+                format!("<h{} id=\"{}\">{}</h{}>", level, id, self.inlines(content), level)
+            }
+            Block::Paragraph { content } => format!("<p>{}</p>", self.inlines(content)),
+            Block::CodeBlock { language, code } => format!(
+                "<pre><code class=\"language-{}\">{}</code></pre>",
+                if language.is_empty() { "plaintext" } else { language },
+                escape_html(code)
+            ),
+            Block::BlockQuote { blocks } => format!("<blockquote>\n{}\n</blockquote>", self.blocks(blocks)),
+            Block::List { ordered, start, items } => {
+                let items: Vec<String> = items.iter().map(|i| self.item(i)).collect();
+                let task = items.iter().any(|i| i.starts_with("<li class=\"task\""));
+                let class = if task { " class=\"task-list\"" } else { "" };
+                match (ordered, start) {
+                    (true, 1) => format!("<ol{}>\n{}\n</ol>", class, items.join("\n")),
+                    (true, n) => format!("<ol start=\"{}\"{}>\n{}\n</ol>", n, class, items.join("\n")),
+                    _ => format!("<ul{}>\n{}\n</ul>", class, items.join("\n")),
+                }
+            }
+            Block::Table { align, header, rows } => {
+                let style = |n: usize| match align.get(n) {
+                    Some(Align::Left) => " style=\"text-align: left\"",
+                    Some(Align::Center) => " style=\"text-align: center\"",
+                    Some(Align::Right) => " style=\"text-align: right\"",
+                    _ => "",
+                };
+                let head: Vec<String> = header
+                    .iter()
+                    .enumerate()
+                    .map(|(n, c)| format!("<th{}>{}</th>", style(n), self.inlines(c)))
+                    .collect();
+                let body: Vec<String> = rows
+                    .iter()
+                    .map(|row| {
+                        let cells: Vec<String> = row
+                            .iter()
+                            .enumerate()
+                            .map(|(n, c)| format!("<td{}>{}</td>", style(n), self.inlines(c)))
+                            .collect();
+                        format!("<tr>{}</tr>", cells.join(""))
+                    })
+                    .collect();
+                format!(
+                    "<table>\n<thead><tr>{}</tr></thead>\n<tbody>\n{}\n</tbody>\n</table>",
+                    head.join(""),
+                    body.join("\n")
+                )
+            }
+            Block::ThematicBreak => "<hr>".to_string(),
+            Block::Html { html } => sanitize(html, &self.policy),
+            Block::MathBlock { tex } => format!("<div class=\"math display\">\\[{}\\]</div>", escape_html(tex)),
+            // Rendered at the end of the document
+            Block::FootnoteDefinition { .. } => String::new(),
+        }
+    }
+
+    fn blocks(&mut self, blocks: &[Block]) -> String {
+        blocks
+            .iter()
+            .map(|b| self.block(b))
+            .filter(|html| !html.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Render a document tree to an HTML fragment. Footnotes are collected
+/// into a `<section class="footnotes">` at the end.
+pub(crate) fn render_html(doc: &Document) -> String {
+    render_html_with(doc, &mut Slugger::default())
+}
+
+/// Render with a slugger shared across several documents, so heading ids
+/// stay unique between them
+pub(crate) fn render_html_with(doc: &Document, slugger: &mut Slugger) -> String {
+    let footnotes = Footnotes::new(&doc.blocks);
+    let mut renderer = Renderer { slugger, footnotes: &footnotes, policy: SanitizePolicy::default() };
+    let mut html = renderer.blocks(&doc.blocks);
+    if !footnotes.is_empty() {
+        let mut items = Vec::new();
+        for (_, label, body) in footnotes.iter() {
+            let body = renderer.blocks(body);
+            let id = escape_html(label);
+            items.push(format!(
+                "<li id=\"fn-{}\">{} <a href=\"#fnref-{}\" class=\"footnote-backref\">↩</a></li>",
+                id, body, id
+            ));
+        }
+        html.push_str(&format!("\n<section class=\"footnotes\">\n<ol>\n{}\n</ol>\n</section>", items.join("\n")));
+    }
+    html
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+
+#[derive(Debug, Clone)]
+pub(crate) enum Node {
+    Element { name: String, attrs: Vec<(String, String)>, children: Vec<Node> },
+    Text(String),
+}
+
+impl Node {
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        match self {
+            Node::Element { attrs, .. } => attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str()),
+            Node::Text(_) => None,
+        }
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attr("class").is_some_and(|c| c.split_whitespace().any(|c| c == class))
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Node::Element { name, .. } => name,
+            Node::Text(_) => "",
+        }
+    }
+
+    pub fn children(&self) -> &[Node] {
+        match self {
+            Node::Element { children, .. } => children,
+            Node::Text(_) => &[],
+        }
+    }
+
+    /// Concatenated text, untouched (for `<pre>`)
+    pub fn raw_text(&self) -> String {
+        match self {
+            Node::Text(text) => text.clone(),
+            Node::Element { name, .. } if name == "br" => "\n".to_string(),
+            Node::Element { children, .. } => children.iter().map(Node::raw_text).collect(),
+        }
+    }
+}
+
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose content is dropped entirely
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style", "head", "title", "template", "noscript", "xml"];
+
+/// Decode character references (`&amp;`, `&#233;`, `&#xE9;`, `&nbsp;`)
+pub(crate) fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let end = rest[1..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '#')).map(|e| e + 1);
+        let decoded = end.filter(|&e| rest[e..].starts_with(';')).and_then(|e| {
+            let name = &rest[1..e];
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                "mdash" => Some('—'),
+                "ndash" => Some('–'),
+                "hellip" => Some('…'),
+                "lsquo" => Some('‘'),
+                "rsquo" => Some('’'),
+                "ldquo" => Some('“'),
+                "rdquo" => Some('”'),
+                "copy" => Some('©'),
+                "reg" => Some('®'),
+                "trade" => Some('™'),
+                "bull" => Some('•'),
+                _ => {
+                    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => name.strip_prefix('#').and_then(|d| d.parse().ok()),
+                    };
+                    code.and_then(char::from_u32)
+                }
+            };
+            c.map(|c| (c, e + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub(crate) fn parse_attrs(src: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let bytes = src.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        let name_start = i;
+        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'=' && bytes[i] != b'/' {
+            i += 1;
+        }
+        if name_start == i {
+            break;
+        }
+        let name = src[name_start..i].to_ascii_lowercase();
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let mut value = String::new();
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match bytes.get(i) {
+                Some(&q) if q == b'"' || q == b'\'' => {
+                    let end = src[i + 1..].find(q as char).map_or(src.len(), |e| i + 1 + e);
+                    value = decode_entities(&src[i + 1..end]);
+                    i = end + 1;
+                }
+                _ => {
+                    let start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() {
+                        i += 1;
+                    }
+                    value = decode_entities(&src[start..i]);
+                }
+            }
+        }
+        attrs.push((name, value));
+    }
+    attrs
+}
+
+/// An open element while parsing: name, attributes and children so far
+type OpenElement = (String, Vec<(String, String)>, Vec<Node>);
+
+/// Parse HTML into a forgiving node tree: unknown end tags are ignored and
+/// unclosed elements are closed by their parent's end tag
+pub(crate) fn parse_html(html: &str) -> Vec<Node> {
+    // Stack of open elements; index 0 is a synthetic root
+    let mut stack: Vec<OpenElement> = vec![(String::new(), Vec::new(), Vec::new())];
+    let mut i = 0;
+    let mut skip_until: Option<String> = None;
+
+    fn close(stack: &mut Vec<OpenElement>) {
+        let (name, attrs, children) = stack.pop().unwrap();
+        stack.last_mut().unwrap().2.push(Node::Element { name, attrs, children });
+    }
+
+    while i < html.len() {
+        let rest = &html[i..];
+        if let Some(name) = &skip_until {
+            let end_tag = format!("</{}", name);
+            let end = rest.to_ascii_lowercase().find(&end_tag).unwrap_or(rest.len());
+            i += end;
+            i += html[i..].find('>').map_or(html.len() - i, |e| e + 1);
+            skip_until = None;
+            continue;
+        }
+        if rest.starts_with("<!--") {
+            i += rest.find("-->").map_or(rest.len(), |e| e + 3);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            i += rest.find('>').map_or(rest.len(), |e| e + 1);
+            continue;
+        }
+        let is_tag = rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/');
+        if !is_tag {
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[first..].find('<').map_or(rest.len(), |e| e + first);
+            stack.last_mut().unwrap().2.push(Node::Text(decode_entities(&rest[..end])));
+            i += end;
+            continue;
+        }
+        let end = rest.find('>').map_or(rest.len(), |e| e + 1);
+        let tag = rest[1..end].trim_end_matches('>');
+        i += end;
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_lowercase();
+            let name = name.rsplit(':').next().unwrap_or(&name).to_string();
+            if let Some(pos) = stack.iter().rposition(|(n, _, _)| *n == name) {
+                while stack.len() > pos {
+                    close(&mut stack);
+                }
+            }
+            continue;
+        }
+        let name_len = tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(tag.len());
+        let name = tag[..name_len].to_ascii_lowercase();
+        // Office namespaces (`o:p`, `w:sdt`) carry no content we want
+        let name = name.rsplit(':').next().unwrap_or(&name).to_string();
+        if SKIPPED_ELEMENTS.contains(&name.as_str()) && !tag.ends_with('/') {
+            skip_until = Some(name);
+            continue;
+        }
+        // A new <p> or <li> implicitly closes an open one
+        if matches!(name.as_str(), "p" | "li") {
+            if let Some(pos) = stack.iter().rposition(|(n, _, _)| *n == name) {
+                let blocked = stack[pos + 1..].iter().any(|(n, _, _)| matches!(n.as_str(), "ul" | "ol" | "table" | "div"));
+                if !blocked {
+                    while stack.len() > pos {
+                        close(&mut stack);
+                    }
+                }
+            }
+        }
+        let attrs = parse_attrs(&tag[name_len..]);
+        if VOID_ELEMENTS.contains(&name.as_str()) || tag.ends_with('/') {
+            stack.last_mut().unwrap().2.push(Node::Element { name, attrs, children: Vec::new() });
+        } else {
+            stack.push((name, attrs, Vec::new()));
+        }
+    }
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    stack.pop().unwrap().2
+}
+
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "body", "center", "dd", "details", "div", "dl", "dt", "figure",
+    "figcaption", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "html", "li", "main",
+    "nav", "ol", "p", "pre", "section", "summary", "table", "tbody", "td", "tfoot", "th", "thead", "tr", "ul",
+];
+
+fn is_block_node(node: &Node) -> bool {
+    BLOCK_ELEMENTS.contains(&node.name()) || (node.name() == "div" && node.has_class("math"))
+}
+
+/// Collapse whitespace runs the way a browser does
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            space = true;
+        } else {
+            if space {
+                out.push(' ');
+                space = false;
+            }
+            out.push(c);
+        }
+    }
+    if space {
+        out.push(' ');
+    }
+    out
+}
+
+/// Merge adjacent text nodes and trim whitespace at the edges of a block
+fn normalize_inlines(inlines: Vec<Inline>) -> Vec<Inline> {
+    let mut out: Vec<Inline> = Vec::new();
+    for inline in inlines {
+        match (out.last_mut(), inline) {
+            (Some(Inline::Text { text: prev }), Inline::Text { text }) => {
+                if prev.ends_with(' ') && text.starts_with(' ') {
+                    prev.push_str(&text[1..]);
+                } else {
+                    prev.push_str(&text);
+                }
+            }
+            (Some(Inline::LineBreak), Inline::Text { text }) => {
+                let text = text.trim_start().to_string();
+                if !text.is_empty() {
+                    out.push(Inline::Text { text });
+                }
+            }
+            (_, inline) => out.push(inline),
+        }
+    }
+    if let Some(Inline::Text { text }) = out.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    if let Some(Inline::Text { text }) = out.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    out.retain(|i| !matches!(i, Inline::Text { text } if text.is_empty()));
+    while matches!(out.last(), Some(Inline::LineBreak)) {
+        out.pop();
+    }
+    out
+}
+
+fn wrap(content: Vec<Inline>, make: fn(Vec<Inline>) -> Inline) -> Vec<Inline> {
+    // Keep surrounding spaces outside the markers: `<b> x </b>` -> ` **x** `
+    let content = normalize_inlines(content);
+    if content.is_empty() {
+        return Vec::new();
+    }
+    vec![make(content)]
+}
+
+fn node_inlines(node: &Node) -> Vec<Inline> {
+    match node {
+        Node::Text(text) => vec![Inline::Text { text: collapse_whitespace(text) }],
+        Node::Element { name, children, .. } => {
+            let inner = || children.iter().flat_map(node_inlines).collect::<Vec<_>>();
+            match name.as_str() {
+                "strong" | "b" => {
+                    let mut out = leading_space(children);
+                    out.extend(wrap(inner(), |content| Inline::Strong { content }));
+                    out.extend(trailing_space(children));
+                    out
+                }
+                "em" | "i" | "cite" | "dfn" => {
+                    let mut out = leading_space(children);
+                    out.extend(wrap(inner(), |content| Inline::Emphasis { content }));
+                    out.extend(trailing_space(children));
+                    out
+                }
+                "del" | "s" | "strike" => wrap(inner(), |content| Inline::Strikethrough { content }),
+                "u" | "ins" => wrap(inner(), |content| Inline::Underline { content }),
+                "code" | "kbd" | "samp" | "tt" => vec![Inline::Code { code: node.raw_text() }],
+                "br" => vec![Inline::LineBreak],
+                "img" => vec![Inline::Image {
+                    url: node.attr("src").unwrap_or("").to_string(),
+                    title: node.attr("title").map(str::to_string),
+                    alt: node.attr("alt").unwrap_or("").to_string(),
+                }],
+                "span" if node.has_class("math") => {
+                    let tex = node.raw_text();
+                    let tex = tex.trim().trim_start_matches("\\(").trim_end_matches("\\)");
+                    vec![Inline::Math { tex: tex.to_string() }]
+                }
+                "sup" if node.has_class("footnote-ref") => {
+                    let href = children.iter().find_map(|c| c.attr("href")).unwrap_or("");
+                    vec![Inline::FootnoteRef { label: href.trim_start_matches("#fn-").to_string() }]
+                }
+                "a" if node.has_class("footnote-backref") => Vec::new(),
+                "a" if node.has_class("wiki-link") => {
+                    let target = node.attr("data-target").unwrap_or("").to_string();
+                    let shown = node.raw_text();
+                    vec![Inline::WikiLink {
+                        alias: Some(shown).filter(|s| *s != target),
+                        anchor: node.attr("data-anchor").map(str::to_string),
+                        target,
+                    }]
+                }
+                "a" => match node.attr("href") {
+                    Some(href) if !href.is_empty() => vec![Inline::Link {
+                        url: href.to_string(),
+                        title: node.attr("title").map(str::to_string),
+                        content: normalize_inlines(inner()),
+                    }],
+                    _ => inner(),
+                },
+                "input" => Vec::new(),
+                _ => inner(),
+            }
+        }
+    }
+}
+
+/// Whitespace at the start of an element's text, kept outside emphasis
+fn leading_space(children: &[Node]) -> Vec<Inline> {
+    match children.first() {
+        Some(Node::Text(t)) if t.starts_with(char::is_whitespace) => vec![Inline::Text { text: " ".to_string() }],
+        _ => Vec::new(),
+    }
+}
+
+fn trailing_space(children: &[Node]) -> Vec<Inline> {
+    match children.last() {
+        Some(Node::Text(t)) if t.ends_with(char::is_whitespace) => vec![Inline::Text { text: " ".to_string() }],
+        _ => Vec::new(),
+    }
+}
+
+fn cell_inlines(cell: &Node) -> Vec<Inline> {
+    let inlines = cell.children().iter().flat_map(|c| {
+        if is_block_node(c) {
+            // Block content in a cell is flattened onto one line
+            let mut v = node_inlines(c);
+            v.push(Inline::Text { text: " ".to_string() });
+            v
+        } else {
+            node_inlines(c)
+        }
+    });
+    normalize_inlines(inlines.collect())
+        .into_iter()
+        .map(|i| if i == Inline::LineBreak { Inline::Text { text: " ".to_string() } } else { i })
+        .collect()
+}
+
+fn table_block(node: &Node) -> Block {
+    fn rows(node: &Node, out: &mut Vec<Node>) {
+        for child in node.children() {
+            match child.name() {
+                "tr" => out.push(child.clone()),
+                "thead" | "tbody" | "tfoot" => rows(child, out),
+                _ => {}
+            }
+        }
+    }
+    let mut all = Vec::new();
+    rows(node, &mut all);
+    let cells = |row: &Node| -> Vec<Node> {
+        row.children().iter().filter(|c| matches!(c.name(), "td" | "th")).cloned().collect()
+    };
+    let header_row = all.first().map(cells).unwrap_or_default();
+    let align = header_row
+        .iter()
+        .map(|c| {
+            let style = c.attr("style").unwrap_or("").replace(' ', "").to_lowercase();
+            let align = c.attr("align").unwrap_or("").to_lowercase();
+            if style.contains("text-align:center") || align == "center" {
+                Align::Center
+            } else if style.contains("text-align:right") || align == "right" {
+                Align::Right
+            } else if style.contains("text-align:left") || align == "left" {
+                Align::Left
+            } else {
+                Align::None
+            }
+        })
+        .collect::<Vec<_>>();
+    let width = all.iter().map(|r| cells(r).len()).max().unwrap_or(0);
+    let mut header: Vec<Vec<Inline>> = header_row.iter().map(cell_inlines).collect();
+    header.resize(width, Vec::new());
+    let mut align = align;
+    align.resize(width, Align::None);
+    let rows = all
+        .iter()
+        .skip(1)
+        .map(|r| {
+            let mut row: Vec<Vec<Inline>> = cells(r).iter().map(cell_inlines).collect();
+            row.resize(width, Vec::new());
+            row
+        })
+        .collect();
+    Block::Table { align, header, rows }
+}
+
+fn list_block(node: &Node, ordered: bool) -> Block {
+    let items = node
+        .children()
+        .iter()
+        .filter(|c| c.name() == "li")
+        .map(|li| {
+            let checkbox = li.children().iter().find(|c| c.name() == "input" && c.attr("type") == Some("checkbox"));
+            ListItem {
+                checked: checkbox.map(|c| c.attr("checked").is_some()),
+                blocks: nodes_to_blocks(li.children()),
+            }
+        })
+        .collect();
+    let start = node.attr("start").and_then(|s| s.parse().ok()).unwrap_or(1);
+    Block::List { ordered, start, items }
+}
+
+fn code_language(pre: &Node) -> String {
+    let class_owner = pre.children().iter().find(|c| c.name() == "code").unwrap_or(pre);
+    class_owner
+        .attr("class")
+        .unwrap_or("")
+        .split_whitespace()
+        .find_map(|c| c.strip_prefix("language-").or_else(|| c.strip_prefix("lang-")))
+        .filter(|l| *l != "plaintext")
+        .unwrap_or("")
+        .to_string()
+}
+
+fn element_blocks(node: &Node, out: &mut Vec<Block>) {
+    let name = node.name();
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let content = normalize_inlines(node.children().iter().flat_map(node_inlines).collect());
+            if !content.is_empty() {
+                out.push(Block::Heading { level: name.as_bytes()[1] - b'0', content });
+            }
+        }
+        "p" => {
+            let content = normalize_inlines(node.children().iter().flat_map(node_inlines).collect());
+            if !content.is_empty() {
+                out.push(Block::Paragraph { content });
+            }
+        }
+        "pre" => {
+            let code = node.raw_text();
+            out.push(Block::CodeBlock {
+                language: code_language(node),
+                code: code.strip_suffix('\n').unwrap_or(&code).to_string(),
+            });
+        }
+        "blockquote" => out.push(Block::BlockQuote { blocks: nodes_to_blocks(node.children()) }),
+        "ul" => out.push(list_block(node, false)),
+        "ol" => out.push(list_block(node, true)),
+        "table" => out.push(table_block(node)),
+        "hr" => out.push(Block::ThematicBreak),
+        "div" if node.has_class("math") => {
+            let tex = node.raw_text();
+            let tex = tex.trim().trim_start_matches("\\[").trim_end_matches("\\]");
+            out.push(Block::MathBlock { tex: tex.trim().to_string() });
+        }
+        "section" if node.has_class("footnotes") => {
+            let items = node.children().iter().filter(|c| c.name() == "ol").flat_map(|ol| ol.children()).filter(|c| c.name() == "li");
+            for li in items {
+                let label = li.attr("id").unwrap_or("").trim_start_matches("fn-").to_string();
+                out.push(Block::FootnoteDefinition { label, blocks: nodes_to_blocks(li.children()) });
+            }
+        }
+        _ => out.extend(nodes_to_blocks(node.children())),
+    }
+}
+
+/// Convert a sequence of sibling nodes into blocks, gathering runs of
+/// inline content into paragraphs
+pub(crate) fn nodes_to_blocks(nodes: &[Node]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut pending: Vec<Inline> = Vec::new();
+    let flush = |pending: &mut Vec<Inline>, blocks: &mut Vec<Block>| {
+        let content = normalize_inlines(std::mem::take(pending));
+        if !content.is_empty() {
+            blocks.push(Block::Paragraph { content });
+        }
+    };
+    for node in nodes {
+        if is_block_node(node) {
+            flush(&mut pending, &mut blocks);
+            element_blocks(node, &mut blocks);
+        } else {
+            pending.extend(node_inlines(node));
+        }
+    }
+    flush(&mut pending, &mut blocks);
+    blocks
+}
+
+/// Read an HTML fragment or document into a document tree. The HTML is
+/// sanitized first so no `javascript:` link survives into the markdown.
+pub(crate) fn html_to_document(html: &str) -> Document {
+    let blocks = nodes_to_blocks(&parse_html(&sanitize(html, &SanitizePolicy::default())));
+    Document { front_matter: None, lines: vec![0; blocks.len()], blocks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_markdown;
+
+    #[test]
+    fn footnote_referenced_inside_another_is_listed() {
+        let html = render_html(&parse_markdown("Text[^a].\n\n[^a]: See[^b].\n\n[^b]: Nested.\n"));
+        assert!(html.contains("<a href=\"#fn-b\" id=\"fnref-b\">2</a>"));
+        assert!(html.contains("<li id=\"fn-b\">"));
+        assert!(html.contains("Nested."));
+    }
+}
@@ -0,0 +1,116 @@
+use wasm_bindgen::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+use crate::format::cached;
+use crate::links::{build_link, find_closing, normalize_label, parse_inline_link, reference_map};
+use crate::markdown::{code_ranges, in_ranges, line_of};
+
+/// An image reference found in the document
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ImageRef {
+    pub alt: String,
+    pub url: String,
+    pub title: Option<String>,
+    /// "inline", "reference" or "html"
+    pub kind: &'static str,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+/// All markdown (`![alt](url)`, `![alt][ref]`) and HTML (`<img src>`) images,
+/// in document order. Images inside code are ignored.
+pub(crate) fn find_images(content: &str) -> Vec<ImageRef> {
+    let skip = code_ranges(content);
+    let references = reference_map(content);
+    let mut images = Vec::new();
+
+    for (i, _) in content.match_indices("![") {
+        if in_ranges(&skip, i) {
+            continue;
+        }
+        if let Some(link) = parse_inline_link(content, i + 1) {
+            images.push(ImageRef {
+                alt: link.text,
+                url: link.url,
+                title: link.title,
+                kind: "inline",
+                start: i,
+                end: link.end,
+                line: line_of(content, i),
+            });
+            continue;
+        }
+        // Reference style: ![alt][ref], ![alt][] or ![alt]
+        let Some(close) = find_closing(content, i + 1, b'[', b']') else { continue };
+        let alt = &content[i + 2..close];
+        let (label, end) = match content[close + 1..].strip_prefix('[') {
+            Some(rest) => match rest.find(']') {
+                Some(0) => (alt, close + 3),
+                Some(len) => (&rest[..len], close + 2 + len + 1),
+                None => continue,
+            },
+            None => (alt, close + 1),
+        };
+        if let Some(def) = references.get(&normalize_label(label)) {
+            images.push(ImageRef {
+                alt: alt.to_string(),
+                url: def.url.clone(),
+                title: def.title.clone(),
+                kind: "reference",
+                start: i,
+                end,
+                line: line_of(content, i),
+            });
+        }
+    }
+
+    static IMG: OnceLock<Regex> = OnceLock::new();
+    static ATTR: OnceLock<Regex> = OnceLock::new();
+    let re_img = cached(&IMG, r#"(?i-u:<img)[^>]*>"#);
+    let re_attr = cached(&ATTR, r#"((?i-u:src|alt|title))[ \t]*=[ \t]*["']([^"']*)["']"#);
+    for m in re_img.find_iter(content) {
+        if in_ranges(&skip, m.start()) {
+            continue;
+        }
+        let mut image = ImageRef {
+            alt: String::new(),
+            url: String::new(),
+            title: None,
+            kind: "html",
+            start: m.start(),
+            end: m.end(),
+            line: line_of(content, m.start()),
+        };
+        for caps in re_attr.captures_iter(m.as_str()) {
+            let value = caps[2].to_string();
+            match caps[1].to_ascii_lowercase().as_str() {
+                "src" => image.url = value,
+                "alt" => image.alt = value,
+                _ => image.title = Some(value),
+            }
+        }
+        if !image.url.is_empty() {
+            images.push(image);
+        }
+    }
+
+    images.sort_by_key(|img| img.start);
+    images
+}
+
+/// Build markdown image syntax: `![alt](url "title")`
+#[wasm_bindgen]
+pub fn insert_image(alt: &str, url: &str, title: Option<String>) -> String {
+    format!("!{}", build_link(alt.trim(), url, title.as_deref()))
+}
+
+/// List every image referenced by the document as JSON
+/// (`[{alt, url, title, kind, start, end, line}]`), for the asset panel
+/// and missing-image validation.
+#[wasm_bindgen]
+pub fn extract_images(content: &str) -> String {
+    serde_json::to_string(&find_images(content)).unwrap_or_else(|_| "[]".to_string())
+}
@@ -0,0 +1,338 @@
+use wasm_bindgen::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use crate::format::cached;
+use crate::links::{build_wiki_link, find_links};
+use crate::markdown::{apply_edits, code_ranges, in_ranges};
+use crate::url::percent_decode;
+use crate::whitespace::{normalize, WhitespaceOptions};
+use crate::zip::{read_zip, ZipEntry};
+
+/// A markdown document recovered from an export archive
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ImportedDocument {
+    pub title: String,
+    /// Slash-separated workspace folder, "" for the root
+    pub folder: String,
+    pub content: String,
+    /// Path of the file inside the archive
+    pub source_path: String,
+}
+
+/// Result of `import_archive`: documents ready to add to the workspace,
+/// their attachments, and anything that couldn't be converted
+#[wasm_bindgen]
+pub struct ImportedArchive {
+    source: String,
+    documents: Vec<ImportedDocument>,
+    attachments: BTreeMap<String, Vec<u8>>,
+    warnings: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl ImportedArchive {
+    /// "notion" or "obsidian"
+    #[wasm_bindgen(getter)]
+    pub fn source(&self) -> String {
+        self.source.clone()
+    }
+
+    /// Documents as JSON `[{title, folder, content, source_path}]`
+    pub fn documents(&self) -> String {
+        serde_json::to_string(&self.documents).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Attachment paths (as referenced by the rewritten documents) as JSON
+    pub fn attachment_paths(&self) -> String {
+        let paths: Vec<&String> = self.attachments.keys().collect();
+        serde_json::to_string(&paths).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Bytes of one attachment
+    pub fn attachment(&self, path: &str) -> Option<Vec<u8>> {
+        self.attachments.get(path).cloned()
+    }
+
+    /// Problems found during import (unresolved links, skipped files) as JSON
+    pub fn warnings(&self) -> String {
+        serde_json::to_string(&self.warnings).unwrap_or_else(|_| "[]".to_string())
+    }
+}
+
+fn is_markdown(path: &str) -> bool {
+    let lower = path.to_ascii_lowercase();
+    lower.ends_with(".md") || lower.ends_with(".markdown")
+}
+
+/// Files that are app metadata rather than content
+fn is_ignored(path: &str) -> bool {
+    path.split('/').any(|segment| {
+        matches!(segment, "__MACOSX" | ".DS_Store" | ".obsidian" | ".trash" | "Thumbs.db")
+    })
+}
+
+/// Notion appends a 32-hex-digit page id to every file and folder name
+/// (`Meeting notes 0123...cdef.md`); strip it
+fn strip_notion_id(segment: &str) -> String {
+    let (stem, ext) = match segment.rfind('.') {
+        Some(dot) if !segment[dot..].contains(' ') => segment.split_at(dot),
+        _ => (segment, ""),
+    };
+    let stripped = match stem.rsplit_once(' ') {
+        Some((name, id)) if id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit()) => name,
+        _ => stem,
+    };
+    format!("{}{}", stripped, ext)
+}
+
+fn normalize_path(path: &str, source: &str) -> String {
+    if source == "notion" {
+        path.split('/').map(strip_notion_id).collect::<Vec<_>>().join("/")
+    } else {
+        path.to_string()
+    }
+}
+
+fn file_stem(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Resolve `target` relative to the directory `base`, handling `.` and `..`
+fn resolve_relative(base: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = if target.starts_with('/') {
+        Vec::new()
+    } else {
+        base.split('/').filter(|s| !s.is_empty()).collect()
+    };
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            other => parts.push(other),
+        }
+    }
+    parts.join("/")
+}
+
+fn detect_source(entries: &[ZipEntry]) -> &'static str {
+    let notion_ids = entries.iter().any(|e| {
+        e.name
+            .split('/')
+            .any(|segment| strip_notion_id(segment) != segment)
+    });
+    if entries.iter().any(|e| e.name.split('/').any(|s| s == ".obsidian")) || !notion_ids {
+        "obsidian"
+    } else {
+        "notion"
+    }
+}
+
+/// Unpack nested `.zip` files (Notion splits large exports into parts)
+fn flatten_archives(entries: Vec<ZipEntry>, warnings: &mut Vec<String>) -> Vec<ZipEntry> {
+    let mut files = Vec::new();
+    for entry in entries {
+        if entry.name.to_ascii_lowercase().ends_with(".zip") {
+            match read_zip(&entry.data) {
+                Ok(inner) => files.extend(inner),
+                Err(e) => warnings.push(format!("Skipped nested archive {}: {}", entry.name, e)),
+            }
+        } else {
+            files.push(entry);
+        }
+    }
+    files
+}
+
+struct Index {
+    /// Archive path -> title, for markdown links
+    by_path: HashMap<String, String>,
+    /// Lowercase file name (no extension) -> titles, for wiki links
+    by_name: HashMap<String, Vec<String>>,
+    /// Archive path -> normalized attachment path
+    attachments: HashMap<String, String>,
+    /// Lowercase attachment file name -> normalized path, for `![[embeds]]`
+    attachments_by_name: HashMap<String, String>,
+}
+
+impl Index {
+    fn note_by_name(&self, target: &str) -> Option<&String> {
+        let name = file_stem(target).to_lowercase();
+        self.by_name.get(&name).and_then(|titles| titles.first())
+    }
+}
+
+/// Rewrite internal links of one document: relative `.md` links and
+/// Obsidian `[[wiki links]]` become our `[[Title]]` links, attachment
+/// references point at the imported attachment paths
+fn rewrite_links(content: &str, path: &str, index: &Index, warnings: &mut Vec<String>) -> String {
+    let skip = code_ranges(content);
+    let dir = parent_dir(path);
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+
+    for link in find_links(content) {
+        let is_image = link.kind == "image";
+        if link.kind != "inline" && !is_image {
+            continue;
+        }
+        if link.url.contains("://") || link.url.starts_with("mailto:") || link.url.starts_with('#') {
+            continue;
+        }
+        let (target, anchor) = match link.url.split_once('#') {
+            Some((target, anchor)) => (target, Some(percent_decode(anchor))),
+            None => (link.url.as_str(), None),
+        };
+        let resolved = resolve_relative(dir, &percent_decode(target));
+        if is_image {
+            if let Some(attachment) = index.attachments.get(&resolved) {
+                let alt = &link.text;
+                edits.push((link.start, link.end, format!("![{}](<{}>)", alt, attachment)));
+            }
+            continue;
+        }
+        if !is_markdown(&resolved) {
+            if let Some(attachment) = index.attachments.get(&resolved) {
+                edits.push((link.start, link.end, format!("[{}](<{}>)", link.text, attachment)));
+            }
+            continue;
+        }
+        match index.by_path.get(&resolved) {
+            Some(title) => edits.push((
+                link.start,
+                link.end,
+                build_wiki_link(title, anchor.as_deref(), Some(&link.text)),
+            )),
+            None => warnings.push(format!("{}: link to missing page {}", path, resolved)),
+        }
+    }
+
+    // Obsidian wiki links and embeds: [[Note#Heading|alias]], ![[image.png]]
+    static WIKI: OnceLock<Regex> = OnceLock::new();
+    let re_wiki = cached(&WIKI, r"(!?)\[\[([^\]\n]+)\]\]");
+    for caps in re_wiki.captures_iter(content) {
+        let whole = caps.get(0).unwrap();
+        if in_ranges(&skip, whole.start()) {
+            continue;
+        }
+        let embed = !caps[1].is_empty();
+        let (target, alias) = match caps[2].split_once('|') {
+            Some((target, alias)) => (target.trim(), Some(alias.trim())),
+            None => (caps[2].trim(), None),
+        };
+        let (target, anchor) = match target.split_once('#') {
+            Some((target, anchor)) => (target, Some(anchor)),
+            None => (target, None),
+        };
+
+        let file_name = target.rsplit('/').next().unwrap_or(target).to_lowercase();
+        if embed && !is_markdown(target) {
+            if let Some(attachment) = index.attachments_by_name.get(&file_name) {
+                let alt = alias.unwrap_or(target);
+                edits.push((whole.start(), whole.end(), format!("![{}](<{}>)", alt, attachment)));
+                continue;
+            }
+        }
+        match index.note_by_name(target) {
+            Some(title) => {
+                let link = build_wiki_link(title, anchor, alias);
+                edits.push((whole.start(), whole.end(), if embed { format!("!{}", link) } else { link }));
+            }
+            // Same-page heading link: [[#Heading]]
+            None if target.is_empty() => {}
+            None => warnings.push(format!("{}: wiki link to missing page {}", path, target)),
+        }
+    }
+
+    // Keep the outermost edit where links nest (`[![img](a.png)](b.md)`)
+    edits.sort_by_key(|e| (e.0, std::cmp::Reverse(e.1)));
+    let mut kept: Vec<(usize, usize, String)> = Vec::new();
+    for edit in edits {
+        if kept.last().is_none_or(|last| edit.0 >= last.1) {
+            kept.push(edit);
+        }
+    }
+    apply_edits(content, kept)
+}
+
+/// Import a Notion or Obsidian export archive. `source` is "notion",
+/// "obsidian" or "auto" (detect from the archive layout). Markdown files
+/// become documents whose folders mirror the archive (Notion page ids
+/// stripped); internal links are rewritten to `[[Title]]` wiki links and
+/// attachment references to the returned attachment paths.
+#[wasm_bindgen]
+pub fn import_archive(zip_bytes: &[u8], source: &str) -> Result<ImportedArchive, JsValue> {
+    let mut warnings = Vec::new();
+    let entries = read_zip(zip_bytes).map_err(|e| JsValue::from_str(&format!("Archive error: {}", e)))?;
+    let entries: Vec<ZipEntry> = flatten_archives(entries, &mut warnings)
+        .into_iter()
+        .filter(|e| !is_ignored(&e.name))
+        .collect();
+    let source = match source {
+        "notion" => "notion",
+        "obsidian" => "obsidian",
+        "auto" | "" => detect_source(&entries),
+        other => return Err(JsValue::from_str(&format!("Unknown archive source: {}", other))),
+    };
+
+    let mut index = Index {
+        by_path: HashMap::new(),
+        by_name: HashMap::new(),
+        attachments: HashMap::new(),
+        attachments_by_name: HashMap::new(),
+    };
+    let mut attachments = BTreeMap::new();
+    for entry in &entries {
+        let normalized = normalize_path(&entry.name, source);
+        if is_markdown(&entry.name) {
+            let title = file_stem(&normalized).to_string();
+            index.by_path.insert(entry.name.clone(), title.clone());
+            let titles = index.by_name.entry(file_stem(&entry.name).to_lowercase()).or_default();
+            titles.push(title.clone());
+            if source == "notion" {
+                index.by_name.entry(title.to_lowercase()).or_default().push(title);
+            }
+        } else if entry.name.to_ascii_lowercase().ends_with(".csv") && source == "notion" {
+            warnings.push(format!("Skipped Notion database export {}", entry.name));
+        } else {
+            let file_name = normalized.rsplit('/').next().unwrap_or(&normalized).to_lowercase();
+            index.attachments.insert(entry.name.clone(), normalized.clone());
+            index.attachments_by_name.entry(file_name).or_insert_with(|| normalized.clone());
+            attachments.insert(normalized, entry.data.clone());
+        }
+    }
+
+    let mut seen_titles: HashMap<String, usize> = HashMap::new();
+    let mut documents = Vec::new();
+    for entry in entries.iter().filter(|e| is_markdown(&e.name)) {
+        let text = normalize(&String::from_utf8_lossy(&entry.data), &WhitespaceOptions::default());
+        let normalized = normalize_path(&entry.name, source);
+        let title = file_stem(&normalized).to_string();
+        *seen_titles.entry(title.to_lowercase()).or_default() += 1;
+        documents.push(ImportedDocument {
+            content: rewrite_links(&text, &entry.name, &index, &mut warnings),
+            folder: parent_dir(&normalized).to_string(),
+            title,
+            source_path: entry.name.clone(),
+        });
+    }
+    for (title, count) in seen_titles.into_iter().filter(|(_, n)| *n > 1) {
+        warnings.push(format!("{} documents are titled \"{}\"; wiki links to it are ambiguous", count, title));
+    }
+    warnings.sort();
+
+    Ok(ImportedArchive {
+        source: source.to_string(),
+        documents,
+        attachments,
+        warnings,
+    })
+}
@@ -0,0 +1,289 @@
+// Daily notes for journaling: one document per day titled `YYYY-MM-DD`,
+// created from a template and linked to the neighbouring days' notes with
+// a navigation line of wiki links.
+
+use wasm_bindgen::prelude::*;
+
+use crate::front_matter::find_front_matter;
+use crate::links::build_wiki_link;
+
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December",
+];
+
+const PREVIOUS_LABEL: &str = "← Previous";
+const NEXT_LABEL: &str = "Next →";
+const NAV_SEPARATOR: &str = " · ";
+
+/// Used when `create_daily_note` is given an empty template
+const DEFAULT_TEMPLATE: &str = "# {{weekday}}, {{month_name}} {{day}}, {{year}}\n\n{{navigation}}\n\n";
+
+/// A calendar date in the proleptic Gregorian calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+/// Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+pub(crate) fn civil_from_days(days: i64) -> Date {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    Date { year, month: month as u32, day: day as u32 }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Date {
+    /// Parse an ISO `YYYY-MM-DD` date, rejecting days that don't exist
+    pub(crate) fn parse(text: &str) -> Option<Date> {
+        let text = text.trim();
+        let mut parts = text.splitn(3, '-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+        if !text.bytes().all(|b| b.is_ascii_digit() || b == b'-') {
+            return None;
+        }
+        let date = Date { year: year.parse().ok()?, month: month.parse().ok()?, day: day.parse().ok()? };
+        let valid = (1..=12).contains(&date.month) && date.day >= 1 && date.day <= days_in_month(date.year, date.month);
+        valid.then_some(date)
+    }
+
+    /// Days since 1970-01-01 (inverse of `civil_from_days`)
+    pub(crate) fn days(self) -> i64 {
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = (i64::from(self.month) + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    pub(crate) fn add_days(self, n: i64) -> Date {
+        civil_from_days(self.days() + n)
+    }
+
+    fn weekday(self) -> &'static str {
+        // 1970-01-01 was a Thursday
+        WEEKDAYS[(self.days() + 3).rem_euclid(7) as usize]
+    }
+
+    fn month_name(self) -> &'static str {
+        MONTHS[self.month as usize - 1]
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Dates of the existing daily notes, from a JSON array of document titles.
+/// Titles that aren't dates are ignored.
+fn note_dates(titles_json: &str) -> Result<Vec<Date>, JsValue> {
+    let titles: Vec<String> = serde_json::from_str(titles_json)
+        .map_err(|e| JsValue::from_str(&format!("Invalid note list: {}", e)))?;
+    let mut dates: Vec<Date> = titles.iter().filter_map(|t| Date::parse(t)).collect();
+    dates.sort();
+    dates.dedup();
+    Ok(dates)
+}
+
+fn parse_date(date: &str) -> Result<Date, JsValue> {
+    Date::parse(date).ok_or_else(|| JsValue::from_str(&format!("Invalid date: {} (expected YYYY-MM-DD)", date)))
+}
+
+/// The notes just before and after `date`. Without a note list the
+/// neighbours are the calendar days either side.
+fn neighbours(date: Date, existing: Option<&[Date]>) -> (Option<Date>, Option<Date>) {
+    match existing {
+        Some(dates) => (
+            dates.iter().rev().find(|d| **d < date).copied(),
+            dates.iter().find(|d| **d > date).copied(),
+        ),
+        None => (Some(date.add_days(-1)), Some(date.add_days(1))),
+    }
+}
+
+/// `[[2024-03-01|← Previous]] · [[2024-03-03|Next →]]`, or an empty string
+/// when there is neither
+fn navigation_line(previous: Option<Date>, next: Option<Date>) -> String {
+    let mut parts = Vec::new();
+    if let Some(previous) = previous {
+        parts.push(build_wiki_link(&previous.to_string(), None, Some(PREVIOUS_LABEL)));
+    }
+    if let Some(next) = next {
+        parts.push(build_wiki_link(&next.to_string(), None, Some(NEXT_LABEL)));
+    }
+    parts.join(NAV_SEPARATOR)
+}
+
+/// Whether `line` is a navigation line written by `navigation_line`
+fn is_navigation_line(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty()
+        && line.split(NAV_SEPARATOR).all(|part| {
+            let Some(inner) = part.strip_prefix("[[").and_then(|p| p.strip_suffix("]]")) else {
+                return false;
+            };
+            match inner.split_once('|') {
+                Some((target, label)) => Date::parse(target).is_some() && (label == PREVIOUS_LABEL || label == NEXT_LABEL),
+                None => false,
+            }
+        })
+}
+
+fn fill_template(template: &str, date: Date, navigation: &str, previous: Option<Date>, next: Option<Date>) -> String {
+    let link = |d: Option<Date>| d.map(|d| build_wiki_link(&d.to_string(), None, None)).unwrap_or_default();
+    [
+        ("{{date}}", date.to_string()),
+        ("{{year}}", format!("{:04}", date.year)),
+        ("{{month}}", format!("{:02}", date.month)),
+        ("{{day}}", date.day.to_string()),
+        ("{{weekday}}", date.weekday().to_string()),
+        ("{{month_name}}", date.month_name().to_string()),
+        ("{{previous}}", link(previous)),
+        ("{{next}}", link(next)),
+        ("{{navigation}}", navigation.to_string()),
+    ]
+    .iter()
+    .fold(template.to_string(), |text, (placeholder, value)| text.replace(placeholder, value))
+}
+
+/// Create the daily note for `date` (`YYYY-MM-DD`) from `template`.
+///
+/// Placeholders: `{{date}}`, `{{year}}`, `{{month}}`, `{{day}}`,
+/// `{{weekday}}`, `{{month_name}}`, `{{previous}}` and `{{next}}` (wiki
+/// links to the neighbouring notes) and `{{navigation}}` (the full
+/// navigation line). A template without `{{navigation}}` gets the line
+/// appended. `existing_notes` is a JSON array of document titles; when
+/// given, the links point to the nearest existing notes instead of the
+/// calendar days either side. An empty template uses a default heading.
+#[wasm_bindgen]
+pub fn create_daily_note(date: &str, template: &str, existing_notes: Option<String>) -> Result<String, JsValue> {
+    let date = parse_date(date)?;
+    let existing = existing_notes.as_deref().map(note_dates).transpose()?;
+    let (previous, next) = neighbours(date, existing.as_deref());
+    let navigation = navigation_line(previous, next);
+
+    let template = if template.trim().is_empty() { DEFAULT_TEMPLATE } else { template };
+    let mut note = fill_template(template, date, &navigation, previous, next);
+    if !template.contains("{{navigation}}") && !navigation.is_empty() {
+        if !note.is_empty() && !note.ends_with('\n') {
+            note.push('\n');
+        }
+        if !note.is_empty() && !note.ends_with("\n\n") {
+            note.push('\n');
+        }
+        note.push_str(&navigation);
+        note.push('\n');
+    }
+    Ok(note)
+}
+
+/// The date of the note before `date`: the nearest earlier entry of
+/// `existing_notes` (a JSON array of titles), or the previous calendar day
+/// when no list is given. `undefined` when there is no earlier note.
+#[wasm_bindgen]
+pub fn previous_note_date(date: &str, existing_notes: Option<String>) -> Result<Option<String>, JsValue> {
+    let date = parse_date(date)?;
+    let existing = existing_notes.as_deref().map(note_dates).transpose()?;
+    Ok(neighbours(date, existing.as_deref()).0.map(|d| d.to_string()))
+}
+
+/// The date of the note after `date`; see `previous_note_date`
+#[wasm_bindgen]
+pub fn next_note_date(date: &str, existing_notes: Option<String>) -> Result<Option<String>, JsValue> {
+    let date = parse_date(date)?;
+    let existing = existing_notes.as_deref().map(note_dates).transpose()?;
+    Ok(neighbours(date, existing.as_deref()).1.map(|d| d.to_string()))
+}
+
+/// Point the navigation line of the daily note for `date` at its current
+/// neighbours in `existing_notes` (a JSON array of titles). Call this on
+/// the notes either side after creating a note so the chain stays linked.
+/// A note without a navigation line gets one after its first heading (or
+/// at the top of the body).
+#[wasm_bindgen]
+pub fn update_daily_note_links(content: &str, date: &str, existing_notes: &str) -> Result<String, JsValue> {
+    let date = parse_date(date)?;
+    let existing = note_dates(existing_notes)?;
+    let (previous, next) = neighbours(date, Some(&existing));
+    let navigation = navigation_line(previous, next);
+
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        if is_navigation_line(text) {
+            let mut out = String::with_capacity(content.len());
+            out.push_str(&content[..offset]);
+            if navigation.is_empty() {
+                // Drop the line and the blank line after it
+                let rest = &content[offset + line.len()..];
+                out.push_str(rest.strip_prefix('\n').unwrap_or(rest));
+            } else {
+                out.push_str(&navigation);
+                out.push_str(&content[offset + text.len()..]);
+            }
+            return Ok(out);
+        }
+        offset += line.len();
+    }
+    if navigation.is_empty() {
+        return Ok(content.to_string());
+    }
+
+    // No navigation line yet: insert one after the first heading
+    let body_start = find_front_matter(content).map_or(0, |fm| fm.body_start);
+    let mut insert_at = body_start;
+    let mut offset = body_start;
+    for line in content[body_start..].split_inclusive('\n') {
+        if !line.trim().is_empty() {
+            if line.starts_with('#') {
+                insert_at = offset + line.len();
+            }
+            break;
+        }
+        offset += line.len();
+    }
+    let mut out = String::with_capacity(content.len() + navigation.len() + 3);
+    out.push_str(&content[..insert_at]);
+    if insert_at > body_start {
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out.push_str(&navigation);
+    out.push('\n');
+    let rest = content[insert_at..].trim_start_matches(['\n', '\r']);
+    if !rest.is_empty() {
+        out.push('\n');
+        out.push_str(rest);
+    }
+    Ok(out)
+}
@@ -0,0 +1,78 @@
+// Document keys from passphrases, for "protect this document with a
+// password" without a server: Argon2id stretches the passphrase with a
+// per-document salt into the 32-byte key the encryption layer takes. The
+// salt and parameters aren't secret and are stored with the document.
+
+use wasm_bindgen::prelude::*;
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::Deserialize;
+
+use crate::hash_chain::{from_hex, to_hex};
+use crate::share::random_bytes;
+
+const SALT_BYTES: usize = 16;
+
+/// Shortest salt Argon2 accepts
+const MIN_SALT_BYTES: usize = 8;
+
+/// Argon2id cost parameters. The defaults (19 MiB, 2 passes, 1 lane) are
+/// the OWASP minimum and take well under a second in a browser; raise
+/// `memory_kib` or `iterations` for documents that warrant it.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+struct KeyDerivationOptions {
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl Default for KeyDerivationOptions {
+    fn default() -> Self {
+        KeyDerivationOptions { memory_kib: 19 * 1024, iterations: 2, parallelism: 1 }
+    }
+}
+
+/// Derive a 32-byte document key for `encrypt_message` from a passphrase
+/// and salt. `options` is JSON `{memory_kib, iterations, parallelism}`;
+/// missing fields (or an empty string) use the defaults. The same
+/// passphrase, salt and options always give the same key, so store the
+/// salt and options alongside the document.
+#[wasm_bindgen]
+pub fn derive_document_key(passphrase: &str, salt: &[u8], options: &str) -> Result<Vec<u8>, JsValue> {
+    let options: KeyDerivationOptions = if options.trim().is_empty() {
+        KeyDerivationOptions::default()
+    } else {
+        serde_json::from_str(options)
+            .map_err(|e| JsValue::from_str(&format!("Invalid key derivation options: {}", e)))?
+    };
+    if salt.len() < MIN_SALT_BYTES {
+        return Err(JsValue::from_str(&format!("Salt must be at least {} bytes", MIN_SALT_BYTES)));
+    }
+    let params = Params::new(options.memory_kib, options.iterations, options.parallelism, Some(32))
+        .map_err(|e| JsValue::from_str(&format!("Invalid key derivation options: {}", e)))?;
+    let mut key = vec![0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| JsValue::from_str(&format!("Key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+/// Generate a random 16-byte salt for a new protected document
+#[wasm_bindgen]
+pub fn generate_salt() -> Result<Vec<u8>, JsValue> {
+    let mut salt = vec![0u8; SALT_BYTES];
+    random_bytes(&mut salt)?;
+    Ok(salt)
+}
+
+/// Salt as lowercase hex, for storing in document metadata
+#[wasm_bindgen]
+pub fn salt_to_hex(salt: &[u8]) -> String {
+    to_hex(salt)
+}
+
+/// Salt from the hex produced by `salt_to_hex`
+#[wasm_bindgen]
+pub fn salt_from_hex(hex: &str) -> Result<Vec<u8>, JsValue> {
+    from_hex(hex.trim()).ok_or_else(|| JsValue::from_str("Salt must be hex"))
+}
@@ -0,0 +1,129 @@
+// Sharing a document key with a new collaborator. Each user has a
+// long-lived X25519 exchange key pair and publishes the public half; to
+// invite someone, the sender makes a one-off key pair, agrees a secret
+// with the recipient's public key, and sends the document key sealed
+// under it in a `key_share` message. Only the recipient's secret key
+// opens it, so the relay never sees the document key.
+
+use wasm_bindgen::prelude::*;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload as Sealed};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::payload::{decode_payload, payload_data, KeyShare, Payload};
+use crate::share::random_bytes;
+use crate::{decode_with_grid_tag, encode_promisegrid_payload};
+
+const NONCE_BYTES: usize = 24;
+
+fn secret_key(bytes: &[u8]) -> Result<StaticSecret, JsValue> {
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| JsValue::from_str("Exchange key must be 32 bytes"))?;
+    Ok(StaticSecret::from(bytes))
+}
+
+fn public_key(bytes: &[u8]) -> Result<PublicKey, JsValue> {
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| JsValue::from_str("Public key must be 32 bytes"))?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// Key sealing the document key: HKDF-SHA256 over the shared secret,
+/// salted with both public keys and bound to the document and recipient
+fn wrapping_cipher(
+    secret: &StaticSecret,
+    their_public: &PublicKey,
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+    share: (&str, &str),
+) -> Result<XChaCha20Poly1305, JsValue> {
+    let shared = secret.diffie_hellman(their_public);
+    if !shared.was_contributory() {
+        return Err(JsValue::from_str("Public key is not a usable X25519 key"));
+    }
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes().as_slice()].concat();
+    let info = format!("collab key share\0{}\0{}", share.0, share.1);
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(info.as_bytes(), &mut key)
+        .map_err(|_| JsValue::from_str("Key derivation failed"))?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+/// Generate a 32-byte X25519 secret key for receiving document keys. Keep
+/// it on the device; publish `exchange_public_key` of it.
+#[wasm_bindgen]
+pub fn generate_exchange_key() -> Result<Vec<u8>, JsValue> {
+    let mut secret = vec![0u8; 32];
+    random_bytes(&mut secret)?;
+    Ok(secret)
+}
+
+/// Public key (32 bytes) for a secret key from `generate_exchange_key`
+#[wasm_bindgen]
+pub fn exchange_public_key(secret_key_bytes: &[u8]) -> Result<Vec<u8>, JsValue> {
+    Ok(PublicKey::from(&secret_key(secret_key_bytes)?).as_bytes().to_vec())
+}
+
+/// Create a `key_share` message giving `document_key` to `recipient_id`,
+/// whose exchange public key is `recipient_public_key`. A fresh one-off
+/// key pair is made for each message, so the sender needs no exchange key
+/// of their own and past shares stay sealed if the sender's device is
+/// later compromised.
+#[wasm_bindgen]
+pub fn create_key_share_message(
+    document_id: &str,
+    user_id: &str,
+    recipient_id: &str,
+    recipient_public_key: &[u8],
+    document_key: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    if document_key.len() != 32 {
+        return Err(JsValue::from_str("Document key must be 32 bytes"));
+    }
+    let recipient = public_key(recipient_public_key)?;
+    let ephemeral_secret = secret_key(&generate_exchange_key()?)?;
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let cipher = wrapping_cipher(&ephemeral_secret, &recipient, &ephemeral, &recipient, (document_id, recipient_id))?;
+    let mut nonce = vec![0u8; NONCE_BYTES];
+    random_bytes(&mut nonce)?;
+    let wrapped_key = cipher
+        .encrypt(XNonce::from_slice(&nonce), Sealed { msg: document_key, aad: document_id.as_bytes() })
+        .map_err(|_| JsValue::from_str("Encryption failed"))?;
+    let share = KeyShare {
+        document_id: document_id.to_string(),
+        user_id: user_id.to_string(),
+        recipient_id: recipient_id.to_string(),
+        ephemeral_key: ephemeral.as_bytes().to_vec(),
+        aead_nonce: nonce,
+        wrapped_key,
+        timestamp: js_sys::Date::now(),
+    };
+    Ok(encode_promisegrid_payload("key_share", payload_data(&share)))
+}
+
+/// Open a `key_share` message with the recipient's exchange secret key,
+/// returning the 32-byte document key. Fails for shares meant for someone
+/// else or altered in transit.
+#[wasm_bindgen]
+pub fn open_key_share(cbor_bytes: &[u8], secret_key_bytes: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    let (_, Payload::KeyShare(share)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+        return Err(JsValue::from_str(&format!("Expected a key_share message, got {}", message.payload.message_type)));
+    };
+    if share.aead_nonce.len() != NONCE_BYTES {
+        return Err(JsValue::from_str("Key share has a malformed nonce"));
+    }
+    let secret = secret_key(secret_key_bytes)?;
+    let ephemeral = public_key(&share.ephemeral_key)?;
+    let cipher = wrapping_cipher(
+        &secret,
+        &ephemeral,
+        &ephemeral,
+        &PublicKey::from(&secret),
+        (&share.document_id, &share.recipient_id),
+    )?;
+    cipher
+        .decrypt(XNonce::from_slice(&share.aead_nonce), Sealed { msg: &share.wrapped_key, aad: share.document_id.as_bytes() })
+        .map_err(|_| JsValue::from_str("Key share could not be opened: not addressed to this key, or altered"))
+}
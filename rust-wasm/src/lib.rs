@@ -3,10 +3,104 @@ use wasm_bindgen::prelude::*;
 use flate2::Compression;
 use flate2::write::{GzEncoder, GzDecoder};
 use std::io::prelude::*;
-// use regex::Regex;
-use serde_json;
-
-
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use locale::LocaleInfo;
+
+mod abbreviations;
+mod access;
+mod activity;
+mod asciidoc;
+mod ast;
+mod audit;
+mod autosave;
+mod batch;
+mod batcher;
+mod blame;
+mod buffer;
+mod capabilities;
+mod capability_token;
+mod cbor_json;
+mod citations;
+mod clock;
+mod codec;
+mod comments;
+mod compaction;
+mod cursor;
+mod document_import;
+mod dedupe;
+mod diagnostic;
+mod diff;
+mod docx;
+mod edit_log;
+mod emphasis;
+mod encryption;
+mod epub;
+mod export;
+mod fidelity;
+mod folder_tree;
+mod footnotes;
+mod format;
+mod front_matter;
+mod goals;
+mod handshake;
+mod hash_chain;
+mod headings;
+mod highlight;
+mod html;
+mod images;
+mod import;
+mod journal;
+mod key_derivation;
+mod key_exchange;
+mod link_check;
+mod links;
+mod line_ops;
+mod lint_scheduler;
+mod locale;
+mod logger;
+mod macros;
+mod markdown;
+mod math;
+mod mentions;
+mod merge;
+mod metadata;
+mod offline;
+mod outbound;
+mod outline;
+mod paste;
+mod patch;
+mod payload;
+mod plaintext;
+mod performance;
+mod profile;
+mod presence;
+mod promisegrid;
+mod punctuation;
+mod quota;
+mod reflow;
+mod replay;
+mod replay_guard;
+mod rst;
+mod rtf;
+mod sanitize;
+mod sequence;
+mod session;
+mod share;
+mod signing;
+mod snapshot;
+mod snippets;
+mod style_metrics;
+mod sync;
+mod syntax_tree;
+mod toc;
+mod transport_stats;
+mod url;
+mod validate;
+mod versions;
+mod whitespace;
+mod workspace;
+mod zip;
 
 #[wasm_bindgen]
 pub fn export_to_markdown(raw: &str) -> String {
@@ -30,119 +124,18 @@ pub fn decompress_document(compressed_data: &[u8]) -> String {
     String::from_utf8(decompressed).unwrap_or_else(|_| String::new())
 }
 
-
-// Format the text for better readability and consistency
-#[wasm_bindgen]
-pub fn format_text(input: &str) -> String {
-    let mut text = input.to_string();
-    
-    // 1. Clean up extra whitespace and line breaks
-    text = clean_whitespace(&text);
-    
-    // 2. Fix markdown headers
-    text = fix_markdown_headers(&text);
-    
-    // 3. Format code blocks
-    text = format_code_blocks(&text);
-    
-    // 4. Fix bold, italic, underline formatting
-    text = fix_markdown_formatting(&text);
-
-    // 5. Fix punctuation.  This  fixes common punctuation spacing issues and cleans up double
-    //    punctuation.  //    It also ensures that punctuation is properly spaced from words.
-    text = fix_punctuation(&text);
-    
-    text
-}
-
-use regex::Regex;
-fn clean_whitespace(text: &str) -> String {
-    let re_multiple_spaces = Regex::new(r" {2,}").unwrap();
-    let re_multiple_newlines = Regex::new(r"\n{3,}").unwrap();
-    let re_trailing_spaces = Regex::new(r" +$").unwrap();
-    
-    let mut result = re_multiple_spaces.replace_all(text, " ").to_string();
-    result = re_multiple_newlines.replace_all(&result, "\n\n").to_string();
-    result = re_trailing_spaces.replace_all(&result, "").to_string();
-    
-    result.trim().to_string()
-}
-
-fn is_url(text: &str) -> bool {
-    text.starts_with("http://") || 
-    text.starts_with("https://") || 
-    text.starts_with("ftp://") ||
-    text.starts_with("www.")
-}
-
-
-fn fix_markdown_headers(text: &str) -> String {
-    let re_headers = Regex::new(r"^(#{1,6}) *(.+)$").unwrap();
-    
-    text.lines()
-        .map(|line| {
-            re_headers.replace(line, |caps: &regex::Captures| {
-                format!("{} {}", &caps[1], &caps[2].trim())
-            }).to_string()
-        })
-        .collect::<Vec<_>>()
-        .join("\n")
-}
-
-fn format_code_blocks(text: &str) -> String {
-    let re_code_blocks = Regex::new(r"```([a-zA-Z]*)\n([\s\S]*?)\n```").unwrap();
-    
-    re_code_blocks.replace_all(text, |caps: &regex::Captures| {
-        let lang = &caps[1];
-        let code = caps[2].trim();
-        format!("```{}\n{}\n```", lang, code)
-    }).to_string()
-}
-
-fn fix_markdown_formatting(text: &str) -> String {
-    let mut result = text.to_string();
-    
-    // Fix bold formatting
-    let re_bold = Regex::new(r"\*\* *([^*]+?) *\*\*").unwrap();
-    result = re_bold.replace_all(&result, "**$1**").to_string();
-    
-    // Fix italic formatting  
-    let re_italic = Regex::new(r"\* *([^*]+?) *\*").unwrap();
-    result = re_italic.replace_all(&result, "*$1*").to_string();
-    
-    result
-}
-
-// Toggle bold formatting on selected text
+// Toggle bold formatting on selected text. Bold spans inside or partly
+// inside the selection are merged rather than nested, and a selection that
+// is already bold (including `***both***`) loses only the bold markers.
 #[wasm_bindgen]
 pub fn toggle_bold(text: &str) -> String {
-    let trimmed = text.trim();
-    
-    // Check if text is already bold (wrapped in **)
-    if trimmed.starts_with("**") && trimmed.ends_with("**") && trimmed.len() > 4 {
-        // Remove bold formatting
-        trimmed[2..trimmed.len()-2].to_string()
-    } else {
-        // Add bold formatting
-        format!("**{}**", trimmed)
-    }
+    emphasis::toggle_style(text, 0, text.len(), emphasis::InlineStyle::Bold).content
 }
 
-// Toggle italic formatting on selected text
+// Toggle italic formatting on selected text; see `toggle_bold`
 #[wasm_bindgen]
 pub fn toggle_italic(text: &str) -> String {
-    let trimmed = text.trim();
-    
-    // Check if text is already italic (wrapped in single *)
-    // Make sure it's not bold (**) by checking it doesn't start with **
-    if trimmed.starts_with("*") && trimmed.ends_with("*") && trimmed.len() > 2 
-        && !trimmed.starts_with("**") {
-        // Remove italic formatting
-        trimmed[1..trimmed.len()-1].to_string()
-    } else {
-        // Add italic formatting
-        format!("*{}*", trimmed)
-    }
+    emphasis::toggle_style(text, 0, text.len(), emphasis::InlineStyle::Italic).content
 }
 
 // Toggle underline formatting on selected text
@@ -164,35 +157,15 @@ pub fn toggle_underline(text: &str) -> String {
 /// Toggle strikethrough formatting using `~~text~~`
 #[wasm_bindgen]
 pub fn toggle_strikethrough(text: &str) -> String {
-    let trimmed = text.trim();
-    if trimmed.starts_with("~~") && trimmed.ends_with("~~") && trimmed.len() > 4 {
-        trimmed[2..trimmed.len()-2].to_string()
-    } else {
-        format!("~~{}~~", trimmed)
-    }
+    emphasis::toggle_style(text, 0, text.len(), emphasis::InlineStyle::Strikethrough).content
 }
 
-
-
 /// Toggle markdown heading level (e.g. "# Heading" -> "## Heading")
 #[wasm_bindgen]
 pub fn toggle_heading(text: &str, level: u8) -> String {
     let trimmed = text.trim();
-
-    // Compile regex safely
-    let re = Regex::new(r"^(#{1,6})\s+(.*)$");
-    if re.is_err() {
-        return format!("<!-- regex compile failed -->\n{}", text);
-    }
-    let re = re.unwrap();
-
-    if let Some(caps) = re.captures(trimmed) {
-        let content = caps.get(2).map_or("", |m| m.as_str()).trim();
-        format!("{} {}", "#".repeat(level as usize), content)
-    } else {
-        // fallback: no match, just prepend heading
-        format!("{} {}", "#".repeat(level as usize), trimmed)
-    }
+    let content = headings::parse_atx(trimmed).map_or(trimmed, |(_, content)| content);
+    format!("{} {}", "#".repeat(level.clamp(1, 6) as usize), content)
 }
 
 /// Check if a line starts with a bullet marker (-, *, or +)
@@ -204,15 +177,10 @@ fn is_bullet_line(line: &str) -> bool {
 /// Remove bullet marker from a line
 fn remove_bullet(line: &str) -> String {
     let trimmed = line.trim_start();
-    if trimmed.starts_with("- ") {
-        trimmed[2..].to_string()
-    } else if trimmed.starts_with("* ") {
-        trimmed[2..].to_string()
-    } else if trimmed.starts_with("+ ") {
-        trimmed[2..].to_string()
-    } else {
-        line.to_string()
-    }
+    ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| trimmed.strip_prefix(marker))
+        .map_or_else(|| line.to_string(), |rest| rest.to_string())
 }
 
 /// Toggle markdown bullet list (add/remove `- `, `* `, or `+ ` at the start of each line)
@@ -288,15 +256,6 @@ pub fn toggle_numbered_list(text: &str) -> String {
     }
 }
 
-
-
-
-
-
-
-
-
-
 #[wasm_bindgen]
 pub fn calculate_document_stats(text: &str) -> String {
     let words = count_words(text);
@@ -312,11 +271,76 @@ pub fn calculate_document_stats(text: &str) -> String {
     )
 }
 
-fn count_words(text: &str) -> usize {
+/// Document statistics with display strings for `locale` (a language tag
+/// such as "de" or "pt-BR"). Returns the fields of
+/// `calculate_document_stats` (with characters counted as characters, not
+/// bytes) plus `locale`, `reading_rate` (words, or characters for Chinese
+/// and Japanese, per minute) and `display`, the same values formatted for
+/// the UI: `{words, chars_with_spaces, chars_without_spaces, lines,
+/// reading_time}`.
+#[wasm_bindgen]
+pub fn calculate_document_stats_localized(text: &str, locale: &str) -> String {
+    let info = LocaleInfo::get(locale);
+    let words = if info.counts_characters { count_cjk_words(text) } else { count_words(text) };
+    let chars_with_spaces = text.chars().count();
+    let chars_without_spaces = text.chars().filter(|c| !c.is_whitespace()).count();
+    let lines = count_lines(text);
+    let reading_time = reading_minutes(words, info.reading_rate);
+
+    serde_json::json!({
+        "locale": info.tag,
+        "words": words,
+        "chars_with_spaces": chars_with_spaces,
+        "chars_without_spaces": chars_without_spaces,
+        "lines": lines,
+        "reading_time": reading_time,
+        "reading_rate": info.reading_rate,
+        "display": {
+            "words": info.words(words),
+            "chars_with_spaces": info.characters(chars_with_spaces),
+            "chars_without_spaces": info.characters(chars_without_spaces),
+            "lines": info.lines(lines),
+            "reading_time": info.reading_time(reading_time),
+        },
+    })
+    .to_string()
+}
+
+pub(crate) fn count_words(text: &str) -> usize {
     text.split_whitespace().count()
 }
 
-fn count_lines(text: &str) -> usize {
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // Hiragana, Katakana
+        | 0x3400..=0x4DBF   // CJK Extension A
+        | 0x4E00..=0x9FFF   // CJK Unified Ideographs
+        | 0xF900..=0xFAFF   // CJK Compatibility Ideographs
+        | 0xAC00..=0xD7AF)  // Hangul syllables
+}
+
+/// Words for languages written without spaces: every CJK character counts
+/// as one, and each run of other letters or digits counts as one more
+fn count_cjk_words(text: &str) -> usize {
+    let mut count = 0;
+    let mut in_run = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            count += 1;
+            in_run = false;
+        } else if c.is_alphanumeric() {
+            if !in_run {
+                count += 1;
+            }
+            in_run = true;
+        } else {
+            in_run = false;
+        }
+    }
+    count
+}
+
+pub(crate) fn count_lines(text: &str) -> usize {
     if text.is_empty() {
         0
     } else {
@@ -326,32 +350,18 @@ fn count_lines(text: &str) -> usize {
 
 fn estimate_reading_time(words: usize) -> usize {
     // Average reading speed: 200 words per minute
-    let minutes = (words as f64 / 200.0).ceil() as usize;
-    if minutes == 0 { 1 } else { minutes }
+    reading_minutes(words, 200)
 }
 
-
-
-fn fix_punctuation(text: &str) -> String {
-    let mut result = text.to_string();
-    
-    // Fix common punctuation spacing issues
-    result = result.replace(" ,", ",");
-    result = result.replace(" .", ".");
-    result = result.replace("( ", "(").replace(" )", ")");
-    result = result.replace(" :", ":");
-    result = result.replace(" ;", ";");
-    result = result.replace(" !", "!");
-    result = result.replace(" ?", "?");
-    
-    // Fix multiple punctuation
-    result = result.replace("..", ".").replace(",,", ",");
-    
-    result
+fn reading_minutes(words: usize, per_minute: u32) -> usize {
+    let minutes = (words as f64 / per_minute as f64).ceil() as usize;
+    if minutes == 0 { 1 } else { minutes }
 }
 
+/// Convert a bare URL into a markdown link.
+/// `link_text` overrides the visible text; by default the URL itself is shown.
 #[wasm_bindgen]
-pub fn convert_url_to_markdown(text: &str) -> String {
+pub fn convert_url_to_markdown(text: &str, link_text: Option<String>) -> String {
     let trimmed = text.trim(); // This removes leading/trailing whitespace
     
     // Check if it's already a markdown link
@@ -359,26 +369,20 @@ pub fn convert_url_to_markdown(text: &str) -> String {
         return text.to_string(); // Return original text to preserve spacing
     }
     
-    // Check if it looks like a URL
-    if is_url(trimmed) {
-        // Replace just the URL part, preserve any surrounding whitespace
-        let before_trim = &text[..text.len() - text.trim_start().len()];
-        let after_trim = &text[trimmed.len() + before_trim.len()..];
-        format!("{}[{}]({}){}", before_trim, trimmed, trimmed, after_trim)
-    } else {
-        text.to_string()
-    }
-}
-
-
-
-
-// PromiseGrid integration placeholder
-// ADD THESE IMPORTS to the top of your existing lib.rs
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+    // Check if it looks like a URL, allowing trailing punctuation like "."
+    // that isn't part of the link
+    let url_end = match url::url_len(trimmed) {
+        Some(len) if trimmed[len..].chars().all(|c| c.is_ascii_punctuation()) => len,
+        _ => return text.to_string(),
+    };
+    let link_url = &trimmed[..url_end];
 
-// ADD THESE STRUCTS after your existing imports but before your functions
+    // Replace just the URL part, preserve any surrounding whitespace
+    let before_trim = &text[..text.len() - text.trim_start().len()];
+    let after_trim = &text[before_trim.len() + url_end..];
+    let label = link_text.as_deref().map(str::trim).filter(|t| !t.is_empty()).unwrap_or(link_url);
+    format!("{}{}{}", before_trim, links::build_link(label, &url::href_for(link_url), None), after_trim)
+}
 
 /// PromiseGrid message structure following the spec
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -390,22 +394,36 @@ pub struct PromiseGridMessage {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MessagePayload {
     pub message_type: String,
-    pub data: HashMap<String, serde_cbor::Value>,
+    /// The payload as it is on the wire: envelope fields and the message
+    /// kind's own fields in one map. Not typed here, as the envelope is
+    /// stamped, relayed and signed the same way for every kind and kinds
+    /// without a struct must still pass through; `payload::decode_payload`
+    /// gives the typed `Envelope` and `Payload` and `payload::payload_data`
+    /// builds the map from a struct.
+    #[serde(serialize_with = "codec::sorted_data")]
+    pub data: HashMap<String, ciborium::Value>,
 }
 
 /// Document edit message for collab-editor integration
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct DocumentEdit {
     pub document_id: String,
     pub edit_type: String,  // "insert", "delete", "replace", "format"
     pub position: u32,
     pub content: String,
+    /// Sender's wall clock; clocks aren't synced, so order by the
+    /// envelope's Lamport time
     pub timestamp: f64,
     pub user_id: String,
+    /// Bytes replaced, when not implied by the edit type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u32>,
+    /// SHA-256 of the previous edit, for hash-chained edits
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "payload::byte_string::option")]
+    pub prev_hash: Option<Vec<u8>>,
 }
 
-// ADD THESE FUNCTIONS to your existing lib.rs (alongside your other #[wasm_bindgen] functions)
-
 /// Create a PromiseGrid CBOR message for a document edit
 #[wasm_bindgen]
 pub fn create_promisegrid_edit_message(
@@ -415,30 +433,28 @@ pub fn create_promisegrid_edit_message(
     content: &str,
     user_id: &str
 ) -> Vec<u8> {
-    let timestamp = js_sys::Date::now();
-    
-    // Create edit data map
-    let mut data = HashMap::new();
-    data.insert("document_id".to_string(), serde_cbor::Value::Text(document_id.to_string()));
-    data.insert("edit_type".to_string(), serde_cbor::Value::Text(edit_type.to_string()));
-    data.insert("position".to_string(), serde_cbor::Value::Integer(position as i128));
-    data.insert("content".to_string(), serde_cbor::Value::Text(content.to_string()));
-    data.insert("timestamp".to_string(), serde_cbor::Value::Float(timestamp));
-    data.insert("user_id".to_string(), serde_cbor::Value::Text(user_id.to_string()));
-
-    let payload = MessagePayload {
-        message_type: "document_edit".to_string(),
-        data,
-    };
+    encode_promisegrid_payload("document_edit", edit_data(document_id, edit_type, position, content, user_id))
+}
 
-    let message = PromiseGridMessage {
-        // Placeholder protocol hash - in real implementation this would be actual CID
-        protocol_hash: "QmPromiseGridProtocolV1".to_string(),
-        payload,
+/// Data map of a document_edit message, stamped with the current time
+pub(crate) fn edit_data(
+    document_id: &str,
+    edit_type: &str,
+    position: u32,
+    content: &str,
+    user_id: &str
+) -> HashMap<String, ciborium::Value> {
+    let edit = DocumentEdit {
+        document_id: document_id.to_string(),
+        edit_type: edit_type.to_string(),
+        position,
+        content: content.to_string(),
+        timestamp: js_sys::Date::now(),
+        user_id: user_id.to_string(),
+        length: None,
+        prev_hash: None,
     };
-
-    // Create CBOR with PromiseGrid tag (0x67726964 = 'grid')
-    encode_with_grid_tag(&message).unwrap_or_else(|_| Vec::new())
+    payload::payload_data(&edit)
 }
 
 /// Create a PromiseGrid message for document statistics
@@ -450,35 +466,144 @@ pub fn create_promisegrid_stats_message(
     line_count: u32,
     user_id: &str
 ) -> Vec<u8> {
-    let timestamp = js_sys::Date::now();
-    
-    let mut data = HashMap::new();
-    data.insert("document_id".to_string(), serde_cbor::Value::Text(document_id.to_string()));
-    data.insert("word_count".to_string(), serde_cbor::Value::Integer(word_count as i128));
-    data.insert("char_count".to_string(), serde_cbor::Value::Integer(char_count as i128));
-    data.insert("line_count".to_string(), serde_cbor::Value::Integer(line_count as i128));
-    data.insert("timestamp".to_string(), serde_cbor::Value::Float(timestamp));
-    data.insert("user_id".to_string(), serde_cbor::Value::Text(user_id.to_string()));
-
-    let payload = MessagePayload {
-        message_type: "document_stats".to_string(),
-        data,
+    let stats = payload::DocumentStats {
+        document_id: document_id.to_string(),
+        word_count,
+        char_count,
+        line_count,
+        timestamp: js_sys::Date::now(),
+        user_id: user_id.to_string(),
     };
-
+    encode_promisegrid_payload("document_stats", payload::payload_data(&stats))
+}
+
+/// Placeholder protocol hash - in real implementation this would be actual CID
+pub(crate) const PROTOCOL_HASH: &str = "QmPromiseGridProtocolV1";
+
+pub(crate) use codec::{
+    decode_with_grid_tag, encode_with_grid_tag, from_cbor, to_canonical_cbor, to_cbor, GRID_TAG,
+};
+
+/// Payload types this build creates and understands
+pub(crate) const MESSAGE_TYPES: &[&str] = &[
+    "document_edit",
+    "document_checkpoint",
+    "edit_batch",
+    "document_stats",
+    "cursor_update",
+    "presence_join",
+    "presence_leave",
+    "presence_idle",
+    "presence_active",
+    "ack",
+    "resend_request",
+    "document_activity",
+    "document_freeze",
+    "document_place",
+    "expired",
+    "folder_create",
+    "folder_rename",
+    "folder_move",
+    "folder_delete",
+    "encrypted",
+    "key_share",
+    "batch",
+    "document_snapshot",
+    "snapshot_request",
+    "sync_request",
+    "sync_response",
+    "acl_grant",
+    "acl_role_change",
+    "acl_revoke",
+    "hello",
+    "hello_ack",
+    "comment_add",
+    "comment_reply",
+    "comment_resolve",
+    "comment_delete",
+    "mention",
+    "word_goal",
+];
+
+/// Wrap a data map in a PromiseGrid message of the given type and encode it
+/// with the 'grid' tag (0x67726964). Shared by all message builders.
+pub(crate) fn encode_promisegrid_payload(message_type: &str, mut data: HashMap<String, ciborium::Value>) -> Vec<u8> {
+    sequence::stamp(&mut data);
+    encode_local_payload(message_type, data)
+}
+
+/// Like `encode_promisegrid_payload`, for messages that are stored or
+/// exported rather than sent: they take no sequence number, so peers
+/// don't see a gap where one was used up.
+pub(crate) fn encode_local_payload(message_type: &str, mut data: HashMap<String, ciborium::Value>) -> Vec<u8> {
+    // Every message gets a random id for acknowledgements
+    if !data.contains_key("message_id") {
+        let mut id = [0u8; 16];
+        if share::random_bytes(&mut id).is_ok() {
+            data.insert("message_id".to_string(), ciborium::Value::Text(hash_chain::to_hex(&id)));
+        }
+    }
+    replay_guard::stamp(&mut data);
     let message = PromiseGridMessage {
-        protocol_hash: "QmPromiseGridProtocolV1".to_string(),
-        payload,
+        protocol_hash: PROTOCOL_HASH.to_string(),
+        payload: MessagePayload {
+            message_type: message_type.to_string(),
+            data,
+        },
     };
 
-    encode_with_grid_tag(&message).unwrap_or_else(|_| Vec::new())
+    encode_with_grid_tag(&message).unwrap_or_else(|e| {
+        logger::log_error!("codec", "Couldn't encode {} message: {}", message_type, e);
+        Vec::new()
+    })
+}
+
+/// Read a text field from a decoded payload data map
+pub(crate) fn data_text<'a>(data: &'a HashMap<String, ciborium::Value>, key: &str) -> Option<&'a str> {
+    match data.get(key) {
+        Some(ciborium::Value::Text(text)) => Some(text),
+        _ => None,
+    }
+}
+
+/// Read a numeric field (integer or float) as f64
+pub(crate) fn data_f64(data: &HashMap<String, ciborium::Value>, key: &str) -> Option<f64> {
+    match data.get(key) {
+        Some(ciborium::Value::Float(value)) => Some(*value),
+        Some(ciborium::Value::Integer(value)) => Some(i128::from(*value) as f64),
+        _ => None,
+    }
+}
+
+/// Read a non-negative integer field
+pub(crate) fn data_u64(data: &HashMap<String, ciborium::Value>, key: &str) -> Option<u64> {
+    match data.get(key) {
+        Some(ciborium::Value::Integer(value)) => u64::try_from(*value).ok(),
+        _ => None,
+    }
+}
+
+/// Read a boolean field
+pub(crate) fn data_bool(data: &HashMap<String, ciborium::Value>, key: &str) -> Option<bool> {
+    match data.get(key) {
+        Some(ciborium::Value::Bool(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Read a byte string field
+pub(crate) fn data_bytes<'a>(data: &'a HashMap<String, ciborium::Value>, key: &str) -> Option<&'a [u8]> {
+    match data.get(key) {
+        Some(ciborium::Value::Bytes(bytes)) => Some(bytes),
+        _ => None,
+    }
 }
 
 /// Parse a PromiseGrid CBOR message and return JSON string
 #[wasm_bindgen]
 pub fn parse_promisegrid_message(cbor_bytes: &[u8]) -> String {
-    // Add detailed error reporting
-    web_sys::console::log_1(&format!("Parsing {} bytes", cbor_bytes.len()).into());
-    
+    logger::log_debug!("codec", "Parsing {} bytes", cbor_bytes.len());
+
     match decode_with_grid_tag(cbor_bytes) {
         Ok(message) => {
             match serde_json::to_string_pretty(&message) {
@@ -490,14 +615,10 @@ pub fn parse_promisegrid_message(cbor_bytes: &[u8]) -> String {
     }
 }
 
-
-
-
-/// Log PromiseGrid message to browser console (for debugging)
+/// Log PromiseGrid message at the "info" level (for debugging)
 #[wasm_bindgen]
 pub fn log_promisegrid_message(cbor_bytes: &[u8]) {
-    let json = parse_promisegrid_message(cbor_bytes);
-    web_sys::console::log_1(&format!("PromiseGrid Message: {}", json).into());
+    logger::log_info!("promisegrid", "PromiseGrid Message: {}", parse_promisegrid_message(cbor_bytes));
 }
 
 /// Export current document content as PromiseGrid CBOR message
@@ -507,13 +628,7 @@ pub fn export_document_as_promisegrid(
     document_id: &str,
     user_id: &str
 ) -> Vec<u8> {
-    create_promisegrid_edit_message(
-        document_id,
-        "export",
-        0,
-        document_content,
-        user_id
-    )
+    encode_local_payload("document_edit", edit_data(document_id, "export", 0, document_content, user_id))
 }
 
 #[wasm_bindgen]
@@ -539,70 +654,3 @@ pub fn search_document(content: &str, query: &str, case_sensitive: bool) -> Stri
     
     format!("[{}]", matches.join(","))
 }
-
-
-
-// ADD THESE HELPER FUNCTIONS (internal, not exported to WASM)
-/// Encode PromiseGrid message with the official 'grid' CBOR tag
-/// (0x67726964)
-fn encode_with_grid_tag(message: &PromiseGridMessage) -> Result<Vec<u8>, serde_cbor::Error> {
-    // First encode the message normally
-    let untagged_bytes = serde_cbor::to_vec(message)?;
-    
-    // Manually add CBOR tag bytes at the beginning
-    // CBOR tag format: major type 6 (0xC0 + tag encoding)
-    let grid_tag = 0x67726964u64; // 'grid' in hex
-    
-    let mut tagged_bytes = Vec::new();
-    
-    // Add CBOR tag header for large positive integer
-    // Tag 0x67726964 requires 5 bytes: 0xDA + 4 bytes for the tag value
-    tagged_bytes.push(0xDA); // Major type 6, additional info 26 (4-byte tag)
-    tagged_bytes.extend_from_slice(&grid_tag.to_be_bytes()[4..8]); // Last 4 bytes of tag
-    
-    // Add the original message bytes
-    tagged_bytes.extend_from_slice(&untagged_bytes);
-    
-    web_sys::console::log_1(&format!("Added CBOR tag manually").into());
-    
-    Ok(tagged_bytes)
-}
-/// Decode PromiseGrid message with tag validation
-fn decode_with_grid_tag(cbor_bytes: &[u8]) -> Result<PromiseGridMessage, Box<dyn std::error::Error>> {
-    // Check if it starts with our manual tag
-    if cbor_bytes.len() >= 5 && cbor_bytes[0] == 0xDA {
-        let tag_bytes = &cbor_bytes[1..5];
-        if tag_bytes == [103, 114, 105, 100] { // "grid"
-            web_sys::console::log_1(&"Found manual PromiseGrid tag!".into());
-            // Parse the rest as the message
-            let message: PromiseGridMessage = serde_cbor::from_slice(&cbor_bytes[5..])?;
-            return Ok(message);
-        }
-    }
-    
-    // Fallback to old parsing
-    let tagged_value: serde_cbor::Value = serde_cbor::from_slice(cbor_bytes)?;
-    
-    match tagged_value {
-        serde_cbor::Value::Tag(tag, boxed_value) => {
-            web_sys::console::log_1(&format!("Found tag: {}", tag).into());
-            if tag == 0x67726964 {
-                let message_cbor = serde_cbor::to_vec(&*boxed_value)?;
-                let message: PromiseGridMessage = serde_cbor::from_slice(&message_cbor)?;
-                Ok(message)
-            } else {
-                Err(format!("Invalid tag: expected 0x67726964, got 0x{:x}", tag).into())
-            }
-        }
-        serde_cbor::Value::Map(_) => {
-            web_sys::console::log_1(&"Parsing untagged message".into());
-            let message: PromiseGridMessage = serde_cbor::from_slice(cbor_bytes)?;
-            Ok(message)
-        }
-        _ => {
-            Err("Message is not tagged with PromiseGrid tag".into())
-        }
-    }
-}
-
-
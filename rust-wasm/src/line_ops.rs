@@ -0,0 +1,136 @@
+// Sorting, deduplicating, reversing and shuffling the lines of a
+// selection. List items move with their continuation lines and nested
+// items, are compared by their text rather than their marker, and a
+// numbered list keeps counting 1, 2, 3 after its items are reordered.
+
+use wasm_bindgen::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use crate::reflow::list_marker;
+use crate::share::random_bytes;
+
+const LINE_OPS: &[&str] = &["sort", "sort_insensitive", "sort_numeric", "dedupe", "reverse", "shuffle"];
+
+/// A line, or a list item with the indented lines that belong to it
+struct Unit<'a> {
+    /// List marker of the first line, with its indentation
+    marker: &'a str,
+    /// The first line after its marker, then any following lines as written
+    text: String,
+}
+
+impl Unit<'_> {
+    /// Text the unit is compared by: the first line without its marker
+    fn key(&self) -> &str {
+        self.text.split('\n').next().unwrap_or("").trim()
+    }
+
+    fn render(&self) -> String {
+        format!("{}{}", self.marker, self.text)
+    }
+}
+
+fn is_ordered(marker: &str) -> bool {
+    marker.trim_start().starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Split lines into units. When the first line is a list item, lines
+/// indented deeper than it (continuations and nested items) are attached
+/// to the item above them.
+fn units<'a>(lines: &[&'a str]) -> Vec<Unit<'a>> {
+    let indent_of = |line: &str| line.len() - line.trim_start_matches(' ').len();
+    let base_indent = lines.first().map_or(0, |l| indent_of(l));
+    let is_list = lines.first().is_some_and(|l| list_marker(l).is_some());
+    let mut units: Vec<Unit> = Vec::new();
+    for &line in lines {
+        if let Some(last) = units.last_mut().filter(|_| is_list && !line.trim().is_empty() && indent_of(line) > base_indent) {
+            last.text.push('\n');
+            last.text.push_str(line);
+            continue;
+        }
+        let marker_len = list_marker(line).unwrap_or(0);
+        units.push(Unit { marker: &line[..marker_len], text: line[marker_len..].to_string() });
+    }
+    units
+}
+
+/// Leading number of a line for numeric sorting: "10 apples" -> 10,
+/// "-2.5" -> -2.5
+fn leading_number(text: &str) -> Option<f64> {
+    let text = text.trim_start();
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && (c == '-' || c == '+'))))
+        .map_or(text.len(), |(i, _)| i);
+    text[..end].parse().ok()
+}
+
+fn compare_numeric(a: &str, b: &str) -> Ordering {
+    match (leading_number(a), leading_number(b)) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal).then_with(|| a.cmp(b)),
+        // Lines without a number go after the numbered ones
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+/// Fisher-Yates shuffle with the platform CSPRNG
+fn shuffle<T>(items: &mut [T]) -> Result<(), JsValue> {
+    let mut random = vec![0u8; items.len() * 4];
+    random_bytes(&mut random)?;
+    for i in (1..items.len()).rev() {
+        let bytes = [random[i * 4], random[i * 4 + 1], random[i * 4 + 2], random[i * 4 + 3]];
+        let j = ((u32::from_le_bytes(bytes) as u64 * (i as u64 + 1)) >> 32) as usize;
+        items.swap(i, j);
+    }
+    Ok(())
+}
+
+/// Apply a line operation to `content` (usually the selected lines):
+///
+/// - "sort": by text, case-sensitive
+/// - "sort_insensitive": by text, ignoring case
+/// - "sort_numeric": by each line's leading number
+/// - "dedupe": drop repeated lines, keeping the first
+/// - "reverse": reverse the order
+/// - "shuffle": random order
+///
+/// List items are compared by their text, not their `- ` or `1.` marker,
+/// and move together with their continuation lines and nested items. A
+/// numbered list keeps its numbering in order after reordering. A trailing
+/// newline is kept.
+#[wasm_bindgen]
+pub fn line_ops(content: &str, op: &str) -> Result<String, JsValue> {
+    let body = content.strip_suffix('\n').unwrap_or(content);
+    let lines: Vec<&str> = body.split('\n').collect();
+    let mut units = units(&lines);
+    let markers: Vec<&str> = units.iter().map(|u| u.marker).collect();
+
+    match op.trim().to_ascii_lowercase().as_str() {
+        "sort" => units.sort_by(|a, b| a.key().cmp(b.key())),
+        "sort_insensitive" => units.sort_by(|a, b| a.key().to_lowercase().cmp(&b.key().to_lowercase()).then_with(|| a.key().cmp(b.key()))),
+        "sort_numeric" => units.sort_by(|a, b| compare_numeric(a.key(), b.key())),
+        "dedupe" => {
+            let mut seen = HashSet::new();
+            units.retain(|u| seen.insert(u.text.clone()));
+        }
+        "reverse" => units.reverse(),
+        "shuffle" => shuffle(&mut units)?,
+        _ => return Err(JsValue::from_str(&format!("Unknown line operation: {} (expected {})", op, LINE_OPS.join(", ")))),
+    }
+
+    // Numbered items take the numbers in their original order
+    if !markers.is_empty() && markers.iter().all(|m| is_ordered(m)) {
+        for (unit, marker) in units.iter_mut().zip(markers) {
+            unit.marker = marker;
+        }
+    }
+
+    let mut out = units.iter().map(Unit::render).collect::<Vec<_>>().join("\n");
+    if body.len() < content.len() {
+        out.push('\n');
+    }
+    Ok(out)
+}
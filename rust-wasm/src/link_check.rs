@@ -0,0 +1,204 @@
+use wasm_bindgen::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use crate::format::cached;
+use crate::headings::{github_slug, slugged_headings};
+use crate::links::{find_closing, normalize_label, parse_inline_link, reference_map};
+use crate::markdown::{code_ranges, in_ranges, line_of};
+use crate::url::percent_decode;
+
+/// A problem found by `check_links`, ready for the problems panel
+#[derive(Serialize, Debug, Clone)]
+struct LinkDiagnostic {
+    /// "broken_anchor", "duplicate_anchor", "undefined_reference",
+    /// "unclosed_link", "empty_destination" or "space_in_destination"
+    kind: &'static str,
+    /// "error" or "warning"
+    severity: &'static str,
+    message: String,
+    start: usize,
+    end: usize,
+    /// 1-based line of `start`
+    line: usize,
+}
+
+/// Explicit HTML anchors (`<a id="x">`, `<a name="x">`) with their ranges
+fn html_anchors(content: &str, skip: &[(usize, usize)]) -> Vec<(String, usize, usize)> {
+    static ANCHOR: OnceLock<Regex> = OnceLock::new();
+    let re = cached(&ANCHOR, r#"<[aA][ \t][^>]*?(?:[iI][dD]|[nN][aA][mM][eE])[ \t]*=[ \t]*"([^"]+)"[^>]*>"#);
+    re.captures_iter(content)
+        .filter_map(|caps| {
+            let m = caps.get(0)?;
+            (!in_ranges(skip, m.start())).then(|| (caps[1].to_string(), m.start(), m.end()))
+        })
+        .collect()
+}
+
+struct Checker<'a> {
+    content: &'a str,
+    diagnostics: Vec<LinkDiagnostic>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, kind: &'static str, severity: &'static str, message: String, start: usize, end: usize) {
+        self.diagnostics.push(LinkDiagnostic {
+            kind,
+            severity,
+            message,
+            start,
+            end,
+            line: line_of(self.content, start),
+        });
+    }
+}
+
+/// Validate the links of a document: `#anchor` targets must match a heading
+/// slug or an explicit `<a id>` anchor, headings must not produce duplicate
+/// anchors, reference links must be defined, and link syntax must be well
+/// formed. Returns JSON
+/// `[{kind, severity, message, start, end, line}]` sorted by position.
+#[wasm_bindgen]
+pub fn check_links(content: &str) -> String {
+    let skip = code_ranges(content);
+    let mut checker = Checker { content, diagnostics: Vec::new() };
+
+    // Anchors defined by the document, and duplicates among them
+    let mut anchors: HashSet<String> = HashSet::new();
+    let mut first_by_base: HashMap<String, usize> = HashMap::new();
+    for (heading, slug) in slugged_headings(content) {
+        let base = github_slug(&heading.text);
+        match first_by_base.get(&base) {
+            Some(&first_line) if !base.is_empty() => checker.report(
+                "duplicate_anchor",
+                "warning",
+                format!(
+                    "Heading anchor #{} is already used on line {}; links to it reach the first heading, this one is #{}",
+                    base, first_line, slug
+                ),
+                heading.start,
+                heading.end,
+            ),
+            _ => {
+                first_by_base.insert(base, heading.line);
+            }
+        }
+        anchors.insert(slug);
+    }
+    for (id, start, end) in html_anchors(content, &skip) {
+        if !anchors.insert(id.clone()) {
+            checker.report(
+                "duplicate_anchor",
+                "warning",
+                format!("Anchor #{} is defined more than once", id),
+                start,
+                end,
+            );
+        }
+    }
+
+    let references = reference_map(content);
+    let check_anchor = |checker: &mut Checker, url: &str, start: usize, end: usize| {
+        let Some(fragment) = url.strip_prefix('#') else { return };
+        let anchor = percent_decode(fragment);
+        if anchor.is_empty() || anchors.contains(&anchor) {
+            return;
+        }
+        let message = match anchors.iter().find(|a| a.eq_ignore_ascii_case(&anchor)) {
+            Some(similar) => format!("No heading matches #{}; anchors are case-sensitive, did you mean #{}?", anchor, similar),
+            None => format!("No heading or anchor matches #{}", anchor),
+        };
+        checker.report("broken_anchor", "error", message, start, end);
+    };
+
+    // Reference definitions can point at anchors too
+    for def in references.values() {
+        check_anchor(&mut checker, &def.url, def.start, def.end);
+    }
+
+    let mut offset = 0;
+    for raw_line in content.split_inclusive('\n') {
+        let line_start = offset;
+        offset += raw_line.len();
+        let line = raw_line.trim_end_matches(['\n', '\r']);
+        let bytes = line.as_bytes();
+
+        let mut i = 0;
+        while i < bytes.len() {
+            let pos = line_start + i;
+            if bytes[i] == b'\\' {
+                i += 2;
+                continue;
+            }
+            if bytes[i] != b'[' || in_ranges(&skip, pos) || line[i..].starts_with("[^") {
+                i += 1;
+                continue;
+            }
+            let Some(close) = find_closing(line, i, b'[', b']') else {
+                i += 1;
+                continue;
+            };
+            let after = &line[close + 1..];
+
+            if after.starts_with('(') {
+                match parse_inline_link(line, i) {
+                    Some(link) => {
+                        let (start, end) = (line_start + i, line_start + link.end);
+                        if link.url.is_empty() {
+                            checker.report("empty_destination", "warning", "Link has an empty destination".to_string(), start, end);
+                        } else if link.url.contains([' ', '\t']) {
+                            checker.report(
+                                "space_in_destination",
+                                "warning",
+                                "Link destination contains spaces; encode them as %20 or wrap the URL in <...>".to_string(),
+                                start,
+                                end,
+                            );
+                        }
+                        check_anchor(&mut checker, &link.url, start, end);
+                        i = link.end;
+                    }
+                    None => {
+                        checker.report(
+                            "unclosed_link",
+                            "error",
+                            "Link destination is missing its closing parenthesis".to_string(),
+                            line_start + i,
+                            line_start + line.len(),
+                        );
+                        i = line.len();
+                    }
+                }
+                continue;
+            }
+
+            if after.starts_with('[') {
+                if let Some(label_close) = find_closing(after, 0, b'[', b']') {
+                    // `[text][]` uses the text itself as the label
+                    let label = match &after[1..label_close] {
+                        "" => &line[i + 1..close],
+                        label => label,
+                    };
+                    let end = close + 1 + label_close + 1;
+                    if !references.contains_key(&normalize_label(label)) {
+                        checker.report(
+                            "undefined_reference",
+                            "error",
+                            format!("Link reference [{}] is not defined", label),
+                            line_start + i,
+                            line_start + end,
+                        );
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+            i = close + 1;
+        }
+    }
+
+    checker.diagnostics.sort_by_key(|d| (d.start, d.end));
+    serde_json::to_string(&checker.diagnostics).unwrap_or_else(|_| "[]".to_string())
+}
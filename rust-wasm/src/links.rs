@@ -0,0 +1,580 @@
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::images::find_images;
+use crate::markdown::{apply_edits, code_ranges, in_ranges, line_of};
+use crate::url::{find_urls, href_for, url_len};
+
+/// A parsed inline markdown link: `[text](url "title")`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct InlineLink {
+    pub text: String,
+    pub url: String,
+    pub title: Option<String>,
+    /// Byte offset of the `(` opening the destination
+    pub dest_start: usize,
+    /// Byte offset just past the closing `)`
+    pub end: usize,
+}
+
+/// Escape a URL for use as a link destination.
+/// Parentheses are backslash-escaped and spaces percent-encoded so the
+/// destination can't terminate the link early.
+pub(crate) fn escape_link_url(url: &str) -> String {
+    let mut out = String::with_capacity(url.len());
+    for c in url.trim().chars() {
+        match c {
+            '(' => out.push_str("\\("),
+            ')' => out.push_str("\\)"),
+            ' ' => out.push_str("%20"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape square brackets and backslashes in link text, so a trailing
+/// backslash can't escape the closing bracket
+pub(crate) fn escape_link_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '[' | ']' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&next) = chars.peek() {
+                if next.is_ascii_punctuation() {
+                    out.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Build a markdown link, escaping text, URL and title
+pub(crate) fn build_link(text: &str, url: &str, title: Option<&str>) -> String {
+    let text = escape_link_text(text);
+    let url = escape_link_url(url);
+    match title.map(str::trim).filter(|t| !t.is_empty()) {
+        Some(title) => format!("[{}]({} \"{}\")", text, url, title.replace('"', "\\\"")),
+        None => format!("[{}]({})", text, url),
+    }
+}
+
+/// Build a wiki link to another document: `[[Title]]`, `[[Title#heading]]`
+/// or `[[Title|shown text]]`. The alias is dropped when it repeats the title.
+pub(crate) fn build_wiki_link(title: &str, anchor: Option<&str>, alias: Option<&str>) -> String {
+    let mut link = format!("[[{}", title);
+    if let Some(anchor) = anchor.filter(|a| !a.is_empty()) {
+        link.push('#');
+        link.push_str(anchor);
+    }
+    if let Some(alias) = alias.map(str::trim).filter(|a| !a.is_empty() && *a != title) {
+        link.push('|');
+        link.push_str(alias);
+    }
+    link.push_str("]]");
+    link
+}
+
+/// Find the index of the bracket closing the one at `open`, honouring
+/// backslash escapes and nesting.
+pub(crate) fn find_closing(s: &str, open: usize, open_ch: u8, close_ch: u8) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            c if c == open_ch => depth += 1,
+            c if c == close_ch => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Parse an inline link starting at byte offset `start` (which must be `[`)
+pub(crate) fn parse_inline_link(s: &str, start: usize) -> Option<InlineLink> {
+    if s.as_bytes().get(start) != Some(&b'[') {
+        return None;
+    }
+    let close_text = find_closing(s, start, b'[', b']')?;
+    if s.as_bytes().get(close_text + 1) != Some(&b'(') {
+        return None;
+    }
+    let close_dest = find_closing(s, close_text + 1, b'(', b')')?;
+    let text = &s[start + 1..close_text];
+    let dest = s[close_text + 2..close_dest].trim();
+
+    // Split an optional quoted title off the destination
+    let (url, title) = match dest.find(" \"") {
+        Some(pos) if dest.ends_with('"') && dest.len() > pos + 2 => {
+            let title = &dest[pos + 2..dest.len() - 1];
+            (dest[..pos].trim(), Some(title.replace("\\\"", "\"")))
+        }
+        _ => (dest, None),
+    };
+    let url = url.trim_start_matches('<').trim_end_matches('>');
+
+    Some(InlineLink {
+        text: unescape(text),
+        url: unescape(url),
+        title,
+        dest_start: close_text + 1,
+        end: close_dest + 1,
+    })
+}
+
+/// A reference definition line: `[label]: url "title"`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ReferenceDef {
+    pub label: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Parse all `[label]: url "title"` reference definitions
+pub(crate) fn parse_reference_definitions(content: &str) -> Vec<ReferenceDef> {
+    let mut defs = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        let indent = trimmed.len() - trimmed.trim_start().len();
+        let body = trimmed.trim_start();
+        if indent < 4 && body.starts_with('[') && !body.starts_with("[^") {
+            if let Some(close) = find_closing(body, 0, b'[', b']') {
+                if let Some(rest) = body[close + 1..].strip_prefix(':') {
+                    let rest = rest.trim();
+                    // A `<...>` destination may contain spaces
+                    let split = match rest.strip_prefix('<') {
+                        Some(inner) => inner.find('>').map(|pos| pos + 2).filter(|&pos| pos < rest.len()),
+                        None => rest.find(char::is_whitespace),
+                    };
+                    let (url, title) = match split {
+                        Some(pos) => {
+                            let title = rest[pos..].trim().trim_matches(|c| c == '"' || c == '\'' || c == '(' || c == ')');
+                            (&rest[..pos], Some(title.to_string()).filter(|t| !t.is_empty()))
+                        }
+                        None => (rest, None),
+                    };
+                    if !url.is_empty() && close > 1 {
+                        defs.push(ReferenceDef {
+                            label: body[1..close].to_string(),
+                            url: url.trim_start_matches('<').trim_end_matches('>').to_string(),
+                            title,
+                            start: offset,
+                            end: offset + trimmed.len(),
+                        });
+                    }
+                }
+            }
+        }
+        offset += line.len();
+    }
+    defs
+}
+
+/// Reference definitions keyed by normalized (lowercase, single-spaced) label.
+/// The first definition of a label wins, as in CommonMark.
+pub(crate) fn reference_map(content: &str) -> HashMap<String, ReferenceDef> {
+    let mut map = HashMap::new();
+    for def in parse_reference_definitions(content) {
+        map.entry(normalize_label(&def.label)).or_insert(def);
+    }
+    map
+}
+
+/// Normalize a reference label for matching
+pub(crate) fn normalize_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Turn a selection into a markdown link.
+/// If the selection is empty, the URL itself is used as the link text.
+#[wasm_bindgen]
+pub fn insert_link(selection: &str, url: &str, title: Option<String>) -> String {
+    let text = selection.trim();
+    let text = if text.is_empty() { url.trim() } else { text };
+    build_link(text, url, title.as_deref())
+}
+
+/// Change the URL and/or text of an existing markdown link.
+/// Any argument left as `undefined` keeps its current value. Text that isn't
+/// a link is turned into one when a new URL is supplied.
+#[wasm_bindgen]
+pub fn edit_link(text: &str, new_url: Option<String>, new_text: Option<String>) -> String {
+    let trimmed = text.trim();
+    match parse_inline_link(trimmed, 0).filter(|link| link.end == trimmed.len()) {
+        Some(link) => build_link(
+            new_text.as_deref().unwrap_or(&link.text),
+            new_url.as_deref().unwrap_or(&link.url),
+            link.title.as_deref(),
+        ),
+        None => match new_url {
+            Some(url) => insert_link(new_text.as_deref().unwrap_or(trimmed), &url, None),
+            None => text.to_string(),
+        },
+    }
+}
+
+/// Any link found in the document
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct LinkRef {
+    pub url: String,
+    pub text: String,
+    pub title: Option<String>,
+    /// "inline", "reference", "autolink" or "image"
+    pub kind: &'static str,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+/// Whether `text` starts with `scheme://` for any scheme name (a letter,
+/// then up to 31 letters, digits, `+`, `.` or `-`)
+fn has_scheme(text: &str) -> bool {
+    text.split_once("://").is_some_and(|(scheme, rest)| {
+        !rest.is_empty()
+            && (2..=32).contains(&scheme.len())
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+    })
+}
+
+/// Target of a `<...>` autolink starting at `start`, with its end offset.
+/// `<scheme://...>` is a link whatever the host (`<http://localhost>`)
+fn angle_autolink(content: &str, start: usize) -> Option<(String, usize)> {
+    let close = content[start..].find('>')? + start;
+    let inner = &content[start + 1..close];
+    if inner.is_empty() || inner.contains(char::is_whitespace) {
+        return None;
+    }
+    if has_scheme(inner) || (url_len(inner) == Some(inner.len()) && inner.contains(':')) {
+        Some((inner.to_string(), close + 1))
+    } else if inner.contains('@') && !inner.contains(':') && !inner.starts_with('@') {
+        Some((format!("mailto:{}", inner), close + 1))
+    } else {
+        None
+    }
+}
+
+/// All links in document order: inline and reference links, `<...>` and
+/// bare-URL autolinks, and images. Links inside code are ignored, as are
+/// reference links whose label is not defined.
+pub(crate) fn find_links(content: &str) -> Vec<LinkRef> {
+    let skip = code_ranges(content);
+    let references = reference_map(content);
+    let definitions: Vec<(usize, usize)> = parse_reference_definitions(content).iter().map(|d| (d.start, d.end)).collect();
+    let mut links: Vec<LinkRef> = Vec::new();
+    // Ranges already claimed by link syntax, so bare URLs inside them are
+    // not reported again
+    let images = find_images(content);
+    let mut covered: Vec<(usize, usize)> = images.iter().map(|image| (image.start, image.end)).collect();
+
+    let bytes = content.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        let is_link_start = match bytes[i] {
+            b'[' => i == 0 || bytes[i - 1] != b'!',
+            b'<' => true,
+            _ => false,
+        };
+        if !is_link_start || in_ranges(&skip, i) || in_ranges(&covered, i) {
+            i += 1;
+            continue;
+        }
+
+        if bytes[i] == b'<' {
+            match angle_autolink(content, i) {
+                Some((url, end)) => {
+                    links.push(LinkRef {
+                        text: content[i + 1..end - 1].to_string(),
+                        url,
+                        title: None,
+                        kind: "autolink",
+                        start: i,
+                        end,
+                        line: line_of(content, i),
+                    });
+                    covered.push((i, end));
+                    i = end;
+                }
+                None => i += 1,
+            }
+            continue;
+        }
+
+        if content[i..].starts_with("[^") || in_ranges(&definitions, i) {
+            i += 1;
+            continue;
+        }
+        if let Some(link) = parse_inline_link(content, i) {
+            links.push(LinkRef {
+                url: link.url,
+                text: link.text,
+                title: link.title,
+                kind: "inline",
+                start: i,
+                end: link.end,
+                line: line_of(content, i),
+            });
+            covered.push((i, link.end));
+            i = link.end;
+            continue;
+        }
+
+        // Reference style: [text][label], [text][] or [text]
+        let Some(close) = find_closing(content, i, b'[', b']') else {
+            i += 1;
+            continue;
+        };
+        let text = &content[i + 1..close];
+        let (label, end) = match content[close + 1..].strip_prefix('[') {
+            Some(rest) => match rest.find(']') {
+                Some(0) => (text, close + 3),
+                Some(len) => (&rest[..len], close + 2 + len + 1),
+                None => (text, close + 1),
+            },
+            None => (text, close + 1),
+        };
+        match references.get(&normalize_label(label)) {
+            Some(def) => {
+                links.push(LinkRef {
+                    url: def.url.clone(),
+                    text: unescape(text),
+                    title: def.title.clone(),
+                    kind: "reference",
+                    start: i,
+                    end,
+                    line: line_of(content, i),
+                });
+                covered.push((i, end));
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+
+    for image in images {
+        links.push(LinkRef {
+            url: image.url,
+            text: image.alt,
+            title: image.title,
+            kind: "image",
+            start: image.start,
+            end: image.end,
+            line: image.line,
+        });
+    }
+
+    for (start, end) in find_urls(content) {
+        if in_ranges(&skip, start) || in_ranges(&covered, start) || in_ranges(&definitions, start) {
+            continue;
+        }
+        let url = &content[start..end];
+        links.push(LinkRef {
+            url: href_for(url),
+            text: url.to_string(),
+            title: None,
+            kind: "autolink",
+            start,
+            end,
+            line: line_of(content, start),
+        });
+    }
+
+    links.sort_by_key(|l| l.start);
+    links
+}
+
+/// List every link in the document as JSON
+/// (`[{url, text, title, kind, start, end, line}]`, kind is "inline",
+/// "reference", "autolink" or "image") for the links panel and the dead-link
+/// checker.
+#[wasm_bindgen]
+pub fn extract_links(content: &str) -> String {
+    serde_json::to_string(&find_links(content)).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Source text between the brackets of the link or image at `start`,
+/// escapes intact
+fn raw_link_text(content: &str, start: usize) -> &str {
+    let open = if content[start..].starts_with('!') { start + 1 } else { start };
+    match find_closing(content, open, b'[', b']') {
+        Some(close) => &content[open + 1..close],
+        None => "",
+    }
+}
+
+/// A markdown link or image in either style
+struct Convertible {
+    start: usize,
+    end: usize,
+    url: String,
+    title: Option<String>,
+    image: bool,
+    reference: bool,
+}
+
+/// Inline and reference links and markdown images (not autolinks or
+/// HTML images) that aren't nested in another, in document order
+fn convertible_links(content: &str) -> Vec<Convertible> {
+    let links = find_links(content).into_iter().filter(|l| l.kind == "inline" || l.kind == "reference").map(|l| Convertible {
+        start: l.start,
+        end: l.end,
+        reference: l.kind == "reference",
+        url: l.url,
+        title: l.title,
+        image: false,
+    });
+    let images = find_images(content).into_iter().filter(|i| i.kind != "html").map(|i| Convertible {
+        start: i.start,
+        end: i.end,
+        reference: i.kind == "reference",
+        url: i.url,
+        title: i.title,
+        image: true,
+    });
+    let mut all: Vec<Convertible> = links.chain(images).collect();
+    all.sort_by_key(|l| l.start);
+    // An image inside link text (`[![alt](img)](url)`) is converted with
+    // its link's text, not on its own
+    let mut end = 0;
+    all.retain(|l| {
+        let outer = l.start >= end;
+        if outer {
+            end = l.end;
+        }
+        outer
+    });
+    all
+}
+
+fn quoted_title(title: Option<&str>) -> String {
+    title.map(|t| format!(" \"{}\"", t.replace('"', "\\\""))).unwrap_or_default()
+}
+
+/// `<url>` when the destination contains spaces, the URL as is otherwise
+fn definition_url(url: &str) -> String {
+    if url.contains(char::is_whitespace) {
+        format!("<{}>", url)
+    } else {
+        url.to_string()
+    }
+}
+
+fn to_reference_links(content: &str) -> String {
+    let definitions = parse_reference_definitions(content);
+    let mut labels: HashMap<(String, Option<String>), String> = HashMap::new();
+    for def in &definitions {
+        labels.entry((def.url.clone(), def.title.clone())).or_insert_with(|| def.label.clone());
+    }
+    let mut next_number = definitions.iter().filter_map(|d| d.label.parse::<usize>().ok()).max().unwrap_or(0) + 1;
+
+    let mut edits = Vec::new();
+    let mut new_definitions = Vec::new();
+    for link in convertible_links(content).into_iter().filter(|l| !l.reference) {
+        let key = (link.url.clone(), link.title.clone());
+        let label = match labels.get(&key) {
+            Some(label) => label.clone(),
+            None => {
+                let label = next_number.to_string();
+                next_number += 1;
+                new_definitions.push(format!("[{}]: {}{}", label, definition_url(&link.url), quoted_title(link.title.as_deref())));
+                labels.insert(key, label.clone());
+                label
+            }
+        };
+        let bang = if link.image { "!" } else { "" };
+        edits.push((link.start, link.end, format!("{}[{}][{}]", bang, raw_link_text(content, link.start), label)));
+    }
+
+    let mut out = apply_edits(content, edits);
+    if !new_definitions.is_empty() {
+        let trimmed = out.trim_end_matches(['\n', '\r', ' ']).len();
+        out.truncate(trimmed);
+        // Join an existing block of definitions at the end of the document
+        let last_line = out.rsplit('\n').next().unwrap_or("");
+        let ends_with_definitions = parse_reference_definitions(last_line).len() == 1;
+        if !out.is_empty() {
+            out.push_str(if ends_with_definitions { "\n" } else { "\n\n" });
+        }
+        out.push_str(&new_definitions.join("\n"));
+        out.push('\n');
+    }
+    out
+}
+
+fn to_inline_links(content: &str) -> String {
+    let mut edits = Vec::new();
+    let mut used = HashSet::new();
+    let references = reference_map(content);
+    for link in convertible_links(content).into_iter().filter(|l| l.reference) {
+        let raw = raw_link_text(content, link.start);
+        // [text][label], [text][] or [text]
+        let after = &content[link.start + raw.len() + if link.image { 3 } else { 2 }..link.end];
+        let label = after.strip_prefix('[').and_then(|l| l.strip_suffix(']')).filter(|l| !l.is_empty()).unwrap_or(raw);
+        if let Some(def) = references.get(&normalize_label(label)) {
+            used.insert(def.start);
+        }
+        let bang = if link.image { "!" } else { "" };
+        let url = escape_link_url(&link.url);
+        edits.push((link.start, link.end, format!("{}[{}]({}{})", bang, raw, url, quoted_title(link.title.as_deref()))));
+    }
+
+    // Drop the definitions that are now unused, with their line breaks
+    for def in parse_reference_definitions(content).iter().filter(|d| used.contains(&d.start)) {
+        let end = if content[def.end..].starts_with("\r\n") { def.end + 2 } else { (def.end + 1).min(content.len()) };
+        edits.push((def.start, end, String::new()));
+    }
+    let out = apply_edits(content, edits);
+    let trimmed = out.trim_end_matches(['\n', '\r', ' ']);
+    if trimmed.len() == out.len() {
+        out
+    } else {
+        format!("{}\n", trimmed)
+    }
+}
+
+/// Convert links and images between inline (`[text](url)`) and reference
+/// style (`[text][1]` with `[1]: url` collected at the end of the
+/// document). `style` is "reference" or "inline". Converting to reference
+/// style gives identical URLs (and titles) one shared definition, reusing
+/// an existing one when there is one; converting to inline style removes
+/// the definitions it used.
+#[wasm_bindgen]
+pub fn convert_links(content: &str, style: &str) -> Result<String, JsValue> {
+    match style.trim().to_ascii_lowercase().as_str() {
+        "reference" => Ok(to_reference_links(content)),
+        "inline" => Ok(to_inline_links(content)),
+        _ => Err(JsValue::from_str(&format!("Unknown link style: {} (expected \"inline\" or \"reference\")", style))),
+    }
+}
+
@@ -0,0 +1,186 @@
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use crate::performance::{self, LiveLint};
+
+/// How long (ms) an edited block counts as "recently edited"
+const RECENT_EDIT_WINDOW_MS: f64 = 5_000.0;
+
+/// A top-level block of the document: a paragraph, list, or fenced code block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BlockRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Split text into blocks separated by blank lines.
+/// Fenced code blocks are kept whole even if they contain blank lines.
+pub(crate) fn split_blocks(text: &str) -> Vec<BlockRange> {
+    let mut blocks = Vec::new();
+    let mut current: Option<BlockRange> = None;
+    let mut in_fence = false;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        if content.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        if content.trim().is_empty() && !in_fence {
+            blocks.extend(current.take());
+        } else {
+            let end = offset + content.len();
+            current.get_or_insert(BlockRange { start: offset, end }).end = end;
+        }
+        offset += line.len();
+    }
+    blocks.extend(current);
+    blocks
+}
+
+pub(crate) fn hash_block(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Block {
+    range: BlockRange,
+    hash: u64,
+    edited_at: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct ScheduledBlock<'a> {
+    start: usize,
+    end: usize,
+    hash: String,
+    reason: &'a str,
+}
+
+/// Decides which blocks of the document should be linted next.
+///
+/// Blocks in the viewport and blocks edited in the last few seconds are
+/// returned first; everything else only comes back from idle slices.
+/// Blocks are tracked by content hash, so a block that moves without
+/// changing is not linted again.
+#[wasm_bindgen]
+pub struct LintScheduler {
+    blocks: Vec<Block>,
+    linted: HashSet<u64>,
+    viewport: (usize, usize),
+}
+
+impl Default for LintScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl LintScheduler {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> LintScheduler {
+        LintScheduler {
+            blocks: Vec::new(),
+            linted: HashSet::new(),
+            viewport: (0, 0),
+        }
+    }
+
+    /// Re-split the document after a change.
+    /// `edit_offset` (if any) marks the block containing it as recently edited.
+    pub fn update_document(&mut self, content: &str, edit_offset: Option<usize>, now: f64) {
+        let previous: Vec<(u64, Option<f64>)> =
+            self.blocks.iter().map(|b| (b.hash, b.edited_at)).collect();
+
+        self.blocks = split_blocks(content)
+            .into_iter()
+            .map(|range| {
+                let hash = hash_block(&content[range.start..range.end]);
+                // Keep the edit time of blocks that survived unchanged
+                let edited_at = previous.iter().find(|(h, _)| *h == hash).and_then(|(_, t)| *t);
+                Block { range, hash, edited_at }
+            })
+            .collect();
+
+        if let Some(offset) = edit_offset {
+            if let Some(block) = self
+                .blocks
+                .iter_mut()
+                .find(|b| offset >= b.range.start && offset <= b.range.end + 1)
+            {
+                block.edited_at = Some(now);
+            }
+        }
+
+        // Forget lint results for blocks that no longer exist
+        let live: HashSet<u64> = self.blocks.iter().map(|b| b.hash).collect();
+        self.linted.retain(|h| live.contains(h));
+    }
+
+    /// Viewport hint from the editor, as byte offsets
+    pub fn set_viewport(&mut self, start: usize, end: usize) {
+        self.viewport = (start.min(end), start.max(end));
+    }
+
+    /// Return up to `max_blocks` blocks to lint as JSON
+    /// (`[{start, end, hash, reason}]`). Urgent blocks (visible or recently
+    /// edited) are always returned first; other blocks are only returned
+    /// when `idle` is true. The "reduced" performance profile only lints
+    /// visible blocks and "minimal" turns live linting off.
+    pub fn next_batch(&self, max_blocks: usize, idle: bool, now: f64) -> String {
+        let live_lint = performance::current().live_lint();
+        let mut candidates: Vec<(u8, &Block)> = self
+            .blocks
+            .iter()
+            .filter(|b| !self.linted.contains(&b.hash))
+            .filter_map(|b| {
+                let visible = b.range.end >= self.viewport.0 && b.range.start <= self.viewport.1;
+                let recent = b.edited_at.is_some_and(|t| now - t <= RECENT_EDIT_WINDOW_MS);
+                match (visible, recent) {
+                    _ if live_lint == LiveLint::Off => None,
+                    (true, true) => Some((0, b)),
+                    (true, false) => Some((1, b)),
+                    _ if live_lint == LiveLint::Viewport => None,
+                    (false, true) => Some((2, b)),
+                    (false, false) if idle => Some((3, b)),
+                    _ => None,
+                }
+            })
+            .collect();
+        candidates.sort_by_key(|(priority, b)| (*priority, b.range.start));
+
+        let batch: Vec<ScheduledBlock> = candidates
+            .into_iter()
+            .take(max_blocks)
+            .map(|(priority, b)| ScheduledBlock {
+                start: b.range.start,
+                end: b.range.end,
+                hash: format!("{:016x}", b.hash),
+                reason: match priority {
+                    0 => "visible_edited",
+                    1 => "visible",
+                    2 => "edited",
+                    _ => "idle",
+                },
+            })
+            .collect();
+        serde_json::to_string(&batch).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Record that the block with this hash (as returned by `next_batch`) was linted
+    pub fn mark_linted(&mut self, hash: &str) {
+        if let Ok(hash) = u64::from_str_radix(hash, 16) {
+            self.linted.insert(hash);
+        }
+    }
+
+    /// Number of blocks still waiting to be linted
+    pub fn pending_count(&self) -> usize {
+        self.blocks.iter().filter(|b| !self.linted.contains(&b.hash)).count()
+    }
+}
@@ -0,0 +1,229 @@
+// Locale data for display strings: number grouping, reading speed and the
+// unit labels used by document statistics.
+
+use wasm_bindgen::prelude::*;
+
+/// Singular and plural form of a unit label
+type Unit = (&'static str, &'static str);
+
+pub(crate) struct LocaleInfo {
+    pub tag: &'static str,
+    /// Thousands separator
+    group: &'static str,
+    decimal: char,
+    /// Average silent reading speed, in words (or characters, for
+    /// `counts_characters` locales) per minute
+    pub reading_rate: u32,
+    /// Text is counted per character rather than per space-separated word
+    pub counts_characters: bool,
+    /// Whether 0 takes the singular form, as in French
+    zero_is_singular: bool,
+    /// Put a space between a number and its unit
+    unit_space: bool,
+    words: Unit,
+    characters: Unit,
+    lines: Unit,
+    /// `{}` is replaced by the number of minutes
+    reading_time: &'static str,
+}
+
+// Reading rates follow Trauzettel-Klosinski et al. (2012), "Standardized
+// assessment of reading performance" across 17 languages.
+const LOCALES: &[LocaleInfo] = &[
+    LocaleInfo {
+        tag: "en",
+        group: ",",
+        decimal: '.',
+        reading_rate: 228,
+        counts_characters: false,
+        zero_is_singular: false,
+        unit_space: true,
+        words: ("word", "words"),
+        characters: ("character", "characters"),
+        lines: ("line", "lines"),
+        reading_time: "{} min read",
+    },
+    LocaleInfo {
+        tag: "fr",
+        group: "\u{202F}",
+        decimal: ',',
+        reading_rate: 195,
+        counts_characters: false,
+        zero_is_singular: true,
+        unit_space: true,
+        words: ("mot", "mots"),
+        characters: ("caractère", "caractères"),
+        lines: ("ligne", "lignes"),
+        reading_time: "{} min de lecture",
+    },
+    LocaleInfo {
+        tag: "de",
+        group: ".",
+        decimal: ',',
+        reading_rate: 179,
+        counts_characters: false,
+        zero_is_singular: false,
+        unit_space: true,
+        words: ("Wort", "Wörter"),
+        characters: ("Zeichen", "Zeichen"),
+        lines: ("Zeile", "Zeilen"),
+        reading_time: "{} Min. Lesezeit",
+    },
+    LocaleInfo {
+        tag: "es",
+        group: ".",
+        decimal: ',',
+        reading_rate: 218,
+        counts_characters: false,
+        zero_is_singular: false,
+        unit_space: true,
+        words: ("palabra", "palabras"),
+        characters: ("carácter", "caracteres"),
+        lines: ("línea", "líneas"),
+        reading_time: "{} min de lectura",
+    },
+    LocaleInfo {
+        tag: "it",
+        group: ".",
+        decimal: ',',
+        reading_rate: 188,
+        counts_characters: false,
+        zero_is_singular: false,
+        unit_space: true,
+        words: ("parola", "parole"),
+        characters: ("carattere", "caratteri"),
+        lines: ("riga", "righe"),
+        reading_time: "{} min di lettura",
+    },
+    LocaleInfo {
+        tag: "pt",
+        group: ".",
+        decimal: ',',
+        reading_rate: 181,
+        counts_characters: false,
+        zero_is_singular: false,
+        unit_space: true,
+        words: ("palavra", "palavras"),
+        characters: ("caractere", "caracteres"),
+        lines: ("linha", "linhas"),
+        reading_time: "{} min de leitura",
+    },
+    LocaleInfo {
+        tag: "nl",
+        group: ".",
+        decimal: ',',
+        reading_rate: 202,
+        counts_characters: false,
+        zero_is_singular: false,
+        unit_space: true,
+        words: ("woord", "woorden"),
+        characters: ("teken", "tekens"),
+        lines: ("regel", "regels"),
+        reading_time: "{} min leestijd",
+    },
+    LocaleInfo {
+        tag: "ja",
+        group: ",",
+        decimal: '.',
+        reading_rate: 357,
+        counts_characters: true,
+        zero_is_singular: false,
+        unit_space: false,
+        words: ("文字", "文字"),
+        characters: ("文字", "文字"),
+        lines: ("行", "行"),
+        reading_time: "{}分で読めます",
+    },
+    LocaleInfo {
+        tag: "zh",
+        group: ",",
+        decimal: '.',
+        reading_rate: 255,
+        counts_characters: true,
+        zero_is_singular: false,
+        unit_space: false,
+        words: ("字", "字"),
+        characters: ("字符", "字符"),
+        lines: ("行", "行"),
+        reading_time: "阅读约{}分钟",
+    },
+];
+
+/// Language tags with their own display data; anything else uses "en"
+pub(crate) fn locale_tags() -> Vec<&'static str> {
+    LOCALES.iter().map(|l| l.tag).collect()
+}
+
+impl LocaleInfo {
+    /// Look up a BCP 47 tag by its language: "pt-BR" -> "pt". Unknown
+    /// languages fall back to English.
+    pub(crate) fn get(tag: &str) -> &'static LocaleInfo {
+        let language = tag.trim().split(['-', '_']).next().unwrap_or("").to_ascii_lowercase();
+        LOCALES.iter().find(|l| l.tag == language).unwrap_or(&LOCALES[0])
+    }
+
+    /// Group the digits of an integer: 1234567 -> "1,234,567" in English
+    pub(crate) fn format_integer(&self, value: i64) -> String {
+        let digits = value.unsigned_abs().to_string();
+        let mut out = String::with_capacity(digits.len() + digits.len() / 3 * self.group.len() + 1);
+        if value < 0 {
+            out.push('-');
+        }
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                out.push_str(self.group);
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    /// Format a number with `decimals` digits after the decimal mark
+    pub(crate) fn format_number(&self, value: f64, decimals: usize) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        let fixed = format!("{:.*}", decimals, value.abs());
+        let (whole, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+        let mut out = self.format_integer(whole.parse().unwrap_or(0));
+        if value < 0.0 && fixed.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            out.insert(0, '-');
+        }
+        if !fraction.is_empty() {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+
+    /// `1234` -> "1,234 words", picking the singular or plural form
+    fn count(&self, n: usize, unit: Unit) -> String {
+        let singular = n == 1 || (n == 0 && self.zero_is_singular);
+        let label = if singular { unit.0 } else { unit.1 };
+        let space = if self.unit_space { " " } else { "" };
+        format!("{}{}{}", self.format_integer(n as i64), space, label)
+    }
+
+    pub(crate) fn words(&self, n: usize) -> String {
+        self.count(n, self.words)
+    }
+
+    pub(crate) fn characters(&self, n: usize) -> String {
+        self.count(n, self.characters)
+    }
+
+    pub(crate) fn lines(&self, n: usize) -> String {
+        self.count(n, self.lines)
+    }
+
+    pub(crate) fn reading_time(&self, minutes: usize) -> String {
+        self.reading_time.replace("{}", &self.format_integer(minutes as i64))
+    }
+}
+
+/// Format `value` with `decimals` fractional digits using the digit grouping
+/// and decimal mark of `locale` ("en", "de-AT", ...)
+#[wasm_bindgen]
+pub fn format_number(value: f64, decimals: usize, locale: &str) -> String {
+    LocaleInfo::get(locale).format_number(value, decimals)
+}
@@ -0,0 +1,172 @@
+// Logging. Messages go to the browser console at or above the level set
+// from JS (info by default), and optionally into a ring buffer of recent
+// entries the app can attach to a bug report. Debug and trace messages
+// are compiled out of release builds entirely, and a message below the
+// current level is never formatted.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+pub(crate) const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+
+/// Most entries the ring buffer can keep
+const MAX_BUFFER: usize = 10_000;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        LOG_LEVELS[self as usize]
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Entry {
+    level: Level,
+    /// The part of the crate that logged it, e.g. "codec"
+    target: &'static str,
+    message: String,
+    timestamp: f64,
+}
+
+struct Logger {
+    level: Level,
+    /// Entries kept; 0 keeps none
+    capacity: usize,
+    recent: VecDeque<Entry>,
+}
+
+thread_local! {
+    static LOGGER: RefCell<Logger> =
+        const { RefCell::new(Logger { level: Level::Info, capacity: 0, recent: VecDeque::new() }) };
+}
+
+/// Whether a message at `level` would be logged
+pub(crate) fn enabled(level: Level) -> bool {
+    LOGGER.with(|logger| level <= logger.borrow().level)
+}
+
+/// Log a message; use the macros, which skip formatting when it wouldn't
+/// be logged
+pub(crate) fn log(level: Level, target: &'static str, message: String) {
+    let line = format!("[{}] {}", target, message);
+    match level {
+        Level::Error => web_sys::console::error_1(&line.into()),
+        Level::Warn => web_sys::console::warn_1(&line.into()),
+        Level::Info => web_sys::console::info_1(&line.into()),
+        Level::Debug | Level::Trace => web_sys::console::debug_1(&line.into()),
+    }
+    LOGGER.with(|logger| {
+        let mut logger = logger.borrow_mut();
+        if logger.capacity == 0 {
+            return;
+        }
+        if logger.recent.len() == logger.capacity {
+            logger.recent.pop_front();
+        }
+        logger.recent.push_back(Entry { level, target, message, timestamp: js_sys::Date::now() });
+    });
+}
+
+macro_rules! log_at {
+    ($level:expr, $target:expr, $($arg:tt)+) => {
+        if $crate::logger::enabled($level) {
+            $crate::logger::log($level, $target, format!($($arg)+));
+        }
+    };
+}
+
+macro_rules! log_error {
+    ($target:expr, $($arg:tt)+) => { $crate::logger::log_at!($crate::logger::Level::Error, $target, $($arg)+) };
+}
+
+macro_rules! log_warn {
+    ($target:expr, $($arg:tt)+) => { $crate::logger::log_at!($crate::logger::Level::Warn, $target, $($arg)+) };
+}
+
+macro_rules! log_info {
+    ($target:expr, $($arg:tt)+) => { $crate::logger::log_at!($crate::logger::Level::Info, $target, $($arg)+) };
+}
+
+/// Compiled out of release builds
+macro_rules! log_debug {
+    ($target:expr, $($arg:tt)+) => {
+        if cfg!(debug_assertions) {
+            $crate::logger::log_at!($crate::logger::Level::Debug, $target, $($arg)+)
+        }
+    };
+}
+
+/// Compiled out of release builds
+macro_rules! log_trace {
+    ($target:expr, $($arg:tt)+) => {
+        if cfg!(debug_assertions) {
+            $crate::logger::log_at!($crate::logger::Level::Trace, $target, $($arg)+)
+        }
+    };
+}
+
+pub(crate) use {log_at, log_debug, log_error, log_info, log_trace, log_warn};
+
+/// Log at "error", "warn", "info", "debug" or "trace" and above; returns
+/// the level. Release builds never log below "info", whatever the level.
+#[wasm_bindgen]
+pub fn set_log_level(name: &str) -> Result<String, JsValue> {
+    let level = Level::parse(name).ok_or_else(|| {
+        JsValue::from_str(&format!("Unknown log level: {} (expected {})", name, LOG_LEVELS.join(", ")))
+    })?;
+    LOGGER.with(|logger| logger.borrow_mut().level = level);
+    Ok(level.as_str().to_string())
+}
+
+/// The current log level
+#[wasm_bindgen]
+pub fn log_level() -> String {
+    LOGGER.with(|logger| logger.borrow().level.as_str().to_string())
+}
+
+/// Keep the most recent `capacity` entries (up to 10,000) for
+/// `recent_logs`; 0, the default, keeps none and drops those kept
+#[wasm_bindgen]
+pub fn set_log_buffer(capacity: usize) {
+    LOGGER.with(|logger| {
+        let mut logger = logger.borrow_mut();
+        logger.capacity = capacity.min(MAX_BUFFER);
+        let excess = logger.recent.len().saturating_sub(logger.capacity);
+        logger.recent.drain(..excess);
+    });
+}
+
+/// The kept entries as JSON `[{level, target, message, timestamp}]`,
+/// oldest first
+#[wasm_bindgen]
+pub fn recent_logs() -> String {
+    LOGGER.with(|logger| serde_json::to_string(&logger.borrow().recent).unwrap_or_else(|_| "[]".into()))
+}
+
+/// Drop the kept entries
+#[wasm_bindgen]
+pub fn clear_logs() {
+    LOGGER.with(|logger| logger.borrow_mut().recent.clear());
+}
@@ -0,0 +1,341 @@
+// Editor macros: named sequences of edit operations that can be recorded,
+// replayed against a document and selection, and shared as JSON.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::emphasis::{toggle_style, InlineStyle};
+use crate::format::format_text;
+
+/// One recorded editor operation. Offsets and counts are in bytes, like the
+/// rest of the API; they are clamped to character boundaries on replay.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum EditOp {
+    /// Replace the selection with `text` and put the cursor after it
+    Insert { text: String },
+    /// Delete the selection, or `count` characters after (positive) or
+    /// before (negative) the cursor when nothing is selected
+    Delete { count: i64 },
+    /// Move the cursor by `by` characters, collapsing the selection
+    Move { by: i64 },
+    /// Move the cursor to "line_start", "line_end", "doc_start" or "doc_end"
+    MoveTo { target: String },
+    /// Extend the selection by `by` characters
+    Extend { by: i64 },
+    /// Select the whole line(s) the selection touches
+    SelectLine,
+    SelectAll,
+    /// Apply a toggle to the selection: "bold", "italic", "underline",
+    /// "strikethrough", "heading1".."heading6", "heading" (cycle), "list" or
+    /// "numbered_list"
+    Toggle { format: String },
+    /// Replace occurrences of `find` in the selection (or the whole
+    /// document when nothing is selected)
+    Replace { find: String, replace: String, all: bool },
+    /// Run `format_text` over the selection (or the whole document)
+    Format,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Macro {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub author: String,
+    pub ops: Vec<EditOp>,
+}
+
+/// A document and selection that ops are applied to
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct EditorState {
+    pub content: String,
+    pub selection_start: usize,
+    pub selection_end: usize,
+}
+
+fn floor_boundary(text: &str, mut pos: usize) -> usize {
+    pos = pos.min(text.len());
+    while !text.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    pos
+}
+
+/// Step `by` characters from `pos`, stopping at either end of `text`
+fn step_chars(text: &str, pos: usize, by: i64) -> usize {
+    if by >= 0 {
+        text[pos..].char_indices().nth(by as usize).map_or(text.len(), |(i, _)| pos + i)
+    } else {
+        text[..pos].char_indices().rev().nth((-by - 1) as usize).map_or(0, |(i, _)| i)
+    }
+}
+
+fn line_start(text: &str, pos: usize) -> usize {
+    text[..pos].rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn line_end(text: &str, pos: usize) -> usize {
+    text[pos..].find('\n').map_or(text.len(), |i| pos + i)
+}
+
+fn toggle(format: &str, text: &str) -> Result<String, String> {
+    Ok(match format {
+        "underline" => crate::toggle_underline(text),
+        "list" => crate::toggle_list(text),
+        "numbered_list" => crate::toggle_numbered_list(text),
+        "heading" => crate::headings::cycle_heading(text),
+        _ => match format.strip_prefix("heading").and_then(|l| l.parse::<u8>().ok()) {
+            Some(level @ 1..=6) => crate::toggle_heading(text, level),
+            _ => return Err(format!("Unknown toggle format: {}", format)),
+        },
+    })
+}
+
+impl EditorState {
+    pub(crate) fn new(content: &str, selection_start: usize, selection_end: usize) -> EditorState {
+        let (start, end) = (selection_start.min(selection_end), selection_start.max(selection_end));
+        EditorState {
+            selection_start: floor_boundary(content, start),
+            selection_end: floor_boundary(content, end),
+            content: content.to_string(),
+        }
+    }
+
+    fn selected(&self) -> &str {
+        &self.content[self.selection_start..self.selection_end]
+    }
+
+    fn has_selection(&self) -> bool {
+        self.selection_start != self.selection_end
+    }
+
+    fn collapse(&mut self, pos: usize) {
+        self.selection_start = pos;
+        self.selection_end = pos;
+    }
+
+    /// Replace the selection with `text`, keeping it selected
+    fn replace_selection(&mut self, text: &str) {
+        self.content.replace_range(self.selection_start..self.selection_end, text);
+        self.selection_end = self.selection_start + text.len();
+    }
+
+    pub(crate) fn apply(&mut self, op: &EditOp) -> Result<(), String> {
+        match op {
+            EditOp::Insert { text } => {
+                self.replace_selection(text);
+                self.collapse(self.selection_end);
+            }
+            EditOp::Delete { count } => {
+                if !self.has_selection() {
+                    let pos = self.selection_start;
+                    let other = step_chars(&self.content, pos, *count);
+                    self.selection_start = pos.min(other);
+                    self.selection_end = pos.max(other);
+                }
+                self.replace_selection("");
+            }
+            EditOp::Move { by } => {
+                let from = if *by < 0 { self.selection_start } else { self.selection_end };
+                self.collapse(step_chars(&self.content, from, *by));
+            }
+            EditOp::MoveTo { target } => {
+                let pos = match target.as_str() {
+                    "line_start" => line_start(&self.content, self.selection_start),
+                    "line_end" => line_end(&self.content, self.selection_end),
+                    "doc_start" => 0,
+                    "doc_end" => self.content.len(),
+                    _ => return Err(format!("Unknown move target: {}", target)),
+                };
+                self.collapse(pos);
+            }
+            EditOp::Extend { by } => {
+                if *by < 0 {
+                    self.selection_start = step_chars(&self.content, self.selection_start, *by);
+                } else {
+                    self.selection_end = step_chars(&self.content, self.selection_end, *by);
+                }
+            }
+            EditOp::SelectLine => {
+                self.selection_start = line_start(&self.content, self.selection_start);
+                self.selection_end = line_end(&self.content, self.selection_end);
+            }
+            EditOp::SelectAll => {
+                self.selection_start = 0;
+                self.selection_end = self.content.len();
+            }
+            EditOp::Toggle { format } => {
+                let inline = match format.as_str() {
+                    "bold" => Some(InlineStyle::Bold),
+                    "italic" => Some(InlineStyle::Italic),
+                    "strikethrough" => Some(InlineStyle::Strikethrough),
+                    _ => None,
+                };
+                match inline {
+                    // Inline styles look at the text around the selection
+                    Some(style) => {
+                        let toggled = toggle_style(&self.content, self.selection_start, self.selection_end, style);
+                        self.content = toggled.content;
+                        self.selection_start = toggled.selection_start;
+                        self.selection_end = toggled.selection_end;
+                    }
+                    None => {
+                        let toggled = toggle(format, self.selected())?;
+                        self.replace_selection(&toggled);
+                    }
+                }
+            }
+            EditOp::Replace { find, replace, all } => {
+                if find.is_empty() {
+                    return Err("Replace needs a non-empty search string".to_string());
+                }
+                if !self.has_selection() {
+                    self.selection_start = 0;
+                    self.selection_end = self.content.len();
+                }
+                let replaced = if *all { self.selected().replace(find, replace) } else { self.selected().replacen(find, replace, 1) };
+                self.replace_selection(&replaced);
+            }
+            EditOp::Format => {
+                if !self.has_selection() {
+                    self.selection_start = 0;
+                    self.selection_end = self.content.len();
+                }
+                let formatted = format_text(self.selected());
+                self.replace_selection(&formatted);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_macro(json: &str) -> Result<Macro, JsValue> {
+    let parsed: Macro = serde_json::from_str(json).map_err(|e| JsValue::from_str(&format!("Invalid macro: {}", e)))?;
+    if parsed.name.trim().is_empty() {
+        return Err(JsValue::from_str("Macro name cannot be empty"));
+    }
+    Ok(parsed)
+}
+
+/// Run `ops` `times` times over `content` with the given selection. Returns
+/// JSON `{content, selection_start, selection_end}`.
+fn replay(ops: &[EditOp], content: &str, selection_start: usize, selection_end: usize, times: u32) -> Result<String, JsValue> {
+    let mut state = EditorState::new(content, selection_start, selection_end);
+    for _ in 0..times.max(1) {
+        for (i, op) in ops.iter().enumerate() {
+            state.apply(op).map_err(|e| JsValue::from_str(&format!("Step {}: {}", i + 1, e)))?;
+        }
+    }
+    serde_json::to_string(&state).map_err(|e| JsValue::from_str(&format!("JSON encoding error: {}", e)))
+}
+
+/// Replay a macro given as JSON `{name, description, author, ops}` (as
+/// produced by `MacroLibrary::export_macro`) without adding it to a library
+#[wasm_bindgen]
+pub fn replay_macro(macro_json: &str, content: &str, selection_start: usize, selection_end: usize, times: u32) -> Result<String, JsValue> {
+    let parsed = parse_macro(macro_json)?;
+    replay(&parsed.ops, content, selection_start, selection_end, times)
+}
+
+/// Named macros plus an optional recording in progress. Each recorded op is
+/// a JSON object tagged by `op`: `{"op":"insert","text":"- "}`,
+/// `{"op":"move_to","target":"line_start"}`, `{"op":"toggle","format":"bold"}`,
+/// `{"op":"replace","find":"a","replace":"b","all":true}`, ...
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MacroLibrary {
+    macros: BTreeMap<String, Macro>,
+    #[serde(skip)]
+    recording: Option<Macro>,
+}
+
+#[wasm_bindgen]
+impl MacroLibrary {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> MacroLibrary {
+        MacroLibrary::default()
+    }
+
+    /// Start recording a macro called `name`, discarding any unfinished one
+    pub fn start_recording(&mut self, name: &str, author: &str) -> Result<(), JsValue> {
+        if name.trim().is_empty() {
+            return Err(JsValue::from_str("Macro name cannot be empty"));
+        }
+        self.recording = Some(Macro {
+            name: name.trim().to_string(),
+            description: String::new(),
+            author: author.to_string(),
+            ops: Vec::new(),
+        });
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Append an operation (JSON) to the recording
+    pub fn record(&mut self, op_json: &str) -> Result<(), JsValue> {
+        let op: EditOp = serde_json::from_str(op_json).map_err(|e| JsValue::from_str(&format!("Invalid edit op: {}", e)))?;
+        let recording = self.recording.as_mut().ok_or_else(|| JsValue::from_str("Not recording a macro"))?;
+        recording.ops.push(op);
+        Ok(())
+    }
+
+    /// Finish the recording and save it, replacing a macro of the same
+    /// name. Returns the saved macro as JSON.
+    pub fn stop_recording(&mut self, description: &str) -> Result<String, JsValue> {
+        let mut recorded = self.recording.take().ok_or_else(|| JsValue::from_str("Not recording a macro"))?;
+        recorded.description = description.to_string();
+        let json = serde_json::to_string(&recorded).unwrap_or_else(|_| "{}".to_string());
+        self.macros.insert(recorded.name.clone(), recorded);
+        Ok(json)
+    }
+
+    pub fn cancel_recording(&mut self) {
+        self.recording = None;
+    }
+
+    /// Names of saved macros as a JSON array, sorted
+    pub fn names(&self) -> String {
+        serde_json::to_string(&self.macros.keys().collect::<Vec<_>>()).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// A saved macro as shareable JSON `{name, description, author, ops}`
+    pub fn export_macro(&self, name: &str) -> Option<String> {
+        self.macros.get(name).and_then(|m| serde_json::to_string(m).ok())
+    }
+
+    /// Add a macro shared by a collaborator, replacing one of the same
+    /// name. Returns its name.
+    pub fn import_macro(&mut self, macro_json: &str) -> Result<String, JsValue> {
+        let parsed = parse_macro(macro_json)?;
+        let name = parsed.name.clone();
+        self.macros.insert(name.clone(), parsed);
+        Ok(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.macros.remove(name).is_some()
+    }
+
+    /// Replay a saved macro `times` times against `content` and the
+    /// selection. Returns JSON `{content, selection_start, selection_end}`.
+    pub fn replay(&self, name: &str, content: &str, selection_start: usize, selection_end: usize, times: u32) -> Result<String, JsValue> {
+        let saved = self.macros.get(name).ok_or_else(|| JsValue::from_str(&format!("Unknown macro: {}", name)))?;
+        replay(&saved.ops, content, selection_start, selection_end, times)
+    }
+
+    /// Serialize the saved macros (not a recording in progress) to CBOR
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<MacroLibrary, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
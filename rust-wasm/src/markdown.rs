@@ -0,0 +1,208 @@
+// Shared markdown scanning helpers used by the analysis and export modules.
+
+use crate::front_matter::front_matter_range;
+use crate::reflow::list_marker;
+
+/// Byte ranges of fenced code blocks (including the fence lines), fenced
+/// with backticks or tildes
+pub(crate) fn fenced_code_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    // Start and fence of the open block; only the same fence closes it
+    let mut open: Option<(usize, &str)> = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let fence = ["```", "~~~"].into_iter().find(|fence| trimmed.starts_with(fence));
+        match (open, fence) {
+            (Some((start, open_fence)), Some(fence)) if fence == open_fence => {
+                ranges.push((start, offset + line.trim_end_matches(['\n', '\r']).len()));
+                open = None;
+            }
+            (None, Some(fence)) => open = Some((offset, fence)),
+            _ => {}
+        }
+        offset += line.len();
+    }
+    // An unclosed fence runs to the end of the document
+    if let Some((start, _)) = open {
+        ranges.push((start, text.len()));
+    }
+    ranges
+}
+
+/// Byte ranges of indented code blocks: lines indented four spaces or a
+/// tab after a blank line, unless they continue a list item, up to the
+/// next line indented less
+pub(crate) fn indented_code_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut block: Option<(usize, usize)> = None;
+    // Content column of the list item being read, whose indented lines
+    // continue it rather than being code
+    let mut list_indent: Option<usize> = None;
+    let mut previous_blank = true;
+    let mut offset = 0;
+
+    for line in text.split('\n') {
+        let line_start = offset;
+        offset += line.len() + 1;
+        let content = line.trim_end_matches('\r');
+        let blank = content.trim().is_empty();
+        let indent = if content.starts_with('\t') { 4 } else { content.len() - content.trim_start_matches(' ').len() };
+        if let Some((start, end)) = block {
+            if blank {
+                continue;
+            }
+            if indent >= 4 {
+                block = Some((start, line_start + content.len()));
+                continue;
+            }
+            ranges.push((start, end));
+            block = None;
+        }
+        if !blank && previous_blank && indent >= 4 && list_indent.is_none_or(|column| indent < column) {
+            block = Some((line_start, line_start + content.len()));
+        } else if let Some(marker) = list_marker(content) {
+            list_indent = Some(marker);
+        } else if !blank && previous_blank && indent < list_indent.unwrap_or(0) {
+            list_indent = None;
+        }
+        previous_blank = blank;
+    }
+    ranges.extend(block);
+    ranges
+}
+
+/// Byte ranges of inline code spans (`code`), outside fenced blocks
+pub(crate) fn inline_code_ranges(text: &str) -> Vec<(usize, usize)> {
+    let fences = fenced_code_ranges(text);
+    let bytes = text.as_bytes();
+    let mut ranges = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(&(_, end)) = fences.iter().find(|(s, e)| i >= *s && i < *e) {
+            i = end;
+            continue;
+        }
+        if bytes[i] == b'`' {
+            // Match a closing run of the same number of backticks
+            let run = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+            let ticks = &text[i..i + run];
+            if let Some(close) = text[i + run..].find(ticks) {
+                let end = i + run + close + run;
+                ranges.push((i, end));
+                i = end;
+                continue;
+            }
+            i += run;
+            continue;
+        }
+        i += 1;
+    }
+    ranges
+}
+
+/// All code ranges (fenced and inline) plus the YAML front matter block:
+/// everything that isn't markdown prose. Sorted by start offset.
+pub(crate) fn code_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = fenced_code_ranges(text);
+    ranges.extend(inline_code_ranges(text));
+    ranges.extend(front_matter_range(text));
+    ranges.sort();
+    ranges
+}
+
+/// Whether `pos` falls inside any of the given ranges
+pub(crate) fn in_ranges(ranges: &[(usize, usize)], pos: usize) -> bool {
+    ranges.iter().any(|&(start, end)| pos >= start && pos < end)
+}
+
+/// Apply non-overlapping `(start, end, replacement)` edits in one pass
+pub(crate) fn apply_edits(content: &str, mut edits: Vec<(usize, usize, String)>) -> String {
+    edits.sort_by_key(|e| e.0);
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (start, end, text) in edits {
+        result.push_str(&content[last..start]);
+        result.push_str(&text);
+        last = end;
+    }
+    result.push_str(&content[last..]);
+    result
+}
+
+/// Replace the given (sorted, non-overlapping) ranges with placeholder
+/// tokens so text transformations can't touch them. Returns the masked text
+/// and the original snippets for `unmask`.
+pub(crate) fn mask_ranges(text: &str, ranges: &[(usize, usize)]) -> (String, Vec<String>) {
+    let mut masked = String::with_capacity(text.len());
+    let mut saved = Vec::with_capacity(ranges.len());
+    let mut last = 0;
+    for &(start, end) in ranges {
+        masked.push_str(&text[last..start]);
+        masked.push_str(&format!("\u{E000}{}\u{E001}", saved.len()));
+        saved.push(text[start..end].to_string());
+        last = end;
+    }
+    masked.push_str(&text[last..]);
+    (masked, saved)
+}
+
+/// Put back the snippets replaced by `mask_ranges`, in one pass over the
+/// text
+pub(crate) fn unmask(text: &str, saved: &[String]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('\u{E000}') {
+        result.push_str(&rest[..open]);
+        let after = &rest[open + '\u{E000}'.len_utf8()..];
+        let marker = after.find('\u{E001}').and_then(|close| {
+            let original = saved.get(after[..close].parse::<usize>().ok()?)?;
+            Some((original, &after[close + '\u{E001}'.len_utf8()..]))
+        });
+        match marker {
+            Some((original, tail)) => {
+                result.push_str(original);
+                rest = tail;
+            }
+            None => {
+                result.push('\u{E000}');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// 1-based line number of a byte offset
+pub(crate) fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset].matches('\n').count() + 1
+}
+
+/// Escape text for inclusion in HTML
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escape text for inclusion in XML, dropping the control characters XML
+/// does not allow
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            // Control characters other than tab and newline are not allowed in XML
+            c if c.is_control() && c != '\t' && c != '\n' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
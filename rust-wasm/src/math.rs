@@ -0,0 +1,157 @@
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::markdown::{code_ranges, in_ranges, line_of};
+
+/// A `$...$` or `$$...$$` math span
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct MathSpan {
+    /// Byte range including the delimiters
+    pub start: usize,
+    pub end: usize,
+    /// Byte range of the TeX source between the delimiters
+    pub content_start: usize,
+    pub content_end: usize,
+    /// `$$` display math rather than inline `$`
+    pub display: bool,
+    pub line: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct MathError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+#[derive(Serialize)]
+struct MathReport {
+    blocks: Vec<MathSpan>,
+    errors: Vec<MathError>,
+}
+
+/// Whether the text after an unmatched `$` looks like TeX rather than a
+/// price, so `$5 and $10` isn't reported
+fn looks_like_tex(text: &str) -> bool {
+    text.contains(['\\', '^', '_', '{', '}'])
+}
+
+/// End of the paragraph containing `pos` (the next blank line)
+fn paragraph_end(content: &str, pos: usize) -> usize {
+    content[pos..].find("\n\n").map_or(content.len(), |i| pos + i)
+}
+
+/// Find math spans outside code. Inline math follows the Pandoc rules: the
+/// opening `$` must be followed by a non-space, the closing `$` preceded by
+/// a non-space and not followed by a digit, and the span stays within one
+/// paragraph. `\$` is a literal dollar sign.
+pub(crate) fn scan_math(content: &str) -> (Vec<MathSpan>, Vec<MathError>) {
+    let skip = code_ranges(content);
+    let bytes = content.as_bytes();
+    let mut spans = Vec::new();
+    let mut errors = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                i += 2;
+                continue;
+            }
+            b'$' if !in_ranges(&skip, i) => {}
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+
+        if content[i..].starts_with("$$") {
+            let body_start = i + 2;
+            match content[body_start..].find("$$") {
+                Some(len) => {
+                    spans.push(MathSpan {
+                        start: i,
+                        end: body_start + len + 2,
+                        content_start: body_start,
+                        content_end: body_start + len,
+                        display: true,
+                        line: line_of(content, i),
+                    });
+                    i = body_start + len + 2;
+                }
+                None => {
+                    errors.push(MathError {
+                        message: "Display math opened with $$ is never closed".to_string(),
+                        start: i,
+                        end: body_start,
+                        line: line_of(content, i),
+                    });
+                    i = body_start;
+                }
+            }
+            continue;
+        }
+
+        // Inline math
+        let opens = bytes.get(i + 1).is_some_and(|b| !b.is_ascii_whitespace());
+        if !opens {
+            i += 1;
+            continue;
+        }
+        let limit = paragraph_end(content, i);
+        let mut j = i + 1;
+        let mut close = None;
+        while j < limit {
+            match bytes[j] {
+                b'\\' => j += 1,
+                b'$' if !bytes[j - 1].is_ascii_whitespace() && !bytes.get(j + 1).is_some_and(|b| b.is_ascii_digit()) => {
+                    close = Some(j);
+                    break;
+                }
+                _ => {}
+            }
+            j += 1;
+        }
+        match close {
+            Some(close) if close > i + 1 => {
+                spans.push(MathSpan {
+                    start: i,
+                    end: close + 1,
+                    content_start: i + 1,
+                    content_end: close,
+                    display: false,
+                    line: line_of(content, i),
+                });
+                i = close + 1;
+            }
+            _ => {
+                if looks_like_tex(&content[i + 1..limit]) {
+                    errors.push(MathError {
+                        message: "Inline math opened with $ is never closed".to_string(),
+                        start: i,
+                        end: i + 1,
+                        line: line_of(content, i),
+                    });
+                }
+                i += 1;
+            }
+        }
+    }
+    (spans, errors)
+}
+
+/// Byte ranges of all math spans, delimiters included
+pub(crate) fn math_ranges(content: &str) -> Vec<(usize, usize)> {
+    scan_math(content).0.iter().map(|m| (m.start, m.end)).collect()
+}
+
+/// Find `$...$` / `$$...$$` math and unbalanced delimiters. Returns JSON
+/// `{blocks: [{start, end, content_start, content_end, display, line}],
+/// errors: [{message, start, end, line}]}`; the preview can hand each
+/// block's content to KaTeX.
+#[wasm_bindgen]
+pub fn validate_math(content: &str) -> String {
+    let (blocks, errors) = scan_math(content);
+    serde_json::to_string(&MathReport { blocks, errors }).unwrap_or_default()
+}
@@ -0,0 +1,169 @@
+// @mentions: finding `@name` tokens for known collaborators in the text,
+// and `mention` messages that tell a collaborator where they were
+// mentioned, so the notification can jump straight to it.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::edit_log::{floor_boundary, message_edits, CHECKPOINT};
+use crate::markdown::{code_ranges, in_ranges};
+use crate::payload::{decode_payload, payload_data, Mention, Payload};
+use crate::{decode_with_grid_tag, encode_promisegrid_payload};
+
+/// Bytes of text either side of a mention kept as its excerpt
+const EXCERPT_BYTES: usize = 40;
+
+#[derive(Serialize)]
+struct Found {
+    user_id: String,
+    /// Byte range of the mention, `@` included
+    start: usize,
+    end: usize,
+    /// 1-based
+    line: usize,
+}
+
+/// Characters a username can contain
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// The name written at `at`, the `@` of a mention: the longest of `users`
+/// it starts with (ignoring ASCII case) that isn't followed by more of a
+/// word, or with no users, the whole name. Trailing dots end a sentence
+/// rather than the name.
+fn name_at<'a>(content: &'a str, at: usize, users: &'a [String]) -> Option<&'a str> {
+    let rest = &content[at + 1..];
+    let written = rest.trim_start_matches(is_name_char);
+    let written = &rest[..rest.len() - written.len()];
+    let written = written.trim_end_matches('.');
+    if written.is_empty() {
+        return None;
+    }
+    if users.is_empty() {
+        return Some(written);
+    }
+    users
+        .iter()
+        .filter(|user| {
+            !user.is_empty()
+                && rest.len() >= user.len()
+                && rest.is_char_boundary(user.len())
+                && rest[..user.len()].eq_ignore_ascii_case(user)
+                && !rest[user.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .max_by_key(|user| user.len())
+        .map(String::as_str)
+}
+
+/// Mentions in `content`, outside code
+fn find_mentions(content: &str, users: &[String]) -> Vec<Found> {
+    let skip = code_ranges(content);
+    content
+        .match_indices('@')
+        .map(|(at, _)| at)
+        // `a@b` is an email address, not a mention
+        .filter(|&at| content[..at].chars().next_back().is_none_or(|c| !is_name_char(c)))
+        .filter(|&at| !in_ranges(&skip, at))
+        .filter_map(|at| {
+            let name = name_at(content, at, users)?;
+            Some(Found {
+                user_id: name.to_string(),
+                start: at,
+                end: at + 1 + name.len(),
+                line: content[..at].matches('\n').count() + 1,
+            })
+        })
+        .collect()
+}
+
+fn parse_users(users_json: &str) -> Result<Vec<String>, JsValue> {
+    serde_json::from_str(users_json).map_err(|e| JsValue::from_str(&format!("Invalid user list: {}", e)))
+}
+
+/// Find @mentions of the users in `users_json`, a JSON array of user ids,
+/// as JSON `[{user_id, start, end, line}]` (byte offsets, the `@`
+/// included). Matching ignores ASCII case and reports the id as listed;
+/// an empty list accepts any name. Mentions in code and email addresses
+/// are skipped.
+#[wasm_bindgen]
+pub fn detect_mentions(content: &str, users_json: &str) -> Result<String, JsValue> {
+    let users = parse_users(users_json)?;
+    Ok(serde_json::to_string(&find_mentions(content, &users)).unwrap_or_else(|_| "[]".into()))
+}
+
+/// The @mentions an edit message wrote: those in `content`, the document
+/// after the edit, that overlap text the message inserted. Same JSON as
+/// `detect_mentions`; a checkpoint reports every mention.
+#[wasm_bindgen]
+pub fn detect_edit_mentions(cbor_bytes: &[u8], content: &str, users_json: &str) -> Result<String, JsValue> {
+    let users = parse_users(users_json)?;
+    let message = decode_with_grid_tag(cbor_bytes)
+        .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    let edits = message_edits(&message);
+    let mentions: Vec<Found> = find_mentions(content, &users)
+        .into_iter()
+        .filter(|found| {
+            edits.iter().any(|edit| {
+                let (_, inserted) = edit.extent();
+                edit.edit_type == CHECKPOINT
+                    || (inserted > 0 && edit.position < found.end && edit.position + inserted > found.start)
+            })
+        })
+        .collect();
+    Ok(serde_json::to_string(&mentions).unwrap_or_else(|_| "[]".into()))
+}
+
+/// Create a `mention` message telling `mentioned_user_id` that `user_id`
+/// mentioned them at `start..end` (bytes) of `content`. It carries the
+/// line and a short excerpt so the notification makes sense, and can jump
+/// there, before the document is open.
+#[wasm_bindgen]
+pub fn create_mention_message(
+    document_id: &str,
+    user_id: &str,
+    mentioned_user_id: &str,
+    content: &str,
+    start: u32,
+    end: u32,
+) -> Result<Vec<u8>, JsValue> {
+    let (start, end) = (start as usize, end as usize);
+    if start > end || end > content.len() {
+        return Err(JsValue::from_str(&format!(
+            "Invalid mention range {}..{} for {} bytes of text",
+            start,
+            end,
+            content.len()
+        )));
+    }
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[end..].find('\n').map_or(content.len(), |i| end + i);
+    let excerpt_start = floor_boundary(content, start.saturating_sub(EXCERPT_BYTES)).max(line_start);
+    let excerpt_end = floor_boundary(content, end + EXCERPT_BYTES).min(line_end);
+    let mention = Mention {
+        document_id: document_id.to_string(),
+        user_id: user_id.to_string(),
+        mentioned_user_id: mentioned_user_id.to_string(),
+        start: start as u64,
+        end: end as u64,
+        line: content[..start].matches('\n').count() as u64 + 1,
+        excerpt: content[excerpt_start..excerpt_end].trim().to_string(),
+        timestamp: js_sys::Date::now(),
+    };
+    Ok(encode_promisegrid_payload("mention", payload_data(&mention)))
+}
+
+/// Read a `mention` message as JSON `{document_id, user_id,
+/// mentioned_user_id, start, end, line, excerpt, timestamp}` for a
+/// notification
+#[wasm_bindgen]
+pub fn parse_mention_message(cbor_bytes: &[u8]) -> Result<String, JsValue> {
+    let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    if message.payload.message_type != "mention" {
+        return Err(JsValue::from_str(&format!("Expected a mention message, got {}", message.payload.message_type)));
+    }
+    let (_, Payload::Mention(mention)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+        return Err(JsValue::from_str("Expected a mention payload"));
+    };
+    Ok(serde_json::to_string(&mention).unwrap_or_else(|_| "{}".into()))
+}
@@ -0,0 +1,139 @@
+// Three-way merge of two versions edited from a common base, for clients
+// reconciling edits made offline. Both sides are diffed against the base;
+// where only one side changed a region its change is taken, and where
+// both changed it differently the region is a conflict.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::diff::{diff_strs, DiffKind};
+
+#[derive(Serialize)]
+struct Conflict {
+    /// 1-based line span of the conflict block, markers included, in the
+    /// merged content
+    start_line: usize,
+    end_line: usize,
+    base: String,
+    ours: String,
+    theirs: String,
+}
+
+#[derive(Serialize)]
+struct MergeResult {
+    content: String,
+    clean: bool,
+    conflicts: Vec<Conflict>,
+}
+
+/// For each base line, the line it is kept as in the other version
+fn base_matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    for op in diff_strs(base, other) {
+        if op.kind == DiffKind::Equal {
+            for (i, j) in op.old.zip(op.new) {
+                matches[i] = Some(j);
+            }
+        }
+    }
+    matches
+}
+
+fn push_lines(content: &mut String, lines: &[&str]) {
+    for line in lines {
+        content.push_str(line);
+    }
+}
+
+/// Close an unterminated last line so a marker after it starts a line
+fn end_line(content: &mut String) {
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+}
+
+fn line_count(content: &str) -> usize {
+    content.matches('\n').count() + usize::from(!content.is_empty() && !content.ends_with('\n'))
+}
+
+/// Merge `ours` and `theirs`, two versions of `base`, as JSON `{content,
+/// clean, conflicts}`. Changes made on only one side are applied; the
+/// same change made on both sides is applied once. A region both sides
+/// changed differently is written into `content` between conflict markers
+/// (`<<<<<<< ours`, `||||||| base`, `=======`, `>>>>>>> theirs`), with
+/// lines both sides agree on at its edges kept outside the markers, and
+/// listed in `conflicts` as `{start_line, end_line, base, ours, theirs}`
+/// so the UI can offer to pick a side instead of editing markers.
+#[wasm_bindgen]
+pub fn merge_documents(base: &str, ours: &str, theirs: &str) -> String {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let our_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let our_matches = base_matches(&base_lines, &our_lines);
+    let their_matches = base_matches(&base_lines, &their_lines);
+
+    let mut content = String::new();
+    let mut conflicts = Vec::new();
+    let (mut i, mut a, mut b) = (0, 0, 0);
+    loop {
+        // A base line both sides kept in place is stable
+        if i < base_lines.len() && our_matches[i] == Some(a) && their_matches[i] == Some(b) {
+            content.push_str(base_lines[i]);
+            i += 1;
+            a += 1;
+            b += 1;
+            continue;
+        }
+        // Otherwise the region runs to the next base line both sides kept
+        let next = (i..base_lines.len()).find(|&k| our_matches[k].is_some() && their_matches[k].is_some());
+        let (base_end, our_end, their_end) = match next {
+            Some(k) => (k, our_matches[k].unwrap_or(a), their_matches[k].unwrap_or(b)),
+            None => (base_lines.len(), our_lines.len(), their_lines.len()),
+        };
+        let base_chunk = &base_lines[i..base_end];
+        let our_chunk = &our_lines[a..our_end];
+        let their_chunk = &their_lines[b..their_end];
+        if our_chunk == base_chunk || our_chunk == their_chunk {
+            push_lines(&mut content, their_chunk);
+        } else if their_chunk == base_chunk {
+            push_lines(&mut content, our_chunk);
+        } else {
+            let prefix = our_chunk.iter().zip(their_chunk).take_while(|(x, y)| x == y).count();
+            let suffix = our_chunk[prefix..]
+                .iter()
+                .rev()
+                .zip(their_chunk[prefix..].iter().rev())
+                .take_while(|(x, y)| x == y)
+                .count();
+            push_lines(&mut content, &our_chunk[..prefix]);
+            end_line(&mut content);
+            let start_line = line_count(&content) + 1;
+            let (ours, theirs) = (
+                our_chunk[prefix..our_chunk.len() - suffix].concat(),
+                their_chunk[prefix..their_chunk.len() - suffix].concat(),
+            );
+            let base_text = base_chunk.concat();
+            content.push_str("<<<<<<< ours\n");
+            content.push_str(&ours);
+            end_line(&mut content);
+            content.push_str("||||||| base\n");
+            content.push_str(&base_text);
+            end_line(&mut content);
+            content.push_str("=======\n");
+            content.push_str(&theirs);
+            end_line(&mut content);
+            content.push_str(">>>>>>> theirs\n");
+            conflicts.push(Conflict { start_line, end_line: line_count(&content), base: base_text, ours, theirs });
+            push_lines(&mut content, &our_chunk[our_chunk.len() - suffix..]);
+        }
+        if next.is_none() {
+            break;
+        }
+        i = base_end;
+        a = our_end;
+        b = their_end;
+    }
+
+    let result = MergeResult { content, clean: conflicts.is_empty(), conflicts };
+    serde_json::to_string(&result).unwrap_or_else(|_| "{}".into())
+}
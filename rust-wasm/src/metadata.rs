@@ -0,0 +1,575 @@
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::audit::AuditLog;
+use crate::edit_log::message_edits;
+use crate::payload::{decode_payload, Payload};
+use crate::sync::relayed_messages;
+use crate::{signing, snapshot};
+use crate::{
+    data_bool, data_bytes, data_f64, data_text, decode_with_grid_tag, encode_promisegrid_payload, PromiseGridMessage,
+};
+
+/// Message types that modify document content by edits
+pub(crate) const CONTENT_MESSAGE_TYPES: &[&str] = &["document_edit", "document_checkpoint", "edit_batch", "batch"];
+
+/// Message types that need write access and are refused once the document
+/// is read-only: edits and whole-document snapshots. A `sync_response`
+/// needs it for whichever of these it relays.
+pub(crate) const WRITE_MESSAGE_TYPES: &[&str] =
+    &["document_edit", "document_checkpoint", "edit_batch", "batch", "document_snapshot"];
+
+/// The messages in `message` that write to a document: itself, or for a
+/// `sync_response` the write messages it relays
+pub(crate) fn write_messages(message: PromiseGridMessage) -> Vec<PromiseGridMessage> {
+    match message.payload.message_type.as_str() {
+        "sync_response" => relayed_messages(&message).into_iter().flat_map(write_messages).collect(),
+        t if WRITE_MESSAGE_TYPES.contains(&t) => vec![message],
+        _ => Vec::new(),
+    }
+}
+
+/// What happens when a document reaches its expiry time
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ExpiryAction {
+    /// The document stays readable but no longer accepts edits
+    ReadOnly,
+    /// The document should be deleted by every client ("self-destruct")
+    Delete,
+}
+
+impl ExpiryAction {
+    fn parse(action: &str) -> Result<ExpiryAction, JsValue> {
+        match action {
+            "read_only" | "" => Ok(ExpiryAction::ReadOnly),
+            "delete" => Ok(ExpiryAction::Delete),
+            other => Err(JsValue::from_str(&format!("Unknown expiry action: {}", other))),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ExpiryAction::ReadOnly => "read_only",
+            ExpiryAction::Delete => "delete",
+        }
+    }
+}
+
+/// Expiry settings for a document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Expiry {
+    /// Milliseconds since the Unix epoch
+    pub expires_at: f64,
+    pub action: ExpiryAction,
+    /// Whether the `expired` notification has already been produced
+    #[serde(default)]
+    pub notified: bool,
+}
+
+/// Legal-hold state: who froze the document, when, and why
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Freeze {
+    pub frozen_by: String,
+    pub frozen_at: f64,
+    pub reason: String,
+}
+
+/// Longest custom field key or value, in bytes
+const MAX_FIELD_BYTES: usize = 1024;
+
+/// Descriptive properties of a document. Travel with snapshots, so a
+/// client joining late sees the same title, tags and history.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct Properties {
+    pub title: String,
+    /// ms since epoch; 0 until known
+    pub created_at: f64,
+    pub modified_at: f64,
+    pub modified_by: String,
+    /// Everyone who has edited, in the order they first did
+    pub authors: Vec<String>,
+    pub tags: BTreeSet<String>,
+    /// BCP 47 tag, e.g. "en-GB"; empty when unset
+    pub language: String,
+    pub custom: BTreeMap<String, String>,
+}
+
+impl Properties {
+    /// Note an edit by `user_id` at `timestamp`; an edit without a time
+    /// only adds its author
+    fn record_edit(&mut self, user_id: &str, timestamp: f64) {
+        if !self.authors.iter().any(|author| author == user_id) {
+            self.authors.push(user_id.to_string());
+        }
+        if timestamp.is_nan() || timestamp <= 0.0 {
+            return;
+        }
+        if self.created_at == 0.0 || timestamp < self.created_at {
+            self.created_at = timestamp;
+        }
+        if timestamp >= self.modified_at {
+            self.modified_at = timestamp;
+            self.modified_by = user_id.to_string();
+        }
+    }
+}
+
+/// Whether `tag` looks like a BCP 47 language tag: subtags of 1 to 8
+/// ASCII letters and digits joined by hyphens, starting with letters
+fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    subtags.next().is_some_and(|primary| (2..=8).contains(&primary.len()) && primary.bytes().all(|b| b.is_ascii_alphabetic()))
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.bytes().all(|b| b.is_ascii_alphanumeric()))
+}
+
+/// Per-document metadata shared by all collaborators
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DocumentMetadata {
+    document_id: String,
+    expiry: Option<Expiry>,
+    /// Owner user_id -> Ed25519 public key used to verify control messages
+    #[serde(default)]
+    owners: BTreeMap<String, Vec<u8>>,
+    #[serde(default)]
+    freeze: Option<Freeze>,
+    #[serde(default)]
+    audit: AuditLog,
+    #[serde(default)]
+    properties: Properties,
+}
+
+#[wasm_bindgen]
+impl DocumentMetadata {
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str) -> DocumentMetadata {
+        DocumentMetadata {
+            document_id: document_id.to_string(),
+            expiry: None,
+            owners: BTreeMap::new(),
+            freeze: None,
+            audit: AuditLog::default(),
+            properties: Properties::default(),
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn document_id(&self) -> String {
+        self.document_id.clone()
+    }
+
+    /// Set the expiry time (ms since epoch). `action` is "read_only"
+    /// (default) or "delete".
+    pub fn set_expiry(&mut self, timestamp: f64, action: Option<String>) -> Result<(), JsValue> {
+        if !timestamp.is_finite() || timestamp <= 0.0 {
+            return Err(JsValue::from_str("Expiry timestamp must be a positive number of milliseconds"));
+        }
+        let action = ExpiryAction::parse(action.as_deref().unwrap_or(""))?;
+        self.expiry = Some(Expiry {
+            expires_at: timestamp,
+            action,
+            notified: false,
+        });
+        Ok(())
+    }
+
+    /// Remove any expiry
+    pub fn clear_expiry(&mut self) {
+        self.expiry = None;
+    }
+
+    /// Expiry time in ms since epoch, if one is set
+    pub fn expires_at(&self) -> Option<f64> {
+        self.expiry.as_ref().map(|e| e.expires_at)
+    }
+
+    /// Whether the document has expired at time `now` (ms since epoch)
+    pub fn is_expired(&self, now: f64) -> bool {
+        self.expiry.as_ref().is_some_and(|e| now >= e.expires_at)
+    }
+
+    /// Fail with a descriptive error if edits are not allowed at `now`
+    pub fn check_edit(&self, now: f64) -> Result<(), JsValue> {
+        self.edit_allowed(now).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Check an incoming PromiseGrid message against the document's state.
+    /// Content-changing messages (edits and snapshots, also when relayed
+    /// in a `sync_response`) are rejected once the document has expired or
+    /// while it is frozen; attempts on a frozen document are audited.
+    pub fn check_message(&mut self, cbor_bytes: &[u8], now: f64) -> Result<(), JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        write_messages(message)
+            .iter()
+            .try_for_each(|write| self.check_write(write, now))
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Register an owner allowed to sign control messages such as freeze
+    pub fn add_owner(&mut self, user_id: &str, public_key: &[u8]) -> Result<(), JsValue> {
+        if public_key.len() != 32 {
+            return Err(JsValue::from_str("Owner public key must be 32 bytes"));
+        }
+        self.owners.insert(user_id.to_string(), public_key.to_vec());
+        Ok(())
+    }
+
+    pub fn remove_owner(&mut self, user_id: &str) {
+        self.owners.remove(user_id);
+    }
+
+    pub fn is_owner(&self, user_id: &str) -> bool {
+        self.owners.contains_key(user_id)
+    }
+
+    /// Whether the document is under legal hold
+    pub fn is_frozen(&self) -> bool {
+        self.freeze.is_some()
+    }
+
+    /// Ask whether history may be pruned or compacted. Refused (and
+    /// audited) while the document is frozen.
+    pub fn check_prune(&mut self, user_id: &str, now: f64) -> Result<(), JsValue> {
+        self.prune_allowed(user_id, now).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Apply a signed control message (currently `document_freeze`).
+    /// The signer must be a registered owner; every attempt is audited.
+    pub fn apply_control_message(&mut self, cbor_bytes: &[u8], now: f64) -> Result<(), JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let data = &message.payload.data;
+        if message.payload.message_type != "document_freeze" {
+            return Err(JsValue::from_str(&format!(
+                "Unsupported control message: {}",
+                message.payload.message_type
+            )));
+        }
+
+        let user_id = data_text(data, "user_id").unwrap_or("unknown");
+        let frozen = data_bool(data, "frozen").unwrap_or(false);
+        let reason = data_text(data, "reason").unwrap_or("");
+        let action = if frozen { "freeze" } else { "unfreeze" };
+
+        let verified = match (
+            data_text(data, "document_id"),
+            data_f64(data, "timestamp"),
+            data_bytes(data, "signature"),
+            self.owners.get(user_id),
+        ) {
+            (Some(document_id), Some(timestamp), Some(signature), Some(public_key)) => {
+                document_id == self.document_id
+                    && signing::verify(
+                        public_key,
+                        &freeze_signing_bytes(document_id, frozen, reason, user_id, timestamp),
+                        signature,
+                    )
+            }
+            _ => false,
+        };
+
+        self.audit.record(now, user_id, action, verified, reason);
+        if !verified {
+            return Err(JsValue::from_str(&format!(
+                "Rejected {} of {}: not signed by a document owner",
+                action, self.document_id
+            )));
+        }
+
+        self.freeze = frozen.then(|| Freeze {
+            frozen_by: user_id.to_string(),
+            frozen_at: now,
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Audit log entries as JSON
+    /// (`[{timestamp, user_id, action, allowed, detail}]`)
+    pub fn audit_log(&self) -> String {
+        serde_json::to_string(self.audit.entries()).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Return the `expired` notification message the first time this is
+    /// called after the document expires, and `undefined` otherwise.
+    /// Call it periodically (and on load) to broadcast expiry exactly once.
+    pub fn poll_expiry(&mut self, now: f64, user_id: &str) -> Option<Vec<u8>> {
+        let expiry = self.expiry.as_mut()?;
+        if now < expiry.expires_at || expiry.notified {
+            return None;
+        }
+        expiry.notified = true;
+        let (expires_at, action) = (expiry.expires_at, expiry.action);
+        Some(create_promisegrid_expired_message(&self.document_id, expires_at, action.as_str(), user_id))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn title(&self) -> String {
+        self.properties.title.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_title(&mut self, title: &str) {
+        self.properties.title = title.trim().to_string();
+    }
+
+    /// BCP 47 language tag; empty when unset
+    #[wasm_bindgen(getter)]
+    pub fn language(&self) -> String {
+        self.properties.language.clone()
+    }
+
+    /// Set the language from a BCP 47 tag such as "en" or "pt-BR"; empty
+    /// clears it
+    pub fn set_language(&mut self, language: &str) -> Result<(), JsValue> {
+        let language = language.trim();
+        if !language.is_empty() && !is_language_tag(language) {
+            return Err(JsValue::from_str(&format!("Invalid language tag: {}", language)));
+        }
+        self.properties.language = language.to_string();
+        Ok(())
+    }
+
+    /// When the document was first edited (ms since epoch), 0 if unknown
+    #[wasm_bindgen(getter)]
+    pub fn created_at(&self) -> f64 {
+        self.properties.created_at
+    }
+
+    /// Set the creation time, e.g. from an imported file
+    #[wasm_bindgen(setter)]
+    pub fn set_created_at(&mut self, timestamp: f64) {
+        self.properties.created_at = timestamp;
+    }
+
+    /// When the document was last edited (ms since epoch), 0 if never
+    #[wasm_bindgen(getter)]
+    pub fn modified_at(&self) -> f64 {
+        self.properties.modified_at
+    }
+
+    /// Who made the last edit
+    #[wasm_bindgen(getter)]
+    pub fn modified_by(&self) -> String {
+        self.properties.modified_by.clone()
+    }
+
+    /// Everyone who has edited, as a JSON array in the order they first did
+    pub fn authors(&self) -> String {
+        serde_json::to_string(&self.properties.authors).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// Credit `user_id` as an author without an edit, e.g. on import
+    pub fn add_author(&mut self, user_id: &str) {
+        if !self.properties.authors.iter().any(|author| author == user_id) {
+            self.properties.authors.push(user_id.to_string());
+        }
+    }
+
+    /// Tags as a sorted JSON array
+    pub fn tags(&self) -> String {
+        serde_json::to_string(&self.properties.tags).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// Add a tag (trimmed); returns false if it was already there or empty
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        let tag = tag.trim();
+        !tag.is_empty() && self.properties.tags.insert(tag.to_string())
+    }
+
+    /// Remove a tag; returns whether it was there
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        self.properties.tags.remove(tag.trim())
+    }
+
+    /// A custom field's value
+    pub fn field(&self, key: &str) -> Option<String> {
+        self.properties.custom.get(key).cloned()
+    }
+
+    /// Set a custom field; keys and values are limited to 1 KiB
+    pub fn set_field(&mut self, key: &str, value: &str) -> Result<(), JsValue> {
+        if key.is_empty() || key.len() > MAX_FIELD_BYTES || value.len() > MAX_FIELD_BYTES {
+            return Err(JsValue::from_str(&format!(
+                "Custom field keys must be 1 to {} bytes and values at most {}",
+                MAX_FIELD_BYTES, MAX_FIELD_BYTES
+            )));
+        }
+        self.properties.custom.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Remove a custom field; returns whether it was there
+    pub fn remove_field(&mut self, key: &str) -> bool {
+        self.properties.custom.remove(key).is_some()
+    }
+
+    /// The descriptive properties as JSON `{title, created_at,
+    /// modified_at, modified_by, authors, tags, language, custom}`
+    pub fn properties(&self) -> String {
+        serde_json::to_string(&self.properties).unwrap_or_else(|_| "{}".into())
+    }
+
+    /// Update the modified time, last editor and authors from a message of
+    /// the edit stream. Returns whether it was an edit of this document.
+    pub fn record_edits(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        if !CONTENT_MESSAGE_TYPES.contains(&message.payload.message_type.as_str())
+            || data_text(&message.payload.data, "document_id") != Some(self.document_id.as_str())
+        {
+            return Ok(false);
+        }
+        for edit in message_edits(&message) {
+            self.properties.record_edit(&edit.user_id, edit.timestamp);
+        }
+        Ok(true)
+    }
+
+    /// A `document_snapshot` of `content` at `version` that carries the
+    /// descriptive properties; see `create_snapshot_message`
+    pub fn snapshot_message(&self, content: &str, version: u64, user_id: &str) -> Result<Vec<u8>, JsValue> {
+        let properties = crate::to_cbor(&self.properties)
+            .map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))?;
+        Ok(snapshot::snapshot_message(&self.document_id, content, version, user_id, Some(properties)))
+    }
+
+    /// Take the descriptive properties from a `document_snapshot` of this
+    /// document, when it carries them and they're at least as recent as
+    /// ours. Returns whether they were taken.
+    pub fn load_snapshot(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let Ok((_, Payload::Snapshot(snapshot))) = decode_payload(&message) else {
+            return Ok(false);
+        };
+        let Some(bytes) = snapshot.metadata.filter(|_| snapshot.document_id == self.document_id) else {
+            return Ok(false);
+        };
+        let properties: Properties = crate::from_cbor(&bytes)
+            .map_err(|e| JsValue::from_str(&format!("Invalid snapshot metadata: {}", e)))?;
+        if properties.modified_at < self.properties.modified_at {
+            return Ok(false);
+        }
+        self.properties = properties;
+        Ok(true)
+    }
+
+    /// Metadata as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Serialize to CBOR for persistence alongside the document
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<DocumentMetadata, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
+
+impl DocumentMetadata {
+    fn edit_allowed(&self, now: f64) -> Result<(), String> {
+        if let Some(freeze) = &self.freeze {
+            return Err(format!(
+                "Document {} is frozen (legal hold by {}: {})",
+                self.document_id, freeze.frozen_by, freeze.reason
+            ));
+        }
+        match &self.expiry {
+            Some(expiry) if now >= expiry.expires_at => {
+                Err(format!("Document {} expired at {} and is read-only", self.document_id, expiry.expires_at))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn prune_allowed(&mut self, user_id: &str, now: f64) -> Result<(), String> {
+        if self.freeze.is_none() {
+            return Ok(());
+        }
+        self.audit.record(now, user_id, "prune_history", false, "history pruning attempted during legal hold");
+        Err(format!("Document {} is frozen; history cannot be pruned", self.document_id))
+    }
+
+    /// Check one write message (see `write_messages`) at `now`. Attempts
+    /// on a frozen document are audited.
+    pub(crate) fn check_write(&mut self, write: &PromiseGridMessage, now: f64) -> Result<(), String> {
+        let result = self.edit_allowed(now);
+        if self.freeze.is_some() {
+            let user_id = data_text(&write.payload.data, "user_id").unwrap_or("unknown");
+            self.audit.record(now, user_id, &write.payload.message_type, result.is_ok(), "edit attempted during legal hold");
+        }
+        result
+    }
+}
+
+/// Create the `expired` notification broadcast when a document reaches its
+/// expiry time. `action` is "read_only" or "delete".
+#[wasm_bindgen]
+pub fn create_promisegrid_expired_message(
+    document_id: &str,
+    expired_at: f64,
+    action: &str,
+    user_id: &str,
+) -> Vec<u8> {
+    let mut data = HashMap::new();
+    data.insert("document_id".to_string(), ciborium::Value::Text(document_id.to_string()));
+    data.insert("expired_at".to_string(), ciborium::Value::Float(expired_at));
+    data.insert("action".to_string(), ciborium::Value::Text(action.to_string()));
+    data.insert("timestamp".to_string(), ciborium::Value::Float(js_sys::Date::now()));
+    data.insert("user_id".to_string(), ciborium::Value::Text(user_id.to_string()));
+
+    encode_promisegrid_payload("expired", data)
+}
+
+/// Bytes covered by the signature of a freeze control message
+fn freeze_signing_bytes(document_id: &str, frozen: bool, reason: &str, user_id: &str, timestamp: f64) -> Vec<u8> {
+    format!("document_freeze\0{}\0{}\0{}\0{}\0{}", document_id, frozen, reason, user_id, timestamp).into_bytes()
+}
+
+/// Create a signed `document_freeze` control message placing the document
+/// under legal hold (`frozen = true`) or releasing it. Only messages signed
+/// with the secret key of a registered owner are accepted.
+#[wasm_bindgen]
+pub fn create_promisegrid_freeze_message(
+    document_id: &str,
+    frozen: bool,
+    reason: &str,
+    user_id: &str,
+    secret_key: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    let data = freeze_data(document_id, frozen, reason, user_id, secret_key, js_sys::Date::now())?;
+    Ok(encode_promisegrid_payload("document_freeze", data))
+}
+
+/// Data of a signed `document_freeze` control message sent at `timestamp`
+pub(crate) fn freeze_data(
+    document_id: &str,
+    frozen: bool,
+    reason: &str,
+    user_id: &str,
+    secret_key: &[u8],
+    timestamp: f64,
+) -> Result<HashMap<String, ciborium::Value>, JsValue> {
+    let signature = signing::sign(
+        secret_key,
+        &freeze_signing_bytes(document_id, frozen, reason, user_id, timestamp),
+    )?;
+
+    let mut data = HashMap::new();
+    data.insert("document_id".to_string(), ciborium::Value::Text(document_id.to_string()));
+    data.insert("frozen".to_string(), ciborium::Value::Bool(frozen));
+    data.insert("reason".to_string(), ciborium::Value::Text(reason.to_string()));
+    data.insert("timestamp".to_string(), ciborium::Value::Float(timestamp));
+    data.insert("user_id".to_string(), ciborium::Value::Text(user_id.to_string()));
+    data.insert("signature".to_string(), ciborium::Value::Bytes(signature));
+    Ok(data)
+}
@@ -0,0 +1,218 @@
+// Offline outbox. While disconnected, the user's edits are queued here
+// instead of sent, and the queue survives a reload through to_bytes and
+// from_bytes. Remote edits received meanwhile, live or through sync on
+// reconnect, move the text the queued edits were made against, so each
+// queued edit is rebased past them; replaying then encodes the edits
+// afresh, with a new envelope, in the order they were made.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::edit_log::{message_edits, Edit, CHECKPOINT};
+use crate::payload::{decode_payload, payload_data, Payload};
+use crate::{data_text, decode_with_grid_tag, encode_promisegrid_payload, DocumentEdit};
+
+/// Where an edit applies: `removed` bytes at `start` replaced by
+/// `inserted` bytes
+#[derive(Clone, Copy)]
+struct Span {
+    start: usize,
+    removed: usize,
+    inserted: usize,
+}
+
+impl Span {
+    fn of(edit_type: &str, position: usize, length: Option<usize>, content: &str) -> Span {
+        let (removed, inserted) = match edit_type {
+            "insert" => (0, content.len()),
+            "delete" => (length.unwrap_or(content.len()), 0),
+            _ => (length.unwrap_or(0), content.len()),
+        };
+        Span { start: position, removed, inserted }
+    }
+
+    /// This span moved past `by`, an edit to the same text, as the edits
+    /// to make in its place: usually one, but two when what it removes
+    /// now lies on both sides of `by`'s insertion, which it must keep.
+    /// Text both remove is removed once. `by_first` decides ties: an
+    /// insertion at the same place goes after `by`'s.
+    fn transform(self, by: Span, by_first: bool) -> Vec<Span> {
+        let by_end = by.start + by.removed;
+        let shift = |pos: usize| pos - by.removed + by.inserted;
+        let end = self.start + self.removed;
+        // What is left to remove before and after `by`'s insertion
+        let head = self.start.min(by.start)..end.min(by.start);
+        let tail = shift(self.start.max(by_end))..shift(end.max(by_end));
+        let at = if self.start < by.start || (self.start == by.start && !by_first) {
+            self.start
+        } else if self.start < by_end {
+            by.start + by.inserted
+        } else {
+            shift(self.start)
+        };
+        if head.is_empty() && (tail.is_empty() || tail.start == at) {
+            vec![Span { start: at, removed: tail.len(), inserted: self.inserted }]
+        } else if tail.is_empty() || head.end == tail.start {
+            let end = if tail.is_empty() { head.end } else { tail.end };
+            vec![Span { start: at, removed: end - at, inserted: self.inserted }]
+        } else {
+            // Remove the tail first so the head keeps its position
+            vec![
+                Span { start: tail.start, removed: tail.len(), inserted: 0 },
+                Span { start: at, removed: head.len(), inserted: self.inserted },
+            ]
+        }
+    }
+}
+
+/// Move edit sequences `a` and `b`, each made to the same text, past each
+/// other: returns `a` as made after `b`, and `b` as made after `a`
+fn transform_all(a: &[Span], b: &[Span], b_first: bool) -> (Vec<Span>, Vec<Span>) {
+    match (a, b) {
+        ([], _) | (_, []) => (a.to_vec(), b.to_vec()),
+        ([x], [y]) => (x.transform(*y, b_first), y.transform(*x, !b_first)),
+        ([x, rest @ ..], _) if !rest.is_empty() => {
+            let (x_moved, b_moved) = transform_all(&[*x], b, b_first);
+            let (rest_moved, b_moved) = transform_all(rest, &b_moved, b_first);
+            ([x_moved, rest_moved].concat(), b_moved)
+        }
+        (_, [y, rest @ ..]) => {
+            let (a_moved, y_moved) = transform_all(a, &[*y], b_first);
+            let (a_moved, rest_moved) = transform_all(&a_moved, rest, b_first);
+            (a_moved, [y_moved, rest_moved].concat())
+        }
+    }
+}
+
+fn remote_span(edit: &Edit) -> Span {
+    Span::of(&edit.edit_type, edit.position, edit.length, &edit.content)
+}
+
+fn queued_span(edit: &DocumentEdit) -> Span {
+    Span::of(&edit.edit_type, edit.position as usize, edit.length.map(|n| n as usize), &edit.content)
+}
+
+/// A queued edit moved to `pieces`: one edit, or a deletion and then the
+/// rest of the edit when it was split around a remote insertion
+fn rebased_edits(edit: DocumentEdit, pieces: &[Span]) -> Vec<DocumentEdit> {
+    let Some((last, before)) = pieces.split_last() else {
+        return vec![edit];
+    };
+    let mut edits: Vec<DocumentEdit> = before
+        .iter()
+        .map(|piece| DocumentEdit {
+            edit_type: "delete".to_string(),
+            position: piece.start as u32,
+            content: String::new(),
+            length: Some(piece.removed as u32),
+            prev_hash: None,
+            ..edit.clone()
+        })
+        .collect();
+    let mut edit = edit;
+    edit.position = last.start as u32;
+    if edit.edit_type != "insert" {
+        edit.length = Some(last.removed as u32);
+    }
+    edits.push(edit);
+    edits
+}
+
+/// Edits made while offline, waiting to be sent
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OfflineQueue {
+    document_id: String,
+    user_id: String,
+    /// Oldest first, each against the text after the ones before it
+    edits: Vec<DocumentEdit>,
+}
+
+#[wasm_bindgen]
+impl OfflineQueue {
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str, user_id: &str) -> OfflineQueue {
+        OfflineQueue { document_id: document_id.to_string(), user_id: user_id.to_string(), edits: Vec::new() }
+    }
+
+    /// Queue an outgoing `document_edit` of this document and user, as
+    /// built by `create_promisegrid_edit_message`, instead of sending it
+    pub fn enqueue(&mut self, cbor_bytes: &[u8]) -> Result<(), JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let (_, Payload::DocumentEdit(edit)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Err(JsValue::from_str(&format!(
+                "Only document_edit messages can be queued, not {}",
+                message.payload.message_type
+            )));
+        };
+        if edit.document_id != self.document_id || edit.user_id != self.user_id {
+            return Err(JsValue::from_str("Edit is for a different document or user than the queue"));
+        }
+        self.edits.push(edit);
+        Ok(())
+    }
+
+    /// Rebase the queued edits past a remote message received since going
+    /// offline: a `document_edit`, `edit_batch` or `batch` of this
+    /// document, which was made without seeing them. Returns the number of
+    /// remote edits applied; other messages return 0. A checkpoint can't
+    /// be rebased past and is an error.
+    pub fn rebase(&mut self, cbor_bytes: &[u8]) -> Result<usize, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        if data_text(&message.payload.data, "document_id") != Some(self.document_id.as_str()) {
+            return Ok(0);
+        }
+        let remote = message_edits(&message);
+        if remote.iter().any(|edit| edit.edit_type == CHECKPOINT) {
+            return Err(JsValue::from_str("Can't rebase queued edits past a checkpoint; reload the document"));
+        }
+        for edit in &remote {
+            let mut by = vec![remote_span(edit)];
+            let mut rebased = Vec::with_capacity(self.edits.len());
+            for queued in std::mem::take(&mut self.edits) {
+                let (pieces, by_moved) = transform_all(&[queued_span(&queued)], &by, true);
+                by = by_moved;
+                rebased.extend(rebased_edits(queued, &pieces));
+            }
+            self.edits = rebased;
+        }
+        Ok(remote.len())
+    }
+
+    /// The oldest queued edit as a new `document_edit` message to send,
+    /// removing it from the queue; call until it returns `undefined`.
+    /// Each gets a fresh envelope, so it isn't refused as a stale replay.
+    pub fn next_message(&mut self) -> Option<Vec<u8>> {
+        if self.edits.is_empty() {
+            return None;
+        }
+        let mut edit = self.edits.remove(0);
+        // The hash chain has moved on since the edit was made
+        edit.prev_hash = None;
+        Some(encode_promisegrid_payload("document_edit", payload_data(&edit)))
+    }
+
+    /// Edits queued
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Drop every queued edit
+    pub fn clear(&mut self) {
+        self.edits.clear();
+    }
+
+    /// Serialize to CBOR, e.g. for localStorage or IndexedDB
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<OfflineQueue, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
@@ -0,0 +1,110 @@
+// Delivery tracking for sent messages: every message waits in the queue
+// until the peer acknowledges its id, is sent again with exponential
+// backoff while it doesn't, and is reported as failed once the attempts
+// run out, instead of vanishing when the transport drops it.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// First retry delay, doubled after each attempt (ms)
+pub(crate) const DEFAULT_RETRY_BASE_MS: f64 = 1_000.0;
+
+/// Longest delay between attempts (ms)
+const MAX_RETRY_DELAY_MS: f64 = 30_000.0;
+
+/// Sends, first one included, before a message is given up on
+pub(crate) const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+struct Unacked {
+    bytes: Vec<u8>,
+    message_type: String,
+    first_sent: f64,
+    attempts: u32,
+    next_retry: f64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DeliveryFailure {
+    pub message_id: String,
+    pub message_type: String,
+    pub attempts: u32,
+    pub first_sent: f64,
+}
+
+pub(crate) struct OutboundQueue {
+    base_ms: f64,
+    max_attempts: u32,
+    /// By message id
+    unacked: BTreeMap<String, Unacked>,
+    failures: Vec<DeliveryFailure>,
+}
+
+impl OutboundQueue {
+    pub fn new(base_ms: f64, max_attempts: u32) -> OutboundQueue {
+        OutboundQueue { base_ms, max_attempts, unacked: BTreeMap::new(), failures: Vec::new() }
+    }
+
+    pub fn set_policy(&mut self, base_ms: f64, max_attempts: u32) {
+        self.base_ms = base_ms;
+        self.max_attempts = max_attempts.max(1);
+    }
+
+    fn delay(&self, attempts: u32) -> f64 {
+        (self.base_ms * 2f64.powi(attempts.saturating_sub(1) as i32)).min(MAX_RETRY_DELAY_MS)
+    }
+
+    /// Start tracking a message that was just sent for the first time
+    pub fn track(&mut self, message_id: &str, message_type: &str, bytes: &[u8], now: f64) {
+        let delay = self.delay(1);
+        self.unacked.entry(message_id.to_string()).or_insert(Unacked {
+            bytes: bytes.to_vec(),
+            message_type: message_type.to_string(),
+            first_sent: now,
+            attempts: 1,
+            next_retry: now + delay,
+        });
+    }
+
+    /// Stop tracking an acknowledged message. False when it wasn't
+    /// pending (already acked, failed or never sent).
+    pub fn acknowledge(&mut self, message_id: &str) -> bool {
+        self.unacked.remove(message_id).is_some()
+    }
+
+    /// The next message due to be sent again, counting the attempt.
+    /// Messages out of attempts move to the failures instead.
+    pub fn next_retry(&mut self, now: f64) -> Option<Vec<u8>> {
+        loop {
+            let (id, entry) = self
+                .unacked
+                .iter()
+                .filter(|(_, u)| u.next_retry <= now)
+                .min_by(|a, b| a.1.next_retry.total_cmp(&b.1.next_retry))?;
+            let id = id.clone();
+            if entry.attempts >= self.max_attempts {
+                let entry = self.unacked.remove(&id)?;
+                self.failures.push(DeliveryFailure {
+                    message_id: id,
+                    message_type: entry.message_type,
+                    attempts: entry.attempts,
+                    first_sent: entry.first_sent,
+                });
+                continue;
+            }
+            let delay = self.delay(entry.attempts + 1);
+            let entry = self.unacked.get_mut(&id)?;
+            entry.attempts += 1;
+            entry.next_retry = now + delay;
+            return Some(entry.bytes.clone());
+        }
+    }
+
+    /// Failures since the last call
+    pub fn take_failures(&mut self) -> Vec<DeliveryFailure> {
+        std::mem::take(&mut self.failures)
+    }
+
+    pub fn pending(&self) -> usize {
+        self.unacked.len()
+    }
+}
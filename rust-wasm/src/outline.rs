@@ -0,0 +1,66 @@
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::headings::{plain_heading_text, slugged_headings};
+
+/// A heading and the section it introduces
+#[derive(Serialize, Debug)]
+pub(crate) struct OutlineNode {
+    pub level: u8,
+    pub text: String,
+    pub slug: String,
+    /// Byte range of the heading itself
+    pub start: usize,
+    pub end: usize,
+    /// End of the section: the start of the next heading at the same or a
+    /// higher level, or the end of the document
+    pub section_end: usize,
+    pub line: usize,
+    pub children: Vec<OutlineNode>,
+}
+
+/// Build the heading tree. A heading becomes a child of the nearest
+/// preceding heading with a lower level; skipped levels (h1 -> h3) nest
+/// directly.
+pub(crate) fn build_outline(content: &str) -> Vec<OutlineNode> {
+    let headings = slugged_headings(content);
+
+    let mut nodes: Vec<OutlineNode> = headings
+        .iter()
+        .enumerate()
+        .map(|(i, (h, slug))| {
+            let section_end = headings[i + 1..]
+                .iter()
+                .find(|(next, _)| next.level <= h.level)
+                .map_or(content.len(), |(next, _)| next.start);
+            OutlineNode {
+                level: h.level,
+                text: plain_heading_text(&h.text),
+                slug: slug.clone(),
+                start: h.start,
+                end: h.end,
+                section_end,
+                line: h.line,
+                children: Vec::new(),
+            }
+        })
+        .collect();
+
+    // Attach nodes to their parents, working backwards so each node's
+    // children are complete before it is moved
+    let mut roots = Vec::new();
+    while let Some(node) = nodes.pop() {
+        match nodes.iter_mut().rev().find(|parent| parent.level < node.level) {
+            Some(parent) => parent.children.insert(0, node),
+            None => roots.insert(0, node),
+        }
+    }
+    roots
+}
+
+/// Document outline as a JSON tree for the sidebar:
+/// `[{level, text, slug, start, end, section_end, line, children: [...]}]`
+#[wasm_bindgen]
+pub fn get_outline(content: &str) -> String {
+    serde_json::to_string(&build_outline(content)).unwrap_or_else(|_| "[]".to_string())
+}
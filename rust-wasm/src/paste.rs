@@ -0,0 +1,380 @@
+// Cleanup of rich text pasted from Word and Google Docs. Both wrap every
+// run of text in styled spans and mark formatting with inline CSS rather
+// than tags, and Word writes lists as paragraphs with a fake bullet, so the
+// node tree is tidied up before it reaches the HTML-to-markdown converter.
+// Plain-text pastes are classified instead, so a URL, a spreadsheet range,
+// JSON or code can be offered as the markdown that fits it.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::ast::blocks_to_markdown;
+use crate::document_import::{csv_records, csv_to_markdown};
+use crate::html::{nodes_to_blocks, parse_html, Node};
+use crate::sanitize::{sanitize, SanitizePolicy};
+use crate::url::{href_for, percent_decode, url_len};
+
+/// Telltale fragments of each language's code, with how strongly each
+/// points to it. Names are those the highlighter knows.
+const LANGUAGE_MARKERS: &[(&str, &[(&str, u32)])] = &[
+    ("rust", &[("fn ", 2), ("let mut ", 3), ("pub fn ", 3), ("impl ", 2), ("println!", 3), ("&str", 2), ("use std::", 3), ("Vec<", 2), ("-> ", 1), ("::", 1)]),
+    ("python", &[("def ", 3), ("elif ", 3), ("self.", 2), ("__init__", 3), (" in range(", 3), ("import ", 1), ("print(", 1), ("None", 1)]),
+    ("javascript", &[("function ", 2), ("const ", 2), ("=> ", 2), ("console.log", 3), ("require(", 3), ("===", 3), ("document.", 2), ("let ", 1), ("export ", 1)]),
+    ("go", &[("func ", 3), (":= ", 3), ("fmt.", 3), ("err != nil", 3), ("package ", 2)]),
+    ("c", &[("#include", 4), ("int main", 3), ("printf(", 3), ("std::", 3), ("nullptr", 3), ("->", 1)]),
+    ("java", &[("public class", 4), ("System.out", 4), ("public static void", 3), ("@Override", 3), ("private ", 2)]),
+    ("css", &[("px;", 3), ("margin:", 3), ("padding:", 3), ("display:", 3), ("color:", 2)]),
+    ("sql", &[("INSERT INTO", 4), ("CREATE TABLE", 4), ("SELECT ", 3), (" FROM ", 2), (" WHERE ", 2), ("JOIN ", 2)]),
+    ("bash", &[("#!/bin/", 5), ("sudo ", 3), (" | grep", 3), ("fi\n", 3), ("echo ", 2), ("then\n", 2), ("npm ", 2)]),
+    ("html", &[("<!DOCTYPE", 5), ("<div", 3), ("</", 2), ("<span", 2), ("<p>", 2)]),
+];
+
+/// Fragments that make JavaScript TypeScript
+const TYPESCRIPT_MARKERS: &[&str] = &[": string", ": number", ": boolean", "interface ", "export type "];
+
+/// A language score that settles it is code, even against a table
+const CERTAIN_CODE_SCORE: u32 = 4;
+
+/// File extensions a pasted URL is embedded as an image for
+const IMAGE_EXTENSIONS: &[&str] = &[".png", ".jpg", ".jpeg", ".gif", ".svg", ".webp", ".avif"];
+
+#[derive(Serialize)]
+struct PasteSuggestion {
+    /// "url", "table", "code", "json" or "prose"
+    kind: &'static str,
+    /// The code's language, when recognised
+    language: Option<&'static str>,
+    /// The link target of a URL, to wrap a selection in instead
+    href: Option<String>,
+    /// Markdown to insert in place of the pasted text; the text itself
+    /// for prose
+    suggestion: String,
+}
+
+/// Inline CSS declarations of an element, names lowercased
+fn styles(node: &Node) -> Vec<(String, String)> {
+    node.attr("style")
+        .unwrap_or("")
+        .split(';')
+        .filter_map(|decl| decl.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_ascii_lowercase()))
+        .collect()
+}
+
+fn style<'a>(styles: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    styles.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+fn is_bold(weight: &str) -> bool {
+    weight == "bold" || weight == "bolder" || weight.parse::<u32>().is_ok_and(|w| w >= 600)
+}
+
+/// Word's list marker span (`style="mso-list:Ignore"`), holding the bullet
+/// or number Word drew itself
+fn is_list_marker(node: &Node) -> bool {
+    style(&styles(node), "mso-list") == Some("ignore")
+}
+
+fn find_list_marker(node: &Node) -> Option<&Node> {
+    if is_list_marker(node) {
+        return Some(node);
+    }
+    node.children().iter().find_map(find_list_marker)
+}
+
+/// A Word list paragraph: its nesting level and whether it is numbered
+fn word_list_item(node: &Node) -> Option<(usize, bool)> {
+    if node.name() != "p" {
+        return None;
+    }
+    let styles = styles(node);
+    let list = style(&styles, "mso-list").filter(|l| *l != "ignore");
+    let class = node.attr("class").unwrap_or("");
+    if list.is_none() && !class.starts_with("MsoListParagraph") {
+        return None;
+    }
+    let level = list
+        .and_then(|l| l.split_whitespace().find_map(|part| part.strip_prefix("level")))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(1);
+    // "1." "a)" "iv." are numbers; "·", "o" and "§" are bullets
+    let marker = find_list_marker(node).map(|m| m.raw_text()).unwrap_or_default();
+    let marker = marker.trim_matches(|c: char| c.is_whitespace());
+    let ordered = marker.ends_with(['.', ')']) && marker[..marker.len() - 1].chars().all(|c| c.is_ascii_alphanumeric());
+    Some((level, ordered))
+}
+
+/// Nest Word list paragraphs (level, numbered, paragraph) into `<ul>` and
+/// `<ol>` elements
+fn build_list(items: &[(usize, bool, Node)]) -> Node {
+    let (base, ordered, _) = &items[0];
+    let mut lis: Vec<Node> = Vec::new();
+    let mut i = 0;
+    while i < items.len() {
+        let (level, _, paragraph) = &items[i];
+        if *level > *base && !lis.is_empty() {
+            let end = items[i..].iter().position(|(l, _, _)| l <= base).map_or(items.len(), |e| i + e);
+            if let Some(Node::Element { children, .. }) = lis.last_mut() {
+                children.push(build_list(&items[i..end]));
+            }
+            i = end;
+            continue;
+        }
+        lis.push(Node::Element { name: "li".to_string(), attrs: Vec::new(), children: paragraph.children().to_vec() });
+        i += 1;
+    }
+    let name = if *ordered { "ol" } else { "ul" };
+    Node::Element { name: name.to_string(), attrs: Vec::new(), children: lis }
+}
+
+/// Replace runs of Word list paragraphs among siblings with real lists
+fn group_word_lists(nodes: &[Node]) -> Vec<Node> {
+    let mut out = Vec::with_capacity(nodes.len());
+    let mut items: Vec<(usize, bool, Node)> = Vec::new();
+    let mut gap: Vec<Node> = Vec::new();
+    for node in nodes {
+        if let Some((level, ordered)) = word_list_item(node) {
+            gap.clear();
+            items.push((level, ordered, node.clone()));
+        } else if !items.is_empty() && matches!(node, Node::Text(t) if t.trim().is_empty()) {
+            // Source line breaks between list paragraphs
+            gap.push(node.clone());
+        } else {
+            if !items.is_empty() {
+                out.push(build_list(&std::mem::take(&mut items)));
+                out.append(&mut gap);
+            }
+            out.push(node.clone());
+        }
+    }
+    if !items.is_empty() {
+        out.push(build_list(&items));
+    }
+    out
+}
+
+/// Curly quotes and non-breaking spaces become their plain forms
+fn clean_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '‘' | '’' | '‚' | '‛' => '\'',
+            '“' | '”' | '„' | '‟' => '"',
+            '\u{a0}' => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+/// Whether a cleaned element has nothing worth keeping
+fn is_empty(children: &[Node]) -> bool {
+    children.iter().all(|c| match c {
+        Node::Text(t) => t.trim().is_empty(),
+        Node::Element { name, children, .. } => name != "img" && name != "hr" && is_empty(children),
+    })
+}
+
+/// Attributes of a kept element without Word's classes and `mso-` styles
+fn clean_attrs(attrs: &[(String, String)]) -> Vec<(String, String)> {
+    attrs
+        .iter()
+        .filter_map(|(key, value)| match key.as_str() {
+            "style" => {
+                let kept: Vec<&str> = value
+                    .split(';')
+                    .filter(|d| !d.trim().is_empty() && !d.trim().to_ascii_lowercase().starts_with("mso-"))
+                    .collect();
+                (!kept.is_empty()).then(|| (key.clone(), kept.join(";")))
+            }
+            "class" => {
+                let kept: Vec<&str> = value.split_whitespace().filter(|c| !c.starts_with("Mso")).collect();
+                (!kept.is_empty()).then(|| (key.clone(), kept.join(" ")))
+            }
+            _ => Some((key.clone(), value.clone())),
+        })
+        .collect()
+}
+
+fn element(name: &str, children: Vec<Node>) -> Node {
+    Node::Element { name: name.to_string(), attrs: Vec::new(), children }
+}
+
+fn clean_nodes(nodes: &[Node], in_link: bool, out: &mut Vec<Node>) {
+    for node in group_word_lists(nodes) {
+        clean_node(&node, in_link, out);
+    }
+}
+
+fn clean_node(node: &Node, in_link: bool, out: &mut Vec<Node>) {
+    let Node::Element { name, attrs, children } = node else {
+        if let Node::Text(text) = node {
+            out.push(Node::Text(clean_text(text)));
+        }
+        return;
+    };
+    if is_list_marker(node) || (name == "br" && node.has_class("Apple-interchange-newline")) {
+        return;
+    }
+    let styles = styles(node);
+    let mut inner = Vec::new();
+    clean_nodes(children, in_link || name == "a", &mut inner);
+    match name.as_str() {
+        // Google Docs wraps the whole paste in <b style="font-weight:normal">
+        "b" | "strong" if style(&styles, "font-weight").is_some_and(|w| !is_bold(w)) => out.extend(inner),
+        "span" | "font" => {
+            // Formatting carried only by CSS becomes real tags
+            let mut content = inner;
+            if style(&styles, "text-decoration").is_some_and(|d| d.contains("underline")) && !in_link {
+                content = vec![element("u", content)];
+            }
+            if style(&styles, "text-decoration").is_some_and(|d| d.contains("line-through")) {
+                content = vec![element("del", content)];
+            }
+            if style(&styles, "font-style") == Some("italic") {
+                content = vec![element("em", content)];
+            }
+            if style(&styles, "font-weight").is_some_and(is_bold) {
+                content = vec![element("strong", content)];
+            }
+            out.extend(content);
+        }
+        "p" | "div" if is_empty(&inner) => {}
+        "p" if node.has_class("MsoTitle") => out.push(element("h1", inner)),
+        "p" if node.has_class("MsoSubtitle") => out.push(element("h2", inner)),
+        _ => out.push(Node::Element { name: name.clone(), attrs: clean_attrs(attrs), children: inner }),
+    }
+}
+
+/// Convert HTML pasted from Word, Google Docs or a web page to markdown.
+/// Scripts and unsafe markup are stripped first; then `mso-` styles, Word
+/// classes and span soup are removed, bold, italic, underline and
+/// strikethrough set through inline CSS become markdown emphasis, Word's
+/// paragraph lists become real (nested) lists, empty paragraphs are
+/// dropped and curly quotes and non-breaking spaces are straightened.
+#[wasm_bindgen]
+pub fn clean_pasted_html(html: &str) -> String {
+    let nodes = parse_html(&sanitize(html, &SanitizePolicy::default()));
+    let mut cleaned = Vec::new();
+    clean_nodes(&nodes, false, &mut cleaned);
+    blocks_to_markdown(&nodes_to_blocks(&cleaned))
+}
+
+/// The language `text` reads as and how strongly, if any marker matched
+fn detect_language(text: &str) -> Option<(&'static str, u32)> {
+    let (language, score) = LANGUAGE_MARKERS
+        .iter()
+        .map(|(language, markers)| {
+            (*language, markers.iter().filter(|(marker, _)| text.contains(marker)).map(|(_, weight)| weight).sum::<u32>())
+        })
+        .max_by_key(|(_, score)| *score)?;
+    if score == 0 {
+        return None;
+    }
+    if language == "javascript" && TYPESCRIPT_MARKERS.iter().any(|marker| text.contains(marker)) {
+        return Some(("typescript", score));
+    }
+    Some((language, score))
+}
+
+/// How much `text` is shaped like code: the share of its lines ending in
+/// `;`, `{`, `}` or `:` (or opening with `}`), and of its characters that
+/// are brackets and operators
+fn code_shape(text: &str) -> (f64, f64) {
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    if lines.is_empty() {
+        return (0.0, 0.0);
+    }
+    let structured = lines.iter().filter(|l| l.ends_with([';', '{', '}', ':']) || l.starts_with('}')).count();
+    let visible = text.chars().filter(|c| !c.is_whitespace()).count().max(1);
+    let symbols = text.chars().filter(|c| "{}()[];=<>".contains(*c)).count();
+    (structured as f64 / lines.len() as f64, symbols as f64 / visible as f64)
+}
+
+/// The delimiter of a pasted spreadsheet range or CSV: every row with the
+/// same two or more columns. Commas and semicolons also need cells that
+/// don't start with a space, or every sentence with a comma would do.
+fn table_delimiter(text: &str) -> Option<char> {
+    ['\t', ',', ';'].into_iter().find(|&delimiter| {
+        let records = csv_records(text, delimiter);
+        let regular = records.len() >= 2 && records[0].len() >= 2 && records.iter().all(|r| r.len() == records[0].len());
+        regular
+            && (delimiter == '\t' || {
+                let cells: Vec<&String> = records.iter().flat_map(|r| r.iter().skip(1)).collect();
+                cells.iter().filter(|c| c.starts_with(' ')).count() * 2 < cells.len()
+            })
+    })
+}
+
+/// `code` as a fenced block, or inline code for one line unless `fenced`,
+/// with fences longer than any backtick run inside
+fn code_markdown(code: &str, language: Option<&str>, fenced: bool) -> String {
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    if !fenced && !code.contains('\n') {
+        let ticks = "`".repeat(longest_run + 1);
+        let pad = if code.starts_with('`') || code.ends_with('`') { " " } else { "" };
+        return format!("{}{}{}{}{}", ticks, pad, code, pad, ticks);
+    }
+    let fence = "`".repeat((longest_run + 1).max(3));
+    format!("{}{}\n{}\n{}", fence, language.unwrap_or(""), code.trim_end_matches(['\n', '\r']), fence)
+}
+
+/// Work out what pasted plain text is and the markdown it would be best
+/// inserted as, returned as JSON `{kind, language, href, suggestion}`:
+///
+/// - `url`: a lone URL, suggested as a link (`href` being its target, to
+///   link a selection instead) or, for an image file, an image
+/// - `json`: an object or array, suggested as a `json` code block
+/// - `table`: tab-separated (as spreadsheets copy) or CSV rows, suggested
+///   as a markdown table with the first row as header
+/// - `code`: recognised by its shape and language markers, suggested as a
+///   fenced block with the `language` when known, or inline code for one
+///   line
+/// - `prose`: anything else, including markdown, suggested unchanged
+#[wasm_bindgen]
+pub fn classify_paste(text: &str) -> String {
+    serde_json::to_string(&classify(text)).unwrap_or_else(|_| "{}".into())
+}
+
+fn classify(text: &str) -> PasteSuggestion {
+    let trimmed = text.trim();
+    let prose = PasteSuggestion { kind: "prose", language: None, href: None, suggestion: text.to_string() };
+    // Markdown already, with its own code blocks
+    if trimmed.is_empty() || trimmed.contains("```") {
+        return prose;
+    }
+
+    if url_len(trimmed) == Some(trimmed.len()) {
+        let href = href_for(trimmed);
+        let path = href.split(['?', '#']).next().unwrap_or("").to_ascii_lowercase();
+        let suggestion = if IMAGE_EXTENSIONS.iter().any(|ext| path.ends_with(ext)) {
+            format!("![]({})", href)
+        } else {
+            let label = trimmed.split_once("://").map_or(trimmed, |(_, rest)| rest).trim_end_matches('/');
+            format!("[{}]({})", percent_decode(label), href)
+        };
+        return PasteSuggestion { kind: "url", language: None, href: Some(href), suggestion };
+    }
+
+    if trimmed.starts_with(['{', '[']) && serde_json::from_str::<serde_json::Value>(trimmed).is_ok() {
+        return PasteSuggestion { kind: "json", language: Some("json"), href: None, suggestion: code_markdown(trimmed, Some("json"), true) };
+    }
+
+    let language = detect_language(text);
+    let score = language.map_or(0, |(_, score)| score);
+    let (structured, symbols) = code_shape(text);
+    let code_shaped = structured >= 0.5 && symbols >= 0.05;
+    if score < CERTAIN_CODE_SCORE && !code_shaped {
+        if let Some(delimiter) = table_delimiter(trimmed) {
+            let (table, _) = csv_to_markdown(trimmed, delimiter);
+            return PasteSuggestion { kind: "table", language: None, href: None, suggestion: table };
+        }
+    }
+
+    let is_code = score >= CERTAIN_CODE_SCORE || code_shaped || (score >= 2 && structured >= 0.3);
+    if is_code {
+        // A weak score names the language only when the shape agrees
+        let language = language.filter(|(_, score)| *score >= 2).map(|(language, _)| language);
+        let code = if trimmed.contains('\n') { text.trim_matches(['\n', '\r']) } else { trimmed };
+        return PasteSuggestion { kind: "code", language, href: None, suggestion: code_markdown(code, language, false) };
+    }
+    prose
+}
@@ -0,0 +1,253 @@
+// Unified-diff patches: store an edit as the changed lines plus a little
+// context, and replay it later onto the same or a drifted document,
+// independently of the CRDT history.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::diff::{diff_strs, DiffKind};
+
+/// Unchanged lines kept around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Leading and trailing context lines that may be ignored when a hunk
+/// doesn't match in full
+const MAX_FUZZ: usize = 2;
+
+const NO_NEWLINE: &str = "\\ No newline at end of file";
+
+/// One patch line: ' ' context, '-' removed or '+' added, with its text
+/// including the line terminator
+struct PatchLine<'a> {
+    tag: char,
+    text: &'a str,
+}
+
+fn write_line(patch: &mut String, tag: char, text: &str) {
+    patch.push(tag);
+    patch.push_str(text);
+    if !text.ends_with('\n') {
+        patch.push('\n');
+        patch.push_str(NO_NEWLINE);
+        patch.push('\n');
+    }
+}
+
+/// Hunk header range: 1-based start and line count, the start being the
+/// line before the hunk when it has no lines on that side
+fn header_range(start: usize, count: usize) -> String {
+    let start = if count == 0 { start } else { start + 1 };
+    if count == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, count)
+    }
+}
+
+/// Unified diff turning `old` into `new`, with three lines of context
+/// around each change. Empty when the texts are the same.
+#[wasm_bindgen]
+pub fn create_patch(old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    // Every line of both texts in order, each with the old and new line
+    // numbers before it
+    let mut lines: Vec<(PatchLine, usize, usize)> = Vec::new();
+    for op in diff_strs(&old_lines, &new_lines) {
+        match op.kind {
+            DiffKind::Equal => {
+                for (i, j) in op.old.zip(op.new) {
+                    lines.push((PatchLine { tag: ' ', text: old_lines[i] }, i, j));
+                }
+            }
+            DiffKind::Delete => {
+                for i in op.old {
+                    lines.push((PatchLine { tag: '-', text: old_lines[i] }, i, op.new.start));
+                }
+            }
+            DiffKind::Insert => {
+                for j in op.new {
+                    lines.push((PatchLine { tag: '+', text: new_lines[j] }, op.old.start, j));
+                }
+            }
+        }
+    }
+
+    let changes: Vec<usize> = (0..lines.len()).filter(|&n| lines[n].0.tag != ' ').collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+    let mut patch = String::from("--- old\n+++ new\n");
+    let mut first = 0;
+    while first < changes.len() {
+        // Changes closer than twice the context share a hunk
+        let mut last = first;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * CONTEXT_LINES + 1 {
+            last += 1;
+        }
+        let start = changes[first].saturating_sub(CONTEXT_LINES);
+        let end = (changes[last] + CONTEXT_LINES + 1).min(lines.len());
+        let hunk = &lines[start..end];
+        let old_count = hunk.iter().filter(|(l, _, _)| l.tag != '+').count();
+        let new_count = hunk.iter().filter(|(l, _, _)| l.tag != '-').count();
+        patch.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            header_range(hunk[0].1, old_count),
+            header_range(hunk[0].2, new_count)
+        ));
+        for (line, _, _) in hunk {
+            write_line(&mut patch, line.tag, line.text);
+        }
+        first = last + 1;
+    }
+    patch
+}
+
+struct Hunk<'a> {
+    /// 0-based line the hunk expects to start at in the document
+    old_start: usize,
+    lines: Vec<PatchLine<'a>>,
+}
+
+impl Hunk<'_> {
+    /// The lines the hunk expects and the lines it writes, dropping `fuzz`
+    /// context lines from each end
+    fn sides(&self, fuzz: usize) -> (Vec<String>, Vec<String>, usize) {
+        let lead = self.lines.iter().take(fuzz).take_while(|l| l.tag == ' ').count();
+        let trail = self.lines.iter().rev().take(fuzz).take_while(|l| l.tag == ' ').count();
+        let body = &self.lines[lead..self.lines.len() - trail.min(self.lines.len() - lead)];
+        let side = |skip: char| body.iter().filter(|l| l.tag != skip).map(|l| l.text.to_string()).collect();
+        (side('+'), side('-'), lead)
+    }
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+fn parse_patch(patch: &str) -> Result<Vec<Hunk<'_>>, String> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    // Lines still expected in the current hunk, old and new side
+    let mut remaining = (0, 0);
+    for (n, raw) in patch.split_inclusive('\n').enumerate() {
+        let line = raw.trim_end_matches(['\n', '\r']);
+        if let Some(header) = line.strip_prefix("@@ -") {
+            let mut fields = header.split(' ');
+            let old = fields.next().and_then(parse_range);
+            let new = fields.next().and_then(|f| f.strip_prefix('+')).and_then(parse_range);
+            let (Some((old_start, old_count)), Some((_, new_count))) = (old, new) else {
+                return Err(format!("bad hunk header on line {}", n + 1));
+            };
+            let old_start = if old_count == 0 { old_start } else { old_start.saturating_sub(1) };
+            hunks.push(Hunk { old_start, lines: Vec::new() });
+            remaining = (old_count, new_count);
+            continue;
+        }
+        if line.starts_with('\\') {
+            // The previous line has no terminator
+            if let Some(last) = hunks.last_mut().and_then(|h| h.lines.last_mut()) {
+                last.text = last.text.trim_end_matches(['\n', '\r']);
+            }
+            continue;
+        }
+        let Some(hunk) = hunks.last_mut() else { continue };
+        if remaining == (0, 0) {
+            continue;
+        }
+        // Some tools strip the space from blank context lines
+        let (tag, text) = match raw.chars().next() {
+            Some(tag @ (' ' | '-' | '+')) => (tag, &raw[1..]),
+            _ if line.is_empty() => (' ', raw),
+            _ => return Err(format!("unexpected line {} in hunk", n + 1)),
+        };
+        match tag {
+            ' ' => remaining = (remaining.0.saturating_sub(1), remaining.1.saturating_sub(1)),
+            '-' => remaining.0 = remaining.0.saturating_sub(1),
+            _ => remaining.1 = remaining.1.saturating_sub(1),
+        }
+        hunk.lines.push(PatchLine { tag, text });
+    }
+    if remaining != (0, 0) {
+        return Err("last hunk is truncated".into());
+    }
+    Ok(hunks)
+}
+
+#[derive(Serialize)]
+struct HunkResult {
+    /// 1-based hunk number in the patch
+    hunk: usize,
+    applied: bool,
+    /// 1-based line the hunk was applied at
+    line: Option<usize>,
+    /// Lines between where the hunk expected to apply and where it did
+    offset: isize,
+    /// Context lines ignored to make it match
+    fuzz: usize,
+}
+
+#[derive(Serialize)]
+struct PatchResult {
+    content: String,
+    clean: bool,
+    hunks: Vec<HunkResult>,
+}
+
+/// Apply a unified diff to `doc` as JSON `{content, clean, hunks}`. Each
+/// hunk is matched by its context and removed lines, at the line it names
+/// or the nearest place after the previous hunk where they match; failing
+/// that, up to two context lines at each end are ignored (fuzz). A hunk
+/// that matches nowhere is skipped and reported with `applied: false`, and
+/// `clean` is false, so the caller can decide whether to keep the result.
+#[wasm_bindgen]
+pub fn apply_patch(doc: &str, patch: &str) -> Result<String, JsValue> {
+    let hunks = parse_patch(patch).map_err(|e| JsValue::from_str(&format!("Invalid patch: {}", e)))?;
+    let lines: Vec<&str> = doc.split_inclusive('\n').collect();
+    let mut content = String::new();
+    let mut results = Vec::new();
+    // Document lines copied or replaced so far
+    let mut done = 0;
+    // How far from its named line the previous hunk applied
+    let mut drift: isize = 0;
+    for (n, hunk) in hunks.iter().enumerate() {
+        let found = (0..=MAX_FUZZ).find_map(|fuzz| {
+            let (expected, replacement, lead) = hunk.sides(fuzz);
+            let named = hunk.old_start + lead;
+            let target = (named as isize + drift).max(done as isize) as usize;
+            let matches_at = |at: usize| {
+                at + expected.len() <= lines.len() && expected.iter().zip(&lines[at..]).all(|(e, l)| e == l)
+            };
+            let at = (0..=lines.len()).find_map(|distance| {
+                let after = target + distance;
+                let before = target.checked_sub(distance).filter(|&b| b >= done && distance > 0);
+                [Some(after), before].into_iter().flatten().find(|&at| at <= lines.len() && matches_at(at))
+            })?;
+            Some((at, expected.len(), replacement, fuzz, named))
+        });
+        match found {
+            Some((at, removed, replacement, fuzz, named)) => {
+                content.extend(lines[done..at].iter().copied());
+                for line in replacement {
+                    content.push_str(&line);
+                }
+                results.push(HunkResult {
+                    hunk: n + 1,
+                    applied: true,
+                    line: Some(at + 1),
+                    offset: at as isize - named as isize,
+                    fuzz,
+                });
+                drift = at as isize - named as isize;
+                done = at + removed;
+            }
+            None => results.push(HunkResult { hunk: n + 1, applied: false, line: None, offset: 0, fuzz: 0 }),
+        }
+    }
+    content.extend(lines[done..].iter().copied());
+    let result = PatchResult { content, clean: results.iter().all(|r| r.applied), hunks: results };
+    Ok(serde_json::to_string(&result).unwrap_or_else(|_| "{}".into()))
+}
@@ -0,0 +1,473 @@
+// Typed message payloads. The wire format stays a map under
+// `payload.data`, but the common message kinds are built from and decoded
+// into structs, so a wrong type or an unexpected field is an error naming
+// the message kind instead of a value that silently reads as missing.
+// Kinds without a struct here decode as their raw map.
+
+use wasm_bindgen::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::access::ACL_MESSAGE_TYPES;
+use crate::comments::COMMENT_MESSAGE_TYPES;
+use crate::presence::PRESENCE_MESSAGE_TYPES;
+use crate::{decode_with_grid_tag, DocumentEdit, MessagePayload, PromiseGridMessage};
+
+/// Byte strings as CBOR byte strings rather than arrays of integers
+pub(crate) mod byte_string {
+    use serde::{Deserializer, Serializer};
+
+    struct Visitor;
+
+    impl serde::de::Visitor<'_> for Visitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a byte string")
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_byte_buf(Visitor)
+    }
+
+    /// The same for an optional field
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        #[derive(Deserialize)]
+        struct Wrapped(#[serde(with = "super")] Vec<u8>);
+
+        pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+            match bytes {
+                Some(bytes) => serializer.serialize_bytes(bytes),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+            Ok(Option::<Wrapped>::deserialize(deserializer)?.map(|w| w.0))
+        }
+    }
+}
+
+/// Fields any message may carry, added when it is encoded
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Envelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lamport: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// Sender's clock when the message was encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<f64>,
+    /// Capability token, added by `attach_capability_token`
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "byte_string::option")]
+    pub capability: Option<Vec<u8>>,
+    /// Token holder's signature of the message
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "byte_string::option")]
+    pub capability_signature: Option<Vec<u8>>,
+    /// Sender's signature of the message, added by `sign_message`
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "byte_string::option")]
+    pub sender_signature: Option<Vec<u8>>,
+}
+
+const ENVELOPE_FIELDS: &[&str] = &["message_id", "seq", "session", "lamport", "nonce", "sent_at", "capability", "capability_signature", "sender_signature"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DocumentStats {
+    pub document_id: String,
+    pub word_count: u32,
+    pub char_count: u32,
+    pub line_count: u32,
+    pub timestamp: f64,
+    pub user_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CursorUpdate {
+    pub document_id: String,
+    pub user_id: String,
+    /// Caret offset in bytes
+    pub offset: u64,
+    pub selection_start: u64,
+    pub selection_end: u64,
+    pub timestamp: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Presence {
+    pub document_id: String,
+    pub user_id: String,
+    pub timestamp: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Hex SHA-256 of the sender's avatar; see profile.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_hash: Option<String>,
+}
+
+/// The whole document at a version, for clients joining late
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Snapshot {
+    pub document_id: String,
+    pub user_id: String,
+    pub version: u64,
+    /// Gzip-compressed UTF-8 content
+    #[serde(with = "byte_string")]
+    pub content: Vec<u8>,
+    /// Hex SHA-256 of the uncompressed content
+    pub content_hash: String,
+    /// The document's properties as CBOR; see metadata.rs
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "byte_string::option")]
+    pub metadata: Option<Vec<u8>>,
+    pub timestamp: f64,
+}
+
+/// A joining client asking for a `document_snapshot`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SnapshotRequest {
+    pub document_id: String,
+    pub user_id: String,
+    pub timestamp: f64,
+}
+
+/// A client announcing the protocol versions and modes it supports; see
+/// handshake.rs. Each mode list is in order of preference.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Hello {
+    pub user_id: String,
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    pub compression: Vec<String>,
+    pub encryption: Vec<String>,
+    pub editing: Vec<String>,
+    pub timestamp: f64,
+}
+
+/// The answer to a `hello`: the modes chosen for the pair, or why the
+/// peers can't work together
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HelloAck {
+    pub user_id: String,
+    pub target_user_id: String,
+    /// The version chosen, or the responder's newest when rejecting
+    pub protocol_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editing: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub timestamp: f64,
+}
+
+/// An encoded message carried inside another, as a CBOR byte string
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ByteString(#[serde(with = "byte_string")] pub Vec<u8>);
+
+/// How far a client has got with one sender's session: every message up
+/// to `seq` has arrived
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SenderVersion {
+    pub user_id: String,
+    pub session: String,
+    pub seq: u64,
+}
+
+/// A reconnecting client's state vector; see sync.rs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SyncRequest {
+    pub user_id: String,
+    pub versions: Vec<SenderVersion>,
+    pub timestamp: f64,
+}
+
+/// The messages a `sync_request` was missing
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SyncResponse {
+    pub user_id: String,
+    pub target_user_id: String,
+    pub target_session: String,
+    /// The missing messages as originally encoded, oldest first
+    pub operations: Vec<ByteString>,
+    /// False when the responder no longer has some of what is missing
+    pub complete: bool,
+    /// True when more messages are left for another request
+    pub more: bool,
+    pub timestamp: f64,
+}
+
+/// A payload sealed with the document key; see encryption.rs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EncryptedPayload {
+    pub document_id: String,
+    /// The XChaCha20 nonce; `nonce` is the envelope's replay nonce
+    #[serde(with = "byte_string")]
+    pub aead_nonce: Vec<u8>,
+    /// The CBOR `{message_type, data}` of the original message, sealed
+    #[serde(with = "byte_string")]
+    pub ciphertext: Vec<u8>,
+}
+
+/// A document key wrapped for one recipient; see key_exchange.rs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KeyShare {
+    pub document_id: String,
+    /// Sender
+    pub user_id: String,
+    pub recipient_id: String,
+    /// Sender's one-off X25519 public key
+    #[serde(with = "byte_string")]
+    pub ephemeral_key: Vec<u8>,
+    /// The XChaCha20 nonce; `nonce` is the envelope's replay nonce
+    #[serde(with = "byte_string")]
+    pub aead_nonce: Vec<u8>,
+    #[serde(with = "byte_string")]
+    pub wrapped_key: Vec<u8>,
+    pub timestamp: f64,
+}
+
+/// An owner-signed `acl_grant`, `acl_role_change` or `acl_revoke`; see
+/// access.rs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AclChange {
+    pub document_id: String,
+    /// Owner who signed the change
+    pub user_id: String,
+    pub target_user_id: String,
+    /// "read", "comment" or "write"; absent from `acl_revoke`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    pub timestamp: f64,
+    #[serde(with = "byte_string")]
+    pub signature: Vec<u8>,
+}
+
+/// A `mention`: `user_id` mentioned `mentioned_user_id`; see mentions.rs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Mention {
+    pub document_id: String,
+    pub user_id: String,
+    pub mentioned_user_id: String,
+    /// Byte range of the mention, and its 1-based line
+    pub start: u64,
+    pub end: u64,
+    pub line: u64,
+    /// The mention's line, or up to 40 bytes either side of it
+    pub excerpt: String,
+    pub timestamp: f64,
+}
+
+/// A `word_goal`: the document's goal and how far `user_id` got towards
+/// it today; see goals.rs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WordGoal {
+    pub document_id: String,
+    pub user_id: String,
+    pub target: u64,
+    /// ms since epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<f64>,
+    /// Who set the goal and when, so the latest goal wins
+    pub set_by: String,
+    pub set_at: f64,
+    pub words_today: u64,
+    pub timestamp: f64,
+}
+
+/// A `comment_add`, `comment_reply`, `comment_resolve` or
+/// `comment_delete`; see comments.rs. Which optional fields are set depends
+/// on the type.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CommentChange {
+    pub document_id: String,
+    pub user_id: String,
+    pub comment_id: String,
+    /// The reply added, or deleted instead of the whole comment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Byte range commented on, with its text and context; `comment_add`
+    /// only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<bool>,
+    pub timestamp: f64,
+}
+
+/// Edits and cursor updates under one envelope; see batch.rs. Each
+/// operation's data leaves out the batch's `document_id` and `user_id`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Batch {
+    pub document_id: String,
+    pub user_id: String,
+    pub timestamp: f64,
+    pub operations: Vec<MessagePayload>,
+    /// Ed25519 signature of the batch without its envelope
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "byte_string::option")]
+    pub signature: Option<Vec<u8>>,
+}
+
+/// A decoded payload: a typed struct for the kinds that have one, the raw
+/// map for the rest
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum Payload {
+    DocumentEdit(DocumentEdit),
+    DocumentStats(DocumentStats),
+    CursorUpdate(CursorUpdate),
+    /// Any of the presence message types; the type gives the state
+    Presence(Presence),
+    Snapshot(Snapshot),
+    SnapshotRequest(SnapshotRequest),
+    Encrypted(EncryptedPayload),
+    KeyShare(KeyShare),
+    Batch(Batch),
+    SyncRequest(SyncRequest),
+    SyncResponse(SyncResponse),
+    /// Any of the ACL message types; the type gives the change
+    AclChange(AclChange),
+    Hello(Hello),
+    HelloAck(HelloAck),
+    /// Any of the comment message types; the type gives the change
+    CommentChange(CommentChange),
+    Mention(Mention),
+    WordGoal(WordGoal),
+    Other(HashMap<String, ciborium::Value>),
+}
+
+/// The data map for a typed payload, ready for `encode_promisegrid_payload`
+pub(crate) fn payload_data<T: Serialize>(payload: &T) -> HashMap<String, ciborium::Value> {
+    match ciborium::Value::serialized(payload) {
+        Ok(ciborium::Value::Map(fields)) => fields
+            .into_iter()
+            .filter_map(|(key, value)| match key {
+                ciborium::Value::Text(key) => Some((key, value)),
+                _ => None,
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+fn typed<T: DeserializeOwned>(message_type: &str, fields: Vec<(ciborium::Value, ciborium::Value)>) -> Result<T, String> {
+    ciborium::Value::Map(fields)
+        .deserialized()
+        .map_err(|e| format!("Invalid {} payload: {}", message_type, e))
+}
+
+/// Split a message's data into its envelope and typed payload
+pub(crate) fn decode_payload(message: &PromiseGridMessage) -> Result<(Envelope, Payload), String> {
+    let message_type = message.payload.message_type.as_str();
+    let (envelope, fields): (Vec<_>, Vec<_>) = message
+        .payload
+        .data
+        .iter()
+        .map(|(key, value)| (ciborium::Value::Text(key.clone()), value.clone()))
+        .partition(|(key, _)| matches!(key, ciborium::Value::Text(k) if ENVELOPE_FIELDS.contains(&k.as_str())));
+    let envelope: Envelope = typed(message_type, envelope)?;
+    let payload = match message_type {
+        "document_edit" => Payload::DocumentEdit(typed(message_type, fields)?),
+        "document_stats" => Payload::DocumentStats(typed(message_type, fields)?),
+        "cursor_update" => Payload::CursorUpdate(typed(message_type, fields)?),
+        "document_snapshot" => Payload::Snapshot(typed(message_type, fields)?),
+        "snapshot_request" => Payload::SnapshotRequest(typed(message_type, fields)?),
+        "encrypted" => Payload::Encrypted(typed(message_type, fields)?),
+        "key_share" => Payload::KeyShare(typed(message_type, fields)?),
+        "batch" => Payload::Batch(typed(message_type, fields)?),
+        "sync_request" => Payload::SyncRequest(typed(message_type, fields)?),
+        "sync_response" => Payload::SyncResponse(typed(message_type, fields)?),
+        "hello" => Payload::Hello(typed(message_type, fields)?),
+        "hello_ack" => Payload::HelloAck(typed(message_type, fields)?),
+        "mention" => Payload::Mention(typed(message_type, fields)?),
+        "word_goal" => Payload::WordGoal(typed(message_type, fields)?),
+        t if ACL_MESSAGE_TYPES.contains(&t) => Payload::AclChange(typed(message_type, fields)?),
+        t if COMMENT_MESSAGE_TYPES.contains(&t) => Payload::CommentChange(typed(message_type, fields)?),
+        t if PRESENCE_MESSAGE_TYPES.iter().any(|(_, p)| *p == t) => Payload::Presence(typed(message_type, fields)?),
+        _ => Payload::Other(message.payload.data.clone()),
+    };
+    Ok((envelope, payload))
+}
+
+#[derive(Serialize)]
+struct DecodedMessage<'a> {
+    protocol_hash: &'a str,
+    message_type: &'a str,
+    envelope: Envelope,
+    data: Payload,
+}
+
+/// Decode a PromiseGrid message into JSON `{protocol_hash, message_type,
+/// envelope, data}`, checking the payload of the typed kinds
+/// (`document_edit`, `document_stats`, `cursor_update`, presence,
+/// `document_snapshot`, `snapshot_request`, `encrypted`, `key_share`,
+/// `batch`, `sync_request`, `sync_response`, the ACL messages, `hello` and
+/// `hello_ack`): a missing field, a field of the wrong type or an unknown
+/// field is an error naming the message type and field.
+#[wasm_bindgen]
+pub fn decode_message(cbor_bytes: &[u8]) -> Result<String, JsValue> {
+    let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    decoded_json(&message).map_err(|e| JsValue::from_str(&e))
+}
+
+/// The JSON `decode_message` returns for an already parsed message
+pub(crate) fn decoded_json(message: &PromiseGridMessage) -> Result<String, String> {
+    let (envelope, data) = decode_payload(message)?;
+    let decoded = DecodedMessage {
+        protocol_hash: &message.protocol_hash,
+        message_type: &message.payload.message_type,
+        envelope,
+        data,
+    };
+    Ok(serde_json::to_string(&decoded).unwrap_or_else(|_| "{}".into()))
+}
@@ -0,0 +1,139 @@
+// Runtime performance profile. Slow devices switch to "reduced" or
+// "minimal" in one call and the expensive features check the active
+// profile instead of each needing its own switch.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::cell::Cell;
+
+use crate::activity::MAX_REVISIONS;
+
+pub(crate) const PERFORMANCE_PROFILES: &[&str] = &["full", "reduced", "minimal"];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Profile {
+    Full,
+    Reduced,
+    Minimal,
+}
+
+/// How much of the document the lint scheduler hands out
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LiveLint {
+    /// Visible, recently edited and (when idle) all other blocks
+    All,
+    /// Visible blocks only
+    Viewport,
+    Off,
+}
+
+thread_local! {
+    static PROFILE: Cell<Profile> = const { Cell::new(Profile::Full) };
+}
+
+/// The active profile
+pub(crate) fn current() -> Profile {
+    PROFILE.with(Cell::get)
+}
+
+impl Profile {
+    fn parse(name: &str) -> Option<Profile> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "full" => Some(Profile::Full),
+            "reduced" => Some(Profile::Reduced),
+            "minimal" => Some(Profile::Minimal),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn live_lint(self) -> LiveLint {
+        match self {
+            Profile::Full => LiveLint::All,
+            Profile::Reduced => LiveLint::Viewport,
+            Profile::Minimal => LiveLint::Off,
+        }
+    }
+
+    /// Sentence, vocabulary and overused-word analysis in `style_metrics`
+    pub(crate) fn readability(self) -> bool {
+        self != Profile::Minimal
+    }
+
+    /// Cursor movement trails and typing indicators for remote users
+    pub(crate) fn presence_animation(self) -> bool {
+        self == Profile::Full
+    }
+
+    /// Revision summaries kept per document
+    pub(crate) fn max_revisions(self) -> usize {
+        match self {
+            Profile::Full => MAX_REVISIONS,
+            Profile::Reduced => 20,
+            Profile::Minimal => 5,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Settings {
+    live_lint: LiveLint,
+    readability: bool,
+    presence_animation: bool,
+    max_revisions: usize,
+}
+
+#[derive(Serialize)]
+struct ProfileReport {
+    profile: Profile,
+    settings: Settings,
+    /// Features running below their full setting
+    degraded: Vec<&'static str>,
+}
+
+fn report(profile: Profile) -> String {
+    let full = Profile::Full;
+    let mut degraded = Vec::new();
+    if profile.live_lint() != full.live_lint() {
+        degraded.push("live_lint");
+    }
+    if profile.readability() != full.readability() {
+        degraded.push("readability");
+    }
+    if profile.presence_animation() != full.presence_animation() {
+        degraded.push("presence_animation");
+    }
+    if profile.max_revisions() != full.max_revisions() {
+        degraded.push("history");
+    }
+    let report = ProfileReport {
+        profile,
+        settings: Settings {
+            live_lint: profile.live_lint(),
+            readability: profile.readability(),
+            presence_animation: profile.presence_animation(),
+            max_revisions: profile.max_revisions(),
+        },
+        degraded,
+    };
+    serde_json::to_string(&report).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// Switch every expensive feature to the "full", "reduced" or "minimal"
+/// profile. Returns the new profile as `performance_profile` does.
+#[wasm_bindgen]
+pub fn set_performance_profile(name: &str) -> Result<String, JsValue> {
+    let profile = Profile::parse(name).ok_or_else(|| {
+        JsValue::from_str(&format!("Unknown performance profile: {} (expected {})", name, PERFORMANCE_PROFILES.join(", ")))
+    })?;
+    PROFILE.with(|p| p.set(profile));
+    Ok(report(profile))
+}
+
+/// The active profile as JSON: `{profile, settings: {live_lint, readability,
+/// presence_animation, max_revisions}, degraded: [feature]}`
+#[wasm_bindgen]
+pub fn performance_profile() -> String {
+    report(current())
+}
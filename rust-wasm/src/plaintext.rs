@@ -0,0 +1,200 @@
+// Plain-text export for email and the clipboard: the document tree with
+// every bit of markdown syntax removed. Link text, list items, image alt
+// text and code are kept; how links and headings show is configurable.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{indent_lines, parse_markdown, Block, Footnotes, Inline, ListItem};
+use crate::html::html_to_document;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LinkStyle {
+    /// Link text only
+    Text,
+    /// Link text followed by the URL in parentheses
+    TextUrl,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HeadingStyle {
+    Plain,
+    Uppercase,
+    /// Underlined with `=` (level 1) or `-` (deeper levels)
+    Underlined,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct PlaintextOptions {
+    /// "text" or "text_url" (default)
+    pub links: LinkStyle,
+    /// "plain", "uppercase" or "underlined" (default)
+    pub headings: HeadingStyle,
+}
+
+impl Default for PlaintextOptions {
+    fn default() -> Self {
+        PlaintextOptions { links: LinkStyle::TextUrl, headings: HeadingStyle::Underlined }
+    }
+}
+
+struct Writer<'a> {
+    options: &'a PlaintextOptions,
+    footnotes: &'a Footnotes<'a>,
+}
+
+impl Writer<'_> {
+    fn inlines(&mut self, inlines: &[Inline]) -> String {
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text { text } | Inline::Code { code: text } | Inline::Math { tex: text } => out.push_str(text),
+                Inline::Emphasis { content }
+                | Inline::Strong { content }
+                | Inline::Strikethrough { content }
+                | Inline::Underline { content } => out.push_str(&self.inlines(content)),
+                Inline::Link { url, content, .. } => {
+                    let text = self.inlines(content);
+                    let bare = url.strip_prefix("mailto:").unwrap_or(url);
+                    if self.options.links == LinkStyle::TextUrl && !url.is_empty() && !url.starts_with('#') && bare != text {
+                        out.push_str(&format!("{} ({})", text, url));
+                    } else {
+                        out.push_str(&text);
+                    }
+                }
+                Inline::Image { alt, .. } => out.push_str(alt),
+                Inline::WikiLink { target, alias, .. } => out.push_str(alias.as_deref().unwrap_or(target)),
+                Inline::FootnoteRef { label } => {
+                    if let Some(n) = self.footnotes.number(label) {
+                        out.push_str(&format!("[{}]", n));
+                    }
+                }
+                Inline::Html { .. } => {}
+                Inline::SoftBreak | Inline::LineBreak => out.push('\n'),
+            }
+        }
+        out
+    }
+
+    fn heading(&mut self, level: u8, content: &[Inline]) -> String {
+        let text = self.inlines(content).replace('\n', " ");
+        match self.options.headings {
+            HeadingStyle::Plain => text,
+            HeadingStyle::Uppercase => text.to_uppercase(),
+            HeadingStyle::Underlined => {
+                let rule = if level == 1 { "=" } else { "-" };
+                format!("{}\n{}", text, rule.repeat(text.chars().count().max(3)))
+            }
+        }
+    }
+
+    fn item(&mut self, item: &ListItem, marker: &str) -> String {
+        let checkbox = match item.checked {
+            Some(true) => "[x] ",
+            Some(false) => "[ ] ",
+            None => "",
+        };
+        // A nested list follows its item's text without a blank line
+        let mut body = String::new();
+        for block in &item.blocks {
+            if let Some(text) = self.block(block) {
+                if !body.is_empty() {
+                    body.push_str(if matches!(block, Block::List { .. }) { "\n" } else { "\n\n" });
+                }
+                body.push_str(&text);
+            }
+        }
+        indent_lines(&format!("{}{}", checkbox, body), marker, &" ".repeat(marker.chars().count()))
+    }
+
+    fn table(&mut self, header: &[Vec<Inline>], rows: &[Vec<Vec<Inline>>]) -> String {
+        let mut cells: Vec<Vec<String>> = Vec::new();
+        cells.push(header.iter().map(|c| self.inlines(c).replace('\n', " ")).collect());
+        for row in rows {
+            cells.push(row.iter().map(|c| self.inlines(c).replace('\n', " ")).collect());
+        }
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|n| cells.iter().filter_map(|r| r.get(n)).map(|c| c.chars().count()).max().unwrap_or(0))
+            .collect();
+        let line = |row: &[String]| {
+            let padded: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(n, &w)| {
+                    let cell = row.get(n).map_or("", String::as_str);
+                    format!("{}{}", cell, " ".repeat(w - cell.chars().count()))
+                })
+                .collect();
+            padded.join("  ").trim_end().to_string()
+        };
+        let mut out = vec![line(&cells[0])];
+        out.push(widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>().join("  "));
+        out.extend(cells[1..].iter().map(|r| line(r)));
+        out.join("\n")
+    }
+
+    fn block(&mut self, block: &Block) -> Option<String> {
+        Some(match block {
+            Block::Heading { level, content } => self.heading(*level, content),
+            Block::Paragraph { content } => self.inlines(content),
+            Block::CodeBlock { code, .. } => code.clone(),
+            Block::BlockQuote { blocks } => indent_lines(&self.blocks(blocks), "> ", "> "),
+            Block::List { ordered, start, items } => {
+                let items: Vec<String> = items
+                    .iter()
+                    .enumerate()
+                    .map(|(n, item)| {
+                        let marker = if *ordered { format!("{}. ", start + n as u64) } else { "• ".to_string() };
+                        self.item(item, &marker)
+                    })
+                    .collect();
+                items.join("\n")
+            }
+            Block::Table { header, rows, .. } => self.table(header, rows),
+            Block::ThematicBreak => "* * *".to_string(),
+            Block::Html { html } => self.blocks(&html_to_document(html).blocks),
+            Block::MathBlock { tex } => tex.clone(),
+            // Listed at the end of the document
+            Block::FootnoteDefinition { .. } => return None,
+        })
+        .filter(|text| !text.trim().is_empty())
+    }
+
+    fn blocks(&mut self, blocks: &[Block]) -> String {
+        blocks.iter().filter_map(|b| self.block(b)).collect::<Vec<_>>().join("\n\n")
+    }
+}
+
+/// Export markdown as plain text for email or the clipboard. All markdown
+/// syntax is removed: emphasis markers go, links keep their text, images
+/// their alt text and lists their items (with `•` bullets and numbers),
+/// tables are aligned in columns and footnotes are numbered `[1]` and
+/// listed at the end. Front matter is dropped. `options` is a JSON object
+/// `{links: "text" | "text_url", headings: "plain" | "uppercase" |
+/// "underlined"}`; an empty string uses the defaults (URLs in parentheses
+/// after the link text, underlined headings).
+#[wasm_bindgen]
+pub fn export_plaintext(content: &str, options: &str) -> Result<String, JsValue> {
+    let options: PlaintextOptions = if options.trim().is_empty() {
+        PlaintextOptions::default()
+    } else {
+        serde_json::from_str(options).map_err(|e| JsValue::from_str(&format!("Invalid plaintext options: {}", e)))?
+    };
+    let doc = parse_markdown(content);
+    let footnotes = Footnotes::new(&doc.blocks);
+    let mut writer = Writer { options: &options, footnotes: &footnotes };
+    let mut out = writer.blocks(&doc.blocks);
+    for (n, _, body) in footnotes.iter() {
+        let marker = format!("[{}] ", n);
+        out.push_str(if n == 1 { "\n\n" } else { "\n" });
+        out.push_str(&indent_lines(&writer.blocks(body), &marker, &" ".repeat(marker.len())));
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
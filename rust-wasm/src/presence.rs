@@ -0,0 +1,187 @@
+// Who is in a document right now: presence messages for joining, leaving,
+// going idle and coming back, and a tracker that builds the participant
+// list from them. Any presence message doubles as a heartbeat; users not
+// heard from within the timeout are dropped, so a closed laptop doesn't
+// leave a ghost in the list.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::payload::{decode_payload, payload_data, Payload, Presence};
+use crate::performance;
+use crate::profile::assign_user_color;
+use crate::{decode_with_grid_tag, encode_promisegrid_payload};
+
+/// Presence message types, by the state they announce
+pub(crate) const PRESENCE_MESSAGE_TYPES: &[(&str, &str)] = &[
+    ("join", "presence_join"),
+    ("leave", "presence_leave"),
+    ("idle", "presence_idle"),
+    ("active", "presence_active"),
+];
+
+/// How long a user stays listed without a heartbeat, by default (ms)
+const DEFAULT_HEARTBEAT_TIMEOUT_MS: f64 = 30_000.0;
+
+/// How recently an `active` message must have come in for the user to
+/// show as typing (ms)
+const TYPING_WINDOW_MS: f64 = 3_000.0;
+
+/// Create a presence message announcing `state` ("join", "leave", "idle"
+/// or "active") for `user_id` in `document_id`. Send "active" as the
+/// heartbeat while the user is around. `name` is shown in the participant
+/// list and may be empty.
+#[wasm_bindgen]
+pub fn create_presence_message(document_id: &str, user_id: &str, state: &str, name: &str) -> Result<Vec<u8>, JsValue> {
+    let (_, message_type) = PRESENCE_MESSAGE_TYPES.iter().find(|(s, _)| *s == state).ok_or_else(|| {
+        JsValue::from_str(&format!("Unknown presence state: {} (expected join, leave, idle or active)", state))
+    })?;
+    let presence = Presence {
+        document_id: document_id.to_string(),
+        user_id: user_id.to_string(),
+        timestamp: js_sys::Date::now(),
+        name: Some(name.to_string()).filter(|n| !n.is_empty()),
+        avatar_hash: None,
+    };
+    Ok(encode_promisegrid_payload(message_type, payload_data(&presence)))
+}
+
+struct Participant {
+    name: String,
+    avatar_hash: String,
+    /// "active" or "idle"
+    state: &'static str,
+    joined: f64,
+    /// When we last heard from them, by our clock
+    last_seen: f64,
+    /// When they were last active, by our clock
+    last_active: f64,
+}
+
+#[derive(Serialize)]
+struct ParticipantInfo<'a> {
+    user_id: &'a str,
+    name: &'a str,
+    /// Empty when they haven't sent one
+    avatar_hash: &'a str,
+    /// See `assign_user_color`
+    color: String,
+    state: &'static str,
+    joined: f64,
+    last_seen: f64,
+    /// Active within the last few seconds; always false when the
+    /// performance profile turns presence animation off
+    typing: bool,
+}
+
+/// Tracks the participants of a document from incoming presence messages.
+/// Times are the receiver's clock (`now`), so senders' clocks don't need
+/// to agree.
+#[wasm_bindgen]
+pub struct PresenceTracker {
+    timeout_ms: f64,
+    participants: BTreeMap<String, Participant>,
+}
+
+#[wasm_bindgen]
+impl PresenceTracker {
+    /// `timeout_ms` of 0 uses the default of 30 seconds
+    #[wasm_bindgen(constructor)]
+    pub fn new(timeout_ms: f64) -> PresenceTracker {
+        PresenceTracker {
+            timeout_ms: if timeout_ms > 0.0 { timeout_ms } else { DEFAULT_HEARTBEAT_TIMEOUT_MS },
+            participants: BTreeMap::new(),
+        }
+    }
+
+    /// Apply a presence message received at `now`. Returns false for
+    /// messages of other types, which are ignored.
+    pub fn ingest(&mut self, cbor_bytes: &[u8], now: f64) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let Some((state, _)) = PRESENCE_MESSAGE_TYPES.iter().find(|(_, t)| *t == message.payload.message_type) else {
+            return Ok(false);
+        };
+        let (_, Payload::Presence(presence)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Ok(false);
+        };
+        self.apply(state, &presence, now);
+        Ok(true)
+    }
+
+    /// Drop users not heard from within the timeout. Returns a JSON array
+    /// of the user ids removed.
+    pub fn expire(&mut self, now: f64) -> String {
+        let expired: Vec<String> = self
+            .participants
+            .iter()
+            .filter(|(_, p)| now - p.last_seen > self.timeout_ms)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.participants.remove(id);
+        }
+        serde_json::to_string(&expired).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// The current participants as a JSON array of `{user_id, name,
+    /// avatar_hash, color, state, joined, last_seen, typing}`, by user id. Users past the
+    /// timeout are left out even before `expire` removes them.
+    pub fn participants(&self, now: f64) -> String {
+        let animate = performance::current().presence_animation();
+        let list: Vec<ParticipantInfo> = self
+            .participants
+            .iter()
+            .filter(|(_, p)| now - p.last_seen <= self.timeout_ms)
+            .map(|(id, p)| ParticipantInfo {
+                user_id: id,
+                name: &p.name,
+                avatar_hash: &p.avatar_hash,
+                color: assign_user_color(id),
+                state: p.state,
+                joined: p.joined,
+                last_seen: p.last_seen,
+                typing: animate && p.state == "active" && now - p.last_active <= TYPING_WINDOW_MS,
+            })
+            .collect();
+        serde_json::to_string(&list).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// Number of users listed, expired or not
+    pub fn count(&self) -> usize {
+        self.participants.len()
+    }
+}
+
+impl PresenceTracker {
+    fn apply(&mut self, state: &str, presence: &Presence, now: f64) {
+        let user_id = presence.user_id.as_str();
+        if state == "leave" {
+            self.participants.remove(user_id);
+            return;
+        }
+        let participant = self.participants.entry(user_id.to_string()).or_insert_with(|| Participant {
+            name: String::new(),
+            avatar_hash: String::new(),
+            state: "active",
+            joined: now,
+            last_seen: now,
+            last_active: f64::NEG_INFINITY,
+        });
+        if let Some(name) = &presence.name {
+            participant.name = name.clone();
+        }
+        if let Some(avatar_hash) = &presence.avatar_hash {
+            participant.avatar_hash = avatar_hash.clone();
+        }
+        participant.last_seen = now;
+        match state {
+            "idle" => participant.state = "idle",
+            "active" => {
+                participant.state = "active";
+                participant.last_active = now;
+            }
+            _ => participant.state = "active",
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// Who a user is to everyone else: a display name, an avatar by content
+// hash, and a color worked out from the user id alone, so every client
+// draws the same person's caret, selections and comments in the same
+// color without agreeing on anything.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::payload::{decode_payload, payload_data, Payload, Presence};
+use crate::presence::PRESENCE_MESSAGE_TYPES;
+use crate::{decode_with_grid_tag, encode_promisegrid_payload, hash_chain};
+
+/// Hues a user color can take, evenly around the wheel
+const HUE_STEPS: u32 = 24;
+const SATURATIONS: [f64; 3] = [0.65, 0.75, 0.85];
+const LIGHTNESSES: [f64; 3] = [0.38, 0.45, 0.52];
+
+/// Yellows and greens look much lighter than blues at the same HSL
+/// lightness; they're darkened by this much to read on a white page
+const BRIGHT_HUE_DARKENING: f64 = 0.10;
+
+/// Hue, saturation and lightness of a user's color
+pub(crate) fn user_hsl(user_id: &str) -> (f64, f64, f64) {
+    let digest = Sha256::digest(user_id.as_bytes());
+    let n = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+    let hue = (n % HUE_STEPS) as f64 * 360.0 / HUE_STEPS as f64;
+    let saturation = SATURATIONS[digest[4] as usize % SATURATIONS.len()];
+    let mut lightness = LIGHTNESSES[digest[5] as usize % LIGHTNESSES.len()];
+    if (45.0..=200.0).contains(&hue) {
+        lightness -= BRIGHT_HUE_DARKENING;
+    }
+    (hue, saturation, lightness)
+}
+
+fn hsl_to_hex((hue, saturation, lightness): (f64, f64, f64)) -> String {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+}
+
+/// The color for `user_id` as `#rrggbb`: one of 24 evenly spread hues at
+/// one of three saturations and lightnesses, picked by hashing the id, so
+/// it's the same on every client and neighbours in a session rarely
+/// clash. Dark enough for text and carets on a light background.
+#[wasm_bindgen]
+pub fn assign_user_color(user_id: &str) -> String {
+    hsl_to_hex(user_hsl(user_id))
+}
+
+/// A user's public identity
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserProfile {
+    user_id: String,
+    display_name: String,
+    /// Hex SHA-256 of the avatar image, to fetch it by content; empty for
+    /// none
+    avatar_hash: String,
+}
+
+#[wasm_bindgen]
+impl UserProfile {
+    #[wasm_bindgen(constructor)]
+    pub fn new(user_id: &str, display_name: &str) -> UserProfile {
+        UserProfile { user_id: user_id.to_string(), display_name: display_name.to_string(), avatar_hash: String::new() }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn user_id(&self) -> String {
+        self.user_id.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn display_name(&self) -> String {
+        self.display_name.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_display_name(&mut self, display_name: &str) {
+        self.display_name = display_name.to_string();
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn avatar_hash(&self) -> String {
+        self.avatar_hash.clone()
+    }
+
+    /// Use the image `avatar` as the avatar, by its hash; empty clears it
+    pub fn set_avatar(&mut self, avatar: &[u8]) {
+        self.avatar_hash = if avatar.is_empty() { String::new() } else { hash_chain::to_hex(&Sha256::digest(avatar)) };
+    }
+
+    /// The user's color; see `assign_user_color`
+    #[wasm_bindgen(getter)]
+    pub fn color(&self) -> String {
+        assign_user_color(&self.user_id)
+    }
+
+    /// A presence message announcing `state` ("join", "leave", "idle" or
+    /// "active") in `document_id` that carries the profile
+    pub fn presence_message(&self, document_id: &str, state: &str) -> Result<Vec<u8>, JsValue> {
+        let (_, message_type) = PRESENCE_MESSAGE_TYPES.iter().find(|(s, _)| *s == state).ok_or_else(|| {
+            JsValue::from_str(&format!("Unknown presence state: {} (expected join, leave, idle or active)", state))
+        })?;
+        let presence = Presence {
+            document_id: document_id.to_string(),
+            user_id: self.user_id.clone(),
+            timestamp: js_sys::Date::now(),
+            name: Some(self.display_name.clone()).filter(|n| !n.is_empty()),
+            avatar_hash: Some(self.avatar_hash.clone()).filter(|h| !h.is_empty()),
+        };
+        Ok(encode_promisegrid_payload(message_type, payload_data(&presence)))
+    }
+
+    /// The profile a presence message carries
+    pub fn from_presence_message(cbor_bytes: &[u8]) -> Result<UserProfile, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let (_, Payload::Presence(presence)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Err(JsValue::from_str(&format!("Expected a presence message, got {}", message.payload.message_type)));
+        };
+        Ok(UserProfile {
+            user_id: presence.user_id,
+            display_name: presence.name.unwrap_or_default(),
+            avatar_hash: presence.avatar_hash.unwrap_or_default(),
+        })
+    }
+
+    /// The profile as JSON `{user_id, display_name, avatar_hash, color}`
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct View<'a> {
+            #[serde(flatten)]
+            profile: &'a UserProfile,
+            color: String,
+        }
+        serde_json::to_string(&View { profile: self, color: self.color() }).unwrap_or_else(|_| "{}".into())
+    }
+
+    /// Serialize the profile to CBOR bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore a profile from `to_bytes` output
+    pub fn from_bytes(bytes: &[u8]) -> Result<UserProfile, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
@@ -1,173 +1,546 @@
-// Cargo.toml dependencies you'll need:
-// [dependencies]
-// wasm-bindgen = "0.2"
-// serde = { version = "1.0", features = ["derive"] }
-// serde_cbor = "0.11"
-// web-sys = "0.3"
-// js-sys = "0.3"
+// Per-client PromiseGrid session state: the messages this client sent,
+// those still waiting on acknowledgements, recent ones kept to answer
+// resend requests and sync requests, the Lamport clock that orders them,
+// the reconnect sync in progress, what was agreed with each peer in the
+// handshake, the application's handlers for incoming messages, and
+// transport statistics. Message encoding itself lives in lib.rs and is
+// shared with the free functions.
 
 use wasm_bindgen::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-// When the `wee_alloc` feature is enabled, use `wee_alloc` as the global allocator
-#[cfg(feature = "wee_alloc")]
-#[global_allocator]
-static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
-
-// PromiseGrid message structure
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PromiseGridMessage {
-    pub protocol_hash: String,  // CID identifying the protocol spec
-    pub payload: MessagePayload,
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::outbound::{DeliveryFailure, OutboundQueue, DEFAULT_MAX_ATTEMPTS, DEFAULT_RETRY_BASE_MS};
+use crate::clock::{self, LamportClock};
+use crate::handshake::{self, HandshakeOptions, PeerState};
+use crate::logger;
+use crate::snapshot;
+use crate::payload::{decode_payload, decoded_json, payload_data, ByteString, Payload, SyncRequest, SyncResponse};
+use crate::sequence::{resend_request_from, session_id};
+use crate::sync::SyncLog;
+use crate::transport_stats::{precise_now, QueueDepth, StatsCollector};
+use crate::validate;
+use crate::{
+    data_text, data_u64, decode_with_grid_tag, encode_promisegrid_payload, encode_with_grid_tag, parse_promisegrid_message,
+    PromiseGridMessage,
+};
+
+/// Sent messages kept to answer resend requests
+const SENT_LOG_LIMIT: usize = 500;
+
+/// Message types that are never acknowledged, so never retried
+const UNACKED_MESSAGE_TYPES: &[&str] = &["ack", "cursor_update", "presence_active", "hello", "hello_ack"];
+
+/// How long to wait for a `sync_response` before asking again (ms)
+const SYNC_TIMEOUT_MS: f64 = 5_000.0;
+
+/// Sync requests sent before giving up
+const SYNC_MAX_ATTEMPTS: u32 = 3;
+
+/// Where a reconnect sync stands
+#[derive(Clone, Copy, PartialEq)]
+enum SyncState {
+    Idle,
+    Awaiting { sent_at: f64, attempts: u32 },
+    /// No response after the last attempt
+    Failed,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct MessagePayload {
-    pub message_type: String,
-    pub data: HashMap<String, serde_cbor::Value>,
+#[derive(Serialize)]
+struct SyncResult {
+    /// "complete", "partial" (more to fetch: call `start_sync` again after
+    /// applying), "incomplete" (the responder no longer has everything:
+    /// load a snapshot) or "ignored" (not for us, or not awaited)
+    status: &'static str,
+    /// Messages queued for `next_sync_operation`
+    operations: usize,
 }
 
-// Document edit message for collab-editor
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct DocumentEdit {
-    pub document_id: String,
-    pub edit_type: String,  // "insert", "delete", "replace"
-    pub position: u32,
-    pub content: String,
-    pub timestamp: f64, // each agent runs their own clock.  we can not garentee sync.  
-    pub user_id: String,
+#[derive(Serialize)]
+struct QueueStatus {
+    pending: usize,
+    failures: Vec<DeliveryFailure>,
 }
 
 #[wasm_bindgen]
 pub struct PromiseGridHandler {
-    protocol_hash: String,
+    user_id: String,
+    outbound: OutboundQueue,
+    /// Recently sent messages by sequence number, oldest first
+    sent: VecDeque<(u64, Vec<u8>)>,
+    resend: VecDeque<Vec<u8>>,
+    sync_log: SyncLog,
+    sync: SyncState,
+    /// Messages from a sync response, waiting to be applied
+    synced: VecDeque<Vec<u8>>,
+    handshake: HandshakeOptions,
+    /// Handshake outcome by peer user id
+    peers: BTreeMap<String, PeerState>,
+    /// Callbacks for `dispatch` by message type; "*" catches the rest
+    handlers: HashMap<String, js_sys::Function>,
+    /// Updated from `&self` methods too, hence the cell
+    stats: RefCell<StatsCollector>,
+    clock: LamportClock,
 }
 
 #[wasm_bindgen]
 impl PromiseGridHandler {
     #[wasm_bindgen(constructor)]
-    pub fn new() -> PromiseGridHandler {
-        console_error_panic_hook::set_once();
-        
+    pub fn new(user_id: &str) -> PromiseGridHandler {
         PromiseGridHandler {
-            // For now, use a placeholder protocol hash
-            // In real implementation, this would be the actual CID of the protocol spec
-            protocol_hash: "QmX1eVtVcs7YHr8L8cj9F4K2Hn7VqY9Z3B4A5C6D7E8F9".to_string(),
+            user_id: user_id.to_string(),
+            outbound: OutboundQueue::new(DEFAULT_RETRY_BASE_MS, DEFAULT_MAX_ATTEMPTS),
+            sent: VecDeque::new(),
+            resend: VecDeque::new(),
+            sync_log: SyncLog::default(),
+            sync: SyncState::Idle,
+            synced: VecDeque::new(),
+            handshake: HandshakeOptions::default(),
+            peers: BTreeMap::new(),
+            handlers: HashMap::new(),
+            stats: RefCell::new(StatsCollector::default()),
+            clock: LamportClock::default(),
         }
     }
 
     /// Create a PromiseGrid message for a document edit
-    #[wasm_bindgen]
-    pub fn create_edit_message(&self, 
-        document_id: &str, 
-        edit_type: &str,
-        position: u32,
-        content: &str,
-        user_id: &str
-    ) -> Result<Vec<u8>, JsValue> {
-        
-        let timestamp = js_sys::Date::now();
-        
-        let edit = DocumentEdit {
-            document_id: document_id.to_string(),
-            edit_type: edit_type.to_string(),
-            position,
-            content: content.to_string(),
-            timestamp,
-            user_id: user_id.to_string(),
-        };
+    pub fn create_edit_message(&self, document_id: &str, edit_type: &str, position: u32, content: &str) -> Vec<u8> {
+        self.encode("document_edit", || crate::edit_data(document_id, edit_type, position, content, &self.user_id))
+    }
 
-        // Convert edit to generic data map
-        let mut data = HashMap::new();
-        data.insert("document_id".to_string(), serde_cbor::Value::Text(edit.document_id));
-        data.insert("edit_type".to_string(), serde_cbor::Value::Text(edit.edit_type));
-        data.insert("position".to_string(), serde_cbor::Value::Integer(position as i128));
-        data.insert("content".to_string(), serde_cbor::Value::Text(edit.content));
-        data.insert("timestamp".to_string(), serde_cbor::Value::Float(timestamp));
-        data.insert("user_id".to_string(), serde_cbor::Value::Text(edit.user_id));
-
-        let payload = MessagePayload {
-            message_type: "document_edit".to_string(),
-            data,
+    /// Create a `document_snapshot` of `content` at Lamport time
+    /// `version`, to answer a joining client's request
+    pub fn create_snapshot_message(&self, document_id: &str, content: &str, version: u64) -> Vec<u8> {
+        self.encode("document_snapshot", || snapshot::snapshot_data(document_id, content, version, &self.user_id, None))
+    }
+
+    /// The document id a received `snapshot_request` from another client
+    /// asks for, or `undefined` for other messages. Answer it with
+    /// `create_snapshot_message`.
+    pub fn snapshot_requested(&self, cbor_bytes: &[u8]) -> Result<Option<String>, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        Ok(snapshot::requested_document(&message, &self.user_id))
+    }
+
+    /// Have `dispatch` call `callback(message, cbor_bytes)` for messages of
+    /// `message_type`, where `message` is the object `decode_message`
+    /// describes. "*" registers a fallback for types with no handler of
+    /// their own. Replaces any handler already registered for the type.
+    pub fn register_handler(&mut self, message_type: &str, callback: js_sys::Function) {
+        self.handlers.insert(message_type.to_string(), callback);
+    }
+
+    /// Remove the handler for `message_type`; returns whether there was one
+    pub fn unregister_handler(&mut self, message_type: &str) -> bool {
+        self.handlers.remove(message_type).is_some()
+    }
+
+    /// Validate and decode an incoming message and pass it to the handler
+    /// registered for its type (or "*"). Returns whether a handler was
+    /// called. An invalid message is an error naming the first problem
+    /// (see `validate_message`), and so is an exception thrown by the
+    /// handler.
+    pub fn dispatch(&self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        if let Some(problem) = validate::first_error(cbor_bytes) {
+            return Err(JsValue::from_str(&format!("Invalid message: {}", problem)));
+        }
+        let message = self.decode(cbor_bytes)?;
+        let Some(handler) = self.handlers.get(&message.payload.message_type).or_else(|| self.handlers.get("*")) else {
+            return Ok(false);
         };
+        let decoded = js_sys::JSON::parse(&decoded_json(&message).map_err(|e| JsValue::from_str(&e))?)?;
+        handler.call2(&JsValue::NULL, &decoded, &js_sys::Uint8Array::from(cbor_bytes))?;
+        Ok(true)
+    }
+
+    /// Parse a PromiseGrid message from CBOR bytes into JSON
+    pub fn parse_message(&self, cbor_bytes: &[u8]) -> String {
+        parse_promisegrid_message(cbor_bytes)
+    }
+
+    /// Retry delays start at `base_ms` and double per attempt (up to 30
+    /// seconds); a message is reported as failed after `max_attempts`
+    /// sends. Defaults: 1000 ms, 5 attempts.
+    pub fn set_retry_policy(&mut self, base_ms: f64, max_attempts: u32) {
+        self.outbound.set_policy(base_ms.max(0.0), max_attempts);
+    }
 
-        let message = PromiseGridMessage {
-            protocol_hash: self.protocol_hash.clone(),
-            payload,
+    /// Record a message as sent at `now` so it is retried until a peer
+    /// acknowledges it, and kept for resend requests. Returns its message
+    /// id. Acks, cursor moves and heartbeats aren't retried; they return
+    /// an empty id.
+    pub fn track_sent(&mut self, cbor_bytes: &[u8], now: f64) -> Result<String, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        if let Some(seq) = data_u64(&message.payload.data, "seq") {
+            if self.sent.len() == SENT_LOG_LIMIT {
+                self.sent.pop_front();
+            }
+            self.sent.push_back((seq, cbor_bytes.to_vec()));
+        }
+        self.stats.get_mut().record_sent(&message.payload.message_type, cbor_bytes.len());
+        self.sync_log.record(&message, cbor_bytes);
+        let message_type = message.payload.message_type.as_str();
+        if UNACKED_MESSAGE_TYPES.contains(&message_type) {
+            return Ok(String::new());
+        }
+        let message_id = data_text(&message.payload.data, "message_id")
+            .ok_or_else(|| JsValue::from_str("Message has no message_id"))?;
+        self.outbound.track(message_id, message_type, cbor_bytes, now);
+        Ok(message_id.to_string())
+    }
+
+    /// Create the `ack` for a received message, to send back. `undefined`
+    /// for messages that aren't acknowledged.
+    pub fn create_ack(&self, cbor_bytes: &[u8]) -> Result<Option<Vec<u8>>, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        if UNACKED_MESSAGE_TYPES.contains(&message.payload.message_type.as_str()) {
+            return Ok(None);
+        }
+        let Some(message_id) = data_text(&message.payload.data, "message_id") else {
+            return Ok(None);
         };
+        let mut data = HashMap::new();
+        data.insert("ack_id".to_string(), ciborium::Value::Text(message_id.to_string()));
+        data.insert("user_id".to_string(), ciborium::Value::Text(self.user_id.clone()));
+        Ok(Some(self.encode("ack", || data)))
+    }
 
-        // Create the CBOR with PromiseGrid tag
-        let cbor_data = self.encode_with_grid_tag(&message)
-            .map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))?;
+    /// Apply a received `ack`. Returns true when it acknowledged one of our
+    /// pending messages.
+    pub fn receive_ack(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        if message.payload.message_type != "ack" {
+            return Ok(false);
+        }
+        Ok(data_text(&message.payload.data, "ack_id").is_some_and(|id| self.outbound.acknowledge(id)))
+    }
 
-        Ok(cbor_data)
+    /// The next unacknowledged message due to be sent again; call until it
+    /// returns `undefined`. Messages out of attempts are moved to the
+    /// failures reported by `delivery_status`.
+    pub fn next_retry(&mut self, now: f64) -> Option<Vec<u8>> {
+        let retry = self.outbound.next_retry(now)?;
+        self.stats.get_mut().record_retry();
+        Some(retry)
     }
 
-    /// Parse a PromiseGrid message from CBOR bytes
-    #[wasm_bindgen]
-    pub fn parse_message(&self, cbor_bytes: &[u8]) -> Result<String, JsValue> {
-        match self.decode_with_grid_tag(cbor_bytes) {
-            Ok(message) => {
-                let json = serde_json::to_string_pretty(&message)
-                    .map_err(|e| JsValue::from_str(&format!("JSON serialization error: {}", e)))?;
-                Ok(json)
+    /// Apply a received `resend_request`: when it is addressed to this
+    /// client, queue the kept messages from the requested sequence number
+    /// for `next_resend`. Returns how many were queued.
+    pub fn receive_resend_request(&mut self, cbor_bytes: &[u8]) -> Result<usize, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        if message.payload.message_type != "resend_request" {
+            return Ok(0);
+        }
+        let Some(from_seq) = resend_request_from(&message.payload.data, &self.user_id) else {
+            return Ok(0);
+        };
+        let before = self.resend.len();
+        for (_, bytes) in self.sent.iter().filter(|(seq, _)| *seq >= from_seq) {
+            if !self.resend.contains(bytes) {
+                self.resend.push_back(bytes.clone());
             }
-            Err(e) => Err(JsValue::from_str(&format!("CBOR parsing error: {}", e)))
         }
+        Ok(self.resend.len() - before)
+    }
+
+    /// The next message queued by a resend request; call until it returns
+    /// `undefined`
+    pub fn next_resend(&mut self) -> Option<Vec<u8>> {
+        let resend = self.resend.pop_front()?;
+        self.stats.get_mut().record_resend();
+        Some(resend)
+    }
+
+    /// Note a received message: the Lamport clock moves past its time, so
+    /// the next message sent is ordered after it, and it is kept for
+    /// answering sync requests. Returns the message's Lamport time (0 when
+    /// it has none). Messages from a peer the handshake failed with are
+    /// refused, as are messages with a Lamport time implausibly far ahead.
+    pub fn receive(&mut self, cbor_bytes: &[u8]) -> Result<u64, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        if let Some(user_id) = data_text(&message.payload.data, "user_id") {
+            if let Some(PeerState::Rejected(reason)) = self.peers.get(user_id) {
+                return Err(JsValue::from_str(&format!("Incompatible peer {}: {}", user_id, reason)));
+            }
+        }
+        self.stats.get_mut().record_received(&message.payload.message_type, cbor_bytes.len());
+        let lamport = data_u64(&message.payload.data, "lamport").unwrap_or(0);
+        if !self.clock.witness(lamport) {
+            return Err(JsValue::from_str(&format!(
+                "Lamport time {} is too far ahead of ours ({})",
+                lamport,
+                self.clock.time()
+            )));
+        }
+        self.sync_log.record(&message, cbor_bytes);
+        Ok(lamport)
+    }
+
+    /// Set the modes this client offers in its `hello`: JSON
+    /// `{compression, encryption, editing}`, each a list most preferred
+    /// first. Defaults: `["gzip", "none"]`, `["xchacha20poly1305",
+    /// "none"]` and `["ot"]`; missing fields (or an empty string) keep
+    /// them.
+    pub fn set_handshake_options(&mut self, options: &str) -> Result<(), JsValue> {
+        self.handshake = HandshakeOptions::parse(options)?;
+        Ok(())
+    }
+
+    /// The `hello` to send on connecting, advertising this client's
+    /// protocol versions and modes
+    pub fn create_hello(&self, now: f64) -> Vec<u8> {
+        self.encode("hello", || payload_data(&self.handshake.hello(&self.user_id, now)))
+    }
+
+    /// Answer another client's `hello` with a `hello_ack` choosing the
+    /// modes to use, or rejecting it when there are none in common (the
+    /// peer's later messages are then refused by `receive`); `undefined`
+    /// for other messages
+    pub fn receive_hello(&mut self, cbor_bytes: &[u8], now: f64) -> Result<Option<Vec<u8>>, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        if message.payload.message_type != "hello" {
+            return Ok(None);
+        }
+        let (_, Payload::Hello(hello)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Ok(None);
+        };
+        if hello.user_id == self.user_id {
+            return Ok(None);
+        }
+        let outcome = self.handshake.negotiate(&self.user_id, &hello);
+        let ack = handshake::ack(&self.user_id, &hello, &outcome, now);
+        if let Err(reason) = &outcome {
+            logger::log_warn!("handshake", "Refusing peer {}: {}", hello.user_id, reason);
+        }
+        self.peers.insert(hello.user_id, outcome.map_or_else(PeerState::Rejected, PeerState::Agreed));
+        Ok(Some(self.encode("hello_ack", || payload_data(&ack))))
     }
 
-    /// Log message to browser console for debugging
-    #[wasm_bindgen]
-    pub fn log_message(&self, cbor_bytes: &[u8]) {
-        match self.parse_message(cbor_bytes) {
-            Ok(json) => {
-                web_sys::console::log_1(&format!("PromiseGrid Message: {}", json).into());
+    /// Apply a peer's `hello_ack` to our `hello`. Returns the agreed modes
+    /// as JSON `{protocol_version, compression, encryption, editing}`, or
+    /// `undefined` for other messages; fails with the reason when the peer
+    /// rejected us or chose modes we didn't offer.
+    pub fn receive_hello_ack(&mut self, cbor_bytes: &[u8]) -> Result<Option<String>, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        if message.payload.message_type != "hello_ack" {
+            return Ok(None);
+        }
+        let (_, Payload::HelloAck(ack)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Ok(None);
+        };
+        if ack.target_user_id != self.user_id {
+            return Ok(None);
+        }
+        match self.handshake.accept(&ack) {
+            Ok(mode) => {
+                let json = serde_json::to_string(&mode).unwrap_or_else(|_| "{}".into());
+                self.peers.insert(ack.user_id, PeerState::Agreed(mode));
+                Ok(Some(json))
             }
-            Err(e) => {
-                web_sys::console::error_1(&format!("Error parsing message: {:?}", e).into());
+            Err(reason) => {
+                logger::log_warn!("handshake", "Refusing peer {}: {}", ack.user_id, reason);
+                let error = format!("Incompatible peer {}: {}", ack.user_id, reason);
+                self.peers.insert(ack.user_id, PeerState::Rejected(reason));
+                Err(JsValue::from_str(&error))
             }
         }
     }
-}
 
-impl PromiseGridHandler {
-    /// Encode message with PromiseGrid CBOR tag (0x67726964)
-    fn encode_with_grid_tag(&self, message: &PromiseGridMessage) -> Result<Vec<u8>, serde_cbor::Error> {
-        // First encode the message normally
-        let message_cbor = serde_cbor::to_vec(message)?;
-        
-        // Then wrap it with the grid tag
-        // Tag 0x67726964 is 'grid' in ASCII
-        let grid_tag = 0x67726964u32;
-        let tagged_value = serde_cbor::Value::Tag(grid_tag as u64, Box::new(
-            serde_cbor::from_slice::<serde_cbor::Value>(&message_cbor)?
-        ));
-        
-        serde_cbor::to_vec(&tagged_value)
-    }
-
-    /// Decode message with PromiseGrid CBOR tag
-    fn decode_with_grid_tag(&self, cbor_bytes: &[u8]) -> Result<PromiseGridMessage, Box<dyn std::error::Error>> {
-        let tagged_value: serde_cbor::Value = serde_cbor::from_slice(cbor_bytes)?;
-        
-        match tagged_value {
-            serde_cbor::Value::Tag(tag, boxed_value) => {
-                if tag == 0x67726964 {  // 'grid' tag
-                    let message_cbor = serde_cbor::to_vec(&*boxed_value)?;
-                    let message: PromiseGridMessage = serde_cbor::from_slice(&message_cbor)?;
-                    Ok(message)
-                } else {
-                    Err(format!("Invalid tag: expected 0x67726964, got 0x{:x}", tag).into())
+    /// The modes agreed with a peer, as JSON `{protocol_version,
+    /// compression, encryption, editing}`; `undefined` before the
+    /// handshake, and an error saying why when it failed
+    pub fn peer_mode(&self, user_id: &str) -> Result<Option<String>, JsValue> {
+        match self.peers.get(user_id) {
+            Some(PeerState::Agreed(mode)) => Ok(Some(serde_json::to_string(mode).unwrap_or_else(|_| "{}".into()))),
+            Some(PeerState::Rejected(reason)) => {
+                Err(JsValue::from_str(&format!("Incompatible peer {}: {}", user_id, reason)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// After reconnecting, ask peers for what was missed: a `sync_request`
+    /// with this client's state vector (the last message seen from each
+    /// sender's session). Answers go to `receive_sync_response`; call
+    /// `poll_sync` to ask again if none comes.
+    pub fn start_sync(&mut self, now: f64) -> Vec<u8> {
+        let attempts = match self.sync {
+            SyncState::Awaiting { attempts, .. } => attempts + 1,
+            _ => 1,
+        };
+        self.sync = SyncState::Awaiting { sent_at: now, attempts };
+        let request = SyncRequest { user_id: self.user_id.clone(), versions: self.sync_log.versions(), timestamp: now };
+        self.encode("sync_request", || payload_data(&request))
+    }
+
+    /// The `sync_request` to send again when the last went unanswered for
+    /// five seconds, or `undefined`. After three attempts the sync fails
+    /// (see `sync_status`) and the client should load a snapshot instead.
+    pub fn poll_sync(&mut self, now: f64) -> Option<Vec<u8>> {
+        let SyncState::Awaiting { sent_at, attempts } = self.sync else {
+            return None;
+        };
+        if now - sent_at < SYNC_TIMEOUT_MS {
+            return None;
+        }
+        if attempts >= SYNC_MAX_ATTEMPTS {
+            self.sync = SyncState::Failed;
+            return None;
+        }
+        Some(self.start_sync(now))
+    }
+
+    /// Answer another client's `sync_request` with a `sync_response` of
+    /// the messages it is missing, or `undefined` for other messages
+    pub fn receive_sync_request(&self, cbor_bytes: &[u8], now: f64) -> Result<Option<Vec<u8>>, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        if message.payload.message_type != "sync_request" {
+            return Ok(None);
+        }
+        let (envelope, Payload::SyncRequest(request)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+            return Ok(None);
+        };
+        let Some(session) = envelope.session else {
+            return Err(JsValue::from_str("sync_request without a session"));
+        };
+        if request.user_id == self.user_id && session == session_id() {
+            return Ok(None);
+        }
+        let delta = self.sync_log.delta(&request.versions);
+        let response = SyncResponse {
+            user_id: self.user_id.clone(),
+            target_user_id: request.user_id,
+            target_session: session,
+            operations: delta.operations,
+            complete: delta.complete,
+            more: delta.more,
+            timestamp: now,
+        };
+        Ok(Some(self.encode("sync_response", || payload_data(&response))))
+    }
+
+    /// Apply a `sync_response` to our request: the messages in it not
+    /// already seen are queued for `next_sync_operation`, and the sync
+    /// ends. Returns JSON `{status, operations}`. Only the first response
+    /// to a request is used.
+    pub fn receive_sync_response(&mut self, cbor_bytes: &[u8]) -> Result<String, JsValue> {
+        let message = self.decode(cbor_bytes)?;
+        let mut result = SyncResult { status: "ignored", operations: 0 };
+        if message.payload.message_type == "sync_response" && matches!(self.sync, SyncState::Awaiting { .. }) {
+            let (_, Payload::SyncResponse(response)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+                return Err(JsValue::from_str("Malformed sync_response"));
+            };
+            if response.target_user_id == self.user_id && response.target_session == session_id() {
+                for ByteString(bytes) in response.operations {
+                    let Ok(operation) = decode_with_grid_tag(&bytes) else { continue };
+                    let lamport = data_u64(&operation.payload.data, "lamport").unwrap_or(0);
+                    if !clock::plausible(self.clock.time(), lamport) {
+                        continue;
+                    }
+                    if self.sync_log.record(&operation, &bytes) {
+                        self.clock.witness(lamport);
+                        self.synced.push_back(bytes);
+                        result.operations += 1;
+                    }
                 }
+                self.sync = SyncState::Idle;
+                result.status = match (response.complete, response.more) {
+                    (false, _) => "incomplete",
+                    (true, true) => "partial",
+                    (true, false) => "complete",
+                };
             }
-            _ => Err("Message is not tagged with PromiseGrid tag".into())
         }
+        Ok(serde_json::to_string(&result).unwrap_or_else(|_| "{}".into()))
+    }
+
+    /// The next message received through sync, oldest first; call until
+    /// it returns `undefined` and apply each like a live message
+    pub fn next_sync_operation(&mut self) -> Option<Vec<u8>> {
+        self.synced.pop_front()
+    }
+
+    /// "idle", "awaiting" or "failed"
+    pub fn sync_status(&self) -> String {
+        match self.sync {
+            SyncState::Idle => "idle",
+            SyncState::Awaiting { .. } => "awaiting",
+            SyncState::Failed => "failed",
+        }
+        .to_string()
+    }
+
+    /// The current Lamport time: at least the time of every message sent
+    /// or received
+    pub fn lamport_time(&self) -> u64 {
+        self.clock.time()
+    }
+
+    /// Give a message built outside the handler this client's next Lamport
+    /// time; the handler's own `create_*` methods do it already. Stamp
+    /// before signing or attaching a capability token, as it changes the
+    /// message.
+    pub fn stamp_message(&self, cbor_bytes: &[u8]) -> Result<Vec<u8>, JsValue> {
+        let mut message = self.decode(cbor_bytes)?;
+        self.clock.stamp(&mut message.payload.data);
+        encode_with_grid_tag(&message).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Compare two messages in causal order: -1, 0 or 1 as `a` comes
+    /// before, together with or after `b` (by Lamport time, then user id)
+    pub fn compare_messages(&self, a: &[u8], b: &[u8]) -> Result<i32, JsValue> {
+        let decode = |bytes: &[u8]| {
+            decode_with_grid_tag(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+        };
+        let (a, b) = (decode(a)?, decode(b)?);
+        Ok(clock::causal_cmp(&a.payload.data, &b.payload.data) as i32)
+    }
+
+    /// JSON `{pending, failures: [{message_id, message_type, attempts,
+    /// first_sent}]}`. Each failure is reported once.
+    pub fn delivery_status(&mut self) -> String {
+        let status = QueueStatus { pending: self.outbound.pending(), failures: self.outbound.take_failures() };
+        serde_json::to_string(&status).unwrap_or_else(|_| "{}".into())
+    }
+
+    /// Transport statistics for a connection-health display, as JSON
+    /// `{sent, received, by_type, encode, decode, retries, resends,
+    /// queue_depth}`. `sent` and `received` are `{messages, bytes}`, counted
+    /// by `track_sent` and `receive`, and `by_type` holds the same per
+    /// message type as `{sent, received}`; `encode` and `decode` are
+    /// `{count, average_ms, max_ms}` over the messages this handler built
+    /// and parsed; `queue_depth` is `{unacked, resend, synced}`.
+    pub fn stats(&self) -> String {
+        let queue_depth =
+            QueueDepth { unacked: self.outbound.pending(), resend: self.resend.len(), synced: self.synced.len() };
+        self.stats.borrow().snapshot(queue_depth)
+    }
+
+    /// Start the statistics afresh
+    pub fn reset_stats(&mut self) {
+        *self.stats.get_mut() = StatsCollector::default();
     }
 }
 
-// Optional: Helper function to initialize panic hook for better error messages
-#[wasm_bindgen(start)]
-pub fn main() {
-    console_error_panic_hook::set_once();
+impl PromiseGridHandler {
+    /// Build an outgoing message from the data map `build` returns,
+    /// stamped with the next Lamport time, timing it for `stats`
+    fn encode(&self, message_type: &str, build: impl FnOnce() -> HashMap<String, ciborium::Value>) -> Vec<u8> {
+        let started = precise_now();
+        let mut data = build();
+        self.clock.stamp(&mut data);
+        let bytes = encode_promisegrid_payload(message_type, data);
+        self.stats.borrow_mut().record_encode(precise_now() - started);
+        bytes
+    }
+
+    /// Parse an incoming message, timing it for `stats`
+    fn decode(&self, cbor_bytes: &[u8]) -> Result<PromiseGridMessage, JsValue> {
+        let started = precise_now();
+        let message = decode_with_grid_tag(cbor_bytes);
+        self.stats.borrow_mut().record_decode(precise_now() - started);
+        message.map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
 }
@@ -0,0 +1,264 @@
+// Punctuation spacing cleanup. Text is split into tokens first so each rule
+// can look at its neighbours: a space before a period is only removed when
+// the period ends a sentence, not when it starts ".5" or ".net", and
+// ellipses, ranges, smileys and paths are left alone.
+
+use wasm_bindgen::prelude::*;
+
+/// Locales with their own spacing rules; anything else uses "en"
+pub(crate) const PUNCTUATION_LOCALES: &[&str] = &["en", "fr"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Locale {
+    /// No space before punctuation
+    English,
+    /// A space before `; : ! ?` and inside `« »`
+    French,
+}
+
+impl Locale {
+    /// Parse a BCP 47 tag by its language: "fr-CA" -> French
+    pub(crate) fn parse(tag: &str) -> Locale {
+        let language = tag.trim().split(['-', '_']).next().unwrap_or("").to_ascii_lowercase();
+        match language.as_str() {
+            "fr" => Locale::French,
+            _ => Locale::English,
+        }
+    }
+
+    /// Whether the space before `mark` is part of the locale's typography
+    fn keeps_space_before(self, mark: char) -> bool {
+        self == Locale::French && matches!(mark, ';' | ':' | '!' | '?' | '»')
+    }
+
+    fn keeps_space_after(self, mark: char) -> bool {
+        self == Locale::French && mark == '«'
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Word,
+    /// Digits, with single `.`, `,` or `:` separators: 3.14, 1,000, 10:30
+    Number,
+    /// Spaces and tabs
+    Space,
+    Newline,
+    /// A run of the same sentence punctuation character: "..", "!!!"
+    Punct,
+    Open,
+    Close,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+}
+
+impl Token<'_> {
+    fn first(&self) -> char {
+        self.text.chars().next().unwrap_or(' ')
+    }
+
+    fn is(&self, kind: Kind) -> bool {
+        self.kind == kind
+    }
+}
+
+fn is_punct(c: char) -> bool {
+    matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | '…')
+}
+
+fn is_open(c: char) -> bool {
+    matches!(c, '(' | '[' | '{' | '«')
+}
+
+fn is_close(c: char) -> bool {
+    matches!(c, ')' | ']' | '}' | '»')
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let kind = if c.is_ascii_digit() {
+            // Absorb digits and single separators followed by a digit
+            loop {
+                match chars.peek() {
+                    Some(&(_, d)) if d.is_ascii_digit() => {
+                        chars.next();
+                    }
+                    Some(&(i, '.' | ',' | ':')) => {
+                        let after = text[i + 1..].chars().next();
+                        if !after.is_some_and(|d| d.is_ascii_digit()) {
+                            break;
+                        }
+                        chars.next();
+                    }
+                    _ => break,
+                }
+            }
+            Kind::Number
+        } else if c.is_alphanumeric() || c == '_' {
+            while chars.next_if(|&(_, d)| d.is_alphanumeric() || d == '_' || d == '\'').is_some() {}
+            Kind::Word
+        } else if c == ' ' || c == '\t' {
+            while chars.next_if(|&(_, d)| d == ' ' || d == '\t').is_some() {}
+            Kind::Space
+        } else if c == '\n' || c == '\r' {
+            Kind::Newline
+        } else if is_punct(c) {
+            while chars.next_if(|&(_, d)| d == c).is_some() {}
+            Kind::Punct
+        } else if is_open(c) {
+            Kind::Open
+        } else if is_close(c) {
+            Kind::Close
+        } else {
+            Kind::Other
+        };
+        let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+        tokens.push(Token { kind, text: &text[start..end] });
+    }
+    tokens
+}
+
+fn is_ellipsis(token: &Token) -> bool {
+    token.text == "…" || token.text.starts_with("...")
+}
+
+/// `:)`, `;-)`, `:D`, `:P` and friends, starting at token `i`
+fn is_smiley(tokens: &[Token], i: usize) -> bool {
+    let Some(eyes) = tokens.get(i) else { return false };
+    if !eyes.is(Kind::Punct) || !matches!(eyes.text, ":" | ";") {
+        return false;
+    }
+    let mut next = i + 1;
+    if tokens.get(next).is_some_and(|t| matches!(t.text, "-" | "'")) {
+        next += 1;
+    }
+    tokens.get(next).is_some_and(|mouth| match mouth.kind {
+        Kind::Open | Kind::Close => matches!(mouth.first(), '(' | ')' | '[' | ']'),
+        Kind::Word => matches!(mouth.text, "D" | "P" | "p" | "O" | "o" | "3"),
+        Kind::Other => matches!(mouth.text, "/" | "|" | "*"),
+        _ => false,
+    })
+}
+
+/// Whether the bracket at `i` is the mouth of a smiley, as in `:-(`
+fn is_smiley_mouth(tokens: &[Token], i: usize) -> bool {
+    (1..=2).any(|back| i >= back && is_smiley(tokens, i - back))
+}
+
+/// Whether the punctuation at `i` ends something: it is followed by
+/// whitespace, the end of the text, a closing bracket or a quote
+fn ends_clause(tokens: &[Token], i: usize) -> bool {
+    match tokens.get(i + 1) {
+        None => true,
+        Some(next) => match next.kind {
+            Kind::Space | Kind::Newline | Kind::Close => true,
+            Kind::Other => matches!(next.text, "\"" | "'" | "”" | "’" | "*" | "_"),
+            _ => false,
+        },
+    }
+}
+
+/// Whether the tokens before `i` are the `#`s opening a line
+fn follows_header_marker(tokens: &[Token], i: usize) -> bool {
+    let hashes = tokens[..i].iter().rev().take_while(|t| t.text == "#").count();
+    hashes > 0 && (hashes == i || tokens[i - hashes - 1].is(Kind::Newline))
+}
+
+/// Whether the space at `i` (between `prev` and `next`) should be dropped
+fn drop_space(tokens: &[Token], i: usize, locale: Locale) -> bool {
+    let (Some(prev), Some(next)) = (i.checked_sub(1).and_then(|p| tokens.get(p)), tokens.get(i + 1)) else {
+        return false;
+    };
+    // Indentation and alignment are not punctuation spacing, nor is the
+    // space after a header's `#`s
+    if prev.is(Kind::Newline) || next.is(Kind::Newline) || follows_header_marker(tokens, i) {
+        return false;
+    }
+    if prev.is(Kind::Open) {
+        // "( x" -> "(x", but keep "( )", "« x" and ":( x"
+        return !next.is(Kind::Close) && !locale.keeps_space_after(prev.first()) && !is_smiley_mouth(tokens, i - 1);
+    }
+    match next.kind {
+        Kind::Close => !prev.is(Kind::Open) && !is_smiley(tokens, i - 1) && !locale.keeps_space_before(next.first()),
+        Kind::Punct => {
+            let mark = next.first();
+            !is_ellipsis(next)
+                && !is_smiley(tokens, i + 1)
+                && ends_clause(tokens, i + 1)
+                && !locale.keeps_space_before(mark)
+                // "1 : 2" is a ratio
+                && (mark != ':' || !prev.is(Kind::Number))
+                // A lone "-" or "—" before the mark is a dash, not a word
+                && !matches!(prev.text, "-" | "–" | "—")
+        }
+        _ => false,
+    }
+}
+
+/// Collapse an accidental doubled mark: ",," -> ",", ".." -> "."
+fn fix_doubled<'a>(tokens: &[Token<'a>], i: usize) -> &'a str {
+    let token = tokens[i];
+    let prev = i.checked_sub(1).map(|p| tokens[p]);
+    let next = tokens.get(i + 1);
+    match token.text {
+        ",," | ";;" => &token.text[..1],
+        ".." => {
+            // "1..10" is a range, "../" a path and "..then" a clipped
+            // ellipsis; only a ".." ending a word is a typo
+            let leads_into = next.is_some_and(|n| n.is(Kind::Number) || n.is(Kind::Word));
+            let path = next.is_some_and(|n| matches!(n.text, "/" | "\\"))
+                || prev.is_some_and(|p| matches!(p.text, "/" | "\\"));
+            if leads_into || path {
+                token.text
+            } else {
+                "."
+            }
+        }
+        text => text,
+    }
+}
+
+/// Fix punctuation spacing in prose for `locale`: no space before `.,;:!?`
+/// or `)` or after `(` (French keeps its spaces before `;:!?` and inside
+/// guillemets), and doubled `,,` `;;` `..` collapsed. Ellipses, decimals,
+/// ranges (`1..10`), times, smileys and paths are left as written.
+pub(crate) fn fix_punctuation(text: &str, locale: Locale) -> String {
+    // Dropping a space can bring two marks together ("a , , b"), so repeat
+    // until nothing changes to keep the formatter idempotent
+    let mut text = fix_punctuation_once(text, locale);
+    for _ in 0..3 {
+        let next = fix_punctuation_once(&text, locale);
+        if next == text {
+            break;
+        }
+        text = next;
+    }
+    text
+}
+
+fn fix_punctuation_once(text: &str, locale: Locale) -> String {
+    let tokens = tokenize(text);
+    let mut out = String::with_capacity(text.len());
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            Kind::Space if drop_space(&tokens, i, locale) => {}
+            Kind::Punct => out.push_str(fix_doubled(&tokens, i)),
+            _ => out.push_str(token.text),
+        }
+    }
+    out
+}
+
+/// Fix punctuation spacing in plain text using the rules of `locale` (a
+/// language tag such as "en" or "fr-CA"; unknown languages use "en")
+#[wasm_bindgen]
+pub fn fix_punctuation_for_locale(text: &str, locale: &str) -> String {
+    fix_punctuation(text, Locale::parse(locale))
+}
@@ -0,0 +1,197 @@
+// Document and attachment size quotas. Limits are enforced here rather
+// than only on the server so every client rejects the same edits: an edit
+// that would take a document past its limit fails with a structured quota
+// error, and sizes close to a limit report a warning.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Free-tier defaults
+pub(crate) const DEFAULT_MAX_DOCUMENT_BYTES: usize = 1024 * 1024;
+pub(crate) const DEFAULT_MAX_ATTACHMENT_BYTES: usize = 5 * 1024 * 1024;
+pub(crate) const DEFAULT_MAX_TOTAL_ATTACHMENT_BYTES: usize = 50 * 1024 * 1024;
+const DEFAULT_WARNING_RATIO: f64 = 0.9;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "snake_case")]
+enum Level {
+    Ok,
+    Warning,
+    Exceeded,
+}
+
+/// Where a size stands against one limit
+#[derive(Serialize, Debug, Clone)]
+struct QuotaStatus {
+    /// "document_size", "attachment_size" or "attachment_total"
+    quota: &'static str,
+    status: Level,
+    size: usize,
+    limit: usize,
+    remaining: usize,
+}
+
+/// The error thrown when an edit or attachment would exceed a quota
+#[derive(Serialize, Debug, Clone)]
+struct QuotaError {
+    error: &'static str,
+    quota: &'static str,
+    size: usize,
+    limit: usize,
+    message: String,
+}
+
+/// Size limits for one user or plan. Set the limits directly or pass JSON
+/// (`{max_document_bytes, max_attachment_bytes, max_total_attachment_bytes,
+/// warning_ratio}`, missing fields keep their defaults) to `from_json`.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct QuotaPolicy {
+    max_document_bytes: usize,
+    max_attachment_bytes: usize,
+    max_total_attachment_bytes: usize,
+    /// Fraction of a limit at which a warning is reported
+    warning_ratio: f64,
+}
+
+impl Default for QuotaPolicy {
+    fn default() -> Self {
+        QuotaPolicy {
+            max_document_bytes: DEFAULT_MAX_DOCUMENT_BYTES,
+            max_attachment_bytes: DEFAULT_MAX_ATTACHMENT_BYTES,
+            max_total_attachment_bytes: DEFAULT_MAX_TOTAL_ATTACHMENT_BYTES,
+            warning_ratio: DEFAULT_WARNING_RATIO,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl QuotaPolicy {
+    /// A policy with the free-tier limits
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> QuotaPolicy {
+        QuotaPolicy::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<QuotaPolicy, JsValue> {
+        let policy: QuotaPolicy = serde_json::from_str(json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid quota policy: {}", e)))?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    pub fn set_max_document_bytes(&mut self, bytes: usize) -> Result<(), JsValue> {
+        self.max_document_bytes = positive(bytes, "max_document_bytes")?;
+        Ok(())
+    }
+
+    pub fn set_max_attachment_bytes(&mut self, bytes: usize) -> Result<(), JsValue> {
+        self.max_attachment_bytes = positive(bytes, "max_attachment_bytes")?;
+        Ok(())
+    }
+
+    pub fn set_max_total_attachment_bytes(&mut self, bytes: usize) -> Result<(), JsValue> {
+        self.max_total_attachment_bytes = positive(bytes, "max_total_attachment_bytes")?;
+        Ok(())
+    }
+
+    pub fn set_warning_ratio(&mut self, ratio: f64) -> Result<(), JsValue> {
+        if !(ratio > 0.0 && ratio <= 1.0) {
+            return Err(JsValue::from_str("warning_ratio must be greater than 0 and at most 1"));
+        }
+        self.warning_ratio = ratio;
+        Ok(())
+    }
+
+    /// Where a document stands against its size limit, as JSON
+    /// `{quota, status: "ok" | "warning" | "exceeded", size, limit, remaining}`.
+    /// Sizes are UTF-8 bytes.
+    pub fn check_document(&self, content: &str) -> String {
+        to_json(&self.status("document_size", content.len(), self.max_document_bytes))
+    }
+
+    /// Check an edit replacing bytes `from..to` of `content` with `insert`.
+    /// Throws a quota error (JSON `{error: "quota_exceeded", quota, size,
+    /// limit, message}`) if the edit would take the document past its limit;
+    /// otherwise returns the status of the edited document as
+    /// `check_document` does. Edits that don't grow the document are always
+    /// accepted, so an over-quota document can still be trimmed.
+    pub fn check_edit(&self, content: &str, from: usize, to: usize, insert: &str) -> Result<String, JsValue> {
+        let (from, to) = (from.min(to).min(content.len()), from.max(to).min(content.len()));
+        let size = content.len() - (to - from) + insert.len();
+        let status = self.status("document_size", size, self.max_document_bytes);
+        if status.status == Level::Exceeded && size > content.len() {
+            return Err(quota_error(&status));
+        }
+        Ok(to_json(&status))
+    }
+
+    /// Check an attachment of `size` bytes being added when `used` bytes of
+    /// attachments are already stored. Throws a quota error if either the
+    /// per-file or the total limit would be exceeded; otherwise returns the
+    /// status closest to its limit.
+    pub fn check_attachment(&self, size: usize, used: usize) -> Result<String, JsValue> {
+        let file = self.status("attachment_size", size, self.max_attachment_bytes);
+        let total = self.status("attachment_total", used.saturating_add(size), self.max_total_attachment_bytes);
+        let worst = if total.status > file.status { total } else { file };
+        if worst.status == Level::Exceeded {
+            return Err(quota_error(&worst));
+        }
+        Ok(to_json(&worst))
+    }
+}
+
+impl QuotaPolicy {
+    fn validate(&self) -> Result<(), JsValue> {
+        positive(self.max_document_bytes, "max_document_bytes")?;
+        positive(self.max_attachment_bytes, "max_attachment_bytes")?;
+        positive(self.max_total_attachment_bytes, "max_total_attachment_bytes")?;
+        if !(self.warning_ratio > 0.0 && self.warning_ratio <= 1.0) {
+            return Err(JsValue::from_str("warning_ratio must be greater than 0 and at most 1"));
+        }
+        Ok(())
+    }
+
+    fn status(&self, quota: &'static str, size: usize, limit: usize) -> QuotaStatus {
+        let status = if size > limit {
+            Level::Exceeded
+        } else if size as f64 >= limit as f64 * self.warning_ratio {
+            Level::Warning
+        } else {
+            Level::Ok
+        };
+        QuotaStatus { quota, status, size, limit, remaining: limit.saturating_sub(size) }
+    }
+}
+
+fn positive(bytes: usize, name: &str) -> Result<usize, JsValue> {
+    if bytes == 0 {
+        return Err(JsValue::from_str(&format!("{} must be greater than 0", name)));
+    }
+    Ok(bytes)
+}
+
+fn to_json(status: &QuotaStatus) -> String {
+    serde_json::to_string(status).unwrap_or_else(|_| "{}".to_string())
+}
+
+fn quota_error(status: &QuotaStatus) -> JsValue {
+    let what = match status.quota {
+        "document_size" => "Document",
+        "attachment_size" => "Attachment",
+        _ => "Total attachment storage",
+    };
+    let error = QuotaError {
+        error: "quota_exceeded",
+        quota: status.quota,
+        size: status.size,
+        limit: status.limit,
+        message: format!("{} size of {} bytes exceeds the limit of {} bytes", what, status.size, status.limit),
+    };
+    JsValue::from_str(&serde_json::to_string(&error).unwrap_or_else(|_| "{\"error\":\"quota_exceeded\"}".to_string()))
+}
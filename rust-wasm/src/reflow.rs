@@ -0,0 +1,271 @@
+// Hard-wrapping prose to a column width and joining hard-wrapped lines
+// back together. Only paragraphs are touched: code, tables, headings,
+// HTML, reference definitions and front matter pass through unchanged,
+// and list items and blockquotes keep their markers and indentation.
+
+use wasm_bindgen::prelude::*;
+
+use crate::front_matter::front_matter_range;
+use crate::headings::parse_headings;
+use crate::links::parse_reference_definitions;
+use crate::markdown::{fenced_code_ranges, in_ranges};
+
+/// Narrowest column reflow will wrap to
+const MIN_WIDTH: usize = 20;
+
+/// A paragraph ready to be rewrapped
+struct Paragraph {
+    /// Prefix of the first line: blockquote markers, list marker
+    first_prefix: String,
+    /// Prefix of every following line
+    prefix: String,
+    /// Runs of text ending in a hard line break (`\` or two trailing
+    /// spaces), or at the end of the paragraph
+    segments: Vec<(String, &'static str)>,
+}
+
+/// Blockquote markers at the start of a line (`> > `) and the rest
+fn split_quote(line: &str) -> (&str, &str) {
+    let mut end = 0;
+    loop {
+        let rest = &line[end..];
+        let indent = rest.len() - rest.trim_start_matches(' ').len();
+        if indent > 3 || !rest[indent..].starts_with('>') {
+            break;
+        }
+        end += indent + 1;
+        if line[end..].starts_with(' ') {
+            end += 1;
+        }
+    }
+    line.split_at(end)
+}
+
+/// Byte length of a list marker at the start of `text`, with its
+/// indentation, trailing space and any task checkbox (`- [ ] `)
+pub(crate) fn list_marker(text: &str) -> Option<usize> {
+    let indent = text.len() - text.trim_start_matches(' ').len();
+    let rest = &text[indent..];
+    let marker = if rest.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 || digits > 9 || !rest[digits..].starts_with(['.', ')']) {
+            return None;
+        }
+        digits + 1
+    };
+    let after = &rest[marker..];
+    if !after.starts_with(' ') {
+        return None;
+    }
+    let mut len = indent + marker + 1;
+    for checkbox in ["[ ] ", "[x] ", "[X] "] {
+        if text[len..].starts_with(checkbox) {
+            len += checkbox.len();
+        }
+    }
+    Some(len)
+}
+
+/// Whether a line (after its quote markers) is a thematic break: `---`,
+/// `***` or `___`, possibly spaced
+fn is_thematic_break(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().filter(|c| *c != ' ').collect();
+    chars.len() >= 3 && ['-', '*', '_'].iter().any(|&m| chars.iter().all(|&c| c == m))
+}
+
+fn is_table_separator(text: &str) -> bool {
+    let text = text.trim();
+    text.contains('-') && text.contains('|') && text.chars().all(|c| matches!(c, '|' | '-' | ':' | ' '))
+}
+
+/// Lines that are never part of a paragraph
+fn is_block_line(text: &str) -> bool {
+    let trimmed = text.trim_start();
+    trimmed.starts_with('#')
+        || trimmed.starts_with('|')
+        || trimmed.starts_with("```")
+        || trimmed.starts_with("~~~")
+        || trimmed.starts_with("$$")
+        || (trimmed.starts_with('<') && trimmed[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!'))
+        || is_thematic_break(trimmed)
+        || is_table_separator(trimmed)
+        || !parse_reference_definitions(trimmed).is_empty()
+}
+
+/// Whether `word` would start a block (list item, heading, quote, setext
+/// underline) if it began a line, so it must not be wrapped onto one
+fn starts_block(word: &str) -> bool {
+    matches!(word, "-" | "+" | "*" | ">")
+        || word.starts_with('>')
+        || word.starts_with('#') && word.trim_start_matches('#').is_empty()
+        || word.chars().all(|c| c == '=')
+        || list_marker(&format!("{} ", word)).is_some_and(|len| len == word.len() + 1)
+        || word.starts_with('|')
+}
+
+/// Greedy word wrap of one segment into lines of at most `width` columns,
+/// the first of which starts `first_used` columns in
+fn wrap_words(text: &str, width: usize, first_used: usize, used: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    let mut column = first_used;
+    for word in text.split_whitespace() {
+        let len = word.chars().count();
+        if !line.is_empty() && column + 1 + len > width && !starts_block(word) {
+            lines.push(std::mem::take(&mut line));
+            column = used;
+        }
+        if !line.is_empty() {
+            line.push(' ');
+            column += 1;
+        }
+        line.push_str(word);
+        column += len;
+    }
+    lines.push(line);
+    lines
+}
+
+impl Paragraph {
+    fn render(&self, width: usize, out: &mut Vec<String>) {
+        let first_used = self.first_prefix.chars().count();
+        let used = self.prefix.chars().count();
+        let mut first = true;
+        for (text, hard_break) in &self.segments {
+            let lines = wrap_words(text, width, if first { first_used } else { used }, used);
+            let count = lines.len();
+            for (i, line) in lines.into_iter().enumerate() {
+                let prefix = if first { &self.first_prefix } else { &self.prefix };
+                let end = if i + 1 == count { *hard_break } else { "" };
+                out.push(format!("{}{}", prefix, line).trim_end().to_string() + end);
+                first = false;
+            }
+        }
+    }
+}
+
+/// Split a paragraph line into its text and hard line break marker
+fn hard_break(text: &str) -> (&str, &'static str) {
+    if text.ends_with("  ") && !text.trim().is_empty() {
+        (text.trim_end(), "  ")
+    } else {
+        (text.trim_end(), "")
+    }
+}
+
+/// Rewrap every paragraph of `content` to `width` columns (no limit for
+/// `usize::MAX`, which joins hard-wrapped lines)
+fn rewrap(content: &str, width: usize) -> String {
+    let mut verbatim = fenced_code_ranges(content);
+    verbatim.extend(front_matter_range(content));
+    verbatim.extend(parse_headings(content).into_iter().map(|h| (h.start, h.end + 1)));
+
+    let mut lines: Vec<(usize, &str)> = Vec::new();
+    let mut offset = 0;
+    for line in content.split('\n') {
+        lines.push((offset, line));
+        offset += line.len() + 1;
+    }
+
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    // Content column of the list item the previous paragraph belonged to,
+    // so indented lines after a blank line continue it rather than being
+    // code
+    let mut list_indent: Option<usize> = None;
+    let mut previous_blank = true;
+    let mut i = 0;
+    while i < lines.len() {
+        let (start, line) = lines[i];
+        let (quote, text) = split_quote(line.trim_end_matches('\r'));
+        let indent = text.len() - text.trim_start_matches(' ').len();
+        let blank = text.trim().is_empty();
+        let indented_code = previous_blank && indent >= 4 && list_indent.is_none_or(|li| indent < li);
+        if in_ranges(&verbatim, start) || blank || is_block_line(text) || indented_code || text.contains('\t') {
+            if !blank && !indented_code {
+                list_indent = None;
+            }
+            previous_blank = blank;
+            out.push(line.to_string());
+            i += 1;
+            continue;
+        }
+
+        // Collect the paragraph: lines at the same quote depth that don't
+        // start a new block or list item
+        let marker = list_marker(text);
+        let (first_prefix, prefix) = match marker {
+            Some(len) => {
+                list_indent = Some(len);
+                (format!("{}{}", quote, &text[..len]), format!("{}{}", quote, " ".repeat(len)))
+            }
+            None => {
+                if list_indent.is_some_and(|li| indent < li) {
+                    list_indent = None;
+                }
+                let lead = format!("{}{}", quote, &text[..indent]);
+                (lead.clone(), lead)
+            }
+        };
+        let depth = quote.matches('>').count();
+        let mut raw = vec![&text[marker.unwrap_or(indent)..]];
+        let mut j = i + 1;
+        while let Some(&(next_start, next)) = lines.get(j) {
+            let (next_quote, next_text) = split_quote(next.trim_end_matches('\r'));
+            if in_ranges(&verbatim, next_start)
+                || next_quote.matches('>').count() != depth
+                || next_text.trim().is_empty()
+                || is_block_line(next_text)
+                || list_marker(next_text).is_some()
+                || next_text.contains('\t')
+            {
+                break;
+            }
+            raw.push(next_text.trim_start());
+            j += 1;
+        }
+        // A table without leading pipes looks like a paragraph until its
+        // separator row
+        if raw.iter().any(|l| is_table_separator(l)) || lines.get(j).is_some_and(|&(_, l)| is_table_separator(split_quote(l).1)) {
+            out.extend(lines[i..j].iter().map(|(_, l)| l.to_string()));
+        } else {
+            let mut paragraph = Paragraph { first_prefix, prefix, segments: Vec::new() };
+            let mut current = String::new();
+            for line in raw {
+                let (text, hard) = hard_break(line.trim_end_matches('\r'));
+                if !current.is_empty() {
+                    current.push(' ');
+                }
+                current.push_str(text);
+                if !hard.is_empty() || text.ends_with('\\') {
+                    paragraph.segments.push((std::mem::take(&mut current), hard));
+                }
+            }
+            if !current.is_empty() || paragraph.segments.is_empty() {
+                paragraph.segments.push((current, ""));
+            }
+            paragraph.render(width, &mut out);
+        }
+        previous_blank = false;
+        i = j;
+    }
+    out.join("\n")
+}
+
+/// Hard-wrap paragraphs at `width` columns (at least 20). List items keep
+/// their marker with continuation lines indented under the text,
+/// blockquote lines keep their `>` prefix, and hard line breaks are kept.
+/// Code blocks, tables, headings, HTML and front matter are left as they
+/// are, and long words such as URLs are never split.
+#[wasm_bindgen]
+pub fn reflow(content: &str, width: usize) -> String {
+    rewrap(content, width.max(MIN_WIDTH))
+}
+
+/// Join hard-wrapped lines so each paragraph (or list item) is one line;
+/// the inverse of `reflow`. Hard line breaks are kept.
+#[wasm_bindgen]
+pub fn unwrap_paragraphs(content: &str) -> String {
+    rewrap(content, usize::MAX)
+}
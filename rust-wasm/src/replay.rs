@@ -0,0 +1,130 @@
+// Time travel through the edit log: rebuild the document as it was at any
+// edit, and step through the edits one at a time for a playback scrubber.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+
+use crate::edit_log::{read_edit_log, Edit};
+
+/// What an applied edit replaced, to undo it when stepping back
+struct Undo {
+    start: usize,
+    removed: String,
+    inserted_len: usize,
+}
+
+#[derive(Serialize)]
+struct EditInfo<'a> {
+    /// 1-based position of the edit in the log
+    seq: usize,
+    edit_type: &'a str,
+    user_id: &'a str,
+    timestamp: f64,
+}
+
+/// Number of edits to apply to reach `until`: a count of edits when
+/// `unit` is "seq", or every edit stamped at or before it when "timestamp"
+fn edits_until(edits: &[Edit], until: f64, unit: &str) -> Result<usize, JsValue> {
+    match unit {
+        "seq" => Ok((until.max(0.0) as usize).min(edits.len())),
+        "timestamp" => Ok(edits.iter().take_while(|e| e.timestamp <= until).count()),
+        _ => Err(JsValue::from_str(&format!("Unknown replay unit: {} (expected \"seq\" or \"timestamp\")", unit))),
+    }
+}
+
+/// The document as it was after the edits of `edit_log` (concatenated
+/// edit messages, as sent) up to `until`, applied in log order from an
+/// empty document. `unit` is "seq" to stop after that many edits, or
+/// "timestamp" to stop at the last edit stamped at or before `until` (ms).
+#[wasm_bindgen]
+pub fn replay_to(edit_log: &[u8], until: f64, unit: &str) -> Result<String, JsValue> {
+    let edits = read_edit_log(edit_log).map_err(|e| JsValue::from_str(&e))?;
+    let count = edits_until(&edits, until, unit)?;
+    let mut text = String::new();
+    for edit in &edits[..count] {
+        edit.apply(&mut text);
+    }
+    Ok(text)
+}
+
+/// Steps through an edit log one edit at a time, forwards and backwards,
+/// for a playback scrubber. Starts before the first edit, on an empty
+/// document.
+#[wasm_bindgen]
+pub struct EditPlayer {
+    edits: Vec<Edit>,
+    text: String,
+    /// One entry per applied edit
+    undo: Vec<Undo>,
+}
+
+#[wasm_bindgen]
+impl EditPlayer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(edit_log: &[u8]) -> Result<EditPlayer, JsValue> {
+        let edits = read_edit_log(edit_log).map_err(|e| JsValue::from_str(&e))?;
+        Ok(EditPlayer { edits, text: String::new(), undo: Vec::new() })
+    }
+
+    /// Number of edits in the log
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Number of edits applied so far
+    pub fn position(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// The document at the current position
+    pub fn content(&self) -> String {
+        self.text.clone()
+    }
+
+    /// Apply the next edit. Returns false at the end of the log.
+    pub fn step_forward(&mut self) -> bool {
+        let Some(edit) = self.edits.get(self.undo.len()) else {
+            return false;
+        };
+        let (range, inserted) = edit.span(&self.text);
+        self.undo.push(Undo { start: range.start, removed: self.text[range.clone()].to_string(), inserted_len: inserted.len() });
+        self.text.replace_range(range, inserted);
+        true
+    }
+
+    /// Undo the last applied edit. Returns false at the start.
+    pub fn step_back(&mut self) -> bool {
+        let Some(undo) = self.undo.pop() else {
+            return false;
+        };
+        self.text.replace_range(undo.start..undo.start + undo.inserted_len, &undo.removed);
+        true
+    }
+
+    /// Move to the point after `seq` edits
+    pub fn seek(&mut self, seq: usize) {
+        let seq = seq.min(self.edits.len());
+        while self.undo.len() > seq && self.step_back() {}
+        while self.undo.len() < seq && self.step_forward() {}
+    }
+
+    /// Move to the last edit stamped at or before `timestamp` (ms)
+    pub fn seek_time(&mut self, timestamp: f64) {
+        let seq = self.edits.iter().take_while(|e| e.timestamp <= timestamp).count();
+        self.seek(seq);
+    }
+
+    /// JSON `{seq, edit_type, user_id, timestamp}` of the edit applied
+    /// last, or `null` at the start
+    pub fn current_edit(&self) -> String {
+        let info = self.undo.len().checked_sub(1).map(|n| {
+            let edit = &self.edits[n];
+            EditInfo { seq: n + 1, edit_type: &edit.edit_type, user_id: &edit.user_id, timestamp: edit.timestamp }
+        });
+        serde_json::to_string(&info).unwrap_or_else(|_| "null".into())
+    }
+}
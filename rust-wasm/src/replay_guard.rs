@@ -0,0 +1,135 @@
+// Replay protection. Every message carries a random nonce and the time it
+// was sent; a receiver's ReplayGuard remembers the (sender, nonce) pairs
+// it accepted within the acceptance window and refuses a message seen
+// before or sent outside the window, so an edit captured off the wire
+// can't be played back into the document later.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::hash_chain::to_hex;
+use crate::share::random_bytes;
+use crate::{data_f64, data_text, decode_with_grid_tag};
+
+/// How old a message may be when it arrives, by default (ms)
+const DEFAULT_MAX_AGE_MS: f64 = 10.0 * 60_000.0;
+
+/// How far ahead of the receiver's clock a sender's may run (ms)
+const DEFAULT_MAX_SKEW_MS: f64 = 2.0 * 60_000.0;
+
+/// Pairs remembered at most; past this the oldest are forgotten early and
+/// anything sent no later than them is refused as stale, which narrows the
+/// window for very busy documents
+const MAX_REMEMBERED: usize = 100_000;
+
+/// Give an outgoing message a fresh nonce and its send time
+pub(crate) fn stamp(data: &mut HashMap<String, ciborium::Value>) {
+    let mut nonce = [0u8; 16];
+    if random_bytes(&mut nonce).is_ok() {
+        data.insert("nonce".to_string(), ciborium::Value::Text(to_hex(&nonce)));
+    }
+    data.insert("sent_at".to_string(), ciborium::Value::Float(js_sys::Date::now()));
+}
+
+#[derive(Serialize)]
+struct Verdict<'a> {
+    accepted: bool,
+    /// "ok", "duplicate", "stale", "future" or "unprotected" (no nonce or
+    /// send time)
+    reason: &'static str,
+    user_id: Option<&'a str>,
+    nonce: Option<&'a str>,
+}
+
+/// Refuses replayed messages. Check every incoming message with `check`
+/// before applying it; the window should be longer than senders keep
+/// messages for resending, or late resends are refused as stale.
+#[wasm_bindgen]
+pub struct ReplayGuard {
+    max_age_ms: f64,
+    max_skew_ms: f64,
+    /// Send time of each accepted (sender, nonce)
+    seen: HashMap<(String, String), f64>,
+    /// Send time of the newest pair forgotten over the cap; messages sent
+    /// at or before it can't be told from replays
+    floor: f64,
+    last_prune: f64,
+}
+
+#[wasm_bindgen]
+impl ReplayGuard {
+    /// Accept messages sent up to `max_age_ms` before and `max_skew_ms`
+    /// after `now`; 0 uses the defaults of 10 and 2 minutes
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_age_ms: f64, max_skew_ms: f64) -> ReplayGuard {
+        ReplayGuard {
+            max_age_ms: if max_age_ms > 0.0 { max_age_ms } else { DEFAULT_MAX_AGE_MS },
+            max_skew_ms: if max_skew_ms > 0.0 { max_skew_ms } else { DEFAULT_MAX_SKEW_MS },
+            seen: HashMap::new(),
+            floor: f64::NEG_INFINITY,
+            last_prune: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Check a message received at `now`, remembering it when accepted.
+    /// Returns JSON `{accepted, reason, user_id, nonce}`; drop the message
+    /// unless `accepted`. A duplicate may be a retry whose ack was lost, so
+    /// it is still worth acknowledging.
+    pub fn check(&mut self, cbor_bytes: &[u8], now: f64) -> Result<String, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let data = &message.payload.data;
+        let user_id = data_text(data, "user_id");
+        let nonce = data_text(data, "nonce");
+        let reason = match (nonce, data_f64(data, "sent_at")) {
+            (Some(nonce), Some(sent_at)) => self.admit(user_id.unwrap_or(""), nonce, sent_at, now),
+            _ => "unprotected",
+        };
+        let verdict = Verdict { accepted: reason == "ok", reason, user_id, nonce };
+        Ok(serde_json::to_string(&verdict).unwrap_or_else(|_| "{}".into()))
+    }
+
+    /// Number of (sender, nonce) pairs remembered
+    pub fn remembered(&self) -> usize {
+        self.seen.len()
+    }
+}
+
+impl ReplayGuard {
+    fn admit(&mut self, user_id: &str, nonce: &str, sent_at: f64, now: f64) -> &'static str {
+        if now - sent_at > self.max_age_ms || sent_at <= self.floor {
+            return "stale";
+        }
+        if sent_at - now > self.max_skew_ms {
+            return "future";
+        }
+        let key = (user_id.to_string(), nonce.to_string());
+        if self.seen.contains_key(&key) {
+            return "duplicate";
+        }
+        self.prune(now);
+        self.seen.insert(key, sent_at);
+        "ok"
+    }
+
+    /// Forget pairs old enough to be refused as stale anyway, at most once
+    /// a second, and the oldest when over the cap, raising the floor to
+    /// the newest of those
+    fn prune(&mut self, now: f64) {
+        if now - self.last_prune >= 1_000.0 {
+            self.last_prune = now;
+            let cutoff = now - self.max_age_ms;
+            self.seen.retain(|_, sent_at| *sent_at >= cutoff);
+        }
+        if self.seen.len() >= MAX_REMEMBERED {
+            let mut times: Vec<f64> = self.seen.values().copied().collect();
+            times.sort_by(f64::total_cmp);
+            let keep_from = times.len() - MAX_REMEMBERED / 2;
+            let cutoff = times[keep_from];
+            if let Some(evicted) = times[..keep_from].iter().rev().find(|sent_at| **sent_at < cutoff) {
+                self.floor = self.floor.max(*evicted);
+            }
+            self.seen.retain(|_, sent_at| *sent_at >= cutoff);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// reStructuredText export for publishing with Sphinx. Headings get
+// underlines by level, code blocks and math become directives, tables a
+// `list-table`, alert blockquotes admonitions and wiki links `:doc:` roles.
+
+use wasm_bindgen::prelude::*;
+
+use crate::ast::{alert, indent_lines, parse_markdown, Block, Inline, ListItem};
+
+/// Underline characters by heading level; level 1 is also overlined
+const UNDERLINES: [char; 6] = ['=', '=', '-', '~', '^', '"'];
+
+/// Escape characters that start inline markup. A `_` only needs escaping
+/// where it would end a reference name (`word_ `).
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let ends_word = chars.peek().is_none_or(|n| !n.is_alphanumeric());
+        if matches!(c, '\\' | '*' | '`' | '|') || (c == '_' && ends_word) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Footnote labels as rst accepts them for `[#label]_`
+fn footnote_label(label: &str) -> String {
+    label.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' }).collect()
+}
+
+fn inlines(items: &[Inline]) -> String {
+    let mut out = String::new();
+    for inline in items {
+        match inline {
+            Inline::Text { text } => out.push_str(&escape(text)),
+            Inline::Code { code } => out.push_str(&format!("``{}``", code)),
+            Inline::Emphasis { content } => out.push_str(&format!("*{}*", inlines_plain(content))),
+            Inline::Strong { content } => out.push_str(&format!("**{}**", inlines_plain(content))),
+            // No rst markup for these; the text is kept
+            Inline::Strikethrough { content } | Inline::Underline { content } => out.push_str(&inlines(content)),
+            Inline::Link { url, content, .. } => {
+                let text = inlines_plain(content);
+                if text.is_empty() || text == escape(url) {
+                    out.push_str(&format!("`<{}>`__", url));
+                } else {
+                    out.push_str(&format!("`{} <{}>`__", text.replace('<', "\\<"), url));
+                }
+            }
+            // Inline images have no direct equivalent; standalone ones
+            // become `.. image::` directives
+            Inline::Image { alt, .. } => out.push_str(&escape(alt)),
+            Inline::Math { tex } => out.push_str(&format!(":math:`{}`", tex)),
+            Inline::FootnoteRef { label } => {
+                // An escaped space lets the reference follow a word directly
+                if !out.is_empty() && !out.ends_with(char::is_whitespace) {
+                    out.push_str("\\ ");
+                }
+                out.push_str(&format!("[#{}]_", footnote_label(label)));
+            }
+            Inline::WikiLink { target, alias, .. } => match alias {
+                Some(alias) => out.push_str(&format!(":doc:`{} <{}>`", alias, target)),
+                None => out.push_str(&format!(":doc:`{}`", target)),
+            },
+            Inline::Html { .. } => {}
+            Inline::SoftBreak => out.push('\n'),
+            Inline::LineBreak => out.push(' '),
+        }
+    }
+    out
+}
+
+/// Inline content inside emphasis or link text, where rst allows no nested
+/// markup: only the escaped text is kept
+fn inlines_plain(content: &[Inline]) -> String {
+    escape(&crate::ast::inline_text(content))
+}
+
+fn heading(level: u8, content: &[Inline]) -> String {
+    let text = inlines(content).replace('\n', " ");
+    let rule: String = std::iter::repeat_n(UNDERLINES[(level as usize).clamp(1, 6) - 1], text.chars().count().max(1)).collect();
+    if level == 1 {
+        format!("{}\n{}\n{}", rule, text, rule)
+    } else {
+        format!("{}\n{}", text, rule)
+    }
+}
+
+/// A directive with its options and indented body
+fn directive(name: &str, argument: &str, options: &[String], body: &str) -> String {
+    let mut out = format!(".. {}::", name);
+    if !argument.is_empty() {
+        out.push(' ');
+        out.push_str(argument);
+    }
+    for option in options {
+        out.push_str(&format!("\n   {}", option));
+    }
+    if !body.is_empty() {
+        out.push_str("\n\n");
+        out.push_str(&indent_lines(body, "   ", "   "));
+    }
+    out
+}
+
+fn item(item: &ListItem, marker: &str) -> String {
+    let task = match item.checked {
+        Some(true) => "[x] ",
+        Some(false) => "[ ] ",
+        None => "",
+    };
+    indent_lines(&format!("{}{}", task, blocks(&item.blocks)), marker, &" ".repeat(marker.len()))
+}
+
+fn table(header: &[Vec<Inline>], rows: &[Vec<Vec<Inline>>]) -> String {
+    let mut body = Vec::new();
+    for row in std::iter::once(header).chain(rows.iter().map(Vec::as_slice)) {
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(n, cell)| {
+                let text = inlines(cell).replace('\n', " ");
+                let marker = if n == 0 { "* - " } else { "  - " };
+                format!("{}{}", marker, text).trim_end().to_string()
+            })
+            .collect();
+        body.push(cells.join("\n"));
+    }
+    directive("list-table", "", &[":header-rows: 1".to_string()], &body.join("\n"))
+}
+
+fn block(block: &Block) -> String {
+    match block {
+        Block::Heading { level, content } => heading(*level, content),
+        Block::Paragraph { content } => match content.as_slice() {
+            [Inline::Image { url, alt, .. }] => directive("image", url, &[format!(":alt: {}", alt)], ""),
+            _ => inlines(content),
+        },
+        Block::CodeBlock { language, code } if language.is_empty() => format!("::\n\n{}", indent_lines(code, "   ", "   ")),
+        Block::CodeBlock { language, code } => directive("code-block", language, &[], code),
+        Block::BlockQuote { blocks: quoted } => match alert(quoted) {
+            Some((kind, body)) => directive(kind, "", &[], &blocks(&body)),
+            // The empty comment keeps the quote from continuing an
+            // indented block above it
+            None => format!("..\n\n{}", indent_lines(&blocks(quoted), "   ", "   ")),
+        },
+        Block::List { ordered, start, items } => items
+            .iter()
+            .enumerate()
+            .map(|(n, i)| item(i, &if *ordered { format!("{}. ", start + n as u64) } else { "- ".to_string() }))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Block::Table { header, rows, .. } => table(header, rows),
+        Block::ThematicBreak => "----".to_string(),
+        Block::Html { html } => directive("raw", "html", &[], html),
+        Block::MathBlock { tex } => directive("math", "", &[], tex),
+        Block::FootnoteDefinition { label, blocks: body } => {
+            let marker = format!(".. [#{}] ", footnote_label(label));
+            indent_lines(&blocks(body), &marker, "   ")
+        }
+    }
+}
+
+fn blocks(blocks: &[Block]) -> String {
+    blocks.iter().map(block).collect::<Vec<_>>().join("\n\n")
+}
+
+/// Export markdown as reStructuredText for Sphinx. Headings are underlined
+/// `=`, `-`, `~`, `^`, `"` by level (level 1 also overlined), fenced code
+/// becomes `.. code-block:: <language>`, tables `.. list-table::`,
+/// GitHub-style alerts (`> [!NOTE]`) the matching admonition, math
+/// `:math:` and `.. math::`, footnotes auto-numbered `[#label]_` and wiki
+/// links `:doc:` references. HTML blocks pass through `.. raw:: html`;
+/// front matter and inline HTML tags are dropped.
+#[wasm_bindgen]
+pub fn export_rst(content: &str) -> String {
+    let doc = parse_markdown(content);
+    let mut out = blocks(&doc.blocks);
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,478 @@
+// RTF reader for imports: the text of an RTF document with its paragraph
+// structure, bold/italic/underline/strikethrough runs, hyperlinks, list
+// paragraphs, outline-level headings and simple tables. Fonts, colours,
+// pictures and page layout are dropped.
+
+use serde_json::{Map, Value};
+
+use crate::ast::{Align, Block, Document, Inline, ListItem};
+
+/// Destinations whose content is not document text
+const SKIPPED_DESTINATIONS: &[&str] = &[
+    "fonttbl", "colortbl", "stylesheet", "pict", "object", "header", "headerl", "headerr", "headerf", "footer",
+    "footerl", "footerr", "footerf", "listtable", "listoverridetable", "rsidtbl", "generator", "xmlnstbl",
+    "themedata", "colorschememapping", "datastore", "latentstyles", "pgdsctbl", "filetbl", "revtbl", "nonshppict",
+    "footnote", "annotation", "bkmkstart", "bkmkend",
+];
+
+/// Info group fields kept as metadata
+const INFO_FIELDS: &[&str] = &["title", "subject", "author", "keywords", "company"];
+
+/// Windows-1252 characters for bytes 0x80-0x9F; other bytes are Latin-1
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}', '\u{90}', '‘', '’',
+    '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+fn cp1252(byte: u8) -> char {
+    match byte {
+        0x80..=0x9f => CP1252_HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+struct CharStyle {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+}
+
+/// What the text of the current group is for
+#[derive(Clone, PartialEq)]
+enum Destination {
+    Text,
+    Skip,
+    /// The info group, whose fields hold metadata
+    InfoGroup,
+    /// A field of the info group
+    Info(String),
+    /// A field instruction such as `HYPERLINK "url"`
+    FieldInstruction,
+    /// The marker text of a list paragraph
+    ListText,
+}
+
+#[derive(Clone)]
+struct GroupState {
+    style: CharStyle,
+    destination: Destination,
+    /// Characters to skip after `\uN`
+    unicode_skip: usize,
+    link: Option<String>,
+}
+
+#[derive(Clone, PartialEq)]
+struct Span {
+    text: String,
+    style: CharStyle,
+    link: Option<String>,
+}
+
+#[derive(Default)]
+struct Reader {
+    metadata: Map<String, Value>,
+    blocks: Vec<Block>,
+    spans: Vec<Span>,
+    /// `\outlinelevelN` of the current paragraph
+    outline_level: Option<u8>,
+    list_marker: String,
+    in_table: bool,
+    cell: Vec<Inline>,
+    row: Vec<Vec<Inline>>,
+    table: Vec<Vec<Vec<Inline>>>,
+    /// Instruction text of the innermost field
+    field_instruction: String,
+    /// Text of the current info field
+    info_text: String,
+    /// A high surrogate waiting for its pair
+    surrogate: Option<u16>,
+}
+
+impl Reader {
+    fn push_text(&mut self, state: &GroupState, text: &str) {
+        match &state.destination {
+            Destination::Text => match self.spans.last_mut() {
+                Some(last) if last.style == state.style && last.link == state.link => last.text.push_str(text),
+                _ => self.spans.push(Span { text: text.to_string(), style: state.style, link: state.link.clone() }),
+            },
+            Destination::Info(_) => self.info_text.push_str(text),
+            Destination::FieldInstruction => self.field_instruction.push_str(text),
+            Destination::ListText => self.list_marker.push_str(text),
+            Destination::Skip | Destination::InfoGroup => {}
+        }
+    }
+
+    fn push_char(&mut self, state: &GroupState, c: char) {
+        let mut buf = [0u8; 4];
+        self.push_text(state, c.encode_utf8(&mut buf));
+    }
+
+    fn push_unicode(&mut self, state: &GroupState, unit: u16) {
+        match (self.surrogate.take(), unit) {
+            (None, 0xd800..=0xdbff) => self.surrogate = Some(unit),
+            (Some(high), 0xdc00..=0xdfff) => {
+                let c = 0x10000 + ((high as u32 - 0xd800) << 10) + (unit as u32 - 0xdc00);
+                self.push_char(state, char::from_u32(c).unwrap_or('\u{fffd}'));
+            }
+            (_, unit) => self.push_char(state, char::from_u32(unit as u32).unwrap_or('\u{fffd}')),
+        }
+    }
+
+    /// The current paragraph's runs as inline content
+    fn take_inlines(&mut self) -> Vec<Inline> {
+        let spans = std::mem::take(&mut self.spans);
+        let mut out: Vec<Inline> = Vec::new();
+        let mut link: Option<(String, Vec<Inline>)> = None;
+        for span in spans {
+            let mut inline = Inline::Text { text: span.text.replace('\t', " ") };
+            if span.style.strike {
+                inline = Inline::Strikethrough { content: vec![inline] };
+            }
+            if span.style.underline && span.link.is_none() {
+                inline = Inline::Underline { content: vec![inline] };
+            }
+            if span.style.italic {
+                inline = Inline::Emphasis { content: vec![inline] };
+            }
+            if span.style.bold {
+                inline = Inline::Strong { content: vec![inline] };
+            }
+            match (&mut link, span.link) {
+                (Some((url, content)), Some(next)) if *url == next => content.push(inline),
+                (_, next) => {
+                    if let Some((url, content)) = link.take() {
+                        out.push(Inline::Link { url, title: None, content });
+                    }
+                    match next {
+                        Some(url) => link = Some((url, vec![inline])),
+                        None => out.push(inline),
+                    }
+                }
+            }
+        }
+        if let Some((url, content)) = link {
+            out.push(Inline::Link { url, title: None, content });
+        }
+        trim_inlines(out)
+    }
+
+    /// End the current paragraph (`\par`)
+    fn paragraph(&mut self) {
+        if self.in_table {
+            let content = self.take_inlines();
+            if !content.is_empty() {
+                if !self.cell.is_empty() {
+                    self.cell.push(Inline::Text { text: " ".to_string() });
+                }
+                self.cell.extend(content);
+            }
+            return;
+        }
+        self.flush_table();
+        let content = self.take_inlines();
+        let marker = std::mem::take(&mut self.list_marker);
+        let marker = marker.trim();
+        if content.is_empty() {
+            return;
+        }
+        if let Some(level) = self.outline_level.filter(|l| *l < 6) {
+            self.blocks.push(Block::Heading { level: level + 1, content });
+            return;
+        }
+        if !marker.is_empty() {
+            let number = marker.trim_end_matches(['.', ')']).parse::<u64>().ok();
+            let item = ListItem { checked: None, blocks: vec![Block::Paragraph { content }] };
+            if let Some(Block::List { ordered, items, .. }) = self.blocks.last_mut() {
+                if *ordered == number.is_some() {
+                    items.push(item);
+                    return;
+                }
+            }
+            self.blocks.push(Block::List { ordered: number.is_some(), start: number.unwrap_or(1), items: vec![item] });
+            return;
+        }
+        self.blocks.push(Block::Paragraph { content });
+    }
+
+    fn end_cell(&mut self) {
+        self.paragraph();
+        let cell = std::mem::take(&mut self.cell);
+        self.row.push(cell);
+    }
+
+    fn end_row(&mut self) {
+        let row = std::mem::take(&mut self.row);
+        if !row.is_empty() {
+            self.table.push(row);
+        }
+    }
+
+    fn flush_table(&mut self) {
+        if self.table.is_empty() {
+            return;
+        }
+        let mut rows = std::mem::take(&mut self.table);
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        for row in &mut rows {
+            row.resize(columns, Vec::new());
+        }
+        let header = rows.remove(0);
+        self.blocks.push(Block::Table { align: vec![Align::None; columns], header, rows });
+    }
+
+    /// Handle a control word; `state` is the current group's state and
+    /// `group_start` whether the word opens its group (a destination)
+    fn control(&mut self, word: &str, param: Option<i32>, state: &mut GroupState, group_start: bool) {
+        match state.destination {
+            Destination::Skip => return,
+            Destination::InfoGroup => {
+                if group_start && INFO_FIELDS.contains(&word) {
+                    self.info_text.clear();
+                    state.destination = Destination::Info(word.to_string());
+                } else if group_start {
+                    state.destination = Destination::Skip;
+                }
+                return;
+            }
+            _ => {}
+        }
+        if group_start {
+            match word {
+                "info" => state.destination = Destination::InfoGroup,
+                "fldinst" => {
+                    self.field_instruction.clear();
+                    state.destination = Destination::FieldInstruction;
+                }
+                "fldrslt" => state.link = hyperlink_target(&self.field_instruction),
+                "listtext" | "pntext" => {
+                    self.list_marker.clear();
+                    state.destination = Destination::ListText;
+                }
+                _ if SKIPPED_DESTINATIONS.contains(&word) => state.destination = Destination::Skip,
+                _ => {}
+            }
+        }
+        let on = param != Some(0);
+        match word {
+            "line" => self.push_text(state, "\n"),
+            "tab" => self.push_text(state, "\t"),
+            "emdash" => self.push_char(state, '—'),
+            "endash" => self.push_char(state, '–'),
+            "bullet" => self.push_char(state, '•'),
+            "lquote" => self.push_char(state, '‘'),
+            "rquote" => self.push_char(state, '’'),
+            "ldblquote" => self.push_char(state, '“'),
+            "rdblquote" => self.push_char(state, '”'),
+            "uc" => state.unicode_skip = param.unwrap_or(1).max(0) as usize,
+            "u" => {
+                if let Some(p) = param {
+                    self.push_unicode(state, p as u16);
+                }
+            }
+            "b" => state.style.bold = on,
+            "i" => state.style.italic = on,
+            "ul" => state.style.underline = on,
+            "ulnone" => state.style.underline = false,
+            "strike" | "striked" => state.style.strike = on,
+            "plain" => state.style = CharStyle::default(),
+            // Paragraph structure only counts in the document text
+            _ if state.destination != Destination::Text => {}
+            "par" | "sect" | "page" => self.paragraph(),
+            "pard" => {
+                self.outline_level = None;
+                self.in_table = false;
+            }
+            "outlinelevel" => self.outline_level = param.map(|p| p.clamp(0, 9) as u8),
+            "intbl" => self.in_table = true,
+            "cell" => self.end_cell(),
+            "row" => self.end_row(),
+            _ => {}
+        }
+    }
+}
+
+/// The URL of a `HYPERLINK "url"` field instruction
+fn hyperlink_target(instruction: &str) -> Option<String> {
+    let rest = instruction.trim().strip_prefix("HYPERLINK")?.trim();
+    let url = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next()?,
+        None => rest.split(' ').next()?,
+    };
+    (!url.is_empty()).then(|| url.to_string())
+}
+
+/// Trim whitespace at the ends of a paragraph's text
+fn trim_inlines(mut inlines: Vec<Inline>) -> Vec<Inline> {
+    fn trim(inline: &mut Inline, start: bool) -> bool {
+        match inline {
+            Inline::Text { text } => {
+                *text = if start { text.trim_start().to_string() } else { text.trim_end().to_string() };
+                text.is_empty()
+            }
+            Inline::Strong { content }
+            | Inline::Emphasis { content }
+            | Inline::Underline { content }
+            | Inline::Strikethrough { content }
+            | Inline::Link { content, .. } => {
+                let edge = if start { content.first_mut() } else { content.last_mut() };
+                edge.is_none_or(|i| trim(i, start))
+            }
+            _ => false,
+        }
+    }
+    while inlines.first_mut().is_some_and(|i| trim(i, true)) {
+        inlines.remove(0);
+    }
+    while inlines.last_mut().is_some_and(|i| trim(i, false)) {
+        inlines.pop();
+    }
+    inlines
+}
+
+/// Read an RTF document into a document tree, with the info group's
+/// `title`, `subject`, `author`, `keywords` and `company` as metadata
+pub(crate) fn rtf_to_document(rtf: &str) -> (Document, Map<String, Value>) {
+    let mut reader = Reader::default();
+    let mut stack: Vec<GroupState> = Vec::new();
+    let mut state = GroupState { style: CharStyle::default(), destination: Destination::Text, unicode_skip: 1, link: None };
+    // Characters still to skip after a `\uN`
+    let mut skip = 0usize;
+    let mut group_start = false;
+    // After `\*`: the group is skipped unless its destination is known
+    let mut ignorable = false;
+    let bytes = rtf.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            b'{' => {
+                stack.push(state.clone());
+                group_start = true;
+                skip = 0;
+                i += 1;
+                continue;
+            }
+            b'}' => {
+                if let Destination::Info(field) = &state.destination {
+                    let text = reader.info_text.trim().to_string();
+                    if !text.is_empty() {
+                        reader.metadata.insert(field.clone(), Value::String(text));
+                    }
+                }
+                if let Some(outer) = stack.pop() {
+                    state = outer;
+                }
+                skip = 0;
+                i += 1;
+            }
+            b'\\' => {
+                let Some(&next) = bytes.get(i + 1) else { break };
+                if next.is_ascii_alphabetic() {
+                    let start = i + 1;
+                    let mut end = start;
+                    while end < bytes.len() && bytes[end].is_ascii_alphabetic() {
+                        end += 1;
+                    }
+                    let word = &rtf[start..end];
+                    let mut param_end = end;
+                    if param_end < bytes.len() && bytes[param_end] == b'-' {
+                        param_end += 1;
+                    }
+                    while param_end < bytes.len() && bytes[param_end].is_ascii_digit() {
+                        param_end += 1;
+                    }
+                    let param = rtf[end..param_end].parse::<i32>().ok();
+                    i = param_end;
+                    if bytes.get(i) == Some(&b' ') {
+                        i += 1;
+                    }
+                    if skip > 0 && word != "u" {
+                        // A control word stands in for one skipped character
+                        skip -= 1;
+                    } else if ignorable && word != "fldinst" {
+                        state.destination = Destination::Skip;
+                    } else {
+                        reader.control(word, param, &mut state, group_start);
+                        if word == "u" {
+                            skip = state.unicode_skip;
+                        }
+                    }
+                } else {
+                    // The symbol after the backslash may be a multibyte character
+                    let symbol = rtf[i + 1..].chars().next().unwrap_or('\\');
+                    i += 1 + symbol.len_utf8();
+                    match next {
+                        b'\'' => {
+                            let digits = bytes[i..].iter().take(2).take_while(|b| b.is_ascii_hexdigit()).count();
+                            let hex = u8::from_str_radix(&rtf[i..i + digits], 16).ok().filter(|_| digits == 2);
+                            i += digits;
+                            if skip > 0 {
+                                skip -= 1;
+                            } else if let Some(byte) = hex {
+                                reader.push_char(&state, cp1252(byte));
+                            }
+                        }
+                        b'*' if group_start => {
+                            ignorable = true;
+                            continue;
+                        }
+                        b'~' => reader.push_char(&state, '\u{a0}'),
+                        b'_' => reader.push_char(&state, '‑'),
+                        b'\n' | b'\r' => reader.paragraph(),
+                        b'\\' | b'{' | b'}' => reader.push_char(&state, next as char),
+                        // Not a control symbol, so keep the character as text
+                        _ if !symbol.is_ascii() => reader.push_char(&state, symbol),
+                        _ => {}
+                    }
+                }
+            }
+            b'\r' | b'\n' => i += 1,
+            _ => {
+                // Plain text up to the next special character
+                let start = i;
+                while i < bytes.len() && !matches!(bytes[i], b'{' | b'}' | b'\\' | b'\r' | b'\n') {
+                    i += 1;
+                }
+                let mut text = &rtf[start..i];
+                while skip > 0 && !text.is_empty() {
+                    let width = text.chars().next().map_or(1, char::len_utf8);
+                    text = &text[width..];
+                    skip -= 1;
+                }
+                reader.push_text(&state, text);
+            }
+        }
+        group_start = false;
+        ignorable = false;
+    }
+    reader.paragraph();
+    reader.flush_table();
+    let blocks = reader.blocks;
+    (Document { front_matter: None, lines: vec![0; blocks.len()], blocks }, reader.metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::to_markdown;
+
+    fn text(rtf: &str) -> String {
+        to_markdown(&rtf_to_document(rtf).0).trim().to_string()
+    }
+
+    #[test]
+    fn non_ascii_after_backslash_is_kept() {
+        assert_eq!(text("\\é"), "é");
+        assert_eq!(text("{\\rtf1 \\é}"), "é");
+        assert_eq!(text("a\\\u{FFFD}b"), "a\u{FFFD}b");
+    }
+
+    #[test]
+    fn non_ascii_in_hex_escape_is_skipped() {
+        assert_eq!(text("{\\rtf1 \\'é1 x}"), "é1 x");
+        assert_eq!(text("{\\rtf1 \\'aé}"), "é");
+        assert_eq!(text("{\\rtf1 caf\\'e9}"), "café");
+    }
+}
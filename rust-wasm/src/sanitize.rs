@@ -0,0 +1,230 @@
+// Allowlist HTML sanitizer for pasted rich content and raw HTML in
+// markdown. Works on the tag stream rather than a tree so fragments such
+// as a lone `<span class="x">` from inline HTML pass through as written.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::html::{decode_entities, parse_attrs, VOID_ELEMENTS};
+use crate::markdown::escape_html;
+
+/// Elements removed together with their content, whatever the policy says
+const REMOVED_ELEMENTS: &[&str] = &[
+    "script", "style", "iframe", "frame", "frameset", "object", "embed", "applet", "noscript", "template", "title",
+    "head", "xml", "svg", "math", "textarea", "select", "button",
+];
+
+/// Attributes holding a URL, checked against the allowed schemes
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "cite", "action", "formaction", "poster", "background", "xlink:href"];
+
+/// Image types allowed in `data:` URLs
+const DATA_IMAGE_TYPES: &[&str] = &["data:image/png", "data:image/jpeg", "data:image/gif", "data:image/webp"];
+
+/// Which elements and attributes survive. Missing fields take their
+/// default, so `{"tags": ["p", "a"]}` is a complete policy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct SanitizePolicy {
+    /// Elements kept; any other element is dropped but its content kept
+    pub tags: Vec<String>,
+    /// Attributes allowed on every kept element
+    pub attributes: Vec<String>,
+    /// Further attributes allowed on particular elements
+    pub tag_attributes: BTreeMap<String, Vec<String>>,
+    /// Schemes allowed in `href` and `src`; relative URLs are always allowed
+    pub url_schemes: Vec<String>,
+    /// Allow `data:image/...` sources on `<img>` (pasted screenshots)
+    pub data_images: bool,
+}
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> Self {
+        let tag_attributes = [
+            ("a", &["href", "target", "rel", "name", "data-target", "data-anchor"][..]),
+            ("img", &["src", "alt", "width", "height"]),
+            ("input", &["type", "checked", "disabled"]),
+            ("ol", &["start", "type", "reversed"]),
+            ("li", &["value"]),
+            ("td", &["colspan", "rowspan", "align"]),
+            ("th", &["colspan", "rowspan", "align", "scope"]),
+            ("col", &["span"]),
+            ("blockquote", &["cite"]),
+            ("q", &["cite"]),
+            ("del", &["cite", "datetime"]),
+            ("ins", &["cite", "datetime"]),
+            ("details", &["open"]),
+        ];
+        SanitizePolicy {
+            tags: strings(&[
+                "a", "abbr", "b", "blockquote", "br", "caption", "cite", "code", "col", "colgroup", "dd", "del",
+                "details", "div", "dl", "dt", "em", "figcaption", "figure", "h1", "h2", "h3", "h4", "h5", "h6", "hr",
+                "i", "img", "input", "ins", "kbd", "li", "mark", "ol", "p", "pre", "q", "s", "samp", "section",
+                "small", "span", "strike", "strong", "sub", "summary", "sup", "table", "tbody", "td", "tfoot", "th",
+                "thead", "tr", "u", "ul",
+            ]),
+            attributes: strings(&["class", "id", "title", "lang", "dir", "style"]),
+            tag_attributes: tag_attributes.iter().map(|(tag, attrs)| (tag.to_string(), strings(attrs))).collect(),
+            url_schemes: strings(&["http", "https", "mailto", "tel"]),
+            data_images: true,
+        }
+    }
+}
+
+impl SanitizePolicy {
+    fn allows_tag(&self, name: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(name))
+    }
+
+    fn allows_attribute(&self, tag: &str, attr: &str) -> bool {
+        self.attributes.iter().any(|a| a == attr)
+            || self.tag_attributes.get(tag).is_some_and(|attrs| attrs.iter().any(|a| a == attr))
+    }
+
+    /// Whether a URL attribute value is safe to keep
+    fn allows_url(&self, tag: &str, attr: &str, url: &str) -> bool {
+        // Browsers ignore whitespace and control characters inside the
+        // scheme, so `java\tscript:` must not slip through
+        let compact: String = url.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_control()).collect();
+        let compact = compact.to_ascii_lowercase();
+        let scheme_end = compact.find(':').filter(|&colon| !compact[..colon].contains(['/', '?', '#']));
+        let Some(colon) = scheme_end else {
+            return true;
+        };
+        if compact.starts_with("data:") {
+            return self.data_images && tag == "img" && attr == "src" && DATA_IMAGE_TYPES.iter().any(|t| compact.starts_with(t));
+        }
+        let scheme = &compact[..colon];
+        !matches!(scheme, "javascript" | "vbscript") && self.url_schemes.iter().any(|s| s.eq_ignore_ascii_case(scheme))
+    }
+
+    /// The attributes of a kept element that pass the policy, or None when
+    /// the element itself must go
+    fn filter_attributes(&self, tag: &str, attrs: Vec<(String, String)>) -> Option<Vec<(String, String)>> {
+        // Only the read-only task list checkbox is kept of all form controls
+        if tag == "input" {
+            let checkbox = attrs.iter().any(|(k, v)| k == "type" && v.eq_ignore_ascii_case("checkbox"));
+            if !checkbox || !attrs.iter().any(|(k, _)| k == "disabled") {
+                return None;
+            }
+        }
+        let mut kept: Vec<(String, String)> = attrs
+            .into_iter()
+            .filter(|(name, value)| {
+                !name.starts_with("on")
+                    && self.allows_attribute(tag, name)
+                    && (!URL_ATTRIBUTES.contains(&name.as_str()) || self.allows_url(tag, name, value))
+                    && (name != "style" || safe_style(value))
+            })
+            .collect();
+        // Links opening a new window must not get a handle on this one
+        if tag == "a" && kept.iter().any(|(k, _)| k == "target") {
+            kept.retain(|(k, _)| k != "rel");
+            kept.push(("rel".to_string(), "noopener noreferrer".to_string()));
+        }
+        Some(kept)
+    }
+}
+
+/// Whether an inline style is free of script and external loads
+fn safe_style(style: &str) -> bool {
+    let style = style.to_ascii_lowercase();
+    !["expression", "javascript", "behavior", "-moz-binding", "url("].iter().any(|bad| style.contains(bad))
+}
+
+fn render_tag(name: &str, attrs: &[(String, String)]) -> String {
+    let mut tag = format!("<{}", name);
+    for (key, value) in attrs {
+        if value.is_empty() {
+            tag.push_str(&format!(" {}", key));
+        } else {
+            tag.push_str(&format!(" {}=\"{}\"", key, escape_html(value)));
+        }
+    }
+    tag.push('>');
+    tag
+}
+
+/// Sanitize an HTML fragment. Comments, doctypes and processing
+/// instructions are dropped, text is re-escaped, and end tags are only
+/// written for elements the policy keeps; the fragment is not balanced.
+pub(crate) fn sanitize(html: &str, policy: &SanitizePolicy) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut i = 0;
+    while i < html.len() {
+        let rest = &html[i..];
+        if rest.starts_with("<!--") {
+            i += rest.find("-->").map_or(rest.len(), |e| e + 3);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            i += rest.find('>').map_or(rest.len(), |e| e + 1);
+            continue;
+        }
+        let is_tag = rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/');
+        if !is_tag {
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[first..].find('<').map_or(rest.len(), |e| e + first);
+            out.push_str(&escape_html(&decode_entities(&rest[..end])));
+            i += end;
+            continue;
+        }
+        let end = rest.find('>').map_or(rest.len(), |e| e + 1);
+        let tag = rest[1..end].trim_end_matches('>');
+        i += end;
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_lowercase();
+            if policy.allows_tag(&name) && !VOID_ELEMENTS.contains(&name.as_str()) {
+                out.push_str(&format!("</{}>", name));
+            }
+            continue;
+        }
+        let name_len = tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(tag.len());
+        let name = tag[..name_len].to_ascii_lowercase();
+        if REMOVED_ELEMENTS.contains(&name.as_str()) {
+            // Even a self-closed `<script/>` runs to its end tag in a browser
+            let end_tag = format!("</{}", name);
+            let rest = &html[i..];
+            let end = rest.to_ascii_lowercase().find(&end_tag).unwrap_or(rest.len());
+            i += end;
+            i += html[i..].find('>').map_or(html.len() - i, |e| e + 1);
+            continue;
+        }
+        if !policy.allows_tag(&name) {
+            continue;
+        }
+        if let Some(attrs) = policy.filter_attributes(&name, parse_attrs(&tag[name_len..])) {
+            out.push_str(&render_tag(&name, &attrs));
+        }
+    }
+    out
+}
+
+/// Strip scripts, event handlers and disallowed elements and attributes
+/// from HTML before it is converted or shown in the preview. `policy` is a
+/// JSON object `{tags, attributes, tag_attributes: {tag: [attribute]},
+/// url_schemes, data_images}`; missing fields keep their defaults and an
+/// empty string uses the default policy. Disallowed elements are unwrapped
+/// (their text kept), while `<script>`, `<style>`, `<iframe>` and similar
+/// are removed with their content. `on*` attributes and `javascript:` URLs
+/// never survive, whatever the policy allows.
+#[wasm_bindgen]
+pub fn sanitize_html(html: &str, policy: &str) -> Result<String, JsValue> {
+    let policy: SanitizePolicy = if policy.trim().is_empty() {
+        SanitizePolicy::default()
+    } else {
+        serde_json::from_str(policy).map_err(|e| JsValue::from_str(&format!("Invalid sanitize policy: {}", e)))?
+    };
+    Ok(sanitize(html, &policy))
+}
+
+/// The default sanitize policy as JSON
+#[wasm_bindgen]
+pub fn default_sanitize_policy() -> String {
+    serde_json::to_string(&SanitizePolicy::default()).unwrap_or_else(|_| "{}".to_string())
+}
@@ -0,0 +1,250 @@
+// Per-sender sequence numbers. Every message a sender sends carries the
+// next number of its session, so receivers can tell when messages were
+// lost or arrived out of order and ask for the missing ones again instead
+// of silently skipping edits. Messages only stored or exported (history
+// checkpoints, document exports) take no number.
+//
+// Numbers count per sender and session, not per document: a session is
+// one editor page with one document open, and resend requests, the sent
+// log answering them and sync state vectors all follow one (user, session)
+// stream. A session sending to several documents has to reach the same
+// peers with all of them, or those peers see the others' numbers as gaps.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::hash_chain::to_hex;
+use crate::share::random_bytes;
+use crate::{data_text, data_u64, decode_with_grid_tag, encode_promisegrid_payload};
+
+/// A session's first sequence number
+pub(crate) const FIRST_SEQ: u64 = 1;
+
+/// How far past the next expected number a sequence number may be; one
+/// further ahead is dropped rather than reported as a vast gap
+const MAX_SEQ_AHEAD: u64 = 1 << 20;
+
+/// Minimum time between resend requests to the same sender (ms)
+const RESEND_REQUEST_INTERVAL_MS: f64 = 2_000.0;
+
+thread_local! {
+    /// Random id of this instance's session; sequence numbers restart in
+    /// every session
+    static SESSION: String = {
+        let mut id = [0u8; 8];
+        let _ = random_bytes(&mut id);
+        to_hex(&id)
+    };
+    /// Next sequence number to use, per sender
+    static SEQUENCES: RefCell<HashMap<String, u64>> = RefCell::new(HashMap::new());
+}
+
+/// Give a message from `user_id` the sender's next sequence number and
+/// this session's id, unless it already has one
+pub(crate) fn stamp(data: &mut HashMap<String, ciborium::Value>) {
+    if data.contains_key("seq") {
+        return;
+    }
+    let Some(user_id) = data_text(data, "user_id").map(str::to_string) else {
+        return;
+    };
+    let seq = SEQUENCES.with(|s| {
+        let mut sequences = s.borrow_mut();
+        let next = sequences.entry(user_id).or_insert(FIRST_SEQ);
+        let seq = *next;
+        *next += 1;
+        seq
+    });
+    data.insert("seq".to_string(), ciborium::Value::from(seq));
+    data.insert("session".to_string(), ciborium::Value::Text(SESSION.with(String::clone)));
+}
+
+/// This instance's session id, as stamped on its messages
+pub(crate) fn session_id() -> String {
+    SESSION.with(String::clone)
+}
+
+/// What is known about one sender's session
+#[derive(Default)]
+pub(crate) struct SenderState {
+    /// Every number below this has arrived
+    next_expected: u64,
+    /// Numbers at or above `next_expected` that have arrived
+    ahead: BTreeSet<u64>,
+    last_request: Option<f64>,
+}
+
+impl SenderState {
+    /// State for a sender first heard from at `seq`
+    pub(crate) fn starting_at(seq: u64) -> SenderState {
+        SenderState { next_expected: seq, ..SenderState::default() }
+    }
+
+    /// Every number below this has arrived
+    pub(crate) fn next_expected(&self) -> u64 {
+        self.next_expected
+    }
+
+    /// Note that `seq` arrived: "in_order", "gap", "late", "duplicate" or
+    /// "out_of_range" (implausibly far ahead, and not recorded)
+    pub(crate) fn record(&mut self, seq: u64) -> &'static str {
+        if seq < self.next_expected || self.ahead.contains(&seq) {
+            return "duplicate";
+        }
+        if seq - self.next_expected > MAX_SEQ_AHEAD {
+            return "out_of_range";
+        }
+        let status = if seq == self.next_expected {
+            "in_order"
+        } else if self.ahead.range(seq..).next().is_some() {
+            "late"
+        } else {
+            "gap"
+        };
+        self.ahead.insert(seq);
+        while self.ahead.remove(&self.next_expected) {
+            self.next_expected += 1;
+        }
+        status
+    }
+
+    /// First missing number, when later ones have arrived
+    fn first_missing(&self) -> Option<u64> {
+        (!self.ahead.is_empty()).then_some(self.next_expected)
+    }
+
+    /// Missing numbers as inclusive ranges
+    fn missing(&self) -> Vec<(u64, u64)> {
+        let mut ranges = Vec::new();
+        let mut from = self.next_expected;
+        for &seq in &self.ahead {
+            if seq > from {
+                ranges.push((from, seq - 1));
+            }
+            from = seq.saturating_add(1);
+        }
+        ranges
+    }
+}
+
+#[derive(Serialize)]
+struct Observation<'a> {
+    /// "in_order", "gap" (later than expected, numbers are missing),
+    /// "late" (fills an earlier gap), "duplicate", "out_of_range" (too far
+    /// ahead to be believed) or "unsequenced"
+    status: &'static str,
+    user_id: &'a str,
+    seq: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct MissingRange<'a> {
+    user_id: &'a str,
+    session: &'a str,
+    from_seq: u64,
+    to_seq: u64,
+}
+
+/// Follows the sequence numbers of incoming messages per sender and
+/// session, reporting gaps, late arrivals and duplicates, and creates
+/// `resend_request` messages for what is missing.
+#[wasm_bindgen]
+pub struct InboundTracker {
+    user_id: String,
+    /// By (user id, session)
+    senders: BTreeMap<(String, String), SenderState>,
+}
+
+#[wasm_bindgen]
+impl InboundTracker {
+    /// `user_id` is this client, the sender of the resend requests
+    #[wasm_bindgen(constructor)]
+    pub fn new(user_id: &str) -> InboundTracker {
+        InboundTracker { user_id: user_id.to_string(), senders: BTreeMap::new() }
+    }
+
+    /// Record an incoming message. Returns JSON `{status, user_id, seq}`;
+    /// a `duplicate` should not be applied again, nor an `out_of_range`
+    /// one applied at all.
+    pub fn observe(&mut self, cbor_bytes: &[u8]) -> Result<String, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let data = &message.payload.data;
+        let user_id = data_text(data, "user_id").unwrap_or("unknown");
+        let (Some(seq), Some(session)) = (data_u64(data, "seq"), data_text(data, "session")) else {
+            let observation = Observation { status: "unsequenced", user_id, seq: None };
+            return Ok(serde_json::to_string(&observation).unwrap_or_else(|_| "{}".into()));
+        };
+        let status = self.record(user_id, session, seq);
+        let observation = Observation { status, user_id, seq: Some(seq) };
+        Ok(serde_json::to_string(&observation).unwrap_or_else(|_| "{}".into()))
+    }
+
+    /// JSON array of `{user_id, session, from_seq, to_seq}`: the ranges of
+    /// messages known to be missing
+    pub fn missing(&self) -> String {
+        let ranges: Vec<MissingRange> = self
+            .senders
+            .iter()
+            .flat_map(|((user_id, session), state)| {
+                state.missing().into_iter().map(move |(from_seq, to_seq)| MissingRange { user_id, session, from_seq, to_seq })
+            })
+            .collect();
+        serde_json::to_string(&ranges).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// The next `resend_request` to send, asking a sender with a gap to
+    /// send everything again from its first missing number. Each sender is
+    /// asked at most every two seconds; call until it returns `undefined`.
+    pub fn next_resend_request(&mut self, now: f64) -> Option<Vec<u8>> {
+        let ((user_id, session), state) = self.senders.iter_mut().find(|(_, state)| {
+            state.first_missing().is_some()
+                && state.last_request.is_none_or(|last| now - last >= RESEND_REQUEST_INTERVAL_MS)
+        })?;
+        state.last_request = Some(now);
+        let mut data = HashMap::new();
+        data.insert("user_id".to_string(), ciborium::Value::Text(self.user_id.clone()));
+        data.insert("target_user_id".to_string(), ciborium::Value::Text(user_id.clone()));
+        data.insert("target_session".to_string(), ciborium::Value::Text(session.clone()));
+        data.insert("from_seq".to_string(), ciborium::Value::from(state.next_expected));
+        data.insert("timestamp".to_string(), ciborium::Value::Float(now));
+        Some(encode_promisegrid_payload("resend_request", data))
+    }
+}
+
+impl InboundTracker {
+    fn record(&mut self, user_id: &str, session: &str, seq: u64) -> &'static str {
+        self.senders
+            .entry((user_id.to_string(), session.to_string()))
+            // Sessions start at FIRST_SEQ; when joining mid-session what
+            // came before is reported missing, so it can be asked for
+            .or_insert_with(|| SenderState::starting_at(FIRST_SEQ))
+            .record(seq)
+    }
+}
+
+/// The sequence number a `resend_request` asks to resend from, when it is
+/// addressed to `user_id` in this session
+pub(crate) fn resend_request_from(data: &HashMap<String, ciborium::Value>, user_id: &str) -> Option<u64> {
+    let session = session_id();
+    if data_text(data, "target_user_id") != Some(user_id) || data_text(data, "target_session") != Some(session.as_str()) {
+        return None;
+    }
+    data_u64(data, "from_seq")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_sequence_number_is_dropped() {
+        let mut state = SenderState::starting_at(FIRST_SEQ);
+        assert_eq!(state.record(u64::MAX), "out_of_range");
+        assert_eq!(state.record(3), "gap");
+        assert_eq!(state.missing(), vec![(1, 2)]);
+        assert_eq!(state.record(1), "in_order");
+        assert_eq!(state.first_missing(), Some(2));
+    }
+}
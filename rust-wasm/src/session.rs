@@ -0,0 +1,179 @@
+// Session archives: everything that happened in an editing session (the
+// edit messages as sent, joins and leaves, chat) in one gzip-compressed
+// CBOR file, so a session can be audited or replayed elsewhere.
+
+use wasm_bindgen::prelude::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::document_import::decompress;
+use crate::edit_log::{message_edits, split_messages};
+use crate::data_text;
+
+const ARCHIVE_FORMAT: &str = "collab-session";
+const ARCHIVE_VERSION: u32 = 1;
+
+/// A join, leave or other presence change, as the UI recorded it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SessionEvent {
+    /// "join", "leave", "idle", ...
+    #[serde(alias = "type")]
+    kind: String,
+    #[serde(default)]
+    user_id: String,
+    #[serde(default)]
+    timestamp: f64,
+    /// Anything else the event carried (name, color, message text, ...)
+    #[serde(flatten)]
+    detail: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SessionArchive {
+    format: String,
+    version: u32,
+    document_id: String,
+    started: f64,
+    ended: f64,
+    /// Everyone who edited or appeared in the session: editors in order
+    /// of their first edit, then anyone who only appeared
+    participants: Vec<String>,
+    /// Edit messages exactly as sent, each as a byte string
+    edits: Vec<ciborium::Value>,
+    presence: Vec<SessionEvent>,
+    #[serde(default)]
+    chat: Vec<SessionEvent>,
+}
+
+#[derive(Serialize)]
+struct SessionSummary<'a> {
+    document_id: &'a str,
+    started: f64,
+    ended: f64,
+    participants: &'a [String],
+    edit_count: usize,
+    presence: &'a [SessionEvent],
+    chat: &'a [SessionEvent],
+    /// The document after the session's edits, replayed from empty
+    content: String,
+}
+
+fn parse_events(json: &str, what: &str) -> Result<Vec<SessionEvent>, JsValue> {
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut events: Vec<SessionEvent> =
+        serde_json::from_str(json).map_err(|e| JsValue::from_str(&format!("Invalid {}: {}", what, e)))?;
+    events.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    Ok(events)
+}
+
+/// Bundle a session into a compressed archive: `edit_log` is the edit
+/// messages concatenated as sent, and `presence_events` a JSON array of
+/// `{kind, user_id, timestamp, ...}` (joins, leaves and other awareness
+/// changes; extra fields are kept). The archive records the session's
+/// time span and participants alongside them, and has room for chat.
+#[wasm_bindgen]
+pub fn export_session(edit_log: &[u8], presence_events: &str) -> Result<Vec<u8>, JsValue> {
+    let messages = split_messages(edit_log).map_err(|e| JsValue::from_str(&e))?;
+    let presence = parse_events(presence_events, "presence events")?;
+
+    let edits: Vec<_> = messages.iter().flat_map(|(_, message)| message_edits(message)).collect();
+    let mut participants: Vec<String> = Vec::new();
+    let mut times: Vec<f64> = Vec::new();
+    let appearances = edits.iter().map(|e| (&e.user_id, e.timestamp)).chain(presence.iter().map(|e| (&e.user_id, e.timestamp)));
+    for (user_id, timestamp) in appearances {
+        if !user_id.is_empty() && !participants.contains(user_id) {
+            participants.push(user_id.clone());
+        }
+        times.push(timestamp);
+    }
+    let document_id = messages
+        .iter()
+        .find_map(|(_, message)| data_text(&message.payload.data, "document_id"))
+        .unwrap_or("")
+        .to_string();
+
+    let archive = SessionArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        document_id,
+        started: times.iter().copied().reduce(f64::min).unwrap_or(0.0),
+        ended: times.iter().copied().reduce(f64::max).unwrap_or(0.0),
+        participants,
+        edits: messages.iter().map(|(bytes, _)| ciborium::Value::Bytes(bytes.to_vec())).collect(),
+        presence,
+        chat: Vec::new(),
+    };
+    let cbor = crate::to_cbor(&archive).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&cbor)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| JsValue::from_str(&format!("Compression error: {}", e)))
+}
+
+fn read_archive(archive: &[u8]) -> Result<SessionArchive, JsValue> {
+    let cbor = decompress(archive, false).map_err(|e| JsValue::from_str(&e))?;
+    let archive: SessionArchive =
+        crate::from_cbor(&cbor).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    if archive.format != ARCHIVE_FORMAT {
+        return Err(JsValue::from_str("Not a session archive"));
+    }
+    if archive.version > ARCHIVE_VERSION {
+        return Err(JsValue::from_str(&format!("Unsupported session archive version {}", archive.version)));
+    }
+    Ok(archive)
+}
+
+fn archive_log(archive: &SessionArchive) -> Vec<u8> {
+    archive
+        .edits
+        .iter()
+        .filter_map(|m| match m {
+            ciborium::Value::Bytes(bytes) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/// Open a session archive from `export_session` as JSON `{document_id,
+/// started, ended, participants, edit_count, presence, chat, content}`,
+/// `content` being the document the session's edits produce
+#[wasm_bindgen]
+pub fn import_session(archive: &[u8]) -> Result<String, JsValue> {
+    let archive = read_archive(archive)?;
+    let log = archive_log(&archive);
+    let edits: Vec<_> = split_messages(&log)
+        .map_err(|e| JsValue::from_str(&e))?
+        .iter()
+        .flat_map(|(_, message)| message_edits(message))
+        .collect();
+    let mut content = String::new();
+    for edit in &edits {
+        edit.apply(&mut content);
+    }
+    let summary = SessionSummary {
+        document_id: &archive.document_id,
+        started: archive.started,
+        ended: archive.ended,
+        participants: &archive.participants,
+        edit_count: edits.len(),
+        presence: &archive.presence,
+        chat: &archive.chat,
+        content,
+    };
+    Ok(serde_json::to_string(&summary).unwrap_or_else(|_| "{}".into()))
+}
+
+/// The edit messages of a session archive concatenated, as taken by
+/// `replay_to`, `EditPlayer` and `blame`
+#[wasm_bindgen]
+pub fn session_edit_log(archive: &[u8]) -> Result<Vec<u8>, JsValue> {
+    Ok(archive_log(&read_archive(archive)?))
+}
@@ -0,0 +1,209 @@
+use wasm_bindgen::prelude::*;
+use qrcode::{EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+
+/// Crockford base32: no I, L, O or U, so codes survive being read aloud
+const SHARE_CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Limits for `generate_share_code`
+pub(crate) const MIN_SHARE_CODE_BITS: u32 = 20;
+pub(crate) const MAX_SHARE_CODE_BITS: u32 = 256;
+
+/// Version of the share payload layout
+pub(crate) const SHARE_PAYLOAD_VERSION: u8 = 1;
+
+/// Pairing phrases draw from exactly 256 words, so each word is one random
+/// byte (8 bits of entropy) with no modulo bias.
+const PAIRING_WORDS: [&str; 256] = [
+    "acid", "acorn", "actor", "agent", "alarm", "album", "alert", "alley", "amber", "angle",
+    "ankle", "apple", "apron", "arena", "armor", "arrow", "aspen", "atlas", "attic", "award",
+    "bacon", "badge", "bagel", "baker", "balmy", "banjo", "barn", "basil", "basin", "beach",
+    "beard", "bench", "berry", "bison", "blade", "blank", "blaze", "bloom", "board", "boat",
+    "bonus", "booth", "boxer", "brave", "bread", "brick", "bride", "broom", "brush", "buddy",
+    "bugle", "cabin", "cable", "camel", "candy", "canoe", "cargo", "cedar", "chalk", "charm",
+    "chess", "chief", "chili", "cider", "civic", "clamp", "cliff", "clock", "cloud", "coach",
+    "cobra", "cocoa", "comet", "coral", "couch", "cove", "crane", "crate", "crisp", "crown", "cube",
+    "cupid", "curry", "daisy", "dance", "delta", "denim", "depot", "diary", "dingo", "diver",
+    "donut", "dream", "drift", "drum", "eagle", "easel", "echo", "elbow", "elder", "elm", "ember",
+    "emu", "epic", "fable", "fancy", "fern", "ferry", "fiber", "field", "finch", "flame", "flask",
+    "fleet", "flint", "flute", "foam", "fox", "frost", "fudge", "gable", "gecko", "gem", "giant",
+    "globe", "glove", "goose", "grape", "hazel", "hedge", "heron", "hippo", "honey", "hook",
+    "hotel", "igloo", "index", "iris", "ivory", "jelly", "jewel", "judo", "juice", "kayak", "kiwi",
+    "koala", "lake", "lemon", "lilac", "lime", "linen", "llama", "lotus", "lunar", "mango", "maple",
+    "melon", "mercy", "metro", "mint", "moose", "motor", "noble", "oasis", "ocean", "olive",
+    "onion", "opera", "orbit", "otter", "palm", "panda", "paper", "pasta", "peach", "pearl",
+    "piano", "pickle", "pilot", "pine", "pixel", "planet", "plaza", "plum", "pony", "poppy",
+    "prism", "puzzle", "quail", "quartz", "quiet", "quilt", "rabbit", "radar", "radio", "raft",
+    "rain", "raven", "reef", "ribbon", "ridge", "river", "robin", "rocket", "rose", "ruby",
+    "saddle", "salmon", "sand", "satin", "scarf", "scout", "shell", "shore", "silver", "sketch",
+    "sled", "slope", "snail", "sonar", "spark", "spice", "spoon", "spruce", "squid", "stable",
+    "star", "stone", "storm", "sugar", "summit", "swan", "tango", "tiger", "timber", "toast",
+    "topaz", "torch", "tower", "trail", "tulip", "tundra", "turtle", "valley", "velvet", "violet",
+    "waffle", "walnut", "walrus", "willow", "wizard", "yacht", "yodel", "zebra", "zephyr", "zinc",
+];
+
+/// Fill `buf` from the platform CSPRNG (crypto.getRandomValues in the browser)
+pub(crate) fn random_bytes(buf: &mut [u8]) -> Result<(), JsValue> {
+    getrandom::getrandom(buf).map_err(|e| JsValue::from_str(&format!("Random number generator unavailable: {}", e)))
+}
+
+/// Generate a random share code with at least `entropy_bits` bits of
+/// entropy (clamped to 20..=256), e.g. `7K3M-Q9XA-2D`.
+/// Codes use Crockford base32 in groups of four characters.
+#[wasm_bindgen]
+pub fn generate_share_code(entropy_bits: u32) -> Result<String, JsValue> {
+    let bits = entropy_bits.clamp(MIN_SHARE_CODE_BITS, MAX_SHARE_CODE_BITS);
+    let chars = bits.div_ceil(5) as usize;
+
+    // One byte per character; the low 5 bits of a uniform byte are uniform
+    let mut bytes = vec![0u8; chars];
+    random_bytes(&mut bytes)?;
+
+    let code: Vec<char> = bytes
+        .iter()
+        .map(|b| SHARE_CODE_ALPHABET[(b & 0x1f) as usize] as char)
+        .collect();
+    Ok(code
+        .chunks(4)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+/// Generate a diceware-style pairing phrase of `words` words (1..=16),
+/// joined with dashes, e.g. `otter-maple-comet-drum`. Each word carries
+/// 8 bits of entropy.
+#[wasm_bindgen]
+pub fn generate_pairing_phrase(words: u32) -> Result<String, JsValue> {
+    let count = words.clamp(1, 16) as usize;
+    let mut bytes = vec![0u8; count];
+    random_bytes(&mut bytes)?;
+    Ok(bytes
+        .iter()
+        .map(|&b| PAIRING_WORDS[b as usize])
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+/// Normalize a share code typed by a user: uppercase, drop separators and
+/// map the look-alike letters Crockford base32 excludes (I/L -> 1, O -> 0).
+#[wasm_bindgen]
+pub fn normalize_share_code(input: &str) -> String {
+    input
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| match c.to_ascii_uppercase() {
+            'I' | 'L' => '1',
+            'O' => '0',
+            other => other,
+        })
+        .collect()
+}
+
+/// Contents of a share QR code. Serialized as packed CBOR (integer keys)
+/// to keep the code small enough to scan reliably.
+#[derive(Serialize, Deserialize, Debug)]
+struct ShareInvite {
+    version: u8,
+    document_id: String,
+    invite_token: String,
+}
+
+impl ShareInvite {
+    /// The invite as a map keyed by field index
+    fn packed(&self) -> ciborium::Value {
+        ciborium::Value::Map(vec![
+            (0.into(), self.version.into()),
+            (1.into(), self.document_id.as_str().into()),
+            (2.into(), self.invite_token.as_str().into()),
+        ])
+    }
+
+    fn unpack(value: ciborium::Value) -> Result<ShareInvite, String> {
+        let fields = value.into_map().map_err(|_| "share payload is not a map".to_string())?;
+        let field = |index: u8| {
+            fields
+                .iter()
+                .find(|(key, _)| key.as_integer() == Some(index.into()))
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("share payload has no field {}", index))
+        };
+        let text = |index: u8| {
+            field(index)?.as_text().map(str::to_string).ok_or_else(|| format!("share payload field {} is not text", index))
+        };
+        let version = field(0)?
+            .as_integer()
+            .and_then(|version| u8::try_from(version).ok())
+            .ok_or("share payload version is not a small integer")?;
+        Ok(ShareInvite { version, document_id: text(1)?, invite_token: text(2)? })
+    }
+}
+
+/// A share payload and the QR code that encodes it
+#[wasm_bindgen]
+pub struct SharePayload {
+    payload: Vec<u8>,
+    width: u32,
+    modules: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl SharePayload {
+    /// The compact CBOR payload encoded in the QR code
+    #[wasm_bindgen(getter)]
+    pub fn payload(&self) -> Vec<u8> {
+        self.payload.clone()
+    }
+
+    /// Width (and height) of the QR matrix in modules
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The QR matrix row by row, one byte per module: 1 = dark, 0 = light.
+    /// Does not include the quiet zone.
+    #[wasm_bindgen(getter)]
+    pub fn modules(&self) -> Vec<u8> {
+        self.modules.clone()
+    }
+}
+
+/// Build the share payload for a document invite and its QR code matrix,
+/// so the frontend can draw the code without a QR library.
+#[wasm_bindgen]
+pub fn share_payload_qr(document_id: &str, invite_token: &str) -> Result<SharePayload, JsValue> {
+    let invite = ShareInvite {
+        version: SHARE_PAYLOAD_VERSION,
+        document_id: document_id.to_string(),
+        invite_token: invite_token.to_string(),
+    };
+    let payload = crate::to_cbor(&invite.packed())
+        .map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))?;
+
+    let code = QrCode::with_error_correction_level(&payload, EcLevel::M)
+        .map_err(|e| JsValue::from_str(&format!("QR encoding error: {}", e)))?;
+    let modules = code
+        .to_colors()
+        .into_iter()
+        .map(|color| u8::from(color == qrcode::Color::Dark))
+        .collect();
+
+    Ok(SharePayload {
+        payload,
+        width: code.width() as u32,
+        modules,
+    })
+}
+
+/// Decode a scanned share payload into JSON `{version, document_id, invite_token}`
+#[wasm_bindgen]
+pub fn parse_share_payload(payload: &[u8]) -> Result<String, JsValue> {
+    let invite = crate::from_cbor(payload)
+        .and_then(ShareInvite::unpack)
+        .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    if invite.version != SHARE_PAYLOAD_VERSION {
+        return Err(JsValue::from_str(&format!("Unsupported share payload version {}", invite.version)));
+    }
+    serde_json::to_string(&invite).map_err(|e| JsValue::from_str(&format!("JSON serialization error: {}", e)))
+}
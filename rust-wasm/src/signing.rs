@@ -0,0 +1,75 @@
+use wasm_bindgen::prelude::*;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use crate::capability_token::CAPABILITY_SIGNATURE_FIELD;
+use crate::share::random_bytes;
+use crate::{data_bytes, decode_with_grid_tag, encode_with_grid_tag, to_canonical_cbor, PromiseGridMessage};
+
+/// Data field carrying the sender's signature of the message, added by
+/// `sign_message`
+pub(crate) const SENDER_SIGNATURE_FIELD: &str = "sender_signature";
+
+fn signing_key(secret_key: &[u8]) -> Result<SigningKey, JsValue> {
+    let bytes: [u8; 32] = secret_key
+        .try_into()
+        .map_err(|_| JsValue::from_str("Signing key must be 32 bytes"))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Sign `message` with a 32-byte Ed25519 secret key
+pub(crate) fn sign(secret_key: &[u8], message: &[u8]) -> Result<Vec<u8>, JsValue> {
+    Ok(signing_key(secret_key)?.sign(message).to_bytes().to_vec())
+}
+
+/// Verify an Ed25519 signature; malformed keys or signatures simply fail
+pub(crate) fn verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let Ok(key_bytes) = <[u8; 32]>::try_from(public_key) else { return false };
+    let Ok(key) = VerifyingKey::from_bytes(&key_bytes) else { return false };
+    let Ok(signature) = Signature::from_slice(signature) else { return false };
+    key.verify(message, &signature).is_ok()
+}
+
+/// Generate a new 32-byte Ed25519 secret key for signing control messages.
+/// Store it locally; share only the public key.
+#[wasm_bindgen]
+pub fn generate_signing_key() -> Result<Vec<u8>, JsValue> {
+    let mut secret = vec![0u8; 32];
+    random_bytes(&mut secret)?;
+    Ok(secret)
+}
+
+/// Public key (32 bytes) for a secret key from `generate_signing_key`
+#[wasm_bindgen]
+pub fn signing_public_key(secret_key: &[u8]) -> Result<Vec<u8>, JsValue> {
+    Ok(signing_key(secret_key)?.verifying_key().to_bytes().to_vec())
+}
+
+/// What a message signature covers: the whole message but its signatures
+pub(crate) fn message_signing_bytes(message: &PromiseGridMessage) -> Result<Vec<u8>, String> {
+    let mut unsigned = message.clone();
+    unsigned.payload.data.remove(SENDER_SIGNATURE_FIELD);
+    unsigned.payload.data.remove(CAPABILITY_SIGNATURE_FIELD);
+    to_canonical_cbor(&unsigned)
+}
+
+/// Whether `message` carries a sender signature that verifies against
+/// `public_key`
+pub(crate) fn verify_message(public_key: &[u8], message: &PromiseGridMessage) -> bool {
+    let Some(signature) = data_bytes(&message.payload.data, SENDER_SIGNATURE_FIELD) else { return false };
+    let Ok(signed) = message_signing_bytes(message) else { return false };
+    verify(public_key, &signed, signature)
+}
+
+/// Sign an outgoing message as its sender with a 32-byte Ed25519 secret
+/// key, replacing any sender signature already there. Receivers whose
+/// AccessController knows the sender's public key accept edits and
+/// comments from them only when signed. Sign last: any later change to the
+/// message, such as attaching a capability token, invalidates it.
+#[wasm_bindgen]
+pub fn sign_message(cbor_bytes: &[u8], secret_key: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let mut message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+    let signed = message_signing_bytes(&message).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))?;
+    let signature = sign(secret_key, &signed)?;
+    message.payload.data.insert(SENDER_SIGNATURE_FIELD.to_string(), ciborium::Value::Bytes(signature));
+    encode_with_grid_tag(&message).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+}
@@ -0,0 +1,186 @@
+// Joining a document from a snapshot. A new client asks for the current
+// document with a `snapshot_request`; any peer that has it answers with a
+// `document_snapshot` (the compressed content, its hash and the Lamport
+// time it reflects). Live edits arriving meanwhile are held back and
+// applied after the snapshot, so the client never replays the whole
+// history and never applies an edit to the wrong base.
+
+use wasm_bindgen::prelude::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::clock;
+use crate::document_import::decompress;
+use crate::hash_chain::to_hex;
+use crate::metadata::CONTENT_MESSAGE_TYPES;
+use crate::payload::{decode_payload, payload_data, Payload, Snapshot, SnapshotRequest};
+use crate::{data_text, data_u64, decode_with_grid_tag, encode_promisegrid_payload, PromiseGridMessage};
+
+/// Hex SHA-256 of document content, as snapshots carry it
+pub(crate) fn content_hash(content: &[u8]) -> String {
+    to_hex(&Sha256::digest(content))
+}
+
+/// Create a `document_snapshot` message with the whole of `content`,
+/// gzip-compressed, and its SHA-256. `version` is the Lamport time the
+/// content reflects (`PromiseGridHandler.lamport_time()` after applying
+/// the last edit): the joining client drops held-back edits at or before
+/// it as already included.
+#[wasm_bindgen]
+pub fn create_snapshot_message(document_id: &str, content: &str, version: u64, user_id: &str) -> Vec<u8> {
+    snapshot_message(document_id, content, version, user_id, None)
+}
+
+/// A `document_snapshot` message, optionally carrying the document's
+/// properties as CBOR; see `DocumentMetadata.snapshot_message`
+pub(crate) fn snapshot_message(
+    document_id: &str,
+    content: &str,
+    version: u64,
+    user_id: &str,
+    metadata: Option<Vec<u8>>,
+) -> Vec<u8> {
+    encode_promisegrid_payload("document_snapshot", snapshot_data(document_id, content, version, user_id, metadata))
+}
+
+/// Data map of a `document_snapshot` message, stamped with the current time
+pub(crate) fn snapshot_data(
+    document_id: &str,
+    content: &str,
+    version: u64,
+    user_id: &str,
+    metadata: Option<Vec<u8>>,
+) -> HashMap<String, ciborium::Value> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let compressed = encoder.write_all(content.as_bytes()).and_then(|_| encoder.finish()).unwrap_or_default();
+    let snapshot = Snapshot {
+        document_id: document_id.to_string(),
+        user_id: user_id.to_string(),
+        version,
+        content: compressed,
+        content_hash: content_hash(content.as_bytes()),
+        metadata,
+        timestamp: js_sys::Date::now(),
+    };
+    payload_data(&snapshot)
+}
+
+/// The document id of a `snapshot_request` from someone other than
+/// `user_id`, for the handler to answer with a snapshot
+pub(crate) fn requested_document(message: &PromiseGridMessage, user_id: &str) -> Option<String> {
+    if message.payload.message_type != "snapshot_request" {
+        return None;
+    }
+    let data = &message.payload.data;
+    if data_text(data, "user_id") == Some(user_id) {
+        return None;
+    }
+    data_text(data, "document_id").map(str::to_string)
+}
+
+/// A client joining a document: requests a snapshot, holds back live
+/// edits until one arrives, then hands back the edits made since it.
+///
+/// Feed every incoming message to `ingest` until `is_ready`; then load
+/// `content`, apply the edits from `next_edit` in order and go on with
+/// live edits as usual.
+#[wasm_bindgen]
+pub struct SnapshotJoin {
+    document_id: String,
+    user_id: String,
+    snapshot: Option<(String, u64)>,
+    /// Live edits received before the snapshot, decoded and as received
+    held: Vec<(PromiseGridMessage, Vec<u8>)>,
+}
+
+#[wasm_bindgen]
+impl SnapshotJoin {
+    #[wasm_bindgen(constructor)]
+    pub fn new(document_id: &str, user_id: &str) -> SnapshotJoin {
+        SnapshotJoin { document_id: document_id.to_string(), user_id: user_id.to_string(), snapshot: None, held: Vec::new() }
+    }
+
+    /// The `snapshot_request` to send; send it again if no snapshot comes
+    pub fn request(&self) -> Vec<u8> {
+        let request = SnapshotRequest {
+            document_id: self.document_id.clone(),
+            user_id: self.user_id.clone(),
+            timestamp: js_sys::Date::now(),
+        };
+        encode_promisegrid_payload("snapshot_request", payload_data(&request))
+    }
+
+    /// Take an incoming message. Returns true when it was used here: the
+    /// first snapshot of this document, a later one (ignored), or a live
+    /// edit held back until the snapshot. Other messages, and all edits
+    /// once ready, return false and should be handled as usual. A snapshot
+    /// whose content doesn't match its hash is an error.
+    pub fn ingest(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        if data_text(&message.payload.data, "document_id") != Some(self.document_id.as_str()) {
+            return Ok(false);
+        }
+        let message_type = message.payload.message_type.as_str();
+        if message_type == "document_snapshot" {
+            if self.snapshot.is_none() {
+                let (_, Payload::Snapshot(snapshot)) = decode_payload(&message).map_err(|e| JsValue::from_str(&e))? else {
+                    return Ok(false);
+                };
+                self.snapshot = Some(open_snapshot(&snapshot).map_err(|e| JsValue::from_str(&e))?);
+            }
+            return Ok(true);
+        }
+        if self.snapshot.is_none() && CONTENT_MESSAGE_TYPES.contains(&message_type) {
+            self.held.push((message, cbor_bytes.to_vec()));
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Whether a snapshot has been applied
+    pub fn is_ready(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// The snapshot's content, once ready
+    pub fn content(&self) -> Option<String> {
+        self.snapshot.as_ref().map(|(content, _)| content.clone())
+    }
+
+    /// The Lamport time the snapshot reflects, once ready
+    pub fn version(&self) -> Option<u64> {
+        self.snapshot.as_ref().map(|(_, version)| *version)
+    }
+
+    /// The next held-back edit made after the snapshot, in causal order;
+    /// call until it returns `undefined`. Edits at or before the
+    /// snapshot's version are skipped as already in it.
+    pub fn next_edit(&mut self) -> Option<Vec<u8>> {
+        let version = self.version()?;
+        self.held.retain(|(message, _)| data_u64(&message.payload.data, "lamport").unwrap_or(0) > version);
+        let (first, _) = self
+            .held
+            .iter()
+            .enumerate()
+            .min_by(|(_, (a, _)), (_, (b, _))| clock::causal_cmp(&a.payload.data, &b.payload.data))?;
+        Some(self.held.remove(first).1)
+    }
+
+    /// Edits held back so far
+    pub fn held_count(&self) -> usize {
+        self.held.len()
+    }
+}
+
+/// The content and version of a snapshot, checked against its hash
+fn open_snapshot(snapshot: &Snapshot) -> Result<(String, u64), String> {
+    let content = decompress(&snapshot.content, false).map_err(|e| format!("Invalid snapshot: {}", e))?;
+    if content_hash(&content) != snapshot.content_hash.to_ascii_lowercase() {
+        return Err("Snapshot content does not match its hash".to_string());
+    }
+    let content = String::from_utf8(content).map_err(|_| "Invalid snapshot: content is not UTF-8".to_string())?;
+    Ok((content, snapshot.version))
+}
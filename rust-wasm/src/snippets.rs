@@ -0,0 +1,352 @@
+// Snippets: short triggers that expand to a body with tab stops. Bodies use
+// the usual placeholder syntax: `$1` and `${1:default}` are tab stops
+// (nested defaults allowed, and a repeated number mirrors the first), `$0`
+// is where the cursor ends up, `$NAME` and `${NAME:default}` take values
+// from the context, and `\$`, `\}` and `\\` are literal.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Deepest nesting of `${...}` a body may use
+const MAX_NESTING: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Snippet {
+    pub trigger: String,
+    pub body: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// What a snippet is expanded into
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct SnippetContext {
+    /// Leading whitespace of the line the snippet goes on, added after
+    /// every newline of the expansion
+    indent: String,
+    /// Values for `$NAME` variables, e.g. `SELECTION` or `DATE`
+    variables: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+struct Range {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct Placeholder {
+    index: u32,
+    /// Where the placeholder and each of its mirrors are
+    ranges: Vec<Range>,
+}
+
+#[derive(Serialize, Debug)]
+struct Expansion {
+    text: String,
+    /// Tab stops in tab order, `$0` excluded
+    placeholders: Vec<Placeholder>,
+    /// Where the cursor goes after the last tab stop
+    cursor: usize,
+}
+
+/// Expands one snippet body. Run twice: the first pass finds each tab
+/// stop's default, so the second can fill mirrors that come before it.
+struct Expander<'a> {
+    body: Vec<char>,
+    pos: usize,
+    context: &'a SnippetContext,
+    defaults: &'a BTreeMap<u32, String>,
+    text: String,
+    stops: BTreeMap<u32, Vec<Range>>,
+}
+
+fn is_variable_start(c: char) -> bool {
+    c.is_ascii_uppercase() || c == '_'
+}
+
+fn is_variable_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'
+}
+
+impl<'a> Expander<'a> {
+    fn new(body: &str, context: &'a SnippetContext, defaults: &'a BTreeMap<u32, String>) -> Expander<'a> {
+        Expander { body: body.chars().collect(), pos: 0, context, defaults, text: String::new(), stops: BTreeMap::new() }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.body.get(self.pos).copied()
+    }
+
+    /// Write text, indenting each new line
+    fn push(&mut self, text: &str) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.text.push('\n');
+                self.text.push_str(&self.context.indent);
+            }
+            self.text.push_str(line);
+        }
+    }
+
+    fn take_while(&mut self, keep: fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(keep) {
+            self.pos += 1;
+        }
+        self.body[start..self.pos].iter().collect()
+    }
+
+    /// Expand up to the end of the body, or with `depth` > 0 up to and
+    /// past the `}` closing the current `${`
+    fn expand(&mut self, depth: usize) -> Result<(), String> {
+        if depth > MAX_NESTING {
+            return Err(format!("Placeholders nested more than {} deep", MAX_NESTING));
+        }
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' if matches!(self.peek(), Some('$' | '}' | '\\')) => {
+                    let escaped = self.body[self.pos];
+                    self.pos += 1;
+                    self.text.push(escaped);
+                }
+                '}' if depth > 0 => return Ok(()),
+                '$' => self.dollar(depth)?,
+                '\n' => self.push("\n"),
+                c => self.text.push(c),
+            }
+        }
+        if depth > 0 {
+            return Err("Unclosed ${ in snippet body".to_string());
+        }
+        Ok(())
+    }
+
+    /// After a `$`
+    fn dollar(&mut self, depth: usize) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let index = self.index()?;
+                self.stop(index, None, depth)
+            }
+            Some(c) if is_variable_start(c) => {
+                let name = self.take_while(is_variable_char);
+                self.variable(&name, false, depth)
+            }
+            Some('{') => {
+                self.pos += 1;
+                let has_default = |expander: &mut Expander| match expander.peek() {
+                    Some('}') => {
+                        expander.pos += 1;
+                        Ok(false)
+                    }
+                    Some(':') => {
+                        expander.pos += 1;
+                        Ok(true)
+                    }
+                    _ => Err(format!("Expected : or }} at character {} of snippet body", expander.pos)),
+                };
+                match self.peek() {
+                    Some(c) if c.is_ascii_digit() => {
+                        let index = self.index()?;
+                        let default = has_default(self)?;
+                        self.stop(index, Some(default), depth)
+                    }
+                    Some(c) if is_variable_start(c) => {
+                        let name = self.take_while(is_variable_char);
+                        let default = has_default(self)?;
+                        self.variable(&name, default, depth)
+                    }
+                    _ => Err(format!("Expected a tab stop number or variable name at character {} of snippet body", self.pos)),
+                }
+            }
+            _ => {
+                self.text.push('$');
+                Ok(())
+            }
+        }
+    }
+
+    fn index(&mut self) -> Result<u32, String> {
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        digits.parse().map_err(|_| format!("Tab stop number {} is too large", digits))
+    }
+
+    /// A tab stop; `default` is whether a default follows (`${1:...}`),
+    /// `None` for the bare `$1` form
+    fn stop(&mut self, index: u32, default: Option<bool>, depth: usize) -> Result<(), String> {
+        let start = self.text.len();
+        match default {
+            Some(true) => self.expand(depth + 1)?,
+            _ => {
+                if let Some(text) = self.defaults.get(&index).cloned() {
+                    self.push(&text);
+                }
+            }
+        }
+        let range = Range { start, end: self.text.len() };
+        self.stops.entry(index).or_default().push(range);
+        Ok(())
+    }
+
+    /// A variable, its value from the context or else its default
+    fn variable(&mut self, name: &str, default: bool, depth: usize) -> Result<(), String> {
+        let start = self.text.len();
+        if default {
+            self.expand(depth + 1)?;
+        }
+        if let Some(value) = self.context.variables.get(name) {
+            // The default was only parsed to get past it
+            self.text.truncate(start);
+            for ranges in self.stops.values_mut() {
+                ranges.retain(|range| range.start < start);
+            }
+            self.stops.retain(|_, ranges| !ranges.is_empty());
+            let value = value.clone();
+            self.push(&value);
+        }
+        Ok(())
+    }
+
+    /// The text of each tab stop's first non-empty occurrence
+    fn defaults(&self) -> BTreeMap<u32, String> {
+        self.stops
+            .iter()
+            .filter_map(|(&index, ranges)| {
+                let range = ranges.iter().find(|range| range.end > range.start)?;
+                Some((index, self.text[range.start..range.end].to_string()))
+            })
+            .collect()
+    }
+}
+
+fn expand(body: &str, context: &SnippetContext) -> Result<Expansion, String> {
+    let none = BTreeMap::new();
+    let mut first = Expander::new(body, context, &none);
+    first.expand(0)?;
+    let defaults = first.defaults();
+    let mut expander = Expander::new(body, context, &defaults);
+    expander.expand(0)?;
+
+    let cursor = expander.stops.remove(&0).and_then(|ranges| ranges.first().map(|range| range.start));
+    Ok(Expansion {
+        cursor: cursor.unwrap_or(expander.text.len()),
+        placeholders: expander.stops.into_iter().map(|(index, ranges)| Placeholder { index, ranges }).collect(),
+        text: expander.text,
+    })
+}
+
+fn parse_context(context_json: &str) -> Result<SnippetContext, JsValue> {
+    if context_json.trim().is_empty() {
+        return Ok(SnippetContext::default());
+    }
+    serde_json::from_str(context_json).map_err(|e| JsValue::from_str(&format!("Invalid snippet context: {}", e)))
+}
+
+fn expansion_json(body: &str, context_json: &str) -> Result<String, JsValue> {
+    let context = parse_context(context_json)?;
+    let expansion = expand(body, &context).map_err(|e| JsValue::from_str(&e))?;
+    serde_json::to_string(&expansion).map_err(|e| JsValue::from_str(&format!("JSON encoding error: {}", e)))
+}
+
+/// Expand a snippet body without adding it to a library; see
+/// `SnippetLibrary::expand_snippet`
+#[wasm_bindgen]
+pub fn expand_snippet_body(body: &str, context_json: &str) -> Result<String, JsValue> {
+    expansion_json(body, context_json)
+}
+
+/// Snippets by trigger
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SnippetLibrary {
+    snippets: BTreeMap<String, Snippet>,
+}
+
+#[wasm_bindgen]
+impl SnippetLibrary {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> SnippetLibrary {
+        SnippetLibrary::default()
+    }
+
+    /// Add a snippet, replacing one with the same trigger. The trigger
+    /// can't contain whitespace; the body must parse.
+    pub fn add(&mut self, trigger: &str, body: &str, description: &str) -> Result<(), JsValue> {
+        let snippet = Snippet { trigger: trigger.to_string(), body: body.to_string(), description: description.to_string() };
+        self.insert(snippet)
+    }
+
+    pub fn remove(&mut self, trigger: &str) -> bool {
+        self.snippets.remove(trigger).is_some()
+    }
+
+    /// The snippets as JSON `[{trigger, body, description}]`, by trigger
+    pub fn list(&self) -> String {
+        serde_json::to_string(&self.snippets.values().collect::<Vec<_>>()).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Add snippets shared as JSON `[{trigger, body, description}]`
+    /// (`list` output), replacing those with the same triggers. Returns how
+    /// many were added; nothing is added if any is invalid.
+    pub fn import(&mut self, snippets_json: &str) -> Result<usize, JsValue> {
+        let snippets: Vec<Snippet> =
+            serde_json::from_str(snippets_json).map_err(|e| JsValue::from_str(&format!("Invalid snippets: {}", e)))?;
+        let mut updated = self.clone();
+        for snippet in &snippets {
+            updated.insert(snippet.clone())?;
+        }
+        *self = updated;
+        Ok(snippets.len())
+    }
+
+    /// The trigger that `before_cursor`, the text before the cursor, ends
+    /// with: the longest one that starts a word, so Tab can expand it
+    pub fn trigger_at(&self, before_cursor: &str) -> Option<String> {
+        self.snippets
+            .keys()
+            .filter(|trigger| {
+                before_cursor.strip_suffix(trigger.as_str()).is_some_and(|before| {
+                    !(trigger.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+                        && before.ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+                })
+            })
+            .max_by_key(|trigger| trigger.len())
+            .cloned()
+    }
+
+    /// Expand the snippet for `trigger`. `context_json` is `{indent,
+    /// variables: {NAME: value}}`, both optional. Returns JSON `{text,
+    /// placeholders: [{index, ranges: [{start, end}]}], cursor}`: byte
+    /// offsets into `text`, tab stops in tab order with each one's mirrors,
+    /// and where `$0` puts the cursor (the end without one).
+    pub fn expand_snippet(&self, trigger: &str, context_json: &str) -> Result<String, JsValue> {
+        let snippet = self.snippets.get(trigger).ok_or_else(|| JsValue::from_str(&format!("Unknown snippet: {}", trigger)))?;
+        expansion_json(&snippet.body, context_json)
+    }
+
+    /// Serialize the snippets to CBOR
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<SnippetLibrary, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
+
+impl SnippetLibrary {
+    fn insert(&mut self, snippet: Snippet) -> Result<(), JsValue> {
+        if snippet.trigger.is_empty() || snippet.trigger.contains(char::is_whitespace) {
+            return Err(JsValue::from_str(&format!("Invalid snippet trigger: {:?}", snippet.trigger)));
+        }
+        expand(&snippet.body, &SnippetContext::default())
+            .map_err(|e| JsValue::from_str(&format!("Invalid snippet {}: {}", snippet.trigger, e)))?;
+        self.snippets.insert(snippet.trigger.clone(), snippet);
+        Ok(())
+    }
+}
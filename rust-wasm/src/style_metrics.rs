@@ -0,0 +1,209 @@
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::performance;
+
+/// Common function words ignored when looking for overused words
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been",
+    "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has", "have", "he",
+    "her", "him", "his", "how", "i", "if", "in", "into", "is", "it", "its", "just", "me", "more",
+    "my", "no", "not", "of", "on", "one", "or", "our", "out", "she", "so", "some", "than", "that",
+    "the", "their", "them", "then", "there", "these", "they", "this", "to", "up", "us", "was",
+    "we", "were", "what", "when", "which", "who", "will", "with", "would", "you", "your",
+];
+
+/// Words ending in -ly that aren't adverbs
+const NOT_ADVERBS: &[&str] = &[
+    "only", "family", "early", "daily", "weekly", "monthly", "yearly", "reply", "supply",
+    "apply", "fly", "ally", "rely", "july", "italy", "holy", "ugly", "lovely", "friendly",
+    "likely", "lonely", "silly", "belly", "jelly", "bully", "rally", "assembly", "anomaly",
+];
+
+/// Upper bounds (inclusive) of the sentence-length histogram buckets, in words
+const SENTENCE_BUCKETS: &[(usize, &str)] =
+    &[(10, "1-10"), (20, "11-20"), (30, "21-30"), (40, "31-40"), (usize::MAX, "41+")];
+
+/// Sentences longer than this are flagged in the insights
+const LONG_SENTENCE_WORDS: usize = 30;
+
+#[derive(Serialize)]
+struct Bucket {
+    range: &'static str,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct SentenceLengths {
+    count: usize,
+    average: f64,
+    median: usize,
+    longest: usize,
+    long_sentences: usize,
+    distribution: Vec<Bucket>,
+}
+
+#[derive(Serialize)]
+struct OverusedWord {
+    word: String,
+    count: usize,
+    per_thousand: f64,
+}
+
+#[derive(Serialize)]
+struct StyleMetrics {
+    words: usize,
+    adverbs: usize,
+    adverb_percent: f64,
+    unique_words: usize,
+    type_token_ratio: f64,
+    sentences: SentenceLengths,
+    overused_words: Vec<OverusedWord>,
+    insights: Vec<String>,
+    /// Sentence and vocabulary analysis was skipped by the performance
+    /// profile
+    degraded: bool,
+}
+
+/// Lowercased words with surrounding punctuation stripped
+pub(crate) fn normalized_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'').to_lowercase())
+        .map(|w| w.trim_matches('\'').to_string())
+        .filter(|w| w.chars().any(char::is_alphabetic))
+        .collect()
+}
+
+/// Split text into sentences on `.`, `!` and `?` followed by whitespace
+pub(crate) fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = matches!(c, '.' | '!' | '?')
+            && chars.peek().is_none_or(|&(_, next)| next.is_whitespace());
+        if at_boundary || c == '\n' && chars.peek().is_some_and(|&(_, next)| next == '\n') {
+            let end = i + c.len_utf8();
+            if !text[start..end].trim().is_empty() {
+                sentences.push(text[start..end].trim());
+            }
+            start = end;
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        sentences.push(text[start..].trim());
+    }
+    sentences
+}
+
+fn is_adverb(word: &str) -> bool {
+    word.len() > 4 && word.ends_with("ly") && !NOT_ADVERBS.contains(&word)
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Writing-style metrics for the "editing insights" panel, as JSON:
+/// adverb density, sentence-length distribution, type-token ratio and the
+/// most overused content words, plus human-readable insight strings.
+/// Under the "minimal" performance profile only word and adverb counts are
+/// computed and `degraded` is true.
+#[wasm_bindgen]
+pub fn style_metrics(text: &str) -> String {
+    let words = normalized_words(text);
+    let word_count = words.len();
+
+    let adverbs = words.iter().filter(|w| is_adverb(w)).count();
+    let degraded = !performance::current().readability();
+    let words = if degraded { Vec::new() } else { words };
+    let text = if degraded { "" } else { text };
+
+    let mut frequencies: HashMap<&str, usize> = HashMap::new();
+    for word in &words {
+        *frequencies.entry(word.as_str()).or_insert(0) += 1;
+    }
+    let unique_words = frequencies.len();
+
+    let mut overused: Vec<(&str, usize)> = frequencies
+        .iter()
+        .filter(|(w, &count)| count >= 3 && w.len() > 3 && !STOPWORDS.contains(w))
+        .map(|(w, &count)| (*w, count))
+        .collect();
+    overused.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    overused.truncate(10);
+
+    let mut lengths: Vec<usize> = split_sentences(text)
+        .iter()
+        .map(|s| normalized_words(s).len())
+        .filter(|&n| n > 0)
+        .collect();
+    lengths.sort_unstable();
+    let sentence_count = lengths.len();
+    let average = if sentence_count == 0 { 0.0 } else { word_count as f64 / sentence_count as f64 };
+    let long_sentences = lengths.iter().filter(|&&n| n > LONG_SENTENCE_WORDS).count();
+
+    let mut lower = 0;
+    let distribution = SENTENCE_BUCKETS
+        .iter()
+        .map(|&(upper, range)| {
+            let count = lengths.iter().filter(|&&n| n > lower && n <= upper).count();
+            lower = upper;
+            Bucket { range, count }
+        })
+        .collect();
+
+    let ratio = |n: usize| if word_count == 0 { 0.0 } else { n as f64 / word_count as f64 };
+    let adverb_density = ratio(adverbs);
+    let type_token_ratio = ratio(unique_words);
+
+    let mut insights = Vec::new();
+    if adverb_density > 0.05 {
+        insights.push(format!(
+            "{:.1}% of words are adverbs; consider stronger verbs.",
+            adverb_density * 100.0
+        ));
+    }
+    if long_sentences > 0 {
+        insights.push(format!(
+            "{} sentence(s) have more than {} words.",
+            long_sentences, LONG_SENTENCE_WORDS
+        ));
+    }
+    if !degraded && word_count >= 100 && type_token_ratio < 0.4 {
+        insights.push("Vocabulary is repetitive; try varying word choice.".to_string());
+    }
+    if let Some((word, count)) = overused.first() {
+        if *count as f64 / word_count as f64 > 0.02 {
+            insights.push(format!("\"{}\" appears {} times.", word, count));
+        }
+    }
+
+    let metrics = StyleMetrics {
+        words: word_count,
+        adverbs,
+        adverb_percent: round2(adverb_density * 100.0),
+        unique_words,
+        type_token_ratio: round2(type_token_ratio),
+        sentences: SentenceLengths {
+            count: sentence_count,
+            average: round2(average),
+            median: lengths.get(sentence_count / 2).copied().unwrap_or(0),
+            longest: lengths.last().copied().unwrap_or(0),
+            long_sentences,
+            distribution,
+        },
+        overused_words: overused
+            .into_iter()
+            .map(|(word, count)| OverusedWord {
+                word: word.to_string(),
+                count,
+                per_thousand: round2(count as f64 * 1000.0 / word_count as f64),
+            })
+            .collect(),
+        insights,
+        degraded,
+    };
+    serde_json::to_string(&metrics).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e))
+}
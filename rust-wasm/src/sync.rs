@@ -0,0 +1,138 @@
+// Delta sync for reconnecting clients. A client back from a dropped
+// connection sends its state vector (how far it got with each sender's
+// session) in a `sync_request`; a peer answers with a `sync_response`
+// holding just the messages past those points, as they were originally
+// encoded. When the peer's log no longer reaches back far enough the
+// response says so, and the client falls back to a snapshot.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::payload::{decode_payload, ByteString, Payload, SenderVersion};
+use crate::sequence::{SenderState, FIRST_SEQ};
+use crate::{data_text, data_u64, decode_with_grid_tag, PromiseGridMessage};
+
+/// Messages kept to answer sync requests
+const SYNC_LOG_LIMIT: usize = 2_000;
+
+/// Most messages sent in one response; the rest need another request
+const MAX_SYNC_OPERATIONS: usize = 500;
+
+/// Message types that are only meaningful live, never synced
+const UNSYNCED_MESSAGE_TYPES: &[&str] = &[
+    "ack",
+    "cursor_update",
+    "presence_join",
+    "presence_leave",
+    "presence_idle",
+    "presence_active",
+    "resend_request",
+    "snapshot_request",
+    "document_snapshot",
+    "sync_request",
+    "sync_response",
+    "hello",
+    "hello_ack",
+];
+
+/// The messages a `sync_response` relays; those that don't decode are
+/// left out, as the receiver drops them too
+pub(crate) fn relayed_messages(message: &PromiseGridMessage) -> Vec<PromiseGridMessage> {
+    match decode_payload(message) {
+        Ok((_, Payload::SyncResponse(response))) => response
+            .operations
+            .iter()
+            .filter_map(|ByteString(bytes)| decode_with_grid_tag(bytes).ok())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+struct LoggedMessage {
+    user_id: String,
+    session: String,
+    seq: u64,
+    bytes: Vec<u8>,
+}
+
+/// The missing messages for one request
+pub(crate) struct SyncDelta {
+    pub operations: Vec<ByteString>,
+    pub complete: bool,
+    pub more: bool,
+}
+
+/// Recent sequenced messages, sent and received, and how far this client
+/// has got with each sender's session
+#[derive(Default)]
+pub(crate) struct SyncLog {
+    entries: VecDeque<LoggedMessage>,
+    senders: BTreeMap<(String, String), SenderState>,
+    /// Highest number per session that has fallen out of the log
+    evicted: BTreeMap<(String, String), u64>,
+}
+
+impl SyncLog {
+    /// Note a message sent or received. Returns false for messages already
+    /// seen, which shouldn't be applied again, and ones numbered too far
+    /// ahead; unsequenced and live-only messages aren't logged and return
+    /// true.
+    pub(crate) fn record(&mut self, message: &PromiseGridMessage, bytes: &[u8]) -> bool {
+        let data = &message.payload.data;
+        if UNSYNCED_MESSAGE_TYPES.contains(&message.payload.message_type.as_str()) {
+            return true;
+        }
+        let (Some(user_id), Some(session), Some(seq)) =
+            (data_text(data, "user_id"), data_text(data, "session"), data_u64(data, "seq"))
+        else {
+            return true;
+        };
+        let status = self
+            .senders
+            .entry((user_id.to_string(), session.to_string()))
+            // Anything before the first number seen was missed, not skipped
+            .or_insert_with(|| SenderState::starting_at(FIRST_SEQ))
+            .record(seq);
+        if status == "duplicate" || status == "out_of_range" {
+            return false;
+        }
+        if self.entries.len() == SYNC_LOG_LIMIT {
+            if let Some(old) = self.entries.pop_front() {
+                let evicted = self.evicted.entry((old.user_id, old.session)).or_insert(0);
+                *evicted = (*evicted).max(old.seq);
+            }
+        }
+        self.entries.push_back(LoggedMessage {
+            user_id: user_id.to_string(),
+            session: session.to_string(),
+            seq,
+            bytes: bytes.to_vec(),
+        });
+        true
+    }
+
+    /// This client's state vector: the last contiguous number per session
+    pub(crate) fn versions(&self) -> Vec<SenderVersion> {
+        self.senders
+            .iter()
+            .map(|((user_id, session), state)| SenderVersion {
+                user_id: user_id.clone(),
+                session: session.clone(),
+                seq: state.next_expected() - 1,
+            })
+            .collect()
+    }
+
+    /// The logged messages past `versions`, oldest first
+    pub(crate) fn delta(&self, versions: &[SenderVersion]) -> SyncDelta {
+        let known: BTreeMap<(&str, &str), u64> =
+            versions.iter().map(|v| ((v.user_id.as_str(), v.session.as_str()), v.seq)).collect();
+        let have = |user_id: &str, session: &str| known.get(&(user_id, session)).copied().unwrap_or(0);
+        // Incomplete when the requester lacks a message already evicted
+        let complete = self.evicted.iter().all(|((user_id, session), &evicted)| have(user_id, session) >= evicted);
+        let mut missing = self.entries.iter().filter(|entry| entry.seq > have(&entry.user_id, &entry.session));
+        let operations: Vec<ByteString> =
+            missing.by_ref().take(MAX_SYNC_OPERATIONS).map(|entry| ByteString(entry.bytes.clone())).collect();
+        let more = missing.next().is_some();
+        SyncDelta { operations, complete, more }
+    }
+}
@@ -0,0 +1,73 @@
+// The document tree as JSON for external tools and plugins: parse to the
+// same `Block`/`Inline` tree the converters use, transform it, and write
+// it back out as markdown.
+
+use wasm_bindgen::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::ast::{parse_markdown, to_markdown, Block, Document};
+
+#[derive(Deserialize)]
+struct AstDocument {
+    #[serde(default)]
+    front_matter: Option<String>,
+    blocks: Vec<Block>,
+}
+
+/// Line span of each top-level block: from its first line to the last
+/// non-blank line before the next block
+fn block_spans(content: &str, starts: &[usize]) -> Vec<(usize, usize)> {
+    let lines: Vec<&str> = content.lines().collect();
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| {
+            let mut end = starts.get(n + 1).map_or(lines.len(), |&next| next - 1).max(start);
+            while end > start && lines.get(end - 1).is_some_and(|l| l.trim().is_empty()) {
+                end -= 1;
+            }
+            (start, end)
+        })
+        .collect()
+}
+
+/// Parse markdown into a JSON tree: `{front_matter, blocks}`. Blocks and
+/// inlines are objects tagged by `type` (`heading`, `paragraph`,
+/// `code_block`, `list`, `table`, ..., `text`, `emphasis`, `link`, ...)
+/// carrying the document's structure, such as list start numbers, task
+/// states, table alignment and link titles. Source markers are not kept:
+/// a setext heading, a `*` or `+` bullet and `__strong__` all parse the
+/// same as their `#`, `-` and `**` forms. Each top-level block also has a
+/// `position` of 1-based `{start_line, end_line}` in `content`, counting
+/// front matter lines.
+#[wasm_bindgen]
+pub fn parse_to_ast(content: &str) -> String {
+    let doc = parse_markdown(content);
+    let spans = block_spans(content, &doc.lines);
+    let blocks: Vec<Value> = doc
+        .blocks
+        .iter()
+        .zip(spans)
+        .map(|(block, (start, end))| {
+            let mut value = serde_json::to_value(block).unwrap_or(Value::Null);
+            if let Value::Object(fields) = &mut value {
+                fields.insert("position".to_string(), json!({ "start_line": start, "end_line": end }));
+            }
+            value
+        })
+        .collect();
+    json!({ "front_matter": doc.front_matter, "blocks": blocks }).to_string()
+}
+
+/// Write a JSON tree in the shape `parse_to_ast` returns back out as
+/// markdown, in the converters' style (ATX headings, `-` bullets, `*` and
+/// `**` emphasis), so an unchanged tree gives the same document but not
+/// always the same text. `position` fields are ignored, so blocks can be
+/// added, moved or edited without updating them.
+#[wasm_bindgen]
+pub fn render_from_ast(ast_json: &str) -> Result<String, JsValue> {
+    let ast: AstDocument = serde_json::from_str(ast_json).map_err(|e| JsValue::from_str(&format!("Invalid AST: {}", e)))?;
+    let doc = Document { front_matter: ast.front_matter, lines: Vec::new(), blocks: ast.blocks };
+    Ok(to_markdown(&doc))
+}
@@ -0,0 +1,73 @@
+use wasm_bindgen::prelude::*;
+
+use crate::headings::{plain_heading_text, slugged_headings};
+use crate::links::escape_link_text;
+
+const TOC_START: &str = "<!-- toc -->";
+/// Accepted closing markers; the first is written when inserting
+const TOC_END_MARKERS: &[&str] = &["<!-- /toc -->", "<!-- tocstop -->"];
+
+/// Byte range between the TOC markers: (end of start marker, start of end
+/// marker or None if the TOC was never closed)
+fn marker_range(content: &str) -> Option<(usize, Option<usize>)> {
+    let start = content.find(TOC_START)? + TOC_START.len();
+    let end = TOC_END_MARKERS
+        .iter()
+        .filter_map(|m| content[start..].find(m).map(|pos| start + pos))
+        .min();
+    Some((start, end))
+}
+
+/// Build the TOC list for `content`, skipping headings inside an existing
+/// TOC block so it never lists itself
+fn build_toc(content: &str, max_depth: u8) -> String {
+    let max_depth = max_depth.clamp(1, 6);
+    let skip = marker_range(content).map(|(start, end)| (start, end.unwrap_or(start)));
+
+    let headings: Vec<_> = slugged_headings(content)
+        .into_iter()
+        .filter(|(h, _)| h.level <= max_depth)
+        .filter(|(h, _)| !skip.is_some_and(|(s, e)| h.start >= s && h.start < e))
+        .collect();
+    let Some(min_level) = headings.iter().map(|(h, _)| h.level).min() else {
+        return String::new();
+    };
+
+    headings
+        .iter()
+        .map(|(h, slug)| {
+            format!(
+                "{}- [{}](#{})",
+                "  ".repeat((h.level - min_level) as usize),
+                escape_link_text(&plain_heading_text(&h.text)),
+                slug
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Generate a nested markdown table of contents from the document's
+/// headings (levels 1..=`max_depth`), linking to GitHub-compatible anchors.
+/// Duplicate headings get `-1`, `-2`, ... suffixes.
+#[wasm_bindgen]
+pub fn generate_toc(content: &str, max_depth: u8) -> String {
+    build_toc(content, max_depth)
+}
+
+/// Insert or refresh the TOC between `<!-- toc -->` and `<!-- /toc -->`
+/// markers (`<!-- tocstop -->` is also accepted). A lone opening marker
+/// gets the TOC and a closing marker inserted after it. Documents without
+/// markers are returned unchanged.
+#[wasm_bindgen]
+pub fn update_toc(content: &str, max_depth: u8) -> String {
+    let Some((start, end)) = marker_range(content) else {
+        return content.to_string();
+    };
+    let toc = build_toc(content, max_depth);
+    let block = format!("\n\n{}\n\n", toc);
+    match end {
+        Some(end) => format!("{}{}{}", &content[..start], block, &content[end..]),
+        None => format!("{}{}{}{}", &content[..start], block, TOC_END_MARKERS[0], &content[start..]),
+    }
+}
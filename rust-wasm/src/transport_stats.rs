@@ -0,0 +1,119 @@
+// Transport statistics for PromiseGridHandler: messages and bytes sent
+// and received by type, how long encoding and decoding take, and how
+// often messages had to be sent again, for a connection-health display.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Milliseconds from `performance.now()`, which resolves well below a
+/// millisecond, or from the wall clock where there is none
+pub(crate) fn precise_now() -> f64 {
+    let performance = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("performance")).ok();
+    performance
+        .and_then(|performance| {
+            let now: js_sys::Function = js_sys::Reflect::get(&performance, &JsValue::from_str("now")).ok()?.dyn_into().ok()?;
+            now.call0(&performance).ok()?.as_f64()
+        })
+        .unwrap_or_else(js_sys::Date::now)
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub(crate) struct Traffic {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+impl Traffic {
+    fn add(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub(crate) struct TypeTraffic {
+    pub sent: Traffic,
+    pub received: Traffic,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub(crate) struct Timing {
+    pub count: u64,
+    pub average_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Timing {
+    fn add(&mut self, elapsed_ms: f64) {
+        let elapsed_ms = elapsed_ms.max(0.0);
+        self.count += 1;
+        self.average_ms += (elapsed_ms - self.average_ms) / self.count as f64;
+        self.max_ms = self.max_ms.max(elapsed_ms);
+    }
+}
+
+/// Messages waiting in the handler's queues
+#[derive(Serialize, Debug, Clone, Copy)]
+pub(crate) struct QueueDepth {
+    /// Sent and not yet acknowledged
+    pub unacked: usize,
+    /// Queued by resend requests
+    pub resend: usize,
+    /// Received through sync and not yet applied
+    pub synced: usize,
+}
+
+/// Counters kept by PromiseGridHandler
+#[derive(Serialize, Debug, Clone, Default)]
+pub(crate) struct StatsCollector {
+    sent: Traffic,
+    received: Traffic,
+    by_type: BTreeMap<String, TypeTraffic>,
+    encode: Timing,
+    decode: Timing,
+    /// Unacknowledged messages sent again
+    retries: u64,
+    /// Messages sent again for resend requests
+    resends: u64,
+}
+
+impl StatsCollector {
+    pub(crate) fn record_sent(&mut self, message_type: &str, bytes: usize) {
+        self.sent.add(bytes);
+        self.by_type.entry(message_type.to_string()).or_default().sent.add(bytes);
+    }
+
+    pub(crate) fn record_received(&mut self, message_type: &str, bytes: usize) {
+        self.received.add(bytes);
+        self.by_type.entry(message_type.to_string()).or_default().received.add(bytes);
+    }
+
+    pub(crate) fn record_encode(&mut self, elapsed_ms: f64) {
+        self.encode.add(elapsed_ms);
+    }
+
+    pub(crate) fn record_decode(&mut self, elapsed_ms: f64) {
+        self.decode.add(elapsed_ms);
+    }
+
+    pub(crate) fn record_retry(&mut self) {
+        self.retries += 1;
+    }
+
+    pub(crate) fn record_resend(&mut self) {
+        self.resends += 1;
+    }
+
+    /// JSON of the counters and the current queue depths
+    pub(crate) fn snapshot(&self, queue_depth: QueueDepth) -> String {
+        #[derive(Serialize)]
+        struct Snapshot<'a> {
+            #[serde(flatten)]
+            stats: &'a StatsCollector,
+            queue_depth: QueueDepth,
+        }
+        serde_json::to_string(&Snapshot { stats: self, queue_depth }).unwrap_or_else(|_| "{}".into())
+    }
+}
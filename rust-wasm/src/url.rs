@@ -0,0 +1,238 @@
+use wasm_bindgen::prelude::*;
+
+use crate::links::parse_inline_link;
+use crate::markdown::{code_ranges, in_ranges};
+
+/// Schemes recognized in front of `://`
+const SCHEMES: &[&str] = &["http", "https", "ftp", "ftps", "ws", "wss", "file", "sftp", "git", "ssh"];
+
+/// Top-level domains accepted for bare domains like `example.com/path`.
+/// Kept deliberately short: a bare `name.ext` is only a URL if the
+/// extension is a well-known TLD and the host is lowercase, so file names
+/// like `lib.rs` and names like `ASP.NET` or `Node.io` are left alone.
+const BARE_TLDS: &[&str] = &[
+    "com", "org", "net", "edu", "gov", "io", "dev", "app", "ai", "co", "uk", "de", "fr", "jp",
+    "ca", "au", "us", "info", "biz", "me", "tv", "eu", "nl", "ch", "se", "no", "es", "it",
+];
+
+/// Characters that end a URL outright
+fn is_terminator(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '<' | '>' | '"' | '`')
+}
+
+fn is_host_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '.'
+}
+
+/// Length of the scheme prefix (`https://`, `mailto:`) at the start of `text`
+fn scheme_len(text: &str) -> Option<usize> {
+    if text.len() > 7 && text.get(..7).is_some_and(|p| p.eq_ignore_ascii_case("mailto:")) {
+        return Some(7);
+    }
+    let sep = text.find("://")?;
+    let scheme = &text[..sep];
+    SCHEMES
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(scheme))
+        .then_some(sep + 3)
+}
+
+/// Whether `host` looks like a real domain: dotted labels ending in a TLD,
+/// for a bare domain a lowercase one ending in a well-known TLD
+fn is_plausible_host(host: &str, require_known_tld: bool) -> bool {
+    let labels: Vec<&str> = host.split('.').collect();
+    if labels.len() < 2 || labels.iter().any(|l| l.is_empty() || l.starts_with('-') || l.ends_with('-')) {
+        return false;
+    }
+    let tld = labels[labels.len() - 1].to_ascii_lowercase();
+    if require_known_tld {
+        !host.chars().any(char::is_uppercase) && BARE_TLDS.contains(&tld.as_str())
+    } else {
+        tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic())
+    }
+}
+
+/// Trim trailing punctuation that is almost always sentence punctuation
+/// rather than part of the URL, keeping balanced closing parentheses
+/// (e.g. Wikipedia links).
+fn trim_trailing(url: &str) -> &str {
+    let mut end = url.len();
+    while let Some(last) = url[..end].chars().next_back() {
+        let trim = match last {
+            '.' | ',' | ';' | ':' | '!' | '?' | '\'' | '*' | '_' => true,
+            ')' => url[..end].matches(')').count() > url[..end].matches('(').count(),
+            ']' => url[..end].matches(']').count() > url[..end].matches('[').count(),
+            _ => false,
+        };
+        if !trim {
+            break;
+        }
+        end -= last.len_utf8();
+    }
+    &url[..end]
+}
+
+/// Length in bytes of the URL starting at the beginning of `text`, if any.
+/// Recognizes `scheme://...`, `mailto:`, `www.` hosts and bare domains with
+/// a well-known TLD, and strips trailing sentence punctuation.
+pub(crate) fn url_len(text: &str) -> Option<usize> {
+    let body_end = text.find(is_terminator).unwrap_or(text.len());
+    let candidate = trim_trailing(&text[..body_end]);
+
+    let (host_start, known_tld) = match scheme_len(candidate) {
+        Some(len) if candidate[..len].eq_ignore_ascii_case("mailto:") => {
+            return candidate[len..].contains('@').then_some(candidate.len());
+        }
+        Some(len) => (len, false),
+        None if candidate.len() > 4 && candidate.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("www.")) => (0, false),
+        None => (0, true),
+    };
+
+    let rest = &candidate[host_start..];
+    let host_end = rest.find(|c: char| !is_host_char(c)).unwrap_or(rest.len());
+    let host = rest[..host_end].trim_end_matches('.');
+    // Allow `user@host`-style credentials and ports only after an explicit scheme
+    let next = rest[host_end..].chars().next();
+    let valid_next = match next {
+        None | Some('/') | Some('?') | Some('#') => true,
+        Some(':') | Some('@') => host_start > 0,
+        _ => false,
+    };
+
+    if host_start > 0 && (host == "localhost" || host.parse::<std::net::Ipv4Addr>().is_ok()) {
+        return valid_next.then_some(candidate.len());
+    }
+    if valid_next && is_plausible_host(host, known_tld) {
+        Some(candidate.len())
+    } else {
+        None
+    }
+}
+
+/// Whether a recognized URL starts with a scheme or `www.`, rather than
+/// being a bare domain
+fn has_prefix(url: &str) -> bool {
+    scheme_len(url).is_some() || url.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("www."))
+}
+
+/// Turn a recognized URL into a usable link target, adding `https://` to
+/// `www.` and bare-domain URLs
+pub(crate) fn href_for(url: &str) -> String {
+    if scheme_len(url).is_some() {
+        url.to_string()
+    } else {
+        format!("https://{}", url)
+    }
+}
+
+/// Decode `%XX` escapes (`caf%C3%A9` -> `café`)
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() {
+            if let Ok(byte) = u8::from_str_radix(&text[i + 1..i + 3], 16) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Byte ranges of all URLs in `text`
+pub(crate) fn find_urls(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut skip_until = 0;
+
+    for (i, c) in text.char_indices() {
+        // URLs start at a word boundary; `@` excludes the domain of an email
+        let at_boundary = prev.is_none_or(|p| !p.is_alphanumeric() && !matches!(p, '@' | '.' | '/' | '-' | '_'));
+        prev = Some(c);
+        if i < skip_until || !at_boundary || !c.is_alphanumeric() {
+            continue;
+        }
+        if let Some(len) = url_len(&text[i..]) {
+            ranges.push((i, i + len));
+            skip_until = i + len;
+        }
+    }
+    ranges
+}
+
+/// Byte ranges already covered by markdown link syntax: inline links
+/// (both text and destination) and `<...>` autolinks
+fn link_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' => {
+                if let Some(link) = parse_inline_link(text, i) {
+                    ranges.push((i, link.end));
+                }
+            }
+            '<' => {
+                if let Some(close) = text[i..].find('>') {
+                    if !text[i + 1..i + close].contains(char::is_whitespace) {
+                        ranges.push((i, i + close + 1));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// Convert every bare URL with a scheme or a `www.` host in the document
+/// into a markdown link. Bare domains (`example.com`) are too easily
+/// ordinary prose to link, and URLs inside code, existing links, and
+/// `<...>` autolinks are left alone.
+#[wasm_bindgen]
+pub fn autolink_urls(content: &str) -> String {
+    let mut skip = code_ranges(content);
+    skip.extend(link_ranges(content));
+
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (start, end) in find_urls(content) {
+        let url = &content[start..end];
+        if in_ranges(&skip, start) || !has_prefix(url) {
+            continue;
+        }
+        result.push_str(&content[last..start]);
+        if scheme_len(url).is_some() {
+            result.push_str(&format!("<{}>", url));
+        } else {
+            result.push_str(&format!("[{}]({})", url, href_for(url)));
+        }
+        last = end;
+    }
+    result.push_str(&content[last..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prose_names_are_not_urls() {
+        for text in ["Built on ASP.NET today.", "Try Node.io now.", "See Example.com for more.", "Edit lib.rs here."] {
+            assert_eq!(find_urls(text), Vec::new(), "{:?}", text);
+            assert_eq!(autolink_urls(text), text);
+        }
+    }
+
+    #[test]
+    fn only_prefixed_urls_are_autolinked() {
+        assert_eq!(autolink_urls("see example.com/docs"), "see example.com/docs");
+        assert_eq!(autolink_urls("see www.example.com"), "see [www.example.com](https://www.example.com)");
+        assert_eq!(autolink_urls("see https://example.io/x."), "see <https://example.io/x>.");
+    }
+}
@@ -0,0 +1,718 @@
+// Structural checks on incoming PromiseGrid messages. Decoding fails on
+// the first problem with a bare serde message; validation walks the whole
+// message instead and reports every problem with the path of the field,
+// so the transport layer can log why a peer was rejected.
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use ciborium::Value;
+use std::collections::BTreeMap;
+
+use crate::access::ACL_MESSAGE_TYPES;
+use crate::comments::COMMENT_MESSAGE_TYPES;
+use crate::payload::decode_payload;
+use crate::presence::PRESENCE_MESSAGE_TYPES;
+use crate::codec::{self, Framing};
+use crate::{PromiseGridMessage, MESSAGE_TYPES, PROTOCOL_HASH};
+
+/// Largest encoded message accepted: a checkpoint of a document at the
+/// default quota, with room for the envelope
+const MAX_MESSAGE_BYTES: usize = 2 * 1024 * 1024;
+
+/// Longest id accepted in `document_id`, `user_id` and the like
+const MAX_ID_BYTES: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    /// Text bounded by `MAX_ID_BYTES`
+    Id,
+    Text,
+    U32,
+    U64,
+    /// Integer or float
+    Number,
+    Bytes,
+    Bool,
+    Array,
+}
+
+impl Kind {
+    fn expected(self) -> &'static str {
+        match self {
+            Kind::Id | Kind::Text => "text",
+            Kind::U32 => "an unsigned 32-bit integer",
+            Kind::U64 => "an unsigned integer",
+            Kind::Number => "a number",
+            Kind::Bytes => "a byte string",
+            Kind::Bool => "a boolean",
+            Kind::Array => "an array",
+        }
+    }
+
+    fn accepts(self, value: &Value) -> bool {
+        match (self, value) {
+            (Kind::Id | Kind::Text, Value::Text(_)) => true,
+            (Kind::U32, Value::Integer(n)) => u32::try_from(*n).is_ok(),
+            (Kind::U64, Value::Integer(n)) => u64::try_from(*n).is_ok(),
+            (Kind::Number, Value::Integer(_) | Value::Float(_)) => true,
+            (Kind::Bytes, Value::Bytes(_)) => true,
+            (Kind::Bool, Value::Bool(_)) => true,
+            (Kind::Array, Value::Array(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A field of a payload: name, kind and whether it is required
+type Field = (&'static str, Kind, bool);
+
+/// Envelope fields any message may carry (`payload::Envelope`)
+const ENVELOPE: &[Field] = &[
+    ("message_id", Kind::Id, false),
+    ("seq", Kind::U64, false),
+    ("session", Kind::Id, false),
+    ("lamport", Kind::U64, false),
+    ("nonce", Kind::Id, false),
+    ("sent_at", Kind::Number, false),
+    ("capability", Kind::Bytes, false),
+    ("capability_signature", Kind::Bytes, false),
+    ("sender_signature", Kind::Bytes, false),
+];
+
+// These mirror the structs in payload.rs, which reject unknown fields, so
+// the kinds listed here are closed: any other field is an error. The tests
+// below validate a sample of every struct with each field set.
+
+const DOCUMENT_EDIT: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("edit_type", Kind::Id, true),
+    ("position", Kind::U32, true),
+    ("content", Kind::Text, true),
+    ("timestamp", Kind::Number, true),
+    ("user_id", Kind::Id, true),
+    ("length", Kind::U32, false),
+    ("prev_hash", Kind::Bytes, false),
+];
+
+const DOCUMENT_STATS: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("word_count", Kind::U32, true),
+    ("char_count", Kind::U32, true),
+    ("line_count", Kind::U32, true),
+    ("timestamp", Kind::Number, true),
+    ("user_id", Kind::Id, true),
+];
+
+const CURSOR_UPDATE: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("offset", Kind::U64, true),
+    ("selection_start", Kind::U64, true),
+    ("selection_end", Kind::U64, true),
+    ("timestamp", Kind::Number, true),
+];
+
+const PRESENCE: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("timestamp", Kind::Number, true),
+    ("name", Kind::Text, false),
+    ("avatar_hash", Kind::Id, false),
+];
+
+const SNAPSHOT: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("version", Kind::U64, true),
+    ("content", Kind::Bytes, true),
+    ("content_hash", Kind::Id, true),
+    ("metadata", Kind::Bytes, false),
+    ("timestamp", Kind::Number, true),
+];
+
+const SNAPSHOT_REQUEST: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("timestamp", Kind::Number, true),
+];
+
+const ENCRYPTED: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("aead_nonce", Kind::Bytes, true),
+    ("ciphertext", Kind::Bytes, true),
+];
+
+const KEY_SHARE: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("recipient_id", Kind::Id, true),
+    ("ephemeral_key", Kind::Bytes, true),
+    ("aead_nonce", Kind::Bytes, true),
+    ("wrapped_key", Kind::Bytes, true),
+    ("timestamp", Kind::Number, true),
+];
+
+const BATCH: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("timestamp", Kind::Number, true),
+    ("operations", Kind::Array, true),
+    ("signature", Kind::Bytes, false),
+];
+
+const SYNC_REQUEST: &[Field] = &[
+    ("user_id", Kind::Id, true),
+    ("versions", Kind::Array, true),
+    ("timestamp", Kind::Number, true),
+];
+
+const SYNC_RESPONSE: &[Field] = &[
+    ("user_id", Kind::Id, true),
+    ("target_user_id", Kind::Id, true),
+    ("target_session", Kind::Id, true),
+    ("operations", Kind::Array, true),
+    ("complete", Kind::Bool, true),
+    ("more", Kind::Bool, true),
+    ("timestamp", Kind::Number, true),
+];
+
+const ACL_CHANGE: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("target_user_id", Kind::Id, true),
+    ("role", Kind::Text, false),
+    ("timestamp", Kind::Number, true),
+    ("signature", Kind::Bytes, true),
+];
+
+const COMMENT_CHANGE: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("comment_id", Kind::Id, true),
+    ("reply_id", Kind::Id, false),
+    ("body", Kind::Text, false),
+    ("start", Kind::U64, false),
+    ("end", Kind::U64, false),
+    ("quote", Kind::Text, false),
+    ("prefix", Kind::Text, false),
+    ("suffix", Kind::Text, false),
+    ("resolved", Kind::Bool, false),
+    ("timestamp", Kind::Number, true),
+];
+
+const MENTION: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("mentioned_user_id", Kind::Id, true),
+    ("start", Kind::U64, true),
+    ("end", Kind::U64, true),
+    ("line", Kind::U64, true),
+    ("excerpt", Kind::Text, true),
+    ("timestamp", Kind::Number, true),
+];
+
+const WORD_GOAL: &[Field] = &[
+    ("document_id", Kind::Id, true),
+    ("user_id", Kind::Id, true),
+    ("target", Kind::U64, true),
+    ("deadline", Kind::Number, false),
+    ("set_by", Kind::Id, true),
+    ("set_at", Kind::Number, true),
+    ("words_today", Kind::U64, true),
+    ("timestamp", Kind::Number, true),
+];
+
+const HELLO: &[Field] = &[
+    ("user_id", Kind::Id, true),
+    ("protocol_version", Kind::U32, true),
+    ("min_protocol_version", Kind::U32, true),
+    ("compression", Kind::Array, true),
+    ("encryption", Kind::Array, true),
+    ("editing", Kind::Array, true),
+    ("timestamp", Kind::Number, true),
+];
+
+const HELLO_ACK: &[Field] = &[
+    ("user_id", Kind::Id, true),
+    ("target_user_id", Kind::Id, true),
+    ("protocol_version", Kind::U32, true),
+    ("compression", Kind::Id, false),
+    ("encryption", Kind::Id, false),
+    ("editing", Kind::Id, false),
+    ("error", Kind::Text, false),
+    ("timestamp", Kind::Number, true),
+];
+
+fn schema(message_type: &str) -> Option<&'static [Field]> {
+    match message_type {
+        "document_edit" => Some(DOCUMENT_EDIT),
+        "document_stats" => Some(DOCUMENT_STATS),
+        "cursor_update" => Some(CURSOR_UPDATE),
+        "document_snapshot" => Some(SNAPSHOT),
+        "snapshot_request" => Some(SNAPSHOT_REQUEST),
+        "encrypted" => Some(ENCRYPTED),
+        "key_share" => Some(KEY_SHARE),
+        "batch" => Some(BATCH),
+        "sync_request" => Some(SYNC_REQUEST),
+        "sync_response" => Some(SYNC_RESPONSE),
+        "hello" => Some(HELLO),
+        "hello_ack" => Some(HELLO_ACK),
+        "mention" => Some(MENTION),
+        "word_goal" => Some(WORD_GOAL),
+        t if ACL_MESSAGE_TYPES.contains(&t) => Some(ACL_CHANGE),
+        t if COMMENT_MESSAGE_TYPES.contains(&t) => Some(COMMENT_CHANGE),
+        t if PRESENCE_MESSAGE_TYPES.iter().any(|(_, p)| *p == t) => Some(PRESENCE),
+        _ => None,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::Bytes(_) => "a byte string",
+        Value::Text(_) => "text",
+        Value::Array(_) => "an array",
+        Value::Map(_) => "a map",
+        _ => "an unsupported value",
+    }
+}
+
+#[derive(Serialize)]
+struct Issue {
+    /// Dotted path of the field, e.g. `payload.data.offset`; `tag` for the
+    /// CBOR tag and empty for the message as a whole
+    path: String,
+    /// "error" makes the message invalid; "warning" is accepted
+    severity: &'static str,
+    problem: String,
+}
+
+#[derive(Serialize)]
+struct ValidationReport {
+    valid: bool,
+    message_type: Option<String>,
+    /// Encoded size in bytes
+    size: usize,
+    issues: Vec<Issue>,
+}
+
+struct Checker {
+    issues: Vec<Issue>,
+}
+
+impl Checker {
+    fn error(&mut self, path: &str, problem: String) {
+        self.issues.push(Issue { path: path.to_string(), severity: "error", problem });
+    }
+
+    fn warning(&mut self, path: &str, problem: String) {
+        self.issues.push(Issue { path: path.to_string(), severity: "warning", problem });
+    }
+
+    /// A map's entries by text key, reporting other keys
+    fn text_keys<'a>(&mut self, path: &str, map: &'a [(Value, Value)]) -> BTreeMap<&'a str, &'a Value> {
+        let mut fields = BTreeMap::new();
+        for (key, value) in map {
+            match key {
+                Value::Text(key) => {
+                    fields.insert(key.as_str(), value);
+                }
+                other => self.error(path, format!("Key is {}, expected text", type_name(other))),
+            }
+        }
+        fields
+    }
+
+    /// The map at `path`, or None after reporting what was there instead
+    fn map<'a>(&mut self, path: &str, value: Option<&'a Value>) -> Option<BTreeMap<&'a str, &'a Value>> {
+        match value {
+            Some(Value::Map(map)) => Some(self.text_keys(path, map)),
+            Some(other) => {
+                self.error(path, format!("Expected a map, got {}", type_name(other)));
+                None
+            }
+            None => {
+                self.error(path, "Required field is missing".to_string());
+                None
+            }
+        }
+    }
+
+    fn field(&mut self, path: &str, (name, kind, required): Field, value: Option<&Value>) {
+        let path = format!("{}.{}", path, name);
+        match value {
+            None if required => self.error(&path, "Required field is missing".to_string()),
+            None => {}
+            Some(value) if !kind.accepts(value) => {
+                self.error(&path, format!("Expected {}, got {}", kind.expected(), type_name(value)))
+            }
+            Some(Value::Text(text)) if kind == Kind::Id && text.len() > MAX_ID_BYTES => self.error(
+                &path,
+                format!("{} bytes long, more than the {} allowed", text.len(), MAX_ID_BYTES),
+            ),
+            Some(_) => {}
+        }
+    }
+}
+
+/// Strip the `grid` tag from the front of a message, reporting a missing
+/// or different tag. A legacy framing is a warning while
+/// `set_legacy_framing` allows it and an error otherwise.
+fn untag<'a>(bytes: &'a [u8], checker: &mut Checker) -> &'a [u8] {
+    let framing = match codec::framing(bytes) {
+        Ok(framing) => framing,
+        Err(e) => {
+            checker.error("tag", e);
+            return bytes;
+        }
+    };
+    match framing.body(bytes) {
+        Ok(body) => {
+            match framing {
+                Framing::Untagged => {
+                    checker.warning("tag", "Message has no grid tag; accepted as a legacy untagged message".to_string())
+                }
+                Framing::LongGrid { .. } => {
+                    checker.warning("tag", "Grid tag has an over-long head; accepted as a legacy framing".to_string())
+                }
+                _ => {}
+            }
+            body
+        }
+        Err(e) => {
+            checker.error("tag", e);
+            match framing {
+                Framing::LongGrid { header } => &bytes[header..],
+                _ => bytes,
+            }
+        }
+    }
+}
+
+/// Check a received message before handing it to the handlers: the CBOR
+/// tag, that it parses, the protocol hash, the shape of the payload, the
+/// type and presence of each field of the message kinds with a typed
+/// payload, and size limits. Returns JSON `{valid, message_type, size,
+/// issues: [{path, severity, problem}]}` listing every problem found, each
+/// at its field path (`payload.data.offset`); `valid` is false when any is
+/// an error. Warnings (a legacy framing, when allowed; an unknown message type
+/// or top-level field) don't stop the message being decoded.
+#[wasm_bindgen]
+pub fn validate_message(cbor_bytes: &[u8]) -> String {
+    let mut checker = Checker { issues: Vec::new() };
+    let message_type = check(cbor_bytes, &mut checker);
+    let report = ValidationReport {
+        valid: checker.issues.iter().all(|i| i.severity != "error"),
+        message_type,
+        size: cbor_bytes.len(),
+        issues: checker.issues,
+    };
+    serde_json::to_string(&report).unwrap_or_else(|_| "{}".into())
+}
+
+/// The first error `validate_message` would report, as "path: problem"
+pub(crate) fn first_error(cbor_bytes: &[u8]) -> Option<String> {
+    let mut checker = Checker { issues: Vec::new() };
+    check(cbor_bytes, &mut checker);
+    let issue = checker.issues.into_iter().find(|i| i.severity == "error")?;
+    Some(if issue.path.is_empty() { issue.problem } else { format!("{}: {}", issue.path, issue.problem) })
+}
+
+/// Run the checks, returning the message type when there is one
+fn check(cbor_bytes: &[u8], checker: &mut Checker) -> Option<String> {
+    if cbor_bytes.is_empty() {
+        checker.error("", "Message is empty".to_string());
+        return None;
+    }
+    if cbor_bytes.len() > MAX_MESSAGE_BYTES {
+        checker.error(
+            "",
+            format!("Message is {} bytes, more than the {} allowed", cbor_bytes.len(), MAX_MESSAGE_BYTES),
+        );
+        return None;
+    }
+    let body = untag(cbor_bytes, checker);
+    let root: Value = match crate::from_cbor(body) {
+        Ok(root) => root,
+        Err(e) => {
+            checker.error("", format!("Not valid CBOR: {}", e));
+            return None;
+        }
+    };
+
+    let message = checker.map("", Some(&root))?;
+    match message.get("protocol_hash") {
+        Some(Value::Text(hash)) if hash == PROTOCOL_HASH => {}
+        Some(Value::Text(hash)) => checker.error("protocol_hash", format!("Unknown protocol {}", hash)),
+        Some(other) => checker.error("protocol_hash", format!("Expected text, got {}", type_name(other))),
+        None => checker.error("protocol_hash", "Required field is missing".to_string()),
+    }
+    for key in message.keys().filter(|k| !["protocol_hash", "payload"].contains(k)) {
+        checker.warning(key, "Unknown field, ignored".to_string());
+    }
+
+    let payload = checker.map("payload", message.get("payload").copied())?;
+    for key in payload.keys().filter(|k| !["message_type", "data"].contains(k)) {
+        checker.warning(&format!("payload.{}", key), "Unknown field, ignored".to_string());
+    }
+    let message_type = match payload.get("message_type") {
+        Some(Value::Text(t)) => Some(t.clone()),
+        Some(other) => {
+            checker.error("payload.message_type", format!("Expected text, got {}", type_name(other)));
+            None
+        }
+        None => {
+            checker.error("payload.message_type", "Required field is missing".to_string());
+            None
+        }
+    };
+    if let Some(t) = message_type.as_deref().filter(|t| !MESSAGE_TYPES.contains(t)) {
+        checker.warning("payload.message_type", format!("Unknown message type {}", t));
+    }
+
+    let Some(data) = checker.map("payload.data", payload.get("data").copied()) else {
+        return message_type;
+    };
+    let typed = message_type.as_deref().and_then(schema);
+    for &field in ENVELOPE.iter().chain(typed.unwrap_or(&[])) {
+        checker.field("payload.data", field, data.get(field.0).copied());
+    }
+    if let Some(fields) = typed {
+        for key in data.keys().filter(|k| !ENVELOPE.iter().chain(fields).any(|f| f.0 == **k)) {
+            checker.error(&format!("payload.data.{}", key), "Unknown field".to_string());
+        }
+    }
+
+    // Anything the checks above missed still surfaces here, once the
+    // message has otherwise passed
+    if checker.issues.iter().all(|i| i.severity != "error") {
+        let decoded = root
+            .deserialized::<PromiseGridMessage>()
+            .map_err(|e| e.to_string())
+            .and_then(|m| decode_payload(&m));
+        if let Err(e) = decoded {
+            checker.error("payload.data", e);
+        }
+    }
+    message_type
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::*;
+    use crate::{encode_with_grid_tag, DocumentEdit, MessagePayload};
+    use std::collections::HashMap;
+
+    /// Every field set, so a field added to a struct must be added here
+    /// and to its schema
+    fn samples() -> Vec<(&'static str, HashMap<String, Value>)> {
+        let id = || "id".to_string();
+        let bytes = || vec![1, 2, 3];
+        let cursor = CursorUpdate {
+            document_id: id(),
+            user_id: id(),
+            offset: 1,
+            selection_start: 1,
+            selection_end: 2,
+            timestamp: 1.0,
+        };
+        let mut edit = payload_data(&DocumentEdit {
+            document_id: id(),
+            edit_type: "insert".into(),
+            position: 1,
+            content: "x".into(),
+            timestamp: 1.0,
+            user_id: id(),
+            length: Some(1),
+            prev_hash: Some(bytes()),
+        });
+        edit.extend(payload_data(&Envelope {
+            message_id: Some(id()),
+            seq: Some(1),
+            session: Some(id()),
+            lamport: Some(1),
+            nonce: Some(id()),
+            sent_at: Some(1.0),
+            capability: Some(bytes()),
+            capability_signature: Some(bytes()),
+            sender_signature: Some(bytes()),
+        }));
+        vec![
+            ("document_edit", edit),
+            (
+                "document_stats",
+                payload_data(&DocumentStats {
+                    document_id: id(),
+                    word_count: 1,
+                    char_count: 1,
+                    line_count: 1,
+                    timestamp: 1.0,
+                    user_id: id(),
+                }),
+            ),
+            ("cursor_update", payload_data(&cursor)),
+            (
+                PRESENCE_MESSAGE_TYPES[0].1,
+                payload_data(&Presence {
+                    document_id: id(),
+                    user_id: id(),
+                    timestamp: 1.0,
+                    name: Some("Ada".into()),
+                    avatar_hash: Some(id()),
+                }),
+            ),
+            (
+                "document_snapshot",
+                payload_data(&Snapshot {
+                    document_id: id(),
+                    user_id: id(),
+                    version: 1,
+                    content: bytes(),
+                    content_hash: id(),
+                    metadata: Some(bytes()),
+                    timestamp: 1.0,
+                }),
+            ),
+            ("snapshot_request", payload_data(&SnapshotRequest { document_id: id(), user_id: id(), timestamp: 1.0 })),
+            ("encrypted", payload_data(&EncryptedPayload { document_id: id(), aead_nonce: bytes(), ciphertext: bytes() })),
+            (
+                "key_share",
+                payload_data(&KeyShare {
+                    document_id: id(),
+                    user_id: id(),
+                    recipient_id: id(),
+                    ephemeral_key: bytes(),
+                    aead_nonce: bytes(),
+                    wrapped_key: bytes(),
+                    timestamp: 1.0,
+                }),
+            ),
+            (
+                "batch",
+                payload_data(&Batch {
+                    document_id: id(),
+                    user_id: id(),
+                    timestamp: 1.0,
+                    operations: vec![MessagePayload { message_type: "cursor_update".into(), data: payload_data(&cursor) }],
+                    signature: Some(bytes()),
+                }),
+            ),
+            (
+                "sync_request",
+                payload_data(&SyncRequest {
+                    user_id: id(),
+                    versions: vec![SenderVersion { user_id: id(), session: id(), seq: 1 }],
+                    timestamp: 1.0,
+                }),
+            ),
+            (
+                "sync_response",
+                payload_data(&SyncResponse {
+                    user_id: id(),
+                    target_user_id: id(),
+                    target_session: id(),
+                    operations: vec![ByteString(bytes())],
+                    complete: true,
+                    more: false,
+                    timestamp: 1.0,
+                }),
+            ),
+            (
+                "hello",
+                payload_data(&Hello {
+                    user_id: id(),
+                    protocol_version: 1,
+                    min_protocol_version: 1,
+                    compression: vec!["gzip".into()],
+                    encryption: vec!["none".into()],
+                    editing: vec!["ot".into()],
+                    timestamp: 1.0,
+                }),
+            ),
+            (
+                "hello_ack",
+                payload_data(&HelloAck {
+                    user_id: id(),
+                    target_user_id: id(),
+                    protocol_version: 1,
+                    compression: Some("gzip".into()),
+                    encryption: Some("none".into()),
+                    editing: Some("ot".into()),
+                    error: Some("none in common".into()),
+                    timestamp: 1.0,
+                }),
+            ),
+            (
+                "mention",
+                payload_data(&Mention {
+                    document_id: id(),
+                    user_id: id(),
+                    mentioned_user_id: id(),
+                    start: 0,
+                    end: 4,
+                    line: 1,
+                    excerpt: "@ada".into(),
+                    timestamp: 1.0,
+                }),
+            ),
+            (
+                "word_goal",
+                payload_data(&WordGoal {
+                    document_id: id(),
+                    user_id: id(),
+                    target: 500,
+                    deadline: Some(2.0),
+                    set_by: id(),
+                    set_at: 1.0,
+                    words_today: 10,
+                    timestamp: 1.0,
+                }),
+            ),
+            (
+                ACL_MESSAGE_TYPES[0],
+                payload_data(&AclChange {
+                    document_id: id(),
+                    user_id: id(),
+                    target_user_id: id(),
+                    role: Some("write".into()),
+                    timestamp: 1.0,
+                    signature: bytes(),
+                }),
+            ),
+            (
+                COMMENT_MESSAGE_TYPES[0],
+                payload_data(&CommentChange {
+                    document_id: id(),
+                    user_id: id(),
+                    comment_id: id(),
+                    reply_id: Some(id()),
+                    body: Some("Looks good".into()),
+                    start: Some(0),
+                    end: Some(4),
+                    quote: Some("text".into()),
+                    prefix: Some("".into()),
+                    suffix: Some("".into()),
+                    resolved: Some(false),
+                    timestamp: 1.0,
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn schemas_accept_every_payload_field() {
+        for (message_type, data) in samples() {
+            assert!(schema(message_type).is_some(), "no schema for {}", message_type);
+            let message = PromiseGridMessage {
+                protocol_hash: PROTOCOL_HASH.to_string(),
+                payload: MessagePayload { message_type: message_type.to_string(), data },
+            };
+            let bytes = encode_with_grid_tag(&message).unwrap();
+            assert_eq!(first_error(&bytes), None, "{} sample rejected", message_type);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// Version history for a document: named and automatic versions stored as
+// periodic full snapshots with line deltas between them, so long
+// histories stay small while any version can be rebuilt from the nearest
+// snapshot.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{diff_documents, diff_strs, DiffKind};
+
+/// Deltas stored in a row before the next version is a full snapshot
+const SNAPSHOT_INTERVAL: usize = 16;
+
+/// One step of a delta from the previous version's lines
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+enum Edit {
+    /// Copy this many lines
+    Keep(usize),
+    /// Skip this many lines
+    Drop(usize),
+    /// Insert this text (whole lines)
+    Add(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+enum Storage {
+    Snapshot(String),
+    Delta(Vec<Edit>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Version {
+    id: String,
+    /// Empty for automatic versions
+    name: String,
+    timestamp: f64,
+    author: String,
+    inserted: usize,
+    deleted: usize,
+    storage: Storage,
+}
+
+#[derive(Serialize)]
+struct VersionInfo<'a> {
+    id: &'a str,
+    name: &'a str,
+    automatic: bool,
+    timestamp: f64,
+    author: &'a str,
+    /// Lines added and removed since the previous version
+    inserted: usize,
+    deleted: usize,
+}
+
+fn delta(old: &str, new: &str) -> (Vec<Edit>, usize, usize) {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let (mut inserted, mut deleted) = (0, 0);
+    let edits = diff_strs(&old_lines, &new_lines)
+        .into_iter()
+        .map(|op| match op.kind {
+            DiffKind::Equal => Edit::Keep(op.old.len()),
+            DiffKind::Delete => {
+                deleted += op.old.len();
+                Edit::Drop(op.old.len())
+            }
+            DiffKind::Insert => {
+                inserted += op.new.len();
+                Edit::Add(new_lines[op.new].concat())
+            }
+        })
+        .collect();
+    (edits, inserted, deleted)
+}
+
+fn apply_delta(old: &str, edits: &[Edit]) -> String {
+    let mut lines = old.split_inclusive('\n');
+    let mut content = String::with_capacity(old.len());
+    for edit in edits {
+        match edit {
+            Edit::Keep(n) => content.extend(lines.by_ref().take(*n)),
+            Edit::Drop(n) => {
+                lines.by_ref().take(*n).for_each(drop);
+            }
+            Edit::Add(text) => content.push_str(text),
+        }
+    }
+    content
+}
+
+/// Saved versions of one document, oldest first. Each version is a full
+/// snapshot or a line delta from the version before it; every
+/// `SNAPSHOT_INTERVAL`th version is a snapshot, bounding the deltas
+/// replayed to rebuild any version. Persist with `to_bytes`, e.g. in
+/// IndexedDB next to the document.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct VersionStore {
+    versions: Vec<Version>,
+    next_id: u64,
+}
+
+#[wasm_bindgen]
+impl VersionStore {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> VersionStore {
+        VersionStore::default()
+    }
+
+    /// Record `content` as a new version and return its id. An empty
+    /// `name` makes an automatic version; an automatic version identical
+    /// to the latest one isn't stored and the latest id is returned.
+    pub fn save_version(&mut self, content: &str, name: &str, author: &str, timestamp: f64) -> String {
+        let latest = self.versions.len().checked_sub(1).map(|n| self.content_at(n));
+        if name.trim().is_empty() && latest.as_deref() == Some(content) {
+            return self.versions[self.versions.len() - 1].id.clone();
+        }
+        let since_snapshot = self.versions.iter().rev().take_while(|v| matches!(v.storage, Storage::Delta(_))).count();
+        let (storage, inserted, deleted) = match latest {
+            Some(previous) if since_snapshot + 1 < SNAPSHOT_INTERVAL => {
+                let (edits, inserted, deleted) = delta(&previous, content);
+                (Storage::Delta(edits), inserted, deleted)
+            }
+            Some(previous) => {
+                let (_, inserted, deleted) = delta(&previous, content);
+                (Storage::Snapshot(content.to_string()), inserted, deleted)
+            }
+            None => (Storage::Snapshot(content.to_string()), content.split_inclusive('\n').count(), 0),
+        };
+        self.next_id += 1;
+        let id = format!("v{}", self.next_id);
+        self.versions.push(Version {
+            id: id.clone(),
+            name: name.trim().to_string(),
+            timestamp,
+            author: author.to_string(),
+            inserted,
+            deleted,
+            storage,
+        });
+        id
+    }
+
+    /// JSON array of `{id, name, automatic, timestamp, author, inserted,
+    /// deleted}`, oldest first, without the content
+    pub fn list_versions(&self) -> String {
+        let list: Vec<VersionInfo> = self
+            .versions
+            .iter()
+            .map(|v| VersionInfo {
+                id: &v.id,
+                name: &v.name,
+                automatic: v.name.is_empty(),
+                timestamp: v.timestamp,
+                author: &v.author,
+                inserted: v.inserted,
+                deleted: v.deleted,
+            })
+            .collect();
+        serde_json::to_string(&list).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// The content of version `id`
+    pub fn get_version(&self, id: &str) -> Result<String, JsValue> {
+        Ok(self.content_at(self.index(id)?))
+    }
+
+    /// Rename a version; an empty name makes it automatic again
+    pub fn rename_version(&mut self, id: &str, name: &str) -> Result<(), JsValue> {
+        let index = self.index(id)?;
+        self.versions[index].name = name.trim().to_string();
+        Ok(())
+    }
+
+    /// Bring back version `id`: its content is recorded as a new version
+    /// named after it, so the versions since stay in the history, and
+    /// returned for the editor to load
+    pub fn restore(&mut self, id: &str, author: &str, timestamp: f64) -> Result<String, JsValue> {
+        let index = self.index(id)?;
+        let content = self.content_at(index);
+        let source = &self.versions[index];
+        let name = if source.name.is_empty() {
+            format!("Restored {}", source.id)
+        } else {
+            format!("Restored \"{}\"", source.name)
+        };
+        self.save_version(&content, &name, author, timestamp);
+        Ok(content)
+    }
+
+    /// Line diff from version `a` to version `b`, in the JSON shape of
+    /// `diff_documents`
+    pub fn diff_versions(&self, a: &str, b: &str) -> Result<String, JsValue> {
+        let old = self.content_at(self.index(a)?);
+        let new = self.content_at(self.index(b)?);
+        Ok(diff_documents(&old, &new))
+    }
+
+    /// Number of versions stored
+    pub fn version_count(&self) -> usize {
+        self.versions.len()
+    }
+
+    /// Serialize the history to CBOR for persistence
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<VersionStore, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
+
+impl VersionStore {
+    fn index(&self, id: &str) -> Result<usize, JsValue> {
+        self.versions
+            .iter()
+            .position(|v| v.id == id)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown version: {}", id)))
+    }
+
+    /// Rebuild version `index` from the nearest snapshot at or before it
+    fn content_at(&self, index: usize) -> String {
+        let snapshot = (0..=index).rev().find(|&n| matches!(self.versions[n].storage, Storage::Snapshot(_))).unwrap_or(0);
+        let mut content = match &self.versions[snapshot].storage {
+            Storage::Snapshot(text) => text.clone(),
+            Storage::Delta(_) => String::new(),
+        };
+        for version in &self.versions[snapshot + 1..=index] {
+            if let Storage::Delta(edits) = &version.storage {
+                content = apply_delta(&content, edits);
+            }
+        }
+        content
+    }
+}
@@ -0,0 +1,143 @@
+// File-level whitespace normalization: byte order marks, line endings,
+// tabs and the final newline. Kept apart from format_text so it can run
+// on imported files without restyling their markdown.
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::markdown::{fenced_code_ranges, in_ranges};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TabStyle {
+    Keep,
+    /// Expand tabs to the next tab stop
+    Spaces,
+    /// Turn leading indentation into tabs
+    Tabs,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LineEndings {
+    Keep,
+    Lf,
+    Crlf,
+}
+
+/// Which normalizations to apply. Missing fields take their default, so
+/// `{"tabs": "spaces"}` is a complete options object.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct WhitespaceOptions {
+    /// "keep" (default), "spaces" or "tabs"
+    pub tabs: TabStyle,
+    /// Columns per tab stop
+    pub tab_width: usize,
+    /// "lf" (default), "crlf" or "keep"
+    pub line_endings: LineEndings,
+    /// Remove a leading U+FEFF byte order mark
+    pub strip_bom: bool,
+    /// End a non-empty document with exactly one line ending
+    pub final_newline: bool,
+}
+
+impl Default for WhitespaceOptions {
+    fn default() -> Self {
+        WhitespaceOptions {
+            tabs: TabStyle::Keep,
+            tab_width: 4,
+            line_endings: LineEndings::Lf,
+            strip_bom: true,
+            final_newline: true,
+        }
+    }
+}
+
+/// Expand every tab to spaces up to the next multiple of `width`
+fn expand_tabs(line: &str, width: usize) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = width - column % width;
+            out.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            out.push(c);
+            column += 1;
+        }
+    }
+    out
+}
+
+/// Turn the leading indentation of a line into tabs, keeping any spaces
+/// short of a full tab stop
+fn indent_with_tabs(line: &str, width: usize) -> String {
+    let body = line.trim_start_matches([' ', '\t']);
+    let indent = expand_tabs(&line[..line.len() - body.len()], width).len();
+    format!("{}{}{}", "\t".repeat(indent / width), " ".repeat(indent % width), body)
+}
+
+pub(crate) fn normalize(content: &str, options: &WhitespaceOptions) -> String {
+    let content = if options.strip_bom { content.strip_prefix('\u{feff}').unwrap_or(content) } else { content };
+    let width = options.tab_width.max(1);
+    // Code blocks keep their tabs: Makefiles and Go depend on them
+    let fences = fenced_code_ranges(content);
+
+    let mut out = String::with_capacity(content.len());
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let ending_len = if line.ends_with("\r\n") { 2 } else if line.ends_with('\n') { 1 } else { 0 };
+        let (text, ending) = line.split_at(line.len() - ending_len);
+        let in_code = in_ranges(&fences, offset) && !text.trim_start().starts_with("```");
+        match options.tabs {
+            TabStyle::Spaces if !in_code && text.contains('\t') => out.push_str(&expand_tabs(text, width)),
+            TabStyle::Tabs if !in_code && text.starts_with([' ', '\t']) => out.push_str(&indent_with_tabs(text, width)),
+            _ => out.push_str(text),
+        }
+        out.push_str(match (options.line_endings, ending) {
+            (_, "") => "",
+            (LineEndings::Lf, _) => "\n",
+            (LineEndings::Crlf, _) => "\r\n",
+            (LineEndings::Keep, ending) => ending,
+        });
+        offset += line.len();
+    }
+
+    if options.final_newline && !out.trim().is_empty() {
+        let ending = match options.line_endings {
+            LineEndings::Crlf => "\r\n",
+            LineEndings::Lf => "\n",
+            // Follow the document's own line endings
+            LineEndings::Keep if content.contains("\r\n") => "\r\n",
+            LineEndings::Keep => "\n",
+        };
+        out.truncate(out.trim_end_matches(['\r', '\n']).len());
+        out.push_str(ending);
+    }
+    out
+}
+
+/// Normalize the whitespace of a whole file: byte order mark, line endings
+/// (CRLF <-> LF), tabs <-> spaces and the final newline. `options` is a
+/// JSON object `{tabs: "keep" | "spaces" | "tabs", tab_width,
+/// line_endings: "lf" | "crlf" | "keep", strip_bom, final_newline}`;
+/// missing fields default to keeping tabs at width 4, LF line endings,
+/// stripping the BOM and ending with one newline. Fenced code blocks keep
+/// their tabs.
+#[wasm_bindgen]
+pub fn normalize_whitespace(content: &str, options: &str) -> Result<String, JsValue> {
+    let options: WhitespaceOptions = if options.trim().is_empty() {
+        WhitespaceOptions::default()
+    } else {
+        serde_json::from_str(options).map_err(|e| JsValue::from_str(&format!("Invalid whitespace options: {}", e)))?
+    };
+    Ok(normalize(content, &options))
+}
+
+/// The default whitespace options as JSON
+#[wasm_bindgen]
+pub fn default_whitespace_options() -> String {
+    serde_json::to_string(&WhitespaceOptions::default()).unwrap_or_else(|_| "{}".to_string())
+}
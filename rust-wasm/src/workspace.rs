@@ -0,0 +1,774 @@
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::activity::ActivityLog;
+use crate::clock;
+use crate::buffer::{find_matches, BufferStats, DocumentBuffer, Match};
+use crate::locale::LocaleInfo;
+use crate::{data_bool, data_f64, data_text, data_u64, decode_with_grid_tag, encode_promisegrid_payload};
+
+/// Messages `apply_control_message` handles
+const CONTROL_MESSAGE_TYPES: &[&str] = &[
+    "document_delete",
+    "document_restore",
+    "document_rename",
+    "document_move",
+    "document_star",
+    "document_pin",
+    "document_activity",
+];
+
+/// How long deleted documents stay in the trash by default (30 days, in ms)
+const DEFAULT_RETENTION_MS: f64 = 30.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Marks a document as being in the trash
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Tombstone {
+    pub deleted_by: String,
+    /// Milliseconds since the Unix epoch
+    pub deleted_at: f64,
+}
+
+/// Lamport time and user of the last accepted change to a field. Concurrent
+/// changes are ordered by Lamport time, then by user id, so every peer
+/// settles on the same winner.
+pub(crate) type Stamp = (u64, String);
+
+/// A document known to the workspace
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub(crate) struct DocumentEntry {
+    pub title: String,
+    /// Slash-separated folder path, "" for the workspace root
+    #[serde(default)]
+    pub folder: String,
+    pub deleted: Option<Tombstone>,
+    /// Timestamp and user of the last delete/restore, used to settle
+    /// concurrent delete and restore messages (last writer wins)
+    #[serde(default)]
+    pub trash_changed: Option<(f64, String)>,
+    #[serde(default)]
+    pub title_changed: Option<Stamp>,
+    #[serde(default)]
+    pub folder_changed: Option<Stamp>,
+}
+
+/// Whether a change stamped `stamp` wins over the last accepted one
+pub(crate) fn supersedes(stamp: &Stamp, previous: &Option<Stamp>) -> bool {
+    previous.as_ref().is_none_or(|p| stamp > p)
+}
+
+/// Normalize a folder path: trimmed segments joined by `/`, no empty parts
+pub(crate) fn normalize_folder(folder: &str) -> String {
+    folder
+        .split('/')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// One user's personal view of the workspace, synced between their devices
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub(crate) struct UserState {
+    /// Starred flag per document with the stamp of its last change
+    #[serde(default)]
+    pub starred: BTreeMap<String, (bool, Stamp)>,
+    /// Pinned flag per document; pinned documents are listed in pin order
+    #[serde(default)]
+    pub pinned: BTreeMap<String, (bool, Stamp)>,
+    /// Last time (ms) the user opened / edited each document. Merged by
+    /// taking the latest, so devices can report activity in any order.
+    #[serde(default)]
+    pub last_opened: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub last_edited: BTreeMap<String, f64>,
+}
+
+impl UserState {
+    fn forget(&mut self, document_id: &str) {
+        self.starred.remove(document_id);
+        self.pinned.remove(document_id);
+        self.last_opened.remove(document_id);
+        self.last_edited.remove(document_id);
+    }
+}
+
+/// Set a flag unless a newer change to it has already been applied
+fn set_flag(flags: &mut BTreeMap<String, (bool, Stamp)>, document_id: &str, value: bool, stamp: Stamp) -> bool {
+    if flags.get(document_id).is_some_and(|(_, previous)| &stamp <= previous) {
+        return false;
+    }
+    let changed = flags.get(document_id).is_none_or(|(old, _)| *old != value);
+    flags.insert(document_id.to_string(), (value, stamp));
+    changed
+}
+
+/// Record an activity time, keeping the latest
+fn record_time(times: &mut BTreeMap<String, f64>, document_id: &str, at: f64) -> bool {
+    let slot = times.entry(document_id.to_string()).or_insert(f64::MIN);
+    if at > *slot {
+        *slot = at;
+        true
+    } else {
+        false
+    }
+}
+
+#[derive(Serialize)]
+struct RecentDocument<'a> {
+    document_id: &'a str,
+    title: &'a str,
+    last_opened: Option<f64>,
+    last_edited: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct DocumentSummary<'a> {
+    document_id: &'a str,
+    title: &'a str,
+    folder: &'a str,
+}
+
+#[derive(Serialize)]
+struct OpenDocument<'a> {
+    document_id: &'a str,
+    title: &'a str,
+    bytes: usize,
+    version: u64,
+}
+
+#[derive(Serialize)]
+struct DocumentMatches<'a> {
+    document_id: &'a str,
+    title: &'a str,
+    matches: Vec<Match>,
+}
+
+#[derive(Serialize)]
+struct DocumentStats<'a> {
+    document_id: &'a str,
+    #[serde(flatten)]
+    stats: BufferStats,
+}
+
+#[derive(Serialize)]
+struct WorkspaceStats<'a> {
+    documents: usize,
+    #[serde(flatten)]
+    total: BufferStats,
+    per_document: Vec<DocumentStats<'a>>,
+}
+
+#[derive(Serialize)]
+struct TrashSummary<'a> {
+    document_id: &'a str,
+    title: &'a str,
+    deleted_by: &'a str,
+    deleted_at: f64,
+    purge_at: f64,
+}
+
+/// The set of documents a user works with, kept consistent across peers
+/// through PromiseGrid control messages
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Workspace {
+    documents: BTreeMap<String, DocumentEntry>,
+    /// Documents removed for good; late messages must not bring them back
+    #[serde(default)]
+    purged: BTreeSet<String>,
+    retention_ms: f64,
+    /// Lamport clock for rename/move ordering
+    #[serde(default)]
+    clock: u64,
+    /// Per-user stars, pins and recency
+    #[serde(default)]
+    users: BTreeMap<String, UserState>,
+    /// Recent changes, for digests
+    #[serde(default)]
+    activity: ActivityLog,
+    /// Documents open for editing in this session; not persisted
+    #[serde(skip)]
+    open: BTreeMap<String, DocumentBuffer>,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Workspace {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Workspace {
+        Workspace {
+            documents: BTreeMap::new(),
+            purged: BTreeSet::new(),
+            retention_ms: DEFAULT_RETENTION_MS,
+            clock: 0,
+            users: BTreeMap::new(),
+            activity: ActivityLog::default(),
+            open: BTreeMap::new(),
+        }
+    }
+
+    /// Register a document (or update its title)
+    pub fn add_document(&mut self, document_id: &str, title: &str) -> Result<(), JsValue> {
+        if self.purged.contains(document_id) {
+            return Err(JsValue::from_str(&format!("Document {} was purged", document_id)));
+        }
+        self.documents
+            .entry(document_id.to_string())
+            .and_modify(|d| d.title = title.to_string())
+            .or_insert_with(|| DocumentEntry {
+                title: title.to_string(),
+                ..Default::default()
+            });
+        Ok(())
+    }
+
+    /// How long documents stay in the trash before `purge_expired` removes
+    /// them, in ms
+    pub fn set_retention_period(&mut self, retention_ms: f64) -> Result<(), JsValue> {
+        if !retention_ms.is_finite() || retention_ms < 0.0 {
+            return Err(JsValue::from_str("Retention period must be a non-negative number of milliseconds"));
+        }
+        self.retention_ms = retention_ms;
+        Ok(())
+    }
+
+    pub fn retention_period(&self) -> f64 {
+        self.retention_ms
+    }
+
+    pub fn document_title(&self, document_id: &str) -> Option<String> {
+        self.documents.get(document_id).map(|d| d.title.clone())
+    }
+
+    pub fn document_folder(&self, document_id: &str) -> Option<String> {
+        self.documents.get(document_id).map(|d| d.folder.clone())
+    }
+
+    /// Current Lamport time of this workspace replica
+    pub fn lamport_time(&self) -> u64 {
+        self.clock
+    }
+
+    /// Rename a document locally and return the `document_rename` control
+    /// message to broadcast
+    pub fn rename_document(&mut self, document_id: &str, title: &str, user_id: &str) -> Result<Vec<u8>, JsValue> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err(JsValue::from_str("Document title cannot be empty"));
+        }
+        self.check_known(document_id)?;
+        let lamport = self.tick();
+        if self.set_title(document_id, title, (lamport, user_id.to_string())) {
+            self.activity.record(js_sys::Date::now(), user_id, document_id, "renamed", title);
+        }
+        Ok(structure_message("document_rename", document_id, "title", title, lamport, user_id))
+    }
+
+    /// Move a document to `folder` (slash-separated path, "" for the root)
+    /// locally and return the `document_move` control message to broadcast
+    pub fn move_document(&mut self, document_id: &str, folder: &str, user_id: &str) -> Result<Vec<u8>, JsValue> {
+        let folder = normalize_folder(folder);
+        self.check_known(document_id)?;
+        let lamport = self.tick();
+        if self.set_folder(document_id, &folder, (lamport, user_id.to_string())) {
+            self.activity.record(js_sys::Date::now(), user_id, document_id, "moved", &folder);
+        }
+        Ok(structure_message("document_move", document_id, "folder", &folder, lamport, user_id))
+    }
+
+    /// Star or unstar a document for `user_id`. Returns the
+    /// `document_star` message that syncs the change to the user's other
+    /// devices.
+    pub fn set_starred(&mut self, document_id: &str, user_id: &str, starred: bool) -> Result<Vec<u8>, JsValue> {
+        self.check_known(document_id)?;
+        let lamport = self.tick();
+        let stamp = (lamport, user_id.to_string());
+        set_flag(&mut self.users.entry(user_id.to_string()).or_default().starred, document_id, starred, stamp);
+        Ok(flag_message("document_star", document_id, "starred", starred, lamport, user_id))
+    }
+
+    /// Pin or unpin a document for `user_id`. Returns the `document_pin`
+    /// message.
+    pub fn set_pinned(&mut self, document_id: &str, user_id: &str, pinned: bool) -> Result<Vec<u8>, JsValue> {
+        self.check_known(document_id)?;
+        let lamport = self.tick();
+        let stamp = (lamport, user_id.to_string());
+        set_flag(&mut self.users.entry(user_id.to_string()).or_default().pinned, document_id, pinned, stamp);
+        Ok(flag_message("document_pin", document_id, "pinned", pinned, lamport, user_id))
+    }
+
+    /// Record that `user_id` opened (`edited = false`) or edited a document
+    /// at `now`. Returns the `document_activity` message.
+    pub fn record_activity(&mut self, document_id: &str, user_id: &str, edited: bool, now: f64) -> Result<Vec<u8>, JsValue> {
+        self.check_known(document_id)?;
+        self.set_activity(document_id, user_id, edited, now);
+
+        let mut data = HashMap::new();
+        data.insert("document_id".to_string(), ciborium::Value::Text(document_id.to_string()));
+        data.insert("edited".to_string(), ciborium::Value::Bool(edited));
+        data.insert("timestamp".to_string(), ciborium::Value::Float(now));
+        data.insert("user_id".to_string(), ciborium::Value::Text(user_id.to_string()));
+        Ok(encode_promisegrid_payload("document_activity", data))
+    }
+
+    /// The user's recently used documents, most recent first, as JSON
+    /// `[{document_id, title, last_opened, last_edited}]`. Trashed documents
+    /// are left out.
+    pub fn recent_documents(&self, user_id: &str, limit: usize) -> String {
+        let Some(user) = self.users.get(user_id) else { return "[]".to_string() };
+        let mut recent: Vec<(f64, RecentDocument)> = self
+            .documents
+            .iter()
+            .filter(|(_, d)| d.deleted.is_none())
+            .filter_map(|(id, d)| {
+                let last_opened = user.last_opened.get(id).copied();
+                let last_edited = user.last_edited.get(id).copied();
+                let latest = last_opened.into_iter().chain(last_edited).reduce(f64::max)?;
+                Some((latest, RecentDocument { document_id: id, title: &d.title, last_opened, last_edited }))
+            })
+            .collect();
+        recent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let recent: Vec<RecentDocument> = recent.into_iter().take(limit).map(|(_, r)| r).collect();
+        serde_json::to_string(&recent).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// The user's starred documents as JSON `[{document_id, title, folder}]`
+    pub fn starred_documents(&self, user_id: &str) -> String {
+        let flags = self.users.get(user_id).map(|u| &u.starred);
+        self.flagged_documents(flags)
+    }
+
+    /// The user's pinned documents in the order they were pinned, as JSON
+    /// `[{document_id, title, folder}]`
+    pub fn pinned_documents(&self, user_id: &str) -> String {
+        let flags = self.users.get(user_id).map(|u| &u.pinned);
+        self.flagged_documents(flags)
+    }
+
+    /// Remember a summary (outline and word count) of a document version so
+    /// digests can describe what changed. Call it after saving.
+    pub fn record_revision(&mut self, document_id: &str, content: &str, now: f64) -> Result<(), JsValue> {
+        self.check_known(document_id)?;
+        self.activity.record_revision(now, document_id, content);
+        Ok(())
+    }
+
+    /// Record a comment or mention for the digest. `kind` is "comment"
+    /// (detail: the comment text) or "mention" (detail: the mentioned user).
+    pub fn record_event(&mut self, document_id: &str, user_id: &str, kind: &str, detail: &str, now: f64) -> Result<(), JsValue> {
+        if !matches!(kind, "comment" | "mention") {
+            return Err(JsValue::from_str(&format!("Unknown activity kind: {}", kind)));
+        }
+        self.check_known(document_id)?;
+        self.activity.record(now, user_id, document_id, kind, detail);
+        Ok(())
+    }
+
+    /// Summarize workspace activity since `since_timestamp` (ms) as a
+    /// markdown digest: documents changed and by whom, renames, moves, trash,
+    /// new and removed sections, word count changes, comments and mentions.
+    pub fn generate_digest(&self, since_timestamp: f64) -> String {
+        self.generate_digest_localized(since_timestamp, "en")
+    }
+
+    /// `generate_digest` with counts formatted for `locale` ("de", "fr-CA", ...)
+    pub fn generate_digest_localized(&self, since_timestamp: f64, locale: &str) -> String {
+        self.activity.digest(since_timestamp, LocaleInfo::get(locale), |id| match self.documents.get(id) {
+            Some(d) if !d.title.is_empty() => d.title.clone(),
+            _ => id.to_string(),
+        })
+    }
+
+    pub fn is_deleted(&self, document_id: &str) -> bool {
+        self.documents.get(document_id).is_some_and(|d| d.deleted.is_some())
+    }
+
+    /// Apply a workspace control message (`document_delete`,
+    /// `document_restore`, `document_rename`, `document_move`,
+    /// `document_star`, `document_pin` or `document_activity`) received from
+    /// a peer or created locally. Returns true if the workspace changed.
+    /// Messages with a Lamport time implausibly far ahead are refused.
+    pub fn apply_control_message(&mut self, cbor_bytes: &[u8]) -> Result<bool, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let data = &message.payload.data;
+        let document_id = data_text(data, "document_id")
+            .ok_or_else(|| JsValue::from_str("Control message is missing document_id"))?;
+        let user_id = data_text(data, "user_id").unwrap_or("unknown");
+        let timestamp = data_f64(data, "timestamp").unwrap_or(0.0);
+        if let Some(lamport) = data_u64(data, "lamport") {
+            if !clock::plausible(self.clock, lamport) {
+                return Err(JsValue::from_str(&format!(
+                    "Control message Lamport time {} is too far ahead of ours ({})",
+                    lamport, self.clock
+                )));
+            }
+            self.clock = self.clock.max(lamport);
+        }
+        let stamp = || -> Result<Stamp, JsValue> {
+            let lamport = data_u64(data, "lamport")
+                .ok_or_else(|| JsValue::from_str("Control message is missing lamport time"))?;
+            Ok((lamport, user_id.to_string()))
+        };
+
+        let log = |workspace: &mut Workspace, changed: bool, kind: &str, detail: &str| {
+            if changed {
+                workspace.activity.record(timestamp, user_id, document_id, kind, detail);
+            }
+            Ok(changed)
+        };
+
+        match message.payload.message_type.as_str() {
+            "document_delete" => {
+                let changed = self.set_trashed(document_id, user_id, timestamp, true);
+                log(self, changed, "deleted", "")
+            }
+            "document_restore" => {
+                let changed = self.set_trashed(document_id, user_id, timestamp, false);
+                log(self, changed, "restored", "")
+            }
+            "document_rename" => {
+                let title = data_text(data, "title").unwrap_or("").trim();
+                if title.is_empty() {
+                    return Err(JsValue::from_str("Rename message has an empty title"));
+                }
+                let changed = self.set_title(document_id, title, stamp()?);
+                log(self, changed, "renamed", title)
+            }
+            "document_move" => {
+                let folder = normalize_folder(data_text(data, "folder").unwrap_or(""));
+                let changed = self.set_folder(document_id, &folder, stamp()?);
+                log(self, changed, "moved", &folder)
+            }
+            "document_star" | "document_pin" => {
+                if self.purged.contains(document_id) {
+                    return Ok(false);
+                }
+                let user = self.users.entry(user_id.to_string()).or_default();
+                let stamp = stamp()?;
+                Ok(match message.payload.message_type.as_str() {
+                    "document_star" => set_flag(&mut user.starred, document_id, data_bool(data, "starred").unwrap_or(false), stamp),
+                    _ => set_flag(&mut user.pinned, document_id, data_bool(data, "pinned").unwrap_or(false), stamp),
+                })
+            }
+            "document_activity" => {
+                let edited = data_bool(data, "edited").unwrap_or(false);
+                Ok(self.set_activity(document_id, user_id, edited, timestamp))
+            }
+            other => Err(JsValue::from_str(&format!("Unsupported control message: {}", other))),
+        }
+    }
+
+    /// Permanently remove trashed documents whose retention period has
+    /// elapsed at `now`. Returns the purged document ids as a JSON array so
+    /// their stored content can be dropped.
+    pub fn purge_expired(&mut self, now: f64) -> String {
+        let expired: Vec<String> = self
+            .documents
+            .iter()
+            .filter(|(_, d)| d.deleted.as_ref().is_some_and(|t| now >= t.deleted_at + self.retention_ms))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.purge(id);
+        }
+        serde_json::to_string(&expired).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Empty a single document from the trash right away
+    pub fn purge_document(&mut self, document_id: &str) -> Result<(), JsValue> {
+        if !self.is_deleted(document_id) {
+            return Err(JsValue::from_str(&format!("Document {} is not in the trash", document_id)));
+        }
+        self.purge(document_id);
+        Ok(())
+    }
+
+    /// Documents not in the trash as JSON `[{document_id, title, folder}]`
+    pub fn list_documents(&self) -> String {
+        let docs: Vec<DocumentSummary> = self
+            .documents
+            .iter()
+            .filter(|(_, d)| d.deleted.is_none())
+            .map(|(id, d)| DocumentSummary { document_id: id, title: &d.title, folder: &d.folder })
+            .collect();
+        serde_json::to_string(&docs).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Trashed documents as JSON
+    /// `[{document_id, title, deleted_by, deleted_at, purge_at}]`
+    pub fn list_trash(&self) -> String {
+        let trash: Vec<TrashSummary> = self
+            .documents
+            .iter()
+            .filter_map(|(id, d)| {
+                let t = d.deleted.as_ref()?;
+                Some(TrashSummary {
+                    document_id: id,
+                    title: &d.title,
+                    deleted_by: &t.deleted_by,
+                    deleted_at: t.deleted_at,
+                    purge_at: t.deleted_at + self.retention_ms,
+                })
+            })
+            .collect();
+        serde_json::to_string(&trash).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Open a known document for editing with its current `content`,
+    /// replacing the text if it's already open
+    pub fn open_document(&mut self, document_id: &str, content: &str) -> Result<(), JsValue> {
+        self.check_known(document_id)?;
+        self.open.insert(document_id.to_string(), DocumentBuffer::new(document_id, content));
+        Ok(())
+    }
+
+    /// Close an open document; returns whether it was open
+    pub fn close_document(&mut self, document_id: &str) -> bool {
+        self.open.remove(document_id).is_some()
+    }
+
+    pub fn is_open(&self, document_id: &str) -> bool {
+        self.open.contains_key(document_id)
+    }
+
+    /// The text of an open document
+    pub fn document_content(&self, document_id: &str) -> Option<String> {
+        self.open.get(document_id).map(|buffer| buffer.content())
+    }
+
+    /// Open documents as JSON `[{document_id, title, bytes, version}]`,
+    /// `version` counting the edits applied since opening
+    pub fn open_documents(&self) -> String {
+        let open: Vec<OpenDocument> = self
+            .open
+            .iter()
+            .map(|(id, buffer)| OpenDocument {
+                document_id: id,
+                title: self.documents.get(id).map_or("", |d| d.title.as_str()),
+                bytes: buffer.text().len(),
+                version: buffer.version(),
+            })
+            .collect();
+        serde_json::to_string(&open).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Route an incoming message: workspace control messages update the
+    /// workspace, edits go to their document if it's open. Returns the
+    /// document id it was applied to, or `undefined` when nothing here
+    /// took it. Check messages with an InboundDeduper first.
+    pub fn route_message(&mut self, cbor_bytes: &[u8]) -> Result<Option<String>, JsValue> {
+        let message = decode_with_grid_tag(cbor_bytes)
+            .map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))?;
+        let Some(document_id) = data_text(&message.payload.data, "document_id").map(str::to_string) else {
+            return Ok(None);
+        };
+        if CONTROL_MESSAGE_TYPES.contains(&message.payload.message_type.as_str()) {
+            return Ok(self.apply_control_message(cbor_bytes)?.then_some(document_id));
+        }
+        let applied = self.open.get_mut(&document_id).is_some_and(|buffer| buffer.apply(&message));
+        Ok(applied.then_some(document_id))
+    }
+
+    /// Search every open document, as JSON `[{document_id, title,
+    /// matches: [{start, end, line}]}]` for the documents that match
+    pub fn search_all(&self, query: &str, case_sensitive: bool) -> String {
+        let results: Vec<DocumentMatches> = self
+            .open
+            .iter()
+            .map(|(id, buffer)| DocumentMatches {
+                document_id: id,
+                title: self.documents.get(id).map_or("", |d| d.title.as_str()),
+                matches: find_matches(buffer.text(), query, case_sensitive),
+            })
+            .filter(|result| !result.matches.is_empty())
+            .collect();
+        serde_json::to_string(&results).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Statistics summed over the open documents, as JSON `{documents,
+    /// words, chars_with_spaces, chars_without_spaces, lines,
+    /// per_document: [{document_id, words, ...}]}`
+    pub fn aggregate_stats(&self) -> String {
+        let mut total = BufferStats::default();
+        let per_document: Vec<DocumentStats> = self
+            .open
+            .iter()
+            .map(|(id, buffer)| {
+                let stats = BufferStats::of(buffer.text());
+                total.add(stats);
+                DocumentStats { document_id: id, stats }
+            })
+            .collect();
+        let stats = WorkspaceStats { documents: per_document.len(), total, per_document };
+        serde_json::to_string(&stats).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Serialize to CBOR for persistence. Open documents aren't included.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        crate::to_cbor(self).map_err(|e| JsValue::from_str(&format!("CBOR encoding error: {}", e)))
+    }
+
+    /// Restore from bytes produced by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Workspace, JsValue> {
+        crate::from_cbor(bytes).map_err(|e| JsValue::from_str(&format!("CBOR parsing error: {}", e)))
+    }
+}
+
+impl Workspace {
+    /// The Lamport time for a local change; stops at `u64::MAX`
+    fn tick(&mut self) -> u64 {
+        self.clock = self.clock.saturating_add(1);
+        self.clock
+    }
+
+    fn purge(&mut self, document_id: &str) {
+        self.documents.remove(document_id);
+        for user in self.users.values_mut() {
+            user.forget(document_id);
+        }
+        self.activity.forget(document_id);
+        self.purged.insert(document_id.to_string());
+    }
+
+    fn set_activity(&mut self, document_id: &str, user_id: &str, edited: bool, at: f64) -> bool {
+        if self.purged.contains(document_id) {
+            return false;
+        }
+        let user = self.users.entry(user_id.to_string()).or_default();
+        let opened = record_time(&mut user.last_opened, document_id, at);
+        // Editing a document implies it is open
+        let edit_recorded = edited && record_time(&mut user.last_edited, document_id, at);
+        if edit_recorded {
+            self.activity.record(at, user_id, document_id, "edited", "");
+        }
+        opened || edit_recorded
+    }
+
+    /// Live documents whose flag is set, ordered by when it was set
+    fn flagged_documents(&self, flags: Option<&BTreeMap<String, (bool, Stamp)>>) -> String {
+        let mut flagged: Vec<(&Stamp, DocumentSummary)> = flags
+            .into_iter()
+            .flatten()
+            .filter(|(_, (set, _))| *set)
+            .filter_map(|(id, (_, stamp))| {
+                let d = self.documents.get(id).filter(|d| d.deleted.is_none())?;
+                Some((stamp, DocumentSummary { document_id: id, title: &d.title, folder: &d.folder }))
+            })
+            .collect();
+        flagged.sort_by(|a, b| a.0.cmp(b.0));
+        let flagged: Vec<DocumentSummary> = flagged.into_iter().map(|(_, d)| d).collect();
+        serde_json::to_string(&flagged).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    fn check_known(&self, document_id: &str) -> Result<(), JsValue> {
+        if self.documents.contains_key(document_id) {
+            Ok(())
+        } else {
+            Err(JsValue::from_str(&format!("Unknown document: {}", document_id)))
+        }
+    }
+
+    /// Entry for a document named in a peer's message, created on first
+    /// sight. None if the document was purged.
+    fn entry_for(&mut self, document_id: &str) -> Option<&mut DocumentEntry> {
+        if self.purged.contains(document_id) {
+            return None;
+        }
+        Some(self.documents.entry(document_id.to_string()).or_default())
+    }
+
+    fn set_title(&mut self, document_id: &str, title: &str, stamp: Stamp) -> bool {
+        let Some(entry) = self.entry_for(document_id) else { return false };
+        if !supersedes(&stamp, &entry.title_changed) {
+            return false;
+        }
+        entry.title_changed = Some(stamp);
+        let changed = entry.title != title;
+        entry.title = title.to_string();
+        changed
+    }
+
+    fn set_folder(&mut self, document_id: &str, folder: &str, stamp: Stamp) -> bool {
+        let Some(entry) = self.entry_for(document_id) else { return false };
+        if !supersedes(&stamp, &entry.folder_changed) {
+            return false;
+        }
+        entry.folder_changed = Some(stamp);
+        let changed = entry.folder != folder;
+        entry.folder = folder.to_string();
+        changed
+    }
+
+    /// Move a document into or out of the trash unless a newer
+    /// delete/restore has already been applied
+    fn set_trashed(&mut self, document_id: &str, user_id: &str, timestamp: f64, trashed: bool) -> bool {
+        // A delete for a document we haven't seen yet still leaves a
+        // tombstone, so a late-arriving copy doesn't resurrect it
+        let Some(entry) = self.entry_for(document_id) else { return false };
+        let stamp = (timestamp, user_id.to_string());
+        if let Some(previous) = &entry.trash_changed {
+            if (previous.0, previous.1.as_str()) >= (stamp.0, stamp.1.as_str()) {
+                return false;
+            }
+        }
+        entry.trash_changed = Some(stamp);
+        let was_trashed = entry.deleted.is_some();
+        entry.deleted = trashed.then(|| Tombstone {
+            deleted_by: user_id.to_string(),
+            deleted_at: timestamp,
+        });
+        was_trashed != trashed
+    }
+}
+
+/// Build a rename/move message carrying one changed `field`
+fn structure_message(message_type: &str, document_id: &str, field: &str, value: &str, lamport: u64, user_id: &str) -> Vec<u8> {
+    let mut data = HashMap::new();
+    data.insert("document_id".to_string(), ciborium::Value::Text(document_id.to_string()));
+    data.insert(field.to_string(), ciborium::Value::Text(value.to_string()));
+    data.insert("lamport".to_string(), ciborium::Value::from(lamport));
+    data.insert("timestamp".to_string(), ciborium::Value::Float(js_sys::Date::now()));
+    data.insert("user_id".to_string(), ciborium::Value::Text(user_id.to_string()));
+    encode_promisegrid_payload(message_type, data)
+}
+
+/// Build a star/pin message carrying one boolean `field`
+fn flag_message(message_type: &str, document_id: &str, field: &str, value: bool, lamport: u64, user_id: &str) -> Vec<u8> {
+    let mut data = HashMap::new();
+    data.insert("document_id".to_string(), ciborium::Value::Text(document_id.to_string()));
+    data.insert(field.to_string(), ciborium::Value::Bool(value));
+    data.insert("lamport".to_string(), ciborium::Value::from(lamport));
+    data.insert("timestamp".to_string(), ciborium::Value::Float(js_sys::Date::now()));
+    data.insert("user_id".to_string(), ciborium::Value::Text(user_id.to_string()));
+    encode_promisegrid_payload(message_type, data)
+}
+
+fn trash_message(message_type: &str, document_id: &str, user_id: &str) -> Vec<u8> {
+    let mut data = HashMap::new();
+    data.insert("document_id".to_string(), ciborium::Value::Text(document_id.to_string()));
+    data.insert("timestamp".to_string(), ciborium::Value::Float(js_sys::Date::now()));
+    data.insert("user_id".to_string(), ciborium::Value::Text(user_id.to_string()));
+    encode_promisegrid_payload(message_type, data)
+}
+
+/// Create a `document_delete` control message moving a shared document to
+/// the trash on every peer
+#[wasm_bindgen]
+pub fn create_promisegrid_delete_message(document_id: &str, user_id: &str) -> Vec<u8> {
+    trash_message("document_delete", document_id, user_id)
+}
+
+/// Create a `document_restore` control message taking a document back out
+/// of the trash
+#[wasm_bindgen]
+pub fn create_promisegrid_restore_message(document_id: &str, user_id: &str) -> Vec<u8> {
+    trash_message("document_restore", document_id, user_id)
+}
@@ -0,0 +1,174 @@
+// Minimal ZIP archive reader and writer: stored and deflated entries, no
+// ZIP64 or encryption. Enough for the export archives produced by
+// note-taking apps and for the OOXML and EPUB packages we write.
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::io::{Read, Write};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+/// A file extracted from an archive
+#[derive(Debug, Clone)]
+pub(crate) struct ZipEntry {
+    /// Path inside the archive, `/`-separated
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+fn u16_at(bytes: &[u8], pos: usize) -> Result<u16, String> {
+    bytes
+        .get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| "Truncated zip archive".to_string())
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> Result<u32, String> {
+    bytes
+        .get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "Truncated zip archive".to_string())
+}
+
+/// Read every file entry of a ZIP archive (directories are skipped)
+pub(crate) fn read_zip(bytes: &[u8]) -> Result<Vec<ZipEntry>, String> {
+    // The end-of-central-directory record sits in the last 64 KiB (+22)
+    let search_from = bytes.len().saturating_sub(65_535 + 22);
+    let eocd = (search_from..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|&pos| u32_at(bytes, pos) == Ok(END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(|| "Not a zip archive".to_string())?;
+    let count = u16_at(bytes, eocd + 10)? as usize;
+    let mut pos = u32_at(bytes, eocd + 16)? as usize;
+    if count == 0xFFFF || pos == 0xFFFF_FFFF {
+        return Err("ZIP64 archives are not supported".to_string());
+    }
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(bytes, pos)? != CENTRAL_HEADER {
+            return Err("Corrupt zip central directory".to_string());
+        }
+        let flags = u16_at(bytes, pos + 8)?;
+        let method = u16_at(bytes, pos + 10)?;
+        let crc = u32_at(bytes, pos + 16)?;
+        let compressed_size = u32_at(bytes, pos + 20)? as usize;
+        let size = u32_at(bytes, pos + 24)? as usize;
+        let name_len = u16_at(bytes, pos + 28)? as usize;
+        let extra_len = u16_at(bytes, pos + 30)? as usize;
+        let comment_len = u16_at(bytes, pos + 32)? as usize;
+        let local = u32_at(bytes, pos + 42)? as usize;
+        let name_bytes = bytes
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or_else(|| "Truncated zip archive".to_string())?;
+        // Bit 11 marks UTF-8 names; older tools use CP437, which agrees
+        // with UTF-8 for the ASCII names we care about
+        let name = String::from_utf8_lossy(name_bytes).replace('\\', "/");
+        pos += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') {
+            continue;
+        }
+        if flags & 1 != 0 {
+            return Err(format!("Encrypted entry not supported: {}", name));
+        }
+        if u32_at(bytes, local)? != LOCAL_HEADER {
+            return Err(format!("Corrupt local header for {}", name));
+        }
+        let data_start = local + 30 + u16_at(bytes, local + 26)? as usize + u16_at(bytes, local + 28)? as usize;
+        let raw = bytes
+            .get(data_start..data_start + compressed_size)
+            .ok_or_else(|| format!("Truncated data for {}", name))?;
+
+        let data = match method {
+            0 => raw.to_vec(),
+            8 => {
+                let mut out = Vec::with_capacity(size);
+                DeflateDecoder::new(raw)
+                    .read_to_end(&mut out)
+                    .map_err(|e| format!("Failed to inflate {}: {}", name, e))?;
+                out
+            }
+            other => return Err(format!("Unsupported compression method {} for {}", other, name)),
+        };
+        let mut check = Crc::new();
+        check.update(&data);
+        if check.sum() != crc {
+            return Err(format!("Checksum mismatch for {}", name));
+        }
+        entries.push(ZipEntry { name, data });
+    }
+    Ok(entries)
+}
+
+/// MS-DOS date of 1980-01-01, the earliest a ZIP entry can carry. Written
+/// for every entry so the same input always gives the same archive.
+const DOS_EPOCH_DATE: u16 = (1 << 5) | 1;
+
+/// Write a ZIP archive with the entries in order. Entries are deflated
+/// unless that doesn't make them smaller; `stored` names entries that must
+/// be written uncompressed whatever their size.
+pub(crate) fn write_zip(entries: &[ZipEntry], stored: &[&str]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for entry in entries {
+        let mut crc = Crc::new();
+        crc.update(&entry.data);
+        let deflated = (!stored.contains(&entry.name.as_str()))
+            .then(|| {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&entry.data).ok()?;
+                encoder.finish().ok()
+            })
+            .flatten()
+            .filter(|d| d.len() < entry.data.len());
+        let (method, data) = match &deflated {
+            Some(d) => (8u16, d.as_slice()),
+            None => (0u16, entry.data.as_slice()),
+        };
+        let offset = out.len() as u32;
+        let name = entry.name.as_bytes();
+
+        // Fields shared by the local and central headers, from "version
+        // needed" to the extra field length
+        let mut common = Vec::with_capacity(26);
+        common.extend_from_slice(&20u16.to_le_bytes());
+        // Bit 11: the name is UTF-8
+        common.extend_from_slice(&(1u16 << 11).to_le_bytes());
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&DOS_EPOCH_DATE.to_le_bytes());
+        common.extend_from_slice(&crc.sum().to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        out.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        out.extend_from_slice(&common);
+        out.extend_from_slice(name);
+        out.extend_from_slice(data);
+
+        central.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        // Version made by
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&common);
+        // Comment length, disk number, internal and external attributes
+        central.extend_from_slice(&[0u8; 10]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name);
+    }
+    let central_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    out.extend_from_slice(&[0u8; 4]);
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
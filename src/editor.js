@@ -25,6 +25,10 @@ import { getClientID } from './utils/clientId.js';
  * @param {AwarenessClient} [options.awareness=null] - Awareness client for cursors
  * @param {string} [options.localUserId=null] - Local user ID (defaults to clientID)
  * @param {Function} [options.onUpdate=null] - Callback for editor updates
+ * @param {QuotaPolicy} [options.quotaPolicy=null] - WASM quota policy; local edits past the document limit are rejected
+ * @param {Function} [options.onQuota=null] - Called with the quota error or warning status
+ * @param {Function} [options.cleanPastedHtml=null] - WASM clean_pasted_html; Word and Google Docs pastes are converted to markdown with it
+ * @param {Function} [options.importDocument=null] - WASM import_document; dropped files are converted to markdown with it
  * @returns {{ view: EditorView, lineNumberCompartment: Compartment, destroy: Function }}
  */
 export function createEditor(parentElement, options = {}) {
@@ -32,7 +36,11 @@ export function createEditor(parentElement, options = {}) {
     initialContent = '',
     awareness = null,
     localUserId = null,
-    onUpdate = null
+    onUpdate = null,
+    quotaPolicy = null,
+    onQuota = null,
+    cleanPastedHtml = null,
+    importDocument = null
   } = options;
 
   // Create compartment for line numbers (allows dynamic reconfiguration)
@@ -93,6 +101,72 @@ export function createEditor(parentElement, options = {}) {
     extensions.push(EditorView.updateListener.of(onUpdate));
   }
 
+  // Reject local edits that would take the document past its size quota.
+  // Remote changes are always applied so replicas don't diverge.
+  if (quotaPolicy) {
+    extensions.push(EditorState.transactionFilter.of((tr) => {
+      if (!tr.docChanged || tr.annotation(isRemoteChange)) {
+        return tr;
+      }
+      const before = tr.startState.doc.toString();
+      try {
+        const status = JSON.parse(quotaPolicy.check_edit(before, 0, before.length, tr.newDoc.toString()));
+        if (status.status === 'warning' && onQuota) {
+          onQuota(status);
+        }
+        return tr;
+      } catch (error) {
+        console.warn('[Editor] Edit rejected by quota:', error);
+        if (onQuota) {
+          onQuota(JSON.parse(error));
+        }
+        return [];
+      }
+    }));
+  }
+
+  // Rich text from Word or Google Docs is pasted as clean markdown instead
+  // of its plain-text fallback. Other HTML pastes keep the default behavior.
+  if (cleanPastedHtml) {
+    extensions.push(EditorView.domEventHandlers({
+      paste: (event, view) => {
+        const html = event.clipboardData?.getData('text/html');
+        if (!html || !/urn:schemas-microsoft-com|mso-|docs-internal-guid/.test(html)) {
+          return false;
+        }
+        const markdown = cleanPastedHtml(html);
+        if (!markdown) {
+          return false;
+        }
+        event.preventDefault();
+        view.dispatch(view.state.replaceSelection(markdown));
+        return true;
+      }
+    }));
+  }
+
+  if (importDocument) {
+    extensions.push(EditorView.domEventHandlers({
+      drop: (event, view) => {
+        const file = event.dataTransfer?.files?.[0];
+        // Images are left to the browser
+        if (!file || file.type.startsWith('image/')) {
+          return false;
+        }
+        event.preventDefault();
+        const pos = view.posAtCoords({ x: event.clientX, y: event.clientY }) ?? view.state.selection.main.head;
+        file.arrayBuffer().then((buffer) => {
+          const result = JSON.parse(importDocument(new Uint8Array(buffer), file.name || file.type));
+          result.warnings.forEach((warning) => console.warn(`[Import] ${file.name}: ${warning}`));
+          view.dispatch({ changes: { from: pos, insert: result.content } });
+        }).catch((err) => {
+          console.error(`[Import] Could not import ${file.name}:`, err);
+        });
+        return true;
+      }
+    }));
+  }
+
   // Create editor state
   const state = EditorState.create({
     doc: initialContent,
@@ -150,13 +224,15 @@ export function createEditor(parentElement, options = {}) {
  * @param {HTMLElement} parentElement - The container element
  * @param {DocHandle} handle - Automerge document handle
  * @param {AwarenessClient} awareness - Awareness client for cursors
+ * @param {Object} [options] - Further `createEditor` options (`cleanPastedHtml`, `importDocument`)
  * @returns {{ view: EditorView, binding: AutomergeBinding, destroy: Function }}
  */
-export function setupEditorWithBinding(parentElement, handle, awareness) {
+export function setupEditorWithBinding(parentElement, handle, awareness, options = {}) {
   // Create binding first (we need it for the update listener)
   let binding = null;
 
   const { view, lineNumberCompartment, lineNumbersExtension, destroy: destroyEditor } = createEditor(parentElement, {
+    ...options,
     awareness,
     onUpdate: (update) => {
       if (binding) {
@@ -10,9 +10,17 @@ import {
   toggle_italic,
   toggle_underline,
   toggle_strikethrough,
+  toggle_bold_at,
+  toggle_italic_at,
+  toggle_strikethrough_at,
   toggle_heading,
   toggle_list,
   convert_url_to_markdown,
+  expand_abbreviations,
+  strip_front_matter,
+  highlight_code_blocks,
+  export_plaintext,
+  ExportManager,
   promiseGrid,
   getCurrentSessionInfo
 } from '../wasm/initWasm.js';
@@ -158,6 +166,14 @@ function generateHtmlDocument(title, htmlContent) {
     code { background: #f4f4f4; padding: 2px 6px; border-radius: 3px; font-family: monospace; }
     pre { background: #f4f4f4; padding: 16px; border-radius: 6px; overflow-x: auto; }
     pre code { background: none; padding: 0; }
+    .hl-keyword { color: #a626a4; }
+    .hl-type, .hl-tag { color: #c18401; }
+    .hl-string { color: #50a14f; }
+    .hl-number, .hl-literal { color: #986801; }
+    .hl-comment { color: #a0a1a7; font-style: italic; }
+    .hl-function { color: #4078f2; }
+    .hl-variable, .hl-attr { color: #e45649; }
+    .hl-meta { color: #0184bc; }
     blockquote { border-left: 4px solid #ddd; margin: 1em 0; padding-left: 1em; color: #666; }
     table { border-collapse: collapse; width: 100%; margin: 1em 0; }
     th, td { border: 1px solid #ddd; padding: 8px; text-align: left; }
@@ -224,6 +240,8 @@ export function setupExportHandlers(handle, view) {
   
 
   if (!saveButton || !formatSelect) return;
+
+  populateExportFormats(formatSelect);
    
   saveButton.onclick = () => {
     const format = formatSelect.value;
@@ -240,14 +258,14 @@ export function setupExportHandlers(handle, view) {
   // Bold button handler
   if (boldButton) {
     boldButton.onclick = () => {
-      handleToggleFormatting(view, toggle_bold, "Bold");
+      handleInlineStyleToggle(view, toggle_bold_at, "Bold");
     };
   }
 
   // Italic button handler
   if (italicButton) {
     italicButton.onclick = () => {
-      handleToggleFormatting(view, toggle_italic, "Italic");
+      handleInlineStyleToggle(view, toggle_italic_at, "Italic");
     };
   }
 
@@ -277,7 +295,7 @@ export function setupExportHandlers(handle, view) {
   // Strikethrough handler
   if (strikeButton) {
     strikeButton.onclick = () => {
-      handleToggleFormatting(view, toggle_strikethrough, "Strikethrough");
+      handleInlineStyleToggle(view, toggle_strikethrough_at, "Strikethrough");
     };
   }
 
@@ -373,6 +391,68 @@ async function handleToggleFormatting(view, toggleFunction, formatName) {
   }
 }
 
+/**
+ * Toggles an inline style (bold, italic, strikethrough) on the selection.
+ * WASM looks at the whole document around the selection so partially
+ * formatted selections and nested markers are handled; a bare cursor
+ * inside a styled span removes that span's style.
+ *
+ * @param {EditorView} view - The CodeMirror editor view
+ * @param {Function} toggleFunction - WASM toggle taking (content, start, end) byte offsets
+ * @param {string} formatName - Display name for logging and the edit message
+ */
+async function handleInlineStyleToggle(view, toggleFunction, formatName) {
+  try {
+    const selection = view.state.selection.main;
+    const content = view.state.doc.toString();
+
+    // WASM works in UTF-8 byte offsets, CodeMirror in UTF-16 code units
+    const encoder = new TextEncoder();
+    const toBytes = (pos) => encoder.encode(content.slice(0, pos)).length;
+    const result = JSON.parse(await toggleFunction(content, toBytes(selection.from), toBytes(selection.to)));
+    if (result.content === content) {
+      return;
+    }
+
+    const bytes = encoder.encode(result.content);
+    const decoder = new TextDecoder();
+    const toChars = (pos) => decoder.decode(bytes.slice(0, pos)).length;
+
+    // Replace only the changed middle of the document
+    let prefix = 0;
+    const maxPrefix = Math.min(content.length, result.content.length);
+    while (prefix < maxPrefix && content[prefix] === result.content[prefix]) {
+      prefix++;
+    }
+    let suffix = 0;
+    while (
+      suffix < maxPrefix - prefix &&
+      content[content.length - 1 - suffix] === result.content[result.content.length - 1 - suffix]
+    ) {
+      suffix++;
+    }
+    const insert = result.content.slice(prefix, result.content.length - suffix);
+
+    view.dispatch({
+      changes: { from: prefix, to: content.length - suffix, insert },
+      selection: {
+        anchor: toChars(result.selection_start),
+        head: toChars(result.selection_end)
+      }
+    });
+
+    console.log(`WASM ${formatName} toggle applied`);
+
+    sendEditAsPromiseGridMessage(formatName.toLowerCase(), prefix, insert, view);
+
+    if (window.updateMarkdownPreview) {
+      window.updateMarkdownPreview();
+    }
+  } catch (error) {
+    console.error(`WASM ${formatName} toggle failed:`, error);
+  }
+}
+
 /**
  * Formats the current document text using WASM.
  * 
@@ -476,6 +556,32 @@ function getPromiseGridFilename() {
 }
 
 
+let exportManager = null;
+
+function getExportManager() {
+  if (!exportManager) {
+    exportManager = new ExportManager();
+  }
+  return exportManager;
+}
+
+/**
+ * Adds an option to the export format list for every format in the WASM
+ * export registry that the list doesn't already offer.
+ *
+ * @param {HTMLSelectElement} formatSelect
+ */
+function populateExportFormats(formatSelect) {
+  const existing = new Set(Array.from(formatSelect.options, (option) => option.value));
+  for (const info of JSON.parse(getExportManager().formats())) {
+    if (existing.has(info.id) || existing.has(info.extension)) continue;
+    const option = document.createElement('option');
+    option.value = info.id;
+    option.textContent = `Export as .${info.extension} (${info.name})`;
+    formatSelect.appendChild(option);
+  }
+}
+
 /**
  * Exports document based on selected format.
  * 
@@ -493,7 +599,7 @@ async function handleSave(format, handle, view) {
 
     switch (format) {
       case 'txt': {
-        content = textContent;
+        content = export_plaintext(textContent, '');
         blob = new Blob([content], { type: 'text/plain' });
         filename = getDocumentFilename('txt');
         break;
@@ -551,7 +657,7 @@ async function handleSave(format, handle, view) {
 
       case 'html': {
         // Convert markdown to styled HTML document
-        const htmlBody = markdownToHtml(textContent);
+        const htmlBody = highlight_code_blocks(markdownToHtml(expand_abbreviations(strip_front_matter(textContent))));
         const title = document.title || 'Document';
         content = generateHtmlDocument(title, htmlBody);
         blob = new Blob([content], { type: 'text/html' });
@@ -559,9 +665,16 @@ async function handleSave(format, handle, view) {
         break;
       }
 
-      default:
-        alert('Unsupported export format.');
-        return;
+      default: {
+        const info = JSON.parse(getExportManager().format(format));
+        if (!info) {
+          alert('Unsupported export format.');
+          return;
+        }
+        blob = new Blob([getExportManager().export(textContent, format, '')], { type: info.mime_type });
+        filename = getDocumentFilename(info.extension);
+        break;
+      }
     }
 
     downloadBlob(blob, filename);
@@ -1,7 +1,7 @@
 // File: src/main.js
 // Main entry point for @collab-editor/editor
 
-import { initWasm } from './wasm/initWasm.js';
+import { initWasm, isWasmReady, highlight_code_blocks, sanitize_html, clean_pasted_html, import_document } from './wasm/initWasm.js';
 import { initDiffWasm } from './wasm/diffWasm.js';
 import { setupDocumentStats } from './ui/documentStats.js';
 import { setupEditorWithBinding } from './editor.js';
@@ -18,7 +18,8 @@ import { addDocument, getDocTitle, updateTitle } from './utils/documentRegistry.
 import { config } from './config.js';
 
 /**
- * Sanitize HTML to prevent XSS attacks.
+ * Sanitize HTML to prevent XSS attacks. Fallback for when WASM is not
+ * loaded; otherwise sanitize_html applies the shared allowlist policy.
  */
 function sanitizeHtml(html) {
   const template = document.createElement('template');
@@ -314,7 +315,8 @@ async function initAppInternal() {
   const { view, binding, destroy: destroyEditor } = setupEditorWithBinding(
     editorElement,
     handle,
-    awareness
+    awareness,
+    { cleanPastedHtml: clean_pasted_html, importDocument: import_document }
   );
 
   // Connection status indicator
@@ -610,8 +612,13 @@ function setupMarkdownPreviewUpdates(view, handle, sanitizeHtml) {
 
       setTimeout(() => {
         const content = view.state.doc.toString();
-        const html = convertMarkdownToHtml(content);
-        previewElement.innerHTML = sanitizeHtml(html);
+        let html = convertMarkdownToHtml(content);
+        if (isWasmReady()) {
+          html = sanitize_html(highlight_code_blocks(html), '');
+        } else {
+          html = sanitizeHtml(html);
+        }
+        previewElement.innerHTML = html;
         editorContainer.classList.remove('loading');
         previewElement.classList.remove('loading');
       }, 10);
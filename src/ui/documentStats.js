@@ -1,5 +1,5 @@
 // File: src/ui/documentStats.js
-import { calculate_document_stats } from '../wasm/initWasm.js';
+import { calculate_document_stats_localized } from '../wasm/initWasm.js';
 
 // Debounce delay in milliseconds - prevents excessive WASM calls during rapid typing
 const STATS_DEBOUNCE_MS = 300;
@@ -31,12 +31,12 @@ export function setupDocumentStats(handle, view) {
   async function updateStats() {
     try {
       const text = view.state.doc.toString();
-      const statsJson = await calculate_document_stats(text);
+      const statsJson = await calculate_document_stats_localized(text, navigator.language || 'en');
       const stats = JSON.parse(statsJson);
 
-      wordCountEl.textContent = `${stats.words} words`;
-      charCountEl.textContent = `${stats.chars_without_spaces} chars`;
-      readingTimeEl.textContent = `${stats.reading_time} min read`;
+      wordCountEl.textContent = stats.display.words;
+      charCountEl.textContent = stats.display.chars_without_spaces;
+      readingTimeEl.textContent = stats.display.reading_time;
     } catch (error) {
       console.error('Failed to update document stats:', error);
     }
@@ -4,15 +4,137 @@ import init, {
   compress_document,
   decompress_document,
   format_text,
+  format_text_with_options,
+  fix_punctuation_for_locale,
+  default_format_options,
+  format_range,
+  format_range_with_options,
+  IncrementalFormatter,
+  roundtrip_check,
+  get_capabilities,
+  edit_message_hash,
+  create_chained_edit_message,
+  EditChainVerifier,
+  MacroLibrary,
+  ExportManager,
+  replay_macro,
+  create_daily_note,
+  previous_note_date,
+  next_note_date,
+  update_daily_note_links,
+  QuotaPolicy,
+  set_performance_profile,
+  performance_profile,
   toggle_bold,
   toggle_italic,
   toggle_underline,
   toggle_strikethrough,
+  toggle_bold_at,
+  toggle_italic_at,
+  toggle_strikethrough_at,
   toggle_heading,
   toggle_list,
   toggle_numbered_list,
+  cycle_heading,
+  shift_headings,
+  convert_headings,
+  convert_links,
+  reflow,
+  unwrap_paragraphs,
+  line_ops,
+  normalize_whitespace,
+  default_whitespace_options,
+  sanitize_html,
+  default_sanitize_policy,
+  clean_pasted_html,
+  import_document,
+  diff_documents,
+  diff_words,
+  merge_documents,
+  create_patch,
+  apply_patch,
+  VersionStore,
+  blame,
+  replay_to,
+  EditPlayer,
+  compact_history,
+  export_session,
+  import_session,
+  session_edit_log,
+  EditBatcher,
+  create_cursor_message,
+  parse_cursor_message,
+  CursorThrottle,
+  create_presence_message,
+  PresenceTracker,
+  PromiseGridHandler,
+  InboundTracker,
+  decode_message,
+  validate_message,
+  generate_document_key,
+  encrypt_message,
+  decrypt_message,
+  derive_document_key,
+  generate_salt,
+  salt_to_hex,
+  salt_from_hex,
+  generate_exchange_key,
+  exchange_public_key,
+  create_key_share_message,
+  open_key_share,
+  ReplayGuard,
+  MessageBatch,
+  BatchReader,
+  create_snapshot_message,
+  SnapshotJoin,
+  AccessController,
+  create_acl_message,
+  mint_capability_token,
+  attach_capability_token,
+  sign_message,
+  describe_capability_token,
+  OfflineQueue,
+  message_key,
+  InboundDeduper,
+  cbor_to_diagnostic,
+  cbor_to_json,
+  json_to_cbor,
+  set_legacy_framing,
+  set_log_level,
+  log_level,
+  set_log_buffer,
+  recent_logs,
+  clear_logs,
+  CommentStore,
+  detect_mentions,
+  detect_edit_mentions,
+  create_mention_message,
+  parse_mention_message,
+  assign_user_color,
+  UserProfile,
+  DocumentBuffer,
+  expand_snippet_body,
+  SnippetLibrary,
+  SaveScheduler,
+  WritingGoals,
+  parse_bibtex,
+  CitationDatabase,
+  classify_paste,
+  export_plaintext,
+  export_rst,
+  export_asciidoc,
+  export_docx,
+  export_epub,
+  parse_to_ast,
+  render_from_ast,
   calculate_document_stats,
+  calculate_document_stats_localized,
+  format_number,
   convert_url_to_markdown,
+  expand_abbreviations,
+  strip_front_matter,
+  highlight_code,
+  highlight_code_blocks,
 
   // NEW: PromiseGrid functions
   create_promisegrid_edit_message,
@@ -103,15 +225,137 @@ window.searchDocument = search_document;
 
 export {
   format_text,
+  format_text_with_options,
+  fix_punctuation_for_locale,
+  default_format_options,
+  format_range,
+  format_range_with_options,
+  IncrementalFormatter,
+  roundtrip_check,
+  get_capabilities,
+  edit_message_hash,
+  create_chained_edit_message,
+  EditChainVerifier,
+  MacroLibrary,
+  ExportManager,
+  replay_macro,
+  create_daily_note,
+  previous_note_date,
+  next_note_date,
+  update_daily_note_links,
+  QuotaPolicy,
+  set_performance_profile,
+  performance_profile,
   toggle_bold,
   toggle_italic,
   toggle_underline,
   toggle_strikethrough,
+  toggle_bold_at,
+  toggle_italic_at,
+  toggle_strikethrough_at,
   toggle_heading,
   toggle_list,
   toggle_numbered_list,
+  cycle_heading,
+  shift_headings,
+  convert_headings,
+  convert_links,
+  reflow,
+  unwrap_paragraphs,
+  line_ops,
+  normalize_whitespace,
+  default_whitespace_options,
+  sanitize_html,
+  default_sanitize_policy,
+  clean_pasted_html,
+  import_document,
+  diff_documents,
+  diff_words,
+  merge_documents,
+  create_patch,
+  apply_patch,
+  VersionStore,
+  blame,
+  replay_to,
+  EditPlayer,
+  compact_history,
+  export_session,
+  import_session,
+  session_edit_log,
+  EditBatcher,
+  create_cursor_message,
+  parse_cursor_message,
+  CursorThrottle,
+  create_presence_message,
+  PresenceTracker,
+  PromiseGridHandler,
+  InboundTracker,
+  decode_message,
+  validate_message,
+  generate_document_key,
+  encrypt_message,
+  decrypt_message,
+  derive_document_key,
+  generate_salt,
+  salt_to_hex,
+  salt_from_hex,
+  generate_exchange_key,
+  exchange_public_key,
+  create_key_share_message,
+  open_key_share,
+  ReplayGuard,
+  MessageBatch,
+  BatchReader,
+  create_snapshot_message,
+  SnapshotJoin,
+  AccessController,
+  create_acl_message,
+  mint_capability_token,
+  attach_capability_token,
+  sign_message,
+  describe_capability_token,
+  OfflineQueue,
+  message_key,
+  InboundDeduper,
+  cbor_to_diagnostic,
+  cbor_to_json,
+  json_to_cbor,
+  set_legacy_framing,
+  set_log_level,
+  log_level,
+  set_log_buffer,
+  recent_logs,
+  clear_logs,
+  CommentStore,
+  detect_mentions,
+  detect_edit_mentions,
+  create_mention_message,
+  parse_mention_message,
+  assign_user_color,
+  UserProfile,
+  DocumentBuffer,
+  expand_snippet_body,
+  SnippetLibrary,
+  SaveScheduler,
+  WritingGoals,
+  parse_bibtex,
+  CitationDatabase,
+  classify_paste,
+  export_plaintext,
+  export_rst,
+  export_asciidoc,
+  export_docx,
+  export_epub,
+  parse_to_ast,
+  render_from_ast,
   calculate_document_stats,
+  calculate_document_stats_localized,
+  format_number,
   convert_url_to_markdown,
+  expand_abbreviations,
+  strip_front_matter,
+  highlight_code,
+  highlight_code_blocks,
   search_document
 };
 